@@ -0,0 +1,93 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Logs one bot in and makes it hop with a task defined here. `#hop <times>` in chat makes it hop again.
+//!
+//! `cargo run --example custom_task -- <host>` with the accounts in `users.csv` and the proxies in `proxies.csv`
+
+use std::fs::File;
+
+use tokio::runtime::Runtime;
+use tokio::task::LocalSet;
+
+use swarm_bot::{Controller, CustomTask, DynTask, GlobalState, InterfaceOut, LocalState, Swarm};
+use swarm_bot::bootstrap::csv::{read_proxies, read_users};
+use swarm_bot::chat::ChatEvent;
+use swarm_bot::client::bot::ActionState;
+
+/// jumps `left` more times
+struct Hop {
+    left: usize,
+}
+
+impl DynTask for Hop {
+    fn name(&self) -> &'static str {
+        "Hop"
+    }
+
+    fn tick(&mut self, _out: &mut dyn InterfaceOut, local: &mut LocalState, _global: &mut GlobalState) -> bool {
+        if self.left == 0 {
+            return true;
+        }
+
+        if local.physics.on_ground() {
+            local.physics.jump();
+            self.left -= 1;
+        }
+        false
+    }
+}
+
+/// hops once when the bot joins and again when told to
+struct Hopper {
+    greeted: bool,
+}
+
+impl Controller for Hopper {
+    fn tick(&mut self, out: &mut dyn InterfaceOut, actions: &mut ActionState, _local: &mut LocalState, _global: &mut GlobalState) {
+        if !self.greeted {
+            self.greeted = true;
+            out.send_chat("hello! say #hop <times> to make me hop");
+            actions.schedule(CustomTask::new(Hop { left: 3 }));
+        }
+    }
+
+    fn on_chat(&mut self, message: &ChatEvent, _out: &mut dyn InterfaceOut, actions: &mut ActionState, _local: &mut LocalState, _global: &mut GlobalState) {
+        let times = message.message.strip_prefix("#hop").and_then(|times| times.trim().parse().ok());
+        if let Some(times) = times {
+            actions.schedule(CustomTask::new(Hop { left: times }));
+        }
+    }
+}
+
+fn main() {
+    let host = std::env::args().nth(1).expect("usage: custom_task <host>");
+    let users = read_users(File::open("users.csv").expect("no users.csv")).unwrap();
+    let proxies = read_proxies(File::open("proxies.csv").expect("no proxies.csv")).unwrap();
+
+    let rt = Runtime::new().unwrap();
+    LocalSet::new().block_on(&rt, async move {
+        let swarm = Swarm::new(host)
+            .accounts(users)
+            .proxies(proxies)
+            .bots(1)
+            .controller(|_| Box::new(Hopper { greeted: false }));
+
+        if let Err(err) = swarm.run().await {
+            println!("{}", err);
+        }
+    });
+}
@@ -14,16 +14,16 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::convert::TryInto;
 use std::io::{Cursor, Read};
 
-use bytes::Buf;
+use bytes::{Buf, Bytes};
 
 use crate::types::{BitField, VarUInt};
 
+/// Cloning is cheap as the bytes are shared
 #[derive(Clone)]
 pub struct ByteReader {
-    bytes: Cursor<Vec<u8>>,
+    bytes: Cursor<Bytes>,
 }
 
 pub struct LenRead<T> {
@@ -57,12 +57,24 @@ impl ByteReader {
         T::read_from_bytes(self, input)
     }
 
+    /// the bytes which have not been read yet. Used to parse without copying.
+    pub fn remaining(&self) -> &[u8] {
+        let position = self.bytes.position() as usize;
+        &self.bytes.get_ref()[position..]
+    }
+
+    /// skip `bytes` bytes (at most to the end)
+    pub fn advance(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.len());
+        Buf::advance(&mut self.bytes, bytes);
+    }
+
     pub fn empty(&self) -> bool {
         !self.bytes.has_remaining()
     }
 
     pub fn len(&self) -> usize {
-        self.bytes.remaining().try_into().unwrap()
+        self.bytes.remaining()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -70,7 +82,12 @@ impl ByteReader {
     }
 
     pub fn new(vec: Vec<u8>) -> ByteReader {
-        let bytes = Cursor::new(vec);
+        Self::from_bytes(Bytes::from(vec))
+    }
+
+    /// read `bytes` without copying them
+    pub fn from_bytes(bytes: Bytes) -> ByteReader {
+        let bytes = Cursor::new(bytes);
         Self {
             bytes
         }
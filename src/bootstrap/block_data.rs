@@ -37,20 +37,41 @@ impl Default for Material {
     }
 }
 
+/// the item dropped. Blocks with variants (i.e., dirt) give the metadata too.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RawDropItem {
+    Id(u32),
+    Variant { id: u32, metadata: u32 },
+}
+
+impl RawDropItem {
+    pub fn id(&self) -> u32 {
+        match *self {
+            RawDropItem::Id(id) | RawDropItem::Variant { id, .. } => id,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RawDrop {
+    pub drop: RawDropItem,
+}
+
 /// Uses prismarine.js block data. We comment out the fields that we do not use
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RawBlock {
     pub id: u32,
     // pub display_name: String,
-    // pub name: String,
+    pub name: String,
     pub hardness: Option<f64>,
     pub harvest_tools: Option<HashMap<u32, bool>>,
     pub material: Option<Material>,
     // pub stack_size: u32,
     // pub diggable: bool,
     // pub bounding_box: String,
-    // drops: [],
+    pub drops: Option<Vec<RawDrop>>,
     // pub transparent: bool,
     // pub emit_light: u32,
     // pub filter_light: u32,
@@ -66,19 +87,26 @@ pub struct RawFood {
 
 pub struct Block {
     pub id: u32,
+    pub name: String,
     pub hardness: Option<f64>,
     pub harvest_tools: Vec<u32>,
     pub material: Material,
+
+    /// whether breaking the block (with a tool which can harvest it) drops the block itself
+    pub drops_self: bool,
 }
 
 impl From<RawBlock> for Block {
     fn from(block: RawBlock) -> Self {
+        let drops_self = block.drops.as_deref().unwrap_or_default().iter().any(|drop| drop.drop.id() == block.id);
         Self {
             id: block.id,
+            name: block.name,
             hardness: block.hardness,
             harvest_tools: block.harvest_tools.unwrap_or_default().into_iter()
                 .filter_map(|(k, v)| v.then(|| k)).collect(),
             material: block.material.unwrap_or_default(),
+            drops_self,
         }
     }
 }
@@ -86,6 +114,9 @@ impl From<RawBlock> for Block {
 pub struct BlockData {
     // lookup by id
     block_lookup: HashMap<u32, Block>,
+
+    /// name -> id
+    name_lookup: HashMap<String, u32>,
     food_lookup: HashSet<u32>,
 }
 
@@ -100,6 +131,12 @@ impl BlockData {
         self.block_lookup.get(&id)
     }
 
+    /// the block called `name` (i.e., `diamond_ore` or `minecraft:diamond_ore`)
+    pub fn by_name(&self, name: &str) -> Option<&Block> {
+        let id = self.name_lookup.get(name.trim_start_matches("minecraft:"))?;
+        self.by_id(*id)
+    }
+
     pub fn is_food(&self, id: u32) -> bool {
         self.food_lookup.contains(&id)
     }
@@ -120,13 +157,17 @@ impl BlockData {
 
         let blocks = blocks.into_iter().map(Block::from);
 
-        let block_lookup = blocks
+        let block_lookup: HashMap<_, _> = blocks
             .map(|elem| (elem.id, elem))
             .collect();
 
+        let name_lookup = block_lookup.values()
+            .map(|block| (block.name.clone(), block.id))
+            .collect();
 
         Ok(BlockData {
             block_lookup,
+            name_lookup,
             food_lookup,
         })
     }
@@ -0,0 +1,192 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Keeping the cached sessions safe and fresh. The sessions file can be encrypted with a key (`SWARM_SESSIONS_KEY`) so
+//! the tokens cannot be read off the disk without it, and the sessions of the accounts in use are renewed in the
+//! background before they expire so a bot which is disconnected after a day can still log back in.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use hmac::Hmac;
+use sha2::Sha256;
+
+use crate::bootstrap::storage::ValidUser;
+use crate::error::{err, Res};
+
+/// the environment variable holding the key of the sessions file
+pub const SESSIONS_KEY_VAR: &str = "SWARM_SESSIONS_KEY";
+
+/// Minecraft tokens of Microsoft accounts last a day. Mojang does not say how long its tokens last, so they are
+/// treated the same.
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// how long before it expires a session is renewed
+pub const RENEW_MARGIN: Duration = Duration::from_secs(60 * 60);
+
+/// how often the sessions are checked for renewal
+pub const RENEW_CHECK: Duration = Duration::from_secs(5 * 60);
+
+/// The start of an encrypted sessions file, followed by the PBKDF2 iterations (u32), salt and nonce of the file. A
+/// plain one starts with `{`.
+const MAGIC: &[u8] = b"SWSESS1";
+
+/// PBKDF2-HMAC-SHA256 iterations of new files
+const ITERATIONS: u32 = 100_000;
+
+/// files asking for more are not opened, deriving the key would take minutes
+const MAX_ITERATIONS: u32 = 10_000_000;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = 7 + 4 + SALT_LEN + NONCE_LEN;
+
+/// The key of the sessions file. Files are encrypted with AES-256-GCM under a key derived from the passphrase with
+/// PBKDF2 and the salt of the file.
+#[derive(Clone)]
+pub struct SessionKey {
+    passphrase: String,
+
+    /// the salt new files are sealed with and its key, so saving does not derive the key again
+    salt: [u8; SALT_LEN],
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SessionKey")
+    }
+}
+
+fn derive(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+impl SessionKey {
+    pub fn new(passphrase: &str) -> SessionKey {
+        let salt: [u8; SALT_LEN] = rand::random();
+        SessionKey {
+            passphrase: passphrase.to_string(),
+            key: derive(passphrase, &salt, ITERATIONS),
+            salt,
+        }
+    }
+
+    /// `plain` encrypted with a random nonce. The header is authenticated along with it.
+    pub fn seal(&self, plain: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let mut sealed = [MAGIC, &ITERATIONS.to_be_bytes()[..], &self.salt[..], &nonce[..]].concat();
+
+        let body = Aes256Gcm::new(Key::from_slice(&self.key))
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plain, aad: &sealed })
+            .expect("the sessions file is too large to encrypt");
+        sealed.extend(body);
+        sealed
+    }
+
+    /// Fails if the key is wrong or the file was changed
+    pub fn open(&self, sealed: &[u8]) -> Res<Vec<u8>> {
+        if !sealed.starts_with(MAGIC) {
+            return Err(err("the sessions file is not encrypted"));
+        }
+        if sealed.len() < HEADER_LEN + TAG_LEN {
+            return Err(err("the sessions file is cut off"));
+        }
+
+        let (header, body) = sealed.split_at(HEADER_LEN);
+        let iterations = u32::from_be_bytes(header[MAGIC.len()..MAGIC.len() + 4].try_into().unwrap());
+        let salt = &header[MAGIC.len() + 4..MAGIC.len() + 4 + SALT_LEN];
+        let nonce = &header[HEADER_LEN - NONCE_LEN..];
+
+        if iterations == 0 || iterations > MAX_ITERATIONS {
+            return Err(err(&format!("the sessions file asks for {} iterations", iterations)));
+        }
+
+        let key = if salt == &self.salt[..] && iterations == ITERATIONS { self.key } else { derive(&self.passphrase, salt, iterations) };
+
+        Aes256Gcm::new(Key::from_slice(&key))
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: body, aad: header })
+            .map_err(|_| err("wrong key for the sessions file or the file was changed"))
+    }
+}
+
+/// The contents of the sessions file as json. A plain file is read as it is (and encrypted when it is saved next).
+pub fn read_sessions(contents: Vec<u8>, key: Option<&SessionKey>) -> Res<Vec<u8>> {
+    match key {
+        _ if !contents.starts_with(MAGIC) => Ok(contents),
+        Some(key) => key.open(&contents),
+        None => Err(err(&format!("the sessions file is encrypted, set {}", SESSIONS_KEY_VAR))),
+    }
+}
+
+/// The latest session of every account, shared with the bots so they log back in with a session renewed while they
+/// were online. Clones share the sessions.
+#[derive(Clone, Debug, Default)]
+pub struct Sessions {
+    latest: Arc<Mutex<HashMap<String, ValidUser>>>,
+}
+
+impl Sessions {
+    pub fn update(&self, user: &ValidUser) {
+        self.latest.lock().unwrap().insert(user.email.clone(), user.clone());
+    }
+
+    /// the latest session of `email`
+    pub fn latest(&self, email: &str) -> Option<ValidUser> {
+        self.latest.lock().unwrap().get(email).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::cache::{HEADER_LEN, read_sessions, SessionKey};
+
+    #[test]
+    fn test_seal() {
+        let key = SessionKey::new("hunter2");
+        let plain = br#"{"users":[]}"#;
+
+        let sealed = key.seal(plain);
+        assert!(!sealed.windows(plain.len()).any(|window| window == plain));
+        assert_ne!(sealed, key.seal(plain), "the iv is random");
+
+        assert_eq!(read_sessions(sealed.clone(), Some(&key)).unwrap(), plain);
+        assert!(read_sessions(sealed.clone(), Some(&SessionKey::new("hunter3"))).is_err());
+        assert!(read_sessions(sealed[..30].to_vec(), Some(&key)).is_err());
+        assert!(read_sessions(sealed.clone(), None).is_err());
+
+        // a key from the same passphrase opens it, the salt is in the file
+        assert_eq!(read_sessions(sealed.clone(), Some(&SessionKey::new("hunter2"))).unwrap(), plain);
+
+        // any change to the ciphertext, tag or header (iterations, salt and nonce) is noticed
+        for &idx in &[sealed.len() - 1, sealed.len() - 20, 10, 12, HEADER_LEN - 1] {
+            let mut tampered = sealed.clone();
+            tampered[idx] ^= 1;
+            assert!(read_sessions(tampered, Some(&key)).is_err(), "byte {} was changed", idx);
+        }
+
+        // plain files are still read, with a key or without
+        assert_eq!(read_sessions(plain.to_vec(), Some(&key)).unwrap(), plain);
+        assert_eq!(read_sessions(plain.to_vec(), None).unwrap(), plain);
+    }
+}
@@ -15,6 +15,7 @@
  */
 
 use std::fs::File;
+use std::io::{BufRead, BufReader};
 
 use serde::de::DeserializeOwned;
 
@@ -38,6 +39,15 @@ pub fn read_users(file: File) -> Res<Vec<CSVUser>> {
     read_csv(file)
 }
 
+/// one [`Proxy`] a line, blank lines are skipped
 pub fn read_proxies(file: File) -> Res<Vec<Proxy>> {
-    read_csv(file)
+    let mut proxies = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() {
+            proxies.push(line.parse()?);
+        }
+    }
+    Ok(proxies)
 }
@@ -23,7 +23,7 @@ use crate::bootstrap::Address;
 async fn dns_lookup(host: &str) -> Result<Address, ResolveError> {
     let resolver = AsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default()).unwrap();
 
-    println!("performing srv lookup");
+    debug!(host, "performing srv lookup");
     resolver.srv_lookup(format!("_minecraft._tcp.{}", host)).await.map(|res| {
         let srv = res.iter().next().unwrap();
         Address {
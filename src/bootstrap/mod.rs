@@ -14,20 +14,30 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::io::ErrorKind;
+use std::str::FromStr;
+use std::time::Duration;
+
 use serde::Deserialize;
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
-use tokio::sync::mpsc::Receiver;
-use tokio_socks::tcp::Socks5Stream;
 
 use crate::bootstrap::mojang::Mojang;
 use crate::bootstrap::storage::{ProxyUser, ValidUser};
+use crate::bootstrap::tunnel::{TunnelRead, TunnelWrite};
+use crate::error::{err, Error, SwarmError};
+use crate::protocol::chat_queue::ChatConfig;
+use crate::protocol::disconnect::DisconnectPatterns;
 
 pub mod opts;
 pub mod csv;
 pub mod block_data;
 pub mod dns;
 pub mod storage;
+pub mod cache;
 pub mod mojang;
+pub mod proxy;
+pub mod schedule;
+pub mod servers;
+pub mod tunnel;
 
 
 #[derive(Clone, Debug)]
@@ -42,39 +52,81 @@ impl From<&Address> for String {
     }
 }
 
-#[derive(Debug)]
+/// how long it may take to connect to the server through a proxy
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// how many proxies to try before giving up on a user
+const CONNECT_ATTEMPTS: u32 = 3;
+
 pub struct Connection {
     pub user: ValidUser,
     pub address: Address,
     pub mojang: Mojang,
-    pub read: OwnedReadHalf,
-    pub write: OwnedWriteHalf,
+    pub read: TunnelRead,
+    pub write: TunnelWrite,
+
+    /// how fast the bot may chat
+    pub chat: ChatConfig,
+
+    /// how disconnect messages are classified
+    pub disconnects: DisconnectPatterns,
+}
+
+impl std::fmt::Debug for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connection")
+            .field("user", &self.user)
+            .field("address", &self.address)
+            .field("mojang", &self.mojang)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Connection {
-    pub fn stream(address: Address, mut users: tokio::sync::mpsc::Receiver<ProxyUser>) -> Receiver<Connection> {
-        let (tx, rx) = tokio::sync::mpsc::channel(1);
-        tokio::task::spawn_local(async move {
-            while let Some(user) = users.recv().await {
-                let tx = tx.clone();
-                let address = address.clone();
-                tokio::task::spawn_local(async move {
-                    let ProxyUser { proxy, user, mojang } = user;
-                    let target = String::from(&address);
-                    let conn = Socks5Stream::connect_with_password(proxy.address().as_str(), target.as_str(), &proxy.user, &proxy.pass).await.unwrap();
-                    let (read, write) = conn.into_inner().into_split();
-                    tx.send(Connection {
+    /// Connect to `address` through the user's proxy. If the proxy does not work, the user is rotated to another
+    /// proxy (at most [`CONNECT_ATTEMPTS`] proxies are tried). The error names the last proxy tried.
+    pub async fn connect(address: Address, user: ProxyUser) -> Result<Connection, SwarmError> {
+        let ProxyUser { mut proxy, user, mut mojang, sessions } = user;
+        let user = sessions.latest(&user.email).unwrap_or(user);
+        let target = String::from(&address);
+
+        let mut attempt = 1;
+        loop {
+            let connect = tunnel::connect(&proxy.proxy, &target);
+
+            let source = match tokio::time::timeout(CONNECT_TIMEOUT, connect).await {
+                Ok(Ok(tunnel)) => {
+                    proxy.success();
+                    let (read, write) = tunnel.into_split();
+                    return Ok(Connection {
                         user,
                         address,
                         mojang,
                         read,
                         write,
-                    }).await.unwrap();
-                });
-            }
-        });
+                        chat: ChatConfig::default(),
+                        disconnects: DisconnectPatterns::default(),
+                    });
+                }
+                Ok(Err(err)) => err,
+                Err(_) => ErrorKind::TimedOut.into(),
+            };
+
+            proxy.failure();
+            let error = SwarmError::Proxy { account: user.username.clone(), proxy: proxy.proxy.url(), source };
 
-        rx
+            // the session server has to see us join from the same ip we connect with
+            let next = proxy.rotate(&user.email).and_then(|next| mojang.with_proxy(&next).ok().map(|mojang| (next, mojang)));
+            match next {
+                Some((next, next_mojang)) if attempt < CONNECT_ATTEMPTS => {
+                    warn!(attempt, %error, "rotating proxy");
+                    proxy = next;
+                    mojang = next_mojang;
+                    attempt += 1;
+                }
+                _ => return Err(error),
+            }
+        }
     }
 }
 
@@ -84,8 +136,36 @@ pub struct CSVUser {
     pub password: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl CSVUser {
+    /// Whether the account logs in through Microsoft, which it does with `msa` as the password
+    pub fn microsoft(&self) -> bool {
+        self.password == "msa"
+    }
+}
+
+/// How a [`Proxy`] is spoken to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProxyKind {
+    Socks5,
+    Http,
+
+    /// an HTTP proxy spoken to over TLS
+    Https,
+}
+
+impl ProxyKind {
+    pub fn scheme(self) -> &'static str {
+        match self {
+            ProxyKind::Socks5 => "socks5",
+            ProxyKind::Http => "http",
+            ProxyKind::Https => "https",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Proxy {
+    pub kind: ProxyKind,
     pub host: String,
     pub port: u32,
     pub user: String,
@@ -96,4 +176,67 @@ impl Proxy {
     pub fn address(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
+
+    /// the address with the scheme, i.e., `http://10.0.0.1:8080`
+    pub fn url(&self) -> String {
+        format!("{}://{}", self.kind.scheme(), self.address())
+    }
+}
+
+/// A line of `proxies.csv`: `[scheme://]host:port[:user:pass]`. Without a scheme the proxy is SOCKS5.
+impl FromStr for Proxy {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (kind, rest) = match line.split_once("://") {
+            None => (ProxyKind::Socks5, line),
+            Some(("socks5", rest)) => (ProxyKind::Socks5, rest),
+            Some(("http", rest)) => (ProxyKind::Http, rest),
+            Some(("https", rest)) => (ProxyKind::Https, rest),
+            Some((scheme, _)) => return Err(err(&format!("unknown proxy scheme {}", scheme))),
+        };
+
+        let fields: Vec<_> = rest.split(':').collect();
+        let (host, port, user, pass) = match fields[..] {
+            [host, port] => (host, port, "", ""),
+            [host, port, user, pass] => (host, port, user, pass),
+            _ => return Err(err(&format!("proxy {} is not host:port[:user:pass]", line))),
+        };
+
+        let port = port.parse().map_err(|_| err(&format!("invalid port of proxy {}", line)))?;
+
+        Ok(Proxy {
+            kind,
+            host: host.to_string(),
+            port,
+            user: user.to_string(),
+            pass: pass.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::{Proxy, ProxyKind};
+
+    #[test]
+    fn test_parse_proxy() {
+        let proxy: Proxy = "10.0.0.1:1080:user:pass".parse().unwrap();
+        assert_eq!(proxy.kind, ProxyKind::Socks5);
+        assert_eq!(proxy.url(), "socks5://10.0.0.1:1080");
+        assert_eq!((proxy.user.as_str(), proxy.pass.as_str()), ("user", "pass"));
+
+        let proxy: Proxy = "http://10.0.0.1:8080".parse().unwrap();
+        assert_eq!(proxy.kind, ProxyKind::Http);
+        assert_eq!(proxy.port, 8080);
+        assert!(proxy.user.is_empty());
+
+        let proxy: Proxy = "https://proxy.example.com:443:user:pass".parse().unwrap();
+        assert_eq!(proxy.kind, ProxyKind::Https);
+        assert_eq!(proxy.host, "proxy.example.com");
+
+        assert!("ftp://10.0.0.1:21".parse::<Proxy>().is_err());
+        assert!("10.0.0.1:1080:user".parse::<Proxy>().is_err());
+        assert!("10.0.0.1:port".parse::<Proxy>().is_err());
+    }
 }
@@ -14,37 +14,273 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
 use num_bigint::BigInt;
+use reqwest::{RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
 use serde_json::json;
 use sha1::Sha1;
 
 use swarm_bot_packets::types::UUID;
 
-use crate::bootstrap::Proxy;
-use crate::error::{MojangErr, Res};
+use crate::bootstrap::proxy::ProxyLease;
+use crate::error::{Error, MojangErr, MojangErrKind, Res};
+
+pub mod msa;
+
+#[derive(Debug, Clone)]
+pub struct MojangConfig {
+    /// how long connecting to the proxy may take
+    pub connect_timeout: Duration,
+
+    /// how long a whole request may take, including connecting
+    pub timeout: Duration,
+
+    /// how many times a request which can safely be sent twice (validate and join) is retried after a timeout, a
+    /// server error or a rate limit
+    pub retries: u32,
+
+    /// the wait before the first retry. Doubled for every retry after.
+    pub backoff: Duration,
+
+    /// how long every request (and login) pauses after being rate limited
+    pub rate_limit_backoff: Duration,
+
+    pub auth_server: String,
+    pub session_server: String,
+
+    /// the id of the Azure application Microsoft accounts log in with. Needed for Microsoft accounts only.
+    pub microsoft_client_id: Option<String>,
+    pub microsoft_server: String,
+    pub xbox_server: String,
+    pub xsts_server: String,
+    pub services_server: String,
+
+    /// shared by clones, so a rate limit seen by one client slows down all of them
+    pub rate_limit: RateLimit,
+}
+
+impl Default for MojangConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(10),
+            retries: 2,
+            backoff: Duration::from_secs(1),
+            rate_limit_backoff: Duration::from_secs(30),
+            auth_server: "https://authserver.mojang.com".to_string(),
+            session_server: "https://sessionserver.mojang.com".to_string(),
+            microsoft_client_id: None,
+            microsoft_server: "https://login.microsoftonline.com/consumers/oauth2/v2.0".to_string(),
+            xbox_server: "https://user.auth.xboxlive.com".to_string(),
+            xsts_server: "https://xsts.auth.xboxlive.com".to_string(),
+            services_server: "https://api.minecraftservices.com".to_string(),
+            rate_limit: RateLimit::default(),
+        }
+    }
+}
+
+/// Mojang rate limits by ip and by account, so once a request is rate limited every request pauses until the limit
+/// has passed. The [`LoginScheduler`](crate::bootstrap::schedule::LoginScheduler) pauses logins as well.
+///
+/// Clones share the limit.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimit {
+    until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl RateLimit {
+    /// pause for `wait`, unless already paused for longer
+    pub fn limited(&self, wait: Duration) {
+        let end = Instant::now() + wait;
+        let mut until = self.until.lock().unwrap();
+        *until = Some(until.map_or(end, |until| until.max(end)));
+    }
+
+    /// when the pause ends. None if not paused.
+    pub fn until(&self) -> Option<Instant> {
+        let until = *self.until.lock().unwrap();
+        until.filter(|&until| until > Instant::now())
+    }
+
+    pub async fn wait(&self) {
+        while let Some(until) = self.until() {
+            tokio::time::sleep_until(tokio::time::Instant::from_std(until)).await;
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Endpoint {
+    Authenticate,
+    Refresh,
+    Validate,
+    Join,
+
+    /// the steps of logging in a Microsoft account (see [`msa`])
+    DeviceCode,
+    Token,
+    Xbox,
+    Xsts,
+    XboxLogin,
+    Profile,
+}
+
+impl Endpoint {
+    fn name(self) -> &'static str {
+        match self {
+            Endpoint::Authenticate => "authenticate",
+            Endpoint::Refresh => "refresh",
+            Endpoint::Validate => "validate",
+            Endpoint::Join => "join",
+            Endpoint::DeviceCode => "devicecode",
+            Endpoint::Token => "token",
+            Endpoint::Xbox => "xbox",
+            Endpoint::Xsts => "xsts",
+            Endpoint::XboxLogin => "login_with_xbox",
+            Endpoint::Profile => "profile",
+        }
+    }
 
-#[derive(Debug)]
+    fn url(self, config: &MojangConfig) -> String {
+        match self {
+            Endpoint::Join => format!("{}/session/minecraft/join", config.session_server),
+            Endpoint::DeviceCode | Endpoint::Token => format!("{}/{}", config.microsoft_server, self.name()),
+            Endpoint::Xbox => format!("{}/user/authenticate", config.xbox_server),
+            Endpoint::Xsts => format!("{}/xsts/authorize", config.xsts_server),
+            Endpoint::XboxLogin => format!("{}/authentication/login_with_xbox", config.services_server),
+            Endpoint::Profile => format!("{}/minecraft/profile", config.services_server),
+            _ => format!("{}/{}", config.auth_server, self.name()),
+        }
+    }
+
+    /// Whether sending the request twice is harmless. A second refresh invalidates the session the first one
+    /// returned and every password login counts towards Mojang locking the account. Microsoft refresh tokens are
+    /// single use as well.
+    fn idempotent(self) -> bool {
+        matches!(self, Endpoint::Validate | Endpoint::Join | Endpoint::Xbox | Endpoint::Xsts | Endpoint::XboxLogin | Endpoint::Profile)
+    }
+}
+
+fn failure(error: reqwest::Error) -> MojangErrKind {
+    if error.is_timeout() {
+        MojangErrKind::Timeout
+    } else {
+        MojangErrKind::Connection(error.to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Mojang {
     client: reqwest::Client,
+    proxy: ProxyLease,
+    config: MojangConfig,
 }
 
 impl Mojang {
-    pub fn socks5(proxy: &Proxy) -> Res<Mojang> {
-        let address = proxy.address();
-        let user = &proxy.user;
-        let pass = &proxy.pass;
-        let full_address = format!("socks5://{}", address);
-
-        let proxy = reqwest::Proxy::https(full_address)?
-            .basic_auth(user, pass);
+    /// a client going through the proxy of `lease`, whichever kind it is
+    pub fn proxied(lease: &ProxyLease, config: &MojangConfig) -> Res<Mojang> {
+        let proxy = &lease.proxy;
+        let reqwest_proxy = reqwest::Proxy::https(proxy.url())?
+            .basic_auth(&proxy.user, &proxy.pass);
 
         let client = reqwest::Client::builder()
-            .proxy(proxy)
+            .proxy(reqwest_proxy)
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.timeout)
             .build()?;
 
         Ok(Mojang {
-            client
+            client,
+            proxy: lease.clone(),
+            config: config.clone(),
+        })
+    }
+
+    /// a client with the same config going through `lease` instead
+    pub fn with_proxy(&self, lease: &ProxyLease) -> Res<Mojang> {
+        Mojang::proxied(lease, &self.config)
+    }
+
+    pub fn proxy(&self) -> &ProxyLease {
+        &self.proxy
+    }
+
+    fn error(&self, endpoint: Endpoint, kind: MojangErrKind) -> Error {
+        MojangErr {
+            endpoint: endpoint.name(),
+            proxy: self.proxy.proxy.url(),
+            kind,
+        }.into()
+    }
+
+    async fn post(&self, endpoint: Endpoint, payload: String) -> Res<Response> {
+        let url = endpoint.url(&self.config);
+        self.send(endpoint, || self.client.post(&url).body(payload.clone())).await
+    }
+
+    /// Send the request `request` builds. Requests which can be sent twice are retried after timeouts, server errors
+    /// and rate limits. Connection errors and rate limits are the proxy's fault, so the proxy is marked as suspect.
+    async fn send(&self, endpoint: Endpoint, request: impl Fn() -> RequestBuilder) -> Res<Response> {
+        let mut backoff = self.config.backoff;
+        let mut retries = 0;
+
+        loop {
+            self.config.rate_limit.wait().await;
+
+            let res = request().send().await;
+
+            let kind = match res {
+                Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    self.proxy.failure();
+                    self.config.rate_limit.limited(self.config.rate_limit_backoff);
+                    MojangErrKind::RateLimited
+                }
+                Ok(res) => {
+                    self.proxy.success();
+                    if !res.status().is_server_error() {
+                        return Ok(res);
+                    }
+                    MojangErrKind::Server(res.status())
+                }
+                Err(e) => {
+                    self.proxy.failure();
+                    failure(e)
+                }
+            };
+
+            // a rate limited request already waits for the limit to pass before it is sent again
+            let rate_limited = matches!(kind, MojangErrKind::RateLimited);
+            let retry = endpoint.idempotent() && retries < self.config.retries && !matches!(kind, MojangErrKind::Connection(_));
+
+            let err = self.error(endpoint, kind);
+            if !retry {
+                return Err(err);
+            }
+
+            retries += 1;
+            warn!(retry = retries, error = %err, "retrying mojang request");
+
+            if !rate_limited {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+    }
+
+    /// the body of a response as `T`
+    async fn parse<T: DeserializeOwned>(&self, endpoint: Endpoint, res: Response) -> Res<T> {
+        let body = res.text().await.map_err(|e| self.error(endpoint, failure(e)))?;
+        serde_json::from_str(&body).map_err(|e| self.error(endpoint, MojangErrKind::Malformed(e.to_string())))
+    }
+
+    async fn rejected(&self, endpoint: Endpoint, res: Response) -> Error {
+        self.error(endpoint, MojangErrKind::InvalidCredentials {
+            error_code: res.status(),
+            info: res.text().await.ok(),
         })
     }
 }
@@ -85,6 +321,9 @@ pub struct AuthResponse {
     pub client_token: String,
     pub username: String,
     pub uuid: UUID,
+
+    /// the Microsoft refresh token of Microsoft accounts
+    pub refresh_token: Option<String>,
 }
 
 impl Mojang {
@@ -101,25 +340,20 @@ impl Mojang {
 
         let payload = payload.to_string();
 
-        let res = self.client.post("https://authserver.mojang.com/authenticate")
-            .body(payload)
-            .send()
-            .await?;
+        let res = self.post(Endpoint::Authenticate, payload).await?;
 
         let status = res.status();
         if status != 200 {
-            return Err(MojangErr::InvalidCredentials {
-                error_code: status,
-                info: res.text().await.ok(),
-            }.into());
+            return Err(self.rejected(Endpoint::Authenticate, res).await);
         }
 
-        let auth: RawAuthResponse = res.json().await?;
+        let auth: RawAuthResponse = self.parse(Endpoint::Authenticate, res).await?;
         let auth = AuthResponse {
             access_token: auth.access_token,
             client_token: auth.client_token,
             username: auth.selected_profile.name,
             uuid: UUID::from(&auth.selected_profile.id),
+            refresh_token: None,
         };
         Ok(auth)
     }
@@ -131,18 +365,20 @@ impl Mojang {
             "requestUser": false,
         }).to_string();
 
-        let res = self.client.post("https://authserver.mojang.com/refresh")
-            .body(payload)
-            .send()
-            .await?;
+        let res = self.post(Endpoint::Refresh, payload).await?;
 
-        let _status = res.status();
-        let auth: RawAuthResponse = res.json().await?;
+        let status = res.status();
+        if status != 200 {
+            return Err(self.rejected(Endpoint::Refresh, res).await);
+        }
+
+        let auth: RawAuthResponse = self.parse(Endpoint::Refresh, res).await?;
         let auth = AuthResponse {
             access_token: auth.access_token,
             client_token: auth.client_token,
             username: auth.selected_profile.name,
             uuid: UUID::from(&auth.selected_profile.id),
+            refresh_token: None,
         };
         Ok(auth)
     }
@@ -153,10 +389,7 @@ impl Mojang {
             "clientToken": client_token,
         }).to_string();
 
-        let res = self.client.post("https://authserver.mojang.com/validate")
-            .body(payload)
-            .send()
-            .await?;
+        let res = self.post(Endpoint::Validate, payload).await?;
 
         let status = res.status();
         Ok(status == 204)
@@ -173,33 +406,161 @@ impl Mojang {
 
         let payload = payload.to_string();
 
-        let res = self.client.post("https://sessionserver.mojang.com/session/minecraft/join")
-            .body(payload)
-            .send()
-            .await?;
+        let res = self.post(Endpoint::Join, payload).await?;
 
         let status = res.status();
         if status != 204 {
-            println!("uuid invalid {}", uuid_str);
-            let err = Err(MojangErr::InvalidCredentials {
-                error_code: status,
-                info: res.text().await.ok(),
-            }.into());
-
-            println!("err {:?}", err);
-            return err;
+            warn!(uuid = %uuid_str, %status, "could not join the session");
+            return Err(self.rejected(Endpoint::Join, res).await);
         }
 
         Ok(())
     }
 }
 
+/// The requests for getting a session. A trait so the order they are tried in can be tested without Mojang.
+#[async_trait::async_trait(?Send)]
+pub trait Authenticator {
+    async fn authenticate(&self, email: &str, password: &str) -> Res<AuthResponse>;
+    async fn refresh(&self, access_token: &str, client_token: &str) -> Res<AuthResponse>;
+    async fn validate(&self, access_token: &str, client_token: &str) -> Res<bool>;
+
+    /// a session of the Microsoft account `email`, by the refresh token of an earlier login or a device code
+    async fn microsoft(&self, email: &str, refresh_token: Option<&str>) -> Res<AuthResponse>;
+    async fn validate_microsoft(&self, access_token: &str) -> Res<bool>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl Authenticator for Mojang {
+    async fn authenticate(&self, email: &str, password: &str) -> Res<AuthResponse> {
+        Mojang::authenticate(self, email, password).await
+    }
+
+    async fn refresh(&self, access_token: &str, client_token: &str) -> Res<AuthResponse> {
+        Mojang::refresh(self, access_token, client_token).await
+    }
+
+    async fn validate(&self, access_token: &str, client_token: &str) -> Res<bool> {
+        Mojang::validate(self, access_token, client_token).await
+    }
+
+    async fn microsoft(&self, email: &str, refresh_token: Option<&str>) -> Res<AuthResponse> {
+        Mojang::microsoft(self, email, refresh_token).await
+    }
+
+    async fn validate_microsoft(&self, access_token: &str) -> Res<bool> {
+        Mojang::validate_microsoft(self, access_token).await
+    }
+}
 
 #[cfg(test)]
 mod tests {
+    use std::future::Future;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
     use sha1::Sha1;
 
-    use crate::bootstrap::mojang::hexdigest;
+    use swarm_bot_packets::types::UUID;
+
+    use crate::bootstrap::mojang::{hexdigest, Mojang, MojangConfig};
+    use crate::bootstrap::{Proxy, ProxyKind};
+    use crate::bootstrap::proxy::ProxyPool;
+    use crate::error::{Error, MojangErr, MojangErrKind};
+
+    /// how the mock server answers a request
+    enum Answer {
+        Status(u16, &'static str),
+
+        /// never answer
+        Stall,
+    }
+
+    /// the path of the request
+    fn read_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        loop {
+            let read = stream.read(&mut buf).unwrap();
+            assert_ne!(read, 0, "the request ended early");
+            request.extend_from_slice(&buf[..read]);
+
+            let text = String::from_utf8_lossy(&request);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text.lines()
+                    .find_map(|line| line.to_lowercase().strip_prefix("content-length: ").map(|len| len.parse::<usize>().unwrap()))
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    return text.split_whitespace().nth(1).unwrap().to_string();
+                }
+            }
+        }
+    }
+
+    /// A Mojang mock answering requests with `answers` in order. Returns its address and the paths requested so far.
+    fn mock(answers: Vec<Answer>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let paths = Arc::new(Mutex::new(Vec::new()));
+
+        let requested = paths.clone();
+        std::thread::spawn(move || {
+            let mut stalled = Vec::new();
+            for answer in answers {
+                let (mut stream, _) = listener.accept().unwrap();
+                let path = read_request(&mut stream);
+                requested.lock().unwrap().push(path);
+
+                match answer {
+                    Answer::Stall => stalled.push(stream),
+                    Answer::Status(204, _) => write!(stream, "HTTP/1.1 204 Mock\r\nConnection: close\r\n\r\n").unwrap(),
+                    Answer::Status(status, body) => {
+                        write!(stream, "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body).unwrap()
+                    }
+                }
+            }
+
+            // the client gives up on stalled requests on its own
+            std::thread::sleep(Duration::from_secs(1));
+        });
+
+        (address, paths)
+    }
+
+    /// A client of the mock at `server`. The proxy is only used for https, so requests go to the mock directly.
+    fn client(server: &str) -> Mojang {
+        let proxy = Proxy { kind: ProxyKind::Socks5, host: "127.0.0.1".to_string(), port: 1080, user: "user".to_string(), pass: "pass".to_string() };
+        let lease = ProxyPool::new(vec![proxy]).assign("bot").unwrap();
+
+        let config = MojangConfig {
+            connect_timeout: Duration::from_millis(100),
+            timeout: Duration::from_millis(200),
+            retries: 2,
+            backoff: Duration::from_millis(10),
+            rate_limit_backoff: Duration::from_millis(50),
+            auth_server: server.to_string(),
+            session_server: server.to_string(),
+            microsoft_client_id: Some("client".to_string()),
+            microsoft_server: server.to_string(),
+            xbox_server: server.to_string(),
+            xsts_server: server.to_string(),
+            services_server: server.to_string(),
+            ..MojangConfig::default()
+        };
+
+        Mojang::proxied(&lease, &config).unwrap()
+    }
+
+    fn run<T>(future: impl Future<Output=T>) -> T {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(future)
+    }
+
+    fn requested(paths: &Arc<Mutex<Vec<String>>>) -> Vec<String> {
+        paths.lock().unwrap().clone()
+    }
 
     fn sha1(input: &[u8]) -> String {
         let mut sha1 = Sha1::new();
@@ -213,4 +574,100 @@ mod tests {
         assert_eq!(sha1(b"simon"), "88e16a1019277b15d58faf0541e11910eb756f6");
         assert_eq!(sha1(b"Notch"), "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
     }
+
+    #[test]
+    fn test_retry() {
+        let (server, paths) = mock(vec![Answer::Stall, Answer::Status(503, ""), Answer::Status(204, "")]);
+        let mojang = client(&server);
+
+        assert!(run(mojang.validate("access", "client")).unwrap());
+        assert_eq!(requested(&paths), vec!["/validate"; 3]);
+    }
+
+    #[test]
+    fn test_no_retry() {
+        let (server, paths) = mock(vec![Answer::Status(403, "{\"error\":\"ForbiddenOperationException\"}"), Answer::Status(503, "")]);
+        let mojang = client(&server);
+
+        match run(mojang.join(UUID(1), "hash", "access")) {
+            Err(Error::Mojang(MojangErr { endpoint: "join", proxy, kind: MojangErrKind::InvalidCredentials { error_code, info } })) => {
+                assert_eq!(proxy, "127.0.0.1:1080");
+                assert_eq!(error_code, 403);
+                assert!(info.unwrap().contains("ForbiddenOperationException"));
+            }
+            other => panic!("unexpected {:?}", other.err()),
+        }
+
+        // a password login is not sent twice
+        let err = run(mojang.authenticate("bot@example.com", "hunter2")).err().unwrap();
+        assert!(matches!(err, Error::Mojang(MojangErr { kind: MojangErrKind::Server(status), .. }) if status == 503));
+
+        assert_eq!(requested(&paths), vec!["/session/minecraft/join", "/authenticate"]);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let (server, paths) = mock(vec![Answer::Status(429, ""), Answer::Status(204, ""), Answer::Status(429, "")]);
+        let mojang = client(&server);
+
+        // waits for the rate limit to pass instead of the usual backoff
+        let start = Instant::now();
+        run(mojang.join(UUID(1), "hash", "access")).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let err = run(mojang.authenticate("bot@example.com", "hunter2")).err().unwrap();
+        assert!(matches!(err, Error::Mojang(MojangErr { endpoint: "authenticate", kind: MojangErrKind::RateLimited, .. })));
+
+        // every client (and the login scheduler) sharing the limit pauses
+        assert!(mojang.config.rate_limit.until().is_some());
+        assert_eq!(requested(&paths), vec!["/session/minecraft/join", "/session/minecraft/join", "/authenticate"]);
+    }
+
+    #[test]
+    fn test_microsoft() {
+        const XBOX: &str = r#"{"Token":"xbox","DisplayClaims":{"xui":[{"uhs":"hash"}]}}"#;
+
+        let (server, paths) = mock(vec![
+            Answer::Status(200, r#"{"device_code":"device","user_code":"ABCD","interval":0,"expires_in":900,"message":"enter ABCD"}"#),
+            Answer::Status(400, r#"{"error":"authorization_pending"}"#),
+            Answer::Status(200, r#"{"access_token":"microsoft","refresh_token":"refresh","expires_in":3600}"#),
+            Answer::Status(200, XBOX),
+            Answer::Status(200, XBOX),
+            Answer::Status(200, r#"{"access_token":"minecraft","expires_in":86400}"#),
+            Answer::Status(200, r#"{"id":"069a79f444e94726a5befca90e38aaf5","name":"Notch"}"#),
+            Answer::Status(200, r#"{"access_token":"microsoft","refresh_token":"refreshed"}"#),
+            Answer::Status(200, XBOX),
+            Answer::Status(401, r#"{"XErr":2148916233}"#),
+            Answer::Status(401, ""),
+        ]);
+        let mojang = client(&server);
+
+        let auth = run(mojang.microsoft("bot@example.com", None)).unwrap();
+        assert_eq!(auth.username, "Notch");
+        assert_eq!(auth.access_token, "minecraft");
+        assert_eq!(auth.uuid.0, 0x069a79f444e94726a5befca90e38aaf5);
+        assert_eq!(auth.refresh_token.as_deref(), Some("refresh"));
+
+        // an account without an Xbox profile
+        let err = run(mojang.microsoft("bot@example.com", Some("refresh"))).err().unwrap();
+        assert!(matches!(err, Error::Mojang(MojangErr { endpoint: "xsts", kind: MojangErrKind::InvalidCredentials { .. }, .. })));
+
+        assert!(!run(mojang.validate_microsoft("minecraft")).unwrap());
+
+        let token = ["/devicecode", "/token", "/token"];
+        let login = ["/user/authenticate", "/xsts/authorize", "/authentication/login_with_xbox", "/minecraft/profile"];
+        let refresh = ["/token", "/user/authenticate", "/xsts/authorize"];
+        let validate = ["/minecraft/profile"];
+        assert_eq!(requested(&paths), [&token[..], &login[..], &refresh[..], &validate[..]].concat());
+    }
+
+    #[test]
+    fn test_malformed() {
+        let (server, _) = mock(vec![Answer::Status(200, "{\"accessToken\":")]);
+        let mojang = client(&server);
+
+        let err = run(mojang.authenticate("bot@example.com", "hunter2")).err().unwrap();
+        assert!(matches!(err, Error::Mojang(MojangErr { kind: MojangErrKind::Malformed(_), .. })));
+        assert!(err.to_string().starts_with("mojang authenticate through 127.0.0.1:1080: malformed response"));
+    }
 }
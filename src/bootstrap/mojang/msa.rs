@@ -0,0 +1,223 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Logging in Microsoft accounts. A device code (or the refresh token of an earlier login) gets a Microsoft token,
+//! which is traded for an Xbox Live token, then an XSTS token and at last a Minecraft token.
+
+use std::time::{Duration, Instant};
+
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use swarm_bot_packets::types::UUID;
+
+use crate::bootstrap::mojang::{AuthResponse, Endpoint, Mojang};
+use crate::error::{err, MojangErrKind, Res};
+
+const SCOPE: &str = "XboxLive.signin offline_access";
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// how much longer to wait between polls when Microsoft asks us to slow down
+const SLOW_DOWN: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+struct DeviceCode {
+    device_code: String,
+
+    /// seconds between polls
+    interval: u64,
+    expires_in: u64,
+
+    /// where to enter which code
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct MicrosoftToken {
+    access_token: String,
+    refresh_token: String,
+}
+
+#[derive(Deserialize)]
+struct TokenError {
+    error: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct XboxToken {
+    token: String,
+    display_claims: DisplayClaims,
+}
+
+#[derive(Deserialize)]
+struct DisplayClaims {
+    xui: Vec<UserHash>,
+}
+
+#[derive(Deserialize)]
+struct UserHash {
+    uhs: String,
+}
+
+#[derive(Deserialize)]
+struct MinecraftToken {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct Profile {
+    id: String,
+    name: String,
+}
+
+impl Mojang {
+    /// A session of the Microsoft account `email`. Without the refresh token of an earlier login this waits for
+    /// someone to enter the device code logged for the account.
+    pub async fn microsoft(&self, email: &str, refresh_token: Option<&str>) -> Res<AuthResponse> {
+        let microsoft = match refresh_token {
+            Some(refresh_token) => self.refresh_microsoft(refresh_token).await?,
+            None => self.device_code(email).await?,
+        };
+
+        let xbox: XboxToken = self.post_json(Endpoint::Xbox, json!({
+            "Properties": {
+                "AuthMethod": "RPS",
+                "SiteName": "user.auth.xboxlive.com",
+                "RpsTicket": format!("d={}", microsoft.access_token)
+            },
+            "RelyingParty": "http://auth.xboxlive.com",
+            "TokenType": "JWT"
+        })).await?;
+
+        let xsts: XboxToken = self.post_json(Endpoint::Xsts, json!({
+            "Properties": {
+                "SandboxId": "RETAIL",
+                "UserTokens": [xbox.token]
+            },
+            "RelyingParty": "rp://api.minecraftservices.com/",
+            "TokenType": "JWT"
+        })).await?;
+
+        let hash = match xsts.display_claims.xui.first() {
+            Some(UserHash { uhs }) => uhs,
+            None => return Err(self.error(Endpoint::Xsts, MojangErrKind::Malformed("no user hash".to_string()))),
+        };
+
+        let minecraft: MinecraftToken = self.post_json(Endpoint::XboxLogin, json!({
+            "identityToken": format!("XBL3.0 x={};{}", hash, xsts.token)
+        })).await?;
+
+        // accounts which do not own the game have no profile
+        let res = self.profile(&minecraft.access_token).await?;
+        if !res.status().is_success() {
+            return Err(self.rejected(Endpoint::Profile, res).await);
+        }
+        let profile: Profile = self.parse(Endpoint::Profile, res).await?;
+
+        Ok(AuthResponse {
+            access_token: minecraft.access_token,
+            client_token: String::new(),
+            username: profile.name,
+            uuid: UUID::from(&profile.id),
+            refresh_token: Some(microsoft.refresh_token),
+        })
+    }
+
+    /// whether the Minecraft token of a Microsoft account still works
+    pub async fn validate_microsoft(&self, access_token: &str) -> Res<bool> {
+        let res = self.profile(access_token).await?;
+        Ok(res.status().is_success())
+    }
+
+    fn client_id(&self) -> Res<&str> {
+        self.config.microsoft_client_id.as_deref().ok_or_else(|| err("Microsoft accounts need --msa-client-id"))
+    }
+
+    async fn refresh_microsoft(&self, refresh_token: &str) -> Res<MicrosoftToken> {
+        let url = Endpoint::Token.url(&self.config);
+        let form = [("grant_type", "refresh_token"), ("client_id", self.client_id()?), ("scope", SCOPE), ("refresh_token", refresh_token)];
+
+        let res = self.send(Endpoint::Token, || self.client.post(&url).form(&form)).await?;
+        if !res.status().is_success() {
+            return Err(self.rejected(Endpoint::Token, res).await);
+        }
+        self.parse(Endpoint::Token, res).await
+    }
+
+    /// Log the device code and poll until someone entered it or it expired
+    async fn device_code(&self, email: &str) -> Res<MicrosoftToken> {
+        let client_id = self.client_id()?;
+
+        let url = Endpoint::DeviceCode.url(&self.config);
+        let res = self.send(Endpoint::DeviceCode, || self.client.post(&url).form(&[("client_id", client_id), ("scope", SCOPE)])).await?;
+        if !res.status().is_success() {
+            return Err(self.rejected(Endpoint::DeviceCode, res).await);
+        }
+        let code: DeviceCode = self.parse(Endpoint::DeviceCode, res).await?;
+
+        info!(account = email, "{}", code.message);
+
+        let url = Endpoint::Token.url(&self.config);
+        let form = [("grant_type", DEVICE_CODE_GRANT), ("client_id", client_id), ("device_code", code.device_code.as_str())];
+        let expires = Instant::now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval);
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let res = self.send(Endpoint::Token, || self.client.post(&url).form(&form)).await?;
+            let status = res.status();
+            if status.is_success() {
+                return self.parse(Endpoint::Token, res).await;
+            }
+
+            let TokenError { error } = self.parse(Endpoint::Token, res).await?;
+            match error.as_str() {
+                "authorization_pending" if Instant::now() < expires => {}
+                "slow_down" => interval += SLOW_DOWN,
+
+                // declined or expired
+                _ => return Err(self.error(Endpoint::Token, MojangErrKind::InvalidCredentials { error_code: status, info: Some(error) })),
+            }
+        }
+    }
+
+    /// post json to Xbox Live or the Minecraft services, which need it declared
+    async fn post_json<T: DeserializeOwned>(&self, endpoint: Endpoint, payload: Value) -> Res<T> {
+        let url = endpoint.url(&self.config);
+        let payload = payload.to_string();
+
+        let res = self.send(endpoint, || {
+            self.client.post(&url)
+                .header(CONTENT_TYPE, "application/json")
+                .header(ACCEPT, "application/json")
+                .body(payload.clone())
+        }).await?;
+
+        if !res.status().is_success() {
+            return Err(self.rejected(endpoint, res).await);
+        }
+        self.parse(endpoint, res).await
+    }
+
+    async fn profile(&self, access_token: &str) -> Res<reqwest::Response> {
+        let url = Endpoint::Profile.url(&self.config);
+        self.send(Endpoint::Profile, || self.client.get(&url).bearer_auth(access_token)).await
+    }
+}
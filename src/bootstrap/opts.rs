@@ -20,7 +20,8 @@ use clap::{AppSettings, Clap};
 #[clap(version = "1.0", author = "Andrew Gazelka")]
 #[clap(setting = AppSettings::ColoredHelp)]
 pub struct Opts {
-    pub host: String,
+    #[clap(required_unless_present_any = &["replay", "servers", "cache_pack", "cache_unpack"])]
+    pub host: Option<String>,
 
     #[clap(long)]
     pub load: bool,
@@ -28,20 +29,176 @@ pub struct Opts {
     #[clap(short, long, default_value = "1")]
     pub count: usize,
 
+    /// how many extra users to keep logged out for `#swarm add`
+    #[clap(long, default_value = "0")]
+    pub reserve: usize,
+
     #[clap(long, default_value = "25565")]
     pub port: u16,
 
+    /// the minimum amount of milliseconds between starting two logins
     #[clap(short, long, default_value = "500")]
     pub delay: u64,
 
+    /// a random amount of milliseconds up to this is added to the delay between logins
+    #[clap(long, default_value = "250")]
+    pub login_jitter: u64,
+
+    /// the maximum amount of logins in progress at once
+    #[clap(long, default_value = "5")]
+    pub login_concurrency: usize,
+
+    /// how many times a throttled login is attempted
+    #[clap(long, default_value = "5")]
+    pub login_attempts: u32,
+
+    /// the most milliseconds connecting to Mojang through a proxy, or a whole request, may take
+    #[clap(long, default_value = "10000")]
+    pub mojang_timeout: u64,
+
+    /// how many times a session validation or server join is retried after a timeout or a Mojang error
+    #[clap(long, default_value = "2")]
+    pub mojang_retries: u32,
+
+    /// the id of the Azure application Microsoft accounts (`msa` as the password in the users file) log in with
+    #[clap(long)]
+    pub msa_client_id: Option<String>,
+
+    /// the minimum amount of milliseconds between two chat messages of a bot
+    #[clap(long, default_value = "1000")]
+    pub chat_interval: u64,
+
+    /// a random amount of milliseconds up to this is added to the interval between chat messages
+    #[clap(long, default_value = "250")]
+    pub chat_jitter: u64,
+
+    /// remove `§` formatting codes from outgoing chat, for servers that kick players sending them
+    #[clap(long)]
+    pub chat_strip_formatting: bool,
+
+    /// warn when a worn piece of armor can take at most this many more hits
+    #[clap(long, default_value = "10")]
+    pub armor_warn: u16,
+
+    /// take off armor (and elytras) which can take at most this many more hits, so it does not break
+    #[clap(long)]
+    pub armor_unequip: Option<u16>,
+
+    /// the most degrees the head turns in a tick. Servers with rotation checks flag heads that snap around.
+    #[clap(long, default_value = "35")]
+    pub max_rotation: f32,
+
+    /// log every account in with its password instead of using the sessions cached in sessions.json
+    #[clap(long)]
+    pub fresh_auth: bool,
+
+    /// carry on with the tasks the bots had when the last run stopped, as saved in snapshot.json
+    #[clap(long)]
+    pub resume: bool,
+
     #[clap(long, default_value = "users.csv")]
     pub users_file: String,
 
     #[clap(long, default_value = "proxies.csv")]
     pub proxies_file: String,
 
-    #[clap(short, long, default_value = "340")]
+    /// extra patterns for classifying disconnect messages, one `<reason>: <pattern>` per line
+    #[clap(long)]
+    pub disconnect_patterns: Option<String>,
+
+    /// weights of block ids for detecting player bases, one `<id>: <weight>` per line. `threshold: <score>` sets the
+    /// score a chunk section needs to be reported.
+    #[clap(long)]
+    pub base_weights: Option<String>,
+
+    /// who may command the bots through chat, one `<username or uuid>: <status|movement|admin>` per line.
+    /// `whisper-only: true` ignores commands in public chat and `refuse: true` answers players who are not allowed.
+    /// Anyone may use every command without this.
+    #[clap(long)]
+    pub permissions: Option<String>,
+
+    /// how many loaded chunks around a bot `#find` searches
+    #[clap(long, default_value = "256")]
+    pub find_chunks: usize,
+
+    /// settings which can be changed while running with `#reload-config`, one `<option>: <value>` per line (i.e.,
+    /// `chat-interval: 1500`). They take precedence over the command line.
+    #[clap(long)]
+    pub config: Option<String>,
+
+    /// run parts of the swarm on different servers, one `<name>: <host>[:<port>] <version> <online|offline>
+    /// <count|email,email,..>` per line. The host, --port, --version and --count are not used then. Websocket commands
+    /// name their server like `{"path": "<name>:goto", ..}`.
+    #[clap(long)]
+    pub servers: Option<String>,
+
+    /// the protocol version: 340 (1.12.2) or 754 (1.16.5). 0 (the default) asks the server with a server list ping.
+    #[clap(short, long, default_value = "0")]
     pub version: usize,
+
+    /// print the server list status of the server and exit. Exits with code 1 if the server cannot be pinged or does
+    /// not meet --expect-protocol and --players-below.
+    #[clap(long)]
+    pub ping: bool,
+
+    /// with --ping, require the server to run this protocol version
+    #[clap(long)]
+    pub expect_protocol: Option<i32>,
+
+    /// with --ping, require fewer than this many players to be online
+    #[clap(long)]
+    pub players_below: Option<i32>,
+
+    /// ping (--ping and --version 0) through the proxies in the proxies file instead of directly
+    #[clap(long)]
+    pub ping_proxy: bool,
+
+    /// record every bot's session to this directory
+    #[clap(long)]
+    pub record: Option<String>,
+
+    /// print the timeline of a recorded session and exit. With --script, the seed to run the script with instead.
+    #[clap(long)]
+    pub replay: Option<String>,
+
+    /// run this script of a simulated swarm with the seed given to --replay and exit. See `client::sim::replay`.
+    #[clap(long, requires = "replay")]
+    pub script: Option<String>,
+
+    /// with --script, compare the run against this baseline and report the first tick it differs. The baseline is
+    /// recorded if the file does not exist yet.
+    #[clap(long, requires = "script")]
+    pub baseline: Option<String>,
+
+    /// only print the last given seconds of the replay (e.g., before a disconnect)
+    #[clap(long)]
+    pub replay_last: Option<u64>,
+
+    /// the world file (as written by `WorldBlocks::save`) --cache-pack and --cache-unpack work with
+    #[clap(long, default_value = "world.bin")]
+    pub world_file: String,
+
+    /// pack the columns of --world-file into this archive and exit. The archive names the host, so it is not unpacked
+    /// for another server by mistake.
+    #[clap(long)]
+    pub cache_pack: Option<String>,
+
+    /// with --cache-pack, only pack the columns within the blocks `x1,z1,x2,z2`
+    #[clap(long)]
+    pub cache_bounds: Option<String>,
+
+    /// merge an archive made with --cache-pack into --world-file and exit. The newest of each column is kept. An
+    /// interrupted unpack carries on where it stopped when it is run again.
+    #[clap(long)]
+    pub cache_unpack: Option<String>,
+
+    /// write a session digest to this directory daily and when all bots have disconnected
+    #[clap(long)]
+    pub digest: Option<String>,
+
+    /// also post the digest as JSON to this webhook (works with Discord and Slack webhooks)
+    #[clap(long)]
+    pub digest_webhook: Option<String>,
 }
 
 impl Opts {
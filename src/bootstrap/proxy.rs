@@ -0,0 +1,269 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::bootstrap::{Proxy, tunnel};
+
+/// how long a health check may take before the proxy is considered dead
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// how often proxies are re-checked
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Health {
+    /// never checked
+    Unknown,
+    Healthy,
+
+    /// a request through the proxy failed. It is not handed out until a health check passes.
+    Suspect,
+
+    /// failed a health check
+    Dead,
+}
+
+impl Health {
+    fn usable(self) -> bool {
+        matches!(self, Health::Unknown | Health::Healthy)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ProxyStats {
+    pub successes: u32,
+    pub failures: u32,
+}
+
+#[derive(Debug)]
+struct Entry {
+    proxy: Proxy,
+    health: Health,
+    stats: ProxyStats,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: Vec<Entry>,
+
+    /// account (email) -> index of the proxy it was assigned
+    pins: HashMap<String, usize>,
+}
+
+/// A pool of proxies which only hands out healthy proxies. An account keeps the same proxy (sticky pinning) until
+/// that proxy goes bad, at which point it is rotated to another one.
+///
+/// Clones share the same pool.
+#[derive(Clone, Debug, Default)]
+pub struct ProxyPool {
+    inner: Arc<Mutex<Inner>>,
+}
+
+/// A proxy handed out by a [`ProxyPool`]. Used to report back how the proxy is doing.
+#[derive(Clone, Debug)]
+pub struct ProxyLease {
+    pool: ProxyPool,
+    idx: usize,
+    pub proxy: Proxy,
+}
+
+/// A row of [`ProxyPool::stats`]
+#[derive(Debug)]
+pub struct ProxyStatus {
+    pub address: String,
+    pub health: Health,
+    pub stats: ProxyStats,
+    pub pinned: usize,
+}
+
+impl ProxyPool {
+    pub fn new(proxies: Vec<Proxy>) -> ProxyPool {
+        let entries = proxies.into_iter()
+            .map(|proxy| Entry { proxy, health: Health::Unknown, stats: ProxyStats::default() })
+            .collect();
+
+        ProxyPool {
+            inner: Arc::new(Mutex::new(Inner { entries, pins: HashMap::new() }))
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<Inner> {
+        self.inner.lock().unwrap()
+    }
+
+    /// The proxy pinned to `account` if it is still usable. Otherwise the usable proxy with the least accounts pinned
+    /// to it, which then becomes pinned. None if there are no usable proxies.
+    pub fn assign(&self, account: &str) -> Option<ProxyLease> {
+        let mut inner = self.lock();
+
+        if let Some(&idx) = inner.pins.get(account) {
+            if inner.entries[idx].health.usable() {
+                return Some(self.lease(&inner, idx));
+            }
+        }
+
+        let mut pinned = vec![0_usize; inner.entries.len()];
+        for &idx in inner.pins.values() {
+            pinned[idx] += 1;
+        }
+
+        // prefer proxies that are known to work
+        let idx = inner.entries.iter().enumerate()
+            .filter(|(_, entry)| entry.health.usable())
+            .min_by_key(|(idx, entry)| (entry.health != Health::Healthy, pinned[*idx]))
+            .map(|(idx, _)| idx)?;
+
+        inner.pins.insert(account.to_string(), idx);
+        Some(self.lease(&inner, idx))
+    }
+
+    fn lease(&self, inner: &Inner, idx: usize) -> ProxyLease {
+        ProxyLease {
+            pool: self.clone(),
+            idx,
+            proxy: inner.entries[idx].proxy.clone(),
+        }
+    }
+
+    /// Check every proxy concurrently by connecting to `target` through it
+    pub async fn check(&self, target: &str, timeout: Duration) {
+        let proxies: Vec<_> = self.lock().entries.iter().map(|entry| entry.proxy.clone()).collect();
+
+        let checks = proxies.iter().map(|proxy| async move {
+            let connect = tunnel::connect(proxy, target);
+            matches!(tokio::time::timeout(timeout, connect).await, Ok(Ok(_)))
+        });
+
+        let results = futures::future::join_all(checks).await;
+
+        let mut inner = self.lock();
+        for (idx, healthy) in results.into_iter().enumerate() {
+            inner.entries[idx].health = if healthy { Health::Healthy } else { Health::Dead };
+        }
+
+        let healthy = inner.entries.iter().filter(|entry| entry.health == Health::Healthy).count();
+        info!(healthy, total = inner.entries.len(), "checked proxies");
+    }
+
+    /// Re-check every proxy every `interval`
+    pub fn spawn_checks(&self, target: String, interval: Duration) {
+        let pool = self.clone();
+        tokio::task::spawn_local(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                pool.check(&target, CHECK_TIMEOUT).await;
+            }
+        });
+    }
+
+    pub fn stats(&self) -> Vec<ProxyStatus> {
+        let inner = self.lock();
+        inner.entries.iter().enumerate()
+            .map(|(idx, entry)| ProxyStatus {
+                address: entry.proxy.url(),
+                health: entry.health,
+                stats: entry.stats,
+                pinned: inner.pins.values().filter(|&&pinned| pinned == idx).count(),
+            })
+            .collect()
+    }
+}
+
+impl ProxyLease {
+    pub fn success(&self) {
+        let mut inner = self.pool.lock();
+        let entry = &mut inner.entries[self.idx];
+        entry.stats.successes += 1;
+    }
+
+    /// mark the proxy as suspect, so it will not be handed out until it passes a health check
+    pub fn failure(&self) {
+        let mut inner = self.pool.lock();
+        let entry = &mut inner.entries[self.idx];
+        entry.stats.failures += 1;
+        if entry.health != Health::Dead {
+            entry.health = Health::Suspect;
+        }
+    }
+
+    /// get a new proxy for `account` from the same pool
+    pub fn rotate(&self, account: &str) -> Option<ProxyLease> {
+        self.pool.assign(account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bootstrap::{Proxy, ProxyKind};
+    use crate::bootstrap::proxy::{Health, ProxyPool};
+
+    fn proxy(port: u32) -> Proxy {
+        Proxy {
+            kind: ProxyKind::Socks5,
+            host: "127.0.0.1".to_string(),
+            port,
+            user: "user".to_string(),
+            pass: "pass".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_pinning_and_rotation() {
+        let pool = ProxyPool::new(vec![proxy(1), proxy(2), proxy(3)]);
+
+        let a = pool.assign("a").unwrap();
+        let b = pool.assign("b").unwrap();
+
+        // accounts are spread over the proxies
+        assert_ne!(a.proxy.port, b.proxy.port);
+
+        // sticky
+        assert_eq!(pool.assign("a").unwrap().proxy.port, a.proxy.port);
+
+        // a failure rotates the account to a different proxy
+        a.failure();
+        let rotated = a.rotate("a").unwrap();
+        assert_ne!(rotated.proxy.port, a.proxy.port);
+        assert_eq!(pool.assign("a").unwrap().proxy.port, rotated.proxy.port);
+
+        rotated.failure();
+        b.failure();
+
+        // every proxy is suspect
+        assert!(pool.assign("c").is_none());
+
+        let stats = pool.stats();
+        assert_eq!(stats.iter().map(|status| status.stats.failures).sum::<u32>(), 3);
+        assert!(stats.iter().all(|status| status.health == Health::Suspect));
+    }
+
+    #[test]
+    fn test_check_dead() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+        // nothing listens on port 1 so the proxy is dead
+        let pool = ProxyPool::new(vec![proxy(1)]);
+        rt.block_on(pool.check("127.0.0.1:25565", Duration::from_secs(1)));
+
+        assert_eq!(pool.stats()[0].health, Health::Dead);
+        assert!(pool.assign("a").is_none());
+    }
+}
@@ -0,0 +1,338 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::bootstrap::mojang::RateLimit;
+use crate::error::{Error, MojangErr, MojangErrKind, SwarmError};
+use crate::protocol::disconnect::{DisconnectPatterns, KickHistory, Reconnect};
+
+#[derive(Clone, Debug)]
+pub struct LoginConfig {
+    /// how many logins can be in progress at once
+    pub max_concurrent: usize,
+
+    /// the minimum time between starting two logins
+    pub min_delay: Duration,
+
+    /// a random amount of time up to this is added to `min_delay`
+    pub jitter: Duration,
+
+    /// how many times a throttled login is attempted before giving up
+    pub max_attempts: u32,
+
+    /// the wait before the first retry. Doubled for every retry after.
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+
+    /// the wait before retrying when the server is restarting
+    pub restart_delay: Duration,
+
+    /// how disconnect messages are classified
+    pub disconnects: DisconnectPatterns,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 5,
+            min_delay: Duration::from_millis(500),
+            jitter: Duration::from_millis(250),
+            max_attempts: 5,
+            backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(60),
+            restart_delay: Duration::from_secs(15),
+            disconnects: DisconnectPatterns::default(),
+        }
+    }
+}
+
+/// Whether a failed login is worth retrying. Logging in too fast (the server's connection throttle or Mojang's rate
+/// limit) and Mojang being unreachable back off, a server that is restarting is retried soon, and bans and invalid
+/// sessions are never retried.
+pub fn login_reconnect(error: &SwarmError, patterns: &DisconnectPatterns) -> Reconnect {
+    match error {
+        SwarmError::Protocol { source: Error::Disconnect(reason), .. } => patterns.classify(reason).reason.reconnect(),
+        SwarmError::Auth { source: MojangErr { kind, .. }, .. } if matches!(kind, MojangErrKind::RateLimited | MojangErrKind::Timeout | MojangErrKind::Server(_)) => Reconnect::Backoff,
+
+        // some servers just close the socket if we connect too often
+        SwarmError::Protocol { source: Error::IO(io), .. } if matches!(io.kind(), ErrorKind::ConnectionReset | ErrorKind::UnexpectedEof) => Reconnect::Backoff,
+
+        // nothing is listening while the server restarts
+        SwarmError::Protocol { source: Error::IO(io), .. } if io.kind() == ErrorKind::ConnectionRefused => Reconnect::Soon,
+        _ => Reconnect::Never
+    }
+}
+
+/// Staggers logins. All logins (including reconnects) should go through the same scheduler (clones share limits) so
+/// many bots connecting at once cannot cause a stampede.
+#[derive(Clone)]
+pub struct LoginScheduler {
+    /// shared by clones so `set_config` changes every login started afterwards
+    config: Rc<RefCell<LoginConfig>>,
+    permits: Arc<Semaphore>,
+
+    /// the earliest time the next login may start, set when a login starts
+    next_start: Rc<Cell<Instant>>,
+
+    /// disconnects while logging in are recorded here
+    kicks: KickHistory,
+
+    /// no login starts while Mojang is rate limiting us
+    rate_limit: RateLimit,
+}
+
+impl LoginScheduler {
+    pub fn new(config: LoginConfig) -> LoginScheduler {
+        LoginScheduler::with_rate_limit(config, RateLimit::default())
+    }
+
+    /// a scheduler which pauses logins whenever a Mojang client sharing `rate_limit` is rate limited
+    pub fn with_rate_limit(config: LoginConfig, rate_limit: RateLimit) -> LoginScheduler {
+        LoginScheduler {
+            permits: Arc::new(Semaphore::new(config.max_concurrent)),
+            next_start: Rc::new(Cell::new(Instant::now())),
+            kicks: KickHistory::default(),
+            rate_limit,
+            config: Rc::new(RefCell::new(config)),
+        }
+    }
+
+    /// Change how fast logins happen. `max_concurrent` cannot change as the permits are already handed out. Logins
+    /// which are retrying keep the config they started with.
+    pub fn set_config(&self, config: LoginConfig) {
+        *self.config.borrow_mut() = config;
+    }
+
+    pub fn kicks(&self) -> &KickHistory {
+        &self.kicks
+    }
+
+    /// Wait until a login may start. The login counts towards `max_concurrent` until the permit is dropped.
+    pub async fn slot(&self) -> OwnedSemaphorePermit {
+        let permit = self.permits.clone().acquire_owned().await.unwrap();
+
+        let (min_delay, jitter) = {
+            let config = self.config.borrow();
+            (config.min_delay, config.jitter)
+        };
+
+        let jitter = match jitter.as_millis() as u64 {
+            0 => Duration::ZERO,
+            millis => Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+        };
+
+        // The next start is only set once we actually start, so a late wakeup cannot shorten the gap to the next
+        // login. Others waiting for the same time wake too and wait again for the new one.
+        loop {
+            let now = Instant::now();
+            let mut start = self.next_start.get();
+            if let Some(until) = self.rate_limit.until() {
+                start = start.max(until);
+            }
+
+            if start <= now {
+                self.next_start.set(now + min_delay + jitter);
+                return permit;
+            }
+
+            tokio::time::sleep_until(tokio::time::Instant::from_std(start)).await;
+        }
+    }
+
+    /// Run `attempt` whenever the scheduler allows it. Failed attempts are retried as [`login_reconnect`] decides,
+    /// throttled attempts with exponential backoff.
+    /// Returns None if the login could not be completed.
+    pub async fn run<T, F, Fut>(&self, name: &str, mut attempt: F) -> Option<T>
+        where F: FnMut() -> Fut,
+              Fut: Future<Output=Result<T, SwarmError>> {
+        let config = self.config.borrow().clone();
+        let mut backoff = config.backoff;
+
+        for attempt_on in 1..=config.max_attempts {
+            let permit = self.slot().await;
+            let res = attempt().await;
+            drop(permit);
+
+            let err = match res {
+                Ok(res) => return Some(res),
+                Err(err) => err
+            };
+
+            if let SwarmError::Protocol { source: Error::Disconnect(reason), .. } = &err {
+                self.kicks.record(name, &config.disconnects.classify(reason));
+            }
+
+            let wait = match login_reconnect(&err, &config.disconnects) {
+                _ if attempt_on == config.max_attempts => None,
+                Reconnect::Never => None,
+                Reconnect::Soon => Some(config.restart_delay),
+                Reconnect::Backoff => {
+                    let wait = backoff;
+                    backoff = (backoff * 2).min(config.max_backoff);
+                    Some(wait)
+                }
+            };
+
+            match wait {
+                Some(wait) => {
+                    warn!(attempt = attempt_on, retry_in = ?wait, error = %err, "login failed");
+                    tokio::time::sleep(wait).await;
+                }
+                None => {
+                    warn!(error = %err, "giving up logging in");
+                    return None;
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use reqwest::StatusCode;
+
+    use crate::bootstrap::mojang::RateLimit;
+    use crate::bootstrap::schedule::{login_reconnect, LoginConfig, LoginScheduler};
+    use crate::error::{Error, err, MojangErr, MojangErrKind, Phase, SwarmError};
+    use crate::protocol::disconnect::{DisconnectPatterns, Reconnect};
+
+    /// mock logins which take some time, one of which is throttled twice
+    #[test]
+    fn test_rate() {
+        const LOGINS: usize = 6;
+
+        let config = LoginConfig {
+            max_concurrent: 2,
+            min_delay: Duration::from_millis(20),
+            jitter: Duration::from_millis(5),
+            max_attempts: 3,
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(20),
+            ..LoginConfig::default()
+        };
+
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let local = tokio::task::LocalSet::new();
+
+        let starts = Rc::new(RefCell::new(Vec::new()));
+        let in_progress = Rc::new(Cell::new(0));
+        let max_in_progress = Rc::new(Cell::new(0));
+        let throttles_left = Rc::new(Cell::new(2));
+        let done = Rc::new(Cell::new(0));
+
+        let scheduler = LoginScheduler::new(config);
+
+        for bot in 0..LOGINS {
+            let scheduler = scheduler.clone();
+            let starts = starts.clone();
+            let in_progress = in_progress.clone();
+            let max_in_progress = max_in_progress.clone();
+            let throttles_left = throttles_left.clone();
+            let done = done.clone();
+
+            local.spawn_local(async move {
+                let res = scheduler.run("mock", || {
+                    let starts = starts.clone();
+                    let in_progress = in_progress.clone();
+                    let max_in_progress = max_in_progress.clone();
+                    let throttles_left = throttles_left.clone();
+                    async move {
+                        starts.borrow_mut().push(Instant::now());
+                        in_progress.set(in_progress.get() + 1);
+                        max_in_progress.set(max_in_progress.get().max(in_progress.get()));
+
+                        tokio::time::sleep(Duration::from_millis(30)).await;
+                        in_progress.set(in_progress.get() - 1);
+
+                        if bot == 0 && throttles_left.get() > 0 {
+                            throttles_left.set(throttles_left.get() - 1);
+                            let throttled = Error::Disconnect("Connection throttled! Please wait before reconnecting.".to_string());
+                            return Err(SwarmError::new("mock", Phase::Join, throttled));
+                        }
+                        Ok(bot)
+                    }
+                }).await;
+
+                assert_eq!(res, Some(bot));
+                done.set(done.get() + 1);
+            });
+        }
+
+        rt.block_on(local);
+
+        // every bot eventually logged in
+        assert_eq!(done.get(), LOGINS);
+
+        // 2 extra attempts because of the throttling
+        let starts = starts.borrow();
+        assert_eq!(scheduler.kicks().count("mock"), 2);
+        assert_eq!(starts.len(), LOGINS + 2);
+
+        assert!(max_in_progress.get() <= 2);
+
+        for pair in starts.windows(2) {
+            let between = pair[1] - pair[0];
+            assert!(between >= Duration::from_millis(20), "logins started {:?} apart", between);
+        }
+    }
+
+    #[test]
+    fn test_login_reconnect() {
+        let patterns = DisconnectPatterns::default();
+        let reconnect = |error: Error| login_reconnect(&SwarmError::new("bot", Phase::Join, error), &patterns);
+
+        assert_eq!(reconnect(Error::Disconnect("{\"text\":\"Connection throttled! Please wait before reconnecting.\"}".to_string())), Reconnect::Backoff);
+        assert_eq!(reconnect(Error::Disconnect("You are banned".to_string())), Reconnect::Never);
+        assert_eq!(reconnect(Error::Disconnect("{\"text\":\"Server is restarting\"}".to_string())), Reconnect::Soon);
+        assert_eq!(reconnect(Error::IO(std::io::ErrorKind::ConnectionRefused.into())), Reconnect::Soon);
+        assert_eq!(reconnect(err("other")), Reconnect::Never);
+
+        let mojang = |kind| Error::Mojang(MojangErr { endpoint: "join", proxy: "127.0.0.1:1080".to_string(), kind });
+        assert_eq!(reconnect(mojang(MojangErrKind::RateLimited)), Reconnect::Backoff);
+        assert_eq!(reconnect(mojang(MojangErrKind::Timeout)), Reconnect::Backoff);
+        assert_eq!(reconnect(mojang(MojangErrKind::InvalidCredentials { error_code: StatusCode::FORBIDDEN, info: None })), Reconnect::Never);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let rate_limit = RateLimit::default();
+        let config = LoginConfig { jitter: Duration::ZERO, ..LoginConfig::default() };
+        let scheduler = LoginScheduler::with_rate_limit(config, rate_limit.clone());
+
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+        // a Mojang client sharing the limit was rate limited
+        rate_limit.limited(Duration::from_millis(50));
+        let start = Instant::now();
+        drop(rt.block_on(scheduler.slot()));
+        assert!(start.elapsed() >= Duration::from_millis(45), "started after {:?}", start.elapsed());
+    }
+}
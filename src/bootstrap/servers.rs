@@ -0,0 +1,310 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Running parts of the swarm on different servers from one process. Each server gets its own bots and world while the
+//! proxies and cached sessions are shared.
+
+use std::collections::HashMap;
+use std::fs;
+
+use crate::bootstrap::cache::Sessions;
+use crate::bootstrap::CSVUser;
+use crate::bootstrap::mojang::{Mojang, MojangConfig};
+use crate::bootstrap::proxy::ProxyPool;
+use crate::bootstrap::storage::{ProxyUser, ValidUser};
+use crate::error::{err, Res};
+
+/// which accounts play on a server
+#[derive(Clone, Debug, PartialEq)]
+pub enum Accounts {
+    /// these accounts by email (the username on offline servers)
+    Named(Vec<String>),
+
+    /// this many of the accounts no server names
+    Count(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerTarget {
+    /// scopes websocket commands to the server, i.e., `{"path": "<name>:goto", ..}`
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+
+    /// the protocol version, 0 to ping the server for it
+    pub version: usize,
+
+    /// Whether the accounts authenticate with Mojang. On offline servers the email column of the users file is the
+    /// username and no session is needed.
+    pub online: bool,
+    pub accounts: Accounts,
+}
+
+impl ServerTarget {
+    /// an online server on the default port, in the version it reports
+    pub fn new(name: impl Into<String>, host: impl Into<String>, accounts: Accounts) -> ServerTarget {
+        ServerTarget {
+            name: name.into(),
+            host: host.into(),
+            port: 25565,
+            version: 0,
+            online: true,
+            accounts,
+        }
+    }
+
+    /// how many bots are kept online
+    pub fn bots(&self) -> usize {
+        match &self.accounts {
+            Accounts::Named(names) => names.len(),
+            Accounts::Count(count) => *count,
+        }
+    }
+
+    /// Read servers from a file, one `<name>: <host>[:<port>] <version> <online|offline> <count|email,email,..>` per
+    /// line (i.e., `hub: play.example.com 340 online 5`)
+    pub fn load(path: &str) -> Res<Vec<ServerTarget>> {
+        ServerTarget::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(file: &str) -> Res<Vec<ServerTarget>> {
+        let mut servers: Vec<ServerTarget> = Vec::new();
+
+        for line in file.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let invalid = || err(&format!("expected <name>: <host>[:<port>] <version> <online|offline> <accounts> but got {}", line));
+
+            let (name, value) = line.split_once(':').ok_or_else(invalid)?;
+            let name = name.trim();
+            if servers.iter().any(|server| server.name == name) {
+                return Err(err(&format!("server {} is listed twice", name)));
+            }
+
+            let parts: Vec<_> = value.split_whitespace().collect();
+            let (address, version, mode, accounts) = match parts[..] {
+                [address, version, mode, accounts] => (address, version, mode, accounts),
+                _ => return Err(invalid()),
+            };
+
+            let (host, port) = match address.split_once(':') {
+                Some((host, port)) => (host, port.parse().map_err(|_| err(&format!("invalid port in {}", line)))?),
+                None => (address, 25565),
+            };
+
+            let version = version.parse().map_err(|_| err(&format!("invalid version in {}", line)))?;
+
+            let online = match mode {
+                "online" => true,
+                "offline" => false,
+                _ => return Err(invalid()),
+            };
+
+            let accounts = match accounts.parse() {
+                Ok(count) => Accounts::Count(count),
+                Err(_) => Accounts::Named(accounts.split(',').map(str::to_string).collect()),
+            };
+
+            servers.push(ServerTarget { port, version, online, ..ServerTarget::new(name, host, accounts) });
+        }
+
+        Ok(servers)
+    }
+}
+
+/// Which server each account plays on. Named accounts go to their server, the others fill the servers wanting a count
+/// in order. Offline servers are given their accounts up front, accounts for online servers once they have a session
+/// so an account which cannot log in is replaced by the next one.
+pub struct Split {
+    online: Vec<bool>,
+    named: HashMap<String, usize>,
+
+    /// how many more unnamed accounts each server wants
+    wanted: Vec<usize>,
+}
+
+impl Split {
+    /// servers counting accounts get `reserve` more for `#swarm add`
+    pub fn new(servers: &[ServerTarget], reserve: usize) -> Split {
+        let mut named = HashMap::new();
+        let mut wanted = Vec::with_capacity(servers.len());
+
+        for (idx, server) in servers.iter().enumerate() {
+            match &server.accounts {
+                Accounts::Named(names) => {
+                    for name in names {
+                        named.insert(name.clone(), idx);
+                    }
+                    wanted.push(0);
+                }
+                Accounts::Count(count) => wanted.push(count + reserve),
+            }
+        }
+
+        Split {
+            online: servers.iter().map(|server| server.online).collect(),
+            named,
+            wanted,
+        }
+    }
+
+    /// How many sessions the online servers need
+    pub fn sessions(&self) -> usize {
+        let named = self.named.values().filter(|&&idx| self.online[idx]).count();
+        let counted: usize = self.wanted.iter().zip(&self.online).filter(|(_, &online)| online).map(|(wanted, _)| wanted).sum();
+        named + counted
+    }
+
+    /// Divide `users` into the ones which need a session (for online servers) and the ones of each offline server
+    pub fn divide(&mut self, users: Vec<CSVUser>) -> (Vec<CSVUser>, Vec<Vec<CSVUser>>) {
+        let any_counting = self.wanted.iter().zip(&self.online).any(|(&wanted, &online)| online && wanted > 0);
+
+        let mut sessions = Vec::new();
+        let mut offline = vec![Vec::new(); self.online.len()];
+
+        for user in users {
+            match self.named.get(&user.email) {
+                Some(&idx) if !self.online[idx] => offline[idx].push(user),
+                Some(_) => sessions.push(user),
+                None => match self.take(false) {
+                    Some(idx) => offline[idx].push(user),
+                    None if any_counting => sessions.push(user),
+                    None => {}
+                },
+            }
+        }
+
+        (sessions, offline)
+    }
+
+    /// The server an account with a session plays on. None if no server wants it anymore.
+    pub fn route(&mut self, email: &str) -> Option<usize> {
+        match self.named.get(email) {
+            Some(&idx) => Some(idx),
+            None => self.take(true),
+        }
+    }
+
+    /// the first server which is `online` and still wants an unnamed account
+    fn take(&mut self, online: bool) -> Option<usize> {
+        let idx = (0..self.wanted.len()).find(|&idx| self.online[idx] == online && self.wanted[idx] > 0)?;
+        self.wanted[idx] -= 1;
+        Some(idx)
+    }
+}
+
+/// The uuid of a player on an offline server. Servers make one up from the name, bots only need it to be stable.
+fn offline_uuid(name: &str) -> String {
+    let mut sha1 = sha1::Sha1::new();
+    sha1.update(format!("OfflinePlayer:{}", name).as_bytes());
+    sha1.digest().bytes()[..16].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Users for an offline server. They still connect through the proxies but never talk to Mojang.
+pub fn offline_users(users: Vec<CSVUser>, pool: &ProxyPool, config: &MojangConfig) -> Vec<ProxyUser> {
+    users.into_iter()
+        .filter_map(|CSVUser { email, .. }| {
+            let proxy = match pool.assign(&email) {
+                Some(proxy) => proxy,
+                None => {
+                    warn!(name = %email, "no healthy proxy");
+                    return None;
+                }
+            };
+            let mojang = Mojang::proxied(&proxy, config).ok()?;
+            let user = ValidUser {
+                username: email.clone(),
+                uuid: offline_uuid(&email),
+                last_checked: 0,
+                access_id: String::new(),
+                client_id: String::new(),
+                refresh_token: None,
+                obtained: 0,
+                email,
+            };
+            Some(ProxyUser { user, proxy, mojang, sessions: Sessions::default() })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::CSVUser;
+    use crate::bootstrap::servers::{Accounts, ServerTarget, Split};
+
+    fn users(emails: &[&str]) -> Vec<CSVUser> {
+        emails.iter().map(|email| CSVUser { email: email.to_string(), password: String::new() }).collect()
+    }
+
+    fn emails(users: &[CSVUser]) -> Vec<&str> {
+        users.iter().map(|user| user.email.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let servers = ServerTarget::parse("# comment\nhub: play.example.com 340 online 5\ntest: localhost:25566 0 offline a,b\n").unwrap();
+        assert_eq!(servers, vec![
+            ServerTarget::new("hub", "play.example.com", Accounts::Count(5)),
+            ServerTarget {
+                port: 25566,
+                version: 0,
+                online: false,
+                ..ServerTarget::new("test", "localhost", Accounts::Named(vec!["a".to_string(), "b".to_string()]))
+            },
+        ]);
+
+        assert!(ServerTarget::parse("hub: play.example.com 340 5").is_err());
+        assert!(ServerTarget::parse("hub: play.example.com 340 sometimes 5").is_err());
+        assert!(ServerTarget::parse("hub: a 340 online 1\nhub: b 340 online 1").is_err());
+    }
+
+    #[test]
+    fn test_split() {
+        let servers = vec![
+            ServerTarget::new("a", "a.example.com", Accounts::Count(2)),
+            ServerTarget::new("b", "b.example.com", Accounts::Named(vec!["named".to_string()])),
+            ServerTarget { online: false, ..ServerTarget::new("c", "localhost", Accounts::Count(1)) },
+        ];
+
+        let mut split = Split::new(&servers, 0);
+        assert_eq!(split.sessions(), 3);
+
+        let (sessions, offline) = split.divide(users(&["1", "named", "2", "3", "4"]));
+
+        // the first unnamed account goes to the offline server as it needs no session
+        assert_eq!(emails(&offline[2]), vec!["1"]);
+        assert!(offline[0].is_empty() && offline[1].is_empty());
+        assert_eq!(emails(&sessions), vec!["named", "2", "3", "4"]);
+
+        // "2" could not log in, so "3" and "4" fill server a
+        assert_eq!(split.route("named"), Some(1));
+        assert_eq!(split.route("3"), Some(0));
+        assert_eq!(split.route("4"), Some(0));
+        assert_eq!(split.route("5"), None);
+    }
+
+    #[test]
+    fn test_split_reserve() {
+        let servers = vec![
+            ServerTarget::new("a", "a.example.com", Accounts::Count(1)),
+            ServerTarget::new("b", "b.example.com", Accounts::Count(1)),
+        ];
+
+        let mut split = Split::new(&servers, 1);
+        assert_eq!(split.sessions(), 4);
+
+        let routed: Vec<_> = ["1", "2", "3", "4", "5"].iter().map(|email| split.route(email)).collect();
+        assert_eq!(routed, vec![Some(0), Some(0), Some(1), Some(1), None]);
+    }
+}
@@ -14,69 +14,102 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+//! Sessions of the accounts, cached between runs so accounts do not log in with their password on every start. Mojang
+//! rate limits password logins (and locks out accounts which do it too often). See [`cache`](crate::bootstrap::cache)
+//! for encrypting the file and renewing the sessions.
 
 use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::fs;
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::Receiver;
 
-use crate::bootstrap::{CSVUser, Proxy};
-use crate::bootstrap::mojang::Mojang;
+use crate::bootstrap::cache::{read_sessions, RENEW_CHECK, RENEW_MARGIN, SessionKey, Sessions, TOKEN_LIFETIME};
+use crate::bootstrap::CSVUser;
+use crate::bootstrap::mojang::{AuthResponse, Authenticator, Mojang, MojangConfig};
+use crate::bootstrap::proxy::{ProxyLease, ProxyPool};
+use crate::error::{err, Res, SwarmContext};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 struct Root {
-    users: Vec<User>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-enum User {
-    Valid(ValidUser),
-    Invalid(InvalidUser),
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct InvalidUser {
-    email: String,
-    password: String,
+    users: Vec<ValidUser>,
 }
 
+/// The session and profile of an account. The password is not stored.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ValidUser {
     pub email: String,
     pub username: String,
-    pub password: String,
     pub last_checked: u64,
     pub uuid: String,
     pub access_id: String,
     pub client_id: String,
+
+    /// the Microsoft refresh token of a Microsoft account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+
+    /// when the access token was issued. 0 if not known.
+    #[serde(default)]
+    pub obtained: u64,
 }
 
-impl User {
-    fn email(&self) -> &String {
-        match self {
-            User::Valid(ValidUser { email, .. }) => email,
-            User::Invalid(InvalidUser { email, .. }) => email
+impl ValidUser {
+    fn new(email: &str, auth: AuthResponse) -> ValidUser {
+        ValidUser {
+            email: email.to_string(),
+            username: auth.username,
+            last_checked: time(),
+            uuid: auth.uuid.to_string(),
+            access_id: auth.access_token,
+            client_id: auth.client_token,
+            refresh_token: auth.refresh_token,
+            obtained: time(),
         }
     }
+
+    /// whether the access token expires within `margin` seconds of `now`
+    pub fn expiring(&self, now: u64, margin: u64) -> bool {
+        self.obtained + TOKEN_LIFETIME.as_secs() <= now + margin
+    }
 }
 
+/// The cached sessions by email. Only the task obtaining users owns the cache, so there is a single writer.
 pub struct UserCache {
     file_path: PathBuf,
-    cache: HashMap<String, User>,
+    cache: HashMap<String, ValidUser>,
+
+    /// log in with the password without looking at the cached sessions
+    fresh: bool,
+
+    /// the file is encrypted with the key if there is one
+    key: Option<SessionKey>,
 }
 
 
 /// A proxy user holds the "Mojang" object used in cache to verify that the user is valid along with
 /// data about what the proxy address is and the valid user information
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProxyUser {
     pub user: ValidUser,
-    pub proxy: Proxy,
+    pub proxy: ProxyLease,
     pub mojang: Mojang,
+
+    /// where the session of `user` is renewed
+    pub sessions: Sessions,
+}
+
+/// How a session was obtained
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Obtained {
+    /// the cached session was still valid
+    Cached,
+    Refreshed,
+
+    /// logged in with the password
+    Authenticated,
 }
 
 fn time() -> u64 {
@@ -87,166 +120,438 @@ fn time() -> u64 {
     since_the_epoch.as_secs()
 }
 
-impl UserCache {
-    pub fn load(file_path: PathBuf) -> UserCache {
-        let exists = std::fs::try_exists(&file_path).unwrap();
-        if !exists {
-            UserCache {
-                file_path,
-                cache: HashMap::new(),
+/// A session for `user`, the cheapest way first: the cached session if it is still valid, then refreshing it and only
+/// then logging in with the password.
+pub async fn session(auth: &impl Authenticator, user: &CSVUser, cached: Option<&ValidUser>) -> Res<(ValidUser, Obtained)> {
+    if user.microsoft() {
+        return microsoft_session(auth, user, cached).await;
+    }
+
+    if let Some(cached) = cached {
+        match auth.validate(&cached.access_id, &cached.client_id).await {
+            Ok(true) => {
+                let valid = ValidUser { last_checked: time(), ..cached.clone() };
+                return Ok((valid, Obtained::Cached));
             }
-        } else {
-            let file = File::open(&file_path).unwrap();
-            let bytes: Result<Vec<_>, _> = file.bytes().collect();
-            let bytes = bytes.unwrap();
-            let Root { users } = bincode::deserialize(&bytes).unwrap();
-
-            let cache: HashMap<_, _> = users.into_iter().map(|user| (user.email().clone(), user)).collect();
-            UserCache {
-                file_path,
-                cache,
+            Ok(false) => debug!(email = %user.email, "failed validating"),
+            Err(e) => debug!(email = %user.email, error = %e, "failed validating"),
+        }
+
+        match auth.refresh(&cached.access_id, &cached.client_id).await {
+            Ok(res) => return Ok((ValidUser::new(&user.email, res), Obtained::Refreshed)),
+            Err(e) => warn!(email = %user.email, error = %e, "failed refreshing"),
+        }
+    }
+
+    let res = auth.authenticate(&user.email, &user.password).await?;
+    Ok((ValidUser::new(&user.email, res), Obtained::Authenticated))
+}
+
+/// The same for a Microsoft account, which is refreshed with its Microsoft refresh token and otherwise waits for
+/// someone to enter a device code
+async fn microsoft_session(auth: &impl Authenticator, user: &CSVUser, cached: Option<&ValidUser>) -> Res<(ValidUser, Obtained)> {
+    let refresh = cached.and_then(|cached| cached.refresh_token.as_deref().map(|token| (cached, token)));
+    if let Some((cached, refresh_token)) = refresh {
+        match auth.validate_microsoft(&cached.access_id).await {
+            Ok(true) => {
+                let valid = ValidUser { last_checked: time(), ..cached.clone() };
+                return Ok((valid, Obtained::Cached));
             }
+            Ok(false) => debug!(email = %user.email, "failed validating"),
+            Err(e) => debug!(email = %user.email, error = %e, "failed validating"),
+        }
+
+        match auth.microsoft(&user.email, Some(refresh_token)).await {
+            Ok(res) => return Ok((ValidUser::new(&user.email, res), Obtained::Refreshed)),
+            Err(e) => warn!(email = %user.email, error = %e, "failed refreshing"),
+        }
+    }
+
+    let res = auth.microsoft(&user.email, None).await?;
+    Ok((ValidUser::new(&user.email, res), Obtained::Authenticated))
+}
+
+/// A new session for `user` before `current` expires. Only if it cannot be refreshed the account logs in with its
+/// password (or a device code) again.
+pub async fn renew(auth: &impl Authenticator, user: &CSVUser, current: &ValidUser) -> Res<(ValidUser, Obtained)> {
+    let refreshed = match current.refresh_token.as_deref() {
+        Some(refresh_token) if user.microsoft() => auth.microsoft(&user.email, Some(refresh_token)).await,
+        None if user.microsoft() => Err(err("no refresh token")),
+        _ => auth.refresh(&current.access_id, &current.client_id).await,
+    };
+
+    match refreshed {
+        Ok(res) => Ok((ValidUser::new(&user.email, res), Obtained::Refreshed)),
+        Err(e) => {
+            warn!(email = %user.email, error = %e, "failed refreshing");
+            session(auth, user, None).await
         }
     }
+}
+
+impl UserCache {
+    /// The sessions in `file_path` (none if it does not exist yet), decrypted with `key`. With `fresh` every account
+    /// logs in with its password and the cache is only written to.
+    pub fn load(file_path: PathBuf, fresh: bool, key: Option<SessionKey>) -> Res<UserCache> {
+        let Root { users } = match fs::read(&file_path) {
+            Ok(contents) => serde_json::from_slice(&read_sessions(contents, key.as_ref())?)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Root::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let cache = users.into_iter().map(|user| (user.email.clone(), user)).collect();
+        Ok(UserCache {
+            file_path,
+            cache,
+            fresh,
+            key,
+        })
+    }
+
+    /// Write the sessions to a temporary file first so a crash while writing cannot lose the cache
+    fn save(&self) -> Res {
+        let mut users: Vec<_> = self.cache.values().cloned().collect();
+        users.sort_by(|a, b| a.email.cmp(&b.email));
+
+        let json = serde_json::to_vec_pretty(&Root { users })?;
+        let contents = match &self.key {
+            Some(key) => key.seal(&json),
+            None => json,
+        };
+
+        let tmp = self.file_path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.file_path)?;
+        Ok(())
+    }
 
-    async fn get_or_put(&mut self, user: &CSVUser, iter: &mut impl Iterator<Item=Proxy>) -> Option<(Mojang, Proxy, ValidUser)> {
-        match self.cache.get_mut(&user.email) {
+    async fn get_or_put(&mut self, user: &CSVUser, pool: &ProxyPool, config: &MojangConfig) -> Option<(Mojang, ProxyLease, ValidUser)> {
+        let proxy = match pool.assign(&user.email) {
+            Some(proxy) => proxy,
             None => {
-                let proxy = iter.next().unwrap();
-                let mojang = Mojang::socks5(&proxy).unwrap();
-                match mojang.authenticate(&user.email, &user.password).await {
-                    Ok(res) => {
-                        let valid_user = ValidUser {
-                            email: user.email.clone(),
-                            username: res.username,
-                            password: user.password.clone(),
-                            last_checked: time(),
-                            uuid: res.uuid.to_string(),
-                            access_id: res.access_token,
-                            client_id: res.client_token,
-                        };
-                        let valid_user = valid_user;
-                        self.cache.insert(valid_user.email.clone(), User::Valid(valid_user.clone()));
-                        Some((mojang, proxy, valid_user))
-                    }
-
-                    // we cannot do anything more -> change to invalid
-                    Err(e) => {
-                        println!("failed authentication for {} .. {}", user.email, e);
-                        let invalid = InvalidUser {
-                            email: user.email.clone(),
-                            password: user.password.clone(),
-                        };
-                        self.cache.insert(invalid.email.clone(), User::Invalid(invalid));
-                        None
-                    }
-                }
+                warn!(email = %user.email, "no healthy proxy");
+                return None;
             }
-            Some(cached) => {
-                match cached {
-                    User::Valid(valid) => {
-                        let proxy = iter.next().unwrap();
-                        let mojang = Mojang::socks5(&proxy).unwrap();
-
-                        // if verified in last day don't even check to verify
-                        if time() - valid.last_checked < 3600 * 24 {
-                            return Some((mojang, proxy, valid.clone()));
-                        }
-
-                        let is_valid = mojang.validate(&valid.access_id, &valid.client_id).await.unwrap();
-
-                        if !is_valid {
-                            println!("failed validating {}", user.email);
-                            match mojang.refresh(&valid.access_id, &valid.client_id).await {
-                                Ok(auth) => {
-                                    valid.access_id = auth.access_token;
-                                    valid.username = auth.username;
-                                    valid.uuid = auth.uuid.to_string();
-                                    valid.client_id = auth.client_token;
-                                    valid.last_checked = time();
-                                    return Some((mojang, proxy, valid.clone()));
-                                }
-
-                                // we could not refresh -> try to authenticate
-                                Err(e) => {
-                                    println!("failed refreshing {} .. {}", user.email, e);
-                                    match mojang.authenticate(&valid.email, &valid.password).await {
-                                        Ok(auth) => {
-                                            valid.access_id = auth.access_token;
-                                            valid.username = auth.username;
-                                            valid.uuid = auth.uuid.to_string();
-                                            valid.client_id = auth.client_token;
-                                            valid.last_checked = time();
-                                            return Some((mojang, proxy, valid.clone()));
-                                        }
-
-                                        // we cannot do anything more -> change to invalid
-                                        Err(e) => {
-                                            println!("failed authenticating {} .. {}", user.email, e);
-                                            *cached = User::Invalid(InvalidUser {
-                                                email: valid.email.clone(),
-                                                password: valid.password.clone(),
-                                            })
-                                        }
-                                    }
-                                }
-                            }
-                        } else {
-                            return Some((mojang, proxy, valid.clone()));
-                        }
-                    }
-                    User::Invalid(_invalid) => {}
-                }
+        };
+
+        let mojang = Mojang::proxied(&proxy, config).unwrap();
+        let cached = if self.fresh { None } else { self.cache.get(&user.email) };
+
+        let res = session(&mojang, user, cached).await.ctx_account(&user.email);
+        match res {
+            Ok((valid, obtained)) => {
+                debug!(email = %user.email, ?obtained, "obtained session");
+                self.put(valid.clone());
+                Some((mojang, proxy, valid))
+            }
+
+            // we cannot do anything more. The session is of no use anymore.
+            Err(e) => {
+                warn!(error = %e, "failed authentication");
+                self.cache.remove(&user.email);
                 None
             }
         }
     }
 
-    pub fn obtain_users(mut self, count: usize, users: Vec<CSVUser>, proxies: Vec<Proxy>) -> Receiver<ProxyUser> {
-        let mut proxies = proxies.into_iter().cycle();
+    fn put(&mut self, valid: ValidUser) {
+        self.cache.insert(valid.email.clone(), valid);
+        if let Err(e) = self.save() {
+            warn!(file = %self.file_path.display(), error = %e, "could not save sessions");
+        }
+    }
+
+    /// Renew the sessions of `users` which are about to expire. Bots logging in afterwards use the new sessions.
+    async fn renew_expiring(&mut self, users: &[(CSVUser, Mojang)], sessions: &Sessions) {
+        let now = time();
+        for (user, mojang) in users {
+            let current = match self.cache.get(&user.email) {
+                Some(current) if current.expiring(now, RENEW_MARGIN.as_secs()) => current.clone(),
+                _ => continue,
+            };
+
+            match renew(mojang, user, &current).await.ctx_account(&user.email) {
+                Ok((valid, obtained)) => {
+                    info!(email = %user.email, ?obtained, "renewed session");
+                    sessions.update(&valid);
+                    self.put(valid);
+                }
+                Err(e) => warn!(error = %e, "could not renew session"),
+            }
+        }
+    }
+
+    /// Whether `user` is a Microsoft account without a session to refresh, which waits for someone to enter a device
+    /// code
+    fn needs_device_code(&self, user: &CSVUser) -> bool {
+        let refreshable = !self.fresh && self.cache.get(&user.email).map_or(false, |cached| cached.refresh_token.is_some());
+        user.microsoft() && !refreshable
+    }
 
+    /// Obtain sessions for up to `count` of `users`, then keep renewing them before they expire. Accounts which need a
+    /// device code come last so waiting for one does not hold up the others.
+    pub fn obtain_users(mut self, count: usize, mut users: Vec<CSVUser>, proxies: ProxyPool, mojang: MojangConfig) -> Receiver<ProxyUser> {
         let (tx, rx) = tokio::sync::mpsc::channel(32);
+        users.sort_by_key(|user| self.needs_device_code(user));
 
         tokio::task::spawn_local(async move {
-            let mut local_count = 0;
+            let sessions = Sessions::default();
+            let mut obtained = Vec::new();
 
-            'user_loop:
             for csv_user in users.into_iter() {
-                if let Some((mojang, proxy, user)) = self.get_or_put(&csv_user, &mut proxies).await {
-                    local_count += 1;
-                    println!("valid user {}", user.email);
+                if let Some((mojang, proxy, user)) = self.get_or_put(&csv_user, &proxies, &mojang).await {
+                    info!(email = %user.email, "valid user");
+                    sessions.update(&user);
+                    obtained.push((csv_user, mojang.clone()));
                     tx.send(ProxyUser {
                         user,
                         proxy,
                         mojang,
+                        sessions: sessions.clone(),
                     }).await.unwrap();
                 } else {
-                    println!("invalid user {}", csv_user.email);
+                    warn!(email = %csv_user.email, "invalid user");
                 }
 
-                if local_count >= count {
-                    break 'user_loop;
+                if obtained.len() >= count {
+                    break;
+                }
+            }
+            drop(tx);
+
+            loop {
+                tokio::time::sleep(RENEW_CHECK).await;
+                self.renew_expiring(&obtained, &sessions).await;
+            }
+        });
+
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::path::PathBuf;
+
+    use crate::bootstrap::CSVUser;
+    use crate::bootstrap::mojang::{AuthResponse, Authenticator};
+    use crate::bootstrap::cache::SessionKey;
+    use crate::bootstrap::storage::{Obtained, renew, session, UserCache, ValidUser};
+    use crate::error::{err, Res};
+
+    /// answers like Mojang (or Microsoft) would for an account whose session is `valid` and which can be `refreshed`
+    struct MockAuth {
+        valid: bool,
+        refreshes: bool,
+        password: &'static str,
+        calls: RefCell<Vec<&'static str>>,
+    }
+
+    impl MockAuth {
+        fn new(valid: bool, refreshes: bool) -> MockAuth {
+            MockAuth { valid, refreshes, password: "hunter2", calls: RefCell::default() }
+        }
+
+        fn response(token: &str) -> AuthResponse {
+            AuthResponse {
+                access_token: token.to_string(),
+                client_token: "client".to_string(),
+                username: "bot".to_string(),
+                ..AuthResponse::default()
+            }
+        }
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl Authenticator for MockAuth {
+        async fn authenticate(&self, _email: &str, password: &str) -> Res<AuthResponse> {
+            self.calls.borrow_mut().push("authenticate");
+            if password == self.password { Ok(MockAuth::response("authenticated")) } else { Err(err("invalid credentials")) }
+        }
+
+        async fn refresh(&self, _access_token: &str, _client_token: &str) -> Res<AuthResponse> {
+            self.calls.borrow_mut().push("refresh");
+            if self.refreshes { Ok(MockAuth::response("refreshed")) } else { Err(err("invalid token")) }
+        }
+
+        async fn validate(&self, _access_token: &str, _client_token: &str) -> Res<bool> {
+            self.calls.borrow_mut().push("validate");
+            Ok(self.valid)
+        }
+
+        async fn microsoft(&self, _email: &str, refresh_token: Option<&str>) -> Res<AuthResponse> {
+            match refresh_token {
+                Some(_) => {
+                    self.calls.borrow_mut().push("refresh microsoft");
+                    if self.refreshes { Ok(MockAuth::response("refreshed")) } else { Err(err("invalid grant")) }
+                }
+                None => {
+                    self.calls.borrow_mut().push("device code");
+                    Ok(AuthResponse { refresh_token: Some("refresh".to_string()), ..MockAuth::response("device code") })
                 }
             }
+        }
 
-            let mut file = OpenOptions::new()
-                .create(true)
-                .append(false)
-                .write(true)
-                .open(&self.file_path)
-                .unwrap();
+        async fn validate_microsoft(&self, _access_token: &str) -> Res<bool> {
+            self.calls.borrow_mut().push("validate microsoft");
+            Ok(self.valid)
+        }
+    }
 
-            let users = self.cache.drain().map(|(_, v)| v).collect();
+    fn user(password: &str) -> CSVUser {
+        CSVUser { email: "bot@example.com".to_string(), password: password.to_string() }
+    }
 
-            let root = Root {
-                users
-            };
+    fn cached() -> ValidUser {
+        ValidUser {
+            email: "bot@example.com".to_string(),
+            username: "bot".to_string(),
+            last_checked: 0,
+            uuid: "0".to_string(),
+            access_id: "cached".to_string(),
+            client_id: "client".to_string(),
+            refresh_token: None,
+            obtained: 0,
+        }
+    }
 
-            let data = bincode::serialize(&root).unwrap();
-            file.write_all(&data).unwrap();
-            file.flush().unwrap();
-        });
+    fn run(auth: &MockAuth, password: &str, cached: Option<&ValidUser>) -> Res<(ValidUser, Obtained)> {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        rt.block_on(session(auth, &user(password), cached))
+    }
 
-        rx
+    #[test]
+    fn test_ladder() {
+        let cached = cached();
+
+        let auth = MockAuth::new(true, true);
+        let (valid, obtained) = run(&auth, "hunter2", Some(&cached)).unwrap();
+        assert_eq!(obtained, Obtained::Cached);
+        assert_eq!(valid.access_id, "cached");
+        assert_eq!(*auth.calls.borrow(), vec!["validate"]);
+
+        let auth = MockAuth::new(false, true);
+        let (valid, obtained) = run(&auth, "hunter2", Some(&cached)).unwrap();
+        assert_eq!(obtained, Obtained::Refreshed);
+        assert_eq!(valid.access_id, "refreshed");
+        assert_eq!(*auth.calls.borrow(), vec!["validate", "refresh"]);
+
+        let auth = MockAuth::new(false, false);
+        let (valid, obtained) = run(&auth, "hunter2", Some(&cached)).unwrap();
+        assert_eq!(obtained, Obtained::Authenticated);
+        assert_eq!(valid.access_id, "authenticated");
+        assert_eq!(*auth.calls.borrow(), vec!["validate", "refresh", "authenticate"]);
+
+        let auth = MockAuth::new(false, false);
+        assert!(run(&auth, "wrong", Some(&cached)).is_err());
+    }
+
+    #[test]
+    fn test_microsoft() {
+        let refreshable = ValidUser { refresh_token: Some("refresh".to_string()), ..cached() };
+
+        let auth = MockAuth::new(true, true);
+        let (_, obtained) = run(&auth, "msa", Some(&refreshable)).unwrap();
+        assert_eq!(obtained, Obtained::Cached);
+        assert_eq!(*auth.calls.borrow(), vec!["validate microsoft"]);
+
+        let auth = MockAuth::new(false, false);
+        let (valid, obtained) = run(&auth, "msa", Some(&refreshable)).unwrap();
+        assert_eq!(obtained, Obtained::Authenticated);
+        assert_eq!(valid.refresh_token.as_deref(), Some("refresh"));
+        assert_eq!(*auth.calls.borrow(), vec!["validate microsoft", "refresh microsoft", "device code"]);
+
+        // a session cached before the account moved to Microsoft has no refresh token
+        let auth = MockAuth::new(true, true);
+        let (valid, obtained) = run(&auth, "msa", Some(&cached())).unwrap();
+        assert_eq!(obtained, Obtained::Authenticated);
+        assert_eq!(valid.access_id, "device code");
+        assert_eq!(*auth.calls.borrow(), vec!["device code"]);
+    }
+
+    #[test]
+    fn test_device_code_last() {
+        let path = std::env::temp_dir().join(format!("swarm-bot-sessions-order-{}.json", std::process::id()));
+        let mut cache = UserCache::load(PathBuf::from(&path), false, None).unwrap();
+
+        let csv = |email: &str, password: &str| CSVUser { email: email.to_string(), password: password.to_string() };
+        let refreshable = ValidUser { email: "refreshable@example.com".to_string(), refresh_token: Some("refresh".to_string()), ..cached() };
+        cache.cache.insert(refreshable.email.clone(), refreshable);
+
+        let mut users = vec![csv("new@example.com", "msa"), csv("mojang@example.com", "hunter2"), csv("refreshable@example.com", "msa")];
+        users.sort_by_key(|user| cache.needs_device_code(user));
+        let emails: Vec<_> = users.iter().map(|user| user.email.as_str()).collect();
+        assert_eq!(emails, vec!["mojang@example.com", "refreshable@example.com", "new@example.com"]);
+
+        // without the cache every Microsoft account needs a device code
+        cache.fresh = true;
+        assert!(cache.needs_device_code(&csv("refreshable@example.com", "msa")));
+    }
+
+    #[test]
+    fn test_uncached() {
+        let auth = MockAuth::new(true, true);
+        let (_, obtained) = run(&auth, "hunter2", None).unwrap();
+        assert_eq!(obtained, Obtained::Authenticated);
+        assert_eq!(*auth.calls.borrow(), vec!["authenticate"]);
+    }
+
+    #[test]
+    fn test_save() {
+        let path = std::env::temp_dir().join(format!("swarm-bot-sessions-{}.json", std::process::id()));
+
+        let mut cache = UserCache::load(PathBuf::from(&path), false, None).unwrap();
+        assert!(cache.cache.is_empty());
+        cache.cache.insert("bot@example.com".to_string(), cached());
+        cache.save().unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("cached"));
+        assert!(!contents.contains("password"));
+
+        let cache = UserCache::load(PathBuf::from(&path), false, None).unwrap();
+        assert_eq!(cache.cache["bot@example.com"].access_id, "cached");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_encrypted() {
+        let path = std::env::temp_dir().join(format!("swarm-bot-sessions-sealed-{}.json", std::process::id()));
+
+        let mut cache = UserCache::load(PathBuf::from(&path), false, Some(SessionKey::new("passphrase"))).unwrap();
+        cache.cache.insert("bot@example.com".to_string(), cached());
+        cache.save().unwrap();
+
+        let contents = std::fs::read(&path).unwrap();
+        assert!(!String::from_utf8_lossy(&contents).contains("cached"));
+
+        assert!(UserCache::load(PathBuf::from(&path), false, None).is_err());
+        assert!(UserCache::load(PathBuf::from(&path), false, Some(SessionKey::new("wrong"))).is_err());
+
+        let cache = UserCache::load(PathBuf::from(&path), false, Some(SessionKey::new("passphrase"))).unwrap();
+        assert_eq!(cache.cache["bot@example.com"].access_id, "cached");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_renew() {
+        let cached = cached();
+        assert!(cached.expiring(24 * 60 * 60, 0));
+
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        let auth = MockAuth::new(true, true);
+        let (valid, obtained) = rt.block_on(renew(&auth, &user("hunter2"), &cached)).unwrap();
+        assert_eq!(obtained, Obtained::Refreshed);
+        assert!(!valid.expiring(valid.obtained, 60 * 60));
+        assert_eq!(*auth.calls.borrow(), vec!["refresh"]);
+
+        let auth = MockAuth::new(true, false);
+        let (_, obtained) = rt.block_on(renew(&auth, &user("hunter2"), &cached)).unwrap();
+        assert_eq!(obtained, Obtained::Authenticated);
+        assert_eq!(*auth.calls.borrow(), vec!["refresh", "authenticate"]);
     }
 }
@@ -0,0 +1,246 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Connections through a proxy. SOCKS5 proxies are asked for the target directly, HTTP proxies with a `CONNECT` (over
+//! TLS for an HTTPS proxy).
+
+use std::convert::TryFrom;
+use std::io::{self, ErrorKind};
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+use tokio_socks::tcp::Socks5Stream;
+
+use crate::bootstrap::{Proxy, ProxyKind};
+
+/// the longest response to a `CONNECT` we read before giving up on the proxy
+const MAX_RESPONSE: usize = 8 * 1024;
+
+pub type TunnelRead = Box<dyn AsyncRead + Unpin + Send>;
+pub type TunnelWrite = Box<dyn AsyncWrite + Unpin + Send>;
+
+/// A connection to the target through a proxy
+pub enum Tunnel {
+    Tcp(TcpStream),
+    Tls(TlsStream<TcpStream>),
+}
+
+impl Tunnel {
+    /// Split into halves. A plain TCP connection is split without locking.
+    pub fn into_split(self) -> (TunnelRead, TunnelWrite) {
+        match self {
+            Tunnel::Tcp(stream) => {
+                let (read, write) = stream.into_split();
+                (Box::new(read), Box::new(write))
+            }
+            Tunnel::Tls(stream) => {
+                let (read, write) = tokio::io::split(stream);
+                (Box::new(read), Box::new(write))
+            }
+        }
+    }
+}
+
+fn other(error: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(ErrorKind::Other, error)
+}
+
+/// trusts the Mozilla root certificates, so no system library is needed
+fn tls_connector() -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(anchor.subject, anchor.spki, anchor.name_constraints)
+    }));
+
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+}
+
+/// Connect to `target` (`host:port`) through `proxy`
+pub async fn connect(proxy: &Proxy, target: &str) -> io::Result<Tunnel> {
+    let address = proxy.address();
+    match proxy.kind {
+        ProxyKind::Socks5 => {
+            // tokio-socks refuses empty credentials, a proxy without them is asked for no authentication
+            let stream = if proxy.user.is_empty() {
+                Socks5Stream::connect(address.as_str(), target).await
+            } else {
+                Socks5Stream::connect_with_password(address.as_str(), target, &proxy.user, &proxy.pass).await
+            };
+            let stream = stream.map_err(|error| match error {
+                tokio_socks::Error::Io(error) => error,
+                error => other(error),
+            })?;
+            Ok(Tunnel::Tcp(stream.into_inner()))
+        }
+        ProxyKind::Http => {
+            let mut stream = TcpStream::connect(address.as_str()).await?;
+            http_connect(&mut stream, proxy, target).await?;
+            Ok(Tunnel::Tcp(stream))
+        }
+        ProxyKind::Https => {
+            let stream = TcpStream::connect(address.as_str()).await?;
+            let name = ServerName::try_from(proxy.host.as_str())
+                .map_err(|_| io::Error::new(ErrorKind::InvalidInput, format!("{} is not a valid host name", proxy.host)))?;
+            let mut stream = tls_connector().connect(name, stream).await?;
+            http_connect(&mut stream, proxy, target).await?;
+            Ok(Tunnel::Tls(stream))
+        }
+    }
+}
+
+/// Ask an HTTP proxy to open a tunnel to `target`. Afterwards `stream` goes to the target.
+async fn http_connect(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), proxy: &Proxy, target: &str) -> io::Result<()> {
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+    if !proxy.user.is_empty() {
+        let credentials = base64::encode(format!("{}:{}", proxy.user, proxy.pass));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // byte by byte so nothing the target sends after the response is read
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_RESPONSE {
+            return Err(io::Error::new(ErrorKind::InvalidData, "response to CONNECT is too long"));
+        }
+        response.push(stream.read_u8().await?);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(io::Error::new(ErrorKind::ConnectionRefused, format!("proxy refused CONNECT: {}", status_line))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use crate::bootstrap::{Proxy, ProxyKind};
+    use crate::bootstrap::tunnel::{connect, Tunnel};
+
+    /// a proxy which answers a `CONNECT` with `status` and then echoes
+    async fn serve(listener: TcpListener, status: &'static str) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await.unwrap());
+        }
+        stream.write_all(format!("HTTP/1.1 {}\r\nProxy-Agent: test\r\n\r\n", status).as_bytes()).await.unwrap();
+
+        let mut byte = [0];
+        if stream.read_exact(&mut byte).await.is_ok() {
+            stream.write_all(&byte).await.unwrap();
+        }
+        String::from_utf8(request).unwrap()
+    }
+
+    fn proxy(port: u16, user: &str) -> Proxy {
+        Proxy { kind: ProxyKind::Http, host: "127.0.0.1".to_string(), port: port as u32, user: user.to_string(), pass: "pass".to_string() }
+    }
+
+    /// a SOCKS5 proxy which only accepts clients without authentication, connects them and then echoes. Returns the
+    /// authentication methods the client offered.
+    async fn serve_socks5(listener: TcpListener) -> Vec<u8> {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert_eq!(stream.read_u8().await.unwrap(), 5);
+        let mut methods = vec![0; stream.read_u8().await.unwrap() as usize];
+        stream.read_exact(&mut methods).await.unwrap();
+        if !methods.contains(&0) {
+            stream.write_all(&[5, 0xFF]).await.unwrap();
+            return methods;
+        }
+        stream.write_all(&[5, 0]).await.unwrap();
+
+        // connect to a domain name
+        let mut request = [0; 4];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(request, [5, 1, 0, 3]);
+        let mut host = vec![0; stream.read_u8().await.unwrap() as usize];
+        stream.read_exact(&mut host).await.unwrap();
+        assert_eq!(host, b"mc.example.com");
+        assert_eq!(stream.read_u16().await.unwrap(), 25565);
+        stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).await.unwrap();
+
+        let mut byte = [0];
+        if stream.read_exact(&mut byte).await.is_ok() {
+            stream.write_all(&byte).await.unwrap();
+        }
+        methods
+    }
+
+    #[test]
+    fn test_http_connect() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let server = tokio::spawn(serve(listener, "200 Connection established"));
+
+            let tunnel = connect(&proxy(port, "user"), "mc.example.com:25565").await.unwrap();
+            let mut stream = match tunnel {
+                Tunnel::Tcp(stream) => stream,
+                Tunnel::Tls(_) => panic!("an HTTP proxy is plain TCP"),
+            };
+            stream.write_all(&[42]).await.unwrap();
+            assert_eq!(stream.read_u8().await.unwrap(), 42);
+
+            let request = server.await.unwrap();
+            assert!(request.starts_with("CONNECT mc.example.com:25565 HTTP/1.1\r\n"));
+            assert!(request.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let server = tokio::spawn(serve(listener, "407 Proxy Authentication Required"));
+
+            let error = connect(&proxy(port, ""), "mc.example.com:25565").await.err().unwrap();
+            assert!(error.to_string().contains("407"));
+            assert!(!server.await.unwrap().contains("Proxy-Authorization"));
+        });
+    }
+
+    #[test]
+    fn test_socks5_without_credentials() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let server = tokio::spawn(serve_socks5(listener));
+
+            let proxy = Proxy { kind: ProxyKind::Socks5, pass: String::new(), ..proxy(port, "") };
+            let mut stream = match connect(&proxy, "mc.example.com:25565").await.unwrap() {
+                Tunnel::Tcp(stream) => stream,
+                Tunnel::Tls(_) => panic!("a SOCKS5 proxy is plain TCP"),
+            };
+            stream.write_all(&[42]).await.unwrap();
+            assert_eq!(stream.read_u8().await.unwrap(), 42);
+
+            assert_eq!(server.await.unwrap(), vec![0]);
+        });
+    }
+}
@@ -0,0 +1,445 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The json chat format (<https://wiki.vg/Chat>) and the chat events derived from it
+
+use std::lazy::SyncLazy;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use swarm_bot_packets::read::{ByteReadable, ByteReader};
+
+use crate::chat::format::{Color, Format};
+use crate::types::PlayerMessage;
+
+pub mod format;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClickEvent {
+    /// e.g., `open_url`, `run_command`, `suggest_command`
+    pub action: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HoverEvent {
+    /// e.g., `show_text`, `show_entity`
+    pub action: String,
+
+    /// `value` before 1.16, `contents` after. Only meaningful for `show_text`.
+    pub contents: Box<Component>,
+}
+
+/// A chat component. Strings, numbers and arrays (the first element is the parent of the rest) are accepted wherever
+/// a component is expected, so deserializing never fails on well-formed json.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(from = "Value")]
+pub struct Component {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub text: String,
+
+    /// a translation key such as `chat.type.text`. `with` holds its arguments.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translate: Option<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub with: Vec<Component>,
+
+    /// children, which inherit the style of this component
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<Component>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub underlined: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub obfuscated: Option<bool>,
+
+    /// text inserted into the chat box when shift clicked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insertion: Option<String>,
+
+    #[serde(rename = "clickEvent", skip_serializing_if = "Option::is_none")]
+    pub click_event: Option<ClickEvent>,
+
+    #[serde(rename = "hoverEvent", skip_serializing_if = "Option::is_none")]
+    pub hover_event: Option<HoverEvent>,
+}
+
+fn string(value: Option<&Value>) -> Option<String> {
+    value.and_then(Value::as_str).map(str::to_string)
+}
+
+fn bool(value: Option<&Value>) -> Option<bool> {
+    value.and_then(Value::as_bool)
+}
+
+fn components(value: Option<&Value>) -> Vec<Component> {
+    match value {
+        Some(Value::Array(values)) => values.iter().cloned().map(Component::from).collect(),
+        Some(value) => vec![Component::from(value.clone())],
+        None => Vec::new()
+    }
+}
+
+impl From<Value> for Component {
+    fn from(value: Value) -> Self {
+        let object = match value {
+            Value::String(text) => return Component::text(text),
+            Value::Number(number) => return Component::text(number.to_string()),
+            Value::Bool(value) => return Component::text(value.to_string()),
+            Value::Null => return Component::default(),
+            Value::Array(values) => {
+                let mut values = values.into_iter().map(Component::from);
+                let mut first = values.next().unwrap_or_default();
+                first.extra.extend(values);
+                return first;
+            }
+            Value::Object(object) => object
+        };
+
+        let click_event = object.get("clickEvent").and_then(|event| serde_json::from_value(event.clone()).ok());
+
+        let hover_event = object.get("hoverEvent").map(|event| HoverEvent {
+            action: string(event.get("action")).unwrap_or_default(),
+            contents: Box::new(event.get("contents").or_else(|| event.get("value")).cloned().map(Component::from).unwrap_or_default()),
+        });
+
+        Component {
+            text: string(object.get("text")).unwrap_or_default(),
+            translate: string(object.get("translate")),
+            with: components(object.get("with")),
+            extra: components(object.get("extra")),
+            color: string(object.get("color")),
+            bold: bool(object.get("bold")),
+            italic: bool(object.get("italic")),
+            underlined: bool(object.get("underlined")),
+            strikethrough: bool(object.get("strikethrough")),
+            obfuscated: bool(object.get("obfuscated")),
+            insertion: string(object.get("insertion")),
+            click_event,
+            hover_event,
+        }
+    }
+}
+
+impl ByteReadable for Component {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let json: String = byte_reader.read();
+        Component::parse(&json)
+    }
+}
+
+/// The english format of the translation keys the bot cares about. Other keys are shown as the key followed by
+/// their arguments.
+fn translation(key: &str) -> Option<&'static str> {
+    let format = match key {
+        "chat.type.text" => "<%s> %s",
+        "chat.type.emote" => "* %s %s",
+        "chat.type.announcement" => "[%s] %s",
+        "commands.message.display.incoming" => "%s whispers to you: %s",
+        "commands.message.display.outgoing" => "You whisper to %s: %s",
+        "multiplayer.player.joined" => "%s joined the game",
+        "multiplayer.player.left" => "%s left the game",
+        _ => return None
+    };
+    Some(format)
+}
+
+/// Whether legacy `§` codes survive flattening a component to a string
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Codes {
+    Strip,
+
+    /// keep the codes, and turn the styles of the components into codes as well
+    Preserve,
+}
+
+impl Component {
+    pub fn text(text: impl Into<String>) -> Component {
+        Component {
+            text: text.into(),
+            ..Component::default()
+        }
+    }
+
+    /// Parse chat json. Invalid json is treated as plain text.
+    pub fn parse(json: &str) -> Component {
+        match serde_json::from_str::<Value>(json) {
+            Ok(value) => Component::from(value),
+            Err(_) => Component::text(json)
+        }
+    }
+
+    fn write_plain(&self, res: &mut String) {
+        res.push_str(&self.text);
+
+        if let Some(key) = self.translate.as_ref() {
+            let args: Vec<String> = self.with.iter().map(Component::to_plain_text).collect();
+            match translation(key) {
+                Some(format) => {
+                    let mut args = args.iter();
+                    let mut parts = format.split("%s");
+                    res.push_str(parts.next().unwrap_or_default());
+                    for part in parts {
+                        res.push_str(args.next().map(String::as_str).unwrap_or_default());
+                        res.push_str(part);
+                    }
+                }
+                None => {
+                    res.push_str(key);
+                    for arg in args {
+                        res.push(' ');
+                        res.push_str(&arg);
+                    }
+                }
+            }
+        }
+
+        for child in &self.extra {
+            child.write_plain(res);
+        }
+    }
+
+    /// the text without any formatting (including legacy `§` codes)
+    pub fn to_plain_text(&self) -> String {
+        self.flatten(Codes::Strip)
+    }
+
+    /// the format of this component given the format of its parent
+    fn format(&self, parent: Format) -> Format {
+        let color = self.color.as_deref().and_then(Color::from_name).or(parent.color);
+        Format {
+            color,
+            bold: self.bold.unwrap_or(parent.bold),
+            italic: self.italic.unwrap_or(parent.italic),
+            underlined: self.underlined.unwrap_or(parent.underlined),
+            strikethrough: self.strikethrough.unwrap_or(parent.strikethrough),
+            obfuscated: self.obfuscated.unwrap_or(parent.obfuscated),
+        }
+    }
+
+    /// `current` is the format at the end of `res`, which codes in the text can make differ from the component's
+    fn write_legacy(&self, res: &mut String, parent: Format, current: &mut Format) {
+        let format = self.format(parent);
+        res.push_str(&current.transition(&format));
+
+        let mut own = String::new();
+        Component { extra: Vec::new(), ..self.clone() }.write_plain(&mut own);
+        res.push_str(&own);
+        *current = format;
+        current.apply_all(&own);
+
+        for child in &self.extra {
+            child.write_legacy(res, format, current);
+        }
+    }
+
+    /// The whole text as one string. Legacy `§` codes are removed or kept (see [`Codes`]).
+    pub fn flatten(&self, codes: Codes) -> String {
+        let mut res = String::new();
+        match codes {
+            Codes::Strip => {
+                self.write_plain(&mut res);
+                format::strip_codes(&res)
+            }
+            Codes::Preserve => {
+                self.write_legacy(&mut res, Format::default(), &mut Format::default());
+                res
+            }
+        }
+    }
+
+    /// the text with ansi colors for printing to the terminal
+    pub fn colorize(&self) -> String {
+        format::to_ansi(&self.flatten(Codes::Preserve))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatKind {
+    /// a message sent to everyone
+    Public,
+
+    /// a private message to us
+    Whisper,
+
+    /// everything else (join messages, server broadcasts, ...)
+    System,
+}
+
+/// A chat message as seen by the processor
+#[derive(Debug, Clone)]
+pub struct ChatEvent {
+    pub kind: ChatKind,
+
+    /// who sent the message. Only set for public messages and whispers.
+    pub sender: Option<String>,
+
+    /// the message itself, without the sender (`hi` for `<Notch> hi`). The whole text for system messages.
+    pub message: String,
+
+    /// the whole message as plain text
+    pub text: String,
+
+    pub component: Component,
+}
+
+/// Servers which format chat with plugins usually send plain text (or extras) in one of these shapes
+fn match_text(text: &str) -> Option<(ChatKind, String, String)> {
+    static PUBLIC: SyncLazy<Regex> = SyncLazy::new(|| Regex::new(r"^<([A-Za-z_0-9]+)> (.*)").unwrap());
+    static WHISPER: SyncLazy<Regex> = SyncLazy::new(|| Regex::new(r"^([A-Za-z_0-9]+) whispers(?: to you)?: (.*)").unwrap());
+
+    let (kind, captures) = if let Some(captures) = PUBLIC.captures(text) {
+        (ChatKind::Public, captures)
+    } else {
+        (ChatKind::Whisper, WHISPER.captures(text)?)
+    };
+
+    Some((kind, captures.get(1)?.as_str().to_string(), captures.get(2)?.as_str().to_string()))
+}
+
+impl From<Component> for ChatEvent {
+    fn from(component: Component) -> Self {
+        let text = component.to_plain_text();
+
+        // vanilla player messages say who sent them in the translation arguments
+        let translated = match (component.translate.as_deref(), component.with.as_slice()) {
+            (Some("chat.type.text"), [sender, message]) => Some(ChatKind::Public).zip(Some((sender, message))),
+            (Some("commands.message.display.incoming"), [sender, message]) => Some(ChatKind::Whisper).zip(Some((sender, message))),
+            _ => None
+        };
+
+        let parsed = match translated {
+            Some((kind, (sender, message))) => Some((kind, sender.to_plain_text(), message.to_plain_text())),
+            None => match_text(&text)
+        };
+
+        match parsed {
+            Some((kind, sender, message)) => ChatEvent { kind, sender: Some(sender), message, text, component },
+            None => ChatEvent { kind: ChatKind::System, sender: None, message: text.clone(), text, component }
+        }
+    }
+}
+
+impl ChatEvent {
+    /// the message if it was sent by a player (publicly or by whispering)
+    pub fn player_message(&self) -> Option<PlayerMessage> {
+        Some(PlayerMessage {
+            player: self.sender.clone()?,
+            message: self.message.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::{ChatEvent, ChatKind, Codes, Component, format};
+
+    #[test]
+    fn test_parse() {
+        let json = r##"{"text":"","extra":[{"text":"hello ","color":"gold","bold":true},"world",3,{"text":"!","clickEvent":{"action":"open_url","value":"https://example.com"},"hoverEvent":{"action":"show_text","value":{"text":"click"}}}]}"##;
+        let component = Component::parse(json);
+        assert_eq!(component.extra.len(), 4);
+        assert_eq!(component.extra[0].color.as_deref(), Some("gold"));
+        assert_eq!(component.extra[0].bold, Some(true));
+        assert_eq!(component.extra[3].click_event.as_ref().unwrap().value, "https://example.com");
+        assert_eq!(component.extra[3].hover_event.as_ref().unwrap().contents.text, "click");
+        assert_eq!(component.to_plain_text(), "hello world3!");
+
+        // through serde as well
+        let component: Component = serde_json::from_str(json).unwrap();
+        assert_eq!(component.to_plain_text(), "hello world3!");
+
+        // arrays, strings and garbage
+        assert_eq!(Component::parse(r##"["a", {"text": "b"}]"##).to_plain_text(), "ab");
+        assert_eq!(Component::parse(r##""plain""##).to_plain_text(), "plain");
+        assert_eq!(Component::parse("not json").to_plain_text(), "not json");
+    }
+
+    #[test]
+    fn test_legacy_codes() {
+        let component = Component::parse(r##"{"text":"§6[§cServer§6] §rhello"}"##);
+        assert_eq!(component.to_plain_text(), "[Server] hello");
+
+        // colorizing does not leave codes behind either
+        assert!(!component.colorize().contains('§'));
+    }
+
+    #[test]
+    fn test_flatten() {
+        let component = Component::parse(r##"{"text":"§6[§cServer§6] ","extra":[{"text":"hi","color":"green","bold":true},"!"]}"##);
+        assert_eq!(component.flatten(Codes::Strip), "[Server] hi!");
+
+        // json styles become codes, and the parent format is restored after a child
+        let preserved = component.flatten(Codes::Preserve);
+        assert_eq!(preserved, "§6[§cServer§6] §a§lhi§r!");
+        assert_eq!(format::strip_codes(&preserved), "[Server] hi!");
+    }
+
+    #[test]
+    fn test_translate() {
+        let json = r##"{"translate":"chat.type.text","with":[{"text":"Notch","insertion":"Notch","clickEvent":{"action":"suggest_command","value":"/tell Notch "},"hoverEvent":{"action":"show_entity","contents":{"type":"minecraft:player","id":"069a79f4-44e9-4726-a5be-fca90e38aaf5","name":{"text":"Notch"}}}},{"text":"#goto ","extra":[{"text":"1 2 3","color":"red"}]}]}"##;
+        let event = ChatEvent::from(Component::parse(json));
+        assert_eq!(event.kind, ChatKind::Public);
+        assert_eq!(event.sender.as_deref(), Some("Notch"));
+        assert_eq!(event.message, "#goto 1 2 3");
+        assert_eq!(event.text, "<Notch> #goto 1 2 3");
+
+        let json = r##"{"translate":"commands.message.display.incoming","with":[{"text":"§aNotch"},{"text":"psst"}],"color":"gray","italic":true}"##;
+        let event = ChatEvent::from(Component::parse(json));
+        assert_eq!(event.kind, ChatKind::Whisper);
+        assert_eq!(event.sender.as_deref(), Some("Notch"));
+        assert_eq!(event.message, "psst");
+
+        let json = r##"{"translate":"multiplayer.player.joined","with":[{"text":"Notch"}],"color":"yellow"}"##;
+        let event = ChatEvent::from(Component::parse(json));
+        assert_eq!(event.kind, ChatKind::System);
+        assert_eq!(event.sender, None);
+        assert_eq!(event.text, "Notch joined the game");
+
+        // unknown keys keep their arguments
+        let event = ChatEvent::from(Component::parse(r##"{"translate":"some.key","with":["a","b"]}"##));
+        assert_eq!(event.text, "some.key a b");
+    }
+
+    #[test]
+    fn test_formatted_plain_text() {
+        // plugin formatted chat with the name in a colored extra
+        let json = r##"{"text":"","extra":[{"text":"<","color":"gray"},{"text":"Notch","color":"gold"},{"text":"> ","color":"gray"},{"text":"#mine"}]}"##;
+        let event = ChatEvent::from(Component::parse(json));
+        assert_eq!(event.kind, ChatKind::Public);
+        assert_eq!(event.sender.as_deref(), Some("Notch"));
+        assert_eq!(event.message, "#mine");
+
+        let event = ChatEvent::from(Component::text("Notch whispers: #stop"));
+        assert_eq!(event.kind, ChatKind::Whisper);
+        assert_eq!(event.message, "#stop");
+
+        let event = ChatEvent::from(Component::text("[Broadcast] restarting"));
+        assert_eq!(event.kind, ChatKind::System);
+    }
+}
@@ -0,0 +1,423 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Legacy `§` formatting codes (<https://minecraft.fandom.com/wiki/Formatting_codes>) for outgoing chat and for
+//! rendering incoming chat in the terminal
+
+use ansi_term::Style;
+
+/// the char starting a formatting code
+pub const SECTION: char = '§';
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Color {
+    Black,
+    DarkBlue,
+    DarkGreen,
+    DarkAqua,
+    DarkRed,
+    DarkPurple,
+    Gold,
+    Gray,
+    DarkGray,
+    Blue,
+    Green,
+    Aqua,
+    Red,
+    LightPurple,
+    Yellow,
+    White,
+}
+
+const COLORS: [Color; 16] = {
+    use Color::*;
+    [Black, DarkBlue, DarkGreen, DarkAqua, DarkRed, DarkPurple, Gold, Gray, DarkGray, Blue, Green, Aqua, Red, LightPurple, Yellow, White]
+};
+
+impl Color {
+    /// the char after `§`
+    pub fn code(self) -> char {
+        std::char::from_digit(self as u32, 16).unwrap()
+    }
+
+    pub fn from_code(code: char) -> Option<Color> {
+        let idx = code.to_ascii_lowercase().to_digit(16)?;
+        Some(COLORS[idx as usize])
+    }
+
+    /// the name used by json chat
+    pub fn name(self) -> &'static str {
+        use Color::*;
+        match self {
+            Black => "black",
+            DarkBlue => "dark_blue",
+            DarkGreen => "dark_green",
+            DarkAqua => "dark_aqua",
+            DarkRed => "dark_red",
+            DarkPurple => "dark_purple",
+            Gold => "gold",
+            Gray => "gray",
+            DarkGray => "dark_gray",
+            Blue => "blue",
+            Green => "green",
+            Aqua => "aqua",
+            Red => "red",
+            LightPurple => "light_purple",
+            Yellow => "yellow",
+            White => "white",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Color> {
+        COLORS.iter().copied().find(|color| color.name() == name)
+    }
+
+    fn ansi(self) -> ansi_term::Color {
+        use ansi_term::Color::*;
+        match self {
+            Color::DarkBlue | Color::Blue => Blue,
+            Color::DarkAqua | Color::Aqua => Cyan,
+            Color::Red | Color::DarkRed => Red,
+            Color::DarkPurple | Color::LightPurple => Purple,
+            Color::Gold | Color::Yellow => Yellow,
+            Color::Green | Color::DarkGreen => Green,
+            Color::Gray | Color::White => White,
+            Color::Black | Color::DarkGray => Black,
+        }
+    }
+}
+
+/// The formatting in effect at some point of a legacy string
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Format {
+    pub color: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underlined: bool,
+    pub strikethrough: bool,
+    pub obfuscated: bool,
+}
+
+impl Format {
+    /// Apply the code after a `§`. Colors and `§r` reset the other formatting like the vanilla client does.
+    pub fn apply(&mut self, code: char) {
+        if let Some(color) = Color::from_code(code) {
+            *self = Format { color: Some(color), ..Format::default() };
+            return;
+        }
+
+        match code.to_ascii_lowercase() {
+            'k' => self.obfuscated = true,
+            'l' => self.bold = true,
+            'm' => self.strikethrough = true,
+            'n' => self.underlined = true,
+            'o' => self.italic = true,
+            'r' => *self = Format::default(),
+            _ => {}
+        }
+    }
+
+    /// apply every code in `text`
+    pub fn apply_all(&mut self, text: &str) {
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == SECTION {
+                if let Some(code) = chars.next() {
+                    self.apply(code);
+                }
+            }
+        }
+    }
+
+    /// the codes which produce this format from no formatting
+    pub fn codes(&self) -> String {
+        let mut res = String::new();
+        let mut push = |on: bool, code: char| if on {
+            res.push(SECTION);
+            res.push(code);
+        };
+
+        push(self.color.is_some(), self.color.map(Color::code).unwrap_or_default());
+        push(self.obfuscated, 'k');
+        push(self.bold, 'l');
+        push(self.strikethrough, 'm');
+        push(self.underlined, 'n');
+        push(self.italic, 'o');
+        res
+    }
+
+    /// The codes which change `self` into `next`. Formatting cannot be turned off on its own, so losing any of it
+    /// resets everything.
+    pub fn transition(&self, next: &Format) -> String {
+        if self == next {
+            return String::new();
+        }
+
+        let lost = (self.color.is_some() && next.color.is_none())
+            || (self.obfuscated && !next.obfuscated)
+            || (self.bold && !next.bold)
+            || (self.strikethrough && !next.strikethrough)
+            || (self.underlined && !next.underlined)
+            || (self.italic && !next.italic);
+
+        // colors reset the formatting as well
+        if lost || self.color != next.color {
+            return match next.color {
+                None => format!("{}r{}", SECTION, next.codes()),
+                Some(_) => next.codes(),
+            };
+        }
+
+        let added = Format { color: None, ..*next };
+        let kept = Format { color: None, ..*self };
+        let mut res = String::new();
+        for (on, was, code) in [(added.obfuscated, kept.obfuscated, 'k'), (added.bold, kept.bold, 'l'), (added.strikethrough, kept.strikethrough, 'm'), (added.underlined, kept.underlined, 'n'), (added.italic, kept.italic, 'o')] {
+            if on && !was {
+                res.push(SECTION);
+                res.push(code);
+            }
+        }
+        res
+    }
+
+    fn style(&self) -> Style {
+        let mut style = Style::default();
+        if let Some(color) = self.color {
+            style = style.fg(color.ansi());
+        }
+        if self.bold {
+            style = style.bold();
+        }
+        if self.italic {
+            style = style.italic();
+        }
+        if self.underlined {
+            style = style.underline();
+        }
+        if self.strikethrough {
+            style = style.strikethrough();
+        }
+        style
+    }
+}
+
+/// Remove legacy `§` formatting codes
+pub fn strip_codes(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == SECTION {
+            chars.next();
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+/// Render text with legacy codes with ansi colors for printing to the terminal
+pub fn to_ansi(text: &str) -> String {
+    let mut res = String::new();
+    let mut format = Format::default();
+    let mut pending = String::new();
+
+    let mut flush = |pending: &mut String, format: Format| {
+        if !pending.is_empty() {
+            res.push_str(&format.style().paint(pending.as_str()).to_string());
+            pending.clear();
+        }
+    };
+
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == SECTION {
+            flush(&mut pending, format);
+            if let Some(code) = chars.next() {
+                format.apply(code);
+            }
+        } else {
+            pending.push(c);
+        }
+    }
+    flush(&mut pending, format);
+    res
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Span {
+    format: Format,
+    text: String,
+}
+
+/// An outgoing chat message made of differently formatted parts.
+///
+/// `Msg::new().green("done ").gray("(37 blocks)")` renders as `§adone §7(37 blocks)`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Msg {
+    spans: Vec<Span>,
+}
+
+impl Msg {
+    pub fn new() -> Msg {
+        Msg::default()
+    }
+
+    /// a message for a command which worked
+    pub fn success(text: impl Into<String>) -> Msg {
+        Msg::new().green(text)
+    }
+
+    pub fn warning(text: impl Into<String>) -> Msg {
+        Msg::new().gold(text)
+    }
+
+    pub fn error(text: impl Into<String>) -> Msg {
+        Msg::new().red(text)
+    }
+
+    /// add text with the given format
+    pub fn push(mut self, format: Format, text: impl Into<String>) -> Msg {
+        self.spans.push(Span { format, text: text.into() });
+        self
+    }
+
+    pub fn color(self, color: Color, text: impl Into<String>) -> Msg {
+        self.push(Format { color: Some(color), ..Format::default() }, text)
+    }
+
+    /// add text without formatting
+    pub fn plain(self, text: impl Into<String>) -> Msg {
+        self.push(Format::default(), text)
+    }
+
+    pub fn green(self, text: impl Into<String>) -> Msg {
+        self.color(Color::Green, text)
+    }
+
+    pub fn gray(self, text: impl Into<String>) -> Msg {
+        self.color(Color::Gray, text)
+    }
+
+    pub fn red(self, text: impl Into<String>) -> Msg {
+        self.color(Color::Red, text)
+    }
+
+    pub fn gold(self, text: impl Into<String>) -> Msg {
+        self.color(Color::Gold, text)
+    }
+
+    pub fn yellow(self, text: impl Into<String>) -> Msg {
+        self.color(Color::Yellow, text)
+    }
+
+    pub fn aqua(self, text: impl Into<String>) -> Msg {
+        self.color(Color::Aqua, text)
+    }
+
+    pub fn white(self, text: impl Into<String>) -> Msg {
+        self.color(Color::White, text)
+    }
+
+    /// make the last part bold
+    pub fn bold(mut self) -> Msg {
+        if let Some(span) = self.spans.last_mut() {
+            span.format.bold = true;
+        }
+        self
+    }
+
+    /// make the last part italic
+    pub fn italic(mut self) -> Msg {
+        if let Some(span) = self.spans.last_mut() {
+            span.format.italic = true;
+        }
+        self
+    }
+
+    /// The message with legacy `§` codes for sending to a 1.12 server. Codes are only emitted where the format changes.
+    pub fn to_legacy(&self) -> String {
+        let mut res = String::new();
+        let mut format = Format::default();
+        for span in self.spans.iter().filter(|span| !span.text.is_empty()) {
+            res.push_str(&format.transition(&span.format));
+            res.push_str(&span.text);
+            format = span.format;
+        }
+        res
+    }
+
+    pub fn to_plain(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    pub fn to_ansi(&self) -> String {
+        self.spans.iter()
+            .filter(|span| !span.text.is_empty())
+            .map(|span| span.format.style().paint(span.text.as_str()).to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::format::{Color, Format, Msg, strip_codes, to_ansi};
+
+    #[test]
+    fn test_legacy() {
+        let msg = Msg::new().green("done ").gray("(37 blocks)");
+        assert_eq!(msg.to_legacy(), "§adone §7(37 blocks)");
+        assert_eq!(msg.to_plain(), "done (37 blocks)");
+        assert_eq!(strip_codes(&msg.to_legacy()), msg.to_plain());
+
+        // no codes for parts with the same format, a reset when the color goes away
+        let msg = Msg::new().red("a").red("b").plain("c");
+        assert_eq!(msg.to_legacy(), "§cab§rc");
+
+        // formatting is kept when only adding to it and re-applied after a color
+        let msg = Msg::new().gold("a").gold("b").bold().plain("c").bold().aqua("d");
+        assert_eq!(msg.to_legacy(), "§6a§lb§r§lc§bd");
+
+        assert_eq!(Msg::new().plain("plain").to_legacy(), "plain");
+    }
+
+    #[test]
+    fn test_codes() {
+        for code in "0123456789abcdef".chars() {
+            let color = Color::from_code(code).unwrap();
+            assert_eq!(color.code(), code);
+            assert_eq!(Color::from_name(color.name()), Some(color));
+        }
+        assert_eq!(Color::from_code('A'), Some(Color::Green));
+        assert_eq!(Color::from_code('z'), None);
+
+        let mut format = Format::default();
+        format.apply('l');
+        format.apply('c');
+        assert_eq!(format, Format { color: Some(Color::Red), ..Format::default() });
+        format.apply('n');
+        assert_eq!(format.codes(), "§c§n");
+        format.apply('r');
+        assert_eq!(format, Format::default());
+    }
+
+    #[test]
+    fn test_ansi() {
+        let msg = Msg::new().green("done ").gray("(37 blocks)");
+        assert_eq!(to_ansi(&msg.to_legacy()), msg.to_ansi());
+        assert!(msg.to_ansi().contains("done"));
+        assert!(!to_ansi("§6[§cServer§6] §rhello").contains('§'));
+    }
+}
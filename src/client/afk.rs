@@ -0,0 +1,227 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Choosing where a bot should stand while AFKing at a mob farm. Mobs only spawn between [`MIN_SPAWN`] and
+//! [`MAX_SPAWN`] blocks from a player, so the best spot has every spawning platform of the farm in that ring and as
+//! few other spawnable surfaces as possible (those take spawns away from the farm).
+//!
+//! Light levels are not stored, so every full block with two free blocks above it counts as spawnable.
+
+use serde::Serialize;
+
+use crate::storage::block::{BlockLocation, SimpleType};
+use crate::storage::blocks::WorldBlocks;
+use crate::types::Location;
+
+/// mobs do not spawn closer than this to a player (1.12)
+pub const MIN_SPAWN: f64 = 24.0;
+
+/// mobs further than this from every player despawn right away
+pub const MAX_SPAWN: f64 = 128.0;
+
+/// An axis-aligned box of blocks. Both corners are included.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Area {
+    pub min: BlockLocation,
+    pub max: BlockLocation,
+}
+
+impl Area {
+    pub fn new(a: BlockLocation, b: BlockLocation) -> Area {
+        Area {
+            min: BlockLocation::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: BlockLocation::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    pub fn contains(&self, loc: BlockLocation) -> bool {
+        (self.min.x..=self.max.x).contains(&loc.x)
+            && (self.min.y..=self.max.y).contains(&loc.y)
+            && (self.min.z..=self.max.z).contains(&loc.z)
+    }
+
+    /// grow the area by `by` blocks in every direction, staying within the build height
+    pub fn expand(&self, by: i32) -> Area {
+        let by_y = by.min(i16::MAX as i32) as i16;
+        Area {
+            min: BlockLocation::new(self.min.x - by, self.min.y.saturating_sub(by_y).max(0), self.min.z - by),
+            max: BlockLocation::new(self.max.x + by, self.max.y.saturating_add(by_y).min(255), self.max.z + by),
+        }
+    }
+
+    pub fn iter(self) -> impl Iterator<Item=BlockLocation> {
+        (self.min.x..=self.max.x).flat_map(move |x| {
+            (self.min.y..=self.max.y).flat_map(move |y| {
+                (self.min.z..=self.max.z).map(move |z| BlockLocation::new(x, y, z))
+            })
+        })
+    }
+}
+
+/// A full block below and two free blocks. Both where a mob can spawn and where the bot can stand.
+pub fn open_floor(world: &WorldBlocks, loc: BlockLocation) -> bool {
+    world.get_block_simple(loc.below()) == Some(SimpleType::Solid)
+        && world.get_block_simple(loc) == Some(SimpleType::WalkThrough)
+        && world.get_block_simple(loc.above()) == Some(SimpleType::WalkThrough)
+}
+
+fn in_ring(player: Location, spawn: Location) -> bool {
+    let dist2 = player.dist2(spawn);
+    (MIN_SPAWN * MIN_SPAWN..=MAX_SPAWN * MAX_SPAWN).contains(&dist2)
+}
+
+/// How good standing at `location` is
+#[derive(Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AfkSpot {
+    pub location: BlockLocation,
+
+    /// spawn locations of the farm within the spawning ring
+    pub farm: usize,
+
+    /// other spawn locations within the spawning ring
+    pub other: usize,
+}
+
+pub struct AfkOptimizer {
+    farm: Vec<Location>,
+    other: Vec<Location>,
+}
+
+impl AfkOptimizer {
+    /// Find the spawn locations in `farm` and everywhere else that could be in the ring of a spot in `search`
+    pub fn new(world: &WorldBlocks, farm: Area, search: Area) -> AfkOptimizer {
+        let reach = search.expand(MAX_SPAWN as i32);
+
+        let origin = BlockLocation::from(search.min.center_bottom());
+        let (farm_spots, other): (Vec<_>, Vec<_>) = world.select(origin, usize::MAX, |state| state.simple_type() == SimpleType::Solid)
+            .map(|loc| loc.above())
+            .filter(|&loc| reach.contains(loc) && open_floor(world, loc))
+            .partition(|&loc| farm.contains(loc));
+
+        AfkOptimizer {
+            farm: farm_spots.into_iter().map(|loc| loc.center_bottom()).collect(),
+            other: other.into_iter().map(|loc| loc.center_bottom()).collect(),
+        }
+    }
+
+    /// the amount of spawn locations the farm has
+    pub fn farm_spots(&self) -> usize {
+        self.farm.len()
+    }
+
+    fn farm_count(&self, at: BlockLocation) -> usize {
+        let player = at.center_bottom();
+        self.farm.iter().filter(|&&spawn| in_ring(player, spawn)).count()
+    }
+
+    fn other_count(&self, at: BlockLocation) -> usize {
+        let player = at.center_bottom();
+        self.other.iter().filter(|&&spawn| in_ring(player, spawn)).count()
+    }
+
+    pub fn evaluate(&self, at: BlockLocation) -> AfkSpot {
+        AfkSpot {
+            location: at,
+            farm: self.farm_count(at),
+            other: self.other_count(at),
+        }
+    }
+
+    /// The `amount` best places to stand in `search`: the most farm coverage first, then the least other spawnable
+    /// area. Spots which do not cover the farm at all are left out.
+    pub fn best(&self, world: &WorldBlocks, search: Area, amount: usize) -> Vec<AfkSpot> {
+        if amount == 0 {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<_> = search.iter()
+            .filter(|&loc| open_floor(world, loc))
+            .map(|loc| (loc, self.farm_count(loc)))
+            .filter(|&(_, farm)| farm > 0)
+            .collect();
+
+        candidates.sort_by(|(a_loc, a), (b_loc, b)| b.cmp(a).then(a_loc.cmp(b_loc)));
+
+        // counting the other spawn locations is the expensive part. With candidates ordered by farm coverage we can
+        // stop once a candidate covers less than the current top `amount`.
+        let mut evaluated: Vec<AfkSpot> = Vec::new();
+        for (location, farm) in candidates {
+            if evaluated.len() >= amount && farm < evaluated[amount - 1].farm {
+                break;
+            }
+            evaluated.push(AfkSpot { location, farm, other: self.other_count(location) });
+        }
+
+        evaluated.sort_by(|a, b| b.farm.cmp(&a.farm).then(a.other.cmp(&b.other)));
+        evaluated.truncate(amount);
+        evaluated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::afk::{AfkOptimizer, AfkSpot, Area};
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+
+    /// a 9x9 spawning platform at y = 100 above a flat world, with places to stand above the farm at different
+    /// heights
+    fn farm_world() -> WorldBlocks {
+        let mut world = WorldBlocks::flat();
+        for x in -4..=4 {
+            for z in -4..=4 {
+                world.set_block(BlockLocation::new(x, 99, z), BlockState::STONE);
+            }
+        }
+
+        for y in [110, 124, 140, 235] {
+            world.set_block(BlockLocation::new(0, y - 1, 0), BlockState::STONE);
+        }
+        world
+    }
+
+    #[test]
+    fn test_best() {
+        let world = farm_world();
+
+        let farm = Area::new(BlockLocation::new(-4, 100, -4), BlockLocation::new(4, 100, 4));
+        let search = Area::new(BlockLocation::new(-2, 105, -2), BlockLocation::new(2, 240, 2));
+        let optimizer = AfkOptimizer::new(&world, farm, search);
+        assert_eq!(optimizer.farm_spots(), 81);
+
+        let best = optimizer.best(&world, search, 5);
+
+        // 110 is too close to the farm and 235 too far. 140 is the only spot which covers the farm and is out of
+        // range of the ground (the other two are the other places to stand).
+        assert_eq!(best.len(), 2);
+        assert_eq!(best[0], AfkSpot { location: BlockLocation::new(0, 140, 0), farm: 81, other: 2 });
+        assert_eq!(best[1].location, BlockLocation::new(0, 124, 0));
+        assert_eq!(best[1].farm, 81);
+        assert!(best[1].other > 1000, "the ground is in range of 124");
+
+        assert_eq!(optimizer.evaluate(BlockLocation::new(0, 110, 0)).farm, 0);
+        assert_eq!(optimizer.best(&world, search, 1), vec![best[0]]);
+    }
+
+    #[test]
+    fn test_area() {
+        let area = Area::new(BlockLocation::new(2, 10, -3), BlockLocation::new(0, 5, 3));
+        assert_eq!(area.min, BlockLocation::new(0, 5, -3));
+        assert_eq!(area.iter().count(), 3 * 6 * 7);
+        assert!(area.contains(BlockLocation::new(1, 10, 0)));
+        assert!(!area.contains(BlockLocation::new(1, 11, 0)));
+        assert_eq!(area.expand(10).min.y, 0);
+    }
+}
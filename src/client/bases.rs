@@ -0,0 +1,254 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Spotting player bases while chunks load. Sections which hold unusually many blocks players place (crafted blocks,
+//! storage, glass) are flagged as likely bases. Section compositions are kept up to date by the world, so checking a
+//! column is cheap.
+
+use std::collections::HashMap;
+use std::fs;
+
+use float_ord::FloatOrd;
+use itertools::Itertools;
+
+use crate::error::{err, Res};
+use crate::storage::block::{BlockKind, BlockLocation};
+use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+use crate::storage::composition::Composition;
+
+/// a sighting is reported again if its score grows by this factor
+const REPORT_GROWTH: f64 = 1.25;
+
+/// (block id, weight) of blocks which are (almost) only found in bases. Blocks also generated in villages,
+/// strongholds and mineshafts weigh less.
+const DEFAULT_WEIGHTS: [(u32, f64); 20] = [
+    (138, 10.0), // beacon
+    (130, 8.0), // ender chest
+    (116, 6.0), // enchanting table
+    (145, 6.0), // anvil
+    (154, 6.0), // hopper
+    (26, 4.0), // bed
+    (117, 4.0), // brewing stand
+    (146, 3.0), // trapped chest
+    (95, 2.0), // stained glass
+    (160, 2.0), // stained glass pane
+    (54, 2.0), // chest
+    (58, 1.5), // crafting table
+    (61, 1.5), // furnace
+    (62, 1.5), // lit furnace
+    (20, 1.0), // glass
+    (71, 1.0), // iron door
+    (102, 0.5), // glass pane
+    (64, 0.5), // wooden door
+    (35, 0.5), // wool
+    (47, 0.5), // bookshelf
+];
+
+/// shulker boxes of every color
+const SHULKER_BOXES: std::ops::RangeInclusive<u32> = 219..=234;
+
+#[derive(Clone, Debug)]
+pub struct BaseConfig {
+    pub weights: HashMap<BlockKind, f64>,
+
+    /// the score a section needs to be a likely base
+    pub threshold: f64,
+}
+
+impl Default for BaseConfig {
+    fn default() -> Self {
+        let weights = DEFAULT_WEIGHTS.iter()
+            .map(|&(id, weight)| (BlockKind(id), weight))
+            .chain(SHULKER_BOXES.map(|id| (BlockKind(id), 10.0)))
+            .collect();
+
+        Self { weights, threshold: 30.0 }
+    }
+}
+
+impl BaseConfig {
+    /// The default weights with the weights in `path` taking precedence. Every line is `<block id>: <weight>` or
+    /// `threshold: <score>`. Empty lines and lines starting with `#` are ignored.
+    pub fn load(path: &str) -> Res<BaseConfig> {
+        let file = fs::read_to_string(path)?;
+        let mut config = BaseConfig::default();
+
+        for line in file.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (key, value) = line.split_once(':').ok_or_else(|| err(&format!("expected <block id>: <weight> but got {}", line)))?;
+            let value: f64 = value.trim().parse().map_err(|_| err(&format!("invalid number in {}", line)))?;
+
+            match key.trim() {
+                "threshold" => config.threshold = value,
+                id => {
+                    let id: u32 = id.parse().map_err(|_| err(&format!("invalid block id in {}", line)))?;
+                    config.weights.insert(BlockKind(id), value);
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn score(&self, composition: &Composition) -> f64 {
+        self.weights.iter()
+            .map(|(&kind, weight)| composition.kind(kind) as f64 * weight)
+            .sum()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BaseSighting {
+    pub chunk: ChunkLocation,
+
+    /// the index of the section in the column
+    pub section: u8,
+    pub score: f64,
+
+    /// the weighted kinds in the section, the most contributing first
+    pub kinds: Vec<(BlockKind, u32)>,
+}
+
+impl BaseSighting {
+    pub fn center(&self) -> BlockLocation {
+        BlockLocation::new((self.chunk.0 << 4) + 8, ((self.section as i16) << 4) + 8, (self.chunk.1 << 4) + 8)
+    }
+}
+
+/// Every likely base seen so far
+#[derive(Debug, Default)]
+pub struct BaseDetector {
+    pub config: BaseConfig,
+    sightings: HashMap<(ChunkLocation, u8), BaseSighting>,
+}
+
+impl BaseDetector {
+    pub fn new(config: BaseConfig) -> BaseDetector {
+        BaseDetector { config, sightings: HashMap::new() }
+    }
+
+    /// Check the sections of the loaded column at `location`. Returns the sightings which are new or scored a lot
+    /// higher than before. Every bot receives the same chunks, so chunks seen again are not reported again.
+    pub fn scan(&mut self, world: &WorldBlocks, location: ChunkLocation) -> Vec<BaseSighting> {
+        let mut reported = Vec::new();
+
+        for section in 0..16_u8 {
+            let composition = match world.section_composition(location, section as usize) {
+                Some(composition) => composition,
+                None => return reported
+            };
+
+            let score = self.config.score(&composition);
+            if score < self.config.threshold {
+                continue;
+            }
+
+            let previous = self.sightings.get(&(location, section)).map(|sighting| sighting.score);
+            if matches!(previous, Some(previous) if score < previous * REPORT_GROWTH) {
+                continue;
+            }
+
+            let kinds = self.config.weights.iter()
+                .map(|(&kind, weight)| (kind, composition.kind(kind), weight))
+                .filter(|(_, count, _)| *count > 0)
+                .sorted_by_key(|(_, count, weight)| FloatOrd(-(*count as f64) * *weight))
+                .map(|(kind, count, _)| (kind, count))
+                .collect();
+
+            let sighting = BaseSighting { chunk: location, section, score, kinds };
+            self.sightings.insert((location, section), sighting.clone());
+            reported.push(sighting);
+        }
+
+        reported
+    }
+
+    /// the highest score first
+    pub fn sightings(&self) -> Vec<&BaseSighting> {
+        self.sightings.values()
+            .sorted_by_key(|sighting| FloatOrd(-sighting.score))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::bases::{BaseConfig, BaseDetector};
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+
+    fn state(id: u32) -> BlockState {
+        BlockState(id << 4)
+    }
+
+    #[test]
+    fn test_detect() {
+        let mut world = WorldBlocks::flat();
+        let mut detector = BaseDetector::default();
+
+        // terrain and a village-like house are not bases
+        for x in 0..16 {
+            world.set_block(BlockLocation::new(x, 5, 0), state(5));
+            world.set_block(BlockLocation::new(x, 6, 0), state(102));
+        }
+        world.set_block(BlockLocation::new(1, 5, 1), state(58));
+        assert!(detector.scan(&world, ChunkLocation(0, 0)).is_empty());
+
+        // a storage room
+        for x in 0..6 {
+            world.set_block(BlockLocation::new(x, 20, 5), state(54));
+            world.set_block(BlockLocation::new(x, 21, 5), state(219 + x as u32));
+        }
+        world.set_block(BlockLocation::new(0, 20, 8), state(130));
+
+        let sightings = detector.scan(&world, ChunkLocation(0, 0));
+        assert_eq!(sightings.len(), 1);
+        let sighting = &sightings[0];
+        assert_eq!(sighting.section, 1);
+        assert_eq!(sighting.center(), BlockLocation::new(8, 24, 8));
+        assert!((sighting.score - (6.0 * 10.0 + 6.0 * 2.0 + 8.0)).abs() < 1e-6);
+        assert_eq!(sighting.kinds.first(), Some(&(BlockKind(54), 6)));
+        assert_eq!(sighting.kinds.last(), Some(&(BlockKind(130), 1)));
+
+        // another bot receives the same chunk
+        assert!(detector.scan(&world, ChunkLocation(0, 0)).is_empty());
+        assert_eq!(detector.sightings().len(), 1);
+
+        // unloaded chunks have nothing to report
+        assert!(detector.scan(&world, ChunkLocation(1000, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_weights() {
+        let path = std::env::temp_dir().join(format!("swarm-bot-bases-{}.txt", std::process::id()));
+        std::fs::write(&path, "# stone counts a bit\n1: 0.01\nthreshold: 2\n\n54: 0\n").unwrap();
+        let config = BaseConfig::load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!((config.threshold - 2.0).abs() < f64::EPSILON);
+        assert!(config.weights[&BlockKind(54)] < f64::EPSILON);
+        assert!((config.weights[&BlockKind(138)] - 10.0).abs() < f64::EPSILON);
+
+        let mut world = WorldBlocks::flat();
+        world.set_block(BlockLocation::new(0, 1, 0), state(1));
+        world.set_block(BlockLocation::new(0, 2, 0), state(54));
+        let mut detector = BaseDetector::new(config);
+
+        // the stone floor and one more stone block
+        let sightings = detector.scan(&world, ChunkLocation(0, 0));
+        assert_eq!(sightings.len(), 1);
+        assert!((sightings[0].score - 2.57).abs() < 1e-6);
+    }
+}
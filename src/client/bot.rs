@@ -14,58 +14,380 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::collections::VecDeque;
+use std::convert::TryFrom;
 use std::fmt::{Display, Formatter};
+use std::fs::File;
 use std::num::ParseIntError;
 use std::time::Instant;
 
-use float_ord::FloatOrd;
-use itertools::Itertools;
-
+use crate::chat::format::Msg;
+use crate::client::afk::{AfkOptimizer, Area};
+use crate::client::commands::Selection2D;
+use crate::client::controller::Controller;
+use crate::client::desync::Resync;
+use crate::client::estimate::{Plan, TaskEstimate};
+use crate::client::find::{find, kind_by_name};
+use crate::client::metrics::MetricsSnapshot;
+use crate::client::physics::Actions;
+use crate::client::recording::{Event, Recording};
+use crate::client::routine::{Demonstration, Routine, Step};
+use crate::client::reply::{BaseRow, block_name, EstimateRow, Item, KickRow, OnlineRow, PlayerRow, ProxyRow, Reply, StatsRow, WaypointRow};
+use crate::client::snapshot::SavedTask;
 use crate::client::state::global::GlobalState;
+use crate::client::state::global::expectations::{EXPECT_TICKS, Rejection};
+use crate::client::state::global::mine_alloc::{MineAlloc, MinePreference};
+use crate::client::state::global::waypoints::{Waypoint, WaypointError};
 use crate::client::state::local::LocalState;
-use crate::client::tasks::{Task, TaskTrait};
+use crate::client::state::local::inventory::ArmorWarning;
+use crate::client::swarm::Scale;
+use crate::client::tasks::{Task, TaskError, TaskTrait};
+use crate::client::tasks::combat::{CombatConfig, CombatTask, Target};
 use crate::client::tasks::compound::CompoundTask;
+use crate::client::tasks::deposit::DepositTask;
 use crate::client::tasks::eat::EatTask;
 use crate::client::tasks::fall_bucket::FallBucketTask;
+use crate::client::tasks::farm::FarmTask;
+use crate::client::tasks::fly::FlyTask;
+use crate::client::tasks::follow_leader::{DEFAULT_SPACING, FollowLeaderTask};
+use crate::client::tasks::guard::GuardTask;
+use crate::client::tasks::hold::HoldTask;
+use crate::client::tasks::light_up::{self, LightUpTask};
 use crate::client::tasks::mine::MineTask;
-use crate::client::tasks::navigate::BlockTravelTask;
-use crate::protocol::{EventQueue, Face, InterfaceOut};
-use crate::storage::block::BlockLocation;
+use crate::client::tasks::mine_region::MineRegion;
+use crate::client::tasks::navigate::{BlockReachTask, BlockTravelTask};
+use crate::client::tasks::pillar::PillarTask;
+use crate::client::tasks::read_signs::ReadSignsTask;
+use crate::client::tasks::refresh::RefreshTask;
+use crate::client::tasks::routine::RoutineTask;
+use crate::client::tasks::set_spawn::SetSpawnTask;
+use crate::client::tasks::travel::TravelTask;
+use crate::client::tasks::write_book::WriteBookTask;
+use crate::error::SwarmError;
+use crate::protocol::{EventQueue, Hand, InterfaceOut};
+use crate::protocol::disconnect::{Disconnect, DisconnectReason};
+use crate::schematic::Schematic;
+use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+use crate::storage::blocks::ChunkLocation;
+use crate::storage::blocks::raycast::AimError;
 use crate::types::Displacement;
 
+/// tasks which keep the bot alive and jump the queue with [`ActionState::suspend`]
+const REFLEXES: &[&str] = &["EatTask", "FallBucketTask"];
+
 #[derive(Default)]
 pub struct ActionState {
     task: Option<Task>,
+
+    /// Tasks which start in order once the current task finishes. Tasks interrupted by [`ActionState::suspend`] are
+    /// at the front.
+    queue: VecDeque<Task>,
+
+    /// incremented every time a task is started so we can tell two tasks of the same kind apart
+    scheduled: u64,
 }
 
 impl ActionState {
+    /// run `task` once the queued tasks are done, or right away if there is nothing to do
     pub fn schedule<T: Into<Task>>(&mut self, task: T) {
+        if self.task.is_some() {
+            self.queue.push_back(task.into());
+            return;
+        }
         self.task = Some(task.into());
+        self.scheduled += 1;
+    }
+
+    /// run `task` before continuing with the current task and the queue, i.e., for reflexes like eating
+    pub fn suspend<T: Into<Task>>(&mut self, task: T) {
+        if let Some(current) = self.task.replace(task.into()) {
+            self.queue.push_front(current);
+        }
+        self.scheduled += 1;
     }
+
+    /// Run `task` before the queued tasks, i.e., for going back for our items after dying. A running reflex finishes
+    /// first, any other task is suspended.
+    pub fn prioritize<T: Into<Task>>(&mut self, task: T) {
+        match self.current() {
+            Some((_, name)) if REFLEXES.contains(&name) => self.queue.push_front(task.into()),
+            _ => self.suspend(task)
+        }
+    }
+
     pub fn clear(&mut self) {
         self.task = None;
+        self.queue.clear();
+    }
+
+    /// drop the queued tasks but keep running the current one
+    pub fn clear_queue(&mut self) {
+        self.queue.clear();
+    }
+
+    /// stop the current task and start the next queued one. Returns the name of the stopped task.
+    pub fn skip(&mut self) -> Option<&'static str> {
+        let name = self.task.take()?.name();
+        self.next();
+        Some(name)
+    }
+
+    fn next(&mut self) {
+        self.task = self.queue.pop_front();
+        if self.task.is_some() {
+            self.scheduled += 1;
+        }
+    }
+
+    /// do the expensive part of the current task, i.e., pathfinding
+    pub fn expensive(&mut self, end_by: Instant, local: &mut LocalState, global: &GlobalState) {
+        if let Some(task) = self.task.as_mut() {
+            task.expensive(end_by, local, global);
+        }
+    }
+
+    pub fn current(&self) -> Option<(u64, &'static str)> {
+        self.task.as_ref().map(|task| (self.scheduled, task.name()))
+    }
+
+    /// the names of the tasks which run after the current one, in order
+    pub fn queued(&self) -> impl Iterator<Item=&'static str> + '_ {
+        self.queue.iter().map(Task::name)
+    }
+
+    /// the current and queued tasks which can be resumed after a restart, in order. The others are dropped.
+    pub fn save(&self, local: &LocalState) -> Vec<SavedTask> {
+        self.task.iter().chain(&self.queue)
+            .filter_map(|task| {
+                let saved = task.save(local);
+                if saved.is_none() {
+                    info!(task = task.name(), "not keeping the task as it cannot be resumed");
+                }
+                saved
+            })
+            .collect()
+    }
+
+    /// queue the tasks of a snapshot
+    pub fn resume(&mut self, tasks: Vec<SavedTask>, local: &LocalState, global: &GlobalState) {
+        for saved in tasks {
+            match saved.clone().restore(local, global) {
+                Some(task) => self.schedule(task),
+                None => info!(task = ?saved, "dropping a saved task which no longer applies"),
+            }
+        }
+    }
+
+    /// Tick the current task. Returns its name if it finished (or gave up). The next queued task starts the tick after.
+    pub fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> Option<&'static str> {
+        let task = self.task.as_mut()?;
+
+        // a task which gave up ends the task it is part of too
+        if !task.tick(out, local, global) && local.failure.is_none() {
+            return None;
+        }
+
+        let name = task.name();
+        self.next();
+        Some(name)
     }
 }
 
+/// how often we check that a totem is in the off-hand. Often enough to re-equip well within a second of one popping.
+const TOTEM_CHECK_TICKS: usize = 5;
+
 pub struct Bot<Queue: EventQueue, Out: InterfaceOut> {
     pub state: LocalState,
     pub actions: ActionState,
     pub queue: Queue,
     pub out: Out,
+    pub recording: Recording,
+
+    /// the task we were running last tick. Used to record task transitions.
+    pub last_task: Option<(u64, &'static str)>,
+
+    /// whether the server thinks we are sneaking
+    pub sneaking: bool,
+
+    /// whether the server thinks we are sprinting
+    pub sprinting: bool,
+
+    /// task events which have not been streamed to WebSocket clients yet
+    pub events: Vec<Event>,
+
+    /// what drives the bot when SwarmBot is used as a library. None if only commands do.
+    pub controller: Option<Box<dyn Controller>>,
 }
 
 impl<Queue: EventQueue, Out: InterfaceOut> Bot<Queue, Out> {
-    pub fn run_sync(&mut self, global: &mut GlobalState) {
-        match self.actions.task.as_mut() {
+    fn record_task_transition(&mut self) {
+        let current = self.actions.current();
+        if current == self.last_task {
+            return;
+        }
+
+        if let Some((_, name)) = self.last_task {
+            self.task_event(|| Event::TaskCancelled { name: name.to_string() });
+        }
+
+        if let Some((_, name)) = current {
+            self.task_event(|| Event::TaskStarted { name: name.to_string() });
+        }
+
+        self.last_task = current;
+    }
+
+    fn task_event(&mut self, event: impl Fn() -> Event) {
+        self.recording.record(&event);
+        self.events.push(event());
+    }
+
+    /// refresh the chunks around us (or reconnect) if the world looks out of sync
+    fn check_desync(&mut self, global: &mut GlobalState) {
+        let refreshing = matches!(self.actions.current(), Some((_, "RefreshTask")));
+        if refreshing {
+            return;
+        }
+
+        match self.state.desync.check(self.state.ticks, &global.config.resync) {
             None => {}
-            Some(task) => {
-                if task.tick(&mut self.out, &mut self.state, global) {
-                    self.actions.task = None;
+            Some(Resync::Refresh) => {
+                self.actions.suspend(RefreshTask::new(global.config.resync.radius, &self.state));
+            }
+            Some(Resync::Reconnect) => {
+                warn!("reconnecting because the world is badly out of sync");
+                self.state.disconnected = true;
+                self.state.disconnect = Some(Disconnect {
+                    reason: DisconnectReason::Resync,
+                    text: "world out of sync".to_string(),
+                    raw: String::new(),
+                });
+            }
+        }
+    }
+
+    /// Undo the changes the server did not answer in time. The task gives up if the server rejected one of its changes.
+    fn check_rejections(&mut self, global: &mut GlobalState) {
+        let timeout = global.lag_ticks(EXPECT_TICKS);
+        global.expectations.expire(global.dimension, global.ticks, timeout, &mut global.blocks);
+
+        for Rejection { location, expected, actual } in global.expectations.take_rejected(self.state.bot_id) {
+            warn!(%location, ?expected, ?actual, "the server rejected a block change");
+            self.task_event(|| Event::ActionRejected { location, expected: expected.kind().id(), actual: actual.map(|state| state.kind().id()) });
+            if self.actions.current().is_some() {
+                self.state.failure.get_or_insert(TaskError::Rejected { location });
+            }
+        }
+    }
+
+    /// A tick of the bot which does not depend on the protocol. Returns what physics did, i.e., for a simulation to
+    /// apply the fall damage like a server would.
+    pub fn run_sync(&mut self, global: &mut GlobalState) -> Actions {
+        self.recording.set_tick(self.state.ticks);
+        self.record_task_transition();
+
+        // actions held back by the budget go out before the ones of this tick
+        self.out.flush_budget();
+
+        if let Some(demonstration) = self.state.demonstration.as_mut() {
+            // the player is only sampled while they are in view
+            let location = global.players.by_name(&demonstration.player)
+                .map(|player| player.uuid)
+                .and_then(|uuid| global.entities.by_player_uuid(uuid))
+                .and_then(|id| global.entities.by_id(id))
+                .map(|entity| entity.location);
+
+            if let Some(location) = location {
+                demonstration.sample(self.state.ticks, location);
+            }
+        }
+
+        // keep a totem in the off-hand whenever we have one
+        if self.state.ticks % TOTEM_CHECK_TICKS == 0 {
+            self.state.inventory.equip_offhand(BlockKind::TOTEM, &mut self.out);
+        }
+
+        // armor wears out (the server sends its new damage) and better armor can be picked up
+        if self.state.inventory.take_changed() {
+            for warning in self.state.inventory.check_armor(&mut self.out) {
+                let ArmorWarning { slot, kind, left, unequipped } = warning;
+                warn!(?slot, kind = kind.id(), left, unequipped, "armor is breaking");
+                self.task_event(|| Event::ArmorBreaking { slot, kind: kind.id(), left, unequipped });
+            }
+            self.state.inventory.equip_armor(&mut self.out);
+        }
+
+        // the server sent the knockback since the last tick
+        if let Some((tick, velocity)) = self.state.last_knockback {
+            if tick == self.state.ticks {
+                debug!(?velocity, "knocked back");
+                self.task_event(|| Event::Knockback { velocity });
+            }
+        }
+
+        self.check_desync(global);
+        self.check_rejections(global);
+
+        // bots without a task see fewer chunks, so the server sends them less
+        if let Some(chunks) = self.state.view.tick(self.actions.current().is_some(), &global.config.view) {
+            debug!(chunks, "view distance changed");
+            self.out.set_view_distance(chunks);
+        }
+
+        let chat = std::mem::take(&mut self.state.chat);
+        if let Some(controller) = self.controller.as_mut() {
+            for message in &chat {
+                controller.on_chat(message, &mut self.out, &mut self.actions, &mut self.state, global);
+            }
+            controller.tick(&mut self.out, &mut self.actions, &mut self.state, global);
+        }
+
+        // a task does not go on while actions it made still wait for the budget
+        let finished = match self.out.deferred() {
+            0 => self.actions.tick(&mut self.out, &mut self.state, global),
+            _ => None,
+        };
+        if let Some(name) = finished {
+            match self.state.failure.take() {
+                Some(reason) => {
+                    self.state.last_failure = Some(format!("{} {}", name, reason));
+                    self.task_event(|| Event::TaskFailed { name: name.to_string(), reason: reason.to_string() });
+                    warn!(error = %SwarmError::Task { name, reason }, "task failed");
                 }
+                None => self.task_event(|| Event::TaskFinished { name: name.to_string() }),
             }
+            self.last_task = None;
         }
+        let mode = self.last_task.map(|(_, name)| name).unwrap_or("idle");
+        let before = self.state.physics.location();
+
+        self.state.effects.tick();
+        self.state.physics.set_effects(&self.state.effects);
+
         let actions = self.state.physics.tick(&mut global.blocks, &self.state.inventory);
         let physics = &self.state.physics;
+
+        self.state.stats.ticks += 1;
+        self.state.stats.traveled(mode, before.dist2(physics.location()).sqrt());
+
+        // like vanilla entity actions go before the movement packet so the server moves us at the right speed
+        if physics.sprinting() != self.sprinting {
+            self.sprinting = physics.sprinting();
+            self.out.sprint(self.sprinting);
+        }
+
+        if physics.sneaking() != self.sneaking {
+            self.sneaking = physics.sneaking();
+            self.out.sneak(self.sneaking);
+        }
+
+        // flying stops by itself when we land
+        if physics.flying() != self.state.abilities.flying {
+            self.state.abilities.flying = physics.flying();
+            self.out.abilities(self.state.abilities);
+        }
+
+        // called every tick. The interface decides which movement packet (if any) this turns into, like vanilla.
         self.out.teleport_and_look(physics.location(), physics.direction(), physics.on_ground());
 
         // if self.actions.task.is_none() {
@@ -81,6 +403,13 @@ impl<Queue: EventQueue, Out: InterfaceOut> Bot<Queue, Out> {
         //
         //
         if let Some(place) = actions.block_placed.as_ref() {
+            if let Some(current) = self.state.inventory.current() {
+                self.state.stats.placed(current.kind);
+
+                let expected = BlockState::from(current.kind.id(), current.damage);
+                let location = place.location + place.face.change();
+                global.expectations.expect(self.state.bot_id, global.dimension, location, place.replaced, expected, global.ticks);
+            }
             self.out.swing_arm();
             self.out.place_block(place.location, place.face);
         }
@@ -88,6 +417,7 @@ impl<Queue: EventQueue, Out: InterfaceOut> Bot<Queue, Out> {
         // this should be after everything else as actions depend on the previous location
 
         self.state.ticks += 1;
+        actions
     }
 }
 
@@ -99,6 +429,33 @@ pub enum ProcessError {
 
     #[error(transparent)]
     Count(#[from] WrongArgCount),
+
+    #[error(transparent)]
+    Aim(#[from] AimError),
+
+    #[error("there is no block called {0}")]
+    UnknownBlock(String),
+
+    #[error("could not load schematic {0}")]
+    Schematic(String),
+
+    #[error("could not save {0}")]
+    Export(String),
+
+    #[error("the area is too large")]
+    TooLarge,
+
+    #[error("the server does not let me fly")]
+    CannotFly,
+
+    #[error("I do not know the light around here (set keep-light: true)")]
+    NoLight,
+
+    #[error("I am not in creative mode")]
+    NotCreative,
+
+    #[error(transparent)]
+    Waypoint(#[from] WaypointError),
 }
 
 #[derive(Debug)]
@@ -122,23 +479,44 @@ impl Display for WrongArgCount {
     }
 }
 
-/// Always returns None.
-#[allow(clippy::many_single_char_names)]
-pub fn process_command(name: &str, args: &[&str], local: &mut LocalState, global: &mut GlobalState, actions: &mut ActionState, out: &mut impl InterfaceOut) -> Result<(), ProcessError> {
-
-    // println! but bold
-    macro_rules! msg {
-        () => {{
-            println!();
-        }};
-        ($($msg: expr),*) => {{
-            let to_print_raw = format!($($msg),*);
-            let to_print = ansi_term::Color::Black.bold().paint(to_print_raw).to_string();
-            println!("{}", to_print);
-        }};
+/// The commands chained by `#do`, i.e., the words of each command between semicolons. A semicolon does not need
+/// spaces around it.
+pub fn chained<'a>(args: &[&'a str]) -> Vec<Vec<&'a str>> {
+    let mut commands = vec![Vec::new()];
+    for &arg in args {
+        for (i, word) in arg.split(';').enumerate() {
+            if i > 0 {
+                commands.push(Vec::new());
+            }
+            if !word.is_empty() {
+                commands.last_mut().unwrap().push(word);
+            }
+        }
     }
+    commands.retain(|words| !words.is_empty());
+    commands
+}
 
-    match name {
+/// the pages of a book command. A `|` starts a new page (`;` already chains commands).
+pub fn book_pages(args: &[&str]) -> Vec<String> {
+    args.join(" ").split('|').map(|page| page.trim().to_string()).collect()
+}
+
+/// print the reply in its color
+pub fn print_reply(reply: &Reply) {
+    println!("{}", reply.to_msg().to_ansi());
+}
+
+/// print why a command failed
+pub fn print_error(err: &ProcessError) {
+    println!("{}", Msg::error(format!("could not process command. Reason: {}", err)).to_ansi());
+}
+
+/// Information commands return a [`Reply`] which the caller renders (text for chat, JSON for the WebSocket). Other
+/// commands return None. `sender` is the player who sent the command if it came from chat.
+#[allow(clippy::many_single_char_names)]
+pub fn process_command(name: &str, args: &[&str], sender: Option<&str>, local: &mut LocalState, global: &mut GlobalState, actions: &mut ActionState, out: &mut impl InterfaceOut) -> Result<Option<Reply>, ProcessError> {
+    let reply = match name {
         // "pillar" => {
         //     if let [a] = args {
         //         let y = a.parse()?;
@@ -166,23 +544,196 @@ pub fn process_command(name: &str, args: &[&str], local: &mut LocalState, global
         //     local.physics.jump();
         // }
         "health" => {
-            println!("Health: {}, Food: {}", local.health, local.food);
+            Some(Reply::Health { health: local.health, food: local.food })
+        }
+        "status" => {
+            Some(Reply::Status {
+                username: local.info.username.clone(),
+                location: local.physics.location(),
+                dimension: local.dimension.to_string(),
+                health: local.health,
+                food: local.food,
+                task: actions.current().map(|(_, task)| task.to_string()),
+                failed: local.last_failure.clone(),
+                tps: global.tps,
+                tick_millis: global.tick_millis,
+                ping: global.players.mean_ping(),
+                metrics: MetricsSnapshot::take(global),
+            })
+        }
+        "players" => {
+            let players = global.players.iter()
+                .map(|player| PlayerRow { name: player.name.clone(), uuid: format!("{:032x}", player.uuid) })
+                .collect();
+            Some(Reply::Players { players })
+        }
+        "online" => {
+            let players = global.players.iter()
+                .map(|player| OnlineRow { name: player.name.clone(), ping: player.ping })
+                .collect();
+            Some(Reply::Online { players })
+        }
+        "stats" => {
+            Some(Reply::Stats {
+                username: local.info.username.clone(),
+                stats: StatsRow::new(&local.stats, &global.block_data),
+            })
+        }
+        "chunks" => {
+            let current = ChunkLocation::from(local.physics.location());
+            Some(Reply::Chunks {
+                loaded: global.blocks.chunk_count(),
+                generation: global.blocks.generation(),
+                current,
+                current_loaded: global.blocks.chunk_generation(current).is_some(),
+            })
+        }
+        "scan" => {
+            // scan <block id> [amount of locations to return]
+            let (id, amount) = match args {
+                [id] => (id.parse()?, 10),
+                [id, amount] => (id.parse()?, amount.parse()?),
+                _ => return Ok(None),
+            };
+
+            let kind = BlockKind::from(id);
+            let origin = BlockLocation::from(local.physics.location());
+
+            // the total comes from the counts every section keeps, only the nearest blocks need a scan
+            let count = global.blocks.composition().kind(kind) as usize;
+            let nearest = global.blocks.closest_kind_iter(origin, usize::MAX, kind).take(amount).collect();
+
+            Some(Reply::Scan { id, name: block_name(&global.block_data, kind), count, nearest })
+        }
+        "find" => {
+            // find <block name or id> [amount] [near <x y z | waypoint>]: the closest blocks of the kind. Whispered to
+            // whoever asked.
+            let (name, rest) = match args {
+                [name, rest @ ..] => (*name, rest),
+                _ => return Ok(None),
+            };
+
+            let (amount, rest) = match rest {
+                [amount, rest @ ..] if *amount != "near" => (amount.parse()?, rest),
+                _ => (1, rest),
+            };
+
+            let origin = match rest {
+                [] => BlockLocation::from(local.physics.location()),
+                ["near", location @ ..] => global.waypoints.location(location, local.dimension)?,
+                _ => return Ok(None),
+            };
+
+            let kind = kind_by_name(&global.block_data, name).ok_or_else(|| ProcessError::UnknownBlock(name.to_string()))?;
+            let found = find(&global.blocks, origin, kind, amount, &global.config.find);
+            let reply = Reply::Found { name: block_name(&global.block_data, kind), found };
+
+            if let Some(sender) = sender {
+                for line in reply.to_string().lines() {
+                    out.send_chat(&format!("/msg {} {}", sender, line));
+                }
+            }
+
+            Some(reply)
         }
         "follow" => {
             local.follow_closest = true;
+            None
+        }
+        "follow-leader" => {
+            // follow-leader <name> [spacing]
+            let (leader, spacing) = match args {
+                [leader] => (leader, DEFAULT_SPACING),
+                [leader, spacing] => (leader, spacing.parse()?),
+                _ => return Ok(None),
+            };
+
+            // the leader itself does not follow
+            if *leader != local.info.username {
+                actions.schedule(FollowLeaderTask::new(leader, spacing));
+            }
+            None
+        }
+        "fight" => {
+            // fight <player> | fight nearest [players to leave alone..]
+            let target = match args {
+                ["nearest", whitelist @ ..] => {
+                    // never the player who asked
+                    let whitelist = whitelist.iter().copied().chain(sender).map(String::from).collect();
+                    Target::NearestPlayer { whitelist }
+                }
+                [name] => {
+                    let uuid = global.player_by_name(name).map(|player| player.uuid);
+                    match uuid.and_then(|uuid| global.entities.by_player_uuid(uuid)) {
+                        Some(id) => Target::Entity(id),
+                        None => return Ok(None),
+                    }
+                }
+                _ => return Ok(None),
+            };
+
+            actions.schedule(CombatTask::new(target, CombatConfig::default()));
+            None
+        }
+        "guard" => {
+            // guard <x y z | waypoint> <radius> [players [players to leave alone..]]: fight the mobs (and with players,
+            // the players but the bots and the player who asked) which come within the radius
+            let split = if args.len() > 3 && args[..3].iter().all(|arg| arg.parse::<i32>().is_ok()) { 3 } else { 1 };
+            if args.len() <= split {
+                return Ok(None);
+            }
+            let (post, rest) = args.split_at(split);
+            let post = global.waypoints.location(post, local.dimension)?;
+
+            let (radius, rest): (u32, _) = match rest {
+                [radius, rest @ ..] => (radius.parse()?, rest),
+                _ => return Ok(None),
+            };
+
+            let players = match rest {
+                [] => None,
+                ["players", whitelist @ ..] => Some(whitelist.iter().copied().chain(sender).map(String::from).collect()),
+                _ => return Ok(None),
+            };
+
+            actions.schedule(GuardTask::new(post, f64::from(radius), players));
+            None
+        }
+        "give" => {
+            // give <item name or id> [count]: take the item out of thin air, only in creative mode (i.e., on test
+            // servers)
+            let (name, count) = match args {
+                [name] => (*name, 1),
+                [name, count] => (*name, count.parse()?),
+                _ => return Ok(None),
+            };
+
+            if !local.gamemode().creative() {
+                return Err(ProcessError::NotCreative);
+            }
+
+            let id = kind_by_name(&global.block_data, name)
+                .and_then(BlockKind::item_id)
+                .ok_or_else(|| ProcessError::UnknownBlock(name.to_string()))?;
+            let slot = local.inventory.free_slot().unwrap_or_else(|| local.inventory.hand_slot(Hand::Main));
+            local.inventory.conjure(slot, id, count, out);
+            None
         }
         "kys" => {
             // TODO: try to kill themself by fall damage/lava/etc
+            None
         }
         "eat" => {
             let eat_task = EatTask::default();
-            actions.task = Some(eat_task.into());
+            actions.schedule(eat_task);
+            None
         }
         "slot" => {
             if let [number] = args {
                 let number: u8 = number.parse().unwrap();
-                local.inventory.change_slot(number, out);
+                local.inventory.select_slot(number, out);
             }
+            None
         }
         "fall" => {
             let below = BlockLocation::from(local.physics.location()).below();
@@ -192,9 +743,11 @@ pub fn process_command(name: &str, args: &[&str], local: &mut LocalState, global
             let mut compound = CompoundTask::default();
             compound.add(mine).add(fall);
             actions.schedule(compound);
+            None
         }
         "drop" => {
             local.inventory.drop_hotbar(out);
+            None
         }
         "goto" => {
             // if let [id] = args {
@@ -212,57 +765,422 @@ pub fn process_command(name: &str, args: &[&str], local: &mut LocalState, global
             //     }
             // }
 
-            if let [a, b, c] = args {
-                let x = a.parse()?;
-                let y = b.parse()?;
-                let z = c.parse()?;
-                let dest = BlockLocation::new(x, y, z);
-                actions.schedule(BlockTravelTask::new(dest, local));
-            }
+            // goto <x y z | waypoint>
+            let dest = global.waypoints.location(args, local.dimension)?;
+            actions.schedule(BlockTravelTask::new(dest, local).prefer_flying());
+            None
+        }
+        "travel" => {
+            // travel <x y z | waypoint>, planned a few hundred blocks at a time so it can be thousands of blocks away
+            let dest = global.waypoints.location(args, local.dimension)?;
+            actions.schedule(TravelTask::new(dest));
+            None
         }
         "stop" => {
-            actions.task = None;
+            actions.clear();
+            None
+        }
+        "queue" => {
+            Some(Reply::Queue {
+                current: actions.current().map(|(_, task)| task.to_string()),
+                queued: actions.queued().map(String::from).collect(),
+            })
+        }
+        "clear-queue" => {
+            actions.clear_queue();
+            None
+        }
+        "skip" => {
+            if let Some(skipped) = actions.skip() {
+                info!(task = skipped, "skipped");
+            }
+            None
+        }
+        "do" => {
+            // do <command> [args..] ; <command> [args..] ; ...: queue the commands one after the other
+            let mut reply = None;
+            for words in chained(args) {
+                if let Some(last) = process_command(words[0], &words[1..], sender, local, global, actions, out)? {
+                    reply = Some(last);
+                }
+            }
+            reply
+        }
+        "mine" => {
+            // mine <x y z | waypoint>: walk next to the block and break it
+            let location = global.waypoints.location(args, local.dimension)?;
+
+            // the tool and face are picked once we are there
+            let routine = Routine { steps: vec![Step::Break { location }] };
+            let mut compound = CompoundTask::default();
+            compound.add(BlockReachTask::new(location, local, global))
+                .add(RoutineTask::new(&routine));
+            actions.schedule(compound);
+            None
+        }
+        "deposit" => {
+            // deposit <x y z | waypoint>: walk to the chest and put everything in it
+            let chest = global.waypoints.location(args, local.dimension)?;
+
+            let mut compound = CompoundTask::default();
+            compound.add(BlockReachTask::new(chest, local, global))
+                .add(DepositTask::new(chest, Vec::new(), None));
+            actions.schedule(compound);
+            None
+        }
+        "light-up" => {
+            // light-up [radius]: torch the spots around us where mobs could spawn
+            let radius = match args {
+                [] => light_up::DEFAULT_RADIUS,
+                [radius] => radius.parse()?,
+                _ => return Ok(None),
+            };
+
+            let origin = BlockLocation::from(local.physics.location());
+            if global.blocks.light_at(origin).is_none() {
+                return Err(ProcessError::NoLight);
+            }
+            actions.schedule(LightUpTask::new(origin, radius));
+            None
+        }
+        "bed" => {
+            // bed <x y z | waypoint>: use the bed (either half) so we reappear there after dying
+            let bed = global.waypoints.location(args, local.dimension)?;
+            actions.schedule(SetSpawnTask::new(bed));
+            None
+        }
+        "waypoint" => match args {
+            // waypoint set <name> [x y z | waypoint] | waypoint del <name> | waypoint list
+            ["set", name, location @ ..] => {
+                let location = match location {
+                    [] => {
+                        // where the player who asked stands if we can see them, else where we are
+                        let seen = sender.and_then(|sender| global.players.by_name(sender).map(|player| player.uuid))
+                            .and_then(|uuid| global.entities.by_player_uuid(uuid))
+                            .and_then(|id| global.entities.by_id(id))
+                            .map(|entity| entity.location);
+                        BlockLocation::from(seen.unwrap_or_else(|| local.physics.location()))
+                    }
+                    location => global.waypoints.location(location, local.dimension)?,
+                };
+
+                info!(name, %location, dimension = %local.dimension, by = ?sender, "set waypoint");
+                global.waypoints.set(name, Waypoint::new(location, local.dimension, sender))?;
+                None
+            }
+            ["del", name] => {
+                global.waypoints.remove(name)?;
+                info!(name, by = ?sender, "removed waypoint");
+                None
+            }
+            ["list"] => {
+                let waypoints = global.waypoints.iter().map(|(name, waypoint)| WaypointRow::new(name, waypoint)).collect();
+                Some(Reply::Waypoints { waypoints })
+            }
+            _ => None,
+        },
+        "record" => {
+            // record start [bot] | record stop <name>
+            match args {
+                ["start"] => {
+                    local.demonstration = sender.map(Demonstration::new);
+                    None
+                }
+                ["start", bot] if bot == &local.info.username => {
+                    local.demonstration = sender.map(Demonstration::new);
+                    None
+                }
+                ["stop", name] => {
+                    let routine = match local.demonstration.take() {
+                        Some(demonstration) => demonstration.distill(),
+                        None => return Ok(None),
+                    };
+                    let reply = Reply::Routine { name: name.to_string(), steps: routine.steps.clone() };
+                    global.routines.insert(name.to_string(), routine);
+                    Some(reply)
+                }
+                _ => None
+            }
+        }
+        "run" => {
+            // run <name> [bot]
+            let name = match args {
+                [name] => name,
+                [name, bot] if bot == &local.info.username => name,
+                _ => return Ok(None),
+            };
+            if let Some(routine) = global.routines.get(*name) {
+                actions.schedule(RoutineTask::new(routine));
+            }
+            None
         }
         "loc" => {
-            msg!("My location is {} in {}", local.physics.location(), local.dimension);
+            Some(Reply::Location { location: local.physics.location(), dimension: local.dimension.to_string() })
         }
         "state" => {
-            if let [name] = args {
-                if name == &local.info.username {
-                    msg!("location {}", local.physics.location());
-                    msg!("on ground {}", local.physics.on_ground());
+            match args {
+                [name] if name == &local.info.username => {
                     let below_loc = BlockLocation::from(local.physics.location() - Displacement::EPSILON_Y);
-                    msg!("below kind {:?}", global.blocks.get_block_kind(below_loc));
-                    msg!("inventory slots {:?}", local.inventory.hotbar());
+                    Some(Reply::State {
+                        location: local.physics.location(),
+                        on_ground: local.physics.on_ground(),
+                        below: global.blocks.get_block_kind(below_loc).map(BlockKind::id),
+                        hotbar: local.inventory.hotbar().iter().map(|slot| slot.as_ref().map(Item::from)).collect(),
+                    })
+                }
+                _ => None
+            }
+        }
+        "proxies" => {
+            let proxies = global.proxies.stats().into_iter().map(ProxyRow::from).collect();
+            Some(Reply::Proxies { proxies })
+        }
+        "afk" => {
+            // afk <x1> <y1> <z1> <x2> <y2> <z2> [search radius] [go]: the corners of the farm's spawning area
+            let go = args.last() == Some(&"go");
+            let args = if go { &args[..args.len() - 1] } else { args };
+
+            let (corners, radius) = match args {
+                [corners @ .., radius] if corners.len() == 6 => (corners, radius.parse()?),
+                corners if corners.len() == 6 => (corners, 32),
+                _ => return Ok(None),
+            };
+
+            let farm = Area::new(BlockLocation::try_from(&corners[..3])?, BlockLocation::try_from(&corners[3..])?);
+            let search = farm.expand(radius);
+
+            let optimizer = AfkOptimizer::new(&global.blocks, farm, search);
+            let spots = optimizer.best(&global.blocks, search, 5);
+
+            if go {
+                if let Some(best) = spots.first() {
+                    let mut compound = CompoundTask::default();
+                    compound.add(BlockTravelTask::new(best.location, local))
+                        .add(HoldTask::new(best.location));
+                    actions.schedule(compound);
                 }
             }
+
+            Some(Reply::Afk { farm_spots: optimizer.farm_spots(), spots })
+        }
+        "farm" => {
+            // farm <x1> <y1> <z1> <x2> <y2> <z2> <crop id> [chest x y z]: harvest and replant the crops in the field
+            let (corners, crop, chest) = match args {
+                [corners @ .., crop] if corners.len() == 6 => (corners, crop, None),
+                [corners @ .., crop, x, y, z] if corners.len() == 6 => (corners, crop, Some(BlockLocation::try_from(&[*x, *y, *z][..])?)),
+                _ => return Ok(None),
+            };
+
+            let region = Area::new(BlockLocation::try_from(&corners[..3])?, BlockLocation::try_from(&corners[3..])?);
+            match FarmTask::new(region, BlockKind(crop.parse()?), chest) {
+                Some(task) => actions.schedule(task),
+                None => warn!(crop, "not a crop"),
+            }
+            None
+        }
+        "fly" => match args {
+            // fly <on | off>: start or stop flying like in creative mode
+            ["on"] => {
+                if !local.set_flying(true) {
+                    return Err(ProcessError::CannotFly);
+                }
+                None
+            }
+            ["off"] => {
+                local.set_flying(false);
+                None
+            }
+            _ => {
+                // fly <x y z | waypoint>: fly there with the worn elytra and land
+                let goal = global.waypoints.location(args, local.dimension)?;
+                actions.schedule(FlyTask::new(goal));
+                None
+            }
+        },
+        "estimate" => {
+            // estimate <goto x y z | mine x y z | clear x1 z1 x2 z2 | pillar y>: plan a task without running it. Waypoint
+            // names work in place of coordinates, one name for each corner of a clear.
+            let estimate = match args {
+                ["goto", location @ ..] => {
+                    let dest = global.waypoints.location(location, local.dimension)?;
+                    BlockTravelTask::new(dest, local).plan(local, global)
+                }
+                ["mine", location @ ..] => {
+                    let location = global.waypoints.location(location, local.dimension)?;
+                    let mut estimate = TaskEstimate::default();
+                    estimate.mine(location, local, global);
+                    estimate
+                }
+                ["clear", corners @ ..] => {
+                    let (from, rest) = global.waypoints.take_column(corners, local.dimension)?;
+                    let (to, rest) = global.waypoints.take_column(rest, local.dimension)?;
+                    if !rest.is_empty() {
+                        return Ok(None);
+                    }
+                    let Selection2D { from, to } = Selection2D { from, to }.normalize();
+
+                    // the same allocation a mine command would make
+                    let mut alloc = MineAlloc::default();
+                    alloc.mine(from, to, Some(MinePreference::FromDist));
+                    MineRegion::plan_regions(alloc.regions(), local, global)
+                }
+                ["pillar", y] => PillarTask::new(y.parse()?).plan(local, global),
+                _ => return Ok(None),
+            };
+
+            Some(Reply::Estimate { task: args.join(" "), estimate: EstimateRow::new(estimate, &global.block_data) })
+        }
+        "swarm" => {
+            // swarm add <n> | swarm remove <n|@name...> | swarm status. The runner does the scaling.
+            if let Some(scale) = Scale::parse(args)? {
+                global.swarm.push(sender, scale, global.ticks);
+            }
+            None
+        }
+        "reload-config" => {
+            // read --config again. The runner swaps the settings before the next tick.
+            global.reload_config = true;
+            None
+        }
+        "refresh" => {
+            // refresh [radius]: get the server to resend the chunks around the bot
+            let radius = match args {
+                [radius] => radius.parse()?,
+                _ => global.config.resync.radius,
+            };
+            actions.suspend(RefreshTask::new(radius, local));
+            None
+        }
+        "bases" => {
+            let bases = global.bases.sightings().into_iter().map(|sighting| BaseRow::new(sighting, &global.block_data)).collect();
+            Some(Reply::Bases { bases })
+        }
+        "signs" => {
+            // signs <x1> <y1> <z1> <x2> <y2> <z2>: the text of the loaded signs in the area
+            if args.len() != 6 {
+                return Ok(None);
+            }
+            let region = Area::new(BlockLocation::try_from(&args[..3])?, BlockLocation::try_from(&args[3..])?);
+            Some(Reply::Signs { signs: ReadSignsTask::signs(region, global) })
+        }
+        "read-signs" => {
+            // read-signs <x1> <y1> <z1> <x2> <y2> <z2>: say the text of the loaded signs in the area in chat
+            if args.len() != 6 {
+                return Ok(None);
+            }
+            let region = Area::new(BlockLocation::try_from(&args[..3])?, BlockLocation::try_from(&args[3..])?);
+            actions.schedule(ReadSignsTask::new(region, sender.map(String::from)));
+            None
+        }
+        "write-book" => {
+            // write-book <text>: write into a writable book in the hotbar. Pages are separated by |
+            actions.schedule(WriteBookTask::new(book_pages(args), None));
+            None
+        }
+        "sign-book" => {
+            // sign-book <title> <text>: write into a writable book in the hotbar and sign it
+            if let [title, text @ ..] = args {
+                actions.schedule(WriteBookTask::new(book_pages(text), Some(title.to_string())));
+            }
+            None
+        }
+        "kicks" => {
+            let accounts = global.kicks.accounts().into_iter().map(|(name, kicks)| KickRow::new(name, &kicks)).collect();
+            Some(Reply::Kicks { accounts })
         }
         "get" => {
-            if let [a, b, c] = args {
+            match args {
+                [a, b, c] => {
+                    let x = a.parse()?;
+                    let y = b.parse()?;
+                    let z = c.parse()?;
+                    let location = BlockLocation::new(x, y, z);
+
+                    Some(Reply::block(location, global.blocks.get_block(location)))
+                }
+                _ => None
+            }
+        }
+        "verify" => {
+            // verify <schematic> <x> <y> <z>: compare the world with the schematic with its lowest corner at x y z
+            if let [file, a, b, c] = args {
                 let x = a.parse()?;
                 let y = b.parse()?;
                 let z = c.parse()?;
                 let location = BlockLocation::new(x, y, z);
 
-                msg!("The block is {:?}", global.blocks.get_block(location));
+                let schematic = File::open(file).ok()
+                    .and_then(|mut reader| Schematic::load(&mut reader).ok())
+                    .ok_or_else(|| ProcessError::Schematic(file.to_string()))?;
+
+                let origin = schematic.origin().unwrap_or_default();
+                let offset = BlockLocation::new(x - origin.x, y - origin.y, z - origin.z);
+                let report = global.blocks.diff(&schematic, offset);
+
+                Some(Reply::Verify {
+                    schematic: file.to_string(),
+                    location,
+                    matches: report.matches.len(),
+                    missing: report.missing.len(),
+                    extra: report.extra.len(),
+                    unknown: report.unknown.len(),
+                })
+            } else {
+                None
             }
         }
+        "export" => {
+            // export <schem|map> <file> <x1> <y1> <z1> <x2> <y2> <z2>: save the known blocks in the area as a schematic
+            // or a top-down png map
+            let (kind, file, corners) = match args {
+                [kind, file, corners @ ..] if corners.len() == 6 => (*kind, *file, corners),
+                _ => return Ok(None),
+            };
+            let (from, to) = (BlockLocation::try_from(&corners[..3])?, BlockLocation::try_from(&corners[3..])?);
+            let region = Area::new(from, to);
+
+            // schematics store their size in shorts
+            let too_large = |from: i32, to: i32| to - from >= i16::MAX as i32;
+            if too_large(region.min.x, region.max.x) || too_large(region.min.z, region.max.z) {
+                return Err(ProcessError::TooLarge);
+            }
+
+            let (blocks, missing) = match kind {
+                "schem" => {
+                    let (schematic, missing) = Schematic::capture(&global.blocks, from, to)
+                        .map_err(|error| ProcessError::Export(format!("{} ({})", file, error)))?;
+                    let mut writer = File::create(file).map_err(|_| ProcessError::Export(file.to_string()))?;
+                    schematic.save(&mut writer).map_err(|_| ProcessError::Export(file.to_string()))?;
+                    (schematic.volume(), missing)
+                }
+                "map" => {
+                    let mut writer = File::create(file).map_err(|_| ProcessError::Export(file.to_string()))?;
+                    let (map, missing) = global.blocks.render_map(region);
+                    map.write_png(&mut writer).map_err(|_| ProcessError::Export(file.to_string()))?;
+                    (map.width as u64 * map.height as u64, missing)
+                }
+                _ => return Ok(None)
+            };
+
+            Some(Reply::Export { file: file.to_string(), blocks, missing })
+        }
         "place" => {
             if let [a, b, c] = args {
                 let x = a.parse()?;
                 let y = b.parse()?;
                 let z = c.parse()?;
 
-                let origin = local.physics.location() + Displacement::EYE_HEIGHT;
-
+                let origin = local.physics.location() + local.physics.eye_height();
                 let location = BlockLocation::new(x, y, z);
-                let faces = location.faces();
-                let best_loc_idx = IntoIterator::into_iter(faces).position_min_by_key(|loc| FloatOrd(loc.dist2(origin))).unwrap();
 
-                local.physics.look_at(faces[best_loc_idx]);
-                out.use_item();
-                out.place_block(location, Face::from(best_loc_idx as u8));
+                // place against a point we can see, servers reject placing against a block through another one
+                let aim = local.aim.aim(&global.blocks, origin, location, None)?;
+
+                local.physics.look_at(aim.point);
+                out.use_item(Hand::Main);
+                out.place_block(location, aim.face);
             }
+            None
         }
         // "mine" => {
         //     let origin = local.physics.location() + Displacement::EYE_HEIGHT;
@@ -274,14 +1192,12 @@ pub fn process_command(name: &str, args: &[&str], local: &mut LocalState, global
         //         actions.schedule(mine_task);
         //     }
         // }
-        _ => {}
-    }
+        _ => None
+    };
 
-    Ok(())
+    Ok(reply)
 }
 
 pub fn run_threaded(_: &rayon::Scope, local: &mut LocalState, actions: &mut ActionState, global: &GlobalState, end_by: Instant) {
-    if let Some(task) = actions.task.as_mut() {
-        task.expensive(end_by, local, global);
-    }
+    actions.expensive(end_by, local, global);
 }
@@ -0,0 +1,157 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+/// how long a tick takes in vanilla
+pub const TICK: Duration = Duration::from_millis(50);
+
+/// The most ticks run at once to catch up. If we are further behind (i.e., the process was suspended) the rest is
+/// dropped as the server has pulled us back anyway.
+pub const MAX_CATCH_UP: usize = 10;
+
+/// how much a new tick moves the average duration
+const SMOOTHING: f64 = 0.1;
+
+/// Schedules the ticks of the game loop on the wall clock. When the loop falls behind (a hiccup of the host, many
+/// bots replanning) the missed ticks are run at once so as many movement packets are sent per second as vanilla.
+pub struct TickClock {
+    /// when the next tick is due
+    next: Instant,
+
+    /// the average time it takes to run a tick in milliseconds
+    average: Option<f64>,
+}
+
+impl TickClock {
+    pub fn new(now: Instant) -> TickClock {
+        TickClock { next: now, average: None }
+    }
+
+    /// when to start the next iteration of the loop
+    pub fn next(&self) -> Instant {
+        self.next
+    }
+
+    /// How many ticks to run in the iteration starting `now`, at least one and at most [`MAX_CATCH_UP`]
+    pub fn due(&mut self, now: Instant) -> usize {
+        let behind = now.saturating_duration_since(self.next);
+        let due = 1 + (behind.as_nanos() / TICK.as_nanos()) as usize;
+
+        if due > MAX_CATCH_UP {
+            self.next = now + TICK;
+            MAX_CATCH_UP
+        } else {
+            self.next += TICK * due as u32;
+            due
+        }
+    }
+
+    /// an iteration running `ticks` ticks took `took`
+    pub fn record(&mut self, ticks: usize, took: Duration) {
+        let sample = took.as_secs_f64() * 1000.0 / ticks.max(1) as f64;
+        self.average = Some(match self.average {
+            None => sample,
+            Some(average) => average + (sample - average) * SMOOTHING,
+        });
+    }
+
+    /// the average time it takes to run a tick in milliseconds. More than 50 means the host cannot keep up.
+    pub fn tick_millis(&self) -> Option<f64> {
+        self.average
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::client::clock::{MAX_CATCH_UP, TICK, TickClock};
+    use crate::client::physics::{Line, Physics};
+    use crate::client::physics::speed::Speed;
+    use crate::client::state::local::inventory::PlayerInventory;
+    use crate::storage::blocks::WorldBlocks;
+    use crate::types::{Direction, Displacement, Location};
+
+    /// sprint for `seconds` of wall clock time. The loop is frozen for 300 ms from `freeze` on.
+    fn sprint(seconds: u64, freeze: Option<Duration>) -> (Location, usize) {
+        let mut world = WorldBlocks::flat();
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(0., 1., 0.));
+        physics.look(Direction::from(Displacement::new(1., 0., 0.)));
+
+        let start = Instant::now();
+        let end = start + Duration::from_secs(seconds);
+        let mut clock = TickClock::new(start);
+        let mut frozen = freeze.is_none();
+        let mut ticks = 0;
+
+        while clock.next() < end {
+            let mut now = clock.next();
+            if let Some(freeze) = freeze {
+                if !frozen && now >= start + freeze {
+                    now += Duration::from_millis(300);
+                    frozen = true;
+                }
+            }
+
+            let due = clock.due(now);
+            assert!((1..=MAX_CATCH_UP).contains(&due));
+
+            // every tick still moves at most as far as one tick allows
+            for _ in 0..due {
+                physics.line(Line::Forward);
+                physics.speed(Speed::SPRINT);
+                physics.tick(&mut world, &PlayerInventory::default());
+                ticks += 1;
+            }
+        }
+
+        (physics.location(), ticks)
+    }
+
+    #[test]
+    fn test_catch_up() {
+        let (unfrozen, unfrozen_ticks) = sprint(2, None);
+        let (frozen, frozen_ticks) = sprint(2, Some(Duration::from_millis(500)));
+
+        assert_eq!(unfrozen_ticks, 40);
+        assert_eq!(frozen_ticks, unfrozen_ticks);
+        assert!(frozen.dist2(unfrozen) < 1e-9);
+    }
+
+    #[test]
+    fn test_bounded() {
+        let start = Instant::now();
+        let mut clock = TickClock::new(start);
+
+        assert_eq!(clock.due(start), 1);
+        assert_eq!(clock.next(), start + TICK);
+
+        // 2 ticks late
+        assert_eq!(clock.due(start + TICK * 3), 3);
+        assert_eq!(clock.next(), start + TICK * 4);
+
+        // suspended for a minute. The rest is dropped.
+        let late = start + Duration::from_secs(60);
+        assert_eq!(clock.due(late), MAX_CATCH_UP);
+        assert_eq!(clock.next(), late + TICK);
+
+        assert_eq!(clock.tick_millis(), None);
+        clock.record(2, Duration::from_millis(30));
+        assert_eq!(clock.tick_millis(), Some(15.0));
+    }
+}
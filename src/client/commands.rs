@@ -14,18 +14,45 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::sync::mpsc::Receiver;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Instant;
 
-use futures::StreamExt;
+use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use tokio::net::TcpListener;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::header::SEC_WEBSOCKET_PROTOCOL;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
 
+use crate::client::formation::FormationKind;
+use crate::client::wire::{self, Connection, SUBPROTOCOL, Topic};
 use crate::error::Res;
 use crate::storage::block::{BlockLocation, BlockLocation2D};
 
+/// The websocket server on port 8080, shared by the runners of every server. A command goes to the runner its path is
+/// scoped to (`<server>:<command>`). Unscoped commands are only accepted while there is a single server.
+pub struct CommandHub {
+    /// every open connection. Stream messages are sent to those which subscribed to them.
+    subscribers: Rc<RefCell<Vec<Subscriber>>>,
+
+    /// the name of each server and where its commands go
+    servers: Rc<RefCell<Vec<(String, Sender<Command>)>>>,
+}
+
+/// The commands for the runner of one server
 pub struct Commands {
     pub pending: Receiver<Command>,
+    server: String,
+    subscribers: Rc<RefCell<Vec<Subscriber>>>,
+}
+
+struct Subscriber {
+    connection: Rc<RefCell<Connection>>,
+    tx: UnboundedSender<Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -62,85 +89,248 @@ pub struct Mine {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GoTo {
     pub location: BlockLocation,
+
+    /// how the bots spread around the location so they do not stand on the same block
+    #[serde(default)]
+    pub formation: FormationKind,
 }
 
 /// Attack a given player
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Attack {
     pub name: String,
+
+    /// ticks between attacks. The full cooldown of the held weapon if missing.
+    #[serde(default)]
+    pub cooldown: Option<u32>,
+}
+
+/// Run a chat command on bots. The result of each bot is sent back as JSON (see [`crate::client::reply::Reply`]).
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Run {
+    /// echoed back in every reply
+    #[serde(default)]
+    pub id: Value,
+
+    /// the username of the bot to run the command on. All bots if missing
+    pub bot: Option<String>,
+    pub name: String,
+
+    #[serde(default)]
+    pub args: Vec<String>,
 }
 
 pub enum Command {
     Mine(Mine),
     GoTo(GoTo),
     Attack(Attack),
+    Run {
+        run: Run,
+        reply: UnboundedSender<Value>,
+    },
 }
 
 
-fn process(path: &str, value: Value) -> Option<Command> {
+/// The command at `path` with its fields in `value`, or why it is not one
+fn process(path: &str, value: Value, reply: &UnboundedSender<Value>) -> Result<Command, String> {
     macro_rules! parse {
         () => {{
-            serde_json::from_value(value).unwrap()
+            serde_json::from_value(value).map_err(|error| format!("invalid {} command: {}", path, error))?
         }};
     }
 
     match path {
-        "mine" => Some(Command::Mine(parse!())),
-        "goto" => Some(Command::GoTo(parse!())),
-        "attack" => Some(Command::Attack(parse!())),
+        "mine" => Ok(Command::Mine(parse!())),
+        "goto" => Ok(Command::GoTo(parse!())),
+        "attack" => Ok(Command::Attack(parse!())),
+        "run" => Ok(Command::Run { run: parse!(), reply: reply.clone() }),
 
         path => {
-            println!("invalid {}", path);
-            None
+            warn!(path, "invalid command path");
+            Err(format!("there is no command {}", path))
         }
     }
 }
 
+/// The server `path` is scoped to and the command without the scope
+fn route<'a>(servers: &[&str], path: &'a str) -> Result<(usize, &'a str), String> {
+    match path.split_once(':') {
+        Some((server, command)) => servers.iter()
+            .position(|&name| name == server)
+            .map(|idx| (idx, command))
+            .ok_or_else(|| format!("there is no server {}", server)),
+        None if servers.len() == 1 => Ok((0, path)),
+        None => Err(format!("{} has to be scoped to one of the servers {}", path, servers.join(", "))),
+    }
+}
+
 
 impl Commands {
-    pub async fn init() -> Res<Self> {
+    /// Send a stream message about `bot` to every connection subscribed to `topic`. The message is only built if a
+    /// connection wants it and says which server the bot is on.
+    pub fn publish(&self, topic: Topic, bot: &str, message: impl FnOnce() -> Value) {
+        let mut subscribers = self.subscribers.borrow_mut();
+        subscribers.retain(|subscriber| !subscriber.tx.is_closed());
+
+        let now = Instant::now();
+        let mut message = Some(message);
+        let mut value = None;
+
+        for subscriber in subscribers.iter() {
+            if subscriber.connection.borrow_mut().filter.admit(topic, bot, now) {
+                let value = value.get_or_insert_with(|| {
+                    let mut value = (message.take().unwrap())();
+                    if let Value::Object(map) = &mut value {
+                        map.insert("server".to_string(), Value::String(self.server.clone()));
+                    }
+                    value
+                });
+                let _ = subscriber.tx.send(value.clone());
+            }
+        }
+    }
+
+}
+
+impl CommandHub {
+    /// the commands of the server `name`
+    pub fn server(&self, name: &str) -> Commands {
         let (tx, rx) = std::sync::mpsc::channel();
+        self.servers.borrow_mut().push((name.to_string(), tx));
+
+        Commands {
+            pending: rx,
+            server: name.to_string(),
+            subscribers: self.subscribers.clone(),
+        }
+    }
 
+    pub async fn init() -> Res<Self> {
         let server = TcpListener::bind("127.0.0.1:8080").await?;
+        let subscribers: Rc<RefCell<Vec<Subscriber>>> = Rc::default();
+        let servers: Rc<RefCell<Vec<(String, Sender<Command>)>>> = Rc::default();
 
+        let all = subscribers.clone();
+        let routes = servers.clone();
         tokio::task::spawn_local(async move {
             loop {
                 let (stream, _) = server.accept().await.unwrap();
-                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
 
-                let tx = tx.clone();
+                let mut binary = false;
+                let ws = tokio_tungstenite::accept_hdr_async(stream, |request: &Request, mut response: Response| {
+                    let protocols = request.headers().get(SEC_WEBSOCKET_PROTOCOL).and_then(|value| value.to_str().ok());
+                    if protocols.map_or(false, |protocols| protocols.split(',').any(|protocol| protocol.trim() == SUBPROTOCOL)) {
+                        response.headers_mut().insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(SUBPROTOCOL));
+                        binary = true;
+                    }
+                    Ok(response)
+                }).await.unwrap();
+                let (mut sink, mut ws) = ws.split();
+
+                let routes = routes.clone();
+
+                let connection = Rc::new(RefCell::new(if binary { Connection::subprotocol() } else { Connection::default() }));
+
+                // replies of commands run from this connection and the streams it subscribed to
+                let (reply_tx, mut reply_rx) = tokio::sync::mpsc::unbounded_channel::<Value>();
+                all.borrow_mut().push(Subscriber { connection: connection.clone(), tx: reply_tx.clone() });
+
+                let writer = connection.clone();
+                tokio::task::spawn_local(async move {
+                    while let Some(reply) = reply_rx.recv().await {
+                        let frame = writer.borrow().encoding.frame(&reply);
+                        if sink.send(frame).await.is_err() {
+                            break;
+                        }
+                    }
+                });
 
+                let all = all.clone();
                 tokio::task::spawn_local(async move {
                     'wloop:
                     while let Some(msg) = ws.next().await {
                         let msg = msg.unwrap();
 
-                        let text = msg.into_text().unwrap();
-
-                        let mut v: Value = match serde_json::from_str(&text) {
-                            Ok(v) => v,
-                            Err(_e) => continue 'wloop,
+                        let mut v: Value = match wire::decode(msg) {
+                            Some(v) => v,
+                            None => continue 'wloop,
                         };
 
-                        let map = match &mut v {
-                            Value::Object(map) => map,
-                            _ => panic!("invalid value"),
+                        let path = match v.as_object_mut().and_then(|map| map.remove("path")) {
+                            Some(Value::String(path)) => path,
+                            _ => {
+                                let _ = reply_tx.send(json!({ "type": "error", "error": "a command is an object with a path" }));
+                                continue 'wloop;
+                            }
                         };
 
-                        let path = match map.remove("path").expect("no path elem") {
-                            Value::String(path) => path,
-                            _ => panic!("invalid path")
+                        if path == "hello" {
+                            let reply = match serde_json::from_value(v) {
+                                Ok(hello) => connection.borrow_mut().hello(hello),
+                                Err(e) => json!({ "type": "hello", "ok": false, "error": e.to_string() }),
+                            };
+                            let _ = reply_tx.send(reply);
+                            continue 'wloop;
+                        }
+
+                        let servers = routes.borrow();
+                        let names: Vec<_> = servers.iter().map(|(name, _)| name.as_str()).collect();
+                        let (idx, command) = match route(&names, &path) {
+                            Ok(route) => route,
+                            Err(error) => {
+                                let _ = reply_tx.send(json!({ "type": "error", "path": path, "error": error }));
+                                continue 'wloop;
+                            }
                         };
 
-                        let command = process(&path, v).expect("invalid command");
-                        tx.send(command).unwrap();
+                        // a malformed command is answered instead of taking the connection down
+                        let command = match process(command, v, &reply_tx) {
+                            Ok(command) => command,
+                            Err(error) => {
+                                let _ = reply_tx.send(json!({ "type": "error", "path": path, "error": error }));
+                                continue 'wloop;
+                            }
+                        };
+                        servers[idx].1.send(command).unwrap();
                     }
+
+                    // stop streaming to the connection. The writer stops once the replies still in flight are sent.
+                    all.borrow_mut().retain(|subscriber| !Rc::ptr_eq(&subscriber.connection, &connection));
                 });
             }
         });
 
         Ok(Self {
-            pending: rx
+            subscribers,
+            servers,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::client::commands::{Command, process, route};
+
+    #[test]
+    fn test_route() {
+        assert_eq!(route(&["hub"], "goto"), Ok((0, "goto")));
+        assert_eq!(route(&["hub"], "hub:goto"), Ok((0, "goto")));
+
+        // with more servers a command has to say where it goes
+        assert_eq!(route(&["hub", "test"], "test:goto"), Ok((1, "goto")));
+        assert!(route(&["hub", "test"], "goto").is_err());
+        assert!(route(&["hub", "test"], "lobby:goto").is_err());
+    }
+
+    #[test]
+    fn test_malformed() {
+        let (reply, _rx) = tokio::sync::mpsc::unbounded_channel();
+
+        assert!(matches!(process("run", json!({ "name": "status" }), &reply), Ok(Command::Run { .. })));
+        assert!(process("run", json!({ "args": "not a list" }), &reply).is_err());
+        assert!(process("goto", json!({ "location": "spawn" }), &reply).is_err());
+        assert!(process("dance", json!({}), &reply).is_err());
+    }
+}
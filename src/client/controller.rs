@@ -0,0 +1,35 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Driving bots from code outside the crate
+
+use crate::chat::ChatEvent;
+use crate::client::bot::ActionState;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::protocol::{ClientInfo, InterfaceOut};
+
+/// Drives one bot: schedules its tasks and answers chat. Made for every bot that logs in by a [`ControllerFactory`].
+pub trait Controller {
+    /// called every tick before the task of the bot ticks
+    fn tick(&mut self, out: &mut dyn InterfaceOut, actions: &mut ActionState, local: &mut LocalState, global: &mut GlobalState);
+
+    /// A chat message the bot received since the last tick. Commands (`#...`) have already been run by then.
+    fn on_chat(&mut self, _message: &ChatEvent, _out: &mut dyn InterfaceOut, _actions: &mut ActionState, _local: &mut LocalState, _global: &mut GlobalState) {}
+}
+
+/// makes the controller of a bot which just logged in
+pub type ControllerFactory = Box<dyn FnMut(&ClientInfo) -> Box<dyn Controller>>;
@@ -0,0 +1,200 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Noticing when the blocks a bot knows differ from the server's. 1.12 clients cannot ask for a chunk again, so once
+//! enough signs of a desync pile up the bot gets the server to resend its chunks (see
+//! [`RefreshTask`](crate::client::tasks::refresh::RefreshTask)), and reconnects if even that does not help.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::storage::block::BlockLocation;
+use crate::storage::blocks::MissingColumn;
+
+/// how long the server has to confirm a block we finished mining. It sends a block change when it breaks the block,
+/// but nothing if the block was already air.
+pub const CONFIRM_TICKS: usize = 20 * 2;
+
+#[derive(Clone, Debug)]
+pub struct ResyncConfig {
+    /// chunks within this many chunks of the bot are refreshed
+    pub radius: i32,
+
+    /// this many signs of a desync within `window` ticks trigger a refresh
+    pub threshold: usize,
+    pub window: usize,
+
+    /// how long a refresh waits for the chunks to be resent
+    pub timeout: usize,
+
+    /// Reconnect if this many signs of a desync pile up within `window` ticks or a refresh times out. None never
+    /// reconnects.
+    pub severe: Option<usize>,
+
+    /// what to do with an update of a column which is not loaded
+    pub missing_column: MissingColumn,
+}
+
+impl Default for ResyncConfig {
+    fn default() -> Self {
+        Self {
+            radius: 2,
+            threshold: 3,
+            window: 20 * 60,
+            timeout: 20 * 10,
+            severe: Some(8),
+            missing_column: MissingColumn::Insert,
+        }
+    }
+}
+
+/// What should be done about a desync
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resync {
+    /// get the server to resend the chunks around the bot
+    Refresh,
+    Reconnect,
+}
+
+#[derive(Debug, Default)]
+pub struct Desync {
+    /// blocks we finished mining -> the tick we finished
+    unconfirmed: HashMap<BlockLocation, usize>,
+
+    /// the ticks of recent signs of a desync
+    strikes: VecDeque<usize>,
+
+    /// a refresh timed out before the server resent the chunks
+    refresh_failed: bool,
+}
+
+impl Desync {
+    /// we finished mining the block at `location`
+    pub fn mined(&mut self, location: BlockLocation, tick: usize) {
+        self.unconfirmed.insert(location, tick);
+    }
+
+    /// the server sent a change of the block at `location`
+    pub fn block_changed(&mut self, location: BlockLocation) {
+        self.unconfirmed.remove(&location);
+    }
+
+    /// something only explained by the world being out of sync happened
+    pub fn strike(&mut self, tick: usize, reason: &str) {
+        debug!(reason, "world looks out of sync");
+        self.strikes.push_back(tick);
+    }
+
+    pub fn refresh_failed(&mut self) {
+        self.refresh_failed = true;
+    }
+
+    /// the number of signs of a desync within the window
+    pub fn strikes(&self) -> usize {
+        self.strikes.len()
+    }
+
+    /// Called every tick. Returns what should be done about the world being out of sync, if anything. The strikes
+    /// start over once something is done.
+    pub fn check(&mut self, tick: usize, config: &ResyncConfig) -> Option<Resync> {
+        let expired: Vec<_> = self.unconfirmed.iter()
+            .filter(|(_, &at)| at + CONFIRM_TICKS <= tick)
+            .map(|(&location, _)| location)
+            .collect();
+
+        for location in expired {
+            self.unconfirmed.remove(&location);
+            self.strike(tick, &format!("mined {} but the server did not break it (it already was air)", location));
+        }
+
+        while matches!(self.strikes.front(), Some(&at) if at + config.window < tick) {
+            self.strikes.pop_front();
+        }
+
+        let severe = config.severe.map_or(false, |severe| self.refresh_failed || self.strikes.len() >= severe);
+
+        let resync = if severe {
+            Resync::Reconnect
+        } else if self.strikes.len() >= config.threshold {
+            Resync::Refresh
+        } else {
+            self.refresh_failed = false;
+            return None;
+        };
+
+        self.strikes.clear();
+        self.refresh_failed = false;
+        Some(resync)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::desync::{CONFIRM_TICKS, Desync, Resync, ResyncConfig};
+    use crate::storage::block::BlockLocation;
+
+    #[test]
+    fn test_unconfirmed_mining() {
+        let config = ResyncConfig { threshold: 2, ..ResyncConfig::default() };
+        let mut desync = Desync::default();
+
+        let a = BlockLocation::new(0, 10, 0);
+        let b = BlockLocation::new(1, 10, 0);
+        let c = BlockLocation::new(2, 10, 0);
+
+        desync.mined(a, 0);
+        desync.mined(b, 0);
+        desync.mined(c, 5);
+
+        // the server broke b
+        desync.block_changed(b);
+        assert_eq!(desync.check(CONFIRM_TICKS - 1, &config), None);
+
+        // a was never broken
+        assert_eq!(desync.check(CONFIRM_TICKS, &config), None);
+        assert_eq!(desync.strikes(), 1);
+
+        assert_eq!(desync.check(CONFIRM_TICKS + 5, &config), Some(Resync::Refresh));
+        assert_eq!(desync.strikes(), 0);
+    }
+
+    #[test]
+    fn test_escalation() {
+        let config = ResyncConfig { threshold: 2, window: 100, severe: Some(4), ..ResyncConfig::default() };
+        let mut desync = Desync::default();
+
+        // strikes too far apart
+        desync.strike(0, "test");
+        desync.strike(101, "test");
+        assert_eq!(desync.check(102, &config), None);
+
+        desync.strike(102, "test");
+        assert_eq!(desync.check(102, &config), Some(Resync::Refresh));
+
+        // strikes piled up before we checked
+        for tick in 110..114 {
+            desync.strike(tick, "test");
+        }
+        assert_eq!(desync.check(114, &config), Some(Resync::Reconnect));
+
+        // a refresh which did not help
+        desync.refresh_failed();
+        assert_eq!(desync.check(115, &config), Some(Resync::Reconnect));
+
+        // unless reconnecting is disabled
+        let config = ResyncConfig { severe: None, ..config };
+        desync.refresh_failed();
+        assert_eq!(desync.check(116, &config), None);
+    }
+}
@@ -0,0 +1,362 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Human-readable session digests. The runner feeds every bot's [`BotStats`] into a [`Digest`] which covers a time
+//! window. At the end of the window (daily or when all bots are gone) the digest is turned into a [`Report`], written
+//! to a file and optionally posted to a webhook.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use itertools::Itertools;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::bootstrap::block_data::BlockData;
+use crate::client::state::local::stats::BotStats;
+use crate::error::Res;
+use crate::protocol::disconnect::DisconnectPatterns;
+use crate::storage::block::BlockKind;
+
+pub mod webhook;
+
+/// how often a digest is emitted during long runs
+pub const DIGEST_INTERVAL: Duration = Duration::from_secs(60 * 60 * 24);
+
+const TOP_KINDS: usize = 10;
+const TICKS_PER_SECOND: u64 = 20;
+
+/// Where digests go. Digests are not built at all if neither is set.
+#[derive(Default, Clone)]
+pub struct DigestOptions {
+    pub dir: Option<PathBuf>,
+    pub webhook: Option<String>,
+}
+
+impl DigestOptions {
+    pub fn enabled(&self) -> bool {
+        self.dir.is_some() || self.webhook.is_some()
+    }
+}
+
+#[derive(Default)]
+struct BotEntry {
+    /// how many times the bot logged in during the window
+    sessions: u32,
+    stats: BotStats,
+}
+
+/// The stats of all bots in the current window
+pub struct Digest {
+    from: SystemTime,
+    bots: HashMap<String, BotEntry>,
+}
+
+impl Default for Digest {
+    fn default() -> Self {
+        Self::new(SystemTime::now())
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct BotReport {
+    pub name: String,
+    pub playtime_secs: u64,
+    pub reconnects: u32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub from: u64,
+    pub to: u64,
+    pub bots: Vec<BotReport>,
+
+    /// the most mined kinds (at most 10). Sorted by count descending.
+    pub mined: Vec<(String, u64)>,
+
+    /// the most placed kinds (at most 10). Sorted by count descending.
+    pub placed: Vec<(String, u64)>,
+
+    /// blocks traveled by the task that was running
+    pub distance: Vec<(String, f64)>,
+    pub deaths: Vec<(String, u64)>,
+
+    /// disconnects grouped by [`classify_disconnect`]
+    pub disconnects: Vec<(String, u64)>,
+}
+
+/// Classify a raw disconnect reason (usually chat json) so the digest can group it
+pub fn classify_disconnect(reason: &str) -> &'static str {
+    DisconnectPatterns::default().classify(reason).reason.name()
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+fn count_sorted(counts: HashMap<String, u64>) -> Vec<(String, u64)> {
+    counts.into_iter()
+        .sorted_by(|(a_name, a), (b_name, b)| b.cmp(a).then_with(|| a_name.cmp(b_name)))
+        .collect()
+}
+
+fn top_kinds(counts: &HashMap<BlockKind, u64>, data: &BlockData) -> Vec<(String, u64)> {
+    let named = counts.iter()
+        .map(|(kind, count)| {
+            let name = data.by_id(kind.id()).map(|block| block.name.clone()).unwrap_or_else(|| format!("#{}", kind.id()));
+            (name, *count)
+        })
+        .collect();
+
+    let mut res = count_sorted(named);
+    res.truncate(TOP_KINDS);
+    res
+}
+
+impl Digest {
+    pub fn new(from: SystemTime) -> Digest {
+        Digest {
+            from,
+            bots: HashMap::new(),
+        }
+    }
+
+    pub fn login(&mut self, username: &str) {
+        self.bots.entry(username.to_string()).or_default().sessions += 1;
+    }
+
+    pub fn add(&mut self, username: &str, stats: BotStats) {
+        self.bots.entry(username.to_string()).or_default().stats.merge(stats);
+    }
+
+    /// Build a report of the window ending at `to`
+    pub fn report(&self, to: SystemTime, data: &BlockData) -> Report {
+        let mut total = BotStats::default();
+        let mut bots = Vec::new();
+
+        for (name, entry) in self.bots.iter().sorted_by_key(|(name, _)| name.as_str()) {
+            bots.push(BotReport {
+                name: name.clone(),
+                playtime_secs: entry.stats.ticks / TICKS_PER_SECOND,
+                reconnects: entry.sessions.saturating_sub(1),
+            });
+            total.merge(entry.stats.clone());
+        }
+
+        let distance = total.distance.into_iter()
+            .map(|(mode, distance)| (mode.to_string(), distance))
+            .sorted_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap())
+            .collect();
+
+        let deaths = count_sorted(total.deaths.into_iter().counts().into_iter().map(|(k, v)| (k, v as u64)).collect());
+
+        let disconnects = total.disconnects.iter()
+            .map(|reason| classify_disconnect(reason).to_string())
+            .counts().into_iter()
+            .map(|(k, v)| (k, v as u64))
+            .collect();
+
+        Report {
+            from: unix_secs(self.from),
+            to: unix_secs(to),
+            bots,
+            mined: top_kinds(&total.mined, data),
+            placed: top_kinds(&total.placed, data),
+            distance,
+            deaths,
+            disconnects: count_sorted(disconnects),
+        }
+    }
+
+    /// Start a new window at `from`. Bots which are still online keep their stats in [`LocalState`] until they are
+    /// taken, so nothing is lost.
+    ///
+    /// [`LocalState`]: crate::client::state::local::LocalState
+    pub fn reset(&mut self, from: SystemTime) {
+        *self = Digest::new(from);
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    format!("{}h {:02}m {:02}s", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+impl Report {
+    pub fn render(&self) -> String {
+        let mut res = String::new();
+
+        // writing to a string cannot fail
+        let _ = self.render_into(&mut res);
+        res
+    }
+
+    fn render_into(&self, f: &mut String) -> std::fmt::Result {
+        let total_secs: u64 = self.bots.iter().map(|bot| bot.playtime_secs).sum();
+        let reconnects: u32 = self.bots.iter().map(|bot| bot.reconnects).sum();
+
+        writeln!(f, "SwarmBot digest ({} - {}, {})", self.from, self.to, format_duration(self.to.saturating_sub(self.from)))?;
+        writeln!(f)?;
+
+        writeln!(f, "Playtime ({} total, {} reconnects)", format_duration(total_secs), reconnects)?;
+        for bot in &self.bots {
+            writeln!(f, "  {:<16} {:>14} {:>4} reconnects", bot.name, format_duration(bot.playtime_secs), bot.reconnects)?;
+        }
+
+        let section = |f: &mut String, title: &str, rows: &[(String, String)]| -> std::fmt::Result {
+            writeln!(f)?;
+            writeln!(f, "{}", title)?;
+            if rows.is_empty() {
+                writeln!(f, "  none")?;
+            }
+            for (name, value) in rows {
+                writeln!(f, "  {:<24} {:>10}", name, value)?;
+            }
+            Ok(())
+        };
+
+        let counts = |rows: &[(String, u64)]| rows.iter().map(|(k, v)| (k.clone(), v.to_string())).collect_vec();
+
+        section(f, "Blocks mined (top 10)", &counts(&self.mined))?;
+        section(f, "Blocks placed (top 10)", &counts(&self.placed))?;
+        section(f, "Distance traveled", &self.distance.iter().map(|(k, v)| (k.clone(), format!("{:.0}", v))).collect_vec())?;
+        section(f, "Deaths", &counts(&self.deaths))?;
+        section(f, "Disconnects", &counts(&self.disconnects))?;
+
+        Ok(())
+    }
+
+    /// A generic JSON payload. `content` is read by Discord and `text` by Slack. The structured report is included
+    /// for anything else.
+    pub fn webhook_payload(&self) -> Value {
+        let text = format!("```\n{}```", self.render());
+        json!({
+            "content": text,
+            "text": text,
+            "digest": self,
+        })
+    }
+
+    pub fn write_to(&self, dir: &Path) -> Res<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("digest-{}.txt", self.to));
+        std::fs::write(&path, self.render())?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::bootstrap::block_data::BlockData;
+    use crate::client::digest::{classify_disconnect, Digest};
+    use crate::client::state::local::stats::BotStats;
+    use crate::storage::block::BlockKind;
+
+    fn synthetic() -> Digest {
+        let from = UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut digest = Digest::new(from);
+
+        digest.login("alice");
+        digest.login("alice");
+        digest.login("bob");
+
+        let mut alice = BotStats {
+            ticks: 20 * 3600,
+            ..BotStats::default()
+        };
+        for _ in 0..5 {
+            alice.mined(BlockKind(1));
+        }
+        alice.mined(BlockKind(3));
+        alice.placed(BlockKind(4));
+        alice.traveled("navigate", 100.0);
+        alice.died(Some("attack.lava".to_string()));
+        alice.disconnected("{\"text\":\"You have been kicked\"}");
+
+        let mut bob = BotStats {
+            ticks: 20 * 60,
+            ..BotStats::default()
+        };
+        for kind in 100..115 {
+            bob.mined(BlockKind(kind));
+        }
+        bob.mined(BlockKind(3));
+        bob.traveled("navigate", 20.0);
+        bob.traveled("idle", 1.0);
+        bob.died(None);
+
+        digest.add("alice", alice);
+        digest.add("bob", bob);
+        digest
+    }
+
+    #[test]
+    fn test_report() {
+        let data = BlockData::read().unwrap();
+        let digest = synthetic();
+        let report = digest.report(UNIX_EPOCH + Duration::from_secs(1_000 + 86_400), &data);
+
+        assert_eq!(report.from, 1_000);
+        assert_eq!(report.bots.len(), 2);
+        assert_eq!(report.bots[0].name, "alice");
+        assert_eq!(report.bots[0].reconnects, 1);
+        assert_eq!(report.bots[0].playtime_secs, 3600);
+
+        assert_eq!(report.mined.len(), 10);
+        assert_eq!(report.mined[0], ("stone".to_string(), 5));
+        assert_eq!(report.mined[1], ("dirt".to_string(), 2));
+
+        assert_eq!(report.distance[0], ("navigate".to_string(), 120.0));
+        assert_eq!(report.deaths.len(), 2);
+        assert_eq!(report.disconnects, vec![("kicked".to_string(), 1)]);
+
+        let text = report.render();
+        assert!(text.contains("Blocks mined (top 10)"));
+        assert!(text.contains("stone"));
+        assert!(text.contains("attack.lava"));
+        assert!(text.contains("1h 00m 00s"));
+    }
+
+    #[test]
+    fn test_webhook_payload() {
+        let data = BlockData::read().unwrap();
+        let report = synthetic().report(UNIX_EPOCH + Duration::from_secs(2_000), &data);
+        let payload = report.webhook_payload();
+
+        let content = payload["content"].as_str().unwrap();
+        assert!(content.starts_with("```\nSwarmBot digest"));
+        assert_eq!(payload["text"], payload["content"]);
+
+        let digest = &payload["digest"];
+        assert_eq!(digest["from"], 1_000);
+        assert_eq!(digest["to"], 2_000);
+        assert_eq!(digest["bots"][1]["name"], "bob");
+        assert_eq!(digest["mined"][0][0], "stone");
+        assert_eq!(digest["mined"][0][1], 5);
+    }
+
+    #[test]
+    fn test_classify_disconnect() {
+        assert_eq!(classify_disconnect("You are banned from this server"), "banned");
+        assert_eq!(classify_disconnect("Connection throttled! Please wait before reconnecting."), "throttled");
+        assert_eq!(classify_disconnect("Timed out"), "timed out");
+        assert_eq!(classify_disconnect("Server closed"), "server closed");
+        assert_eq!(classify_disconnect("something else"), "other");
+    }
+}
@@ -0,0 +1,52 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::error::{err, Res};
+
+const ATTEMPTS: u32 = 4;
+
+/// POST `payload` as JSON to `url`. Failed attempts are retried with exponential backoff (1s, 2s, 4s).
+pub async fn post(url: &str, payload: &Value) -> Res {
+    let client = reqwest::Client::new();
+    let body = payload.to_string();
+
+    let mut backoff = Duration::from_secs(1);
+
+    for attempt in 1..=ATTEMPTS {
+        let res = client.post(url)
+            .header("Content-Type", "application/json")
+            .body(body.clone())
+            .send()
+            .await;
+
+        match res {
+            Ok(res) if res.status().is_success() => return Ok(()),
+            Ok(res) => warn!(attempt, status = %res.status(), "webhook attempt failed"),
+            Err(error) => warn!(attempt, %error, "webhook attempt failed"),
+        }
+
+        if attempt != ATTEMPTS {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    Err(err("could not post to webhook"))
+}
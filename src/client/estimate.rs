@@ -0,0 +1,418 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Dry runs of tasks. A [`TaskEstimate`] is what planning a task says executing it would take: how long, which tools
+//! and materials it uses up, what it produces and what could go wrong. Nothing is sent to the server.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::client::physics::tools::ToolMat;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::storage::block::{BlockKind, BlockLocation};
+use crate::storage::blocks::ChunkLocation;
+use crate::types::Location;
+
+/// blocks per tick when sprinting on flat ground (100 blocks take 358 ticks)
+pub const SPRINT_PER_TICK: f64 = 100.0 / 358.0;
+
+/// blocks per tick when walking (4.317 blocks/s)
+pub const WALK_PER_TICK: f64 = 4.317 / 20.0;
+
+/// ticks to jump and place one block below us
+pub const PILLAR_TICKS: usize = 12;
+
+/// https://minecraft.fandom.com/wiki/Hunger#Exhaustion_level_increase
+const EXHAUSTION_PER_FOOD: f64 = 4.0;
+const SPRINT_EXHAUSTION: f64 = 0.1;
+const JUMP_EXHAUSTION: f64 = 0.05;
+const BREAK_EXHAUSTION: f64 = 0.005;
+
+/// Something that could make a task fail or get the bot hurt
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Risk {
+    /// we do not know what is there, so the estimate is missing whatever is in the chunk
+    Unloaded { chunk: ChunkLocation },
+
+    /// water or lava next to a block that will be mined
+    Fluid { location: BlockLocation, lava: bool },
+
+    /// a block that cannot be mined, like bedrock
+    Unbreakable { location: BlockLocation },
+
+    /// the task places more blocks than the hotbar has
+    MissingBlocks { needed: usize, have: usize },
+}
+
+impl Display for Risk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Risk::Unloaded { chunk } => write!(f, "chunk {},{} is not loaded", chunk.0, chunk.1),
+            Risk::Fluid { location, lava } => write!(f, "{} at {}", if *lava { "lava" } else { "water" }, location),
+            Risk::Unbreakable { location } => write!(f, "cannot mine {}", location),
+            Risk::MissingBlocks { needed, have } => write!(f, "needs {} blocks but has {}", needed, have),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct TaskEstimate {
+    /// blocks mined or placed
+    pub blocks: usize,
+    pub ticks: usize,
+
+    /// blocks traveled
+    pub distance: f64,
+
+    /// tool id -> durability used
+    pub tools: BTreeMap<u32, usize>,
+
+    /// food points used. Saturation is used up first, so the food bar may not go down by this much.
+    pub food: f64,
+
+    /// block id -> count
+    pub required: BTreeMap<u32, usize>,
+    pub produced: BTreeMap<u32, usize>,
+    pub risks: Vec<Risk>,
+}
+
+/// Tasks which can be planned without executing them
+pub trait Plan {
+    fn plan(&self, local: &LocalState, global: &GlobalState) -> TaskEstimate;
+}
+
+impl TaskEstimate {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.ticks as u64 * 50)
+    }
+
+    pub fn risk(&mut self, risk: Risk) {
+        if !self.risks.contains(&risk) {
+            self.risks.push(risk);
+        }
+    }
+
+    fn exhaust(&mut self, exhaustion: f64) {
+        self.food += exhaustion / EXHAUSTION_PER_FOOD;
+    }
+
+    /// travel in a straight line. Unloaded chunks on the way are risks because the path could be anything there.
+    pub fn travel(&mut self, from: Location, to: Location, sprint: bool, global: &GlobalState) {
+        let dx = to.x - from.x;
+        let dz = to.z - from.z;
+        let horizontal = (dx * dx + dz * dz).sqrt();
+
+        // climbing and falling take about as long as walking the same distance
+        let distance = horizontal + (to.y - from.y).abs();
+        let per_tick = if sprint { SPRINT_PER_TICK } else { WALK_PER_TICK };
+
+        self.distance += distance;
+        self.ticks += (distance / per_tick).round() as usize;
+        if sprint {
+            self.exhaust(distance * SPRINT_EXHAUSTION);
+        }
+
+        let steps = horizontal.ceil() as usize;
+        for step in 0..=steps {
+            let progress = if steps == 0 { 0.0 } else { step as f64 / steps as f64 };
+            let at = Location::new(from.x + dx * progress, from.y, from.z + dz * progress);
+            let chunk = ChunkLocation::from(at);
+            if global.blocks.chunk_generation(chunk).is_none() {
+                self.risk(Risk::Unloaded { chunk });
+            }
+        }
+    }
+
+    /// mine a block with the best tool of the hotbar. Takes as long as [`MineTask`](crate::client::tasks::mine::MineTask).
+    pub fn mine(&mut self, location: BlockLocation, local: &LocalState, global: &GlobalState) {
+        let kind = match global.blocks.get_block_kind(location) {
+            Some(kind) => kind,
+            None => {
+                self.risk(Risk::Unloaded { chunk: ChunkLocation::from(location) });
+                return;
+            }
+        };
+
+        if kind.id() == 0 {
+            return;
+        }
+
+        if !kind.mineable(&global.block_data) {
+            self.risk(Risk::Unbreakable { location });
+            return;
+        }
+
+        let (_, tool) = local.inventory.best_tool(kind, &global.block_data);
+
+        // MineTask sends the start, waits and then sends the finish
+        self.ticks += kind.break_ticks(&tool, true, false, &local.effects, &global.block_data) + 2;
+        self.blocks += 1;
+        *self.produced.entry(kind.id()).or_default() += 1;
+        self.exhaust(BREAK_EXHAUSTION);
+
+        if tool.material != ToolMat::Hand {
+            *self.tools.entry(tool.id).or_default() += 1;
+        }
+
+        let neighbors = [location.above(), location.below(), location + BlockLocation::new(1, 0, 0),
+            location + BlockLocation::new(-1, 0, 0), location + BlockLocation::new(0, 0, 1), location + BlockLocation::new(0, 0, -1)];
+
+        for neighbor in neighbors {
+            if let Some(kind @ 8..=11) = global.blocks.get_block_kind(neighbor).map(|kind| kind.id()) {
+                self.risk(Risk::Fluid { location: neighbor, lava: kind >= 10 });
+            }
+        }
+    }
+
+    /// place `count` blocks taken from the hotbar. `ticks` is how long placing all of them takes.
+    pub fn place(&mut self, kind: BlockKind, count: usize, ticks: usize, local: &LocalState) {
+        if count == 0 {
+            return;
+        }
+
+        self.blocks += count;
+        self.ticks += ticks;
+        *self.required.entry(kind.id()).or_default() += count;
+
+        let have: usize = local.inventory.hotbar().iter()
+            .flatten()
+            .filter(|stack| stack.kind == kind)
+            .map(|stack| stack.count as usize)
+            .sum();
+
+        let needed = self.required[&kind.id()];
+        if needed > have {
+            self.risks.retain(|risk| !matches!(risk, Risk::MissingBlocks { .. }));
+            self.risk(Risk::MissingBlocks { needed, have });
+        }
+    }
+
+    /// jump `count` times
+    pub fn jump(&mut self, count: usize) {
+        self.exhaust(count as f64 * JUMP_EXHAUSTION);
+    }
+
+    pub fn wait(&mut self, ticks: usize) {
+        self.ticks += ticks;
+    }
+
+    /// do `other` after this
+    pub fn then(&mut self, other: TaskEstimate) {
+        self.blocks += other.blocks;
+        self.ticks += other.ticks;
+        self.distance += other.distance;
+        self.food += other.food;
+
+        for (id, uses) in other.tools {
+            *self.tools.entry(id).or_default() += uses;
+        }
+        for (id, count) in other.required {
+            *self.required.entry(id).or_default() += count;
+        }
+        for (id, count) in other.produced {
+            *self.produced.entry(id).or_default() += count;
+        }
+        for risk in other.risks {
+            self.risk(risk);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::client::estimate::{Plan, Risk, TaskEstimate};
+    use crate::client::state::global::GlobalState;
+    use crate::client::state::local::effects::Effect;
+    use crate::client::state::local::inventory::ItemStack;
+    use crate::client::state::local::LocalState;
+    use crate::client::tasks::mine::MineTask;
+    use crate::client::tasks::mine_region::MineRegion;
+    use crate::client::tasks::navigate::BlockTravelTask;
+    use crate::client::tasks::TaskTrait;
+    use crate::protocol::v340::Interface340;
+    use crate::storage::block::{BlockKind, BlockLocation, BlockLocation2D, BlockState};
+    use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+
+    fn within(estimate: usize, actual: usize, tolerance: f64) {
+        let error = (estimate as f64 - actual as f64).abs() / actual as f64;
+        assert!(error <= tolerance, "estimated {} ticks but it took {}", estimate, actual);
+    }
+
+    fn pickaxe(local: &mut LocalState) {
+        local.inventory.add(36, ItemStack::new(BlockKind::from(278), 1, 0, None));
+    }
+
+    #[test]
+    fn test_mine() {
+        let (mut out, _rx) = Interface340::test();
+        let mut local = LocalState::mock();
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+        pickaxe(&mut local);
+        local.physics.teleport(BlockLocation::new(0, 1, 0).center_bottom());
+
+        let dirt = BlockLocation::new(1, 1, 0);
+        global.blocks.set_block(dirt, BlockState::from(BlockKind::DIRT.id(), 0));
+
+        let blocks = [BlockLocation::new(1, 0, 0), dirt, BlockLocation::new(0, 0, 1)];
+
+        let mut estimate = TaskEstimate::default();
+        let mut actual = 0;
+
+        for &location in &blocks {
+            let mut task = MineTask::new(location, &mut out, &mut local, &global);
+            estimate.then(task.plan(&local, &global));
+
+            actual += 1;
+            while !task.tick(&mut out, &mut local, &mut global) {
+                actual += 1;
+            }
+        }
+
+        within(estimate.ticks, actual, 0.05);
+        assert_eq!(estimate.blocks, 3);
+        assert_eq!(estimate.produced[&BlockKind::STONE.id()], 2);
+        assert_eq!(estimate.produced[&BlockKind::DIRT.id()], 1);
+        assert_eq!(local.stats.mined[&BlockKind::STONE], 2);
+
+        // the pickaxe is the best tool for stone but the hand is as good for dirt
+        assert_eq!(estimate.tools[&278], 2);
+        assert!(estimate.risks.is_empty());
+    }
+
+    /// the ticks it takes to mine the stone next to the bot
+    fn dig(local: &mut LocalState) -> usize {
+        let (mut out, _rx) = Interface340::test();
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+        pickaxe(local);
+        local.physics.teleport(BlockLocation::new(0, 1, 0).center_bottom());
+
+        let mut task = MineTask::new(BlockLocation::new(1, 0, 0), &mut out, local, &global);
+        let mut ticks = 1;
+        while !task.tick(&mut out, local, &mut global) {
+            ticks += 1;
+        }
+        ticks
+    }
+
+    #[test]
+    fn test_fatigue() {
+        let normal = dig(&mut LocalState::mock());
+
+        // mining fatigue I slows a diamond pickaxe on stone from 6 ticks to 19
+        let mut local = LocalState::mock();
+        local.effects.add(Effect::MiningFatigue, 0, 600);
+        let fatigued = dig(&mut local);
+
+        assert_eq!(fatigued - normal, 13);
+    }
+
+    #[test]
+    fn test_travel() {
+        let (mut out, _rx) = Interface340::test();
+        let mut local = LocalState::mock();
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+        local.physics.teleport(BlockLocation::new(0, 1, 0).center_bottom());
+
+        let goal = BlockLocation::new(60, 1, 40);
+        let mut task = BlockTravelTask::new(goal, &local);
+        let estimate = task.plan(&local, &global);
+
+        let mut actual = 0;
+        loop {
+            task.expensive(Instant::now() + Duration::from_millis(100), &mut local, &global);
+            if task.tick(&mut out, &mut local, &mut global) {
+                break;
+            }
+            local.physics.tick(&mut global.blocks, &local.inventory);
+            actual += 1;
+            assert!(actual < 2000, "never arrived");
+        }
+
+        within(estimate.ticks, actual, 0.1);
+        assert!(estimate.food > 0.0);
+        assert!(estimate.risks.is_empty());
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut local = LocalState::mock();
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+        pickaxe(&mut local);
+
+        // a 7x7 region of 3 stone layers which are high enough to be mined
+        let center = BlockLocation2D::new(3, 3);
+        for x in 0..7 {
+            for z in 0..7 {
+                for y in 11..14 {
+                    global.blocks.set_block(BlockLocation::new(x, y, z), BlockState::STONE);
+                }
+            }
+        }
+        local.physics.teleport(BlockLocation::new(3, 14, 3).center_bottom());
+
+        let estimate = MineRegion::plan_regions(std::iter::once(center), &local, &global);
+        assert_eq!(estimate.blocks, 7 * 7 * 3);
+        assert_eq!(estimate.produced[&BlockKind::STONE.id()], 7 * 7 * 3);
+        assert_eq!(estimate.tools[&278], 7 * 7 * 3);
+        assert!(estimate.required.is_empty());
+        assert!(estimate.risks.is_empty());
+
+        // lava next to the region makes it skipped
+        let lava = BlockLocation::new(7, 12, 3);
+        global.blocks.set_block(lava, BlockState::from(11, 0));
+
+        let estimate = MineRegion::plan_regions(std::iter::once(center), &local, &global);
+        assert_eq!(estimate.blocks, 0);
+        assert_eq!(estimate.risks, vec![Risk::Fluid { location: lava, lava: true }]);
+    }
+
+    #[test]
+    fn test_risks() {
+        let local = LocalState::mock();
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+
+        let bedrock = BlockLocation::new(0, 0, 0);
+        global.blocks.set_block(bedrock, BlockState::from(7, 0));
+
+        let water = BlockLocation::new(2, 1, 0);
+        global.blocks.set_block(water, BlockState::from(9, 0));
+
+        let mut estimate = TaskEstimate::default();
+        estimate.mine(bedrock, &local, &global);
+        estimate.mine(BlockLocation::new(2, 0, 0), &local, &global);
+        estimate.mine(BlockLocation::new(500, 0, 0), &local, &global);
+        estimate.place(BlockKind::COBBLESTONE, 10, 0, &local);
+
+        assert_eq!(estimate.blocks, 11);
+        assert_eq!(estimate.risks, vec![
+            Risk::Unbreakable { location: bedrock },
+            Risk::Fluid { location: water, lava: false },
+            Risk::Unloaded { chunk: ChunkLocation(31, 0) },
+            Risk::MissingBlocks { needed: 10, have: 0 },
+        ]);
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `#find`: the closest blocks of a kind, with what their block entity tells us (i.e., the mob a spawner spawns)
+
+use crate::bootstrap::block_data::BlockData;
+use crate::client::reply::FoundRow;
+use crate::storage::block::{BlockKind, BlockLocation};
+use crate::storage::blocks::WorldBlocks;
+use crate::storage::tile_entity::TileEntity;
+
+/// names players use which are not the name of the block
+const ALIASES: &[(&str, &str)] = &[
+    ("spawner", "mob_spawner"),
+    ("nether_portal", "portal"),
+];
+
+#[derive(Clone, Debug)]
+pub struct FindConfig {
+    /// how many loaded columns around the bot are searched, closest first
+    pub max_chunks: usize,
+}
+
+impl Default for FindConfig {
+    fn default() -> Self {
+        Self {
+            max_chunks: 256
+        }
+    }
+}
+
+/// the kind of a block name (`diamond_ore`), alias (`spawner`) or id (`56`)
+pub fn kind_by_name(data: &BlockData, name: &str) -> Option<BlockKind> {
+    if let Ok(id) = name.parse() {
+        return Some(BlockKind(id));
+    }
+
+    let name = ALIASES.iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, real)| *real);
+
+    data.by_name(name).map(|block| BlockKind(block.id))
+}
+
+/// the `amount` blocks of `kind` closest to `origin`, closest first
+pub fn find(world: &WorldBlocks, origin: BlockLocation, kind: BlockKind, amount: usize, config: &FindConfig) -> Vec<FoundRow> {
+    world.closest_kind_iter(origin, config.max_chunks, kind)
+        .take(amount)
+        .map(|location| {
+            let mob = match world.tile_entity(location) {
+                Some(TileEntity::Spawner { mob }) => mob.clone(),
+                _ => None
+            };
+            FoundRow { location, distance: location.dist(origin), mob }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::block_data::BlockData;
+    use crate::client::find::{find, FindConfig, kind_by_name};
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::storage::tile_entity::TileEntity;
+
+    #[test]
+    fn test_kind_by_name() {
+        let data = BlockData::read().unwrap();
+        assert_eq!(kind_by_name(&data, "diamond_ore"), Some(BlockKind(56)));
+        assert_eq!(kind_by_name(&data, "minecraft:diamond_ore"), Some(BlockKind(56)));
+        assert_eq!(kind_by_name(&data, "spawner"), Some(BlockKind::MOB_SPAWNER));
+        assert_eq!(kind_by_name(&data, "portal"), Some(BlockKind(90)));
+        assert_eq!(kind_by_name(&data, "52"), Some(BlockKind::MOB_SPAWNER));
+        assert_eq!(kind_by_name(&data, "not_a_block"), None);
+    }
+
+    #[test]
+    fn test_find() {
+        let mut world = WorldBlocks::flat();
+        let spawner = BlockState::from(BlockKind::MOB_SPAWNER.id(), 0);
+        let near = BlockLocation::new(5, 1, 0);
+        let far = BlockLocation::new(-40, 1, 30);
+        for &location in &[far, near] {
+            world.set_block(location, spawner);
+        }
+        world.set_tile_entity(near, TileEntity::Spawner { mob: Some("skeleton".to_string()) });
+
+        let origin = BlockLocation::new(0, 1, 0);
+        let found = find(&world, origin, BlockKind::MOB_SPAWNER, 10, &FindConfig::default());
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].location, near);
+        assert_eq!(found[0].mob.as_deref(), Some("skeleton"));
+        assert_eq!(found[0].distance, 5.0);
+        assert_eq!(found[1].location, far);
+        assert_eq!(found[1].mob, None);
+
+        assert_eq!(find(&world, origin, BlockKind::MOB_SPAWNER, 1, &FindConfig::default()).len(), 1);
+
+        // only the column of the bot
+        let config = FindConfig { max_chunks: 1 };
+        assert_eq!(find(&world, origin, BlockKind::MOB_SPAWNER, 10, &config).len(), 1);
+    }
+}
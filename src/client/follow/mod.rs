@@ -14,15 +14,20 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 
-use crate::client::pathfind::context::MoveRecord;
+use crate::client::pathfind::context::{Action, MoveRecord};
 use crate::client::pathfind::incremental::PathResult;
 use crate::client::physics::Line;
 use crate::client::physics::speed::Speed;
+use crate::client::physics::sprint;
 use crate::client::state::global::GlobalState;
 use crate::client::state::local::LocalState;
-use crate::types::{Direction, Location};
+use crate::storage::block::{BlockLocation, SimpleType};
+use crate::storage::blocks::WorldBlocks;
+use crate::types::{Direction, Displacement, Location};
+
+pub mod stuck;
 
 const PROGRESS_THRESHOLD: f64 = 0.3;
 const PROGRESS_THRESHOLD_Y: f64 = 0.48;
@@ -31,10 +36,37 @@ const EPSILON: f64 = 0.001;
 
 const MIN_JUMP_DIST: f64 = 1.2;
 const MIN_SPRINT_DIST: f64 = 3.0;
-const MAX_JUMP_DIST: f64 = 4.0;
 
 const MAX_TICKS: usize = 20 * 10;
 
+/// points of a path further apart than this have a jump between them
+const MAX_STEP: f64 = 1.5;
+
+/// how far apart the points checked along a shortcut are
+const STEP: f64 = 0.25;
+
+/// half the width of a player
+const HALF_WIDTH: f64 = 0.3;
+
+const CORNERS: [(f64, f64); 4] = [(-HALF_WIDTH, -HALF_WIDTH), (-HALF_WIDTH, HALF_WIDTH), (HALF_WIDTH, -HALF_WIDTH), (HALF_WIDTH, HALF_WIDTH)];
+
+/// Whether a player can walk in a straight line from `from` to `to` without jumping: there is floor under the whole
+/// player and room for it all the way. Blocks which are not loaded are not walkable.
+fn walkable(from: Location, to: Location, world: &WorldBlocks) -> bool {
+    let line = to - from;
+    let steps = (line.mag() / STEP).ceil().max(1.0) as usize;
+
+    (0..=steps).all(|i| {
+        let at = from + line * (i as f64 / steps as f64);
+        CORNERS.iter().all(|&(dx, dz)| {
+            let feet = BlockLocation::from(at + Displacement::new(dx, EPSILON, dz));
+            world.get_block_simple(feet.below()) == Some(SimpleType::Solid)
+                && world.get_block_simple(feet) == Some(SimpleType::WalkThrough)
+                && world.get_block_simple(feet.above()) == Some(SimpleType::WalkThrough)
+        })
+    })
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub enum FollowResult {
     Failed,
@@ -60,6 +92,9 @@ pub struct Follower {
     ticks: usize,
     complete: bool,
     should_recalc: bool,
+
+    /// points reached by a fall broken with a water bucket
+    mlg: HashSet<BlockLocation>,
 }
 
 impl Follower {
@@ -71,6 +106,12 @@ impl Follower {
         if path.is_empty() { return None; }
 
         let initial = path.len();
+
+        let mlg = path.iter()
+            .filter(|ctx| matches!(ctx.action_to_obtain, Some(Action::Mlg(..))))
+            .map(|ctx| ctx.state.location)
+            .collect();
+
         let xs = path.into_iter().map(|ctx| {
             let loc = ctx.state.location;
             loc.center_bottom()
@@ -82,9 +123,30 @@ impl Follower {
             ticks: 0,
             complete: path_result.complete,
             should_recalc: false,
+            mlg,
         })
     }
 
+    /// Follow `xs` in order, i.e., the steps another player took. The points are known to be walkable so there is
+    /// nothing to recalculate.
+    pub fn along(xs: VecDeque<Location>) -> Option<Follower> {
+        if xs.is_empty() { return None; }
+
+        Some(Follower {
+            initial: xs.len(),
+            xs,
+            ticks: 0,
+            complete: true,
+            should_recalc: false,
+            mlg: HashSet::new(),
+        })
+    }
+
+    /// add a point to the end of the path
+    pub fn push(&mut self, location: Location) {
+        self.xs.push_back(location);
+    }
+
     pub fn merge(&mut self, result: PathResult<MoveRecord>) {
         let other = match Follower::new(result) {
             Some(res) => res,
@@ -125,11 +187,50 @@ impl Follower {
         *self = other;
     }
 
+    /// whether we are headed down a drop the path planned to break with a water bucket
+    pub fn mlg(&self) -> bool {
+        self.xs.front().map_or(false, |&on| self.mlg.contains(&BlockLocation::from(on)))
+    }
+
     fn next(&mut self) {
         self.xs.pop_front();
         self.ticks = 0;
     }
 
+    /// Head for the farthest point within `lookahead` blocks we can walk to in a straight line (pure pursuit) and
+    /// drop the points before it, so corners are cut instead of walking to the center of every block. Jumps, drops
+    /// and ladders need the exact points, so there is no shortcut while one is within `lookahead`. Returns the point
+    /// to head for.
+    fn cut_corners(&mut self, current: Location, lookahead: f64, world: &WorldBlocks) -> Option<Location> {
+        let mut target = None;
+        let mut previous = current;
+
+        for (i, &point) in self.xs.iter().enumerate() {
+            if (point - current).make_dy(0.).mag2() > lookahead * lookahead {
+                break;
+            }
+
+            let block = BlockLocation::from(point);
+            let level = point.y + world.standing_height(block);
+            let step = (point - previous).make_dy(0.).mag2();
+            if (level - current.y).abs() > EPSILON || (i > 0 && step > MAX_STEP * MAX_STEP) || self.mlg.contains(&block) {
+                return None;
+            }
+
+            if walkable(current, point, world) {
+                target = Some(i);
+            }
+            previous = point;
+        }
+
+        let target = target?;
+        if target > 0 {
+            self.xs.drain(..target);
+            self.ticks = 0;
+        }
+        self.xs.front().copied()
+    }
+
     pub fn should_recalc(&mut self) -> bool {
 
         // we should only recalc if this is not complete
@@ -140,7 +241,7 @@ impl Follower {
         self.should_recalc
     }
 
-    pub fn follow(&mut self, local: &mut LocalState, _global: &mut GlobalState) -> FollowResult {
+    pub fn follow(&mut self, local: &mut LocalState, global: &mut GlobalState) -> FollowResult {
 
 
         // We only want to recalc if we are on the ground to prevent issues with the pathfinder thinking
@@ -157,12 +258,23 @@ impl Follower {
         let mut displacement;
 
         let current = local.physics.location();
+
+        // steer for a point further along the path if we can walk straight there
+        let shortcut = if local.physics.on_ground() {
+            self.cut_corners(current, global.config.path.lookahead, &global.blocks)
+        } else {
+            None
+        };
+
         loop {
             let on = match self.xs.front() {
                 None => return if self.complete { FollowResult::Finished } else { FollowResult::Failed },
                 Some(on) => *on
             };
 
+            // our feet are higher than the block if we are standing in it (i.e., a bottom slab)
+            let on = on + Displacement::new(0.0, global.blocks.standing_height(BlockLocation::from(on)), 0.0);
+
             displacement = on - current;
             mag2_horizontal = displacement.make_dy(0.).mag2();
 
@@ -173,6 +285,9 @@ impl Follower {
             }
         }
 
+        // a shortcut we already reached is not one anymore
+        let shortcut = shortcut.is_some() && self.xs.front().copied() == shortcut;
+
         // by default move forward and sprint. Strafing is not needed; we can just change the direction we look
         local.physics.line(Line::Forward);
         local.physics.speed(Speed::SPRINT);
@@ -182,7 +297,7 @@ impl Follower {
 
         // more than 1.5 seconds on same block => failed
         if self.ticks >= MAX_TICKS {
-            println!("follower failed (time) for {} -> {}", local.physics.location(), self.xs.front().unwrap());
+            debug!(location = %local.physics.location(), next = %self.xs.front().unwrap(), "follower ran out of time");
             return FollowResult::Failed;
         }
 
@@ -207,12 +322,15 @@ impl Follower {
         const THRESH_VEL: f64 = 3.0 / 20.;
         // const THRESH_VEL: f64 = 0.0;
 
-        // sqrt(2) is 1.41 which is the distance from the center of a block to the next
-        if local.physics.on_ground() && mag2_horizontal > MIN_JUMP_DIST * MIN_JUMP_DIST {
+        // sqrt(2) is 1.41 which is the distance from the center of a block to the next. A shortcut is flat all the way.
+        if !shortcut && local.physics.on_ground() && mag2_horizontal > MIN_JUMP_DIST * MIN_JUMP_DIST {
             // it is far away... we probably have to jump to it
 
+            // a jump we cannot make without sprinting waits for the sprint to start
+            let max_jump = sprint::max_jump(local.physics.sprinting()) - 0.5;
+
             // min distance we can jump at
-            if mag2_horizontal < MAX_JUMP_DIST * MAX_JUMP_DIST && corr > 0.95 && velocity.mag2() > THRESH_VEL * THRESH_VEL {
+            if mag2_horizontal < max_jump * max_jump && corr > 0.95 && velocity.mag2() > THRESH_VEL * THRESH_VEL {
                 local.physics.jump();
             }
 
@@ -227,7 +345,7 @@ impl Follower {
         dir.pitch = 0.;
         local.physics.look(dir);
 
-        if displacement.dy > 0.0 {
+        if !shortcut && displacement.dy > 0.0 {
             // we want to move vertically first (jump)
             local.physics.jump();
         } else if displacement.dy < 0.0 {
@@ -242,6 +360,7 @@ impl Follower {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::VecDeque;
     use std::fs::OpenOptions;
     use std::time::{Duration, Instant};
 
@@ -255,7 +374,143 @@ mod tests {
     use crate::client::state::local::LocalState;
     use crate::client::timing::Increment;
     use crate::schematic::Schematic;
-    use crate::storage::block::BlockLocation;
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::types::Location;
+
+    fn points(blocks: &[(i32, i16, i32)]) -> VecDeque<Location> {
+        blocks.iter().map(|&(x, y, z)| BlockLocation::new(x, y, z).center_bottom()).collect()
+    }
+
+    /// 5 blocks east and then 5 south
+    fn corner() -> VecDeque<Location> {
+        let east = (1..=5).map(|x| (x, 1_i16, 0));
+        let south = (1..=5).map(|z| (5, 1_i16, z));
+        points(&east.chain(south).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_cut_corners() {
+        let mut world = WorldBlocks::flat();
+        let center = |x, z| BlockLocation::new(x, 1, z).center_bottom();
+
+        // the farthest point within the lookahead, then around the corner
+        let mut follower = Follower::along(corner()).unwrap();
+        assert_eq!(follower.cut_corners(center(0, 0), 4.0, &world), Some(center(4, 0)));
+        assert_eq!(follower.cut_corners(center(4, 0), 4.0, &world), Some(center(5, 3)));
+        assert_eq!(follower.points().len(), 3);
+
+        // a wall on the inside of the corner has to be walked around
+        world.set_block(BlockLocation::new(4, 1, 1), BlockState::STONE);
+        world.set_block(BlockLocation::new(4, 2, 1), BlockState::STONE);
+        let mut follower = Follower::along(corner()).unwrap();
+        assert_eq!(follower.cut_corners(center(4, 0), 4.0, &world), Some(center(5, 0)));
+
+        // no shortcut with a step up or a gap to jump ahead
+        world.set_block(BlockLocation::new(3, 1, 0), BlockState::STONE);
+        let mut step = Follower::along(points(&[(1, 1, 0), (2, 1, 0), (3, 2, 0), (4, 2, 0)])).unwrap();
+        assert_eq!(step.cut_corners(center(0, 0), 4.0, &world), None);
+
+        let mut gap = Follower::along(points(&[(0, 1, -2), (2, 1, -2)])).unwrap();
+        assert_eq!(gap.cut_corners(center(-1, -2), 4.0, &world), None);
+        assert_eq!(gap.points().len(), 2);
+
+        // nothing is cut without a lookahead
+        let mut follower = Follower::along(corner()).unwrap();
+        assert_eq!(follower.cut_corners(center(0, 0), 0.0, &world), None);
+        assert_eq!(follower.points().len(), 10);
+    }
+
+    /// path from `start` to `end` and follow the path. Returns if the player could get there
+    fn travel(blocks: WorldBlocks, start: BlockLocation, end: BlockLocation) -> bool {
+        let mut local_state = LocalState::mock();
+        let mut global_state = GlobalState::init();
+        global_state.blocks = blocks;
+
+        let mut problem = TravelProblem::navigate_block(start, end);
+
+        let increment = problem.iterate_until(Instant::now() + Duration::from_secs(10), &mut local_state, &global_state);
+
+        let result = match increment {
+            Increment::InProgress => panic!("not finished"),
+            Increment::Finished(res) => res
+        };
+
+        if !result.complete {
+            return false;
+        }
+
+        let mut follower = Follower::new(result).unwrap();
+
+        local_state.physics.teleport(start.center_bottom());
+
+        while let FollowResult::InProgress = follower.follow(&mut local_state, &mut global_state) {
+            local_state.physics.tick(&mut global_state.blocks, &PlayerInventory::default());
+        }
+
+        let mut end_loc = end.center_bottom();
+        end_loc.y += global_state.blocks.standing_height(end);
+
+        follower.follow(&mut local_state, &mut global_state) == FollowResult::Finished
+            && local_state.physics.location().dist2(end_loc) < 0.6 * 0.6
+    }
+
+    #[test]
+    fn test_stairs_and_slabs() {
+        let mut world = WorldBlocks::flat();
+
+        const STONE_STAIRS: u32 = 67;
+        const STONE_SLAB: u32 = 44;
+
+        // a staircase going up 3 blocks
+        for step in 0..3 {
+            let x = 3 + step;
+            for y in 1..=step {
+                world.set_block(BlockLocation::new(x, y as i16, 0), BlockState::STONE);
+            }
+            world.set_block(BlockLocation::new(x, step as i16 + 1, 0), BlockState::from(STONE_STAIRS, 0));
+        }
+
+        // a platform with a floor of bottom slabs
+        for x in 6..=12 {
+            for y in 1..=3 {
+                world.set_block(BlockLocation::new(x, y, 0), BlockState::STONE);
+            }
+            world.set_block(BlockLocation::new(x, 4, 0), BlockState::from(STONE_SLAB, 0));
+        }
+
+        assert!(travel(world, BlockLocation::new(0, 1, 0), BlockLocation::new(12, 4, 0)));
+    }
+
+    #[test]
+    fn test_fence_gate() {
+        const OAK_FENCE_GATE: u32 = 107;
+        const OPEN: u16 = 4;
+
+        // a pen surrounded by a wall with a gate
+        let pen = |gate: BlockState| {
+            let mut world = WorldBlocks::flat();
+            for x in -3..=3_i32 {
+                for z in -3..=3_i32 {
+                    if x.abs() == 3 || z.abs() == 3 {
+                        for y in 1..=3 {
+                            world.set_block(BlockLocation::new(x, y, z), BlockState::STONE);
+                        }
+                    }
+                }
+            }
+
+            world.set_block(BlockLocation::new(3, 1, 0), gate);
+            world.set_block(BlockLocation::new(3, 2, 0), BlockState::AIR);
+            world
+        };
+
+        let start = BlockLocation::new(0, 1, 0);
+        let end = BlockLocation::new(8, 1, 0);
+
+        assert!(!travel(pen(BlockState::from(OAK_FENCE_GATE, 0)), start, end));
+        assert!(travel(pen(BlockState::from(OAK_FENCE_GATE, OPEN)), start, end));
+    }
 
     #[test]
     fn test_parkour_course() {
@@ -264,7 +519,7 @@ mod tests {
             .open("test-data/parkour.schematic")
             .unwrap();
 
-        let course = Schematic::load(&mut reader);
+        let course = Schematic::load(&mut reader).unwrap();
 
 
         let mut local_state = LocalState::mock();
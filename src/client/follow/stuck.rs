@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Noticing when following a path goes nowhere, i.e., the bot is wedged on a fence, blocked by another bot or snagged
+//! on a block placed after the path was found.
+
+use std::collections::VecDeque;
+
+use crate::types::Location;
+
+/// how long to jump and strafe to get loose
+pub const JITTER_TICKS: usize = 10;
+
+#[derive(Clone, Debug)]
+pub struct StuckConfig {
+    /// the ticks of following to look back over
+    pub window: usize,
+
+    /// moving less than this (in blocks) over the window is being stuck
+    pub min_distance: f64,
+
+    /// how many times to get loose in a row before giving up
+    pub max_attempts: u32,
+
+    /// the extra path cost of the block we could not get to
+    pub penalty: f64,
+
+    /// the ticks it takes the penalty to wear off
+    pub penalty_ticks: usize,
+}
+
+impl Default for StuckConfig {
+    fn default() -> Self {
+        Self {
+            window: 40,
+            min_distance: 0.5,
+            max_attempts: 3,
+            penalty: 20.0,
+            penalty_ticks: 20 * 60,
+        }
+    }
+}
+
+/// what to do about being stuck
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Recovery {
+    /// jump and strafe for [`JITTER_TICKS`] to get off whatever we are caught on
+    Jitter,
+
+    /// path again avoiding the block we could not get to
+    Reroute,
+
+    /// nothing worked
+    Abort,
+}
+
+#[derive(Default)]
+pub struct StuckDetector {
+    /// where we were the last ticks we were following, oldest first
+    history: VecDeque<Location>,
+
+    /// the times we tried to get loose without making progress in between
+    attempts: u32,
+}
+
+impl StuckDetector {
+    /// Remember where we are while following a path. Returns how to get loose if we did not move far enough.
+    pub fn check(&mut self, location: Location, config: &StuckConfig) -> Option<Recovery> {
+        self.history.push_back(location);
+        if self.history.len() <= config.window {
+            return None;
+        }
+
+        let oldest = self.history.pop_front().unwrap();
+        if oldest.dist2(location) >= config.min_distance * config.min_distance {
+            self.attempts = 0;
+            return None;
+        }
+
+        // the recovery gets a whole window to work
+        self.history.clear();
+        self.attempts += 1;
+
+        let recovery = match self.attempts {
+            1 => Recovery::Jitter,
+            attempts if attempts <= config.max_attempts => Recovery::Reroute,
+            _ => Recovery::Abort
+        };
+
+        Some(recovery)
+    }
+
+    /// Forget where we were, i.e., when not following a path. Standing still while finding a path is not being stuck.
+    pub fn pause(&mut self) {
+        self.history.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::follow::stuck::{Recovery, StuckConfig, StuckDetector};
+    use crate::types::Location;
+
+    #[test]
+    fn test_escalate() {
+        let config = StuckConfig::default();
+        let mut detector = StuckDetector::default();
+        let wall = Location::new(4.2, 1.0, 0.5);
+
+        let mut recoveries = Vec::new();
+        for _ in 0..(config.window + 1) * 4 {
+            if let Some(recovery) = detector.check(wall, &config) {
+                recoveries.push(recovery);
+            }
+        }
+
+        assert_eq!(recoveries, vec![Recovery::Jitter, Recovery::Reroute, Recovery::Reroute, Recovery::Abort]);
+    }
+
+    #[test]
+    fn test_progress() {
+        let config = StuckConfig::default();
+        let mut detector = StuckDetector::default();
+
+        // walking slowly is not being stuck
+        for tick in 0..1000 {
+            let location = Location::new(tick as f64 * 0.05, 1.0, 0.5);
+            assert_eq!(detector.check(location, &config), None);
+        }
+
+        // getting loose starts over once we are moving again
+        let mut at = Location::new(0.5, 1.0, 0.5);
+        for _ in 0..=config.window {
+            detector.check(at, &config);
+        }
+        for _ in 0..config.window {
+            at.x += 0.1;
+            assert_eq!(detector.check(at, &config), None);
+        }
+        for _ in 0..=config.window {
+            detector.check(at, &config);
+        }
+        assert_eq!(detector.attempts, 1);
+    }
+}
@@ -0,0 +1,263 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Spreading bots sent to the same place over nearby blocks so they do not all stand inside each other
+
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+
+use float_ord::FloatOrd;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::block::{BlockLocation, SimpleType};
+use crate::storage::blocks::WorldBlocks;
+
+/// how far (horizontally) from a slot to look for a block to stand on instead
+const SEARCH_RADIUS: i32 = 6;
+
+/// how far up or down from a slot to look for a block to stand on instead
+const SEARCH_HEIGHT: i16 = 3;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FormationKind {
+    /// a square around the goal
+    Grid,
+
+    /// a ring around the goal
+    Circle,
+
+    /// a line through the goal along the x axis
+    Line,
+}
+
+impl Default for FormationKind {
+    fn default() -> Self {
+        FormationKind::Grid
+    }
+}
+
+impl FormationKind {
+    /// the (dx, dz) offsets from the goal of `count` bots
+    pub fn offsets(self, count: usize) -> Vec<(i32, i32)> {
+        match self {
+            FormationKind::Grid => {
+                let side = (count as f64).sqrt().ceil() as i32;
+                let low = -(side - 1) / 2;
+                let mut offsets: Vec<_> = (low..low + side)
+                    .flat_map(|dx| (low..low + side).map(move |dz| (dx, dz)))
+                    .collect();
+
+                // the middle of the square first if it is not full
+                offsets.sort_by_key(|&(dx, dz)| (dx * dx + dz * dz, dx, dz));
+                offsets.truncate(count);
+                offsets
+            }
+            FormationKind::Circle => {
+                if count <= 1 {
+                    return vec![(0, 0); count];
+                }
+
+                // about two blocks between neighbors
+                let radius = (count as f64 * 2.0 / (2.0 * PI)).max(1.0);
+                let mut offsets = Vec::with_capacity(count);
+                for i in 0..count {
+                    let angle = 2.0 * PI * i as f64 / count as f64;
+                    let offset = ((radius * angle.cos()).round() as i32, (radius * angle.sin()).round() as i32);
+                    if !offsets.contains(&offset) {
+                        offsets.push(offset);
+                    }
+                }
+
+                // rounding put some bots on the same block. They get a block close by.
+                let unique = offsets.len();
+                for i in 0..count - unique {
+                    offsets.push(offsets[i % unique]);
+                }
+                offsets
+            }
+            FormationKind::Line => (0..count as i32)
+                .map(|i| if i % 2 == 0 { (i / 2, 0) } else { (-(i + 1) / 2, 0) })
+                .collect()
+        }
+    }
+}
+
+/// a player fits with the feet at `location`: something solid to stand on and room for the legs and head
+pub fn standable(world: &WorldBlocks, location: BlockLocation) -> bool {
+    world.get_block_simple(location.below()) == Some(SimpleType::Solid)
+        && world.get_block_simple(location) == Some(SimpleType::WalkThrough)
+        && world.get_block_simple(location.above()) == Some(SimpleType::WalkThrough)
+}
+
+/// the closest block to `to` which can be stood on and is not `taken`
+fn closest_standable(world: &WorldBlocks, to: BlockLocation, taken: &HashSet<BlockLocation>) -> Option<BlockLocation> {
+    (-SEARCH_RADIUS..=SEARCH_RADIUS)
+        .flat_map(|dx| (-SEARCH_RADIUS..=SEARCH_RADIUS).map(move |dz| (dx, dz)))
+        .flat_map(|(dx, dz)| (-SEARCH_HEIGHT..=SEARCH_HEIGHT).map(move |dy| BlockLocation::new(to.x + dx, to.y + dy, to.z + dz)))
+        .filter(|location| !taken.contains(location) && standable(world, *location))
+        .min_by_key(|location| (FloatOrd(location.dist2(to)), location.x, location.y, location.z))
+}
+
+/// Assigns bots going to the same goal distinct blocks around it. A bot keeps its block when the same goal is given
+/// again so bots do not swap places and cross each other's paths.
+#[derive(Default)]
+pub struct Formation {
+    goal: Option<(BlockLocation, FormationKind)>,
+    targets: HashMap<String, BlockLocation>,
+}
+
+impl Formation {
+    /// where each of `bots` (username and location) should go. Bots which do not fit near the goal go to the goal.
+    pub fn assign(&mut self, world: &WorldBlocks, goal: BlockLocation, kind: FormationKind, bots: &[(&str, BlockLocation)]) -> HashMap<String, BlockLocation> {
+        if self.goal != Some((goal, kind)) {
+            self.goal = Some((goal, kind));
+            self.targets.clear();
+        }
+
+        // the blocks of the formation. One which cannot be stood on is moved to the closest free block.
+        let mut slots = Vec::new();
+        let mut found = HashSet::new();
+        for (dx, dz) in kind.offsets(bots.len()) {
+            let slot = BlockLocation::new(goal.x + dx, goal.y, goal.z + dz);
+            if let Some(slot) = closest_standable(world, slot, &found) {
+                found.insert(slot);
+                slots.push(slot);
+            }
+        }
+
+        let mut assigned = HashMap::new();
+        let mut taken = HashSet::new();
+
+        // keep the blocks from last time if they can still be stood on
+        for &(name, _) in bots {
+            if let Some(&target) = self.targets.get(name) {
+                if standable(world, target) && taken.insert(target) {
+                    assigned.insert(name.to_string(), target);
+                }
+            }
+        }
+
+        slots.retain(|slot| !taken.contains(slot));
+
+        // closest pairs first so bots do not walk past each other
+        let mut pairs: Vec<_> = bots.iter()
+            .filter(|(name, _)| !assigned.contains_key(*name))
+            .flat_map(|&(name, at)| slots.iter().map(move |&slot| (name, at, slot)))
+            .collect();
+        pairs.sort_by_key(|&(name, at, slot)| (FloatOrd(at.dist2(slot)), name, slot.x, slot.y, slot.z));
+
+        for (name, _, slot) in pairs {
+            if assigned.contains_key(name) || !taken.insert(slot) {
+                continue;
+            }
+            assigned.insert(name.to_string(), slot);
+        }
+
+        for &(name, _) in bots {
+            assigned.entry(name.to_string()).or_insert(goal);
+        }
+
+        self.targets = assigned.clone();
+        assigned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::client::formation::{Formation, FormationKind, standable};
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+
+    fn swarm(count: usize) -> Vec<(String, BlockLocation)> {
+        (0..count).map(|i| (format!("bot{}", i), BlockLocation::new(20 + i as i32, 1, -10))).collect()
+    }
+
+    fn assign(formation: &mut Formation, world: &WorldBlocks, goal: BlockLocation, kind: FormationKind, bots: &[(String, BlockLocation)]) -> Vec<BlockLocation> {
+        let bots: Vec<_> = bots.iter().map(|(name, at)| (name.as_str(), *at)).collect();
+        let targets = formation.assign(world, goal, kind, &bots);
+        bots.iter().map(|(name, _)| targets[*name]).collect()
+    }
+
+    #[test]
+    fn test_grid() {
+        let world = WorldBlocks::flat();
+        let goal = BlockLocation::new(0, 1, 0);
+        let bots = swarm(9);
+
+        let targets = assign(&mut Formation::default(), &world, goal, FormationKind::Grid, &bots);
+
+        let distinct: HashSet<_> = targets.iter().copied().collect();
+        assert_eq!(distinct.len(), 9);
+
+        let grid: HashSet<_> = (-1..=1).flat_map(|dx| (-1..=1).map(move |dz| BlockLocation::new(dx, 1, dz))).collect();
+        assert_eq!(distinct, grid);
+        assert!(targets.iter().all(|&target| standable(&world, target)));
+    }
+
+    #[test]
+    fn test_blocked() {
+        let mut world = WorldBlocks::flat();
+
+        // a pillar where one slot would be and a hole in the floor of another
+        world.set_block(BlockLocation::new(1, 1, 1), BlockState::STONE);
+        world.set_block(BlockLocation::new(1, 2, 1), BlockState::STONE);
+        world.set_block(BlockLocation::new(-1, 0, -1), BlockState::AIR);
+
+        let goal = BlockLocation::new(0, 1, 0);
+        let targets = assign(&mut Formation::default(), &world, goal, FormationKind::Grid, &swarm(9));
+
+        let distinct: HashSet<_> = targets.iter().copied().collect();
+        assert_eq!(distinct.len(), 9);
+        assert!(targets.iter().all(|&target| standable(&world, target)));
+        assert!(targets.iter().all(|target| target.dist2(goal) <= 8.0));
+    }
+
+    #[test]
+    fn test_stable() {
+        let world = WorldBlocks::flat();
+        let goal = BlockLocation::new(0, 1, 0);
+        let mut formation = Formation::default();
+
+        let mut bots = swarm(9);
+        let before = assign(&mut formation, &world, goal, FormationKind::Grid, &bots);
+
+        // the bots moved on their way there and one more joined
+        for (_, at) in bots.iter_mut() {
+            at.x -= 15;
+        }
+        bots.push(("late".to_string(), BlockLocation::new(0, 1, 30)));
+
+        let after = assign(&mut formation, &world, goal, FormationKind::Grid, &bots);
+        assert_eq!(&after[..9], &before[..]);
+        assert!(!before.contains(&after[9]));
+    }
+
+    #[test]
+    fn test_offsets() {
+        for kind in [FormationKind::Grid, FormationKind::Circle, FormationKind::Line] {
+            for count in 0..30 {
+                assert_eq!(kind.offsets(count).len(), count);
+            }
+        }
+
+        assert_eq!(FormationKind::Line.offsets(4), vec![(0, 0), (-1, 0), (1, 0), (-2, 0)]);
+        let circle: HashSet<_> = FormationKind::Circle.offsets(12).into_iter().collect();
+        assert_eq!(circle.len(), 12);
+    }
+}
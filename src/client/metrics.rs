@@ -0,0 +1,137 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Counters shared by all bots and gauges of the shared world, returned by the status command
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+use crate::client::state::global::GlobalState;
+
+/// a count which only goes up. Relaxed as the counts are only read for display.
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Counter {
+        Counter(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, count: u64) {
+        self.0.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Metrics {
+    pub packets_in: Counter,
+    pub packets_out: Counter,
+
+    /// the bytes of the packets the server sent (as sent, so compressed if the connection is)
+    pub bytes_in: Counter,
+
+    /// the chunk columns the server sent, which make up most of `bytes_in`
+    pub chunks_in: Counter,
+
+    /// path searches started, including ones restarted from a new location
+    pub path_searches: Counter,
+    pub blocks_mined: Counter,
+    pub disconnects: Counter,
+}
+
+pub static METRICS: Metrics = Metrics {
+    packets_in: Counter::new(),
+    packets_out: Counter::new(),
+    bytes_in: Counter::new(),
+    chunks_in: Counter::new(),
+    path_searches: Counter::new(),
+    blocks_mined: Counter::new(),
+    disconnects: Counter::new(),
+};
+
+/// the counters and gauges at one point in time
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct MetricsSnapshot {
+    pub packets_in: u64,
+    pub packets_out: u64,
+    pub bytes_in: u64,
+    pub chunks_in: u64,
+    pub path_searches: u64,
+    pub blocks_mined: u64,
+    pub disconnects: u64,
+
+    /// the chunks loaded in the dimension the bots are in
+    pub chunks: usize,
+    pub entities: usize,
+
+    /// the resident memory of the process. None if it could not be read (i.e., not on Linux).
+    pub resident_bytes: Option<u64>,
+}
+
+impl MetricsSnapshot {
+    pub fn take(global: &GlobalState) -> MetricsSnapshot {
+        MetricsSnapshot {
+            packets_in: METRICS.packets_in.get(),
+            packets_out: METRICS.packets_out.get(),
+            bytes_in: METRICS.bytes_in.get(),
+            chunks_in: METRICS.chunks_in.get(),
+            path_searches: METRICS.path_searches.get(),
+            blocks_mined: METRICS.blocks_mined.get(),
+            disconnects: METRICS.disconnects.get(),
+            chunks: global.blocks.chunk_count(),
+            entities: global.entities.iter().count(),
+            resident_bytes: resident_bytes(),
+        }
+    }
+}
+
+/// the second field of statm is the resident size in pages, which are 4 KiB on the platforms we run on
+fn resident_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * 4096)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::metrics::{Counter, MetricsSnapshot};
+    use crate::client::state::global::GlobalState;
+
+    #[test]
+    fn test_counter() {
+        let counter = Counter::new();
+        assert_eq!(counter.get(), 0);
+        counter.inc();
+        counter.inc();
+        assert_eq!(counter.get(), 2);
+        counter.add(40);
+        assert_eq!(counter.get(), 42);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let snapshot = MetricsSnapshot::take(&GlobalState::init());
+        assert_eq!(snapshot.chunks, 0);
+        assert_eq!(snapshot.entities, 0);
+    }
+}
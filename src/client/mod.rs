@@ -19,8 +19,29 @@ pub mod runner;
 pub mod processor;
 pub mod pathfind;
 mod timing;
+mod clock;
 mod follow;
 pub mod state;
 pub mod physics;
-mod tasks;
-mod commands;
+pub mod tasks;
+pub mod commands;
+mod wire;
+pub mod recording;
+pub mod digest;
+pub mod reply;
+pub mod routine;
+pub mod afk;
+pub mod estimate;
+pub mod swarm;
+pub mod bases;
+pub mod desync;
+pub mod formation;
+pub mod permissions;
+pub mod shutdown;
+pub mod metrics;
+pub mod find;
+pub mod tunables;
+pub mod controller;
+pub mod snapshot;
+pub mod view;
+pub mod sim;
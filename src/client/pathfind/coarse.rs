@@ -0,0 +1,283 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Planning long journeys over whole chunks instead of blocks. A chunk is judged by the top of its columns only (see
+//! [`Heightmap`](crate::storage::blocks::heightmap::Heightmap)) and one which is not loaded is assumed to be fine.
+
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::f64::consts::SQRT_2;
+
+use crate::client::pathfind::MinHeapNode;
+use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+
+/// the most chunks looked at for a plan. The plan goes to the closest chunk to the goal instead.
+const MAX_CELLS: usize = 20_000;
+
+const NEIGHBORS: [(i32, i32); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+#[derive(Clone, Debug)]
+pub struct TravelConfig {
+    /// how far apart (in blocks) the waypoints of a journey are
+    pub spacing: u32,
+
+    /// a chunk with less of its top dry (i.e., an ocean) is not crossed
+    pub min_dry: f64,
+
+    /// a chunk with more of its top too steep to walk up (i.e., a cliff) is not crossed
+    pub max_steep: f64,
+
+    /// the most (in blocks) the average ground of neighbouring chunks may differ by
+    pub max_climb: f64,
+}
+
+impl Default for TravelConfig {
+    fn default() -> Self {
+        Self {
+            spacing: 256,
+            min_dry: 0.5,
+            max_steep: 0.5,
+            max_climb: 16.0,
+        }
+    }
+}
+
+/// what is known about crossing a chunk
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Cell {
+    /// not loaded, assumed to be fine
+    Unknown,
+
+    /// loaded and fine to cross. `floor` is the average height of the ground.
+    Open { cost: f64, floor: f64 },
+
+    /// loaded and not to be crossed
+    Blocked,
+}
+
+impl Cell {
+    pub fn judge(world: &WorldBlocks, location: ChunkLocation, config: &TravelConfig) -> Cell {
+        let summary = match world.heightmap(location) {
+            None => return Cell::Unknown,
+            Some(heightmap) => heightmap.summary()
+        };
+
+        match summary.floor {
+            Some(floor) if summary.dry >= config.min_dry && summary.steep <= config.max_steep => {
+                Cell::Open { cost: 1.0 + summary.steep, floor }
+            }
+            _ => Cell::Blocked,
+        }
+    }
+
+    fn cost(self) -> f64 {
+        match self {
+            Cell::Unknown => 1.0,
+            Cell::Open { cost, .. } => cost,
+            Cell::Blocked => f64::INFINITY,
+        }
+    }
+}
+
+/// whether we can go from a chunk to its neighbour judging by what is known about them
+pub fn crossable(from: Cell, to: Cell, config: &TravelConfig) -> bool {
+    match (from, to) {
+        (_, Cell::Blocked) => false,
+        (Cell::Open { floor: a, .. }, Cell::Open { floor: b, .. }) => (a - b).abs() <= config.max_climb,
+        _ => true,
+    }
+}
+
+/// the cost of going straight (diagonals included) from `from` to `to` over chunks which are all fine
+fn octile(from: ChunkLocation, to: ChunkLocation) -> f64 {
+    let dx = (from.0 - to.0).abs() as f64;
+    let dz = (from.1 - to.1).abs() as f64;
+    dx.max(dz) + (SQRT_2 - 1.0) * dx.min(dz)
+}
+
+fn reconstruct(parents: &HashMap<ChunkLocation, ChunkLocation>, mut at: ChunkLocation) -> Vec<ChunkLocation> {
+    let mut path = vec![at];
+    while let Some(&parent) = parents.get(&at) {
+        path.push(parent);
+        at = parent;
+    }
+    path.reverse();
+    path
+}
+
+/// The chunks to go through from `from` to `to` (both included) not crossing any of `avoid`. If too many chunks had to
+/// be looked at the plan ends at the one closest to `to`. None if there is no way.
+pub fn plan(world: &WorldBlocks, from: ChunkLocation, to: ChunkLocation, avoid: &HashSet<ChunkLocation>, config: &TravelConfig) -> Option<Vec<ChunkLocation>> {
+    let mut judged = HashMap::new();
+
+    // we are already in the first chunk and want to be in the last no matter what they are like
+    let mut cell = |location: ChunkLocation| {
+        if location == from || location == to {
+            Cell::Unknown
+        } else if avoid.contains(&location) {
+            Cell::Blocked
+        } else {
+            *judged.entry(location).or_insert_with(|| Cell::judge(world, location, config))
+        }
+    };
+
+    let mut parents = HashMap::new();
+    let mut g_scores = HashMap::new();
+    let mut closed = HashSet::new();
+    let mut open = BinaryHeap::new();
+
+    g_scores.insert(from, 0.0);
+    open.push(MinHeapNode::new(from, octile(from, to)));
+
+    let mut closest = (octile(from, to), from);
+
+    while let Some(node) = open.pop() {
+        let current = node.contents;
+        if !closed.insert(current) {
+            continue;
+        }
+
+        if current == to {
+            return Some(reconstruct(&parents, to));
+        }
+
+        if closed.len() > MAX_CELLS {
+            debug!(?from, ?to, ?closest, "looked at too many chunks, planning to the closest");
+            return (closest.1 != from).then(|| reconstruct(&parents, closest.1));
+        }
+
+        let remaining = octile(current, to);
+        if remaining < closest.0 {
+            closest = (remaining, current);
+        }
+
+        let here = cell(current);
+        let g_score = g_scores[&current];
+
+        for &(dx, dz) in NEIGHBORS.iter() {
+            let next = ChunkLocation(current.0 + dx, current.1 + dz);
+            let there = cell(next);
+            if !crossable(here, there, config) {
+                continue;
+            }
+
+            // do not cut the corner of a chunk we cannot cross
+            let diagonal = dx != 0 && dz != 0;
+            if diagonal && (cell(ChunkLocation(next.0, current.1)) == Cell::Blocked || cell(ChunkLocation(current.0, next.1)) == Cell::Blocked) {
+                continue;
+            }
+
+            let step = if diagonal { SQRT_2 } else { 1.0 };
+            let tentative = g_score + there.cost() * step;
+            if g_scores.get(&next).map_or(true, |&before| tentative < before) {
+                g_scores.insert(next, tentative);
+                parents.insert(next, current);
+                open.push(MinHeapNode::new(next, tentative + octile(next, to)));
+            }
+        }
+    }
+
+    None
+}
+
+/// the indices of the chunks of `path` to stop at: one every `spacing` blocks and the last
+pub fn waypoints(path: &[ChunkLocation], spacing: u32) -> Vec<usize> {
+    let step = (spacing as usize / 16).max(1);
+    let last = path.len() - 1;
+
+    let mut waypoints: Vec<_> = (step..path.len()).step_by(step).collect();
+    if waypoints.last() != Some(&last) {
+        waypoints.push(last);
+    }
+    waypoints
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::client::pathfind::coarse::{Cell, crossable, plan, TravelConfig, waypoints};
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+
+    fn ocean(world: &mut WorldBlocks, chunk: ChunkLocation) {
+        for dx in 0..16 {
+            for dz in 0..16 {
+                let (x, z) = ((chunk.0 << 4) + dx, (chunk.1 << 4) + dz);
+                world.set_block(BlockLocation::new(x, 0, z), BlockState::STONE);
+                world.set_block(BlockLocation::new(x, 1, z), BlockState::from(9, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown() {
+        let config = TravelConfig::default();
+        let path = plan(&WorldBlocks::default(), ChunkLocation(0, 0), ChunkLocation(100, 0), &HashSet::new(), &config).unwrap();
+
+        assert_eq!(path.len(), 101);
+        assert!(path.iter().all(|cell| cell.1 == 0));
+        assert_eq!(waypoints(&path, 256), vec![16, 32, 48, 64, 80, 96, 100]);
+        assert_eq!(waypoints(&path[..1], 256), vec![0]);
+    }
+
+    #[test]
+    fn test_around() {
+        let config = TravelConfig::default();
+        let mut world = WorldBlocks::default();
+
+        let mut blocked = HashSet::new();
+        for z in -3..=3 {
+            ocean(&mut world, ChunkLocation(5, z));
+            blocked.insert(ChunkLocation(5, z));
+        }
+
+        let avoid: HashSet<_> = vec![ChunkLocation(5, 4)].into_iter().collect();
+        let path = plan(&world, ChunkLocation(0, 0), ChunkLocation(10, 0), &avoid, &config).unwrap();
+
+        assert_eq!(path.first(), Some(&ChunkLocation(0, 0)));
+        assert_eq!(path.last(), Some(&ChunkLocation(10, 0)));
+        assert!(path.iter().all(|cell| !blocked.contains(cell) && !avoid.contains(cell)));
+        assert!(path.contains(&ChunkLocation(5, -4)));
+    }
+
+    #[test]
+    fn test_enclosed() {
+        let config = TravelConfig::default();
+        let mut world = WorldBlocks::default();
+        for x in -2..=2 {
+            for z in -2..=2 {
+                if x == -2 || x == 2 || z == -2 || z == 2 {
+                    ocean(&mut world, ChunkLocation(x, z));
+                }
+            }
+        }
+
+        assert_eq!(plan(&world, ChunkLocation(0, 0), ChunkLocation(10, 0), &HashSet::new(), &config), None);
+    }
+
+    #[test]
+    fn test_cliff() {
+        let config = TravelConfig::default();
+        let low = Cell::Open { cost: 1.0, floor: 64.0 };
+        let high = Cell::Open { cost: 1.0, floor: 90.0 };
+
+        assert!(!crossable(low, high, &config));
+        assert!(!crossable(high, low, &config));
+        assert!(crossable(low, Cell::Unknown, &config));
+        assert!(crossable(Cell::Unknown, high, &config));
+        assert!(!crossable(Cell::Unknown, Cell::Blocked, &config));
+    }
+}
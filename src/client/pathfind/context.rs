@@ -16,6 +16,8 @@
 
 use std::hash::{Hash, Hasher};
 
+use crate::client::afk::Area;
+use crate::client::follow::stuck::StuckConfig;
 use crate::client::pathfind::incremental::Node;
 use crate::storage::block::{BlockLocation, BlockState};
 use crate::storage::blocks::WorldBlocks;
@@ -31,11 +33,28 @@ pub struct Costs {
     pub ascend: f64,
     pub no_breathe_mult: f64,
     pub fall: f64,
+
+    /// per point (half a heart) of damage a fall is expected to do
+    pub fall_damage: f64,
+
+    /// placing and picking back up a water bucket to break a fall
+    pub mlg: f64,
 }
 
+#[derive(Clone)]
 pub struct PathConfig {
     pub costs: Costs,
     pub parkour: bool,
+
+    /// the highest drop taken without a water bucket. Vanilla only hurts falls over 3 blocks.
+    pub max_fall: i32,
+
+    /// when following a path is being stuck and what to do about it
+    pub stuck: StuckConfig,
+
+    /// how far ahead along the path (in blocks) to steer for where we can walk straight there. 0 walks to the center
+    /// of every block.
+    pub lookahead: f64,
 }
 
 impl Default for PathConfig {
@@ -48,11 +67,16 @@ impl Default for PathConfig {
                 ascend: 1.0,
                 no_breathe_mult: 3.0,
                 fall: 1.0,
+                fall_damage: 10.0,
+                mlg: 5.0,
                 place_unrelated: 20.0,
                 mine_required: 1.0,
                 place_required: 1.0,
             },
             parkour: true,
+            max_fall: 4,
+            stuck: StuckConfig::default(),
+            lookahead: 4.0,
         }
     }
 }
@@ -61,6 +85,15 @@ impl Default for PathConfig {
 pub struct GlobalContext<'a> {
     pub path_config: &'a PathConfig,
     pub world: &'a WorldBlocks,
+
+    /// whether the bot can sprint. Decides how far jumps can be.
+    pub sprint: bool,
+
+    /// no jumps or falls start or end in this area (i.e., a field, where landing tramples the farmland)
+    pub no_jump: Option<Area>,
+
+    /// whether drops higher than [PathConfig::max_fall] can be broken with a water bucket
+    pub water_bucket: bool,
 }
 
 #[derive(Debug)]
@@ -133,6 +166,9 @@ impl Node for MoveNode {
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum Action {
     Change(BlockLocation, BlockState),
+
+    /// a fall broken by placing water on top of the block before landing (an "MLG")
+    Mlg(BlockLocation),
 }
 
 #[derive(Hash, Eq, PartialEq, Clone, Debug)]
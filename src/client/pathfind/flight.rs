@@ -0,0 +1,153 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Paths for flying where the server lets us. Nothing is in the way in the air, so instead of searching blocks the
+//! path climbs above everything between the ends, flies straight across and comes down.
+
+use float_ord::FloatOrd;
+use itertools::iproduct;
+
+use crate::storage::block::{BlockLocation, SimpleType};
+use crate::storage::blocks::WorldBlocks;
+use crate::types::{Displacement, Location};
+
+/// how far apart the points checked along a line are
+const STEP: f64 = 0.25;
+
+/// how high above the highest block between the ends we fly across
+const CLEARANCE: f64 = 2.0;
+
+/// half the width of a player and a bit more so flying past a corner does not clip it
+const HALF_WIDTH: f64 = 0.35;
+const HEIGHT: f64 = 1.8;
+
+const CORNERS: [(f64, f64); 4] = [(-HALF_WIDTH, -HALF_WIDTH), (-HALF_WIDTH, HALF_WIDTH), (HALF_WIDTH, -HALF_WIDTH), (HALF_WIDTH, HALF_WIDTH)];
+
+/// whether the box of a player at `feet` is clear of blocks it would bump into. Chunks which are not loaded are
+/// assumed to be empty.
+fn box_clear(feet: Location, world: &WorldBlocks) -> bool {
+    let min = BlockLocation::from(feet + Displacement::new(-HALF_WIDTH, 0.001, -HALF_WIDTH));
+    let max = BlockLocation::from(feet + Displacement::new(HALF_WIDTH, HEIGHT, HALF_WIDTH));
+
+    iproduct!(min.x..=max.x, min.y..=max.y, min.z..=max.z)
+        .map(|(x, y, z)| world.get_block_simple(BlockLocation::new(x, y, z)))
+        .all(|kind| !matches!(kind, Some(SimpleType::Solid | SimpleType::Avoid)))
+}
+
+/// whether a player can fly in a straight line from `from` to `to`
+pub fn clear(from: Location, to: Location, world: &WorldBlocks) -> bool {
+    let line = to - from;
+    let steps = (line.mag() / STEP).ceil().max(1.0) as usize;
+    (0..=steps).all(|i| box_clear(from + line * (i as f64 / steps as f64), world))
+}
+
+/// [`CLEARANCE`] above the highest known block under the box of a player flying from `from` to `to`
+fn cruise_height(from: Location, to: Location, world: &WorldBlocks) -> f64 {
+    let line = Displacement::new(to.x - from.x, 0., to.z - from.z);
+    let steps = (line.mag() / STEP).ceil().max(1.0) as usize;
+
+    let highest = (0..=steps)
+        .map(|i| from + line * (i as f64 / steps as f64))
+        .flat_map(|at| CORNERS.iter().map(move |&(dx, dz)| at + Displacement::new(dx, 0., dz)))
+        .filter_map(|corner| {
+            let column = BlockLocation::from(corner);
+            world.first_below(BlockLocation::new(column.x, 256, column.z))
+        })
+        .map(|(loc, _)| FloatOrd(loc.y as f64 + 1.0))
+        .max()
+        .map_or(to.y, |FloatOrd(top)| top.max(to.y));
+
+    (highest + CLEARANCE).max(from.y)
+}
+
+/// The points to fly through to get from `from` to `to`, ending with `to`: straight up (unless we are already high
+/// enough), across and straight down. None if something is above either end.
+pub fn flight_path(from: Location, to: Location, world: &WorldBlocks) -> Option<Vec<Location>> {
+    let cruise = cruise_height(from, to, world);
+    let points = vec![
+        Location::new(from.x, cruise, from.z),
+        Location::new(to.x, cruise, to.z),
+        to,
+    ];
+
+    let mut prev = from;
+    for &point in &points {
+        if !clear(prev, point, world) {
+            return None;
+        }
+        prev = point;
+    }
+
+    Some(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::pathfind::flight::{clear, flight_path};
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::types::Location;
+
+    /// the flat world with a wall 5 blocks high at x = 10
+    fn walled() -> WorldBlocks {
+        let mut world = WorldBlocks::flat();
+        for y in 1..=5 {
+            for z in -3..=3 {
+                world.set_block(BlockLocation::new(10, y, z), BlockState::STONE);
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn test_flat() {
+        let world = WorldBlocks::flat();
+        let from = Location::new(0.5, 1., 0.5);
+        let to = Location::new(20.5, 1., 0.5);
+        assert!(clear(from, to, &world));
+
+        let path = flight_path(from, to, &world).unwrap();
+        assert_eq!(path, vec![Location::new(0.5, 3., 0.5), Location::new(20.5, 3., 0.5), to]);
+
+        // already flying high enough
+        let high = Location::new(0.5, 10., 0.5);
+        assert_eq!(flight_path(high, to, &world).unwrap()[1].y, 10.0);
+    }
+
+    #[test]
+    fn test_wall() {
+        let world = walled();
+        let from = Location::new(0.5, 1., 0.5);
+        let to = Location::new(20.5, 1., 0.5);
+        assert!(!clear(from, to, &world));
+
+        // 2 blocks over the top of the wall
+        let path = flight_path(from, to, &world).unwrap();
+        assert_eq!(path.len(), 3);
+        assert_eq!(path[1], Location::new(20.5, 8., 0.5));
+        assert_eq!(path[2], to);
+    }
+
+    #[test]
+    fn test_roof() {
+        let mut world = walled();
+        world.set_block(BlockLocation::new(0, 4, 0), BlockState::STONE);
+
+        let from = Location::new(0.5, 1., 0.5);
+        let to = Location::new(20.5, 1., 0.5);
+        assert!(flight_path(from, to, &world).is_none());
+    }
+}
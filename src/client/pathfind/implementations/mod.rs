@@ -17,9 +17,12 @@
 use std::collections::HashMap;
 use std::time::Instant;
 
+use crate::client::afk::Area;
+use crate::client::metrics::METRICS;
 use crate::client::pathfind::context::{GlobalContext, MoveNode, MoveRecord};
 use crate::client::pathfind::incremental::{AStar, Node, PathResult};
 use crate::client::pathfind::moves::Movements;
+use crate::client::pathfind::penalty::Penalties;
 use crate::client::pathfind::traits::{GoalCheck, Heuristic, Progression, Progressor};
 use crate::client::state::global::GlobalState;
 use crate::client::state::local::LocalState;
@@ -41,49 +44,73 @@ pub struct PlayerProblem<H: Heuristic<MoveNode>, G: GoalCheck<MoveNode>> {
     a_star: AStar<MoveNode>,
     heuristic: H,
     goal_checker: G,
+    no_jump: Option<Area>,
 }
 
 
 impl<H: Heuristic<MoveNode> + Send + Sync, G: GoalCheck<MoveNode> + Send + Sync> PlayerProblem<H, G> {
     pub fn new(start: MoveNode, heuristic: H, goal_checker: G) -> PlayerProblem<H, G> {
+        METRICS.path_searches.inc();
         let a_star = AStar::new(start);
         Self {
             heuristic,
             a_star,
             goal_checker,
+            no_jump: None,
         }
     }
 
     pub fn set_max_millis(&mut self, value: u128) {
         self.a_star.set_max_millis(value);
     }
+
+    /// do not jump or fall into or out of `area`
+    pub fn set_no_jump(&mut self, area: Area) {
+        self.no_jump = Some(area);
+    }
 }
 
 
 #[derive(Clone)]
 struct GenericProgressor<'a> {
     ctx: GlobalContext<'a>,
+
+    /// blocks we got stuck at and the tick it is now
+    penalties: &'a Penalties,
+    now: usize,
 }
 
 impl Progressor<MoveNode> for GenericProgressor<'_> {
     fn progressions(&self, location: &MoveNode) -> Progression<MoveNode> {
-        Movements::obtain_all(location, &self.ctx)
+        let mut progression = Movements::obtain_all(location, &self.ctx);
+
+        if let Progression::Movements(neighbors) = &mut progression {
+            for neighbor in neighbors {
+                neighbor.cost += self.penalties.cost(neighbor.value.location, self.now);
+            }
+        }
+
+        progression
     }
 }
 
 impl<H: Heuristic<MoveNode> + Send + Sync, G: GoalCheck<MoveNode> + Send + Sync> Problem for PlayerProblem<H, G> {
     type Node = MoveNode;
 
-    fn iterate_until(&mut self, end_at: Instant, _: &mut LocalState, global: &GlobalState) -> Increment<PathResult<MoveRecord>> {
+    fn iterate_until(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) -> Increment<PathResult<MoveRecord>> {
         let ctx = GlobalContext {
-            path_config: &global.travel_config,
+            path_config: &global.config.path,
             world: &global.blocks,
+            sprint: local.physics.can_sprint(),
+            no_jump: self.no_jump,
+            water_bucket: local.inventory.has_water_bucket(),
         };
-        let progressor = GenericProgressor { ctx };
+        let progressor = GenericProgressor { ctx, penalties: &local.penalties, now: local.ticks };
         self.a_star.iterate_until(end_at, &self.heuristic, &progressor, &self.goal_checker)
     }
 
     fn recalc(&mut self, context: Self::Node) {
+        METRICS.path_searches.inc();
         self.a_star = AStar::new(context);
     }
 }
@@ -15,6 +15,8 @@
  */
 
 
+use std::collections::HashSet;
+
 use crate::client::pathfind::context::MoveNode;
 use crate::client::pathfind::implementations::PlayerProblem;
 use crate::client::pathfind::traits::{GoalCheck, Heuristic};
@@ -50,6 +52,17 @@ impl GoalCheck for BlockNearGoalCheck {
     }
 }
 
+/// any of the blocks in `goals`, e.g., the blocks a block can be reached from
+pub struct AnyOfGoalCheck {
+    goals: HashSet<BlockLocation>,
+}
+
+impl GoalCheck for AnyOfGoalCheck {
+    fn is_goal(&self, input: &MoveNode) -> bool {
+        self.goals.contains(&input.location)
+    }
+}
+
 pub struct ChunkGoalCheck {
     goal: ChunkLocation,
 }
@@ -145,6 +158,7 @@ pub struct TravelProblem;
 
 pub type TravelNearProblem = PlayerProblem<BlockHeuristic, BlockNearGoalCheck>;
 pub type TravelBlockProblem = PlayerProblem<BlockHeuristic, BlockGoalCheck>;
+pub type TravelAnyOfProblem = PlayerProblem<BlockHeuristic, AnyOfGoalCheck>;
 pub type TravelChunkProblem = PlayerProblem<ChunkHeuristic, ChunkGoalCheck>;
 pub type TravelChunkCenterProblem = PlayerProblem<ChunkHeuristic, CenterChunkGoalCheck>;
 
@@ -163,6 +177,14 @@ impl TravelProblem {
         PlayerProblem::new(start_node, heuristic, goal_checker)
     }
 
+    /// to any of `goals`, which are around `center`
+    pub fn navigate_any_of(start: BlockLocation, center: BlockLocation, goals: impl IntoIterator<Item=BlockLocation>) -> TravelAnyOfProblem {
+        let heuristic = BlockHeuristic { move_cost: 1.0, goal: center };
+        let start_node = MoveNode::simple(start);
+        let goal_checker = AnyOfGoalCheck { goals: goals.into_iter().collect() };
+        PlayerProblem::new(start_node, heuristic, goal_checker)
+    }
+
     pub fn navigate_chunk(start: BlockLocation, goal: ChunkLocation) -> TravelChunkProblem {
         let heuristic = ChunkHeuristic::new(goal, 1.0);
         let start_node = MoveNode::simple(start);
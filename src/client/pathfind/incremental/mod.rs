@@ -190,7 +190,7 @@ impl<T: Node> AStar<T> {
             }
             let g_score = state.g_scores[&id];
             if g_score > MIN_DIST {
-                println!("larger than min dist");
+                debug!("larger than min dist");
                 let path = reconstruct_path(state.idx_to_record, id, &state.parent_map);
                 return Increment::Finished(PathResult::incomplete(path));
             }
@@ -212,7 +212,7 @@ impl<T: Node> AStar<T> {
                 let dur = &mut state.total_duration_ms;
                 *dur += iter_duration.as_millis();
                 return if *dur > state.max_duration_ms {
-                    println!("reached maxed duration");
+                    debug!("reached maxed duration");
                     return self.select_best();
                 } else {
                     Increment::InProgress
@@ -264,13 +264,18 @@ impl<T: Node> AStar<T> {
                 let tentative_g_score = parent_g_score + neighbor.cost;
 
                 let value = neighbor.value.clone();
-                let record = value.get_record();
+
+                // from the neighbor itself as cloning does not keep the action needed to obtain it
+                let record = neighbor.value.get_record();
 
                 let (record_idx, _g_score) = match state.record_to_idx.get(&record) {
                     Some(idx) => {
                         let prev_g_score = state.g_scores.get_mut(idx).unwrap();
                         if tentative_g_score < *prev_g_score {
-                            *prev_g_score = tentative_g_score
+                            *prev_g_score = tentative_g_score;
+
+                            // a better way of getting here might need a different action
+                            state.idx_to_record[*idx] = record;
                         } else {
                             continue 'neighbor_loop;
                         }
@@ -311,7 +316,7 @@ impl<T: Node> AStar<T> {
                 state.open_set.push(heap_node);
             }
         } else {
-            println!("no more nodes iterated through {}", state.idx_to_record.len());
+            debug!(nodes = state.idx_to_record.len(), "no more nodes");
             return self.select_best();
         }
 
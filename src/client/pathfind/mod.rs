@@ -22,6 +22,10 @@ pub mod moves;
 pub mod implementations;
 pub mod context;
 pub mod incremental;
+pub mod penalty;
+pub mod reach;
+pub mod flight;
+pub mod coarse;
 
 pub struct MinHeapNode<T, C: PartialOrd + PartialEq> {
     pub contents: T,
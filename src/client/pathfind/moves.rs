@@ -17,14 +17,14 @@
 
 
 
-use crate::client::pathfind::context::{GlobalContext, MoveNode};
+use crate::client::pathfind::context::{Action, GlobalContext, MoveNode};
 use crate::client::pathfind::moves::cenetered_arr::CenteredArray;
 use crate::client::pathfind::traits::{Neighbor, Progression};
+use crate::client::physics::{fall_damage, sprint};
 use crate::storage::block::{BlockLocation, SimpleType};
+use crate::storage::block::properties::Shape;
 use crate::storage::blocks::WorldBlocks;
 
-pub const MAX_FALL: i32 = 3;
-
 mod cenetered_arr;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -54,6 +54,13 @@ impl Movements {
         }
 
 
+        // standing in the block rather than on top of it (bottom slabs, closed bottom trapdoors)
+        macro_rules! stand_in {
+            ($x: expr, $y: expr, $z:expr) => {{
+                matches!(w.get_shape(BlockLocation::new($x,$y,$z)), Some(Shape::Bottom(_)))
+            }};
+        }
+
         // macro_rules! get_kind {
         //     ($x: expr, $y: expr, $z:expr) => {{
         //         let res: Option<BlockKind> = w.get_block_kind(BlockLocation::new($x,$y,$z));
@@ -61,6 +68,14 @@ impl Movements {
         //     }};
         // }
 
+        // jumps and falls are not allowed to start or end in the no jump area
+        macro_rules! may_jump {
+            ($block_loc: expr) => {{
+                let to: BlockLocation = $block_loc;
+                ctx.no_jump.map_or(true, |area| !area.contains(on.location) && !area.contains(to))
+            }};
+        }
+
         macro_rules! wrap {
             ($block_loc: expr) => {{
                 let mut node = MoveNode::from(&on);
@@ -88,6 +103,12 @@ impl Movements {
         // if adj_legs && adj_head is true for any idx
         let mut can_move_adj_noplace = [false; 4];
 
+        // if we would stand in the adjacent legs block
+        let mut adj_stand_in = [false; 4];
+
+        // if we are standing in our block our head is partially in the block above it
+        let raised = stand_in!(x, y, z);
+
         for (idx, direction) in CardinalDirection::ALL.iter().enumerate() {
             let Change { dx, dz, .. } = direction.unit_change();
 
@@ -98,7 +119,15 @@ impl Movements {
                 (Some(legs), Some(head)) => {
                     adj_legs[idx] = legs;
                     adj_head[idx] = head;
-                    can_move_adj_noplace[idx] = matches!(legs, WalkThrough | Water) && matches!(head, WalkThrough | Water);
+                    adj_stand_in[idx] = stand_in!(x + dx, y, z + dz);
+
+                    let clear = |block: SimpleType| matches!(block, WalkThrough | Water);
+                    let raised_clear = || get_block!(x + dx, y + 2, z + dz).map(clear).unwrap_or(false);
+
+                    let legs_clear = clear(legs) || adj_stand_in[idx];
+                    let needs_raised = raised || adj_stand_in[idx];
+
+                    can_move_adj_noplace[idx] = legs_clear && clear(head) && (!needs_raised || raised_clear());
                 }
                 _ => return Progression::Edge,
             };
@@ -114,7 +143,7 @@ impl Movements {
             let Change { dx, dz, .. } = direction.unit_change();
             if can_move_adj_noplace[idx] {
                 let floor = get_block!(x + dx, y - 1, z + dz).unwrap();
-                let walkable = floor == Solid || adj_legs[idx] == Water || adj_head[idx] == Water;
+                let walkable = floor == Solid || adj_stand_in[idx] || adj_legs[idx] == Water || adj_head[idx] == Water;
                 traverse_possible_no_place[idx] = walkable;
                 if walkable {
                     res.push(Neighbor {
@@ -132,16 +161,41 @@ impl Movements {
             let floor = get_block!(x + dx, y - 1, z + dz).unwrap();
             if can_move_adj_noplace[idx] && !traverse_possible_no_place[idx] && floor != Avoid {
                 let start = BlockLocation::new(x + dx, y, z + dz);
-                let collided_y = drop_y(start, w);
-                if let Some(collided_y) = collided_y {
-                    let new_pos = BlockLocation::new(x + dx, collided_y + 1, z + dz);
+                if let Some(landing) = drop_y(start, w) {
+                    let new_pos = BlockLocation::new(x + dx, landing.y + 1, z + dz);
+                    let costs = &ctx.path_config.costs;
+
+                    // water breaks any fall. Otherwise high falls need a water bucket placed on a full block.
+                    let safe = landing.on == Water || landing.height <= ctx.path_config.max_fall;
+                    let mlg = !safe && ctx.water_bucket && landing.on == Solid;
+
+                    if (safe || mlg) && may_jump!(new_pos) {
+                        let mut value = wrap!(new_pos);
+
+                        let cost = if mlg {
+                            value.action_to_obtain = Some(Action::Mlg(BlockLocation::new(x + dx, landing.y, z + dz)));
+                            costs.mlg
+                        } else if landing.on == Water {
+                            0.0
+                        } else {
+                            f64::from(fall_damage(f64::from(landing.height))) * costs.fall_damage
+                        };
 
-                    res.push(Neighbor {
-                        value: wrap!(new_pos),
-                        cost: ctx.path_config.costs.fall * multiplier,
-                    })
+                        res.push(Neighbor {
+                            value,
+                            cost: costs.fall * multiplier + cost,
+                        })
+                    }
                 }
             }
+
+            // stepping down into a bottom slab
+            if can_move_adj_noplace[idx] && !adj_stand_in[idx] && stand_in!(x + dx, y - 1, z + dz) {
+                res.push(Neighbor {
+                    value: wrap!(BlockLocation::new(x + dx, y - 1, z + dz)),
+                    cost: ctx.path_config.costs.fall * multiplier,
+                })
+            }
         }
 
         let above = get_block!(x, y + 2, z).unwrap();
@@ -163,7 +217,7 @@ impl Movements {
         }
 
 
-        let can_micro_jump = above == WalkThrough && (floor == Solid || feet == Water);
+        let can_micro_jump = above == WalkThrough && (floor == Solid || raised || feet == Water);
 
         if can_micro_jump {
             // ascending adjacent
@@ -173,7 +227,7 @@ impl Movements {
                 // we can only move if we couldn't move adjacent without changing elevation
                 if !can_move_adj_noplace[idx] {
                     let adj_above = matches!(get_block!(x+dx, y+2, z+dz).unwrap(), WalkThrough | Water);
-                    let can_jump = adj_above && adj_legs[idx] == Solid && matches!(adj_head[idx], WalkThrough | Water);
+                    let can_jump = adj_above && adj_legs[idx] == Solid && matches!(adj_head[idx], WalkThrough | Water) && may_jump!(BlockLocation::new(x+dx,y+1,z+dz));
                     if can_jump {
                         res.push(Neighbor {
                             value: wrap!(BlockLocation::new(x+dx,y+1,z+dz)),
@@ -279,9 +333,9 @@ impl Movements {
                     let rad2 = (dx * dx + dz * dz) as f64;
 
                     const MIN_RAD: f64 = 1.1;
-                    const MAX_RAD: f64 = 4.5;
+                    let max_rad = sprint::max_jump(ctx.sprint);
 
-                    if same_y_possible && rad2 <= MAX_RAD * MAX_RAD && rad2 >= MIN_RAD * MIN_RAD && is_open {
+                    if same_y_possible && rad2 <= max_rad * max_rad && rad2 >= MIN_RAD * MIN_RAD && is_open && may_jump!(BlockLocation::new(x+dx,y,z+dz)) {
                         res.push(Neighbor {
                             value: wrap!(BlockLocation::new(x+dx,y,z+dz)),
                             cost: ctx.path_config.costs.block_parkour * multiplier,
@@ -296,7 +350,19 @@ impl Movements {
     }
 }
 
-fn drop_y(start: BlockLocation, world: &WorldBlocks) -> Option<i16> {
+/// where dropping from a location ends up
+struct Landing {
+    /// the block landed on (or in for water and bottom slabs)
+    y: i16,
+
+    /// how many blocks are fallen
+    height: i32,
+
+    /// [SimpleType::Avoid] for a bottom slab
+    on: SimpleType,
+}
+
+fn drop_y(start: BlockLocation, world: &WorldBlocks) -> Option<Landing> {
     let BlockLocation { x, y: init_y, z } = start;
 
     // only falling we could do would be into the void
@@ -308,12 +374,14 @@ fn drop_y(start: BlockLocation, world: &WorldBlocks) -> Option<i16> {
     for y in (0..=(init_y - 2)).rev() {
         let loc = BlockLocation::new(x, y, z);
         let block_type = world.get_block_simple(loc).unwrap();
+        let landing = |y| Some(Landing { y, height: travelled, on: block_type });
         match block_type {
-            SimpleType::Solid => {
-                return (travelled <= MAX_FALL).then(|| y);
+            SimpleType::Solid | SimpleType::Water => {
+                return landing(y);
             }
-            SimpleType::Water => {
-                return Some(y);
+            // we land partially inside of the block (i.e., a bottom slab)
+            SimpleType::Avoid if matches!(world.get_shape(loc), Some(Shape::Bottom(_))) => {
+                return landing(y - 1);
             }
             SimpleType::Avoid => {
                 return None;
@@ -366,3 +434,67 @@ impl CardinalDirection {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::afk::Area;
+    use crate::client::pathfind::context::{Action, GlobalContext, MoveNode, PathConfig};
+    use crate::client::pathfind::moves::Movements;
+    use crate::client::pathfind::traits::{Neighbor, Progression};
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+
+    fn moves(world: &WorldBlocks, from: BlockLocation, no_jump: Option<Area>, water_bucket: bool) -> Vec<Neighbor<MoveNode>> {
+        let path_config = PathConfig::default();
+        let ctx = GlobalContext { path_config: &path_config, world, sprint: true, no_jump, water_bucket };
+        match Movements::obtain_all(&MoveNode::simple(from), &ctx) {
+            Progression::Edge => panic!("edge"),
+            Progression::Movements(neighbors) => neighbors,
+        }
+    }
+
+    fn neighbors(world: &WorldBlocks, no_jump: Option<Area>) -> Vec<BlockLocation> {
+        moves(world, BlockLocation::new(0, 1, 0), no_jump, false).into_iter().map(|neighbor| neighbor.value.location).collect()
+    }
+
+    /// a pillar `height` blocks above the floor and the drop off of it
+    fn drop(height: i16, water_bucket: bool) -> Option<Neighbor<MoveNode>> {
+        let mut world = WorldBlocks::flat();
+        for y in 1..=height {
+            world.set_block(BlockLocation::new(0, y, 0), BlockState::STONE);
+        }
+
+        moves(&world, BlockLocation::new(0, height + 1, 0), None, water_bucket).into_iter()
+            .find(|neighbor| neighbor.value.location == BlockLocation::new(1, 1, 0))
+    }
+
+    #[test]
+    fn test_no_jump() {
+        let mut world = WorldBlocks::flat();
+        world.set_block(BlockLocation::new(1, 1, 0), BlockState::STONE);
+
+        let all = neighbors(&world, None);
+        assert!(all.contains(&BlockLocation::new(1, 2, 0)));
+        assert!(all.iter().any(|location| location.x.abs() + location.z.abs() > 1), "no parkour");
+
+        // only walking is left
+        let field = Area::new(BlockLocation::new(-5, 1, -5), BlockLocation::new(5, 1, 5));
+        let walks = neighbors(&world, Some(field));
+        assert_eq!(walks.len(), 3);
+        assert!(walks.iter().all(|location| location.y == 1 && location.x.abs() + location.z.abs() == 1));
+    }
+
+    #[test]
+    fn test_fall_damage() {
+        let safe = drop(3, false).unwrap();
+        let hurts = drop(4, false).unwrap();
+        assert!(hurts.cost > safe.cost);
+        assert_eq!(hurts.value.action_to_obtain, None);
+
+        // too high without a water bucket
+        assert!(drop(5, false).is_none());
+
+        let mlg = drop(30, true).unwrap();
+        assert_eq!(mlg.value.action_to_obtain, Some(Action::Mlg(BlockLocation::new(1, 0, 0))));
+    }
+}
@@ -0,0 +1,94 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Extra path cost for blocks the bot got stuck at so a new path goes around them. The cost wears off as whatever was
+//! in the way may be gone by then (i.e., another bot).
+
+use std::collections::HashMap;
+
+use crate::storage::block::BlockLocation;
+
+struct Penalty {
+    cost: f64,
+
+    /// the tick the penalty was given
+    since: usize,
+
+    /// the ticks it takes to wear off
+    lasts: usize,
+}
+
+impl Penalty {
+    fn cost(&self, now: usize) -> f64 {
+        let elapsed = now.saturating_sub(self.since);
+        if elapsed >= self.lasts {
+            return 0.0;
+        }
+        self.cost * (1.0 - elapsed as f64 / self.lasts as f64)
+    }
+}
+
+/// The blocks moving into costs more. A penalty falls linearly to nothing.
+#[derive(Default)]
+pub struct Penalties {
+    blocks: HashMap<BlockLocation, Penalty>,
+}
+
+impl Penalties {
+    /// Make moving into `location` cost `cost` more for `lasts` ticks from `now`. Penalizing a block again adds to
+    /// what is left of its penalty.
+    pub fn add(&mut self, location: BlockLocation, cost: f64, now: usize, lasts: usize) {
+        let left = self.cost(location, now);
+        self.blocks.retain(|_, penalty| penalty.cost(now) > 0.0);
+        self.blocks.insert(location, Penalty { cost: left + cost, since: now, lasts });
+    }
+
+    /// the extra cost of moving into `location` at tick `now`
+    pub fn cost(&self, location: BlockLocation, now: usize) -> f64 {
+        self.blocks.get(&location).map_or(0.0, |penalty| penalty.cost(now))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::pathfind::penalty::Penalties;
+    use crate::storage::block::BlockLocation;
+
+    #[test]
+    fn test_decay() {
+        let mut penalties = Penalties::default();
+        let location = BlockLocation::new(3, 64, -2);
+        penalties.add(location, 20.0, 100, 200);
+
+        assert_eq!(penalties.cost(location, 100), 20.0);
+        assert_eq!(penalties.cost(location, 200), 10.0);
+        assert_eq!(penalties.cost(location, 300), 0.0);
+        assert_eq!(penalties.cost(BlockLocation::new(3, 65, -2), 100), 0.0);
+
+        // getting stuck again adds up
+        penalties.add(location, 20.0, 200, 200);
+        assert_eq!(penalties.cost(location, 200), 30.0);
+
+        // worn off penalties are forgotten
+        penalties.add(BlockLocation::default(), 1.0, 1000, 10);
+        penalties.add(BlockLocation::new(1, 0, 0), 1.0, 1010, 10);
+        assert_eq!(penalties.blocks.len(), 1);
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Where to stand to interact with a block
+
+use float_ord::FloatOrd;
+
+use crate::client::formation::standable;
+use crate::protocol::Face;
+use crate::storage::block::BlockLocation;
+use crate::storage::blocks::WorldBlocks;
+use crate::types::Displacement;
+
+/// Where the bot can stand (the block its feet are in) to reach `target` and the face of `target` it would use from
+/// there, the closest to `target` first. The face has to be within `reach` (at most
+/// [`REACH`](crate::storage::blocks::raycast::REACH)) and visible. Standing on `target` is left out since breaking
+/// it would drop us.
+pub fn reach_positions(target: BlockLocation, world: &WorldBlocks, reach: f64) -> impl Iterator<Item=(BlockLocation, Face)> {
+    let horizontal = reach.ceil() as i32;
+    let vertical = (reach + Displacement::EYE_HEIGHT.dy).ceil() as i16;
+
+    let mut positions: Vec<_> = (-horizontal..=horizontal)
+        .flat_map(|dx| (-horizontal..=horizontal).map(move |dz| (dx, dz)))
+        .flat_map(|(dx, dz)| (-vertical..=vertical).map(move |dy| BlockLocation::new(target.x + dx, target.y + dy, target.z + dz)))
+        .filter(|&feet| feet != target && feet.above() != target && feet.below() != target)
+        .filter(|&feet| standable(world, feet))
+        .filter_map(|feet| {
+            let eye = feet.center_bottom() + Displacement::EYE_HEIGHT;
+            world.aim(eye, target).ok()
+                .filter(|aim| aim.point.dist2(eye) <= reach * reach)
+                .map(|aim| (feet, aim.face))
+        })
+        .collect();
+
+    positions.sort_by_key(|(feet, _)| (FloatOrd(feet.dist2(target)), feet.x, feet.y, feet.z));
+    positions.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::pathfind::reach::reach_positions;
+    use crate::protocol::Face;
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::storage::blocks::raycast::REACH;
+
+    /// a floor at y = 0 with a block of stone on top of it around the origin
+    fn buried() -> WorldBlocks {
+        let mut world = WorldBlocks::flat();
+        for x in -6..=6 {
+            for z in -6..=6 {
+                for y in 1..=6 {
+                    world.set_block(BlockLocation::new(x, y, z), BlockState::STONE);
+                }
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn test_trench() {
+        // a trench three blocks long with the target in the middle of its floor
+        let mut world = buried();
+        for x in -1..=1 {
+            for y in 1..=6 {
+                world.set_block(BlockLocation::new(x, y, 0), BlockState::AIR);
+            }
+        }
+
+        let target = BlockLocation::new(0, 0, 0);
+        let positions: Vec<_> = reach_positions(target, &world, REACH).collect();
+        assert_eq!(positions, vec![
+            (BlockLocation::new(-1, 1, 0), Face::PosY),
+            (BlockLocation::new(1, 1, 0), Face::PosY),
+        ]);
+    }
+
+    #[test]
+    fn test_enclosed() {
+        let world = buried();
+        assert_eq!(reach_positions(BlockLocation::new(0, 3, 0), &world, REACH).count(), 0);
+
+        // the top of the stone can be reached from on top of it
+        let (feet, face) = reach_positions(BlockLocation::new(0, 6, 0), &world, REACH).next().unwrap();
+        assert_eq!(feet.y, 7);
+        assert_eq!(face, Face::PosY);
+    }
+
+    #[test]
+    fn test_reach() {
+        let mut world = WorldBlocks::flat();
+        let target = BlockLocation::new(0, 1, 0);
+        world.set_block(target, BlockState::STONE);
+
+        let near: Vec<_> = reach_positions(target, &world, 2.0).collect();
+        let far: Vec<_> = reach_positions(target, &world, REACH).collect();
+        assert!(!near.is_empty());
+        assert!(near.len() < far.len());
+
+        // closest first
+        assert!(far.windows(2).all(|pair| pair[0].0.dist2(target) <= pair[1].0.dist2(target)));
+        assert_eq!(far[0].0.dist2(target), 1.0);
+    }
+}
@@ -0,0 +1,252 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Who may command the bots through chat. Without a permissions file anyone can, like before. With one, a sender needs
+//! a level at least as high as the command they use.
+
+use std::collections::HashMap;
+use std::fs;
+
+use crate::chat::ChatKind;
+use crate::client::bot::chained;
+use crate::error::{err, Res};
+
+/// a refused player is told so at most once in this many ticks (30 seconds), however many bots see the command
+const REFUSAL_TICKS: usize = 20 * 30;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Level {
+    /// commands which only report (`#status`, `#loc`, ...)
+    Status,
+
+    /// commands which move the bots or stop them (`#goto`, `#stop`, ...)
+    Movement,
+
+    /// everything else: fighting, breaking and placing blocks, dropping items, scaling the swarm
+    Admin,
+}
+
+impl Level {
+    /// The level needed to use `command`. Commands not listed need [`Level::Admin`]. Chaining commands with `#do`
+    /// needs the highest level of the chained commands.
+    pub fn required(command: &str, args: &[&str]) -> Level {
+        match command {
+            "do" => chained(args).iter()
+                .map(|words| Level::required(words[0], &words[1..]))
+                .max()
+                .unwrap_or(Level::Status),
+            "health" | "status" | "players" | "online" | "stats" | "chunks" | "scan" | "loc" | "state" | "proxies"
+            | "estimate" | "bases" | "kicks" | "get" | "queue" | "signs" | "find" => Level::Status,
+            "follow" | "follow-leader" | "goto" | "stop" | "eat" | "slot" | "fly" | "afk" | "refresh" | "skip"
+            | "clear-queue" | "read-signs" | "bed" | "travel" | "light-up" => Level::Movement,
+            "waypoint" => match args {
+                ["list"] => Level::Status,
+                _ => Level::Movement,
+            },
+            _ => Level::Admin,
+        }
+    }
+
+    fn parse(level: &str) -> Option<Level> {
+        match level {
+            "status" => Some(Level::Status),
+            "movement" => Some(Level::Movement),
+            "admin" => Some(Level::Admin),
+            _ => None
+        }
+    }
+}
+
+/// a username (lowercase, as names are case insensitive) or a uuid
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+enum Who {
+    Name(String),
+    Uuid(u128),
+}
+
+impl Who {
+    fn parse(who: &str) -> Who {
+        let hex = who.replace('-', "");
+        match u128::from_str_radix(&hex, 16) {
+            Ok(uuid) if hex.len() == 32 => Who::Uuid(uuid),
+            _ => Who::Name(who.to_lowercase()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Access {
+    Allowed,
+
+    /// the command is dropped without answering, i.e., it was not whispered or we do not know who sent it
+    Ignored,
+
+    /// the sender is known but their level is too low
+    Denied,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Permissions {
+    /// the level of every authorized player. None if anyone can use every command.
+    users: Option<HashMap<Who, Level>>,
+
+    /// only accept commands which are whispered to the bot
+    whisper_only: bool,
+
+    /// whisper a refusal to players who are denied a command
+    refuse: bool,
+
+    /// the tick each player was last refused at
+    refused: HashMap<String, usize>,
+}
+
+impl Permissions {
+    /// Every line is `<username or uuid>: <status|movement|admin>`, `whisper-only: <true|false>` or
+    /// `refuse: <true|false>`. Empty lines and lines starting with `#` are ignored.
+    pub fn load(path: &str) -> Res<Permissions> {
+        Permissions::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(file: &str) -> Res<Permissions> {
+        let mut permissions = Permissions::default();
+        let users = permissions.users.get_or_insert_default();
+
+        for line in file.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (key, value) = line.split_once(':').ok_or_else(|| err(&format!("expected <player>: <level> but got {}", line)))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "whisper-only" => permissions.whisper_only = value.parse().map_err(|_| err(&format!("invalid bool in {}", line)))?,
+                "refuse" => permissions.refuse = value.parse().map_err(|_| err(&format!("invalid bool in {}", line)))?,
+                who => {
+                    let level = Level::parse(value).ok_or_else(|| err(&format!("invalid level in {}", line)))?;
+                    users.insert(Who::parse(who), level);
+                }
+            }
+        }
+
+        Ok(permissions)
+    }
+
+    /// Whether `sender` may use `command`. `uuid` is the uuid of the sender according to the player list. Commands of
+    /// senders without a uuid are ignored when permissions are set as anyone could be using the name.
+    pub fn check(&self, sender: &str, uuid: Option<u128>, kind: ChatKind, command: &str, args: &[&str]) -> Access {
+        if self.whisper_only && kind != ChatKind::Whisper {
+            return Access::Ignored;
+        }
+
+        let users = match self.users.as_ref() {
+            None => return Access::Allowed,
+            Some(users) => users
+        };
+
+        let uuid = match uuid {
+            None => return Access::Ignored,
+            Some(uuid) => uuid
+        };
+
+        let level = [Who::Uuid(uuid), Who::Name(sender.to_lowercase())].iter()
+            .filter_map(|who| users.get(who))
+            .max();
+
+        match level {
+            Some(&level) if level >= Level::required(command, args) => Access::Allowed,
+            _ => Access::Denied
+        }
+    }
+
+    /// Whether to whisper a refusal to `sender` at tick `ticks`. Only true once every [`REFUSAL_TICKS`] for each
+    /// player so a spammer cannot make the bots spam back.
+    pub fn refusal(&mut self, sender: &str, ticks: usize) -> bool {
+        if !self.refuse {
+            return false;
+        }
+
+        match self.refused.get(sender) {
+            Some(&last) if ticks < last + REFUSAL_TICKS => false,
+            _ => {
+                self.refused.insert(sender.to_string(), ticks);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::ChatKind;
+    use crate::client::permissions::{Access, Level, Permissions};
+
+    const NOTCH: u128 = 0x069a79f444e94726a5befca90e38aaf5;
+
+    #[test]
+    fn test_check() {
+        let open = Permissions::default();
+        assert_eq!(open.check("anyone", None, ChatKind::Public, "fight", &[]), Access::Allowed);
+
+        let permissions = Permissions::parse("
+            # the owner
+            069a79f4-44e9-4726-a5be-fca90e38aaf5: admin
+            Friend: movement
+            whisper-only: false
+        ").unwrap();
+
+        assert_eq!(permissions.check("Notch", Some(NOTCH), ChatKind::Public, "fight", &[]), Access::Allowed);
+
+        // someone else using the name
+        assert_eq!(permissions.check("Notch", Some(1), ChatKind::Public, "fight", &[]), Access::Denied);
+
+        assert_eq!(permissions.check("friend", Some(2), ChatKind::Public, "goto", &[]), Access::Allowed);
+        assert_eq!(permissions.check("friend", Some(2), ChatKind::Public, "status", &[]), Access::Allowed);
+        assert_eq!(permissions.check("friend", Some(2), ChatKind::Public, "drop", &[]), Access::Denied);
+        assert_eq!(permissions.check("griefer", Some(3), ChatKind::Public, "status", &[]), Access::Denied);
+
+        // not in the player list
+        assert_eq!(permissions.check("friend", None, ChatKind::Public, "goto", &[]), Access::Ignored);
+
+        assert!(Permissions::parse("Notch: owner").is_err());
+    }
+
+    #[test]
+    fn test_do() {
+        assert_eq!(Level::required("do", &["goto", "0", "64", "0;", "status"]), Level::Movement);
+        assert_eq!(Level::required("do", &["goto", "0", "64", "0", ";", "mine", "1", "2", "3"]), Level::Admin);
+        assert_eq!(Level::required("do", &["do", "queue;loc"]), Level::Status);
+        assert_eq!(Level::required("do", &["waypoint", "list;", "waypoint", "set", "base"]), Level::Movement);
+
+        let permissions = Permissions::parse("Friend: movement").unwrap();
+        assert_eq!(permissions.check("friend", Some(2), ChatKind::Public, "do", &["goto", "0", "64", "0", ";", "skip"]), Access::Allowed);
+        assert_eq!(permissions.check("friend", Some(2), ChatKind::Public, "do", &["goto", "0", "64", "0", ";", "drop"]), Access::Denied);
+    }
+
+    #[test]
+    fn test_whisper_only() {
+        let permissions = Permissions::parse("whisper-only: true\nFriend: admin").unwrap();
+        assert_eq!(permissions.check("Friend", Some(2), ChatKind::Public, "stop", &[]), Access::Ignored);
+        assert_eq!(permissions.check("Friend", Some(2), ChatKind::Whisper, "stop", &[]), Access::Allowed);
+    }
+
+    #[test]
+    fn test_refusal() {
+        let mut permissions = Permissions::parse("refuse: true").unwrap();
+        assert!(permissions.refusal("griefer", 100));
+        assert!(!permissions.refusal("griefer", 101));
+        assert!(permissions.refusal("spammer", 101));
+        assert!(permissions.refusal("griefer", 100 + 20 * 30));
+
+        assert!(!Permissions::default().refusal("griefer", 0));
+    }
+}
@@ -21,20 +21,27 @@ use float_ord::FloatOrd;
 use itertools::Itertools;
 use num::traits::Pow;
 
+use crate::client::physics::rotation::{Rotation, RotationConfig};
 use crate::client::physics::speed::Speed;
+use crate::client::physics::sprint::SprintController;
+use crate::client::state::local::effects::{Effect, Effects};
 use crate::client::state::local::inventory::PlayerInventory;
 use crate::protocol::Face;
 use crate::storage::block::{BlockApprox, BlockKind, BlockLocation, BlockState, SimpleType};
+use crate::storage::block::properties::Shape;
 use crate::storage::blocks::WorldBlocks;
 use crate::types::{Direction, Displacement, Location};
 
 pub mod tools;
 pub mod speed;
+pub mod sprint;
+pub mod rotation;
 
 const JUMP_UPWARDS_MOTION: f64 = 0.42;
 const WATER_JUMP_UPWARDS: f64 = 0.04;
 
-const SPRINT_SPEED: f64 = 0.2806;
+/// blocks a tick sprinting on flat ground
+pub const SPRINT_SPEED: f64 = 0.2806;
 const WALK_SPEED: f64 = 0.21585;
 const SWIM_SPEED: f64 = 0.11;
 
@@ -53,6 +60,9 @@ const WATER_DECEL: f64 = 0.2;
 
 const DRAG_MULT: f64 = 0.98; // 00000190734863;
 
+/// the part of the horizontal velocity kept each tick, times the slip of the block below (1.0 in the air)
+const MOMENTUM: f64 = 0.91;
+
 // player width divided by 2
 const PLAYER_WIDTH_2: f64 = 0.6 / 2.0;// + 0.001;
 // const PLAYER_WIDTH_2_REG: f64 = 0.6 / 2.0;
@@ -64,17 +74,23 @@ const PLAYER_HEIGHT_Y: Displacement = Displacement::new(0., PLAYER_HEIGHT, 0.);
 const UNIT_Y: Displacement = Displacement::new(0., 1., 0.);
 const EPSILON_Y: Displacement = Displacement::new(0., 0.001, 0.);
 
+/// how high a block can be for the player to walk onto it without jumping
+const STEP_HEIGHT: f64 = 0.6;
+
 #[derive(Debug, Default)]
 struct Pending {
     strafe: Option<Strafe>,
     pub place: Option<BlockPlaced>,
     jump: bool,
+
+    /// go down while flying (vanilla sneaks for this)
+    descend: bool,
     line: Option<Line>,
     speed: Speed,
 }
 
 fn effects_multiplier(speed: f64, slowness: f64) -> f64 {
-    (1.0 + 0.2 * speed) * (1. - 0.15 * slowness)
+    ((1.0 + 0.2 * speed) * (1. - 0.15 * slowness)).max(0.0)
 }
 
 fn initial_ver(jump_boost: usize) -> f64 {
@@ -101,6 +117,11 @@ fn jump_speed(prev_speed: f64, prev_slip: f64, move_mult: f64, effect_mult: f64,
     ground_speed(prev_speed, prev_slip, move_mult, effect_mult, slip) + jump_sprint_boost
 }
 
+/// the damage (in half hearts) vanilla does for landing after falling `distance` blocks
+pub fn fall_damage(distance: f64) -> f32 {
+    (distance - 3.0).ceil().max(0.0) as f32
+}
+
 fn air_speed(prev_speed: f64, prev_slip: f64, move_mult: f64) -> f64 {
     let momentum = prev_speed * prev_slip * 0.91;
     let acc = 0.02 * move_mult;
@@ -133,16 +154,45 @@ impl Default for MovementState {
 pub struct BlockPlaced {
     pub location: BlockLocation,
     pub face: Face,
+
+    /// the block the placed block went in place of. None until placed or if it was not known.
+    pub replaced: Option<BlockState>,
 }
 
 pub struct Actions {
     pub block_placed: Option<BlockPlaced>,
+
+    /// the damage we would take from landing this tick. The server decides the actual damage.
+    pub fall_damage: f32,
 }
 
 fn threshold(value: f64) -> f64 {
     value
 }
 
+/// the levels of the effects which change how we move
+#[derive(Debug, Default, Copy, Clone)]
+struct MoveEffects {
+    speed: u8,
+    slowness: u8,
+    jump_boost: u8,
+}
+
+/// flying like in creative mode
+#[derive(Debug)]
+struct Flight {
+    active: bool,
+
+    /// how fast the server lets us fly (the fly speed of Player Abilities)
+    speed: f64,
+}
+
+impl Default for Flight {
+    fn default() -> Self {
+        Flight { active: false, speed: 0.05 }
+    }
+}
+
 /// # Purpose
 /// Used to simulate a player position. Takes in movement events (such as jumping and strafing) and allows polling the resulting player data---for instance location.
 /// # Resources
@@ -151,10 +201,29 @@ fn threshold(value: f64) -> f64 {
 pub struct Physics {
     location: Location,
     look: Direction,
+
+    /// where tasks want to look. [Physics::look] follows it at a human speed.
+    rotation: Rotation,
     prev: MovementState,
     horizontal: Displacement,
     pending: Pending,
     in_water: bool,
+
+    /// flying with an elytra
+    gliding: bool,
+
+    /// how many more ticks a firework pushes us
+    boost_ticks: u32,
+
+    flight: Flight,
+
+    /// how far we have fallen since last on the ground or in water
+    fall_distance: f64,
+
+    /// unlike other movement this stays set until changed
+    sneaking: bool,
+    sprint: SprintController,
+    effects: MoveEffects,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -198,28 +267,183 @@ impl Physics {
         self.location.y += 0.001;
         self.prev = MovementState::default();
         self.prev.falling = true;
+        self.fall_distance = 0.0;
+    }
+
+    /// move faster or slower and jump higher with the effects we have
+    pub fn set_effects(&mut self, effects: &Effects) {
+        self.effects = MoveEffects {
+            speed: effects.level(Effect::Speed),
+            slowness: effects.level(Effect::Slowness),
+            jump_boost: effects.level(Effect::JumpBoost),
+        };
     }
 
     pub fn jump(&mut self) {
         self.pending.jump = true;
     }
 
+    /// add to the velocity, e.g., when an explosion knocks us back
+    pub fn push(&mut self, velocity: Displacement) {
+        self.prev.speeds[0] += velocity.dx;
+        self.prev.speeds[1] += velocity.dz;
+        self.prev.y_vel += velocity.dy;
+
+        // otherwise the vertical velocity is dropped while on the ground
+        if velocity.dy > 0.0 {
+            self.prev.falling = true;
+        }
+    }
+
+    /// Set the velocity like vanilla does when the server sends one (i.e., knockback). The next tick moves by
+    /// `velocity` and drag and gravity slow it down after.
+    pub fn set_velocity(&mut self, velocity: Displacement) {
+        // a tick applies momentum and gravity to the previous velocity before moving, so that is undone here
+        self.prev.slip = 1.0;
+        self.prev.speeds = [velocity.dx / MOMENTUM, velocity.dz / MOMENTUM];
+
+        if velocity.dy > 0.0 || self.prev.falling {
+            self.prev.y_vel = velocity.dy / DRAG_MULT + ACC_G;
+            self.prev.falling = true;
+        } else {
+            self.prev.y_vel = 0.0;
+        }
+    }
+
+    /// Start flying with an elytra. Like vanilla this only works while falling and out of water. Whether an elytra is
+    /// worn is up to the caller, who also has to tell the server.
+    pub fn start_gliding(&mut self) -> bool {
+        if self.prev.falling && !self.in_water && !self.flight.active {
+            self.gliding = true;
+        }
+        self.gliding
+    }
+
+    pub fn stop_gliding(&mut self) {
+        self.gliding = false;
+        self.boost_ticks = 0;
+    }
+
+    pub fn gliding(&self) -> bool {
+        self.gliding
+    }
+
+    /// a firework was used while gliding. It pulls us in the look direction for `ticks` ticks.
+    pub fn boost(&mut self, ticks: u32) {
+        if self.gliding {
+            self.boost_ticks = ticks;
+        }
+    }
+
+    pub fn boosting(&self) -> bool {
+        self.boost_ticks > 0
+    }
+
+    /// Fly like in creative mode: no gravity, [`Physics::jump`] goes up and [`Physics::descend`] goes down. Whether
+    /// the server lets us is up to the caller, who also has to tell the server.
+    pub fn start_flying(&mut self) {
+        self.stop_gliding();
+        self.flight.active = true;
+        self.prev.falling = true;
+        self.fall_distance = 0.0;
+    }
+
+    /// gravity takes over again. Like vanilla the fall is counted from here.
+    pub fn stop_flying(&mut self) {
+        self.flight.active = false;
+    }
+
+    pub fn flying(&self) -> bool {
+        self.flight.active
+    }
+
+    pub fn set_fly_speed(&mut self, speed: f32) {
+        self.flight.speed = speed as f64;
+    }
+
+    /// go down this tick while flying
+    pub fn descend(&mut self) {
+        self.pending.descend = true;
+    }
+
     /// if the bot is at the highest location of a jump
     pub fn at_apex(&self) -> bool {
         self.prev.falling && self.prev.y_vel >= 0.0 && self.prev.y_vel - ACC_G < 0.0
     }
 
+    /// turn towards `direction`. Unless [Physics::set_rotation] was called this happens right away.
     pub fn look(&mut self, direction: Direction) {
+        self.rotation.target(direction);
+        self.turned(self.rotation.current());
+    }
+
+    /// look in `direction` right away no matter how fast the head is allowed to turn
+    pub fn force_look(&mut self, direction: Direction) {
+        self.rotation.force(direction);
+        self.turned(direction);
+    }
+
+    fn turned(&mut self, direction: Direction) {
         self.look = direction;
         self.horizontal = direction.horizontal().unit_vector();
     }
 
+    /// limit how fast the head turns
+    pub fn set_rotation(&mut self, config: RotationConfig) {
+        self.rotation.set_config(config);
+    }
+
+    /// see [Rotation::seed]
+    pub fn seed(&mut self, seed: u64) {
+        self.rotation.seed(seed);
+    }
+
+    /// whether the head is turned close enough to where it should look to mine, place or attack
+    pub fn aimed(&self) -> bool {
+        self.rotation.aimed()
+    }
+
     pub fn look_at(&mut self, loc: Location) {
-        let current = self.location + Displacement::EYE_HEIGHT;
+        let current = self.location + self.eye_height();
         let displacement = loc - current;
         self.look(displacement.into());
     }
 
+    /// A sneaking player moves at 30% speed, cannot sprint and cannot walk off the edge of a block
+    pub fn set_sneaking(&mut self, sneaking: bool) {
+        self.sneaking = sneaking;
+    }
+
+    pub fn sneaking(&self) -> bool {
+        self.sneaking
+    }
+
+    /// whether the player is actually sprinting. Asking for [`Speed::SPRINT`] is not enough, see [`SprintController`].
+    pub fn sprinting(&self) -> bool {
+        self.sprint.sprinting()
+    }
+
+    /// whether a task asking to sprint would be allowed to. Used to plan jumps only sprinting can make.
+    pub fn can_sprint(&self) -> bool {
+        self.sprint.can_sprint(self.sneaking)
+    }
+
+    pub fn set_food(&mut self, food: u8) {
+        self.sprint.set_food(food);
+    }
+
+    pub fn set_using_item(&mut self, using_item: bool) {
+        self.sprint.set_using_item(using_item);
+    }
+
+    pub fn eye_height(&self) -> Displacement {
+        if self.sneaking {
+            Displacement::SNEAK_EYE_HEIGHT
+        } else {
+            Displacement::EYE_HEIGHT
+        }
+    }
+
     pub fn direction(&self) -> Direction {
         self.look
     }
@@ -260,12 +484,13 @@ impl Physics {
         self.pending.place = Some(BlockPlaced {
             location: against,
             face,
+            replaced: None,
         });
     }
 
     pub fn place_hand(&mut self, against: BlockLocation) {
         let faces = against.faces();
-        let eye_loc = self.location + Displacement::EYE_HEIGHT;
+        let eye_loc = self.location + self.eye_height();
         let face_idx = IntoIterator::into_iter(faces).position_min_by_key(|&location| FloatOrd(location.dist2(eye_loc))).unwrap();
 
         let face = Face::from(face_idx as u8);
@@ -304,45 +529,354 @@ impl Physics {
         }
     }
 
-    pub fn cross_section_empty(&self, loc: Location, world: &WorldBlocks) -> bool {
+    /// The (bottom, top) of every block shape a corner of the cross-section at `loc` is in
+    fn collisions_at(&self, loc: Location, world: &WorldBlocks) -> Vec<(f64, f64)> {
         let dif_x = [-PLAYER_WIDTH_2, PLAYER_WIDTH_2];
         let dif_z = [-PLAYER_WIDTH_2, PLAYER_WIDTH_2];
 
+        let mut collisions = Vec::new();
         for dx in dif_x {
             for dz in dif_z {
                 let test_loc = loc + Displacement::new(dx, 0., dz);
                 let test_block_loc = BlockLocation::from(test_loc);
-                let solid = matches!(world.get_block_simple(test_block_loc), Some(SimpleType::Solid));
-                if solid {
-                    return false;
+                let range = world.get_block(test_block_loc).and_then(|block| block.shape()).and_then(Shape::range);
+                if let Some((min, max)) = range {
+                    let y = test_block_loc.y as f64;
+                    if min <= loc.y - y && loc.y - y < max {
+                        collisions.push((y + min, y + max));
+                    }
+                }
+            }
+        }
+        collisions
+    }
+
+    pub fn cross_section_empty(&self, loc: Location, world: &WorldBlocks) -> bool {
+        self.collisions_at(loc, world).is_empty()
+    }
+
+    /// the height of the highest block surface below `loc` within the cross-section
+    fn ground_height(&self, loc: Location, world: &WorldBlocks) -> Option<f64> {
+        self.collisions_at(loc, world).into_iter().map(|(_, top)| FloatOrd(top)).max().map(|top| top.0)
+    }
+
+    /// Add the blocks the body of a player standing at `loc` is in. Blocks we avoid without knowing their shape
+    /// always count.
+    fn blocked_sideways(&self, loc: Location, world: &WorldBlocks, set: &mut HashSet<BlockLocation>) {
+        let dif_x = [-PLAYER_WIDTH_2, PLAYER_WIDTH_2];
+        let dif_z = [-PLAYER_WIDTH_2, PLAYER_WIDTH_2];
+
+        let from = loc.y + EPSILON_Y.dy;
+        let to = loc.y + PLAYER_HEIGHT;
+
+        for dx in dif_x {
+            for dz in dif_z {
+                let feet = BlockLocation::from(loc + EPSILON_Y + Displacement::new(dx, 0., dz));
+                let head = BlockLocation::from(loc + PLAYER_HEIGHT_Y + Displacement::new(dx, 0., dz));
+
+                for y in feet.y..=head.y {
+                    let test_block_loc = BlockLocation::new(feet.x, y, feet.z);
+                    let collides = match world.get_block(test_block_loc) {
+                        None => false,
+                        Some(block) => block.shape().map_or(true, |shape| shape.overlaps(from - y as f64, to - y as f64)),
+                    };
+                    if collides {
+                        set.insert(test_block_loc);
+                    }
                 }
             }
         }
-        true
+    }
+
+    /// if the body of a player standing at `loc` would be inside blocks they collide with
+    pub fn inside_blocks(&self, loc: Location, world: &WorldBlocks) -> bool {
+        let mut blocked = HashSet::new();
+        self.blocked_sideways(loc, world, &mut blocked);
+        blocked.into_iter().any(|block| world.get_shape(block).is_some())
+    }
+
+    /// The height to step up to if the player walked into `blocked` at `loc` and all of them are low enough to
+    /// walk onto (i.e., slabs)
+    fn step_height(&self, loc: Location, blocked: &HashSet<BlockLocation>, world: &WorldBlocks) -> Option<f64> {
+        let mut step = loc.y;
+        for &block in blocked {
+            let (_, top) = world.get_block(block)?.shape()?.range()?;
+            step = step.max(block.y as f64 + top);
+        }
+
+        if step - loc.y > STEP_HEIGHT {
+            return None;
+        }
+
+        let mut stepped = loc;
+        stepped.y = step;
+
+        let mut still_blocked = HashSet::new();
+        self.blocked_sideways(stepped, world, &mut still_blocked);
+        still_blocked.is_empty().then(|| step)
+    }
+
+    /// Shorten the horizontal move so there is still ground below the player afterwards. This is how vanilla stops
+    /// a sneaking player from walking off an edge. Drops smaller than a block do not count as an edge.
+    fn clamp_to_edge(&self, loc: Location, mut speeds: [f64; 2], world: &WorldBlocks) -> [f64; 2] {
+        const STEP: f64 = 0.05;
+
+        let shorten = |speed: &mut f64| {
+            if speed.abs() < STEP {
+                *speed = 0.0;
+            } else {
+                *speed -= STEP * speed.signum();
+            }
+        };
+
+        let unsupported = |dx: f64, dz: f64| self.cross_section_empty(loc + Displacement::new(dx, 0., dz) - EPSILON_Y, world);
+
+        while speeds[0] != 0.0 && unsupported(speeds[0], 0.0) {
+            shorten(&mut speeds[0]);
+        }
+
+        while speeds[1] != 0.0 && unsupported(0.0, speeds[1]) {
+            shorten(&mut speeds[1]);
+        }
+
+        while speeds[0] != 0.0 && speeds[1] != 0.0 && unsupported(speeds[0], speeds[1]) {
+            shorten(&mut speeds[0]);
+            shorten(&mut speeds[1]);
+        }
+
+        speeds
+    }
+
+    /// (strafe, forward) like the movement keys pressed this tick
+    fn keys(&self) -> (f64, f64) {
+        let strafe = match self.pending.strafe {
+            None => 0.0,
+            Some(Strafe::Right) => 1.0,
+            Some(Strafe::Left) => -1.0,
+        };
+
+        let line = match self.pending.line {
+            None => 0.0,
+            Some(Line::Forward) => 1.0,
+            Some(Line::Backward) => -1.0,
+        };
+
+        (strafe, line)
+    }
+
+    /// Creative flight. Follows `EntityPlayer#travel` of 1.12: the movement keys accelerate by the fly speed (twice it
+    /// when sprinting) and 60% of the vertical speed is kept each tick.
+    fn tick_flying(&mut self, world: &WorldBlocks) -> Actions {
+        let forward = matches!(self.pending.line, Some(Line::Forward));
+        let sprinting = self.sprint.update(self.pending.speed == Speed::SPRINT, forward, self.sneaking);
+
+        let (strafe, line) = self.keys();
+        let speed = if sprinting { self.flight.speed * 2.0 } else { self.flight.speed };
+        let factor = speed * Speed::WALK.multiplier();
+        let [strafe_change, forward_change] = mot_xz(strafe, line, factor);
+
+        let horizontal = self.horizontal;
+        let sideway = horizontal.cross(UNIT_Y);
+
+        let [prev_x, prev_z] = self.prev.speeds;
+        let mut x = prev_x * MOMENTUM + horizontal.dx * forward_change + sideway.dx * strafe_change;
+        let mut z = prev_z * MOMENTUM + horizontal.dz * forward_change + sideway.dz * strafe_change;
+
+        let mut y = self.prev.y_vel * 0.6;
+        if self.pending.jump {
+            y += self.flight.speed * 3.0;
+        }
+        if self.pending.descend {
+            y -= self.flight.speed * 3.0;
+        }
+
+        let mut just_hit_ground = false;
+        let mut new_loc = self.location + Displacement::new(0., y, 0.);
+
+        if y < 0.0 {
+            if let Some(ground) = self.ground_height(new_loc - EPSILON_Y, world) {
+                new_loc.y = ground;
+                y = 0.0;
+                just_hit_ground = true;
+            }
+        } else {
+            let head_loc = new_loc + EPSILON_Y + PLAYER_HEIGHT_Y;
+            let ceiling = self.collisions_at(head_loc, world).into_iter().map(|(bottom, _)| FloatOrd(bottom)).min();
+            if let Some(FloatOrd(ceiling)) = ceiling {
+                new_loc.y = ceiling - PLAYER_HEIGHT - 0.0001;
+                y = 0.0;
+            }
+        }
+
+        // slide along walls instead of stopping dead
+        let stop_x = self.inside_blocks(new_loc + Displacement::new(x, 0., 0.), world);
+        if stop_x {
+            x = 0.0;
+        }
+
+        let stop_z = self.inside_blocks(new_loc + Displacement::new(x, 0., z), world);
+        if stop_z {
+            z = 0.0;
+        }
+        self.sprint.collided(stop_x || stop_z);
+
+        new_loc.x += x;
+        new_loc.z += z;
+
+        self.location = new_loc;
+        self.in_water = world.get_block_simple(BlockLocation::from(new_loc)) == Some(SimpleType::Water);
+
+        // like vanilla touching the ground ends the flight
+        if just_hit_ground {
+            self.flight.active = false;
+        }
+
+        let actions = Actions { block_placed: self.pending.place.take(), fall_damage: 0.0 };
+
+        self.pending = Pending::default();
+        self.prev = MovementState {
+            speeds: [x, z],
+            y_vel: y,
+            just_hit_ground,
+            slip: BlockKind::DEFAULT_SLIP,
+            falling: !just_hit_ground,
+        };
+
+        // flying does not build up a fall
+        self.fall_distance = 0.0;
+
+        actions
+    }
+
+    /// Elytra flight. Only the look direction steers, movement keys do nothing. Follows `EntityLivingBase#travel` (and
+    /// `EntityFireworkRocket#onUpdate` for boosts) of 1.12.
+    fn tick_gliding(&mut self, world: &WorldBlocks) -> Actions {
+        let look = self.look.unit_vector();
+        let pitch = (self.look.pitch as f64).to_radians();
+
+        let [mut x, mut z] = self.prev.speeds;
+        let mut y = self.prev.y_vel;
+
+        if self.boost_ticks > 0 {
+            self.boost_ticks -= 1;
+            x += look.dx * 0.1 + (look.dx * 1.5 - x) * 0.5;
+            y += look.dy * 0.1 + (look.dy * 1.5 - y) * 0.5;
+            z += look.dz * 0.1 + (look.dz * 1.5 - z) * 0.5;
+        }
+
+        let look_horizontal = look.dx.hypot(look.dz);
+        let speed_horizontal = x.hypot(z);
+        let lift = pitch.cos().powi(2) * (look.mag() / 0.4).min(1.0);
+
+        y += -ACC_G + lift * 0.06;
+
+        if look_horizontal > 0.0 {
+            // falling is turned into forward speed
+            if y < 0.0 {
+                let converted = y * -0.1 * lift;
+                y += converted;
+                x += look.dx * converted / look_horizontal;
+                z += look.dz * converted / look_horizontal;
+            }
+
+            // and looking up turns forward speed into height
+            if pitch < 0.0 {
+                let converted = speed_horizontal * -pitch.sin() * 0.04;
+                y += converted * 3.2;
+                x -= look.dx * converted / look_horizontal;
+                z -= look.dz * converted / look_horizontal;
+            }
+
+            x += (look.dx / look_horizontal * speed_horizontal - x) * 0.1;
+            z += (look.dz / look_horizontal * speed_horizontal - z) * 0.1;
+        }
+
+        x *= 0.99;
+        y *= DRAG_MULT;
+        z *= 0.99;
+
+        let mut just_hit_ground = false;
+        let mut new_loc = self.location + Displacement::new(0., y, 0.);
+
+        if y < 0.0 {
+            if let Some(ground) = self.ground_height(new_loc - EPSILON_Y, world) {
+                new_loc.y = ground;
+                y = 0.0;
+                just_hit_ground = true;
+            }
+        } else {
+            let head_loc = new_loc + EPSILON_Y + PLAYER_HEIGHT_Y;
+            let ceiling = self.collisions_at(head_loc, world).into_iter().map(|(bottom, _)| FloatOrd(bottom)).min();
+            if let Some(FloatOrd(ceiling)) = ceiling {
+                new_loc.y = ceiling - PLAYER_HEIGHT - 0.0001;
+                y = 0.0;
+            }
+        }
+
+        let moved = new_loc + Displacement::new(x, 0., z);
+        if self.inside_blocks(moved, world) {
+            // flew into a wall. Vanilla also hurts us for this.
+            x = 0.0;
+            z = 0.0;
+        } else {
+            new_loc = moved;
+        }
+
+        self.location = new_loc;
+        self.in_water = world.get_block_simple(BlockLocation::from(new_loc)) == Some(SimpleType::Water);
+
+        if just_hit_ground || self.in_water {
+            self.stop_gliding();
+        }
+
+        self.pending = Pending::default();
+        self.prev = MovementState {
+            speeds: [x, z],
+            y_vel: y,
+            just_hit_ground,
+            slip: BlockKind::DEFAULT_SLIP,
+            falling: !just_hit_ground,
+        };
+
+        // gliding does not build up a fall
+        self.fall_distance = 0.0;
+
+        Actions { block_placed: None, fall_damage: 0.0 }
     }
 
     pub fn tick(&mut self, world: &mut WorldBlocks, inventory: &PlayerInventory) -> Actions {
-        if let Some(place) = self.pending.place.as_ref() {
+        let direction = self.rotation.turn();
+        self.turned(direction);
+
+        if self.gliding {
+            return self.tick_gliding(world);
+        }
+
+        if let Some(place) = self.pending.place.as_mut() {
             let against = place.location;
             let actual_loc = against + place.face.change();
 
             match inventory.current() {
                 Some(current) => {
+                    place.replaced = world.get_block_exact(actual_loc);
                     world.set_block(actual_loc, BlockState::from(current.kind.id(), current.damage));
                 }
                 None => {
-                    eprintln!("tried to place air");
+                    warn!("tried to place air");
                     self.pending.place = None;
                 }
             };
         }
 
+        if self.flight.active {
+            return self.tick_flying(world);
+        }
 
         let in_block_loc = BlockLocation::from(self.location);
 
         let in_block = world.get_block_simple(in_block_loc) == Some(SimpleType::Solid);
         if in_block {
-            println!("was in block at {} of type {:?}", in_block_loc, world.get_block(in_block_loc));
+            debug!(location = %in_block_loc, block = ?world.get_block(in_block_loc), "was in a block");
 
             // auto jump if we are stuck in a block
             self.pending.jump = true;
@@ -351,10 +885,12 @@ impl Physics {
         let below_loc = self.location - EPSILON_Y;
         let below_block_loc = BlockLocation::from(below_loc);
 
-        let current_block_loc = BlockLocation::from(self.location + EPSILON_Y);
-
-        // first check is because we can be counted as falling if we are inside a block (Minecraft is weird)
-        let mut falling = below_block_loc.y == current_block_loc.y || self.cross_section_empty(below_loc, world);
+        // we are also counted as falling if we are inside a block (Minecraft is weird). Moving up (i.e., knocked
+        // up while on the ground) leaves the ground.
+        let mut falling = self.prev.y_vel > 0.0 || match self.ground_height(below_loc, world) {
+            None => true,
+            Some(ground) => (ground - self.location.y).abs() > EPSILON_Y.dy,
+        };
 
         let mut just_hit_ground = false;
 
@@ -369,21 +905,20 @@ impl Physics {
         // the horizontal direction we are moving determined from the look direction
         let horizontal = self.horizontal;
 
+        let forward = matches!(self.pending.line, Some(Line::Forward));
+        let sprinting = self.sprint.update(self.pending.speed == Speed::SPRINT, forward, self.sneaking);
+
         let [strafe_change, forward_change] = {
-            let strafe_factor = match self.pending.strafe {
-                None => 0.0,
-                Some(Strafe::Right) => 1.0,
-                Some(Strafe::Left) => -1.0,
-            };
+            let (strafe_factor, line_factor) = self.keys();
 
-            let line_factor = match self.pending.line {
-                None => 0.0,
-                Some(Line::Forward) => 1.0,
-                Some(Line::Backward) => -1.0,
+            let move_factor = if self.sneaking {
+                Speed::SNEAK.multiplier()
+            } else if self.pending.speed == Speed::SPRINT && !sprinting {
+                Speed::WALK.multiplier()
+            } else {
+                self.pending.speed.multiplier()
             };
 
-            let move_factor = self.pending.speed.multiplier();
-
             mot_xz(strafe_factor, line_factor, move_factor)
         };
 
@@ -394,7 +929,7 @@ impl Physics {
             horizontal.dz * forward_change + sideway.dz * strafe_change,
         ];
 
-        let effect_mult = effects_multiplier(0.0, 0.0);
+        let effect_mult = effects_multiplier(self.effects.speed as f64, self.effects.slowness as f64);
 
         let mut speeds = [0.0, 0.0];
 
@@ -415,13 +950,14 @@ impl Physics {
                 for i in 0..2 {
                     speeds[i] = ground_speed(prev_speeds[i], prev_slip, move_mults[i], effect_mult, slip);
                 }
-                if self.pending.speed == Speed::SPRINT {
+                // the sprint jump boost
+                if sprinting {
                     let move_displacement = Displacement::new(move_mults[0], 0., move_mults[1]).normalize();
                     speeds[0] += move_displacement.dx * 0.2;
                     speeds[1] += move_displacement.dz * 0.2;
                 }
                 falling = true;
-                initial_ver(0)
+                initial_ver(self.effects.jump_boost as usize)
             } else {
 
                 // we are not falling and not jumping
@@ -463,8 +999,8 @@ impl Physics {
         let mut new_loc_first = self.location + Displacement::new(0., y_vel, 0.);
 
         if y_vel < 0.0 {
-            if !self.cross_section_empty(new_loc_first - EPSILON_Y, world) {
-                new_loc_first.y = new_loc_first.y.round();
+            if let Some(ground) = self.ground_height(new_loc_first - EPSILON_Y, world) {
+                new_loc_first.y = ground;
                 y_vel = 0.0;
                 falling = false;
                 just_hit_ground = true;
@@ -474,8 +1010,9 @@ impl Physics {
             let mut head_loc = new_loc_first + EPSILON_Y;
             head_loc.y += PLAYER_HEIGHT;
 
-            if !self.cross_section_empty(head_loc, world) {
-                new_loc_first.y = head_loc.y.round() - PLAYER_HEIGHT - 0.0001;
+            let ceiling = self.collisions_at(head_loc, world).into_iter().map(|(bottom, _)| FloatOrd(bottom)).min();
+            if let Some(FloatOrd(ceiling)) = ceiling {
+                new_loc_first.y = ceiling - PLAYER_HEIGHT - 0.0001;
                 y_vel = 0.0;
             }
         }
@@ -483,6 +1020,10 @@ impl Physics {
 
         let prev_loc = self.location;
 
+        if self.sneaking && !falling {
+            speeds = self.clamp_to_edge(new_loc_first, speeds, world);
+        }
+
         {
             let mut new_loc = new_loc_first;
             new_loc.x += speeds[0];
@@ -491,9 +1032,16 @@ impl Physics {
             let mut locs = HashSet::new();
 
 
-            self.in_cross_section(new_loc + EPSILON_Y, world, &mut locs);
-            self.in_cross_section(new_loc + UNIT_Y, world, &mut locs);
-            self.in_cross_section(new_loc + PLAYER_HEIGHT_Y, world, &mut locs);
+            self.blocked_sideways(new_loc, world, &mut locs);
+
+            // walk onto slabs without jumping like vanilla
+            if !falling && !locs.is_empty() {
+                if let Some(step) = self.step_height(new_loc, &locs, world) {
+                    new_loc_first.y = step;
+                    new_loc.y = step;
+                    locs.clear();
+                }
+            }
 
             let mut stop_x: bool = false;
             let mut stop_z: bool = false;
@@ -524,6 +1072,8 @@ impl Physics {
             let head_block = world.get_block_simple(head);
 
             let against_block = stop_x || stop_z;
+            self.sprint.collided(against_block);
+
             if against_block {
                 if stop_x {
                     speeds[0] = 0.0;
@@ -550,10 +1100,13 @@ impl Physics {
         new_loc_first.z += speeds[1];
 
 
+        let fall_damage = self.fall(new_loc_first.y - self.location.y, just_hit_ground);
+
         self.location = new_loc_first;
 
         let actions = Actions {
-            block_placed: self.pending.place.take()
+            block_placed: self.pending.place.take(),
+            fall_damage,
         };
 
         self.pending = Pending::default();
@@ -570,10 +1123,34 @@ impl Physics {
         actions
     }
 
+    /// like vanilla the distance fallen builds up until we land, which hurts. Water breaks any fall.
+    fn fall(&mut self, dy: f64, landed: bool) -> f32 {
+        if self.in_water {
+            self.fall_distance = 0.0;
+            return 0.0;
+        }
+
+        if dy < 0.0 {
+            self.fall_distance -= dy;
+        }
+
+        if landed {
+            let damage = fall_damage(self.fall_distance);
+            self.fall_distance = 0.0;
+            damage
+        } else {
+            0.0
+        }
+    }
+
     pub fn on_ground(&self) -> bool {
         !self.prev.falling
     }
 
+    pub fn fall_distance(&self) -> f64 {
+        self.fall_distance
+    }
+
     pub fn location(&self) -> Location {
         self.location
     }
@@ -590,7 +1167,9 @@ mod tests {
 
     use crate::client::physics::{Line, Physics};
     use crate::client::physics::speed::Speed;
+    use crate::client::state::local::effects::{Effect, Effects};
     use crate::client::state::local::inventory::PlayerInventory;
+    use crate::storage::block::{BlockLocation, BlockState};
     use crate::storage::blocks::WorldBlocks;
     use crate::types::{Direction, Displacement, Location};
 
@@ -703,4 +1282,308 @@ mod tests {
         // 12 is the number of blocks a player should be in the air
         assert_eq!(12, ticks_in_air);
     }
+
+    #[test]
+    fn test_sneak_edge() {
+        // the flat world ends at x = 101
+        let mut world = WorldBlocks::flat();
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(98.5, 1., 0.5));
+        physics.look(Direction::from(Displacement::new(1., 0., 0.)));
+        physics.set_sneaking(true);
+
+        for _ in 0..200 {
+            physics.line(Line::Forward);
+            physics.speed(Speed::SPRINT);
+            physics.tick(&mut world, &PlayerInventory::default());
+            assert!(physics.on_ground());
+        }
+
+        // the back of the hitbox is still on the last block
+        let x = physics.location().x;
+        assert_le!(x, 101.3);
+        assert_ge!(x, 101.2);
+        assert_lt!(physics.eye_height().dy, Displacement::EYE_HEIGHT.dy);
+
+        // without sneaking the player walks off
+        physics.set_sneaking(false);
+        for _ in 0..20 {
+            physics.line(Line::Forward);
+            physics.speed(Speed::WALK);
+            physics.tick(&mut world, &PlayerInventory::default());
+        }
+        assert_lt!(physics.location().y, 1.0);
+    }
+
+    /// with 6 food the player walks even when asked to sprint
+    #[test]
+    fn test_hungry() {
+        let mut world = WorldBlocks::flat();
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(0., 1., 0.));
+        physics.look(Direction::from(Displacement::new(1., 0., 0.)));
+        physics.set_food(6);
+
+        let mut ticks = 0;
+        while physics.location().x < 100.0 {
+            physics.line(Line::Forward);
+            physics.speed(Speed::SPRINT);
+            physics.jump();
+            physics.tick(&mut world, &PlayerInventory::default());
+            assert!(!physics.sprinting());
+            ticks += 1;
+        }
+
+        // sprint jumping takes 286
+        assert_gt!(ticks, 330);
+
+        physics.set_food(20);
+        physics.line(Line::Forward);
+        physics.speed(Speed::SPRINT);
+        physics.tick(&mut world, &PlayerInventory::default());
+        assert!(physics.sprinting());
+    }
+
+    /// the total damage of dropping onto the ground from `y`
+    fn land(world: &mut WorldBlocks, y: f64) -> f32 {
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(0.5, y, 0.5));
+
+        let mut damage = 0.0;
+        for _ in 0..100 {
+            damage += physics.tick(world, &PlayerInventory::default()).fall_damage;
+        }
+        assert!(physics.on_ground());
+        damage
+    }
+
+    #[test]
+    fn test_fall_damage() {
+        let mut world = WorldBlocks::flat();
+
+        // 3 blocks never hurt, every block after is a half heart
+        assert_eq!(land(&mut world, 3.9), 0.0);
+        assert_eq!(land(&mut world, 11.5), 8.0);
+
+        world.set_block(BlockLocation::new(0, 1, 0), BlockState::WATER);
+        assert_eq!(land(&mut world, 31.5), 0.0);
+    }
+
+    #[test]
+    fn test_glide() {
+        // no chunks are loaded so there is nothing to land on
+        let mut world = WorldBlocks::default();
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(0., 200., 0.));
+        physics.look(Direction { yaw: 270.0, pitch: 0.0 });
+
+        // fall for a tick first
+        physics.tick(&mut world, &PlayerInventory::default());
+        assert!(physics.start_gliding());
+
+        for _ in 0..200 {
+            physics.tick(&mut world, &PlayerInventory::default());
+        }
+
+        // a glide ratio of about 10:1 looking straight ahead
+        let Location { x, y, .. } = physics.location();
+        let fallen = 200.0 - y;
+        assert_gt!(x, 100.0);
+        assert_lt!(fallen, x / 5.0);
+
+        // a firework speeds us up to about 1.5 blocks per tick in the look direction
+        physics.boost(20);
+        for _ in 0..20 {
+            physics.tick(&mut world, &PlayerInventory::default());
+        }
+        assert!(!physics.boosting());
+        assert_gt!(physics.velocity().dx, 1.4);
+        assert_lt!(physics.velocity().dy.abs(), 0.2);
+    }
+
+    #[test]
+    fn test_glide_land() {
+        let mut world = WorldBlocks::flat();
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(-90., 20., 0.));
+        physics.look(Direction { yaw: 270.0, pitch: 20.0 });
+
+        physics.tick(&mut world, &PlayerInventory::default());
+        assert!(physics.start_gliding());
+
+        let mut ticks = 0;
+        while physics.gliding() {
+            physics.tick(&mut world, &PlayerInventory::default());
+            ticks += 1;
+            assert_lt!(ticks, 1000);
+        }
+
+        // touching the ground ends the flight
+        assert!(physics.on_ground());
+        assert_lt!((physics.location().y - 1.0).abs(), 0.001);
+        assert_gt!(physics.location().x, -80.0);
+    }
+
+    #[test]
+    fn test_sneak_speed() {
+        let mut world = WorldBlocks::flat();
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(0., 1., 0.));
+        physics.look(Direction::from(Displacement::new(1., 0., 0.)));
+        physics.set_sneaking(true);
+
+        for _ in 0..100 {
+            physics.line(Line::Forward);
+            physics.speed(Speed::SPRINT);
+            physics.tick(&mut world, &PlayerInventory::default());
+        }
+
+        // vanilla sneaks at about 1.3 blocks/s. Sprinting is ignored.
+        let per_second = physics.location().x / 5.0;
+        assert_le!((per_second - 1.3).abs(), 0.1);
+    }
+
+    /// the blocks walked in a tick once up to speed
+    fn walk_speed(effects: &Effects) -> f64 {
+        let mut world = WorldBlocks::flat();
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(0., 1., 0.));
+        physics.look(Direction::from(Displacement::new(1., 0., 0.)));
+        physics.set_effects(effects);
+
+        let mut last = 0.0;
+        let mut speed = 0.0;
+        for _ in 0..60 {
+            physics.line(Line::Forward);
+            physics.speed(Speed::WALK);
+            physics.tick(&mut world, &PlayerInventory::default());
+            speed = physics.location().x - last;
+            last = physics.location().x;
+        }
+        speed
+    }
+
+    #[test]
+    fn test_speed_effect() {
+        let mut effects = Effects::default();
+
+        // vanilla walks 4.317 blocks/s, 20% faster for each level of speed
+        assert_le!((walk_speed(&effects) - 0.21585).abs(), 0.0005);
+
+        effects.add(Effect::Speed, 0, 600);
+        assert_le!((walk_speed(&effects) - 0.25902).abs(), 0.0005);
+
+        effects.add(Effect::Speed, 1, 600);
+        assert_le!((walk_speed(&effects) - 0.30219).abs(), 0.0005);
+
+        // and 15% slower for each level of slowness
+        let mut effects = Effects::default();
+        effects.add(Effect::Slowness, 0, 600);
+        assert_le!((walk_speed(&effects) - 0.18347).abs(), 0.0005);
+    }
+
+    #[test]
+    fn test_jump_boost() {
+        let mut world = WorldBlocks::flat();
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(0., 1., 0.));
+
+        let mut effects = Effects::default();
+        effects.add(Effect::JumpBoost, 0, 600);
+        physics.set_effects(&effects);
+        physics.jump();
+
+        let mut highest_y = 0_f64;
+        for _ in 0..30 {
+            physics.tick(&mut world, &PlayerInventory::default());
+            highest_y = highest_y.max(physics.location.y);
+        }
+
+        // jump boost I jumps 1.836 blocks instead of 1.252
+        assert_le!((highest_y - 2.836).abs(), 0.001);
+    }
+
+    #[test]
+    fn test_fly() {
+        // no chunks are loaded so there is nothing to bump into
+        let mut world = WorldBlocks::default();
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(0., 100., 0.));
+        physics.look(Direction::from(Displacement::new(1., 0., 0.)));
+        physics.start_flying();
+
+        // vanilla flies at about 10.9 blocks/s and twice that sprinting, without falling
+        for _ in 0..100 {
+            physics.line(Line::Forward);
+            physics.speed(Speed::WALK);
+            physics.tick(&mut world, &PlayerInventory::default());
+        }
+        assert_le!((physics.velocity().dx - 0.5444).abs(), 0.001);
+        assert_le!((physics.location().y - 100.001).abs(), 0.001);
+
+        for _ in 0..100 {
+            physics.line(Line::Forward);
+            physics.speed(Speed::SPRINT);
+            physics.tick(&mut world, &PlayerInventory::default());
+        }
+        assert_le!((physics.velocity().dx - 1.0889).abs(), 0.001);
+
+        // jumping climbs at 7.5 blocks/s
+        for _ in 0..50 {
+            physics.jump();
+            physics.tick(&mut world, &PlayerInventory::default());
+        }
+        assert_le!((physics.velocity().dy - 0.375).abs(), 0.001);
+
+        // and letting go hovers
+        for _ in 0..50 {
+            physics.tick(&mut world, &PlayerInventory::default());
+        }
+        assert_lt!(physics.velocity().dy.abs(), 0.001);
+        assert_lt!(physics.velocity().dx.abs(), 0.01);
+        assert!(physics.flying());
+        assert_eq!(physics.fall_distance(), 0.0);
+    }
+
+    #[test]
+    fn test_fly_land() {
+        let mut world = WorldBlocks::flat();
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(0.5, 20., 0.5));
+        physics.start_flying();
+
+        let mut damage = 0.0;
+        for _ in 0..100 {
+            physics.descend();
+            damage += physics.tick(&mut world, &PlayerInventory::default()).fall_damage;
+        }
+
+        // touching the ground ends the flight and going down slowly does not hurt
+        assert!(!physics.flying());
+        assert!(physics.on_ground());
+        assert_eq!(damage, 0.0);
+        assert_lt!((physics.location().y - 1.0).abs(), 0.001);
+    }
+
+    #[test]
+    fn test_fly_revoked() {
+        let mut world = WorldBlocks::flat();
+        let mut physics = Physics::default();
+        physics.teleport(Location::new(0.5, 30., 0.5));
+        physics.start_flying();
+
+        for _ in 0..20 {
+            physics.tick(&mut world, &PlayerInventory::default());
+        }
+        assert_lt!((physics.location().y - 30.0).abs(), 0.01);
+
+        // the server stopped the flight so we fall and take the whole fall as damage
+        physics.stop_flying();
+        let mut damage = 0.0;
+        for _ in 0..100 {
+            damage += physics.tick(&mut world, &PlayerInventory::default()).fall_damage;
+        }
+        assert!(physics.on_ground());
+        assert_eq!(damage, 27.0);
+    }
 }
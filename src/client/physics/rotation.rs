@@ -0,0 +1,205 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Turning the head a little each tick instead of snapping it around, which servers with rotation checks flag
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::types::Direction;
+
+/// the part of the remaining angle turned each tick, so turns slow down as they finish
+const EASE_OUT: f32 = 0.6;
+
+/// closer than this (in degrees) the head just looks at the target
+const SNAP: f32 = 0.5;
+
+#[derive(Clone, Debug)]
+pub struct RotationConfig {
+    /// the most the head turns in a tick (in degrees) along each axis
+    pub max_degrees: f32,
+
+    /// the largest random error (in degrees) added to each turn
+    pub noise: f32,
+
+    /// how far off the target (in degrees) the head can be to mine, place or attack
+    pub tolerance: f32,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        Self {
+            max_degrees: 35.0,
+            noise: 0.4,
+            tolerance: 4.0,
+        }
+    }
+}
+
+/// the signed change in yaw to go from `from` to `to` the short way around
+fn yaw_delta(from: f32, to: f32) -> f32 {
+    (to - from + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Where the head looks and where tasks want it to look. Without a config the head snaps to the target like before.
+#[derive(Debug)]
+pub struct Rotation {
+    config: Option<RotationConfig>,
+    current: Direction,
+    target: Direction,
+    rng: StdRng,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self {
+            config: None,
+            current: Direction::default(),
+            target: Direction::default(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+}
+
+impl Rotation {
+    pub fn set_config(&mut self, config: RotationConfig) {
+        self.config = Some(config);
+    }
+
+    /// make the noise added to turns the same each time, i.e., to replay a simulated run
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn current(&self) -> Direction {
+        self.current
+    }
+
+    pub fn target(&mut self, direction: Direction) {
+        self.target = direction;
+        if self.config.is_none() {
+            self.current = direction;
+        }
+    }
+
+    /// look at `direction` right away, i.e., when the server tells us where we look
+    pub fn force(&mut self, direction: Direction) {
+        self.current = direction;
+        self.target = direction;
+    }
+
+    /// whether the head is close enough to the target to interact with what is there
+    pub fn aimed(&self) -> bool {
+        match self.config.as_ref() {
+            None => true,
+            Some(config) => {
+                let yaw = yaw_delta(self.current.yaw, self.target.yaw).abs();
+                let pitch = (self.target.pitch - self.current.pitch).abs();
+                yaw <= config.tolerance && pitch <= config.tolerance
+            }
+        }
+    }
+
+    /// turn towards the target for a tick
+    pub fn turn(&mut self) -> Direction {
+        let config = match self.config.as_ref() {
+            None => return self.current,
+            Some(config) => config
+        };
+
+        let max = config.max_degrees;
+        let noise = config.noise;
+        let rng = &mut self.rng;
+
+        let mut step = |delta: f32| {
+            if delta.abs() <= SNAP {
+                return delta;
+            }
+            let error = if noise > 0.0 { rng.gen_range(-noise..=noise) } else { 0.0 };
+            (delta * EASE_OUT + error).clamp(-max, max)
+        };
+
+        let yaw = step(yaw_delta(self.current.yaw, self.target.yaw));
+        let pitch = step(self.target.pitch - self.current.pitch);
+
+        self.current.yaw += yaw;
+        self.current.pitch = (self.current.pitch + pitch).clamp(-90.0, 90.0);
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use more_asserts::*;
+
+    use crate::client::physics::rotation::{Rotation, RotationConfig, yaw_delta};
+    use crate::types::Direction;
+
+    #[test]
+    fn test_yaw_delta() {
+        assert_eq!(yaw_delta(0.0, 90.0), 90.0);
+        assert_eq!(yaw_delta(350.0, 10.0), 20.0);
+        assert_eq!(yaw_delta(10.0, 350.0), -20.0);
+        assert_eq!(yaw_delta(-720.0, 270.0), -90.0);
+    }
+
+    #[test]
+    fn test_max_degrees() {
+        let config = RotationConfig::default();
+        let max = config.max_degrees;
+
+        let mut rotation = Rotation::default();
+        rotation.set_config(config);
+
+        let targets = [
+            Direction { yaw: 180.0, pitch: 0.0 },
+            Direction { yaw: -170.0, pitch: 90.0 },
+            Direction { yaw: 45.0, pitch: -60.0 },
+            Direction { yaw: 46.0, pitch: -61.0 },
+        ];
+
+        for &target in &targets {
+            rotation.target(target);
+
+            let mut ticks = 0;
+            while !rotation.aimed() {
+                let before = rotation.current();
+                let after = rotation.turn();
+                assert_le!(yaw_delta(before.yaw, after.yaw).abs(), max + 0.001);
+                assert_le!((after.pitch - before.pitch).abs(), max + 0.001);
+
+                ticks += 1;
+                assert_lt!(ticks, 20, "never reached {:?}", target);
+            }
+        }
+
+        // half a turn needs a few ticks
+        rotation.force(Direction::default());
+        rotation.target(Direction { yaw: 180.0, pitch: 0.0 });
+        assert!(!rotation.aimed());
+        assert_ne!(rotation.turn().yaw, 180.0);
+    }
+
+    #[test]
+    fn test_instant() {
+        let mut rotation = Rotation::default();
+        let target = Direction { yaw: 123.0, pitch: 45.0 };
+        rotation.target(target);
+        assert!(rotation.aimed());
+        assert_eq!(rotation.current().yaw, 123.0);
+        assert_eq!(rotation.turn().pitch, 45.0);
+    }
+}
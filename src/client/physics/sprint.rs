@@ -0,0 +1,117 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Whether the player is sprinting. Tasks only ask for [`Speed::SPRINT`](crate::client::physics::speed::Speed);
+//! [`SprintController`] decides if the player actually sprints, like the vanilla client does. The server checks
+//! movement speed against the sprinting state we send, and sprinting while hungry gets flagged by anti-cheats.
+
+/// players with this much food or less cannot sprint
+pub const MIN_SPRINT_FOOD: u8 = 6;
+
+/// the farthest (center to center) a sprint jump can reach
+pub const SPRINT_JUMP_DIST: f64 = 4.5;
+
+/// the farthest (center to center) a walking jump can reach
+pub const WALK_JUMP_DIST: f64 = 3.0;
+
+/// the farthest a jump can reach with or without sprinting
+pub fn max_jump(sprint: bool) -> f64 {
+    if sprint { SPRINT_JUMP_DIST } else { WALK_JUMP_DIST }
+}
+
+#[derive(Debug)]
+pub struct SprintController {
+    sprinting: bool,
+    food: u8,
+    using_item: bool,
+
+    /// the player ran into a wall last tick
+    collided: bool,
+}
+
+impl Default for SprintController {
+    fn default() -> Self {
+        SprintController {
+            sprinting: false,
+            food: 20,
+            using_item: false,
+            collided: false,
+        }
+    }
+}
+
+impl SprintController {
+    pub fn set_food(&mut self, food: u8) {
+        self.food = food;
+    }
+
+    pub fn set_using_item(&mut self, using_item: bool) {
+        self.using_item = using_item;
+    }
+
+    /// record whether the player's horizontal movement was blocked this tick
+    pub fn collided(&mut self, collided: bool) {
+        self.collided = collided;
+    }
+
+    /// whether the player could sprint if a task asked for it (ignoring walls)
+    pub fn can_sprint(&self, sneaking: bool) -> bool {
+        self.food > MIN_SPRINT_FOOD && !self.using_item && !sneaking
+    }
+
+    pub fn sprinting(&self) -> bool {
+        self.sprinting
+    }
+
+    /// Decide whether the player sprints this tick. `wants` is whether the task asked to sprint and `forward` whether
+    /// the player moves forward (vanilla only sprints forwards).
+    pub fn update(&mut self, wants: bool, forward: bool, sneaking: bool) -> bool {
+        // running into a wall stops the sprint, and it does not start again while the wall is in the way
+        self.sprinting = wants && forward && !self.collided && self.can_sprint(sneaking);
+        self.sprinting
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::physics::sprint::SprintController;
+
+    #[test]
+    fn test_sprint() {
+        let mut sprint = SprintController::default();
+
+        assert!(sprint.update(true, true, false));
+        assert!(!sprint.update(true, false, false), "only forwards");
+        assert!(!sprint.update(true, true, true), "sneaking");
+        assert!(!sprint.update(false, true, false), "not asked to");
+
+        // a wall stops the sprint until the player is free again
+        assert!(sprint.update(true, true, false));
+        sprint.collided(true);
+        assert!(!sprint.update(true, true, false));
+        sprint.collided(false);
+        assert!(sprint.update(true, true, false));
+
+        sprint.set_using_item(true);
+        assert!(!sprint.update(true, true, false));
+        sprint.set_using_item(false);
+
+        sprint.set_food(6);
+        assert!(!sprint.can_sprint(false));
+        assert!(!sprint.update(true, true, false));
+        sprint.set_food(7);
+        assert!(sprint.update(true, true, false));
+    }
+}
@@ -14,9 +14,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::bootstrap::block_data::{BlockData, Material};
 use crate::client::state::local::inventory::ItemStack;
-use crate::storage::block::BlockKind;
 use crate::types::Enchantment;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -30,7 +28,7 @@ pub enum ToolMat {
 }
 
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ToolKind {
     Generic,
     Pickaxe,
@@ -65,42 +63,13 @@ pub struct Tool {
 
 impl From<&ItemStack> for Tool {
     fn from(stack: &ItemStack) -> Self {
-        use crate::client::physics::tools::ToolKind::*;
-        use crate::client::physics::tools::ToolMat::*;
-
-        let id = stack.kind.id();
-
-        let mut simple_tool = match id {
-            256 => Tool::simple(Shovel, Iron),
-            257 => Tool::simple(Pickaxe, Iron),
-            258 => Tool::simple(Axe, Iron),
-
-            269 => Tool::simple(Shovel, Wood),
-            270 => Tool::simple(Pickaxe, Wood),
-            271 => Tool::simple(Axe, Wood),
-
-            273 => Tool::simple(Shovel, Stone),
-            274 => Tool::simple(Pickaxe, Stone),
-            275 => Tool::simple(Axe, Stone),
-
-            277 => Tool::simple(Shovel, Diamond),
-            278 => Tool::simple(Pickaxe, Diamond),
-            279 => Tool::simple(Axe, Diamond),
-
-            284 => Tool::simple(Shovel, Gold),
-            285 => Tool::simple(Pickaxe, Gold),
-            286 => Tool::simple(Axe, Gold),
-
-            _ => Tool::simple(Generic, Hand)
-        };
-
-        simple_tool.id = id;
+        let mut tool = Tool::by_id(stack.kind.id());
 
         if let Some(nbt) = stack.nbt.as_ref() {
-            simple_tool.enchantments = nbt.ench.clone().unwrap_or_default()
+            tool.enchantments = nbt.ench.clone().unwrap_or_default()
         }
 
-        simple_tool
+        tool
     }
 }
 
@@ -120,64 +89,42 @@ impl Tool {
         Self { material, kind, enchantments: Vec::new(), id: 0 }
     }
 
-    pub fn efficiency(&self) -> Option<u16> {
-        self.enchantments.iter().filter_map(|ench| ench.efficiency())
-            .max()
-    }
-
-    fn strength_against_block(&self, kind: BlockKind, underwater: bool, on_ground: bool, data: &BlockData) -> f64 {
-        let block = kind.data(data);
-
-        let can_harvest = match block.material {
-            Material::Web | Material::Generic | Material::Plant | Material::Dirt | Material::Wool | Material::Wood => true,
-            Material::Rock => block.harvest_tools.contains(&self.id)
-        };
-
-        let best_tool = match (block.material, self.kind) {
-            (Material::Rock, ToolKind::Pickaxe) => true,
-            (Material::Wood, ToolKind::Axe) => true,
-            (Material::Dirt, ToolKind::Shovel) => true,
-            (Material::Web, ToolKind::Sword) => true,
-            (Material::Plant, _) => false, // need sheers
-            (Material::Generic, _) => false, // i.e., glass
-
-            _ => false
-        };
-
-        let hardness = block.hardness.unwrap_or(f64::INFINITY).max(0.0);
-
-        let mut d = 1.0;
-
+    /// the tool (without enchantments) of an item id. Items which are not tools are a hand.
+    pub fn by_id(id: u32) -> Self {
+        use crate::client::physics::tools::ToolKind::*;
+        use crate::client::physics::tools::ToolMat::*;
 
-        if best_tool {
-            if can_harvest {
-                d *= self.material.strength()
-            }
+        let mut tool = match id {
+            256 => Tool::simple(Shovel, Iron),
+            257 => Tool::simple(Pickaxe, Iron),
+            258 => Tool::simple(Axe, Iron),
 
-            let efficiency = self.efficiency().unwrap_or(0);
+            269 => Tool::simple(Shovel, Wood),
+            270 => Tool::simple(Pickaxe, Wood),
+            271 => Tool::simple(Axe, Wood),
 
-            if efficiency > 0 {
-                d += (efficiency.pow(2) + 1) as f64
-            }
-        }
+            273 => Tool::simple(Shovel, Stone),
+            274 => Tool::simple(Pickaxe, Stone),
+            275 => Tool::simple(Axe, Stone),
 
-        if underwater { d /= 5.0; }
-        if !on_ground { d /= 5.0; }
+            277 => Tool::simple(Shovel, Diamond),
+            278 => Tool::simple(Pickaxe, Diamond),
+            279 => Tool::simple(Axe, Diamond),
 
+            284 => Tool::simple(Shovel, Gold),
+            285 => Tool::simple(Pickaxe, Gold),
+            286 => Tool::simple(Axe, Gold),
 
-        let res = d / hardness;
+            _ => Tool::simple(Generic, Hand)
+        };
 
-        if can_harvest {
-            res / 30.
-        } else {
-            res / 100.
-        }
+        tool.id = id;
+        tool
     }
 
-    /// https://minecraft.fandom.com/wiki/Breaking#Speed
-    pub fn wait_time(&self, kind: BlockKind, underwater: bool, on_ground: bool, data: &BlockData) -> usize {
-        let strength = self.strength_against_block(kind, underwater, on_ground, data);
-        (1.0 / strength).round() as usize
+    pub fn efficiency(&self) -> Option<u16> {
+        self.enchantments.iter().filter_map(|ench| ench.efficiency())
+            .max()
     }
 }
 
@@ -185,22 +132,21 @@ impl Tool {
 #[cfg(test)]
 mod tests {
     use crate::bootstrap::block_data::BlockData;
-    use crate::client::physics::tools::{Tool, ToolKind, ToolMat};
+    use crate::client::physics::tools::{Tool, ToolKind};
+    use crate::client::state::local::effects::{Effect, Effects};
     use crate::storage::block::BlockKind;
 
     #[test]
     fn test_break_time() {
         let data = BlockData::read().unwrap();
 
-        let mut diamond_pick = Tool::simple(ToolKind::Pickaxe, ToolMat::Diamond);
-        diamond_pick.id = 278;
-
-        let mut diamond_shovel = Tool::simple(ToolKind::Shovel, ToolMat::Diamond);
-        diamond_shovel.id = 277;
+        let diamond_pick = Tool::by_id(278);
+        let diamond_shovel = Tool::by_id(277);
+        let wooden_pick = Tool::by_id(270);
+        let hand = Tool::default();
 
-        let hand = Tool::simple(ToolKind::Generic, ToolMat::Hand);
-
-        let time = |tool: &Tool, kind: BlockKind| tool.wait_time(kind, false, true, &data);
+        let none = Effects::default();
+        let time = |tool: &Tool, kind: BlockKind| kind.break_ticks(tool, true, false, &none, &data);
 
         // glass
         assert_eq!(9, time(&hand, BlockKind::GLASS));
@@ -213,6 +159,9 @@ mod tests {
         assert_eq!(6, time(&diamond_pick, BlockKind::STONE));
         assert_eq!(150, time(&diamond_shovel, BlockKind::STONE));
 
+        // 1.15 seconds
+        assert_eq!(23, time(&wooden_pick, BlockKind::STONE));
+
         // dirt
         assert_eq!(15, time(&hand, BlockKind::DIRT));
         assert_eq!(15, time(&diamond_pick, BlockKind::DIRT));
@@ -222,5 +171,64 @@ mod tests {
         assert_eq!(6, time(&hand, BlockKind::LEAVES));
         assert_eq!(6, time(&diamond_pick, BlockKind::LEAVES));
         assert_eq!(6, time(&diamond_shovel, BlockKind::LEAVES));
+
+        // obsidian: 9.4 seconds with diamond, 125 with a pickaxe which cannot harvest it
+        let obsidian = BlockKind(49);
+        assert_eq!(188, time(&diamond_pick, obsidian));
+        assert_eq!(2500, time(&wooden_pick, obsidian));
+
+        // cobweb: 20 seconds by hand
+        assert_eq!(400, time(&hand, BlockKind(30)));
+
+        // a torch breaks instantly
+        assert_eq!(0, time(&hand, BlockKind(50)));
+
+        // hardened clay needs a pickaxe even though prismarine calls it dirt
+        assert_eq!(Some(ToolKind::Pickaxe), BlockKind(172).best_tool(&data));
+
+        // off the ground and in water each take 5 times as long
+        assert_eq!(29, BlockKind::STONE.break_ticks(&diamond_pick, false, false, &none, &data));
+        assert_eq!(29, BlockKind::STONE.break_ticks(&diamond_pick, true, true, &none, &data));
+        assert_eq!(141, BlockKind::STONE.break_ticks(&diamond_pick, false, true, &none, &data));
+    }
+
+    #[test]
+    fn test_break_effects() {
+        let data = BlockData::read().unwrap();
+        let diamond_pick = Tool::by_id(278);
+
+        // mining fatigue I: 30% speed, so 6 ticks become 19
+        let mut effects = Effects::default();
+        effects.add(Effect::MiningFatigue, 0, 600);
+        assert_eq!(19, BlockKind::STONE.break_ticks(&diamond_pick, true, false, &effects, &data));
+
+        // mining fatigue II: 9% speed
+        effects.add(Effect::MiningFatigue, 1, 600);
+        assert_eq!(63, BlockKind::STONE.break_ticks(&diamond_pick, true, false, &effects, &data));
+
+        // haste II: 140% speed
+        let mut effects = Effects::default();
+        effects.add(Effect::Haste, 1, 600);
+        assert_eq!(5, BlockKind::STONE.break_ticks(&diamond_pick, true, false, &effects, &data));
+    }
+
+    #[test]
+    fn test_block_data() {
+        let data = BlockData::read().unwrap();
+
+        assert_eq!(Some(1.5), BlockKind::STONE.hardness(&data));
+        assert_eq!(Some(ToolKind::Pickaxe), BlockKind::STONE.best_tool(&data));
+        assert_eq!(Some(ToolKind::Shovel), BlockKind::DIRT.best_tool(&data));
+        assert_eq!(None, BlockKind::GLASS.best_tool(&data));
+
+        // stone drops cobblestone, glass nothing
+        assert!(!BlockKind::STONE.drops_self(&data));
+        assert!(BlockKind::DIRT.drops_self(&data));
+        assert!(BlockKind::COBBLESTONE.drops_self(&data));
+        assert!(!BlockKind::GLASS.drops_self(&data));
+
+        assert!(BlockKind::STONE.can_harvest(&Tool::by_id(270), &data));
+        assert!(!BlockKind::STONE.can_harvest(&Tool::default(), &data));
+        assert!(BlockKind::DIRT.can_harvest(&Tool::default(), &data));
     }
 }
@@ -14,39 +14,102 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::client::bot::{ActionState, process_command};
+use crate::chat::ChatEvent;
+use crate::client::desync::Desync;
+use crate::client::bot::{ActionState, print_error, print_reply, process_command};
+use crate::client::permissions::Access;
 use crate::client::state::global::GlobalState;
-use crate::client::state::local::inventory::ItemStack;
+use crate::client::state::local::abilities::Abilities;
+use crate::client::state::local::effects::Effect;
+use crate::client::state::local::inventory::{ItemStack, Window};
 use crate::client::state::local::LocalState;
+use crate::client::state::local::spawn::BedResponse;
 use crate::client::tasks::eat::EatTask;
+use crate::client::tasks::recover::{Death, RecoverItemsTask};
+use crate::error::WorldError;
 use crate::protocol::InterfaceOut;
-use crate::storage::block::{BlockLocation, BlockState};
+use crate::protocol::disconnect::{Disconnect, DisconnectReason};
+use crate::storage::block::{BlockKind, BlockLocation, BlockState, SimpleType};
 use crate::storage::blocks::ChunkLocation;
+use crate::storage::blocks::raycast::AimCache;
 use crate::storage::chunk::ChunkColumn;
-use crate::types::{Chat, Dimension, Location, LocationOrigin, PlayerMessage};
+use crate::storage::tile_entity::TileEntity;
+use crate::types::{Dimension, Direction, Displacement, GameMode, Location, LocationOrigin, PlayerMessage};
 use crate::storage::entities::EntityKind;
 use crate::client::state::global::world_players::Player;
 
 pub trait InterfaceIn {
-    fn on_chat(&mut self, message: Chat);
+    fn on_chat(&mut self, message: ChatEvent);
     fn on_pickup_item(&mut self, idx: usize, item: ItemStack);
     fn on_lose_item(&mut self, idx: usize);
-    fn on_death(&mut self);
+    /// a totem of undying kept us alive
+    fn on_totem_pop(&mut self);
+    fn on_death(&mut self, cause: Option<String>);
     fn on_update_health(&mut self, health: f32, food: u8);
+
+    /// what the server lets us do changed, i.e., whether we may fly
+    fn on_abilities(&mut self, abilities: Abilities);
+    fn on_gamemode(&mut self, gamemode: GameMode);
     fn on_dimension_change(&mut self, dimension: Dimension);
+
+    /// where players without a bed spawn
+    fn on_spawn_position(&mut self, location: BlockLocation);
+
+    /// we lay down in a bed
+    fn on_sleep(&mut self);
     fn on_join(&mut self);
-    fn on_move(&mut self, location: Location);
+    fn on_move(&mut self, location: Location, direction: Direction);
     fn on_recv_chunk(&mut self, location: ChunkLocation, column: ChunkColumn, new: bool);
+
+    /// the server stopped sending the column at `location` to us
+    fn on_unload_chunk(&mut self, location: ChunkLocation);
     fn on_entity_move(&mut self, id: u32, location: LocationOrigin);
+
+    /// the server set the velocity of an entity. For our own entity this is knockback.
+    fn on_entity_velocity(&mut self, id: u32, velocity: Displacement);
+
+    /// an entity got `effect` for `ticks` ticks. Only our own effects are kept.
+    fn on_entity_effect(&mut self, id: u32, effect: Effect, amplifier: u8, ticks: u32);
+    fn on_remove_entity_effect(&mut self, id: u32, effect: Effect);
     fn on_block_change(&mut self, location: BlockLocation, state: BlockState);
+
+    /// the block entity at `location`, from the chunk it is in or because it changed
+    fn on_tile_entity(&mut self, location: BlockLocation, entity: TileEntity);
+
+    /// `destroyed` blocks became air and the bot was pushed by `velocity`
+    fn on_explosion(&mut self, destroyed: Vec<BlockLocation>, velocity: Displacement);
+
+    /// the server opened a window (i.e., a chest) with `slots` slots of its own
+    fn on_open_window(&mut self, id: u8, slots: usize);
+    fn on_close_window(&mut self, id: u8);
+
+    /// the server made us hold hotbar slot `slot`
+    fn on_held_slot(&mut self, slot: u8);
+
+    /// the estimated ticks per second of the server. Sent with every Time Update once known.
+    fn on_server_tps(&mut self, tps: f64);
     fn on_entity_destroy(&mut self, id: u32);
     fn on_entity_spawn(&mut self, id: u32, location: Location, kind: EntityKind);
-    fn on_player_join(&mut self, uuid: u128, name: String);
+    fn on_player_join(&mut self, player: Player);
+
+    /// the server measured the ping of the player again or changed their gamemode
+    fn on_player_update(&mut self, uuid: u128, ping: Option<u32>, gamemode: Option<u8>);
     fn on_player_leave(&mut self, uuid: u128);
-    fn on_disconnect(&mut self, reason: &str);
+    fn on_disconnect(&mut self, disconnect: Disconnect);
     fn on_socket_close(&mut self);
 }
 
+/// A block the server changed. Every bot receives the same change from the server, so only the first to process it
+/// records it in [`GlobalState`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockChanged {
+    pub location: BlockLocation,
+
+    /// None if the block was not known
+    pub old: Option<BlockState>,
+    pub new: BlockState,
+}
+
 pub struct SimpleInterfaceIn<'a, I: InterfaceOut> {
     global: &'a mut GlobalState,
     local: &'a mut LocalState,
@@ -67,23 +130,37 @@ impl<I: InterfaceOut> SimpleInterfaceIn<'a, I> {
 
 
 impl<'a, I: InterfaceOut> InterfaceIn for SimpleInterfaceIn<'a, I> {
-    fn on_chat(&mut self, message: Chat) {
-        println!("{}", message.clone().colorize());
-
-        let mut process = |msg: PlayerMessage| {
-            if let Some(cmd) = msg.into_cmd() {
-                let name = cmd.command;
-                let args_str: Vec<&str> = cmd.args.iter().map(|x| x.as_str()).collect();
-                if let Err(err) = process_command(&name, &args_str, self.local, self.global, self.actions, self.out) {
-                    println!("could not process command. Reason: {}", err);
+    fn on_chat(&mut self, message: ChatEvent) {
+        info!("{}", message.component.colorize());
+        self.local.chat.push(message.clone());
+
+        if let Some(response) = BedResponse::parse(&message) {
+            self.local.spawn.on_response(self.local.ticks, response);
+        }
+
+        // commands are parsed from the plain text of the message, without the sender
+        if let Some(cmd) = message.player_message().and_then(PlayerMessage::into_cmd) {
+            // the player list knows the uuid of the sender, the chat only their name
+            let uuid = self.global.players.by_name(&cmd.player).map(|player| player.uuid);
+            let args_str: Vec<&str> = cmd.args.iter().map(|x| x.as_str()).collect();
+            match self.global.permissions.check(&cmd.player, uuid, message.kind, &cmd.command, &args_str) {
+                Access::Allowed => {}
+                Access::Ignored => return,
+                Access::Denied => {
+                    info!(player = %cmd.player, command = %cmd.command, "not allowed to use the command");
+                    if self.global.permissions.refusal(&cmd.player, self.global.ticks) {
+                        self.out.send_chat(&format!("/msg {} you are not allowed to use #{}", cmd.player, cmd.command));
+                    }
+                    return;
                 }
             }
-        };
 
-        if let Some(msg) = message.player_message() {
-            process(msg);
-        } else if let Some(msg) = message.player_dm() {
-            process(msg);
+            let name = cmd.command;
+            match process_command(&name, &args_str, Some(&cmd.player), self.local, self.global, self.actions, self.out) {
+                Ok(Some(reply)) => print_reply(&reply),
+                Ok(None) => {}
+                Err(err) => print_error(&err),
+            }
         }
     }
 
@@ -95,59 +172,244 @@ impl<'a, I: InterfaceOut> InterfaceIn for SimpleInterfaceIn<'a, I> {
         self.local.inventory.remove(idx);
     }
 
-    fn on_death(&mut self) {
+    fn on_totem_pop(&mut self) {
+        let inventory = &mut self.local.inventory;
+        if let Some(hand) = inventory.hand_holding(BlockKind::TOTEM) {
+            inventory.consume(hand);
+        }
+
+        // the server would tell us about the empty slot soon, but the next hit could come sooner
+        let equipped = inventory.equip_offhand(BlockKind::TOTEM, self.out);
+        info!(equipped_another = equipped, "used a totem of undying");
+    }
+
+    fn on_death(&mut self, cause: Option<String>) {
+        self.local.stats.died(cause);
+        self.local.spawn.on_death();
+
+        // no point in going back if we cannot make it before the items despawn
+        self.local.death = match (Death::new(self.local), self.local.spawn.point()) {
+            (Some(death), Some(spawn)) if !death.in_time(spawn.location(), self.global) => {
+                let died_at = BlockLocation::from(death.location);
+                info!(%spawn, %died_at, "too far from where we reappear to go back for the items");
+                None
+            }
+            (death, _) => death,
+        };
+        self.local.effects.clear();
         self.actions.clear();
         self.out.respawn();
         self.out.send_chat("I died... oof... well I guess I should respawn");
     }
 
     fn on_update_health(&mut self, health: f32, food: u8) {
+        if health < self.local.health {
+            self.local.last_hurt = Some(self.local.ticks);
+        }
+
         self.local.health = health;
         self.local.food = food;
+        self.local.physics.set_food(food);
+
+        debug!(health, food, "updated health");
 
-        println!("updated health {} food is {}", health, food);
+        // we only get health again once we respawned. Going back for our items waits for a reflex but not for
+        // anything else.
+        if health > 0.0 {
+            if let Some(death) = self.local.death.take() {
+                let task = RecoverItemsTask::new(death, self.global.config.recover.radius);
+                self.actions.prioritize(task);
+            }
+        }
 
         // we should probably eat something
         if food < 10 {
             // if we could switch to food
-            if self.local.inventory.switch_food(&self.global.block_data, self.out) {
-                self.actions.schedule(EatTask::default());
+            // eating jumps the queue, the task we were doing continues afterwards
+            let eating = matches!(self.actions.current(), Some((_, "EatTask")));
+            if !eating && self.local.inventory.switch_food(&self.global.block_data, self.out) {
+                self.actions.suspend(EatTask::default());
             }
         }
     }
 
+    fn on_abilities(&mut self, abilities: Abilities) {
+        debug!(?abilities, "updated abilities");
+        self.local.on_abilities(abilities);
+    }
+
+    fn on_gamemode(&mut self, gamemode: GameMode) {
+        self.local.set_gamemode(gamemode);
+    }
+
     fn on_dimension_change(&mut self, dimension: Dimension) {
-        self.local.dimension = dimension;
+        if dimension != self.local.dimension {
+            info!(from = %self.local.dimension, to = %dimension, "changed dimension");
+
+            // vanilla forgets every entity, the server does not tell us which ones we cannot see anymore
+            self.global.entities.release(self.local.bot_id);
+
+            // paths and blocks we aimed at or mined are of the dimension we left
+            self.actions.clear();
+            self.global.mine.release(self.local.bot_id);
+            self.local.aim = AimCache::default();
+            self.local.desync = Desync::default();
+            self.local.window = None;
+            self.local.dimension = dimension;
+        }
+
+        self.global.enter(dimension);
+    }
+
+    fn on_spawn_position(&mut self, location: BlockLocation) {
+        self.local.spawn.on_world_spawn(location);
+    }
+
+    fn on_sleep(&mut self) {
+        self.local.spawn.on_response(self.local.ticks, BedResponse::Slept);
     }
 
     fn on_join(&mut self) {
-        // always start with slot 0
-        self.out.change_slot(0);
+        // the server tells us the held slot right after. Until then it is the first, like for a new player.
+        self.local.inventory.on_held_slot(0);
     }
 
-    fn on_move(&mut self, location: Location) {
-        println!("moved {} -> {}", self.local.physics.location(), location);
+    fn on_move(&mut self, location: Location, direction: Direction) {
+        debug!(from = %self.local.physics.location(), to = %location, "the server moved us");
+
+        // the server would not put us inside of a block, so we must know a block it does not
+        if self.local.physics.inside_blocks(location, &self.global.blocks) {
+            self.local.desync.strike(self.local.ticks, &format!("the server moved us into a block at {}", location));
+        }
+
         self.local.physics.teleport(location);
+        self.local.spawn.on_move(location);
+
+        // the server already has our head here, turning it back slowly would look odd
+        self.local.physics.force_look(direction);
     }
 
     fn on_recv_chunk(&mut self, location: ChunkLocation, column: ChunkColumn, new: bool) {
+        // the light is dropped as the column is stored unless the config asks for it
+        self.global.blocks.keep_light(self.global.config.index.light);
+        self.global.blocks.compact_sections(self.global.config.index.compact);
+
         if new {
             self.global.blocks.add_column(location, column);
-        } else {
-            self.global.blocks.modify_column(location, column);
+        } else if !self.global.blocks.modify_column(location, column, self.global.config.resync.missing_column) {
+            let ChunkLocation(x, z) = location;
+            warn!(error = %WorldError::MissingChunk { x, z }, "dropped an update");
+        }
+        self.global.blocks.hold(location, self.local.bot_id);
+
+        for sighting in self.global.bases.scan(&self.global.blocks, location) {
+            info!(center = %sighting.center(), score = sighting.score, "likely base");
         }
     }
 
+    fn on_unload_chunk(&mut self, location: ChunkLocation) {
+        // the column is kept while the server still sends it to another bot
+        self.global.blocks.release(location, self.local.bot_id);
+    }
+
     fn on_entity_move(&mut self, id: u32, location: LocationOrigin) {
         self.global.entities.update_entity(id, self.local.bot_id, location);
     }
 
+    fn on_entity_velocity(&mut self, id: u32, velocity: Displacement) {
+        if id != self.local.info.entity_id {
+            self.global.entities.set_velocity(id, self.local.bot_id, velocity);
+            return;
+        }
+
+        // the server moves us like this, so we have to as well or it pulls us back
+        self.local.physics.set_velocity(velocity);
+        self.local.last_knockback = Some((self.local.ticks, velocity));
+    }
+
+    fn on_entity_effect(&mut self, id: u32, effect: Effect, amplifier: u8, ticks: u32) {
+        if id == self.local.info.entity_id {
+            self.local.effects.add(effect, amplifier, ticks);
+        }
+    }
+
+    fn on_remove_entity_effect(&mut self, id: u32, effect: Effect) {
+        if id == self.local.info.entity_id {
+            self.local.effects.remove(effect);
+        }
+    }
+
     fn on_block_change(&mut self, location: BlockLocation, state: BlockState) {
-        self.global.blocks.set_block(location, state);
+        // it would wrap around to a block inside of the world
+        if !(0..256).contains(&location.y) {
+            warn!(error = %WorldError::InvalidLocation { location }, "ignored a block change");
+            return;
+        }
+
+        self.local.desync.block_changed(location);
+        self.global.expectations.answer(self.global.dimension, location, state);
+
+        let previous = self.global.blocks.get_block_exact(location);
+        if let Some(demonstration) = self.local.demonstration.as_mut() {
+            demonstration.block_change(self.local.ticks, location, previous, state);
+        }
+
+        if previous != Some(state) {
+            self.global.blocks.set_block(location, state);
+            self.global.record_change(BlockChanged { location, old: previous, new: state });
+        }
+    }
+
+    fn on_tile_entity(&mut self, location: BlockLocation, entity: TileEntity) {
+        self.global.blocks.set_tile_entity(location, entity);
+    }
+
+    fn on_explosion(&mut self, destroyed: Vec<BlockLocation>, velocity: Displacement) {
+        for location in destroyed {
+            self.on_block_change(location, BlockState::AIR);
+        }
+
+        // the server expects us to move as if we were knocked back
+        self.local.physics.push(velocity);
+    }
+
+    fn on_open_window(&mut self, id: u8, slots: usize) {
+        self.local.window = Some(Window { id, slots });
+    }
+
+    fn on_close_window(&mut self, id: u8) {
+        if matches!(self.local.window, Some(window) if window.id == id) {
+            self.local.window = None;
+        }
+    }
+
+    fn on_held_slot(&mut self, slot: u8) {
+        self.local.inventory.on_held_slot(slot);
     }
 
+    fn on_server_tps(&mut self, tps: f64) {
+        self.global.tps = Some(tps);
+    }
 
     fn on_entity_destroy(&mut self, id: u32) {
+        // a falling block is removed when it lands. Usually the server also sends the block change, but not always
+        // to every bot.
+        if let Some((state, mut location)) = self.global.entities.remove_falling_block(id) {
+            location.y = location.y.round();
+            let at = BlockLocation::from(location);
+            let below = self.global.blocks.get_block_simple(at.below());
+
+            // falling blocks which land on torches, slabs, ... break into items instead
+            if self.global.blocks.get_block_exact(at) == Some(BlockState::AIR) && below == Some(SimpleType::Solid) {
+                self.on_block_change(at, state);
+            }
+            return;
+        }
+
+        if self.global.entities.remove_item(id) {
+            return;
+        }
+
         self.global.entities.remove_entity(id, self.local.bot_id);
     }
 
@@ -155,20 +417,45 @@ impl<'a, I: InterfaceOut> InterfaceIn for SimpleInterfaceIn<'a, I> {
         self.global.entities.put_entity(id, self.local.bot_id, location, kind);
     }
 
-    fn on_player_join(&mut self, uuid: u128, name: String) {
-       self.global.players.add(Player {
-           name,
-           uuid
-       });
+    fn on_player_join(&mut self, player: Player) {
+        let panic = self.global.config.panic.triggered_by(&player.name);
+        let name = player.name.clone();
+        self.global.players.add(player);
+
+        // every bot gets the join (or the whole list when it logs in), so each stops itself
+        if panic {
+            warn!(player = %name, "a player of the panic list is online, stopping");
+            self.actions.clear();
+            if self.global.config.panic.disconnect {
+                self.local.disconnected = true;
+                self.local.disconnect = Some(Disconnect {
+                    reason: DisconnectReason::Other,
+                    text: format!("{} joined", name),
+                    raw: String::new(),
+                });
+            }
+        }
+    }
+
+    fn on_player_update(&mut self, uuid: u128, ping: Option<u32>, gamemode: Option<u8>) {
+        if let Some(ping) = ping {
+            self.global.players.set_ping(uuid, ping);
+        }
+        if let Some(gamemode) = gamemode {
+            self.global.players.set_gamemode(uuid, gamemode);
+        }
     }
 
     fn on_player_leave(&mut self, uuid: u128) {
        self.global.players.remove(uuid);
     }
 
-    fn on_disconnect(&mut self, reason: &str) {
-        println!("disconnecting because {}", reason);
+    fn on_disconnect(&mut self, disconnect: Disconnect) {
+        info!(text = %disconnect.text, reason = disconnect.reason.name(), "disconnecting");
+        self.local.stats.disconnected(&disconnect.raw);
+        self.global.kicks.record(&self.local.info.username, &disconnect);
         self.local.disconnected = true;
+        self.local.disconnect = Some(disconnect);
     }
 
     fn on_socket_close(&mut self) {}
@@ -0,0 +1,379 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::chat::ChatEvent;
+use crate::client::processor::InterfaceIn;
+use crate::client::recording::{Event, InEvent, OutEvent, Recording};
+use crate::client::state::global::world_players::Player;
+use crate::client::state::local::abilities::Abilities;
+use crate::client::state::local::effects::Effect;
+use crate::client::state::local::inventory::ItemStack;
+use crate::protocol::{Closing, Face, Hand, InterfaceOut, InvAction, Mine};
+use crate::protocol::budget::BudgetConfig;
+use crate::protocol::chat_queue::ChatConfig;
+use crate::protocol::disconnect::Disconnect;
+use crate::storage::block::{BlockLocation, BlockState};
+use crate::storage::blocks::ChunkLocation;
+use crate::storage::chunk::ChunkColumn;
+use crate::storage::entities::EntityKind;
+use crate::storage::tile_entity::TileEntity;
+use crate::types::{Dimension, Direction, Displacement, GameMode, Location, LocationOrigin};
+
+/// Records every call to the wrapped [`InterfaceOut`] before passing it on. Tasks do not know they are being
+/// recorded.
+pub struct RecordingOut<I: InterfaceOut> {
+    inner: I,
+    recording: Recording,
+}
+
+impl<I: InterfaceOut> RecordingOut<I> {
+    pub fn new(inner: I, recording: Recording) -> Self {
+        Self { inner, recording }
+    }
+
+    fn record(&self, event: impl FnOnce() -> OutEvent) {
+        self.recording.record(|| Event::Out(event()));
+    }
+}
+
+impl<I: InterfaceOut> InterfaceOut for RecordingOut<I> {
+    fn place_block(&mut self, against: BlockLocation, face: Face) {
+        self.record(|| OutEvent::PlaceBlock { against, face });
+        self.inner.place_block(against, face);
+    }
+
+    fn attack_entity(&mut self, id: u32) {
+        self.record(|| OutEvent::AttackEntity { id });
+        self.inner.attack_entity(id);
+    }
+
+    fn send_chat(&mut self, message: &str) {
+        self.record(|| OutEvent::SendChat { message: message.to_string() });
+        self.inner.send_chat(message);
+    }
+
+    fn set_chat_config(&mut self, config: ChatConfig) {
+        self.inner.set_chat_config(config);
+    }
+
+    fn set_budget(&mut self, config: BudgetConfig) {
+        self.inner.set_budget(config);
+    }
+
+    fn flush_budget(&mut self) {
+        self.inner.flush_budget();
+    }
+
+    fn set_view_distance(&mut self, chunks: u8) {
+        self.inner.set_view_distance(chunks);
+    }
+
+    fn deferred(&self) -> usize {
+        self.inner.deferred()
+    }
+
+    fn inventory_action(&mut self, action: InvAction) {
+        self.record(|| {
+            let (action, slot) = match &action {
+                InvAction::Q(slot) => ("Q", *slot),
+                InvAction::CtrlQ(slot) => ("CtrlQ", *slot),
+                InvAction::Click(slot, ..) => ("Click", *slot),
+                InvAction::ShiftClick(slot, ..) => ("ShiftClick", *slot),
+                InvAction::SwapHotbar(slot, _) => ("SwapHotbar", *slot),
+                InvAction::SwapOffhand => ("SwapOffhand", 0),
+                InvAction::WindowShiftClick(_, slot, _) => ("WindowShiftClick", *slot),
+                InvAction::Creative(slot, _) => ("Creative", *slot),
+            };
+            OutEvent::InventoryAction { action: action.to_string(), slot }
+        });
+        self.inner.inventory_action(action);
+    }
+
+    fn swing_arm(&mut self) {
+        self.record(|| OutEvent::SwingArm);
+        self.inner.swing_arm();
+    }
+
+    fn finish_eating(&mut self) {
+        self.record(|| OutEvent::FinishEating);
+        self.inner.finish_eating();
+    }
+
+    fn use_item(&mut self, hand: Hand) {
+        self.record(|| OutEvent::UseItem { hand });
+        self.inner.use_item(hand);
+    }
+
+    fn change_slot(&mut self, number: u8) {
+        self.record(|| OutEvent::ChangeSlot { number });
+        self.inner.change_slot(number);
+    }
+
+    fn mine(&mut self, location: BlockLocation, mine: Mine, face: Face) {
+        self.record(|| OutEvent::Mine { location, mine, face });
+        self.inner.mine(location, mine, face);
+    }
+
+    fn respawn(&mut self) {
+        self.record(|| OutEvent::Respawn);
+        self.inner.respawn();
+    }
+
+    fn teleport(&mut self, location: Location) {
+        self.record(|| OutEvent::Teleport { location });
+        self.inner.teleport(location);
+    }
+
+    fn look(&mut self, direction: Direction) {
+        self.record(|| OutEvent::Look { direction });
+        self.inner.look(direction);
+    }
+
+    fn teleport_and_look(&mut self, location: Location, direction: Direction, on_ground: bool) {
+        self.record(|| OutEvent::TeleportAndLook { location, direction, on_ground });
+        self.inner.teleport_and_look(location, direction, on_ground);
+    }
+
+    fn sneak(&mut self, sneaking: bool) {
+        self.record(|| OutEvent::Sneak { sneaking });
+        self.inner.sneak(sneaking);
+    }
+
+    fn sprint(&mut self, sprinting: bool) {
+        self.record(|| OutEvent::Sprint { sprinting });
+        self.inner.sprint(sprinting);
+    }
+
+    fn start_gliding(&mut self) {
+        self.record(|| OutEvent::StartGliding);
+        self.inner.start_gliding();
+    }
+
+    fn leave_bed(&mut self) {
+        self.record(|| OutEvent::LeaveBed);
+        self.inner.leave_bed();
+    }
+
+    fn abilities(&mut self, abilities: Abilities) {
+        self.record(|| OutEvent::Abilities { flying: abilities.flying });
+        self.inner.abilities(abilities);
+    }
+
+    fn close_window(&mut self, window: u8) {
+        self.record(|| OutEvent::CloseWindow { window });
+        self.inner.close_window(window);
+    }
+
+    fn edit_book(&mut self, pages: &[String]) {
+        self.record(|| OutEvent::EditBook { pages: pages.len() });
+        self.inner.edit_book(pages);
+    }
+
+    fn sign_book(&mut self, pages: &[String], title: &str, author: &str) {
+        self.record(|| OutEvent::SignBook { pages: pages.len(), title: title.to_string() });
+        self.inner.sign_book(pages, title, author);
+    }
+
+    fn close(&mut self) -> Closing {
+        self.recording.flush();
+        self.inner.close()
+    }
+}
+
+/// Records every processed event before passing it to the wrapped [`InterfaceIn`]
+pub struct RecordingIn<'a, P: InterfaceIn> {
+    inner: &'a mut P,
+    recording: &'a Recording,
+}
+
+impl<P: InterfaceIn> RecordingIn<'a, P> {
+    pub fn new(inner: &'a mut P, recording: &'a Recording) -> Self {
+        Self { inner, recording }
+    }
+
+    fn record(&self, event: impl FnOnce() -> InEvent) {
+        self.recording.record(|| Event::In(event()));
+    }
+}
+
+impl<P: InterfaceIn> InterfaceIn for RecordingIn<'_, P> {
+    fn on_chat(&mut self, message: ChatEvent) {
+        self.record(|| InEvent::Chat { text: message.text.clone() });
+        self.inner.on_chat(message);
+    }
+
+    fn on_pickup_item(&mut self, idx: usize, item: ItemStack) {
+        self.record(|| InEvent::PickupItem { idx, kind: item.kind.id(), count: item.count });
+        self.inner.on_pickup_item(idx, item);
+    }
+
+    fn on_lose_item(&mut self, idx: usize) {
+        self.record(|| InEvent::LoseItem { idx });
+        self.inner.on_lose_item(idx);
+    }
+
+    fn on_totem_pop(&mut self) {
+        self.record(|| InEvent::TotemPop);
+        self.inner.on_totem_pop();
+    }
+
+    fn on_death(&mut self, cause: Option<String>) {
+        self.record(|| InEvent::Death { cause: cause.clone() });
+        self.inner.on_death(cause);
+    }
+
+    fn on_update_health(&mut self, health: f32, food: u8) {
+        self.record(|| InEvent::UpdateHealth { health, food });
+        self.inner.on_update_health(health, food);
+    }
+
+    fn on_abilities(&mut self, abilities: Abilities) {
+        self.record(|| InEvent::Abilities { flying: abilities.flying, may_fly: abilities.may_fly });
+        self.inner.on_abilities(abilities);
+    }
+
+    fn on_gamemode(&mut self, gamemode: GameMode) {
+        self.record(|| InEvent::Gamemode { gamemode });
+        self.inner.on_gamemode(gamemode);
+    }
+
+    fn on_dimension_change(&mut self, dimension: Dimension) {
+        self.record(|| InEvent::DimensionChange { dimension: dimension.to_string() });
+        self.inner.on_dimension_change(dimension);
+    }
+
+    fn on_spawn_position(&mut self, location: BlockLocation) {
+        self.record(|| InEvent::SpawnPosition { location });
+        self.inner.on_spawn_position(location);
+    }
+
+    fn on_sleep(&mut self) {
+        self.record(|| InEvent::Sleep);
+        self.inner.on_sleep();
+    }
+
+    fn on_join(&mut self) {
+        self.record(|| InEvent::Join);
+        self.inner.on_join();
+    }
+
+    fn on_move(&mut self, location: Location, direction: Direction) {
+        self.record(|| InEvent::Move { location, direction });
+        self.inner.on_move(location, direction);
+    }
+
+    fn on_recv_chunk(&mut self, location: ChunkLocation, column: ChunkColumn, new: bool) {
+        self.record(|| InEvent::RecvChunk { location, new });
+        self.inner.on_recv_chunk(location, column, new);
+    }
+
+    fn on_unload_chunk(&mut self, location: ChunkLocation) {
+        self.record(|| InEvent::UnloadChunk { location });
+        self.inner.on_unload_chunk(location);
+    }
+
+    fn on_entity_move(&mut self, id: u32, location: LocationOrigin) {
+        self.record(|| InEvent::EntityMove { id });
+        self.inner.on_entity_move(id, location);
+    }
+
+    fn on_block_change(&mut self, location: BlockLocation, state: BlockState) {
+        self.record(|| InEvent::BlockChange { location, state: state.0 });
+        self.inner.on_block_change(location, state);
+    }
+
+    fn on_tile_entity(&mut self, location: BlockLocation, entity: TileEntity) {
+        self.record(|| InEvent::TileEntity { location });
+        self.inner.on_tile_entity(location, entity);
+    }
+
+    fn on_entity_velocity(&mut self, id: u32, velocity: Displacement) {
+        self.record(|| InEvent::EntityVelocity { id, velocity });
+        self.inner.on_entity_velocity(id, velocity);
+    }
+
+    fn on_entity_effect(&mut self, id: u32, effect: Effect, amplifier: u8, ticks: u32) {
+        self.record(|| InEvent::EntityEffect { id, effect, amplifier, ticks });
+        self.inner.on_entity_effect(id, effect, amplifier, ticks);
+    }
+
+    fn on_remove_entity_effect(&mut self, id: u32, effect: Effect) {
+        self.record(|| InEvent::RemoveEntityEffect { id, effect });
+        self.inner.on_remove_entity_effect(id, effect);
+    }
+
+    fn on_explosion(&mut self, destroyed: Vec<BlockLocation>, velocity: Displacement) {
+        self.record(|| InEvent::Explosion { destroyed: destroyed.len(), velocity });
+        self.inner.on_explosion(destroyed, velocity);
+    }
+
+    fn on_open_window(&mut self, id: u8, slots: usize) {
+        self.record(|| InEvent::OpenWindow { id, slots });
+        self.inner.on_open_window(id, slots);
+    }
+
+    fn on_close_window(&mut self, id: u8) {
+        self.record(|| InEvent::CloseWindow { id });
+        self.inner.on_close_window(id);
+    }
+
+    fn on_held_slot(&mut self, slot: u8) {
+        self.record(|| InEvent::HeldSlot { slot });
+        self.inner.on_held_slot(slot);
+    }
+
+    fn on_server_tps(&mut self, tps: f64) {
+        self.record(|| InEvent::ServerTps { tps });
+        self.inner.on_server_tps(tps);
+    }
+
+    fn on_entity_destroy(&mut self, id: u32) {
+        self.record(|| InEvent::EntityDestroy { id });
+        self.inner.on_entity_destroy(id);
+    }
+
+    fn on_entity_spawn(&mut self, id: u32, location: Location, kind: EntityKind) {
+        self.record(|| InEvent::EntitySpawn { id, location, player: matches!(kind, EntityKind::Player { .. }) });
+        self.inner.on_entity_spawn(id, location, kind);
+    }
+
+    fn on_player_join(&mut self, player: Player) {
+        self.record(|| InEvent::PlayerJoin { name: player.name.clone() });
+        self.inner.on_player_join(player);
+    }
+
+    fn on_player_update(&mut self, uuid: u128, ping: Option<u32>, gamemode: Option<u8>) {
+        self.record(|| InEvent::PlayerUpdate { uuid: format!("{:x}", uuid), ping, gamemode });
+        self.inner.on_player_update(uuid, ping, gamemode);
+    }
+
+    fn on_player_leave(&mut self, uuid: u128) {
+        self.record(|| InEvent::PlayerLeave { uuid: format!("{:x}", uuid) });
+        self.inner.on_player_leave(uuid);
+    }
+
+    fn on_disconnect(&mut self, disconnect: Disconnect) {
+        self.record(|| InEvent::Disconnect { reason: disconnect.raw.clone() });
+        self.recording.flush();
+        self.inner.on_disconnect(disconnect);
+    }
+
+    fn on_socket_close(&mut self) {
+        self.record(|| InEvent::SocketClose);
+        self.recording.flush();
+        self.inner.on_socket_close();
+    }
+}
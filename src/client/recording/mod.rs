@@ -0,0 +1,218 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Per-bot session recordings. Every bot gets a [`Recording`] handle. When recording is enabled the
+//! handle writes one JSON [`Entry`] per line (ndjson) to `<dir>/<username>.ndjson`. When it is disabled
+//! the handle is empty and every call is a no-op (the event closure is never even evaluated).
+
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Res;
+use crate::client::state::local::armor::ArmorSlot;
+use crate::client::state::local::effects::Effect;
+use crate::protocol::{Face, Hand, Mine};
+use crate::storage::block::BlockLocation;
+use crate::storage::blocks::ChunkLocation;
+use crate::types::{Direction, Displacement, GameMode, Location};
+
+pub use interface::{RecordingIn, RecordingOut};
+
+mod interface;
+pub mod replay;
+
+/// A single line in a recording
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Entry {
+    /// the bot's tick counter when the event happened
+    pub tick: usize,
+    pub event: Event,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Event {
+    /// a call to [`crate::protocol::InterfaceOut`]
+    Out(OutEvent),
+
+    /// an event processed by [`crate::client::processor::InterfaceIn`]
+    In(InEvent),
+    TaskStarted { name: String },
+    TaskFinished { name: String },
+    TaskCancelled { name: String },
+
+    /// the task gave up, i.e., the bot was stuck
+    TaskFailed { name: String, reason: String },
+
+    /// a worn piece of armor can only take `left` more hits
+    ArmorBreaking { slot: ArmorSlot, kind: u32, left: u16, unequipped: bool },
+
+    /// the server knocked the bot back (i.e., it was hit)
+    Knockback { velocity: Displacement },
+
+    /// The server did not make a change of a block the bot mined or placed. `actual` is the kind the server says is
+    /// there, None if it did not answer.
+    ActionRejected { location: BlockLocation, expected: u32, actual: Option<u32> },
+}
+
+/// Mirrors [`crate::protocol::InterfaceOut`]. Note that `TeleportAndLook` is sent every tick, so it doubles as the
+/// per-tick position record.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum OutEvent {
+    PlaceBlock { against: BlockLocation, face: Face },
+    AttackEntity { id: u32 },
+    SendChat { message: String },
+    InventoryAction { action: String, slot: u16 },
+    SwingArm,
+    FinishEating,
+    UseItem { hand: Hand },
+    ChangeSlot { number: u8 },
+    Mine { location: BlockLocation, mine: Mine, face: Face },
+    Respawn,
+    Teleport { location: Location },
+    Look { direction: Direction },
+    TeleportAndLook { location: Location, direction: Direction, on_ground: bool },
+    Sneak { sneaking: bool },
+    Sprint { sprinting: bool },
+    StartGliding,
+    LeaveBed,
+    Abilities { flying: bool },
+    CloseWindow { window: u8 },
+    EditBook { pages: usize },
+    SignBook { pages: usize, title: String },
+}
+
+/// Mirrors [`crate::client::processor::InterfaceIn`]. Bulky payloads (chunk columns, chat components) are reduced to
+/// what is useful on a timeline.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum InEvent {
+    Chat { text: String },
+    PickupItem { idx: usize, kind: u32, count: u8 },
+    LoseItem { idx: usize },
+    TotemPop,
+    Death { cause: Option<String> },
+    UpdateHealth { health: f32, food: u8 },
+    Abilities { flying: bool, may_fly: bool },
+    Gamemode { gamemode: GameMode },
+    DimensionChange { dimension: String },
+    SpawnPosition { location: BlockLocation },
+    Sleep,
+    Join,
+    Move { location: Location, #[serde(default)] direction: Direction },
+    RecvChunk { location: ChunkLocation, new: bool },
+    UnloadChunk { location: ChunkLocation },
+    EntityMove { id: u32 },
+    EntityVelocity { id: u32, velocity: Displacement },
+    EntityEffect { id: u32, effect: Effect, amplifier: u8, ticks: u32 },
+    RemoveEntityEffect { id: u32, effect: Effect },
+    BlockChange { location: BlockLocation, state: u32 },
+    TileEntity { location: BlockLocation },
+    Explosion { destroyed: usize, velocity: Displacement },
+    OpenWindow { id: u8, slots: usize },
+    CloseWindow { id: u8 },
+    HeldSlot { slot: u8 },
+    ServerTps { tps: f64 },
+    EntityDestroy { id: u32 },
+    EntitySpawn { id: u32, location: Location, player: bool },
+    PlayerJoin { name: String },
+    PlayerUpdate { uuid: String, ping: Option<u32>, gamemode: Option<u8> },
+    PlayerLeave { uuid: String },
+    Disconnect { reason: String },
+    SocketClose,
+}
+
+struct RecordWriter {
+    tick: usize,
+    writer: BufWriter<File>,
+}
+
+/// A cheap, clonable handle to a bot's recording. Clones share the same underlying file.
+#[derive(Clone, Default)]
+pub struct Recording {
+    inner: Option<Rc<RefCell<RecordWriter>>>,
+}
+
+impl Recording {
+    /// A recording which does nothing
+    pub fn off() -> Recording {
+        Recording::default()
+    }
+
+    /// Start a recording for `username` in `dir`. Any previous recording for the user is overwritten.
+    pub fn start(dir: &Path, username: &str) -> Res<Recording> {
+        std::fs::create_dir_all(dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(dir.join(format!("{}.ndjson", username)))?;
+
+        let writer = RecordWriter {
+            tick: 0,
+            writer: BufWriter::new(file),
+        };
+
+        Ok(Recording {
+            inner: Some(Rc::new(RefCell::new(writer)))
+        })
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// update the tick all later events are stamped with
+    pub fn set_tick(&self, tick: usize) {
+        if let Some(inner) = self.inner.as_ref() {
+            inner.borrow_mut().tick = tick;
+        }
+    }
+
+    /// Record an event. The closure is only called if recording is on.
+    pub fn record(&self, event: impl FnOnce() -> Event) {
+        let inner = match self.inner.as_ref() {
+            None => return,
+            Some(inner) => inner
+        };
+
+        let mut inner = inner.borrow_mut();
+        let entry = Entry {
+            tick: inner.tick,
+            event: event(),
+        };
+
+        // a recording should never take the bot down with it
+        let res = serde_json::to_writer(&mut inner.writer, &entry)
+            .map_err(std::io::Error::from)
+            .and_then(|_| inner.writer.write_all(b"\n"));
+
+        if let Err(err) = res {
+            warn!(error = %err, "could not write recording entry");
+        }
+    }
+
+    pub fn flush(&self) {
+        if let Some(inner) = self.inner.as_ref() {
+            if let Err(err) = inner.borrow_mut().writer.flush() {
+                warn!(error = %err, "could not flush recording");
+            }
+        }
+    }
+}
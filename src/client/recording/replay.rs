@@ -0,0 +1,117 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::client::recording::{Entry, Event, OutEvent};
+use crate::error::Res;
+
+const TICKS_PER_SECOND: usize = 20;
+
+pub fn read_entries(path: &Path) -> Res<Vec<Entry>> {
+    let reader = BufReader::new(File::open(path)?);
+
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+
+        // the last line might be cut off if the process was killed
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => warn!(error = %err, "skipping invalid entry"),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// only keep the entries in the last `seconds` of the recording (e.g., the seconds before a disconnect)
+pub fn last_seconds(entries: Vec<Entry>, seconds: u64) -> Vec<Entry> {
+    let last_tick = match entries.last() {
+        None => return entries,
+        Some(entry) => entry.tick
+    };
+
+    let from = last_tick.saturating_sub(seconds as usize * TICKS_PER_SECOND);
+    entries.into_iter().filter(|entry| entry.tick >= from).collect()
+}
+
+/// Print a recording as a timeline. Per-tick position packets are only printed when the position changes so the
+/// timeline stays readable.
+pub fn print_timeline(path: &Path, seconds: Option<u64>) -> Res {
+    let mut entries = read_entries(path)?;
+    if let Some(seconds) = seconds {
+        entries = last_seconds(entries, seconds);
+    }
+
+    let mut last_location = None;
+
+    for Entry { tick, event } in entries {
+        if let Event::Out(OutEvent::TeleportAndLook { location, .. }) = &event {
+            if last_location == Some(*location) {
+                continue;
+            }
+            last_location = Some(*location);
+        }
+
+        let seconds = tick as f64 / TICKS_PER_SECOND as f64;
+        println!("{:>8} ({:>8.2}s) {:?}", tick, seconds, event);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use crate::client::recording::{Event, InEvent, OutEvent, Recording};
+    use crate::client::recording::replay::{last_seconds, read_entries};
+    use crate::types::Location;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = std::env::temp_dir().join("swarm-bot-recording-test");
+        let recording = Recording::start(&dir, "abc").unwrap();
+
+        let mut called = false;
+        Recording::off().record(|| {
+            called = true;
+            Event::In(InEvent::Join)
+        });
+
+        // recording off should never construct the event
+        assert!(!called);
+
+        for tick in 0..200 {
+            recording.set_tick(tick);
+            recording.record(|| Event::Out(OutEvent::Teleport { location: Location::new(tick as f64, 0.0, 0.0) }));
+        }
+        recording.record(|| Event::In(InEvent::Disconnect { reason: "kicked".to_string() }));
+        recording.flush();
+
+        let entries = read_entries(&dir.join("abc.ndjson")).unwrap();
+        assert_eq!(entries.len(), 201);
+        assert_matches!(&entries[200].event, Event::In(InEvent::Disconnect { reason }) if reason == "kicked");
+
+        // 1 second = 20 ticks. The last tick is 199
+        let last = last_seconds(entries, 1);
+        assert_eq!(last.first().unwrap().tick, 179);
+        assert_eq!(last.len(), 22);
+    }
+}
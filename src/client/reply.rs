@@ -0,0 +1,990 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The typed output of information commands. The same [`Reply`] is rendered as text for chat and as JSON for the
+//! WebSocket, so machine consumers never have to parse human text.
+//!
+//! The JSON is part of the WebSocket API. Fields may be added but must not be renamed or removed.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use itertools::Itertools;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::bootstrap::block_data::BlockData;
+use crate::bootstrap::proxy::ProxyStatus;
+use crate::chat::format::Msg;
+use crate::client::afk::AfkSpot;
+use crate::client::bases::BaseSighting;
+use crate::client::bot::ProcessError;
+use crate::client::estimate::{Risk, TaskEstimate};
+use crate::client::metrics::MetricsSnapshot;
+use crate::client::routine::Step;
+use crate::client::state::global::waypoints::Waypoint;
+use crate::client::state::local::inventory::ItemStack;
+use crate::client::state::local::stats::BotStats;
+use crate::protocol::disconnect::{DisconnectReason, Kick};
+use crate::storage::block::{BlockApprox, BlockKind, BlockLocation};
+use crate::storage::blocks::ChunkLocation;
+use crate::storage::sign::SignText;
+use crate::types::{Dimension, Location};
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Item {
+    pub id: u32,
+    pub count: u8,
+}
+
+impl From<&ItemStack> for Item {
+    fn from(stack: &ItemStack) -> Self {
+        Item { id: stack.kind.id(), count: stack.count }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Block {
+    pub id: u32,
+    pub data: u8,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ProxyRow {
+    pub address: String,
+    pub health: String,
+    pub successes: u32,
+    pub failures: u32,
+    pub pinned: usize,
+}
+
+impl From<ProxyStatus> for ProxyRow {
+    fn from(status: ProxyStatus) -> Self {
+        ProxyRow {
+            address: status.address,
+            health: format!("{:?}", status.health).to_lowercase(),
+            successes: status.stats.successes,
+            failures: status.stats.failures,
+            pinned: status.pinned,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct KickRow {
+    pub name: String,
+    pub kicks: usize,
+
+    /// the reason of the latest kick
+    pub last: String,
+    pub banned: bool,
+}
+
+impl KickRow {
+    pub fn new(name: String, kicks: &[Kick]) -> KickRow {
+        KickRow {
+            name,
+            kicks: kicks.len(),
+            last: kicks.last().map(|kick| kick.reason.name().to_string()).unwrap_or_default(),
+            banned: kicks.iter().any(|kick| kick.reason == DisconnectReason::Banned),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BaseRow {
+    /// the center of the chunk section
+    pub location: BlockLocation,
+    pub score: f64,
+
+    /// block name -> count of the weighted blocks in the section
+    pub blocks: BTreeMap<String, u32>,
+}
+
+impl BaseRow {
+    pub fn new(sighting: &BaseSighting, data: &BlockData) -> BaseRow {
+        BaseRow {
+            location: sighting.center(),
+            score: sighting.score,
+            blocks: sighting.kinds.iter().map(|(kind, count)| (block_name(data, *kind), *count)).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FoundRow {
+    pub location: BlockLocation,
+
+    /// in blocks from the bot
+    pub distance: f64,
+
+    /// the mob of a spawner
+    pub mob: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WaypointRow {
+    pub name: String,
+    pub location: BlockLocation,
+    pub dimension: Dimension,
+
+    /// the player who set it through chat
+    pub set_by: Option<String>,
+}
+
+impl WaypointRow {
+    pub fn new(name: &str, waypoint: &Waypoint) -> WaypointRow {
+        WaypointRow {
+            name: name.to_string(),
+            location: waypoint.location,
+            dimension: waypoint.dimension,
+            set_by: waypoint.set_by.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SignRow {
+    pub location: BlockLocation,
+
+    /// the four lines as plain text
+    pub lines: SignText,
+}
+
+impl SignRow {
+    /// the location and the lines which are not empty
+    pub fn line(&self) -> String {
+        let lines = self.lines.iter().map(|line| line.trim()).filter(|line| !line.is_empty()).join(" | ");
+        format!("{} {}", self.location, lines)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PlayerRow {
+    pub name: String,
+
+    /// hex without dashes
+    pub uuid: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OnlineRow {
+    pub name: String,
+
+    /// milliseconds
+    pub ping: u32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct StatsRow {
+    pub ticks: u64,
+
+    /// block name -> count
+    pub mined: BTreeMap<String, u64>,
+    pub placed: BTreeMap<String, u64>,
+
+    /// task name -> blocks traveled
+    pub distance: BTreeMap<String, f64>,
+    pub deaths: Vec<String>,
+    pub disconnects: Vec<String>,
+}
+
+impl StatsRow {
+    pub fn new(stats: &BotStats, data: &BlockData) -> StatsRow {
+        let named = |counts: &std::collections::HashMap<BlockKind, u64>| {
+            counts.iter()
+                .map(|(kind, count)| (block_name(data, *kind), *count))
+                .collect()
+        };
+
+        StatsRow {
+            ticks: stats.ticks,
+            mined: named(&stats.mined),
+            placed: named(&stats.placed),
+            distance: stats.distance.iter().map(|(mode, dist)| (mode.to_string(), *dist)).collect(),
+            deaths: stats.deaths.clone(),
+            disconnects: stats.disconnects.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct EstimateRow {
+    pub blocks: usize,
+    pub ticks: usize,
+    pub seconds: f64,
+    pub distance: f64,
+
+    /// tool id -> durability used
+    pub tools: BTreeMap<u32, usize>,
+    pub food: f64,
+
+    /// block name -> count
+    pub required: BTreeMap<String, usize>,
+    pub produced: BTreeMap<String, usize>,
+    pub risks: Vec<Risk>,
+}
+
+impl EstimateRow {
+    pub fn new(estimate: TaskEstimate, data: &BlockData) -> EstimateRow {
+        let named = |counts: BTreeMap<u32, usize>| {
+            counts.into_iter()
+                .map(|(id, count)| (block_name(data, BlockKind::from(id)), count))
+                .collect()
+        };
+
+        EstimateRow {
+            blocks: estimate.blocks,
+            ticks: estimate.ticks,
+            seconds: estimate.duration().as_secs_f64(),
+            distance: estimate.distance,
+            food: estimate.food,
+            tools: estimate.tools,
+            required: named(estimate.required),
+            produced: named(estimate.produced),
+            risks: estimate.risks,
+        }
+    }
+}
+
+pub fn block_name(data: &BlockData, kind: BlockKind) -> String {
+    data.by_id(kind.id()).map(|block| block.name.clone()).unwrap_or_else(|| format!("#{}", kind.id()))
+}
+
+/// The result of an information command
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reply {
+    Health {
+        health: f32,
+        food: u8,
+    },
+    Location {
+        location: Location,
+        dimension: String,
+    },
+    State {
+        location: Location,
+        on_ground: bool,
+
+        /// the block id below the bot. None if the chunk is not loaded
+        below: Option<u32>,
+        hotbar: Vec<Option<Item>>,
+    },
+    Block {
+        location: BlockLocation,
+
+        /// None if the block is not known exactly
+        block: Option<Block>,
+
+        /// the estimated type of an unknown block
+        estimate: Option<String>,
+    },
+    Status {
+        username: String,
+        location: Location,
+        dimension: String,
+        health: f32,
+        food: u8,
+
+        /// the name of the running task
+        task: Option<String>,
+
+        /// the last task which gave up and why
+        failed: Option<String>,
+
+        /// the estimated ticks per second of the server
+        tps: Option<f64>,
+
+        /// how long a tick of our game loop takes. Over 50 ms the host cannot keep up.
+        tick_millis: Option<f64>,
+
+        /// the mean ping of the online players in milliseconds. High for everyone when the server struggles.
+        ping: Option<u32>,
+
+        /// counters of all bots since the start and gauges of the world
+        metrics: MetricsSnapshot,
+    },
+    Players {
+        players: Vec<PlayerRow>,
+    },
+    Online {
+        players: Vec<OnlineRow>,
+    },
+    Stats {
+        username: String,
+        stats: StatsRow,
+    },
+    Chunks {
+        loaded: usize,
+        generation: u64,
+
+        /// the chunk the bot is in and whether it is loaded
+        current: ChunkLocation,
+        current_loaded: bool,
+    },
+    Scan {
+        id: u32,
+        name: String,
+        count: usize,
+
+        /// closest first
+        nearest: Vec<BlockLocation>,
+    },
+    Routine {
+        name: String,
+        steps: Vec<Step>,
+    },
+    Queue {
+        /// the name of the running task
+        current: Option<String>,
+
+        /// the tasks which run after it, in order
+        queued: Vec<String>,
+    },
+    Proxies {
+        proxies: Vec<ProxyRow>,
+    },
+    Kicks {
+        accounts: Vec<KickRow>,
+    },
+    Bases {
+        /// the highest score first
+        bases: Vec<BaseRow>,
+    },
+    Signs {
+        signs: Vec<SignRow>,
+    },
+    Found {
+        name: String,
+
+        /// closest first
+        found: Vec<FoundRow>,
+    },
+    Waypoints {
+        /// by name
+        waypoints: Vec<WaypointRow>,
+    },
+    Estimate {
+        /// the command that was planned
+        task: String,
+        estimate: EstimateRow,
+    },
+    Afk {
+        /// the amount of spawn locations in the farm
+        farm_spots: usize,
+
+        /// best first
+        spots: Vec<AfkSpot>,
+    },
+    Verify {
+        schematic: String,
+
+        /// where the lowest corner of the schematic is
+        location: BlockLocation,
+        matches: usize,
+        missing: usize,
+        extra: usize,
+
+        /// blocks in columns which are not loaded
+        unknown: usize,
+    },
+    Export {
+        file: String,
+
+        /// the blocks (or columns of a map) which were saved
+        blocks: u64,
+
+        /// columns which are not loaded, saved as air
+        missing: usize,
+    },
+}
+
+impl Reply {
+    pub fn block(location: BlockLocation, approx: Option<BlockApprox>) -> Reply {
+        let (block, estimate) = match approx {
+            Some(BlockApprox::Realized(state)) => (Some(Block { id: state.id(), data: state.metadata() }), None),
+            Some(BlockApprox::Estimate(estimate)) => (None, Some(format!("{:?}", estimate).to_lowercase())),
+            None => (None, None),
+        };
+        Reply::Block { location, block, estimate }
+    }
+
+    /// The reply colored for chat or the terminal. Replies which found nothing, or plans with risks, are warnings.
+    pub fn to_msg(&self) -> Msg {
+        let warning = match self {
+            Reply::Block { block: None, estimate: None, .. } => true,
+            Reply::Scan { count: 0, .. } => true,
+            Reply::Found { found, .. } => found.is_empty(),
+            Reply::Estimate { estimate, .. } => !estimate.risks.is_empty(),
+            Reply::Verify { missing, extra, unknown, .. } => missing + extra + unknown > 0,
+            Reply::Export { missing, .. } => *missing > 0,
+            _ => false
+        };
+
+        if warning {
+            Msg::warning(self.to_string())
+        } else {
+            Msg::success(self.to_string())
+        }
+    }
+
+    /// The envelope sent to WebSocket clients. `id` is echoed back so requests can be matched with replies.
+    pub fn envelope(id: &Value, bot: &str, command: &str, result: &Result<Option<Reply>, ProcessError>) -> Value {
+        match result {
+            Ok(reply) => json!({ "id": id, "bot": bot, "command": command, "ok": true, "result": reply }),
+            Err(error) => json!({ "id": id, "bot": bot, "command": command, "ok": false, "error": error.to_string() }),
+        }
+    }
+}
+
+impl Display for Reply {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reply::Health { health, food } => write!(f, "Health: {}, Food: {}", health, food),
+            Reply::Location { location, dimension } => write!(f, "My location is {} in {}", location, dimension),
+            Reply::State { location, on_ground, below, hotbar } => {
+                writeln!(f, "location {}", location)?;
+                writeln!(f, "on ground {}", on_ground)?;
+                writeln!(f, "below kind {:?}", below)?;
+                write!(f, "inventory slots {:?}", hotbar)
+            }
+            Reply::Block { block, estimate, .. } => match (block, estimate) {
+                (Some(Block { id, data }), _) => write!(f, "The block is {}:{}", id, data),
+                (None, Some(estimate)) => write!(f, "The block is probably {}", estimate),
+                (None, None) => write!(f, "The block is not loaded"),
+            },
+            Reply::Status { username, location, dimension, health, food, task, failed, tps, tick_millis, ping, metrics } => {
+                let task = task.as_deref().unwrap_or("idle");
+                write!(f, "{} at {} in {} -- health {} food {} -- {}", username, location, dimension, health, food, task)?;
+                if let Some(failed) = failed {
+                    write!(f, " -- last failed: {}", failed)?;
+                }
+                if let Some(tps) = tps {
+                    write!(f, " -- {:.1} tps", tps)?;
+                }
+                if let Some(millis) = tick_millis {
+                    write!(f, " -- tick {:.1} ms", millis)?;
+                }
+                if let Some(ping) = ping {
+                    write!(f, " -- ping {} ms", ping)?;
+                }
+                write!(f, " -- {} chunks, {} packets in ({} KiB), {} out", metrics.chunks, metrics.packets_in, metrics.bytes_in / 1024, metrics.packets_out)
+            }
+            Reply::Players { players } => {
+                let names: Vec<_> = players.iter().map(|player| player.name.as_str()).collect();
+                write!(f, "{} players: {}", players.len(), names.join(", "))
+            }
+            Reply::Online { players } => {
+                let players: Vec<_> = players.iter().map(|player| format!("{} ({} ms)", player.name, player.ping)).collect();
+                write!(f, "{} online: {}", players.len(), players.join(", "))
+            }
+            Reply::Stats { username, stats } => {
+                let mined: u64 = stats.mined.values().sum();
+                let placed: u64 = stats.placed.values().sum();
+                let traveled: f64 = stats.distance.values().sum();
+                write!(f, "{}: {} ticks, mined {}, placed {}, traveled {:.0} blocks, died {} times", username, stats.ticks, mined, placed, traveled, stats.deaths.len())
+            }
+            Reply::Chunks { loaded, generation, current, current_loaded } => {
+                let state = if *current_loaded { "loaded" } else { "not loaded" };
+                write!(f, "{} chunks loaded (generation {}), current chunk {},{} is {}", loaded, generation, current.0, current.1, state)
+            }
+            Reply::Scan { name, count, nearest, .. } => match nearest.first() {
+                Some(closest) => write!(f, "{} {} loaded, closest at {}", count, name, closest),
+                None => write!(f, "There is no {} loaded", name),
+            },
+            Reply::Routine { name, steps } => {
+                let interactions = steps.iter().filter(|step| !matches!(step, Step::Goto { .. })).count();
+                write!(f, "recorded {}: {} waypoints, {} interactions", name, steps.len() - interactions, interactions)
+            }
+            Reply::Queue { current, queued } => {
+                write!(f, "{}", current.as_deref().unwrap_or("idle"))?;
+                if queued.is_empty() {
+                    return write!(f, ", nothing queued");
+                }
+                let rows: Vec<_> = queued.iter().enumerate().map(|(i, task)| format!("{}. {}", i + 1, task)).collect();
+                write!(f, ", then {}", rows.join(", "))
+            }
+            Reply::Proxies { proxies } => {
+                if proxies.is_empty() {
+                    return write!(f, "no proxies");
+                }
+                let rows: Vec<_> = proxies.iter()
+                    .map(|row| format!("{} {} ok {} failed {} pinned {}", row.address, row.health, row.successes, row.failures, row.pinned))
+                    .collect();
+                write!(f, "{}", rows.join("\n"))
+            }
+            Reply::Afk { farm_spots, spots } => {
+                if spots.is_empty() {
+                    return write!(f, "no spot covers the farm");
+                }
+                let rows: Vec<_> = spots.iter()
+                    .map(|spot| format!("{} covers {}/{} other {}", spot.location, spot.farm, farm_spots, spot.other))
+                    .collect();
+                write!(f, "{}", rows.join("\n"))
+            }
+            Reply::Verify { schematic, location, matches, missing, extra, unknown } => {
+                write!(f, "{} at {}: {} right, {} missing, {} extra", schematic, location, matches, missing, extra)?;
+                if *unknown > 0 {
+                    write!(f, ", {} not loaded", unknown)?;
+                }
+                Ok(())
+            }
+            Reply::Export { file, blocks, missing } => {
+                write!(f, "saved {} blocks to {}", blocks, file)?;
+                if *missing > 0 {
+                    write!(f, ", {} columns not loaded", missing)?;
+                }
+                Ok(())
+            }
+            Reply::Estimate { task, estimate } => {
+                let counts = |counts: &BTreeMap<String, usize>| counts.iter().map(|(name, count)| format!("{} x{}", name, count)).join(", ");
+
+                write!(f, "{}: {} blocks in {:.0}s, traveling {:.0} blocks, {:.1} food", task, estimate.blocks, estimate.seconds, estimate.distance, estimate.food)?;
+                if !estimate.tools.is_empty() {
+                    write!(f, "; tools {}", estimate.tools.iter().map(|(id, uses)| format!("#{} x{}", id, uses)).join(", "))?;
+                }
+                if !estimate.required.is_empty() {
+                    write!(f, "; needs {}", counts(&estimate.required))?;
+                }
+                if !estimate.produced.is_empty() {
+                    write!(f, "; produces {}", counts(&estimate.produced))?;
+                }
+                if !estimate.risks.is_empty() {
+                    write!(f, "; risks: {}", estimate.risks.iter().join(", "))?;
+                }
+                Ok(())
+            }
+            Reply::Kicks { accounts } => {
+                if accounts.is_empty() {
+                    return write!(f, "no kicks");
+                }
+                let rows: Vec<_> = accounts.iter()
+                    .map(|row| format!("{} kicked {} last {}{}", row.name, row.kicks, row.last, if row.banned { " (banned)" } else { "" }))
+                    .collect();
+                write!(f, "{}", rows.join("\n"))
+            }
+            Reply::Bases { bases } => {
+                if bases.is_empty() {
+                    return write!(f, "no bases");
+                }
+                let rows: Vec<_> = bases.iter()
+                    .map(|row| {
+                        let blocks = row.blocks.iter()
+                            .sorted_by_key(|(_, count)| std::cmp::Reverse(**count))
+                            .map(|(name, count)| format!("{} {}", count, name))
+                            .join(", ");
+                        format!("{} score {:.0}: {}", row.location, row.score, blocks)
+                    })
+                    .collect();
+                write!(f, "{}", rows.join("\n"))
+            }
+            Reply::Signs { signs } => {
+                if signs.is_empty() {
+                    return write!(f, "no signs");
+                }
+                write!(f, "{}", signs.iter().map(SignRow::line).join("\n"))
+            }
+            Reply::Found { name, found } => {
+                if found.is_empty() {
+                    return write!(f, "There is no {} nearby", name);
+                }
+                let rows: Vec<_> = found.iter()
+                    .map(|row| {
+                        let mob = row.mob.as_ref().map(|mob| format!(" spawning {}", mob)).unwrap_or_default();
+                        format!("{} at {} ({:.0} blocks){}", name, row.location, row.distance, mob)
+                    })
+                    .collect();
+                write!(f, "{}", rows.join("\n"))
+            }
+            Reply::Waypoints { waypoints } => {
+                if waypoints.is_empty() {
+                    return write!(f, "no waypoints");
+                }
+                let rows: Vec<_> = waypoints.iter()
+                    .map(|row| {
+                        let by = row.set_by.as_ref().map(|by| format!(" set by {}", by)).unwrap_or_default();
+                        format!("{} at {} in the {}{}", row.name, row.location, row.dimension, by)
+                    })
+                    .collect();
+                write!(f, "{}", rows.join("\n"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::SystemTime;
+
+    use serde_json::{json, Value};
+
+    use crate::client::afk::AfkSpot;
+    use crate::client::bot::ProcessError;
+    use crate::client::estimate::Risk;
+    use crate::client::metrics::MetricsSnapshot;
+    use crate::client::reply::{BaseRow, Block, EstimateRow, FoundRow, Item, KickRow, OnlineRow, PlayerRow, ProxyRow, Reply, SignRow, StatsRow, WaypointRow};
+    use crate::client::routine::Step;
+    use crate::protocol::disconnect::{DisconnectPatterns, Kick};
+    use crate::storage::block::BlockLocation;
+    use crate::storage::blocks::ChunkLocation;
+    use crate::types::{Dimension, Location};
+
+    fn to_json(reply: &Reply) -> Value {
+        serde_json::to_value(reply).unwrap()
+    }
+
+    fn location() -> Location {
+        Location::new(1.5, 64.0, -2.5)
+    }
+
+    #[test]
+    fn test_health() {
+        let reply = Reply::Health { health: 20.0, food: 18 };
+        assert_eq!(to_json(&reply), json!({"type": "health", "health": 20.0, "food": 18}));
+        assert_eq!(reply.to_string(), "Health: 20, Food: 18");
+        assert_eq!(reply.to_msg().to_legacy(), "§aHealth: 20, Food: 18");
+    }
+
+    #[test]
+    fn test_location() {
+        let reply = Reply::Location { location: location(), dimension: "overworld".to_string() };
+        assert_eq!(to_json(&reply), json!({"type": "location", "location": {"x": 1.5, "y": 64.0, "z": -2.5}, "dimension": "overworld"}));
+    }
+
+    #[test]
+    fn test_state() {
+        let reply = Reply::State {
+            location: location(),
+            on_ground: true,
+            below: Some(1),
+            hotbar: vec![Some(Item { id: 278, count: 1 }), None],
+        };
+        assert_eq!(to_json(&reply), json!({
+            "type": "state",
+            "location": {"x": 1.5, "y": 64.0, "z": -2.5},
+            "on_ground": true,
+            "below": 1,
+            "hotbar": [{"id": 278, "count": 1}, null]
+        }));
+    }
+
+    #[test]
+    fn test_block() {
+        let location = BlockLocation::new(1, 2, 3);
+        let reply = Reply::Block { location, block: Some(Block { id: 1, data: 2 }), estimate: None };
+        assert_eq!(to_json(&reply), json!({"type": "block", "location": {"x": 1, "y": 2, "z": 3}, "block": {"id": 1, "data": 2}, "estimate": null}));
+        assert_eq!(reply.to_string(), "The block is 1:2");
+
+        let reply = Reply::block(location, None);
+        assert_eq!(to_json(&reply), json!({"type": "block", "location": {"x": 1, "y": 2, "z": 3}, "block": null, "estimate": null}));
+    }
+
+    #[test]
+    fn test_status() {
+        let reply = Reply::Status {
+            username: "bot".to_string(),
+            location: location(),
+            dimension: "nether".to_string(),
+            health: 10.0,
+            food: 20,
+            task: Some("mine".to_string()),
+            failed: None,
+            tps: Some(19.5),
+            tick_millis: Some(12.25),
+            ping: Some(85),
+            metrics: MetricsSnapshot {
+                packets_in: 120,
+                packets_out: 40,
+                bytes_in: 300 * 1024,
+                chunks_in: 12,
+                path_searches: 3,
+                blocks_mined: 7,
+                disconnects: 1,
+                chunks: 49,
+                entities: 5,
+                resident_bytes: None,
+            },
+        };
+        assert_eq!(reply.to_string(), "bot at [1.50 64.00 -2.50] in nether -- health 10 food 20 -- mine -- 19.5 tps -- tick 12.2 ms -- ping 85 ms -- 49 chunks, 120 packets in (300 KiB), 40 out");
+        assert_eq!(to_json(&reply), json!({
+            "type": "status",
+            "username": "bot",
+            "location": {"x": 1.5, "y": 64.0, "z": -2.5},
+            "dimension": "nether",
+            "health": 10.0,
+            "food": 20,
+            "task": "mine",
+            "failed": null,
+            "tps": 19.5,
+            "tick_millis": 12.25,
+            "ping": 85,
+            "metrics": {
+                "packets_in": 120,
+                "packets_out": 40,
+                "bytes_in": 307200,
+                "chunks_in": 12,
+                "path_searches": 3,
+                "blocks_mined": 7,
+                "disconnects": 1,
+                "chunks": 49,
+                "entities": 5,
+                "resident_bytes": null
+            }
+        }));
+    }
+
+    #[test]
+    fn test_players() {
+        let reply = Reply::Players { players: vec![PlayerRow { name: "Notch".to_string(), uuid: format!("{:032x}", 15_u128) }] };
+        assert_eq!(to_json(&reply), json!({"type": "players", "players": [{"name": "Notch", "uuid": "0000000000000000000000000000000f"}]}));
+        assert_eq!(reply.to_string(), "1 players: Notch");
+    }
+
+    #[test]
+    fn test_online() {
+        let reply = Reply::Online { players: vec![
+            OnlineRow { name: "Notch".to_string(), ping: 40 },
+            OnlineRow { name: "jeb_".to_string(), ping: 120 },
+        ] };
+        assert_eq!(to_json(&reply), json!({"type": "online", "players": [{"name": "Notch", "ping": 40}, {"name": "jeb_", "ping": 120}]}));
+        assert_eq!(reply.to_string(), "2 online: Notch (40 ms), jeb_ (120 ms)");
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut mined = BTreeMap::new();
+        mined.insert("stone".to_string(), 3);
+
+        let mut distance = BTreeMap::new();
+        distance.insert("idle".to_string(), 2.0);
+
+        let stats = StatsRow {
+            ticks: 100,
+            mined,
+            distance,
+            deaths: vec!["fall".to_string()],
+            ..StatsRow::default()
+        };
+
+        let reply = Reply::Stats { username: "bot".to_string(), stats };
+        assert_eq!(to_json(&reply), json!({
+            "type": "stats",
+            "username": "bot",
+            "stats": {
+                "ticks": 100,
+                "mined": {"stone": 3},
+                "placed": {},
+                "distance": {"idle": 2.0},
+                "deaths": ["fall"],
+                "disconnects": []
+            }
+        }));
+    }
+
+    #[test]
+    fn test_chunks() {
+        let reply = Reply::Chunks { loaded: 49, generation: 7, current: ChunkLocation(0, -1), current_loaded: true };
+        assert_eq!(to_json(&reply), json!({"type": "chunks", "loaded": 49, "generation": 7, "current": [0, -1], "current_loaded": true}));
+    }
+
+    #[test]
+    fn test_scan() {
+        let reply = Reply::Scan { id: 56, name: "diamond_ore".to_string(), count: 2, nearest: vec![BlockLocation::new(1, 12, 1)] };
+        assert_eq!(to_json(&reply), json!({"type": "scan", "id": 56, "name": "diamond_ore", "count": 2, "nearest": [{"x": 1, "y": 12, "z": 1}]}));
+
+        let reply = Reply::Scan { id: 56, name: "diamond_ore".to_string(), count: 0, nearest: vec![] };
+        assert_eq!(reply.to_string(), "There is no diamond_ore loaded");
+
+        // finding nothing is a warning
+        assert_eq!(reply.to_msg().to_legacy(), "§6There is no diamond_ore loaded");
+    }
+
+    #[test]
+    fn test_routine() {
+        let location = BlockLocation::new(1, 64, 2);
+        let reply = Reply::Routine { name: "door".to_string(), steps: vec![Step::Goto { location }, Step::Use { location }] };
+        assert_eq!(to_json(&reply), json!({
+            "type": "routine",
+            "name": "door",
+            "steps": [{"type": "goto", "location": {"x": 1, "y": 64, "z": 2}}, {"type": "use", "location": {"x": 1, "y": 64, "z": 2}}]
+        }));
+        assert_eq!(reply.to_string(), "recorded door: 1 waypoints, 1 interactions");
+    }
+
+    #[test]
+    fn test_queue() {
+        let reply = Reply::Queue { current: Some("CompoundTask".to_string()), queued: vec!["BlockTravelTask".to_string(), "CompoundTask".to_string()] };
+        assert_eq!(to_json(&reply), json!({"type": "queue", "current": "CompoundTask", "queued": ["BlockTravelTask", "CompoundTask"]}));
+        assert_eq!(reply.to_string(), "CompoundTask, then 1. BlockTravelTask, 2. CompoundTask");
+
+        let idle = Reply::Queue { current: None, queued: vec![] };
+        assert_eq!(idle.to_string(), "idle, nothing queued");
+    }
+
+    #[test]
+    fn test_proxies() {
+        let row = ProxyRow { address: "1.2.3.4:1080".to_string(), health: "healthy".to_string(), successes: 3, failures: 1, pinned: 2 };
+        let reply = Reply::Proxies { proxies: vec![row] };
+        assert_eq!(to_json(&reply), json!({
+            "type": "proxies",
+            "proxies": [{"address": "1.2.3.4:1080", "health": "healthy", "successes": 3, "failures": 1, "pinned": 2}]
+        }));
+    }
+
+    #[test]
+    fn test_estimate() {
+        let estimate = EstimateRow {
+            blocks: 2,
+            ticks: 40,
+            seconds: 2.0,
+            distance: 10.0,
+            tools: vec![(278, 2)].into_iter().collect(),
+            food: 0.25,
+            required: BTreeMap::new(),
+            produced: vec![("stone".to_string(), 2)].into_iter().collect(),
+            risks: vec![Risk::Fluid { location: BlockLocation::new(1, 2, 3), lava: true }],
+        };
+        let reply = Reply::Estimate { task: "mine 1 1 3".to_string(), estimate };
+
+        assert_eq!(to_json(&reply), json!({
+            "type": "estimate",
+            "task": "mine 1 1 3",
+            "estimate": {
+                "blocks": 2, "ticks": 40, "seconds": 2.0, "distance": 10.0, "tools": {"278": 2}, "food": 0.25,
+                "required": {}, "produced": {"stone": 2},
+                "risks": [{"type": "fluid", "location": {"x": 1, "y": 2, "z": 3}, "lava": true}]
+            }
+        }));
+        assert_eq!(reply.to_string(), "mine 1 1 3: 2 blocks in 2s, traveling 10 blocks, 0.2 food; tools #278 x2; produces stone x2; risks: lava at [1, 2, 3]");
+    }
+
+    #[test]
+    fn test_afk() {
+        let spot = AfkSpot { location: BlockLocation::new(0, 140, 0), farm: 81, other: 2 };
+        let reply = Reply::Afk { farm_spots: 81, spots: vec![spot] };
+        assert_eq!(to_json(&reply), json!({
+            "type": "afk",
+            "farm_spots": 81,
+            "spots": [{"location": {"x": 0, "y": 140, "z": 0}, "farm": 81, "other": 2}]
+        }));
+        assert_eq!(reply.to_string(), format!("{} covers 81/81 other 2", spot.location));
+        assert_eq!(Reply::Afk { farm_spots: 81, spots: vec![] }.to_string(), "no spot covers the farm");
+    }
+
+    #[test]
+    fn test_export() {
+        let reply = Reply::Export { file: "base.schematic".to_string(), blocks: 4096, missing: 2 };
+        assert_eq!(to_json(&reply)["missing"], json!(2));
+        assert_eq!(reply.to_string(), "saved 4096 blocks to base.schematic, 2 columns not loaded");
+        assert!(reply.to_msg().to_legacy().starts_with("§6"));
+    }
+
+    #[test]
+    fn test_verify() {
+        let location = BlockLocation::new(10, 64, -20);
+        let reply = Reply::Verify { schematic: "house.schematic".to_string(), location, matches: 950, missing: 50, extra: 0, unknown: 0 };
+        assert_eq!(to_json(&reply)["missing"], json!(50));
+        assert_eq!(reply.to_string(), format!("house.schematic at {}: 950 right, 50 missing, 0 extra", location));
+        assert!(reply.to_msg().to_legacy().starts_with("§6"));
+    }
+
+    #[test]
+    fn test_kicks() {
+        let patterns = DisconnectPatterns::default();
+        let kick = |raw: &str| {
+            let disconnect = patterns.classify(raw);
+            Kick { reason: disconnect.reason, text: disconnect.text, at: SystemTime::UNIX_EPOCH }
+        };
+
+        let row = KickRow::new("bob".to_string(), &[kick("You are banned"), kick("Timed out")]);
+        let reply = Reply::Kicks { accounts: vec![row] };
+        assert_eq!(to_json(&reply), json!({
+            "type": "kicks",
+            "accounts": [{"name": "bob", "kicks": 2, "last": "timed out", "banned": true}]
+        }));
+        assert_eq!(reply.to_string(), "bob kicked 2 last timed out (banned)");
+    }
+
+    #[test]
+    fn test_bases() {
+        let mut blocks = BTreeMap::new();
+        blocks.insert("chest".to_string(), 4);
+        blocks.insert("furnace".to_string(), 6);
+
+        let row = BaseRow { location: BlockLocation::new(8, 72, 24), score: 52.0, blocks };
+        let reply = Reply::Bases { bases: vec![row] };
+        assert_eq!(to_json(&reply), json!({
+            "type": "bases",
+            "bases": [{"location": {"x": 8, "y": 72, "z": 24}, "score": 52.0, "blocks": {"chest": 4, "furnace": 6}}]
+        }));
+        assert_eq!(reply.to_string(), "[8, 72, 24] score 52: 6 furnace, 4 chest");
+        assert_eq!(Reply::Bases { bases: vec![] }.to_string(), "no bases");
+    }
+
+    #[test]
+    fn test_signs() {
+        let lines = ["home".to_string(), "".to_string(), "100 64 -20".to_string(), "".to_string()];
+        let row = SignRow { location: BlockLocation::new(3, 65, 7), lines };
+        let reply = Reply::Signs { signs: vec![row] };
+        assert_eq!(to_json(&reply), json!({
+            "type": "signs",
+            "signs": [{"location": {"x": 3, "y": 65, "z": 7}, "lines": ["home", "", "100 64 -20", ""]}]
+        }));
+        assert_eq!(reply.to_string(), "[3, 65, 7] home | 100 64 -20");
+        assert_eq!(Reply::Signs { signs: vec![] }.to_string(), "no signs");
+    }
+
+    #[test]
+    fn test_found() {
+        let row = FoundRow { location: BlockLocation::new(10, 20, -30), distance: 41.6, mob: Some("zombie".to_string()) };
+        let reply = Reply::Found { name: "mob_spawner".to_string(), found: vec![row] };
+        assert_eq!(to_json(&reply), json!({
+            "type": "found",
+            "name": "mob_spawner",
+            "found": [{"location": {"x": 10, "y": 20, "z": -30}, "distance": 41.6, "mob": "zombie"}]
+        }));
+        assert_eq!(reply.to_string(), "mob_spawner at [10, 20, -30] (42 blocks) spawning zombie");
+
+        let reply = Reply::Found { name: "portal".to_string(), found: vec![] };
+        assert_eq!(reply.to_string(), "There is no portal nearby");
+        assert_eq!(reply.to_msg().to_legacy(), "§6There is no portal nearby");
+    }
+
+    #[test]
+    fn test_waypoints() {
+        let row = WaypointRow { name: "base".to_string(), location: BlockLocation::new(10, 64, -20), dimension: Dimension::Nether, set_by: Some("Steve".to_string()) };
+        let reply = Reply::Waypoints { waypoints: vec![row] };
+        assert_eq!(to_json(&reply), json!({
+            "type": "waypoints",
+            "waypoints": [{"name": "base", "location": {"x": 10, "y": 64, "z": -20}, "dimension": "nether", "set_by": "Steve"}]
+        }));
+        assert_eq!(reply.to_string(), "base at [10, 64, -20] in the nether set by Steve");
+        assert_eq!(Reply::Waypoints { waypoints: vec![] }.to_string(), "no waypoints");
+    }
+
+    #[test]
+    fn test_envelope() {
+        let reply = Reply::Health { health: 20.0, food: 20 };
+        let ok = Reply::envelope(&json!(1), "bot", "health", &Ok(Some(reply)));
+        assert_eq!(ok, json!({"id": 1, "bot": "bot", "command": "health", "ok": true, "result": {"type": "health", "health": 20.0, "food": 20}}));
+
+        let parse_err = "x".parse::<i32>().unwrap_err();
+        let err = Reply::envelope(&Value::Null, "bot", "goto", &Err(ProcessError::from(parse_err)));
+        assert_eq!(err, json!({"id": null, "bot": "bot", "command": "goto", "ok": false, "error": "invalid digit found in string"}));
+    }
+}
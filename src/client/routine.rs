@@ -0,0 +1,303 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Routines taught by demonstration. `#record start` makes a bot watch the player who sent the command. Their
+//! positions and the blocks they change are captured until `#record stop <name>`, when the observation is distilled
+//! into a [`Routine`] any bot can execute with `#run <name>`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::block::{BlockLocation, BlockState};
+use crate::types::Location;
+
+/// block changes further than this from the player are not attributed to them
+const REACH: f64 = 6.0;
+
+/// positions closer than this to the simplified path are treated as jitter
+const PATH_TOLERANCE: f64 = 0.75;
+
+/// consecutive waypoints closer than this (squared) are merged
+const MIN_SPACING2: f64 = 2.0 * 2.0;
+
+/// changes to vertically adjacent blocks this many ticks apart are one interaction (doors, tall plants)
+const MERGE_TICKS: usize = 2;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Step {
+    /// walk to the block
+    Goto { location: BlockLocation },
+    Break { location: BlockLocation },
+
+    /// place a block on top of the block below `location`
+    Place { location: BlockLocation },
+
+    /// right click the block (doors, levers, trapdoors, ...)
+    Use { location: BlockLocation },
+}
+
+impl Step {
+    pub fn location(self) -> BlockLocation {
+        match self {
+            Step::Goto { location } | Step::Break { location } | Step::Place { location } | Step::Use { location } => location
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Routine {
+    pub steps: Vec<Step>,
+}
+
+/// a block change attributed to the player
+#[derive(Debug, Copy, Clone)]
+struct Observation {
+    tick: usize,
+    location: BlockLocation,
+
+    /// None if the block was not known before
+    from: Option<BlockState>,
+    to: BlockState,
+}
+
+/// What a bot saw a player do while recording
+#[derive(Debug)]
+pub struct Demonstration {
+    /// the name of the player being watched
+    pub player: String,
+    samples: Vec<(usize, Location)>,
+    observations: Vec<Observation>,
+}
+
+fn is_air(state: BlockState) -> bool {
+    state.kind() == BlockState::AIR.kind()
+}
+
+/// the distance of `point` to the segment from `a` to `b`
+fn segment_dist2(point: Location, a: Location, b: Location) -> f64 {
+    let ab = b - a;
+    let len2 = ab.mag2();
+    if len2 == 0.0 {
+        return point.dist2(a);
+    }
+    let ap = point - a;
+    let t = ((ap.dx * ab.dx + ap.dy * ab.dy + ap.dz * ab.dz) / len2).clamp(0.0, 1.0);
+    point.dist2(a + ab * t)
+}
+
+/// Ramer–Douglas–Peucker. Marks the points which are needed to keep the path within `tolerance` of the original.
+fn simplify(points: &[Location], tolerance: f64, keep: &mut [bool]) {
+    if points.len() < 3 {
+        return;
+    }
+
+    let first = points[0];
+    let last = points[points.len() - 1];
+
+    let (idx, dist2) = points.iter().enumerate()
+        .take(points.len() - 1)
+        .skip(1)
+        .map(|(idx, point)| (idx, segment_dist2(*point, first, last)))
+        .fold((0, 0.0), |best, current| if current.1 > best.1 { current } else { best });
+
+    if dist2 > tolerance * tolerance {
+        keep[idx] = true;
+        simplify(&points[..=idx], tolerance, &mut keep[..=idx]);
+        simplify(&points[idx..], tolerance, &mut keep[idx..]);
+    }
+}
+
+impl Demonstration {
+    pub fn new(player: impl Into<String>) -> Demonstration {
+        Demonstration {
+            player: player.into(),
+            samples: Vec::new(),
+            observations: Vec::new(),
+        }
+    }
+
+    /// the position of the player at `tick`
+    pub fn sample(&mut self, tick: usize, location: Location) {
+        self.samples.push((tick, location));
+    }
+
+    /// A block changed. It is attributed to the player if it is within reach of them.
+    pub fn block_change(&mut self, tick: usize, location: BlockLocation, from: Option<BlockState>, to: BlockState) {
+        let player = match self.samples.last() {
+            Some((_, player)) => *player,
+            None => return
+        };
+
+        if location.center_bottom().dist2(player) > REACH * REACH {
+            return;
+        }
+
+        self.observations.push(Observation { tick, location, from, to });
+    }
+
+    fn location_at(&self, tick: usize) -> Option<Location> {
+        let idx = self.samples.partition_point(|(at, _)| *at <= tick);
+        let idx = idx.checked_sub(1).unwrap_or_default();
+        self.samples.get(idx).map(|(_, location)| *location)
+    }
+
+    /// the interactions in order with changes belonging to the same interaction merged
+    fn interactions(&self) -> Vec<(usize, Step)> {
+        let mut res: Vec<(usize, Step)> = Vec::new();
+
+        for obs in &self.observations {
+            let location = obs.location;
+            let step = match obs.from {
+                Some(from) if !is_air(from) && is_air(obs.to) => Step::Break { location },
+                Some(from) if is_air(from) && !is_air(obs.to) => Step::Place { location },
+                Some(from) if from != obs.to => Step::Use { location },
+                _ => continue
+            };
+
+            if let Some((tick, previous)) = res.last() {
+                let same_kind = std::mem::discriminant(previous) == std::mem::discriminant(&step);
+                let prev = previous.location();
+                let adjacent = prev.x == location.x && prev.z == location.z && (prev.y - location.y).abs() <= 1;
+                if same_kind && adjacent && obs.tick - *tick <= MERGE_TICKS {
+                    continue;
+                }
+            }
+
+            res.push((obs.tick, step));
+        }
+
+        res
+    }
+
+    /// Turn the observation into clean steps. The path is simplified into waypoints and every interaction is
+    /// preceded by a waypoint where the player stood when they did it.
+    pub fn distill(&self) -> Routine {
+        let points: Vec<_> = self.samples.iter().map(|(_, location)| *location).collect();
+
+        // the start and end of the path are always waypoints
+        let mut keep = vec![false; points.len()];
+        if let Some(first) = keep.first_mut() {
+            *first = true;
+        }
+        if let Some(last) = keep.last_mut() {
+            *last = true;
+        }
+        simplify(&points, PATH_TOLERANCE, &mut keep);
+
+        let mut timeline: Vec<(usize, Step)> = self.samples.iter().zip(keep)
+            .filter(|(_, keep)| *keep)
+            .map(|((tick, location), _)| (*tick, Step::Goto { location: BlockLocation::from(*location) }))
+            .collect();
+
+        for (tick, step) in self.interactions() {
+            if let Some(location) = self.location_at(tick) {
+                timeline.push((tick, Step::Goto { location: BlockLocation::from(location) }));
+            }
+            timeline.push((tick, step));
+        }
+
+        // stable so an interaction stays after its waypoint
+        timeline.sort_by_key(|(tick, _)| *tick);
+
+        let mut steps: Vec<Step> = Vec::new();
+        for (_, step) in timeline {
+            if let (Step::Goto { location }, Some(Step::Goto { location: previous })) = (step, steps.last_mut()) {
+                let dist2 = location.center_bottom().dist2(previous.center_bottom());
+                if dist2 < MIN_SPACING2 {
+                    // keep the later position, which is where the player ended up
+                    *previous = location;
+                    continue;
+                }
+            }
+            steps.push(step);
+        }
+
+        Routine { steps }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::routine::{Demonstration, Step};
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::types::Location;
+
+    /// the player walks east along z = 0 with a bit of jitter, turns north at x = 20, and on the way mines a block,
+    /// opens a door and places a block
+    #[test]
+    fn test_distill() {
+        let mut demo = Demonstration::new("Notch");
+        let jitter = |tick: usize| if tick % 2 == 0 { 0.2 } else { -0.2 };
+
+        let mut tick = 0;
+        for i in 0..=40 {
+            demo.sample(tick, Location::new(0.5 + i as f64 * 0.5, 64.0, 0.5 + jitter(tick)));
+
+            if i == 10 {
+                // mined stone next to the path
+                let location = BlockLocation::new(5, 64, 2);
+                demo.block_change(tick, location, Some(BlockState::from(1, 0)), BlockState::AIR);
+            }
+            tick += 1;
+        }
+
+        // standing still for a while
+        for _ in 0..20 {
+            demo.sample(tick, Location::new(20.5 + jitter(tick) / 2.0, 64.0, 0.5));
+            tick += 1;
+        }
+
+        // opened a door: both halves change in the same tick
+        let door = BlockLocation::new(21, 64, 0);
+        demo.block_change(tick, door, Some(BlockState::from(64, 0)), BlockState::from(64, 4));
+        demo.block_change(tick, door.above(), Some(BlockState::from(64, 8)), BlockState::from(64, 9));
+
+        // a block change far away is someone else's
+        demo.block_change(tick, BlockLocation::new(100, 64, 100), Some(BlockState::AIR), BlockState::from(1, 0));
+
+        for i in 0..=20 {
+            demo.sample(tick, Location::new(20.5 + jitter(tick), 64.0, 0.5 - i as f64 * 0.5));
+            tick += 1;
+        }
+
+        demo.block_change(tick, BlockLocation::new(20, 64, -11), Some(BlockState::AIR), BlockState::from(4, 0));
+        demo.sample(tick, Location::new(20.5, 64.0, -9.5));
+
+        let routine = demo.distill();
+
+        let goto = |x, z| Step::Goto { location: BlockLocation::new(x, 64, z) };
+        assert_eq!(routine.steps, vec![
+            goto(0, 0),
+            goto(5, 0),
+            Step::Break { location: BlockLocation::new(5, 64, 2) },
+            goto(20, 0),
+            Step::Use { location: door },
+            goto(20, -10),
+            Step::Place { location: BlockLocation::new(20, 64, -11) },
+        ]);
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut demo = Demonstration::new("Notch");
+        assert!(demo.distill().steps.is_empty());
+
+        // changes before the player was seen cannot be attributed
+        demo.block_change(0, BlockLocation::new(0, 64, 0), Some(BlockState::AIR), BlockState::from(1, 0));
+        demo.sample(1, Location::new(0.5, 64.0, 0.5));
+        assert_eq!(demo.distill().steps, vec![Step::Goto { location: BlockLocation::new(0, 64, 0) }]);
+    }
+}
@@ -15,27 +15,55 @@
  */
 
 use std::cell::RefCell;
-use std::default::default;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
+use futures::FutureExt;
+use serde_json::json;
 use tokio::sync::Notify;
-
-use crate::bootstrap::Connection;
-use crate::client::bot::{ActionState, Bot, run_threaded};
+use tokio::sync::mpsc::{Receiver, UnboundedSender};
+use tracing::Instrument;
+
+use crate::bootstrap::{Address, Connection};
+use crate::bootstrap::mojang::RateLimit;
+use crate::bootstrap::schedule::LoginScheduler;
+use crate::bootstrap::storage::ProxyUser;
+use crate::bootstrap::proxy::ProxyPool;
+use crate::client::bases::{BaseConfig, BaseDetector};
+use crate::client::bot::{ActionState, Bot, process_command, run_threaded};
+use crate::client::clock::TickClock;
+use crate::client::reply::Reply;
+use crate::client::shutdown::{Shutdown, SHUTDOWN_TIMEOUT};
+use crate::client::snapshot::{SavedTask, Snapshot, SNAPSHOT_INTERVAL};
+use crate::client::view::EVICT_INTERVAL;
 use crate::client::commands::{Command, Commands, Selection2D};
+use crate::client::controller::ControllerFactory;
+use crate::client::digest::{Digest, DIGEST_INTERVAL, DigestOptions, webhook};
+use crate::client::formation::Formation;
+use crate::client::permissions::Permissions;
+use crate::client::state::global::waypoints::Waypoints;
 use crate::client::processor::SimpleInterfaceIn;
+use crate::client::recording::{Recording, RecordingIn, RecordingOut};
 use crate::client::state::global::GlobalState;
 use crate::client::state::global::mine_alloc::MinePreference;
 use crate::client::state::local::LocalState;
-use crate::client::tasks::attack_entity::AttackEntity;
+use crate::client::swarm::{COMBAT_TAG_TICKS, Scale, Swarm};
+use crate::client::tasks::combat::{CombatConfig, CombatTask, Target};
 use crate::client::tasks::lazy_stream::LazyStream;
 use crate::client::tasks::mine_region::MineRegion;
 use crate::client::tasks::navigate::BlockTravelTask;
+use crate::client::tunables::Tunables;
+use crate::client::wire::Topic;
 
 use crate::error::{Res, ResBox};
-use crate::protocol::{EventQueue, Login, Minecraft};
+use crate::protocol::{EventQueue, InterfaceOut, Login, Minecraft};
+use crate::protocol::disconnect::{Disconnect, Reconnect};
+use crate::storage::block::BlockLocation;
+use crate::storage::blocks::ChunkLocation;
+use crate::types::Dimension;
 
 
 struct SyncGlobal(*const GlobalState);
@@ -51,7 +79,8 @@ unsafe impl Sync for SyncLocal {}
 unsafe impl Send for SyncLocal {}
 
 
-pub type Logins<T> = Rc<RefCell<Vec<Login<<T as Minecraft>::Queue, <T as Minecraft>::Interface>>>>;
+/// established logins and the user they are for
+pub type Logins<T> = Rc<RefCell<Vec<(Login<<T as Minecraft>::Queue, <T as Minecraft>::Interface>, ProxyUser)>>>;
 
 /// Runs the game loop and holds all bots.
 pub struct Runner<T: Minecraft> {
@@ -64,115 +93,358 @@ pub struct Runner<T: Minecraft> {
     commands: Commands,
 
     /// the bots created by pending logins
-    bots: Vec<Bot<T::Queue, T::Interface>>,
+    bots: Vec<Bot<T::Queue, RecordingOut<T::Interface>>>,
+
+    /// where to write session recordings. None if recording is off
+    recording_dir: Option<PathBuf>,
+
+    /// where each bot stands around the last goto
+    formation: Formation,
+
+    /// the stats of the current digest window
+    digest: Digest,
+    digest_opts: DigestOptions,
+    last_digest: Instant,
+
+    /// where the task queues are saved
+    snapshot: PathBuf,
+    last_snapshot: Instant,
+    last_eviction: Instant,
+
+    /// the saved tasks of bots which are not online by username. A bot takes them back when it logs in.
+    parked: HashMap<String, Vec<SavedTask>>,
 
     /// An id counter that increases for each bot. Used as a unique identifier.
     id_on: u32,
+
+    /// the user of every bot by username. Used to log bots back in.
+    users: HashMap<String, ProxyUser>,
+
+    /// users to log in again after the delay
+    relogin: UnboundedSender<(ProxyUser, Duration)>,
+
+    /// a clone of the scheduler of the login task, to change how fast it logs in
+    scheduler: LoginScheduler,
+
+    /// where `#reload-config` reads the settings from
+    config_file: Option<PathBuf>,
+
+    /// the settings of the command line, which the config file takes precedence over
+    cli_config: Tunables,
+
+    /// valid users as they are obtained. They go to the reserve of the swarm.
+    new_users: Receiver<ProxyUser>,
+
+    /// how many bots should be online and the accounts not in use
+    swarm: Swarm<ProxyUser>,
+
+    /// set on Ctrl-C or SIGTERM
+    shutdown: Shutdown,
+
+    /// makes the controller of each bot. None if the bots are only commanded through chat.
+    controllers: Option<ControllerFactory>,
 }
 
 /// Runner launch options
 pub struct RunnerOptions {
-    /// The amount of milliseconds to wait between logging in successive users
-    pub delay_millis: u64,
+    /// The settings which can change while running
+    pub config: Tunables,
+
+    /// The file the settings are reloaded from. None if there is no config file.
+    pub config_file: Option<PathBuf>,
+
+    /// The settings given on the command line
+    pub cli_config: Tunables,
+
+    /// The directory to record bot sessions to. None if sessions should not be recorded
+    pub recording_dir: Option<PathBuf>,
+
+    /// Where to send session digests
+    pub digest: DigestOptions,
+
+    /// The proxies bots connect through. Only used for statistics in the runner.
+    pub proxies: ProxyPool,
+
+    /// Shared with the Mojang clients of the users, so logins pause while Mojang rate limits us
+    pub rate_limit: RateLimit,
+
+    /// Which blocks make a chunk section look like a player base
+    pub bases: BaseConfig,
+
+    /// Who may command the bots through chat
+    pub permissions: Permissions,
+
+    /// The waypoints saved by previous runs
+    pub waypoints: Waypoints,
+
+    /// Where the task queues are saved on shutdown and every [`SNAPSHOT_INTERVAL`]
+    pub snapshot: PathBuf,
+
+    /// The snapshot of a previous run to carry on from. Empty unless resuming.
+    pub resume: Snapshot,
+
+    /// How many bots to keep online. The other users are kept in reserve for `#swarm add`.
+    pub bots: usize,
+
+    /// Makes the controller of each bot that logs in. None if the bots are only commanded through chat.
+    pub controllers: Option<ControllerFactory>,
+
+    /// The websocket commands for this server
+    pub commands: Commands,
+
+    /// Shared by the runners of every server
+    pub shutdown: Shutdown,
 }
 
 impl<T: Minecraft + 'static> Runner<T> {
     /// Start the runner process
-    pub async fn run(connections: tokio::sync::mpsc::Receiver<Connection>, opts: RunnerOptions) -> Res {
-        let mut runner = Runner::<T>::init(connections, opts).await?;
+    pub async fn run(address: Address, users: tokio::sync::mpsc::Receiver<ProxyUser>, opts: RunnerOptions) -> Res {
+        let mut runner = Runner::<T>::init(address, users, opts).await?;
         runner.game_loop().await;
         Ok(())
     }
 
 
-    /// Initialize the runner. Go through the handshake process for each [`Connection`]
-    async fn init(mut connections: tokio::sync::mpsc::Receiver<Connection>, opts: RunnerOptions) -> Res<Runner<T>> {
-        let commands = Commands::init().await?;
-
-        let RunnerOptions { delay_millis } = opts;
+    /// Initialize the runner. Connect and go through the handshake process for each user as scheduled by the
+    /// [`LoginScheduler`]
+    async fn init(address: Address, users: Receiver<ProxyUser>, opts: RunnerOptions) -> Res<Runner<T>> {
+        let RunnerOptions { config, config_file, cli_config, recording_dir, digest, proxies, rate_limit, bases, permissions, waypoints, snapshot, resume, bots, controllers, commands, shutdown } = opts;
         let pending_logins = Rc::new(RefCell::new(Vec::new()));
+        let scheduler = LoginScheduler::with_rate_limit(config.login.clone(), rate_limit);
+        let kicks = scheduler.kicks().clone();
+        let chat = config.chat.clone();
+
+        let (relogin, mut to_login) = tokio::sync::mpsc::unbounded_channel::<(ProxyUser, Duration)>();
+        let swarm = Swarm::new(bots);
 
         {
             let pending_logins = pending_logins.clone();
+            let logging_in = swarm.logging_in();
+            let scheduler = scheduler.clone();
+            let disconnects = config.login.disconnects.clone();
 
             // login task for all users
             tokio::task::spawn_local(async move {
-                while let Some(connection) = connections.recv().await {
+                while let Some((user, delay)) = to_login.recv().await {
                     let logins = pending_logins.clone();
+                    let scheduler = scheduler.clone();
+                    let address = address.clone();
+                    let chat = chat.clone();
+                    let disconnects = disconnects.clone();
+                    let logging_in = logging_in.clone();
 
-                    // login task for an individual user
+                    // login task for an individual user. Waits until the scheduler lets it start
                     tokio::task::spawn_local(async move {
-                        println!("Starting login of {}", connection.user.username);
-                        let username = connection.user.username.clone();
-                        let login = match T::login(connection).await {
-                            Ok(res) => {
-                                println!("Finished logging in {}", username);
-                                res
-                            }
-                            Err(err) => {
-                                println!("Error logging in {} -- {}", username, err);
-                                return;
+                        tokio::time::sleep(delay).await;
+
+                        let username = user.user.username.clone();
+                        let span = info_span!("login", name = %username);
+                        let login = scheduler.run(&username, || {
+                            let user = user.clone();
+                            let address = address.clone();
+                            let chat = chat.clone();
+                            let disconnects = disconnects.clone();
+                            async move {
+                                info!("starting login");
+                                let mut connection = Connection::connect(address, user).await?;
+                                connection.chat = chat;
+                                connection.disconnects = disconnects;
+                                T::login(connection).await
                             }
-                        };
-                        logins.borrow_mut().push(login);
-                    });
+                        }).instrument(span.clone()).await;
 
-                    // if we want a delay between logging in
-                    tokio::time::sleep(Duration::from_millis(delay_millis)).await;
+                        if let Some(login) = login {
+                            info!(parent: &span, "logged in");
+                            logins.borrow_mut().push((login, user));
+                        }
+                        logging_in.set(logging_in.get() - 1);
+                    });
                 }
             });
         }
 
+        let mut global_state = GlobalState::init();
+        global_state.proxies = proxies;
+        global_state.kicks = kicks;
+        global_state.bases = BaseDetector::new(bases);
+        global_state.permissions = permissions;
+        global_state.waypoints = waypoints;
+        global_state.config = config;
+        global_state.mine.restore(resume.mine);
+
         Ok(Runner {
             pending_logins,
-            global_state: GlobalState::init(),
+            global_state,
             commands,
             bots: Vec::new(),
             id_on: 0,
+            recording_dir,
+            formation: Formation::default(),
+            digest: Digest::default(),
+            digest_opts: digest,
+            last_digest: Instant::now(),
+            snapshot,
+            last_snapshot: Instant::now(),
+            last_eviction: Instant::now(),
+            parked: resume.bots,
+            users: HashMap::new(),
+            relogin,
+            scheduler,
+            config_file,
+            cli_config,
+            new_users: users,
+            swarm,
+            shutdown,
+            controllers,
         })
     }
 
 
     pub async fn game_loop(&mut self) {
-        let mut previous_goal = Instant::now();
+        let mut clock = TickClock::new(Instant::now());
 
-        // a game loop repeating every 50 ms
+        // a game loop repeating every 50 ms until a shutdown. Missed ticks are caught up.
         loop {
-            let end_by = previous_goal + Duration::from_millis(50);
-            self.game_iter(end_by).await;
-            tokio::time::sleep_until(tokio::time::Instant::from_std(end_by)).await;
-            let now = Instant::now();
-            let difference = now - end_by;
-            let millis_off = difference.as_millis();
+            if self.shutdown.requested() {
+                self.shut_down().await;
+                return;
+            }
+
+            let start = Instant::now();
 
             // log if we are wayyyy off
+            let millis_off = start.saturating_duration_since(clock.next()).as_millis();
             if millis_off > 100 {
-                println!("off by {}ms", millis_off);
+                warn!(millis_off = millis_off as u64, "the game loop is behind");
             }
 
-            previous_goal = end_by;
+            let ticks = clock.due(start);
+
+            self.game_iter(ticks, clock.next()).await;
+            clock.record(ticks, start.elapsed());
+            self.global_state.tick_millis = clock.tick_millis();
+
+            tokio::time::sleep_until(tokio::time::Instant::from_std(clock.next())).await;
         }
     }
 
-    async fn game_iter(&mut self, end_by: Instant) {
+    /// Disconnect every bot cleanly and write the digest. Connections which do not close within [`SHUTDOWN_TIMEOUT`]
+    /// are given up on.
+    async fn shut_down(&mut self) {
+        info!(bots = self.bots.len(), "disconnecting");
+
+        // the digest takes the stats of the bots still online
+        self.emit_digest();
+        self.save_snapshot();
+
+        let closing: Vec<_> = self.bots.iter_mut()
+            .map(|bot| {
+                bot.actions.clear();
+                bot.out.close()
+            })
+            .collect();
+
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, futures::future::join_all(closing)).await.is_err() {
+            warn!(timeout = ?SHUTDOWN_TIMEOUT, "some connections did not close");
+        }
+
+        self.bots.clear();
+    }
+
+    /// run `ticks` ticks of the bots (more than one to catch up) and the expensive part until `end_by`
+    async fn game_iter(&mut self, ticks: usize, end_by: Instant) {
+        if std::mem::take(&mut self.global_state.reload_config) {
+            self.reload_config();
+        }
+
+        self.global_state.ticks += ticks;
+        self.global_state.prune_changes();
+
+        while let Some(Some(user)) = self.new_users.recv().now_or_never() {
+            self.swarm.offer(user);
+        }
+
         let old_count = self.bots.len();
         // first step: removing disconnected clients
         {
-            self.bots.retain(|client| !client.state.disconnected);
+            let digest = &mut self.digest;
+            let global = &mut self.global_state;
+            let mut disconnected = Vec::new();
+            self.bots.retain(|client| {
+                if client.state.disconnected {
+                    digest.add(&client.state.info.username, client.state.stats.clone());
+                    let tasks = client.actions.save(&client.state);
+                    disconnected.push((client.state.info.username.clone(), client.state.disconnect.clone(), client.state.bot_id, tasks));
+                    global.members.remove(&client.state.info.uuid.0);
+                    global.guards.release(client.state.bot_id);
+                    global.release_columns(client.state.bot_id);
+                }
+                !client.state.disconnected
+            });
+
+            for (username, disconnect, bot_id, tasks) in disconnected {
+                if self.swarm.is_leaving(&username) {
+                    if let Some(user) = self.users.remove(&username) {
+                        self.swarm.left(&username, user);
+                    }
+                } else {
+                    // the bot comes back with another id, so someone else finishes its region
+                    self.global_state.mine.release(bot_id);
+                    if !tasks.is_empty() {
+                        self.parked.insert(username.clone(), tasks);
+                    }
+                    self.reconnect(&username, disconnect);
+                }
+            }
         }
 
         // second step: turning pending logins into clients
         {
             let mut logins = self.pending_logins.borrow_mut();
 
-            for login in logins.drain(..) {
-                let Login { queue, out, info } = login;
+            for (login, user) in logins.drain(..) {
+                let Login { queue, mut out, info } = login;
+                self.users.insert(info.username.clone(), user);
+
+                self.digest.login(&info.username);
+                self.global_state.members.insert(info.uuid.0);
+
+                let recording = match self.recording_dir.as_ref() {
+                    None => Recording::off(),
+                    Some(dir) => Recording::start(dir, &info.username).unwrap_or_else(|err| {
+                        warn!(name = %info.username, error = %err, "could not start recording");
+                        Recording::off()
+                    })
+                };
+
+                let controller = self.controllers.as_mut().map(|make| make(&info));
+
+                // the login may have started with settings which were reloaded since
+                let config = &self.global_state.config;
+                let mut state = LocalState::new(self.id_on, info);
+                state.inventory.armor = config.armor.clone();
+                state.inventory.policy = config.inventory.clone();
+                state.physics.set_rotation(config.rotation.clone());
+                out.set_chat_config(config.chat.clone());
+                out.set_budget(config.budget.clone());
+
+                let mut actions = ActionState::default();
+                if let Some(tasks) = self.parked.remove(&state.info.username) {
+                    info!(name = %state.info.username, tasks = tasks.len(), "resuming the tasks from before");
+                    actions.resume(tasks, &state, &self.global_state);
+                }
 
                 let client = Bot {
-                    state: LocalState::new(self.id_on, info),
-                    actions: default(),
+                    state,
+                    actions,
                     queue,
-                    out,
+                    out: RecordingOut::new(out, recording.clone()),
+                    recording,
+                    last_task: None,
+                    sneaking: false,
+                    sprinting: false,
+                    events: Vec::new(),
+                    controller,
                 };
                 self.id_on += 1;
                 self.bots.push(client);
@@ -184,28 +456,87 @@ impl<T: Minecraft + 'static> Runner<T> {
 
         // log clients if they have changed
         if new_count != old_count {
-            println!("{} clients", new_count);
+            info!(clients = new_count, "clients changed");
+        }
+
+        // a digest is emitted daily and when the last bot is gone
+        let all_gone = old_count > 0 && new_count == 0;
+        if all_gone || self.last_digest.elapsed() >= DIGEST_INTERVAL {
+            self.emit_digest();
+        }
+
+        if self.last_snapshot.elapsed() >= SNAPSHOT_INTERVAL {
+            self.save_snapshot();
         }
 
         // process pending commands (from forge mod)
         while let Ok(command) = self.commands.pending.try_recv() {
             if let Err(err) = self.process_command(command) {
-                println!("Error processing command: {}", err)
+                warn!(error = %err, "could not process command")
             }
         }
 
+        let requests: Vec<_> = self.global_state.swarm.drain().collect();
+        for scale in requests {
+            self.scale(scale);
+        }
+        self.update_swarm();
+
         // fourth step: process packets from game loop
         for bot in &mut self.bots {
+            let span = bot.state.span.clone();
+            let _enter = span.enter();
+
+            self.global_state.enter(bot.state.dimension);
             let mut processor = SimpleInterfaceIn::new(&mut bot.state, &mut bot.actions, &mut self.global_state, &mut bot.out);
+            let mut processor = RecordingIn::new(&mut processor, &bot.recording);
 
             // protocol-specific logic. Translates input packets and sends to processor
             bot.queue.flush(&mut processor);
 
-            // fifth step: general sync logic that isn't dependent on protocol implementation
-            bot.run_sync(&mut self.global_state);
+            // fifth step: general sync logic that isn't dependent on protocol implementation. Each tick moves as far
+            // as one tick may.
+            for _ in 0..ticks {
+                bot.run_sync(&mut self.global_state);
+            }
+
+            let name = &bot.state.info.username;
+            let location = bot.state.physics.location();
+            self.commands.publish(Topic::Position, name, || json!({ "type": "position", "bot": name, "location": location }));
+
+            for event in bot.events.drain(..) {
+                self.commands.publish(Topic::Event, name, || json!({ "type": "event", "bot": name, "event": event }));
+            }
+        }
+
+        if self.last_eviction.elapsed() >= EVICT_INTERVAL {
+            self.evict_columns();
+        }
+
+        // the index of interesting blocks gets a small share of the time so many new columns do not stall the loop
+        let index_end = (Instant::now() + self.global_state.config.index.budget).min(end_by);
+        self.global_state.index_chunks(index_end);
+
+        // sixth step: run multi-threaded environment for the rest of the game loop. GlobalState will be read-only and LocalState will be mutable.
+        // Bots in different dimensions need a different world, so each dimension gets its share of the time.
+        let mut dimensions = Vec::new();
+        for bot in &self.bots {
+            if !dimensions.contains(&bot.state.dimension) {
+                dimensions.push(bot.state.dimension);
+            }
         }
 
-        // sixth step: run multi-threaded environment for the rest of the game loop. GlobalState will be read-only and LocalState will be mutable
+        let count = dimensions.len();
+        for (idx, dimension) in dimensions.into_iter().enumerate() {
+            self.global_state.enter(dimension);
+            let now = Instant::now();
+            let share = end_by.saturating_duration_since(now) / (count - idx) as u32;
+            self.threaded_iter(dimension, now + share).await;
+        }
+    }
+
+    /// run the expensive part of the tasks of the bots in `dimension` on multiple threads until `end_by`
+    async fn threaded_iter(&mut self, dimension: Dimension, end_by: Instant) {
         let thread_loop_end = Arc::new(Notify::new());
 
         {
@@ -217,6 +548,7 @@ impl<T: Minecraft + 'static> Runner<T> {
             // safe as it still requires the states to be Send+Sync, so it is hard to make errors.
             let global_state_sync = SyncGlobal(&self.global_state);
             let states_sync: Vec<_> = self.bots.iter_mut()
+                .filter(|bot| bot.state.dimension == dimension)
                 .map(|bot| (&mut bot.state, &mut bot.actions))
                 .map(|(state, actions)| (state as *mut LocalState, actions as *mut ActionState))
                 .map(SyncLocal)
@@ -231,6 +563,8 @@ impl<T: Minecraft + 'static> Runner<T> {
                         let (state, actions) = unsafe { (&mut *state, &mut *actions) };
 
                         s.spawn(move |inner_scope| {
+                            let span = state.span.clone();
+                            let _enter = span.enter();
                             run_threaded(inner_scope, state, actions, global_state, end_by);
                         });
                     }
@@ -245,6 +579,228 @@ impl<T: Minecraft + 'static> Runner<T> {
         thread_loop_end.notified().await;
     }
 
+    fn emit_digest(&mut self) {
+        self.last_digest = Instant::now();
+
+        if !self.digest_opts.enabled() {
+            return;
+        }
+
+        for bot in &mut self.bots {
+            self.digest.add(&bot.state.info.username, std::mem::take(&mut bot.state.stats));
+        }
+
+        let now = SystemTime::now();
+        let report = self.digest.report(now, &self.global_state.block_data);
+        self.digest.reset(now);
+
+        if let Some(dir) = self.digest_opts.dir.as_ref() {
+            match report.write_to(dir) {
+                Ok(path) => info!(path = %path.display(), "wrote digest"),
+                Err(err) => warn!(error = %err, "could not write digest"),
+            }
+        }
+
+        if let Some(url) = self.digest_opts.webhook.clone() {
+            let payload = report.webhook_payload();
+            tokio::task::spawn_local(async move {
+                if let Err(err) = webhook::post(&url, &payload).await {
+                    warn!(error = %err, "could not post digest");
+                }
+            });
+        }
+    }
+
+    /// Write the task queues and the regions left to mine. Bots which are offline keep the tasks they had.
+    fn save_snapshot(&mut self) {
+        self.last_snapshot = Instant::now();
+
+        let mut bots = self.parked.clone();
+        for bot in &self.bots {
+            let tasks = bot.actions.save(&bot.state);
+            if !tasks.is_empty() {
+                bots.insert(bot.state.info.username.clone(), tasks);
+            }
+        }
+
+        let snapshot = Snapshot { bots, mine: self.global_state.mine.save() };
+        match snapshot.save(&self.snapshot) {
+            Ok(()) => debug!(path = %self.snapshot.display(), bots = snapshot.bots.len(), "wrote snapshot"),
+            Err(err) => warn!(path = %self.snapshot.display(), error = %err, "could not write snapshot"),
+        }
+    }
+
+    /// drop the columns outside the view of every bot, so the memory of the world follows the view distances
+    fn evict_columns(&mut self) {
+        self.last_eviction = Instant::now();
+
+        let distance = self.global_state.config.view.distance;
+        let views: Vec<_> = self.bots.iter()
+            .map(|bot| {
+                let center = ChunkLocation::from(BlockLocation::from(bot.state.physics.location()));
+                (bot.state.dimension, center, bot.state.view.distance().unwrap_or(distance) as i32)
+            })
+            .collect();
+
+        let dropped = self.global_state.evict_columns(&views);
+        if dropped > 0 {
+            debug!(dropped, "dropped columns out of view");
+        }
+    }
+
+    /// Read the config file again and give the login scheduler and every bot the new settings. The current settings
+    /// are kept if the file cannot be read.
+    fn reload_config(&mut self) {
+        let path = match self.config_file.as_ref() {
+            Some(path) => path,
+            None => {
+                warn!("there is no config file to reload. Start with --config.");
+                return;
+            }
+        };
+
+        let config = match Tunables::load(path, &self.cli_config) {
+            Ok(config) => config,
+            Err(err) => {
+                warn!(path = %path.display(), error = %err, "could not reload the config");
+                return;
+            }
+        };
+
+        self.scheduler.set_config(config.login.clone());
+        for bot in &mut self.bots {
+            bot.state.inventory.armor = config.armor.clone();
+            bot.state.inventory.policy = config.inventory.clone();
+            bot.state.physics.set_rotation(config.rotation.clone());
+            bot.out.set_chat_config(config.chat.clone());
+            bot.out.set_budget(config.budget.clone());
+        }
+
+        self.global_state.config = config;
+        info!(path = %path.display(), "reloaded the config");
+    }
+
+    /// Log the bot back in if the reason it was disconnected for allows it
+    fn reconnect(&mut self, username: &str, disconnect: Option<Disconnect>) {
+        let disconnect = match disconnect {
+            Some(disconnect) => disconnect,
+            None => return
+        };
+
+        let config = &self.global_state.config.login;
+        let delay = match disconnect.reason.reconnect() {
+            Reconnect::Never => return,
+            Reconnect::Soon => config.restart_delay,
+            Reconnect::Backoff => {
+                // doubled for every time the account was disconnected before
+                let kicks = self.global_state.kicks.count(username).saturating_sub(1).min(16) as u32;
+                (config.backoff * 2_u32.pow(kicks)).min(config.max_backoff)
+            }
+        };
+
+        if let Some(user) = self.users.remove(username) {
+            info!(name = %username, ?delay, reason = disconnect.reason.name(), "reconnecting");
+            if self.relogin.send((user, delay)).is_ok() {
+                self.swarm.login_started();
+            }
+        }
+    }
+
+    /// Change how many bots should be online
+    fn scale(&mut self, scale: Scale) {
+        let online: Vec<_> = self.bots.iter().map(|bot| (bot.state.info.username.as_str(), bot.state.ticks)).collect();
+        let status = self.swarm.status(online.len());
+
+        match &scale {
+            Scale::Add(count) if *count > status.reserve => {
+                warn!(count, reserve = status.reserve, "swarm: adding more bots than accounts in reserve");
+            }
+            Scale::Add(count) => info!(count, "swarm: adding bots"),
+            Scale::Remove(count) => info!(count, "swarm: removing bots"),
+            Scale::RemoveNamed(names) => info!(names = %names.join(", "), "swarm: removing bots"),
+            Scale::Status => {}
+        }
+
+        let leaving = self.swarm.apply(scale, &online);
+        self.start_leaving(&leaving);
+        self.report_swarm();
+    }
+
+    /// Wind down bots over the target, disconnect the ones which are ready and log in bots missing from the target
+    fn update_swarm(&mut self) {
+        let online: Vec<_> = self.bots.iter().map(|bot| (bot.state.info.username.as_str(), bot.state.ticks)).collect();
+        let leaving = self.swarm.wind_down(&online);
+        self.start_leaving(&leaving);
+
+        let mut changed = !leaving.is_empty();
+
+        // leaving bots stay paused and do not hold on to a region even if a command gave them a task
+        let global = &mut self.global_state;
+        let swarm = &self.swarm;
+        for bot in self.bots.iter_mut().filter(|bot| swarm.is_leaving(&bot.state.info.username)) {
+            bot.actions.clear();
+            global.mine.release(bot.state.bot_id);
+        }
+
+        let digest = &mut self.digest;
+        let mut left = Vec::new();
+        self.bots.retain(|bot| {
+            let state = &bot.state;
+            let leave = swarm.can_leave(&state.info.username, state.ticks, state.last_hurt);
+            if leave {
+                digest.add(&state.info.username, state.stats.clone());
+                global.release_columns(state.bot_id);
+                left.push(state.info.username.clone());
+            }
+            !leave
+        });
+
+        for username in left {
+            info!(name = %username, "swarm: left");
+            if let Some(user) = self.users.remove(&username) {
+                self.swarm.left(&username, user);
+            }
+            changed = true;
+        }
+
+        let staying = self.bots.iter().filter(|bot| !self.swarm.is_leaving(&bot.state.info.username)).count();
+        for user in self.swarm.logins(staying) {
+            info!(name = %user.user.username, "swarm: logging in");
+            if self.relogin.send((user, Duration::ZERO)).is_err() {
+                self.swarm.logging_in().set(self.swarm.logging_in().get() - 1);
+            }
+            changed = true;
+        }
+
+        if changed {
+            self.report_swarm();
+        }
+    }
+
+    /// Pause the bots and give their regions back so other bots mine them
+    fn start_leaving(&mut self, usernames: &[String]) {
+        for bot in self.bots.iter_mut().filter(|bot| usernames.contains(&bot.state.info.username)) {
+            bot.actions.clear();
+            self.global_state.mine.release(bot.state.bot_id);
+
+            let waiting = bot.state.last_hurt.map_or(false, |hurt| bot.state.ticks < hurt + COMBAT_TAG_TICKS);
+            info!(parent: &bot.state.span, waiting_for_combat = waiting, "swarm: leaving");
+        }
+    }
+
+    fn report_swarm(&mut self) {
+        let status = self.swarm.status(self.bots.len());
+        info!("swarm: {}", status);
+        self.commands.publish(Topic::Event, "swarm", || json!({
+            "type": "swarm",
+            "online": status.online,
+            "target": status.target,
+            "logging_in": status.logging_in,
+            "leaving": status.leaving,
+            "reserve": status.reserve,
+        }));
+    }
+
     fn process_command(&mut self, command: Command) -> ResBox {
         let global = &mut self.global_state;
         let bots = &mut self.bots;
@@ -259,17 +815,41 @@ impl<T: Minecraft + 'static> Runner<T> {
                 }
             }
             Command::GoTo(goto) => {
+                let positions: Vec<_> = bots.iter()
+                    .map(|bot| (bot.state.info.username.as_str(), BlockLocation::from(bot.state.physics.location())))
+                    .collect();
+                let targets = self.formation.assign(&global.blocks, goto.location, goto.formation, &positions);
+
                 for bot in bots {
-                    bot.actions.schedule(BlockTravelTask::new(goto.location, &bot.state));
+                    let target = targets.get(&bot.state.info.username).copied().unwrap_or(goto.location);
+                    bot.actions.schedule(BlockTravelTask::new(target, &bot.state));
                 }
             }
             Command::Attack(attack) => {
                 let player = self.global_state.players.by_name(&attack.name).ok_or("player does not exist")?;
                 let entity_id = self.global_state.entities.by_player_uuid(player.uuid).ok_or("could not find entity id for player")?;
 
+                let config = CombatConfig { cooldown: attack.cooldown, ..CombatConfig::default() };
+                for bot in bots {
+                    bot.actions.schedule(CombatTask::new(Target::Entity(entity_id), config.clone()))
+                }
+            }
+            Command::Run { run, reply } => {
+                let args: Vec<&str> = run.args.iter().map(String::as_str).collect();
+
+                let bots = bots.iter_mut()
+                    .filter(|bot| run.bot.as_ref().map_or(true, |name| name == &bot.state.info.username));
+
                 for bot in bots {
-                    let task = LazyStream::from(AttackEntity::new(entity_id));
-                    bot.actions.schedule(task)
+                    let span = bot.state.span.clone();
+                    let _enter = span.enter();
+
+                    global.enter(bot.state.dimension);
+                    let res = process_command(&run.name, &args, None, &mut bot.state, global, &mut bot.actions, &mut bot.out);
+                    let envelope = Reply::envelope(&run.id, &bot.state.info.username, &run.name, &res);
+
+                    // the connection may have closed in the meantime
+                    let _ = reply.send(envelope);
                 }
             }
         }
@@ -0,0 +1,68 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Shutting down on Ctrl-C or SIGTERM so servers see the bots disconnect instead of timing out
+
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+/// how long connections get to send what is queued and close before we exit anyway
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// resolves on the next Ctrl-C or SIGTERM
+#[cfg(unix)]
+async fn signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("could not listen for SIGTERM");
+    futures::future::select(Box::pin(tokio::signal::ctrl_c()), Box::pin(terminate.recv())).await;
+}
+
+#[cfg(not(unix))]
+async fn signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Whether a shutdown was asked for. The first signal asks the runners to shut down, a second one exits right away
+/// in case a shutdown hangs. Clones share the listener so every runner of the process sees the same signal.
+#[derive(Clone)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    pub fn listen() -> Shutdown {
+        let (tx, rx) = watch::channel(false);
+
+        tokio::task::spawn(async move {
+            signal().await;
+            info!("shutting down... (again to exit right away)");
+            let _ = tx.send(true);
+
+            signal().await;
+            info!("exiting");
+            std::process::exit(130);
+        });
+
+        Shutdown { rx }
+    }
+
+    /// true from the first signal on
+    pub fn requested(&self) -> bool {
+        *self.rx.borrow()
+    }
+}
@@ -0,0 +1,661 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A bot without a server for testing tasks. The task under test and physics step like in the game loop and what the
+//! bot sends is applied to the world like a server would. [`replay`] runs seeded swarms from a script.
+
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::time::{Duration, Instant};
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+use crate::client::bot::Bot;
+use crate::client::processor::InterfaceIn;
+use crate::client::recording::Recording;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::abilities::Abilities;
+use crate::client::state::local::inventory::ItemStack;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::Task;
+use crate::protocol::{Closing, EventQueue, Face, Hand, InterfaceOut, InvAction, Mine};
+use crate::protocol::budget::BudgetConfig;
+use crate::protocol::chat_queue::ChatConfig;
+use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+use crate::storage::blocks::WorldBlocks;
+use crate::storage::entities::EntityKind;
+use crate::types::{Direction, Displacement, Location};
+
+pub mod replay;
+
+/// how long the expensive part of a task may take each tick
+const EXPENSIVE_TIME: Duration = Duration::from_millis(50);
+
+/// The time seeded runs give the expensive part of a task. Long enough that it finishes its work, so how fast the
+/// machine is does not change the tick a path is found in.
+const SEEDED_EXPENSIVE_TIME: Duration = Duration::from_secs(10);
+
+/// the ticks vanilla takes to eat
+const EAT_TICKS: usize = 32;
+
+/// the size of the box of a player
+const PLAYER_WIDTH: f64 = 0.6;
+const PLAYER_HEIGHT: f64 = 1.8;
+
+/// how far beyond the box of a player items are picked up
+const PICKUP_REACH: f64 = 1.0;
+
+/// the id of the first item dropped. Far from the ids of bots.
+const FIRST_ITEM: u32 = 1000;
+
+/// how hard players standing inside each other push each other away
+const PUSH: f64 = 0.05;
+
+/// the furthest the server accepts attacks on an entity the bot can see from
+const ATTACK_REACH: f64 = 6.0;
+
+/// something the bot sent. Movement is left out as the simulation knows where the bot is.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Call {
+    PlaceBlock { against: BlockLocation, face: Face },
+    AttackEntity(u32),
+    Chat(String),
+    Inventory,
+    SwingArm,
+    FinishEating,
+    UseItem(Hand),
+    ChangeSlot(u8),
+    Mine { location: BlockLocation, mine: Mine },
+    Respawn,
+    Sneak(bool),
+    Sprint(bool),
+    StartGliding,
+    LeaveBed,
+    Fly(bool),
+    CloseWindow(u8),
+    EditBook,
+    SignBook { title: String },
+    ViewDistance(u8),
+}
+
+/// records what the bot sends instead of sending it
+#[derive(Default)]
+pub struct SimOut {
+    /// every call and the tick it was made in
+    pub calls: Vec<(usize, Call)>,
+    tick: usize,
+}
+
+impl SimOut {
+    fn push(&mut self, call: Call) {
+        self.calls.push((self.tick, call));
+    }
+
+    /// the ticks `call` was made in
+    pub fn ticks_of(&self, call: &Call) -> Vec<usize> {
+        self.calls.iter().filter(|(_, made)| made == call).map(|(tick, _)| *tick).collect()
+    }
+}
+
+impl InterfaceOut for SimOut {
+    fn place_block(&mut self, against: BlockLocation, face: Face) {
+        self.push(Call::PlaceBlock { against, face });
+    }
+
+    fn attack_entity(&mut self, id: u32) {
+        self.push(Call::AttackEntity(id));
+    }
+
+    fn send_chat(&mut self, message: &str) {
+        self.push(Call::Chat(message.to_string()));
+    }
+
+    fn set_chat_config(&mut self, _config: ChatConfig) {}
+
+    fn set_budget(&mut self, _config: BudgetConfig) {}
+
+    fn flush_budget(&mut self) {}
+
+    fn deferred(&self) -> usize {
+        0
+    }
+
+    fn set_view_distance(&mut self, chunks: u8) {
+        self.push(Call::ViewDistance(chunks));
+    }
+
+    fn inventory_action(&mut self, _action: InvAction) {
+        self.push(Call::Inventory);
+    }
+
+    fn swing_arm(&mut self) {
+        self.push(Call::SwingArm);
+    }
+
+    fn finish_eating(&mut self) {
+        self.push(Call::FinishEating);
+    }
+
+    fn use_item(&mut self, hand: Hand) {
+        self.push(Call::UseItem(hand));
+    }
+
+    fn change_slot(&mut self, number: u8) {
+        self.push(Call::ChangeSlot(number));
+    }
+
+    fn mine(&mut self, location: BlockLocation, mine: Mine, _face: Face) {
+        self.push(Call::Mine { location, mine });
+    }
+
+    fn respawn(&mut self) {
+        self.push(Call::Respawn);
+    }
+
+    fn teleport(&mut self, _location: Location) {}
+
+    fn look(&mut self, _direction: Direction) {}
+
+    fn teleport_and_look(&mut self, _location: Location, _direction: Direction, _on_ground: bool) {}
+
+    fn sneak(&mut self, sneaking: bool) {
+        self.push(Call::Sneak(sneaking));
+    }
+
+    fn sprint(&mut self, sprinting: bool) {
+        self.push(Call::Sprint(sprinting));
+    }
+
+    fn start_gliding(&mut self) {
+        self.push(Call::StartGliding);
+    }
+
+    fn leave_bed(&mut self) {
+        self.push(Call::LeaveBed);
+    }
+
+    fn abilities(&mut self, abilities: Abilities) {
+        self.push(Call::Fly(abilities.flying));
+    }
+
+    fn close_window(&mut self, window: u8) {
+        self.push(Call::CloseWindow(window));
+    }
+
+    fn edit_book(&mut self, _pages: &[String]) {
+        self.push(Call::EditBook);
+    }
+
+    fn sign_book(&mut self, _pages: &[String], title: &str, _author: &str) {
+        self.push(Call::SignBook { title: title.to_string() });
+    }
+
+    fn close(&mut self) -> Closing {
+        Box::pin(async {})
+    }
+}
+
+/// there is no server, so there are no packets
+pub struct NoPackets;
+
+impl EventQueue for NoPackets {
+    fn flush(&mut self, _processor: &mut impl InterfaceIn) {}
+}
+
+/// A bot in a world of its own. [`SimulatedClient::tick`] runs a tick of the game loop for it.
+pub struct SimulatedClient {
+    pub bot: Bot<NoPackets, SimOut>,
+    pub global: GlobalState,
+
+    /// where the bot was at the start of each tick
+    pub positions: Vec<Location>,
+
+    /// the damage the server would have done for landing
+    pub fall_damage: f32,
+
+    /// the items the bot picked up, in order
+    pub picked_up: Vec<u32>,
+
+    /// the entities the server took an attack on, in order
+    pub attacked: Vec<u32>,
+
+    /// how many calls were applied to the world
+    applied: usize,
+
+    /// the items on the ground with where they are
+    items: Vec<(u32, Location, ItemStack)>,
+    dropped: u32,
+
+    /// the tick the bot started eating
+    eating_since: Option<usize>,
+
+    /// blocks the server does not let the bot change and what it keeps them as
+    protected: HashMap<BlockLocation, BlockState>,
+
+    /// the hits left until each mob dies
+    mobs: HashMap<u32, u32>,
+
+    /// how long the expensive part of the task may take each tick
+    budget: Duration,
+
+    /// the most ticks the server takes to get what the bot sent. Zero applies it in the tick it was sent.
+    latency: usize,
+
+    /// how late each tick of calls gets to the server
+    lag: StdRng,
+
+    /// the tick each tick of calls gets to the server and how many calls were sent up to then, oldest first
+    arrivals: VecDeque<(usize, usize)>,
+}
+
+impl SimulatedClient {
+    /// a bot with a full health and food bar standing at `location` in `world`
+    pub fn new(world: WorldBlocks, location: Location) -> SimulatedClient {
+        let mut global = GlobalState::init();
+        global.blocks = world;
+
+        let mut state = LocalState::mock();
+        state.health = 20.0;
+        state.food = 20;
+        state.physics.teleport(location);
+
+        let bot = Bot {
+            state,
+            actions: Default::default(),
+            queue: NoPackets,
+            out: SimOut::default(),
+            recording: Recording::off(),
+            last_task: None,
+            sneaking: false,
+            sprinting: false,
+            events: Vec::new(),
+            controller: None,
+        };
+
+        SimulatedClient {
+            bot,
+            global,
+            positions: Vec::new(),
+            fall_damage: 0.0,
+            picked_up: Vec::new(),
+            attacked: Vec::new(),
+            applied: 0,
+            items: Vec::new(),
+            dropped: 0,
+            eating_since: None,
+            protected: HashMap::new(),
+            mobs: HashMap::new(),
+            budget: EXPENSIVE_TIME,
+            latency: 0,
+            lag: StdRng::seed_from_u64(0),
+            arrivals: VecDeque::new(),
+        }
+    }
+
+    /// Derive everything random about the bot and its connection from `seed`. The expensive part of the task gets as
+    /// long as it needs instead of a slice of the tick.
+    pub fn seed(&mut self, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        self.bot.state.seed(rng.gen());
+        self.lag = StdRng::seed_from_u64(rng.gen());
+        self.budget = SEEDED_EXPENSIVE_TIME;
+    }
+
+    /// What the bot sends gets to the server up to `ticks` late. Calls stay in the order they were sent.
+    pub fn set_latency(&mut self, ticks: usize) {
+        self.latency = ticks;
+    }
+
+    pub fn local(&mut self) -> &mut LocalState {
+        &mut self.bot.state
+    }
+
+    pub fn out(&self) -> &SimOut {
+        &self.bot.out
+    }
+
+    /// put `count` of `kind` in hotbar slot `slot` (0-8)
+    pub fn give(&mut self, slot: usize, kind: BlockKind, count: u8) {
+        self.bot.state.inventory.add(36 + slot, ItemStack::new(kind, count, 0, None));
+    }
+
+    /// the server keeps the block at `location` as it is now, like in a protected region
+    pub fn protect(&mut self, location: BlockLocation) {
+        let state = self.global.blocks.get_block_exact(location).unwrap_or(BlockState::AIR);
+        self.protected.insert(location, state);
+    }
+
+    /// drop `stack` at `location` like a dying player would. Returns the id of the item.
+    pub fn drop_item(&mut self, location: Location, stack: ItemStack) -> u32 {
+        let id = FIRST_ITEM + self.dropped;
+        self.dropped += 1;
+        self.global.entities.put_entity(id, self.bot.state.bot_id, location, EntityKind::Item);
+        self.items.push((id, location, stack));
+        id
+    }
+
+    /// an entity of `kind` at `location` which dies after `hits` attacks. It does not move.
+    pub fn spawn_mob(&mut self, id: u32, location: Location, kind: EntityKind, hits: u32) {
+        self.global.entities.put_entity(id, self.bot.state.bot_id, location, kind);
+        self.mobs.insert(id, hits);
+    }
+
+    pub fn schedule(&mut self, task: impl Into<Task>) {
+        self.bot.actions.schedule(task);
+    }
+
+    /// a tick of the game loop: the expensive part of the task, the task, physics and then the server
+    pub fn tick(&mut self) {
+        self.global.ticks += 1;
+        self.step();
+    }
+
+    /// the part of a tick for this bot. Every bot of a [`SimulatedSwarm`] steps once a tick.
+    fn step(&mut self) {
+        let bot = &mut self.bot;
+        self.positions.push(bot.state.physics.location());
+        let tick = bot.state.ticks;
+        bot.out.tick = tick;
+
+        bot.actions.expensive(Instant::now() + self.budget, &mut bot.state, &self.global);
+        let actions = bot.run_sync(&mut self.global);
+        self.fall_damage += actions.fall_damage;
+
+        let lag = if self.latency == 0 { 0 } else { self.lag.gen_range(0..=self.latency) };
+        let arrives = self.arrivals.back().map_or(0, |&(at, _)| at).max(tick + lag);
+        self.arrivals.push_back((arrives, self.bot.out.calls.len()));
+
+        self.apply(tick);
+        self.pick_up();
+    }
+
+    pub fn run_ticks(&mut self, ticks: usize) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    /// Tick until there is no task left. Returns the ticks it took and panics if it takes more than `max`.
+    pub fn run_task(&mut self, max: usize) -> usize {
+        let mut ticks = 0;
+        while self.bot.actions.current().is_some() {
+            assert!(ticks < max, "the task did not finish in {} ticks", max);
+            self.tick();
+            ticks += 1;
+        }
+        ticks
+    }
+
+    /// do what the server would with the calls which got to it by `tick`
+    fn apply(&mut self, tick: usize) {
+        let mut arrived = self.applied;
+        while let Some(&(at, sent)) = self.arrivals.front() {
+            if at > tick {
+                break;
+            }
+            arrived = sent;
+            self.arrivals.pop_front();
+        }
+
+        let calls: Vec<_> = self.bot.out.calls[self.applied..arrived].iter().map(|(_, call)| call.clone()).collect();
+        self.applied = arrived;
+
+        for call in calls {
+            match call {
+                Call::PlaceBlock { against, face } => self.place(against + face.change()),
+                Call::Mine { location, mine: Mine::Finished } => self.change_block(location, BlockState::AIR),
+                Call::Mine { location, mine: Mine::Start } if self.bot.state.gamemode().creative() => {
+                    self.change_block(location, BlockState::AIR);
+                }
+                Call::UseItem(hand) => self.use_item(hand),
+                Call::FinishEating => self.finish_eating(),
+                Call::AttackEntity(id) => self.attack(id),
+                _ => {}
+            }
+        }
+    }
+
+    /// Items touching the bot go into the first free slot like the server would put them. Stacking them onto stacks
+    /// of the same kind is left out.
+    fn pick_up(&mut self) {
+        let me = self.bot.state.physics.location();
+        let reach = PLAYER_WIDTH / 2.0 + PICKUP_REACH;
+
+        for (id, location, stack) in mem::take(&mut self.items) {
+            let dy = location.y - me.y;
+            let touching = (location.x - me.x).abs() <= reach
+                && (location.z - me.z).abs() <= reach
+                && (-0.5..=PLAYER_HEIGHT + 0.5).contains(&dy);
+
+            match self.bot.state.inventory.free_slot() {
+                Some(slot) if touching => {
+                    self.bot.state.inventory.add(slot, stack);
+                    self.global.entities.remove_item(id);
+                    self.picked_up.push(id);
+                }
+                _ => self.items.push((id, location, stack)),
+            }
+        }
+    }
+
+    /// An attack in reach lands. A mob which took its hits dies, so no bot sees it anymore.
+    fn attack(&mut self, id: u32) {
+        let (location, owner) = match self.global.entities.by_id(id) {
+            Some(data) => (data.location, data.owner),
+            None => return,
+        };
+
+        if location.dist2(self.bot.state.physics.location()) > ATTACK_REACH * ATTACK_REACH {
+            return;
+        }
+        self.attacked.push(id);
+
+        let hits = match self.mobs.get_mut(&id) {
+            Some(hits) => hits,
+            None => return,
+        };
+        *hits = hits.saturating_sub(1);
+        if *hits == 0 {
+            self.mobs.remove(&id);
+            if let Some(owner) = owner {
+                self.global.entities.remove_entity(id, owner);
+            }
+        }
+    }
+
+    /// The server changes the block and tells the bot. A protected block is sent back as it is instead.
+    fn change_block(&mut self, location: BlockLocation, state: BlockState) {
+        let state = self.protected.get(&location).copied().unwrap_or(state);
+        self.global.blocks.set_block(location, state);
+        self.global.expectations.answer(self.global.dimension, location, state);
+    }
+
+    /// the held block is placed at `location` and used up
+    fn place(&mut self, location: BlockLocation) {
+        let held = match self.bot.state.inventory.current() {
+            Some(held) if held.kind.id() < 256 => BlockState::from(held.kind.id(), held.damage),
+            _ => return,
+        };
+
+        self.change_block(location, held);
+        if !self.protected.contains_key(&location) {
+            self.bot.state.inventory.consume(Hand::Main);
+        }
+    }
+
+    /// Buckets are emptied or filled (the bot changes the block itself) and food starts being eaten
+    fn use_item(&mut self, hand: Hand) {
+        let inventory = &mut self.bot.state.inventory;
+        let slot = inventory.hand_slot(hand);
+        let held = match inventory.in_hand(hand) {
+            Some(held) => held.kind,
+            None => return,
+        };
+
+        if held == BlockKind::WATER_BUCKET {
+            inventory.add(slot, ItemStack::new(BlockKind::BUCKET, 1, 0, None));
+        } else if held == BlockKind::BUCKET {
+            inventory.add(slot, ItemStack::new(BlockKind::WATER_BUCKET, 1, 0, None));
+        } else if self.global.block_data.is_food(held.id()) {
+            self.eating_since = Some(self.bot.state.ticks);
+        }
+    }
+
+    /// Food eaten for long enough is used up and fills the food bar. How filling each food is is not known, so the
+    /// bar is filled completely.
+    fn finish_eating(&mut self) {
+        let since = match self.eating_since.take() {
+            Some(since) => since,
+            None => return,
+        };
+
+        let data = &self.global.block_data;
+        let local = &mut self.bot.state;
+        let held = local.inventory.current().map(|held| held.kind);
+        if local.ticks - since >= EAT_TICKS && held.map_or(false, |kind| data.is_food(kind.id())) {
+            local.inventory.consume(Hand::Main);
+            local.food = 20;
+        }
+    }
+}
+
+/// Bots sharing a world, each like a [`SimulatedClient`]. Bots standing inside each other are pushed apart like vanilla
+/// pushes players.
+pub struct SimulatedSwarm {
+    pub clients: Vec<SimulatedClient>,
+    pub global: GlobalState,
+
+    /// how hard each bot was pushed by the others, added up over every tick
+    pub shoved: Vec<f64>,
+
+    /// the hits left until each mob dies, shared like the global state
+    mobs: HashMap<u32, u32>,
+
+    /// shuffles the order the bots step in each tick, like the order their packets get to the server. None steps
+    /// them by id.
+    order: Option<StdRng>,
+}
+
+impl SimulatedSwarm {
+    /// a bot standing at each of `locations` in `world`. The bot ids are their index.
+    pub fn new(world: WorldBlocks, locations: &[Location]) -> SimulatedSwarm {
+        let clients = locations.iter().enumerate()
+            .map(|(id, &location)| {
+                let mut client = SimulatedClient::new(WorldBlocks::default(), location);
+                client.bot.state.bot_id = id as u32;
+                client
+            })
+            .collect();
+
+        let mut global = GlobalState::init();
+        global.blocks = world;
+
+        SimulatedSwarm { shoved: vec![0.0; locations.len()], clients, global, mobs: HashMap::new(), order: None }
+    }
+
+    /// Like [`SimulatedSwarm::new`], but everything random (each bot, its connection and the order the bots step in)
+    /// is derived from `seed`. The same seed runs the same way tick for tick.
+    pub fn seeded(world: WorldBlocks, locations: &[Location], seed: u64) -> SimulatedSwarm {
+        let mut swarm = SimulatedSwarm::new(world, locations);
+        let mut rng = StdRng::seed_from_u64(seed);
+        for client in &mut swarm.clients {
+            client.seed(rng.gen());
+        }
+        swarm.order = Some(rng);
+        swarm
+    }
+
+    /// see [`SimulatedClient::set_latency`]
+    pub fn set_latency(&mut self, ticks: usize) {
+        for client in &mut self.clients {
+            client.set_latency(ticks);
+        }
+    }
+
+    /// like [`SimulatedClient::spawn_mob`], for every bot
+    pub fn spawn_mob(&mut self, id: u32, location: Location, kind: EntityKind, hits: u32) {
+        self.global.entities.put_entity(id, 0, location, kind);
+        self.mobs.insert(id, hits);
+    }
+
+    /// whether the mob is still alive
+    pub fn alive(&self, id: u32) -> bool {
+        self.mobs.contains_key(&id)
+    }
+
+    /// a tick of the game loop for every bot, each using the shared global state
+    pub fn tick(&mut self) {
+        self.global.ticks += 1;
+
+        let mut order: Vec<_> = (0..self.clients.len()).collect();
+        if let Some(rng) = self.order.as_mut() {
+            order.shuffle(rng);
+        }
+
+        for idx in order {
+            let client = &mut self.clients[idx];
+            mem::swap(&mut client.global, &mut self.global);
+            mem::swap(&mut client.mobs, &mut self.mobs);
+            client.step();
+            mem::swap(&mut client.global, &mut self.global);
+            mem::swap(&mut client.mobs, &mut self.mobs);
+        }
+        self.push_apart();
+    }
+
+    /// Tick until no bot has a task left. Returns the ticks it took and panics if it takes more than `max`.
+    pub fn run_tasks(&mut self, max: usize) -> usize {
+        let mut ticks = 0;
+        while self.clients.iter().any(|client| client.bot.actions.current().is_some()) {
+            assert!(ticks < max, "the tasks did not finish in {} ticks", max);
+            self.tick();
+            ticks += 1;
+        }
+        ticks
+    }
+
+    /// like vanilla's entity collision: bots whose boxes overlap get a small push away from each other
+    fn push_apart(&mut self) {
+        let locations: Vec<_> = self.clients.iter().map(|client| client.bot.state.physics.location()).collect();
+        for (i, a) in locations.iter().enumerate() {
+            for (j, b) in locations.iter().enumerate().skip(i + 1) {
+                let (mut dx, dy, mut dz) = (b.x - a.x, b.y - a.y, b.z - a.z);
+                if dx.abs() >= PLAYER_WIDTH || dz.abs() >= PLAYER_WIDTH || dy.abs() >= PLAYER_HEIGHT {
+                    continue;
+                }
+
+                let dist = dx.abs().max(dz.abs());
+                if dist < 0.01 {
+                    continue;
+                }
+
+                let dist = dist.sqrt();
+                let scale = (1.0 / dist).min(1.0) * PUSH / dist;
+                dx *= scale;
+                dz *= scale;
+
+                self.clients[i].bot.state.physics.push(Displacement::new(-dx, 0.0, -dz));
+                self.clients[j].bot.state.physics.push(Displacement::new(dx, 0.0, dz));
+
+                let shove = (dx * dx + dz * dz).sqrt();
+                self.shoved[i] += shove;
+                self.shoved[j] += shove;
+            }
+        }
+    }
+}
@@ -0,0 +1,350 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Seeded runs of a [`SimulatedSwarm`] from a script. A coordination bug seen once can be handed on as a seed and a
+//! script, and every tick of a run can be kept as a baseline to find the first tick a later run goes differently.
+
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::bot::process_command;
+use crate::client::sim::SimulatedSwarm;
+use crate::error::{err, Error, Res};
+use crate::storage::blocks::WorldBlocks;
+use crate::types::Location;
+
+/// a bot command run before a tick
+#[derive(Debug, PartialEq)]
+pub struct Scheduled {
+    pub tick: usize,
+
+    /// the bot which runs it. None if every bot does.
+    pub bot: Option<usize>,
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// What a replay runs. Each line is one of these (`#` starts a comment):
+/// - `world flat` or `world <file>` (as written by [`WorldBlocks::save`]). Flat if left out.
+/// - `bot <x> <y> <z>` a bot standing there. The first bot is bot 0.
+/// - `latency <ticks>` what bots send gets to the server up to this many ticks late
+/// - `at <tick> <bot|all> <command> [args...]` run a command before the tick, like it was typed in chat
+/// - `ticks <n>` how many ticks to run
+#[derive(Debug, Default, PartialEq)]
+pub struct Script {
+    pub world: Option<String>,
+    pub bots: Vec<Location>,
+    pub latency: usize,
+
+    /// by tick
+    pub commands: Vec<Scheduled>,
+    pub ticks: usize,
+}
+
+fn parse<T: FromStr>(word: &str, line: usize) -> Res<T> {
+    word.parse().map_err(|_| err(&format!("line {}: {} is not a number", line, word)))
+}
+
+impl FromStr for Script {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut script = Script::default();
+
+        for (idx, line) in s.lines().enumerate() {
+            let line_no = idx + 1;
+            let line = line.split('#').next().unwrap_or_default();
+            let words: Vec<_> = line.split_whitespace().collect();
+
+            match words[..] {
+                [] => {}
+                ["world", "flat"] => script.world = None,
+                ["world", file] => script.world = Some(file.to_string()),
+                ["bot", x, y, z] => script.bots.push(Location::new(parse(x, line_no)?, parse(y, line_no)?, parse(z, line_no)?)),
+                ["latency", ticks] => script.latency = parse(ticks, line_no)?,
+                ["ticks", ticks] => script.ticks = parse(ticks, line_no)?,
+                ["at", tick, bot, name, ref args @ ..] => {
+                    let bot: Option<usize> = match bot {
+                        "all" => None,
+                        bot => Some(parse(bot, line_no)?),
+                    };
+                    script.commands.push(Scheduled {
+                        tick: parse(tick, line_no)?,
+                        bot,
+                        name: name.to_string(),
+                        args: args.iter().map(|arg| arg.to_string()).collect(),
+                    });
+                }
+                _ => return Err(err(&format!("line {}: cannot understand {}", line_no, line.trim()))),
+            }
+        }
+
+        for command in &script.commands {
+            if command.tick >= script.ticks {
+                return Err(err(&format!("{} runs at tick {}, after the last tick", command.name, command.tick)));
+            }
+            if command.bot.map_or(false, |bot| bot >= script.bots.len()) {
+                return Err(err(&format!("{} is for a bot which is not in the script", command.name)));
+            }
+        }
+
+        // stable, so commands of the same tick run in the order they are written
+        script.commands.sort_by_key(|command| command.tick);
+        Ok(script)
+    }
+}
+
+/// where a bot was at the end of a tick and what it was doing
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BotFrame {
+    pub location: Location,
+
+    /// the name of the running task. None if there is none.
+    pub task: Option<String>,
+}
+
+/// every bot at the end of a tick, by id
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub tick: usize,
+    pub bots: Vec<BotFrame>,
+}
+
+/// the first line of a baseline
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Header {
+    /// the seed of the run everything random was derived from
+    pub seed: u64,
+    pub bots: usize,
+    pub ticks: usize,
+}
+
+/// the first tick and bot where a run differs from the baseline
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    pub tick: usize,
+    pub bot: usize,
+    pub field: &'static str,
+    pub baseline: String,
+    pub replayed: String,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "tick {}: the {} of bot {} is {} but was {} in the baseline", self.tick, self.field, self.bot, self.replayed, self.baseline)
+    }
+}
+
+/// the first tick a bot is somewhere else or doing something else in `replayed` than in `baseline`
+pub fn diverges(baseline: &[Frame], replayed: &[Frame]) -> Option<Divergence> {
+    for (before, now) in baseline.iter().zip(replayed) {
+        for (bot, (a, b)) in before.bots.iter().zip(&now.bots).enumerate() {
+            let differs = |field, baseline: String, replayed: String| Divergence { tick: now.tick, bot, field, baseline, replayed };
+
+            if a.task != b.task {
+                return Some(differs("task", format!("{:?}", a.task), format!("{:?}", b.task)));
+            }
+            if a.location != b.location {
+                return Some(differs("location", a.location.to_string(), b.location.to_string()));
+            }
+        }
+    }
+    None
+}
+
+/// Run `script` with everything random derived from `seed`. Returns every bot at the end of each tick.
+pub fn run(script: &Script, seed: u64) -> Res<Vec<Frame>> {
+    let world = match script.world.as_ref() {
+        None => WorldBlocks::flat(),
+        Some(file) => {
+            let mut world = WorldBlocks::default();
+            world.load(Path::new(file))?;
+            world
+        }
+    };
+
+    let mut swarm = SimulatedSwarm::seeded(world, &script.bots, seed);
+    swarm.set_latency(script.latency);
+
+    let mut commands = script.commands.iter().peekable();
+    let mut frames = Vec::with_capacity(script.ticks);
+
+    for tick in 0..script.ticks {
+        while let Some(command) = commands.next_if(|command| command.tick == tick) {
+            run_command(&mut swarm, command)?;
+        }
+
+        swarm.tick();
+
+        let bots = swarm.clients.iter()
+            .map(|client| BotFrame {
+                location: client.bot.state.physics.location(),
+                task: client.bot.actions.current().map(|(_, name)| name.to_string()),
+            })
+            .collect();
+        frames.push(Frame { tick, bots });
+    }
+
+    Ok(frames)
+}
+
+fn run_command(swarm: &mut SimulatedSwarm, command: &Scheduled) -> Res {
+    let args: Vec<_> = command.args.iter().map(String::as_str).collect();
+    let bots = match command.bot {
+        Some(bot) => bot..bot + 1,
+        None => 0..swarm.clients.len(),
+    };
+
+    for bot in bots {
+        let client = &mut swarm.clients[bot];
+        process_command(&command.name, &args, None, &mut client.bot.state, &mut swarm.global, &mut client.bot.actions, &mut client.bot.out)
+            .map_err(|e| err(&format!("tick {}: bot {} could not run {}: {}", command.tick, bot, command.name, e)))?;
+    }
+
+    Ok(())
+}
+
+/// write a run as a baseline: the [`Header`] and then a [`Frame`] per line
+pub fn save_baseline(path: &Path, seed: u64, frames: &[Frame]) -> Res {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let header = Header { seed, bots: frames.first().map_or(0, |frame| frame.bots.len()), ticks: frames.len() };
+    serde_json::to_writer(&mut writer, &header)?;
+    writeln!(writer)?;
+
+    for frame in frames {
+        serde_json::to_writer(&mut writer, frame)?;
+        writeln!(writer)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load_baseline(path: &Path) -> Res<(Header, Vec<Frame>)> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => return Err(err("the baseline is empty")),
+    };
+
+    let frames = lines.map(|line| Ok(serde_json::from_str(&line?)?)).collect::<Res<_>>()?;
+    Ok((header, frames))
+}
+
+/// Run the script at `script` with `seed`. If `baseline` exists the run is compared against it, otherwise the run is
+/// written to it.
+pub fn replay(script: &Path, seed: u64, baseline: Option<&Path>) -> Res {
+    let script: Script = std::fs::read_to_string(script)?.parse()?;
+    println!("replaying {} bots for {} ticks with seed {}", script.bots.len(), script.ticks, seed);
+    let frames = run(&script, seed)?;
+
+    let baseline = match baseline {
+        Some(baseline) => baseline,
+        None => {
+            if let Some(last) = frames.last() {
+                for (id, bot) in last.bots.iter().enumerate() {
+                    println!("bot {} ended at {} running {:?}", id, bot.location, bot.task);
+                }
+            }
+            return Ok(());
+        }
+    };
+
+    if !baseline.exists() {
+        save_baseline(baseline, seed, &frames)?;
+        println!("recorded the baseline {}", baseline.display());
+        return Ok(());
+    }
+
+    let (header, recorded) = load_baseline(baseline)?;
+    if header.seed != seed {
+        return Err(err(&format!("the baseline was recorded with seed {}", header.seed)));
+    }
+    if header.bots != script.bots.len() || header.ticks != script.ticks {
+        return Err(err(&format!("the baseline has {} bots for {} ticks but the script {} bots for {} ticks", header.bots, header.ticks, script.bots.len(), script.ticks)));
+    }
+
+    match diverges(&recorded, &frames) {
+        Some(divergence) => Err(err(&format!("the run diverged from the baseline at {}", divergence))),
+        None => {
+            println!("the run is the same as the baseline for all {} ticks", frames.len());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::sim::replay::{diverges, run, Scheduled, Script};
+    use crate::types::Location;
+
+    const SCRIPT: &str = "\
+# three bots who all want to stand on the same block
+bot 0.5 1 0.5
+bot 1.5 1 0.5
+bot 0.5 1 1.5
+latency 2
+at 0 all goto 20 1 20
+at 20 1 stop
+ticks 120
+";
+
+    #[test]
+    fn test_parse() {
+        let script: Script = SCRIPT.parse().unwrap();
+        assert_eq!(script.bots, vec![Location::new(0.5, 1.0, 0.5), Location::new(1.5, 1.0, 0.5), Location::new(0.5, 1.0, 1.5)]);
+        assert_eq!(script.latency, 2);
+        assert_eq!(script.ticks, 120);
+        assert_eq!(script.commands[1], Scheduled { tick: 20, bot: Some(1), name: "stop".to_string(), args: Vec::new() });
+
+        assert!("bot 1 2".parse::<Script>().is_err());
+        assert!("bot 0 1 0\nat 5 0 stop\nticks 5".parse::<Script>().is_err());
+        assert!("bot 0 1 0\nat 0 1 stop\nticks 5".parse::<Script>().is_err());
+    }
+
+    #[test]
+    fn test_same_seed() {
+        let script: Script = SCRIPT.parse().unwrap();
+        let first = run(&script, 7).unwrap();
+        let second = run(&script, 7).unwrap();
+
+        assert_eq!(first.len(), 120);
+        assert_eq!(diverges(&first, &second), None);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_divergence() {
+        let script: Script = SCRIPT.parse().unwrap();
+        let baseline = run(&script, 7).unwrap();
+
+        // bot 1 no longer stops
+        let changed: Script = SCRIPT.replace("at 20 1 stop\n", "").parse().unwrap();
+        let replayed = run(&changed, 7).unwrap();
+
+        let divergence = diverges(&baseline, &replayed).unwrap();
+        assert_eq!(divergence.tick, 20);
+        assert_eq!(divergence.bot, 1);
+        assert_eq!(divergence.field, "task");
+    }
+}
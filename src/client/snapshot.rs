@@ -0,0 +1,192 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The state worth keeping across restarts: the task queue of every bot and the `#mine` regions nobody finished.
+//! Written on shutdown and every [`SNAPSHOT_INTERVAL`], read with `--resume`. Waypoints are saved as they change in
+//! their own file.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::afk::Area;
+use crate::client::routine::{Routine, Step};
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::{MineRegionTask, Task};
+use crate::client::tasks::farm::FarmTask;
+use crate::client::tasks::guard::GuardTask;
+use crate::client::tasks::light_up::LightUpTask;
+use crate::client::tasks::mine_region::MineRegion;
+use crate::client::tasks::navigate::BlockTravelTask;
+use crate::client::tasks::recover::RecoverItemsTask;
+use crate::client::tasks::routine::RoutineTask;
+use crate::client::tasks::set_spawn::SetSpawnTask;
+use crate::client::tasks::travel::TravelTask;
+use crate::error::Res;
+use crate::storage::block::{BlockKind, BlockLocation, BlockLocation2D};
+use crate::types::{Dimension, Location};
+
+/// how often the snapshot is written while running
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// What is needed to start a task again after a restart. The task works out the rest (paths, what is left to mine) on
+/// its first ticks as the world may have changed in the meantime.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SavedTask {
+    Goto { goal: BlockLocation, fly: bool },
+    Travel { goal: BlockLocation },
+
+    /// the regions themselves are in [`Snapshot::mine`]
+    Mine,
+
+    /// the steps which were not done yet, the one in progress first
+    Routine { steps: Vec<Step> },
+    Farm { min: BlockLocation, max: BlockLocation, crop: u32, chest: Option<BlockLocation> },
+    LightUp { origin: BlockLocation, radius: u8 },
+    Bed { bed: BlockLocation },
+    Guard { post: BlockLocation, radius: f64, players: Option<Vec<String>> },
+
+    /// going back for the items dropped when dying. `despawn` is in unix seconds.
+    Recover { location: Location, dimension: Dimension, items: Vec<(u32, u32)>, despawn: u64 },
+}
+
+impl SavedTask {
+    /// None if the task makes no sense anymore, i.e., the items to recover despawned
+    pub fn restore(self, local: &LocalState, global: &GlobalState) -> Option<Task> {
+        let task = match self {
+            SavedTask::Goto { goal, fly } => {
+                let task = BlockTravelTask::new(goal, local);
+                let task = if fly { task.prefer_flying() } else { task };
+                task.into()
+            }
+            SavedTask::Travel { goal } => TravelTask::new(goal).into(),
+            SavedTask::Mine => MineRegionTask::from(MineRegion).into(),
+            SavedTask::Routine { steps } => RoutineTask::new(&Routine { steps }).into(),
+            SavedTask::Farm { min, max, crop, chest } => FarmTask::new(Area { min, max }, BlockKind(crop), chest)?.into(),
+            SavedTask::LightUp { origin, radius } => LightUpTask::new(origin, radius).into(),
+            SavedTask::Bed { bed } => SetSpawnTask::new(bed).into(),
+            SavedTask::Guard { post, radius, players } => GuardTask::new(post, radius, players).into(),
+            SavedTask::Recover { location, dimension, items, despawn } => {
+                let left = despawn.checked_sub(unix_secs(SystemTime::now())).filter(|&left| left > 0)?;
+                let items = items.into_iter().map(|(kind, count)| (BlockKind(kind), count)).collect();
+                RecoverItemsTask::resume(location, dimension, items, Duration::from_secs(left), local, global.config.recover.radius).into()
+            }
+        };
+        Some(task)
+    }
+}
+
+pub fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default()
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    /// the queue of every bot by username, the running task first
+    pub bots: HashMap<String, Vec<SavedTask>>,
+
+    /// the corners of the `#mine` regions no bot finished, in the order they are handed out
+    pub mine: Vec<BlockLocation2D>,
+}
+
+impl Snapshot {
+    /// an empty snapshot if there is no file yet
+    pub fn load(file: &Path) -> Res<Snapshot> {
+        match fs::read_to_string(file) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Snapshot::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write to a temporary file first so a crash while writing cannot lose the last snapshot
+    pub fn save(&self, file: &Path) -> Res {
+        let tmp = file.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, file)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::bot::ActionState;
+    use crate::client::routine::{Routine, Step};
+    use crate::client::snapshot::{SavedTask, Snapshot};
+    use crate::client::state::global::GlobalState;
+    use crate::client::state::local::LocalState;
+    use crate::client::tasks::MineRegionTask;
+    use crate::client::tasks::compound::CompoundTask;
+    use crate::client::tasks::delay::DelayTask;
+    use crate::client::tasks::mine_region::MineRegion;
+    use crate::client::tasks::navigate::BlockTravelTask;
+    use crate::client::tasks::routine::RoutineTask;
+    use crate::client::tasks::set_spawn::SetSpawnTask;
+    use crate::client::tasks::travel::TravelTask;
+    use crate::storage::block::{BlockLocation, BlockLocation2D};
+
+    fn names(actions: &ActionState) -> Vec<&'static str> {
+        actions.current().map(|(_, name)| name).into_iter().chain(actions.queued()).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let global = GlobalState::init();
+        let local = LocalState::mock();
+
+        let routine = Routine {
+            steps: vec![
+                Step::Goto { location: BlockLocation::new(1, 64, 1) },
+                Step::Break { location: BlockLocation::new(2, 64, 1) },
+            ]
+        };
+
+        let mut actions = ActionState::default();
+        actions.schedule(MineRegionTask::from(MineRegion));
+        actions.schedule(BlockTravelTask::new(BlockLocation::new(10, 64, 10), &local).prefer_flying());
+        actions.schedule(DelayTask::new(20));
+        actions.schedule(TravelTask::new(BlockLocation::new(1000, 64, -1000)));
+        actions.schedule(RoutineTask::new(&routine));
+        actions.schedule(CompoundTask::default());
+        actions.schedule(SetSpawnTask::new(BlockLocation::new(5, 64, 5)));
+
+        let saved = actions.save(&local);
+        assert_eq!(saved.len(), 5);
+        assert_eq!(saved[1], SavedTask::Goto { goal: BlockLocation::new(10, 64, 10), fly: true });
+        assert_eq!(saved[3], SavedTask::Routine { steps: routine.steps.clone() });
+
+        let mut snapshot = Snapshot::default();
+        snapshot.bots.insert("abc".to_string(), saved);
+        snapshot.mine = vec![BlockLocation2D::new(0, 0), BlockLocation2D::new(7, 0)];
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let mut read: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, snapshot);
+
+        let mut resumed = ActionState::default();
+        resumed.resume(read.bots.remove("abc").unwrap(), &local, &global);
+
+        // the delay and the compound task are not resumable
+        let expected = vec!["MineRegionTask", "BlockTravelTask", "TravelTask", "RoutineTask", "SetSpawnTask"];
+        assert_eq!(names(&resumed), expected);
+        assert_eq!(resumed.save(&local), snapshot.bots["abc"]);
+    }
+}
@@ -14,25 +14,109 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::time::Instant;
+
 use crate::bootstrap::block_data::BlockData;
-use crate::client::pathfind::context::PathConfig;
+use crate::bootstrap::proxy::ProxyPool;
+use crate::client::bases::BaseDetector;
+use crate::client::state::global::caravan::Caravans;
+use crate::client::state::global::expectations::Expectations;
+use crate::client::state::global::guards::Guards;
+use crate::client::permissions::Permissions;
+use crate::client::processor::BlockChanged;
+use crate::client::routine::Routine;
+use crate::client::swarm::ScaleRequests;
+use crate::client::tunables::Tunables;
+use crate::client::view::in_view;
+use crate::protocol::disconnect::KickHistory;
+use crate::protocol::tick::MAX_TPS;
 use crate::client::state::global::mine_alloc::MineAlloc;
-use crate::storage::blocks::WorldBlocks;
+use crate::client::state::global::reservations::Reservations;
+use crate::storage::block::{BlockLocation, BlockState};
+use crate::storage::blocks::{ChunkLocation, WorldBlocks};
 use crate::storage::entities::WorldEntities;
-use crate::client::state::global::world_players::WorldPlayers;
+use crate::client::state::global::waypoints::Waypoints;
+use crate::client::state::global::world_players::{Player, WorldPlayers};
+use crate::types::Dimension;
 
+pub mod caravan;
+pub mod expectations;
+pub mod guards;
 pub mod mine_alloc;
+pub mod reservations;
+pub mod waypoints;
 pub mod world_players;
 
 #[derive(Default)]
 pub struct GlobalState {
+    /// The blocks of `dimension`, shared by every bot. A column is stored once however many bots the server sends it
+    /// to, and what one bot sees every other bot can use. Other threads read a [`WorldBlocks::snapshot`].
     pub blocks: WorldBlocks,
     pub mine: MineAlloc,
     pub block_data: BlockData,
+
+    /// the entities of `dimension`
     pub entities: WorldEntities,
+
+    /// the dimension of `blocks` and `entities`. Bots can be in different dimensions, so [`GlobalState::enter`]
+    /// must be called with the dimension of a bot before processing it.
+    pub dimension: Dimension,
+
+    /// the blocks and entities of the other dimensions
+    other_dimensions: HashMap<Dimension, (WorldBlocks, WorldEntities)>,
     pub players: WorldPlayers,
     pub ticks: usize,
-    pub travel_config: PathConfig,
+
+    /// the estimated ticks per second of the server. None until known.
+    pub tps: Option<f64>,
+
+    /// the average time our game loop takes for a tick in milliseconds
+    pub tick_millis: Option<f64>,
+    pub proxies: ProxyPool,
+
+    /// every disconnect of every account (including disconnects while logging in)
+    pub kicks: KickHistory,
+
+    /// routines recorded with `#record` by name
+    pub routines: HashMap<String, Routine>,
+
+    /// `#swarm` requests for the runner
+    pub swarm: ScaleRequests,
+
+    /// the bots following each leader with `#follow-leader`
+    pub caravans: Caravans,
+
+    /// the blocks navigating bots are in or about to walk through
+    pub reservations: Reservations,
+
+    /// blocks bots changed which the server did not confirm yet
+    pub expectations: Expectations,
+
+    /// the mobs guarding bots are fighting
+    pub guards: Guards,
+
+    /// the uuids of the bots which are online, which bots never fight
+    pub members: HashSet<u128>,
+
+    /// who may command the bots through chat
+    pub permissions: Permissions,
+
+    /// likely player bases seen in loaded chunks
+    pub bases: BaseDetector,
+
+    /// the named locations set with `#waypoint`
+    pub waypoints: Waypoints,
+
+    /// the settings which can change while running. Only swapped between ticks, so tasks see one config per tick.
+    pub config: Tunables,
+
+    /// a `#reload-config` for the runner
+    pub reload_config: bool,
+
+    /// (tick, dimension, change) of blocks the server changed in this or the previous tick
+    block_changes: Vec<(usize, Dimension, BlockChanged)>,
 }
 
 impl GlobalState {
@@ -40,6 +124,100 @@ impl GlobalState {
         GlobalState::default()
     }
 
+    /// Make `blocks` and `entities` those of `dimension`. The ones of the previous dimension are kept for when a bot
+    /// goes back.
+    pub fn enter(&mut self, dimension: Dimension) {
+        if dimension == self.dimension {
+            return;
+        }
+
+        let (blocks, entities) = self.other_dimensions.remove(&dimension).unwrap_or_default();
+        let previous = (mem::replace(&mut self.blocks, blocks), mem::replace(&mut self.entities, entities));
+        self.other_dimensions.insert(self.dimension, previous);
+        self.dimension = dimension;
+    }
+
+    /// The bot `bot` disconnected or left: release the columns it holds in every dimension. Returns how many were
+    /// dropped.
+    pub fn release_columns(&mut self, bot: u32) -> usize {
+        let others: usize = self.other_dimensions.values_mut().map(|(blocks, _)| blocks.release_all(bot)).sum();
+        self.blocks.release_all(bot) + others
+    }
+
+    /// Drop the columns no bot sees. `views` has the dimension, column and view distance of every bot. Dimensions
+    /// without a bot are kept for when one comes back. Returns how many columns were dropped.
+    pub fn evict_columns(&mut self, views: &[(Dimension, ChunkLocation, i32)]) -> usize {
+        let margin = self.config.view.keep_margin;
+        let seen = |dimension: Dimension| move |column: ChunkLocation| views.iter()
+            .any(|&(viewer, center, distance)| viewer == dimension && in_view(center, distance + margin, column));
+        let occupied = |dimension: Dimension| views.iter().any(|&(viewer, ..)| viewer == dimension);
+
+        let mut dropped = 0;
+        if occupied(self.dimension) {
+            dropped += self.blocks.retain_columns(seen(self.dimension));
+        }
+        for (&dimension, (blocks, _)) in &mut self.other_dimensions {
+            if occupied(dimension) {
+                dropped += blocks.retain_columns(seen(dimension));
+            }
+        }
+        dropped
+    }
+
+    /// index the columns of every dimension which were added or changed until `end_at`
+    pub fn index_chunks(&mut self, end_at: Instant) {
+        let kinds = &self.config.index.kinds;
+        self.blocks.index_pending(kinds, end_at);
+        for (blocks, _) in self.other_dimensions.values_mut() {
+            blocks.index_pending(kinds, end_at);
+        }
+    }
+
+    /// How many of our ticks `server_ticks` ticks of the server take. More than `server_ticks` when the server lags, so
+    /// waits (i.e., for a block to break) are not cut short.
+    pub fn lag_ticks(&self, server_ticks: usize) -> usize {
+        match self.tps {
+            Some(tps) if tps > 0.0 => (server_ticks as f64 * MAX_TPS / tps).ceil() as usize,
+            _ => server_ticks
+        }
+    }
+
+    /// the online player called `name`. Names are not case sensitive.
+    pub fn player_by_name(&self, name: &str) -> Option<&Player> {
+        self.players.iter().find(|player| player.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Change the block at `location` in our world ahead of the server, i.e., when mining or placing it. The change
+    /// is undone if the server does not confirm it (see [`Expectations`]).
+    pub fn predict(&mut self, bot_id: u32, location: BlockLocation, state: BlockState) {
+        let previous = self.blocks.get_block_exact(location);
+        self.blocks.set_block(location, state);
+        self.expectations.expect(bot_id, self.dimension, location, previous, state, self.ticks);
+    }
+
+    pub fn record_change(&mut self, change: BlockChanged) {
+        self.block_changes.push((self.ticks, self.dimension, change));
+    }
+
+    /// Forget changes from before the previous tick. They are kept for a tick because a bot can tick before another
+    /// bot receives the change in the same game loop.
+    pub fn prune_changes(&mut self) {
+        let ticks = self.ticks;
+        self.block_changes.retain(|(at, ..)| at + 1 >= ticks);
+    }
+
+    /// the latest recent change of the block at `location`
+    pub fn block_changed(&self, location: BlockLocation) -> Option<BlockChanged> {
+        self.block_changes().filter(|change| change.location == location).last()
+    }
+
+    /// the recent changes in the current dimension
+    pub fn block_changes(&self) -> impl Iterator<Item=BlockChanged> + '_ {
+        self.block_changes.iter()
+            .filter(move |(_, dimension, _)| *dimension == self.dimension)
+            .map(|(.., change)| *change)
+    }
+
     /// # Goal
     /// we want to assign regions to explore for each bot
     /// we want to explore in rings
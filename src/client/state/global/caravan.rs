@@ -0,0 +1,68 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+/// a bot not seen following for this many ticks left the line
+const STALE_TICKS: usize = 20;
+
+/// The bots following each leader with `#follow-leader` in the order they joined. A bot's place decides how far behind
+/// the leader it stays so the line strings out instead of bunching up.
+#[derive(Debug, Default)]
+pub struct Caravans {
+    /// (bot id, tick last seen) of the followers of each leader by username
+    lines: HashMap<String, Vec<(u32, usize)>>,
+}
+
+impl Caravans {
+    /// Mark `bot_id` as following `leader` at tick `ticks`. Returns its place in the line, 0 being right behind the
+    /// leader. Bots which stopped following (i.e., with `#stop`) are dropped so the bots behind them move up.
+    pub fn place(&mut self, leader: &str, bot_id: u32, ticks: usize) -> usize {
+        let line = self.lines.entry(leader.to_string()).or_default();
+        line.retain(|&(id, seen)| id == bot_id || seen + STALE_TICKS >= ticks);
+
+        match line.iter().position(|&(id, _)| id == bot_id) {
+            Some(place) => {
+                line[place].1 = ticks;
+                place
+            }
+            None => {
+                line.push((bot_id, ticks));
+                line.len() - 1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::global::caravan::Caravans;
+
+    #[test]
+    fn test_place() {
+        let mut caravans = Caravans::default();
+        assert_eq!(caravans.place("leader", 7, 0), 0);
+        assert_eq!(caravans.place("leader", 3, 0), 1);
+        assert_eq!(caravans.place("other", 3, 0), 0);
+
+        // the first bot stops following
+        for ticks in 1..=30 {
+            assert_eq!(caravans.place("leader", 3, ticks), if ticks <= 20 { 1 } else { 0 });
+        }
+
+        assert_eq!(caravans.place("leader", 7, 31), 1);
+    }
+}
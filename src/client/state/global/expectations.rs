@@ -0,0 +1,199 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Blocks we changed in our world ahead of the server. The server answers a dig or a place with a block change. One of
+//! another kind (a protected region, anti-cheat) or no answer at all means it rejected the action, and the block goes
+//! back to what it was.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::storage::block::{BlockLocation, BlockState};
+use crate::storage::blocks::WorldBlocks;
+use crate::types::Dimension;
+
+/// how long the server has to answer a dig or place
+pub const EXPECT_TICKS: usize = 20 * 2;
+
+/// a change of ours the server did not make
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rejection {
+    pub location: BlockLocation,
+    pub expected: BlockState,
+
+    /// what the server says the block is. None if it did not answer in time.
+    pub actual: Option<BlockState>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    bot_id: u32,
+
+    /// the block before the first change the server did not answer yet. None if it was not known.
+    previous: Option<BlockState>,
+
+    /// our changes (i.e., harvesting and replanting a crop) with the tick they were made, oldest first
+    changes: VecDeque<(BlockState, usize)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Expectations {
+    pending: HashMap<(Dimension, BlockLocation), Pending>,
+
+    /// rejections by the id of the bot which made the change, until it takes them
+    rejected: HashMap<u32, Vec<Rejection>>,
+}
+
+impl Expectations {
+    /// `bot_id` changed the block at `location` from `previous` to `expected` in tick `tick`
+    pub fn expect(&mut self, bot_id: u32, dimension: Dimension, location: BlockLocation, previous: Option<BlockState>, expected: BlockState, tick: usize) {
+        let pending = self.pending.entry((dimension, location)).or_insert_with(|| Pending {
+            bot_id,
+            previous,
+            changes: VecDeque::new(),
+        });
+        pending.bot_id = bot_id;
+        pending.changes.push_back((expected, tick));
+    }
+
+    /// The server says the block at `location` is `state`. Confirms our changes up to the one of the same kind, or
+    /// rejects all of them if there is none. The caller puts `state` into the world either way.
+    pub fn answer(&mut self, dimension: Dimension, location: BlockLocation, state: BlockState) {
+        let key = (dimension, location);
+        let pending = match self.pending.get_mut(&key) {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        match pending.changes.iter().position(|(expected, _)| expected.kind() == state.kind()) {
+            Some(idx) => {
+                pending.changes.drain(..=idx);
+                pending.previous = Some(state);
+                if pending.changes.is_empty() {
+                    self.pending.remove(&key);
+                }
+            }
+            None => {
+                let pending = self.pending.remove(&key).unwrap();
+                let expected = pending.changes.back().map(|&(expected, _)| expected).unwrap();
+                self.rejected.entry(pending.bot_id).or_default().push(Rejection { location, expected, actual: Some(state) });
+            }
+        }
+    }
+
+    /// Reject the changes in `dimension` the server did not answer within `timeout` ticks. Blocks which are still what
+    /// we changed them to go back to what they were before.
+    pub fn expire(&mut self, dimension: Dimension, tick: usize, timeout: usize, blocks: &mut WorldBlocks) {
+        let mut expired: Vec<_> = self.pending.iter()
+            .filter(|((at, _), pending)| *at == dimension && pending.changes.front().map_or(true, |&(_, made)| made + timeout <= tick))
+            .map(|(&key, _)| key)
+            .collect();
+
+        // rejections are taken in order, so they must not depend on the order of the map
+        expired.sort_by_key(|&(_, location)| location);
+
+        for key in expired {
+            let (_, location) = key;
+            let pending = self.pending.remove(&key).unwrap();
+            let expected = pending.changes.back().map(|&(expected, _)| expected).unwrap();
+
+            if let Some(previous) = pending.previous {
+                if blocks.get_block_exact(location) == Some(expected) {
+                    blocks.set_block(location, previous);
+                }
+            }
+
+            self.rejected.entry(pending.bot_id).or_default().push(Rejection { location, expected, actual: None });
+        }
+    }
+
+    /// the rejected changes of `bot_id` since the last time
+    pub fn take_rejected(&mut self, bot_id: u32) -> Vec<Rejection> {
+        self.rejected.remove(&bot_id).unwrap_or_default()
+    }
+
+    /// how many blocks wait for an answer of the server
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::global::expectations::{EXPECT_TICKS, Expectations, Rejection};
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::types::Dimension;
+
+    const OVERWORLD: Dimension = Dimension::Overworld;
+
+    #[test]
+    fn test_answer() {
+        let mut expectations = Expectations::default();
+        let location = BlockLocation::new(0, 1, 0);
+        let wheat = BlockState::from(59, 7);
+        let planted = BlockState::from(59, 0);
+
+        // harvesting and replanting a crop. The server answers both.
+        expectations.expect(0, OVERWORLD, location, Some(wheat), BlockState::AIR, 0);
+        expectations.expect(0, OVERWORLD, location, Some(BlockState::AIR), planted, 1);
+        expectations.answer(OVERWORLD, location, BlockState::AIR);
+        assert_eq!(expectations.pending(), 1);
+        expectations.answer(OVERWORLD, location, planted);
+        assert_eq!(expectations.pending(), 0);
+        assert!(expectations.take_rejected(0).is_empty());
+
+        // the same block in another dimension is not ours
+        expectations.expect(1, OVERWORLD, location, Some(BlockState::STONE), BlockState::AIR, 2);
+        expectations.answer(Dimension::Nether, location, BlockState::STONE);
+        assert_eq!(expectations.pending(), 1);
+
+        expectations.answer(OVERWORLD, location, BlockState::STONE);
+        assert_eq!(expectations.pending(), 0);
+        assert_eq!(expectations.take_rejected(1), vec![Rejection { location, expected: BlockState::AIR, actual: Some(BlockState::STONE) }]);
+        assert!(expectations.take_rejected(1).is_empty());
+    }
+
+    #[test]
+    fn test_expire() {
+        let mut blocks = WorldBlocks::flat();
+        let mut expectations = Expectations::default();
+
+        let mined = BlockLocation::new(0, 0, 0);
+        let placed = BlockLocation::new(1, 1, 0);
+        let changed = BlockLocation::new(2, 1, 0);
+        let torch = BlockState::from(BlockKind::TORCH.id(), 5);
+
+        blocks.set_block(mined, BlockState::AIR);
+        blocks.set_block(placed, BlockState::STONE);
+        blocks.set_block(changed, torch);
+        expectations.expect(0, OVERWORLD, mined, Some(BlockState::STONE), BlockState::AIR, 0);
+        expectations.expect(0, OVERWORLD, placed, Some(BlockState::AIR), BlockState::STONE, 5);
+        expectations.expect(0, OVERWORLD, changed, Some(BlockState::AIR), BlockState::STONE, 5);
+
+        // a chunk was resent since we placed
+        blocks.set_block(changed, BlockState::AIR);
+
+        expectations.expire(OVERWORLD, EXPECT_TICKS, EXPECT_TICKS, &mut blocks);
+        assert_eq!(expectations.pending(), 2);
+        assert_eq!(blocks.get_block_exact(mined), Some(BlockState::STONE));
+
+        expectations.expire(OVERWORLD, EXPECT_TICKS + 5, EXPECT_TICKS, &mut blocks);
+        assert_eq!(expectations.pending(), 0);
+        assert_eq!(blocks.get_block_exact(placed), Some(BlockState::AIR));
+        assert_eq!(blocks.get_block_exact(changed), Some(BlockState::AIR));
+        assert_eq!(expectations.take_rejected(0).len(), 3);
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Bots guarding an area share out the mobs in it, so five guards do not all go for the same zombie while a creeper
+//! walks past. Every tick a fighting guard claims its target again.
+
+use std::collections::HashMap;
+
+/// claims not renewed for this many ticks are dropped (i.e., the guard died or logged out)
+const STALE_TICKS: usize = 20;
+
+#[derive(Debug, Default)]
+pub struct Guards {
+    /// the bot fighting each entity and the tick it last claimed it
+    claims: HashMap<u32, (u32, usize)>,
+}
+
+impl Guards {
+    /// `bot_id` fights `entity` at tick `ticks`. A claim of another bot is taken over.
+    pub fn claim(&mut self, entity: u32, bot_id: u32, ticks: usize) {
+        self.claims.insert(entity, (bot_id, ticks));
+    }
+
+    /// the bot other than `bot_id` which fights `entity` as of tick `ticks`
+    pub fn claimed_by(&self, entity: u32, bot_id: u32, ticks: usize) -> Option<u32> {
+        match self.claims.get(&entity) {
+            Some(&(id, tick)) if id != bot_id && tick + STALE_TICKS >= ticks => Some(id),
+            _ => None,
+        }
+    }
+
+    /// `bot_id` does not fight anything anymore
+    pub fn release(&mut self, bot_id: u32) {
+        self.claims.retain(|_, &mut (id, _)| id != bot_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::global::guards::Guards;
+
+    #[test]
+    fn test_claims() {
+        let mut guards = Guards::default();
+        guards.claim(100, 1, 0);
+
+        assert_eq!(guards.claimed_by(100, 2, 5), Some(1));
+        assert_eq!(guards.claimed_by(100, 1, 5), None);
+        assert_eq!(guards.claimed_by(101, 2, 5), None);
+
+        // a guard which stops renewing its claim leaves the mob to the others
+        assert_eq!(guards.claimed_by(100, 2, 30), None);
+
+        guards.claim(100, 1, 30);
+        guards.release(1);
+        assert_eq!(guards.claimed_by(100, 2, 30), None);
+    }
+}
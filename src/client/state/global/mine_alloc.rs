@@ -15,7 +15,7 @@
  */
 
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 use itertools::Itertools;
 use rayon::prelude::ParallelSliceMut;
@@ -23,13 +23,16 @@ use rayon::prelude::ParallelSliceMut;
 use crate::storage::block::{BlockLocation, BlockLocation2D};
 
 /// Represents the bottom left corner of a region
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct MineRegion(BlockLocation2D);
 
 /// Allocates mine regions to bots
 #[derive(Debug, Default)]
 pub struct MineAlloc {
     regions: VecDeque<MineRegion>,
+
+    /// the region each bot is mining by bot id. A claim ends when the bot asks for its next region.
+    claims: HashMap<u32, MineRegion>,
 }
 
 pub enum MinePreference {
@@ -45,14 +48,50 @@ impl MineAlloc {
 
     pub fn cancel(&mut self) {
         self.regions.clear();
+        self.claims.clear();
     }
 
-    pub fn obtain_region(&mut self) -> Option<BlockLocation2D> {
-        let BlockLocation2D { x, z } = self.regions.pop_front()?.0;
-        let centered = BlockLocation2D::new(x + Self::REGION_WIDTH / 2, z + Self::REGION_WIDTH / 2);
+    /// Claim the next region for `bot_id`. The region `bot_id` claimed before is considered mined.
+    pub fn obtain_region(&mut self, bot_id: u32) -> Option<BlockLocation2D> {
+        self.claims.remove(&bot_id);
+        let centered = self.regions().next()?;
+        let region = self.regions.pop_front()?;
+        self.claims.insert(bot_id, region);
         Some(centered)
     }
 
+    /// Give the region `bot_id` is mining back so the next bot asking for a region mines it instead. Used when a bot
+    /// stops before it finished its region.
+    pub fn release(&mut self, bot_id: u32) -> bool {
+        match self.claims.remove(&bot_id) {
+            Some(region) => {
+                self.regions.push_front(region);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// the center of the region `bot_id` is mining
+    pub fn claim(&self, bot_id: u32) -> Option<BlockLocation2D> {
+        self.claims.get(&bot_id).map(Self::center)
+    }
+
+    /// how many regions are claimed by a bot
+    pub fn claimed(&self) -> usize {
+        self.claims.len()
+    }
+
+    fn center(region: &MineRegion) -> BlockLocation2D {
+        let BlockLocation2D { x, z } = region.0;
+        BlockLocation2D::new(x + Self::REGION_WIDTH / 2, z + Self::REGION_WIDTH / 2)
+    }
+
+    /// the centers of the regions which are not allocated yet, in the order they will be allocated
+    pub fn regions(&self) -> impl Iterator<Item=BlockLocation2D> + '_ {
+        self.regions.iter().map(Self::center)
+    }
+
     fn locations_rad(center: BlockLocation2D, rad: i32) -> Locations {
         (0..256).cartesian_product(-rad..=rad).cartesian_product(-rad..=rad)
             .map(move |((y, z), x)| BlockLocation::new(center.x + x, y as i16, center.z + z))
@@ -104,4 +143,65 @@ impl MineAlloc {
             self.regions.push_back(elem);
         }
     }
+
+    /// The corners of the regions not mined yet for a snapshot. The claimed regions come first as the bots mining them
+    /// will not be back with the same id.
+    pub fn save(&self) -> Vec<BlockLocation2D> {
+        let claims = self.claims.iter().sorted_by_key(|(id, _)| **id).map(|(_, region)| region);
+        claims.chain(&self.regions).map(|region| region.0).collect()
+    }
+
+    /// take over the regions of a snapshot. Ignored if there is a `#mine` going on already.
+    pub fn restore(&mut self, corners: Vec<BlockLocation2D>) {
+        if self.regions.is_empty() && self.claims.is_empty() {
+            self.regions = corners.into_iter().map(MineRegion).collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::global::mine_alloc::MineAlloc;
+    use crate::storage::block::BlockLocation2D;
+
+    #[test]
+    fn test_release() {
+        let mut alloc = MineAlloc::default();
+        alloc.mine(BlockLocation2D::new(0, 0), BlockLocation2D::new(20, 0), None);
+        assert_eq!(alloc.regions().count(), 3);
+
+        let first = alloc.obtain_region(0).unwrap();
+        let second = alloc.obtain_region(1).unwrap();
+        assert_eq!(alloc.claim(0), Some(first));
+        assert_eq!(alloc.claimed(), 2);
+
+        // bot 1 stops early so its region is handed out next
+        assert!(alloc.release(1));
+        assert!(!alloc.release(1));
+        assert_eq!(alloc.obtain_region(2), Some(second));
+
+        // asking for the next region finishes the previous one
+        let third = alloc.obtain_region(0).unwrap();
+        assert_ne!(third, first);
+        assert_eq!(alloc.obtain_region(0), None);
+        assert_eq!(alloc.claim(0), None);
+        assert_eq!(alloc.claimed(), 1);
+    }
+
+    #[test]
+    fn test_save() {
+        let mut alloc = MineAlloc::default();
+        alloc.mine(BlockLocation2D::new(0, 0), BlockLocation2D::new(20, 0), None);
+        let first = alloc.obtain_region(4).unwrap();
+
+        let saved = alloc.save();
+        assert_eq!(saved.len(), 3);
+
+        // the claimed region is handed out first after a restart
+        let mut restored = MineAlloc::default();
+        restored.restore(saved);
+        assert_eq!(restored.regions().count(), 3);
+        assert_eq!(restored.obtain_region(0), Some(first));
+        assert_eq!(restored.obtain_region(1), alloc.obtain_region(5));
+    }
 }
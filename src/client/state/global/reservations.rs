@@ -0,0 +1,115 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Bots following paths through the same blocks take turns instead of shoving each other off their paths. Every tick
+//! a navigating bot claims the block it is in and the next few blocks of its path. It waits if one of those blocks has
+//! a bot in it or was claimed by a bot with a lower id.
+
+use std::collections::HashMap;
+
+use crate::storage::block::BlockLocation;
+
+/// how many blocks of its path a bot claims ahead of it
+pub const HORIZON: usize = 3;
+
+/// claims not renewed for this many ticks are dropped (i.e., the bot stopped navigating)
+const STALE_TICKS: usize = 5;
+
+#[derive(Debug)]
+struct Claim {
+    /// the block the bot is in
+    at: BlockLocation,
+
+    /// the next blocks of its path. Empty while waiting.
+    ahead: Vec<BlockLocation>,
+
+    /// the bot it waits for
+    waiting_for: Option<u32>,
+    tick: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Reservations {
+    /// the claim of each bot by id
+    claims: HashMap<u32, Claim>,
+}
+
+impl Reservations {
+    /// Claim `at` and `ahead` (the next blocks of the path, closest first) for `bot_id` at tick `ticks`. Returns the
+    /// bot to wait for if there is one in the way. Two bots waiting for each other would never move, so the one with
+    /// the lower id goes first.
+    pub fn claim(&mut self, bot_id: u32, at: BlockLocation, ahead: &[BlockLocation], ticks: usize) -> Option<u32> {
+        self.claims.retain(|&id, claim| id == bot_id || claim.tick + STALE_TICKS >= ticks);
+
+        let blocker = self.claims.iter()
+            .filter(|&(&id, claim)| id != bot_id && !(bot_id < id && claim.waiting_for == Some(bot_id)))
+            .filter(|&(&id, claim)| {
+                ahead.iter()
+                    .filter(|&&block| block != at)
+                    .any(|block| *block == claim.at || (id < bot_id && claim.ahead.contains(block)))
+            })
+            .map(|(&id, _)| id)
+            .min();
+
+        let ahead = if blocker.is_some() { Vec::new() } else { ahead.to_vec() };
+        self.claims.insert(bot_id, Claim { at, ahead, waiting_for: blocker, tick: ticks });
+        blocker
+    }
+
+    /// `bot_id` is not navigating anymore
+    pub fn release(&mut self, bot_id: u32) {
+        self.claims.remove(&bot_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::global::reservations::Reservations;
+    use crate::storage::block::BlockLocation;
+
+    fn block(x: i32) -> BlockLocation {
+        BlockLocation::new(x, 1, 0)
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut reservations = Reservations::default();
+
+        // both are headed for block 2. The lower id gets it even though it asked second.
+        assert_eq!(reservations.claim(5, block(3), &[block(2), block(1)], 0), None);
+        assert_eq!(reservations.claim(1, block(0), &[block(1), block(2)], 0), None);
+        assert_eq!(reservations.claim(5, block(3), &[block(2), block(1)], 1), Some(1));
+
+        // a bot in the way is waited for whatever its id
+        assert_eq!(reservations.claim(1, block(0), &[block(1), block(2)], 1), None);
+        assert_eq!(reservations.claim(7, block(-1), &[block(0), block(1)], 1), Some(1));
+
+        // once it stops renewing its claim the way is free
+        reservations.release(5);
+        assert_eq!(reservations.claim(7, block(-1), &[block(0), block(1)], 10), None);
+    }
+
+    #[test]
+    fn test_deadlock() {
+        let mut reservations = Reservations::default();
+
+        // facing each other in a corridor
+        assert_eq!(reservations.claim(2, block(0), &[block(1)], 0), None);
+        assert_eq!(reservations.claim(4, block(1), &[block(0)], 0), Some(2));
+        assert_eq!(reservations.claim(2, block(0), &[block(1)], 1), None);
+        assert_eq!(reservations.claim(4, block(1), &[block(0)], 1), Some(2));
+    }
+}
@@ -0,0 +1,273 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Named locations shared by the whole swarm (`#waypoint set base`). They are saved to disk so they survive restarts,
+//! and every command taking a location accepts a waypoint name instead of coordinates.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::client::bot::{ProcessError, WrongArgCount};
+use crate::error::Res;
+use crate::storage::block::{BlockLocation, BlockLocation2D};
+use crate::types::Dimension;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Waypoint {
+    pub location: BlockLocation,
+
+    /// a waypoint can only be used by bots in the same dimension
+    pub dimension: Dimension,
+
+    /// the player who set it through chat. None if it was set from the console or the WebSocket.
+    pub set_by: Option<String>,
+
+    /// unix seconds
+    pub set_at: u64,
+}
+
+impl Waypoint {
+    pub fn new(location: BlockLocation, dimension: Dimension, set_by: Option<&str>) -> Waypoint {
+        let set_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+        Waypoint { location, dimension, set_by: set_by.map(String::from), set_at }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum WaypointError {
+    #[error("there is no waypoint called {name} (known: {known})")]
+    Unknown { name: String, known: String },
+
+    #[error("waypoint {name} is in the {dimension} but I am in the {current}")]
+    WrongDimension { name: String, dimension: Dimension, current: Dimension },
+
+    #[error("{0} is not a valid waypoint name, names start with a letter and only have letters, digits, - and _")]
+    InvalidName(String),
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Root {
+    waypoints: BTreeMap<String, Waypoint>,
+}
+
+/// The waypoints by lowercase name. Names start with a letter so they can never be mistaken for coordinates.
+#[derive(Debug, Default)]
+pub struct Waypoints {
+    /// where the waypoints are saved. None if they are only kept in memory.
+    file: Option<PathBuf>,
+    by_name: BTreeMap<String, Waypoint>,
+}
+
+impl Waypoints {
+    /// The waypoints in `file` (none if it does not exist yet). Changes are saved back to it.
+    pub fn load(file: PathBuf) -> Res<Waypoints> {
+        let Root { waypoints } = match fs::read_to_string(&file) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Root::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Waypoints { file: Some(file), by_name: waypoints })
+    }
+
+    /// Write to a temporary file first so a crash while writing cannot lose the waypoints
+    fn save(&self) -> Res {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let tmp = file.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&Root { waypoints: self.by_name.clone() })?)?;
+        fs::rename(&tmp, file)?;
+        Ok(())
+    }
+
+    fn saved(&self) {
+        if let Err(e) = self.save() {
+            warn!(file = ?self.file, error = %e, "could not save waypoints");
+        }
+    }
+
+    /// Set (or move) the waypoint `name`. Every bot sees a chat command, so the last one to process it wins.
+    pub fn set(&mut self, name: &str, waypoint: Waypoint) -> Result<(), WaypointError> {
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !valid {
+            return Err(WaypointError::InvalidName(name.to_string()));
+        }
+
+        self.by_name.insert(name.to_ascii_lowercase(), waypoint);
+        self.saved();
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Waypoint, WaypointError> {
+        let removed = self.by_name.remove(&name.to_ascii_lowercase()).ok_or_else(|| self.unknown(name))?;
+        self.saved();
+        Ok(removed)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Waypoint> {
+        self.by_name.get(&name.to_ascii_lowercase())
+    }
+
+    /// by name
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &Waypoint)> + '_ {
+        self.by_name.iter().map(|(name, waypoint)| (name.as_str(), waypoint))
+    }
+
+    fn unknown(&self, name: &str) -> WaypointError {
+        let known = if self.by_name.is_empty() {
+            "none".to_string()
+        } else {
+            self.by_name.keys().join(", ")
+        };
+        WaypointError::Unknown { name: name.to_string(), known }
+    }
+
+    /// the location of the waypoint `name` for a bot in `dimension`
+    pub fn resolve(&self, name: &str, dimension: Dimension) -> Result<BlockLocation, WaypointError> {
+        let waypoint = self.get(name).ok_or_else(|| self.unknown(name))?;
+        if waypoint.dimension != dimension {
+            return Err(WaypointError::WrongDimension { name: name.to_string(), dimension: waypoint.dimension, current: dimension });
+        }
+        Ok(waypoint.location)
+    }
+
+    /// The location at the start of `args`, either `x y z` or a waypoint name, and the args after it
+    pub fn take_location<'a, 'b>(&self, args: &'b [&'a str], dimension: Dimension) -> Result<(BlockLocation, &'b [&'a str]), ProcessError> {
+        match args {
+            [name, rest @ ..] if is_name(name) => Ok((self.resolve(name, dimension)?, rest)),
+            [x, y, z, rest @ ..] => Ok((BlockLocation::new(x.parse()?, y.parse()?, z.parse()?), rest)),
+            _ => Err(WrongArgCount::new(3).into()),
+        }
+    }
+
+    /// `args` as exactly `x y z` or a waypoint name
+    pub fn location(&self, args: &[&str], dimension: Dimension) -> Result<BlockLocation, ProcessError> {
+        match self.take_location(args, dimension)? {
+            (location, []) => Ok(location),
+            _ => Err(WrongArgCount::new(3).into()),
+        }
+    }
+
+    /// The column at the start of `args`, either `x z` or a waypoint name, and the args after it
+    pub fn take_column<'a, 'b>(&self, args: &'b [&'a str], dimension: Dimension) -> Result<(BlockLocation2D, &'b [&'a str]), ProcessError> {
+        match args {
+            [name, rest @ ..] if is_name(name) => Ok((self.resolve(name, dimension)?.into(), rest)),
+            [x, z, rest @ ..] => Ok((BlockLocation2D::new(x.parse()?, z.parse()?), rest)),
+            _ => Err(WrongArgCount::new(2).into()),
+        }
+    }
+}
+
+/// coordinates start with a digit or a sign, names with a letter
+fn is_name(arg: &str) -> bool {
+    arg.starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::client::bot::ProcessError;
+    use crate::client::state::global::waypoints::{Waypoint, WaypointError, Waypoints};
+    use crate::storage::block::{BlockLocation, BlockLocation2D};
+    use crate::types::Dimension;
+
+    fn waypoint(x: i32, y: i16, z: i32, dimension: Dimension) -> Waypoint {
+        Waypoint { location: BlockLocation::new(x, y, z), dimension, set_by: Some("Steve".to_string()), set_at: 1_600_000_000 }
+    }
+
+    fn waypoints() -> Waypoints {
+        let mut waypoints = Waypoints::default();
+        waypoints.set("Base", waypoint(10, 64, -20, Dimension::Overworld)).unwrap();
+        waypoints.set("hub", waypoint(0, 100, 0, Dimension::Nether)).unwrap();
+        waypoints
+    }
+
+    #[test]
+    fn test_parse() {
+        let waypoints = waypoints();
+        let overworld = Dimension::Overworld;
+
+        assert_eq!(waypoints.location(&["1", "2", "-3"], overworld).unwrap(), BlockLocation::new(1, 2, -3));
+        assert_eq!(waypoints.location(&["base"], overworld).unwrap(), BlockLocation::new(10, 64, -20));
+        assert_eq!(waypoints.location(&["BASE"], overworld).unwrap(), BlockLocation::new(10, 64, -20));
+
+        let (location, rest) = waypoints.take_location(&["base", "4"], overworld).unwrap();
+        assert_eq!(location, BlockLocation::new(10, 64, -20));
+        assert_eq!(rest, &["4"]);
+
+        let (column, rest) = waypoints.take_column(&["1", "2", "base"], overworld).unwrap();
+        assert_eq!(column, BlockLocation2D::new(1, 2));
+        assert_eq!(waypoints.take_column(rest, overworld).unwrap().0, BlockLocation2D::new(10, -20));
+
+        assert!(matches!(waypoints.location(&["1", "2"], overworld), Err(ProcessError::Count(_))));
+        assert!(matches!(waypoints.location(&["1", "2", "3", "4"], overworld), Err(ProcessError::Count(_))));
+        assert!(matches!(waypoints.location(&["1", "x2", "3"], overworld), Err(ProcessError::Parse(_))));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let waypoints = waypoints();
+
+        let unknown = waypoints.resolve("mine1", Dimension::Overworld).unwrap_err();
+        assert_eq!(unknown.to_string(), "there is no waypoint called mine1 (known: base, hub)");
+        assert_eq!(Waypoints::default().resolve("base", Dimension::Overworld).unwrap_err().to_string(), "there is no waypoint called base (known: none)");
+
+        // a nether waypoint is not somewhere to go from the overworld
+        let wrong = waypoints.resolve("hub", Dimension::Overworld).unwrap_err();
+        assert_eq!(wrong, WaypointError::WrongDimension { name: "hub".to_string(), dimension: Dimension::Nether, current: Dimension::Overworld });
+        assert_eq!(wrong.to_string(), "waypoint hub is in the nether but I am in the overworld");
+        assert_eq!(waypoints.resolve("hub", Dimension::Nether).unwrap(), BlockLocation::new(0, 100, 0));
+
+        let mut waypoints = waypoints;
+        assert_eq!(waypoints.set("1st", waypoint(0, 0, 0, Dimension::Overworld)), Err(WaypointError::InvalidName("1st".to_string())));
+        assert_eq!(waypoints.set("a;b", waypoint(0, 0, 0, Dimension::Overworld)), Err(WaypointError::InvalidName("a;b".to_string())));
+        assert_eq!(waypoints.remove("hub").unwrap().dimension, Dimension::Nether);
+        assert!(matches!(waypoints.remove("hub"), Err(WaypointError::Unknown { .. })));
+    }
+
+    #[test]
+    fn test_save() {
+        let path = std::env::temp_dir().join(format!("swarm-bot-waypoints-{}.json", std::process::id()));
+
+        let mut waypoints = Waypoints::load(PathBuf::from(&path)).unwrap();
+        assert_eq!(waypoints.iter().count(), 0);
+        waypoints.set("base", waypoint(10, 64, -20, Dimension::Overworld)).unwrap();
+        waypoints.set("hub", waypoint(0, 100, 0, Dimension::Nether)).unwrap();
+        waypoints.remove("hub").unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("Steve"));
+        assert!(contents.contains("overworld"));
+
+        let loaded = Waypoints::load(PathBuf::from(&path)).unwrap();
+        assert_eq!(loaded.iter().map(|(name, _)| name).collect::<Vec<_>>(), vec!["base"]);
+        assert_eq!(loaded.get("base"), Some(&waypoint(10, 64, -20, Dimension::Overworld)));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -14,38 +14,122 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+//! The players on the server as the tab list shows them. Every bot receives the same list, so a player is kept once
+//! by their uuid however many bots told us about them.
+
+use indexmap::IndexMap;
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Player {
     pub name: String,
     pub uuid: u128,
+
+    /// the latency the server measured in milliseconds
+    pub ping: u32,
+
+    /// 0 survival, 1 creative, 2 adventure, 3 spectator
+    pub gamemode: u8,
+}
+
+impl Player {
+    pub fn new(name: &str, uuid: u128) -> Player {
+        Player { name: name.to_string(), uuid, ping: 0, gamemode: 0 }
+    }
+}
+
+/// Usernames which make the bots stop what they are doing when they join (i.e., the staff of the server)
+#[derive(Clone, Debug, Default)]
+pub struct PanicConfig {
+    pub names: Vec<String>,
+
+    /// leave the server too
+    pub disconnect: bool,
+}
+
+impl PanicConfig {
+    pub fn triggered_by(&self, name: &str) -> bool {
+        self.names.iter().any(|panic| panic.eq_ignore_ascii_case(name))
+    }
 }
 
 #[derive(Default)]
 pub struct WorldPlayers {
-    players: Vec<Player>,
+    /// in the order they joined
+    players: IndexMap<u128, Player>,
 }
 
 impl WorldPlayers {
-    pub fn add(&mut self, player: Player) {
-        self.players.push(player);
+    /// Add the player or replace what we knew about them. Returns true if they were not online before.
+    pub fn add(&mut self, player: Player) -> bool {
+        self.players.insert(player.uuid, player).is_none()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&Player> {
+        self.players.values()
     }
 
-    pub fn by_name(&mut self, name: &str) -> Option<&Player> {
-        self.players.iter().find(|player| player.name == name)
+    pub fn len(&self) -> usize {
+        self.players.len()
     }
 
-    pub fn by_uuid(&mut self, uuid: u128) -> Option<&Player> {
-        self.players.iter().find(|player| player.uuid == uuid)
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Player> {
+        self.iter().find(|player| player.name == name)
+    }
+
+    pub fn by_uuid(&self, uuid: u128) -> Option<&Player> {
+        self.players.get(&uuid)
+    }
+
+    pub fn set_ping(&mut self, uuid: u128, ping: u32) {
+        if let Some(player) = self.players.get_mut(&uuid) {
+            player.ping = ping;
+        }
+    }
+
+    pub fn set_gamemode(&mut self, uuid: u128, gamemode: u8) {
+        if let Some(player) = self.players.get_mut(&uuid) {
+            player.gamemode = gamemode;
+        }
+    }
+
+    /// the mean ping of the players the server measured one for. A crude sign of how the server is doing.
+    pub fn mean_ping(&self) -> Option<u32> {
+        let pings: Vec<_> = self.iter().map(|player| player.ping).filter(|&ping| ping > 0).collect();
+        (!pings.is_empty()).then(|| pings.iter().sum::<u32>() / pings.len() as u32)
     }
 
     /// return true if successfully removed
     pub fn remove(&mut self, uuid: u128) -> bool {
+        self.players.shift_remove(&uuid).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::global::world_players::{Player, WorldPlayers};
+
+    #[test]
+    fn test_players() {
+        let mut players = WorldPlayers::default();
+        assert!(players.add(Player::new("Notch", 1)));
+        assert!(players.add(Player::new("jeb_", 2)));
+
+        // another bot receiving the same player
+        assert!(!players.add(Player::new("Notch", 1)));
+        assert_eq!(players.len(), 2);
 
-        let mut action = ||{
-            let idx = self.players.iter().position(|player| player.uuid == uuid)?;
-            self.players.swap_remove(idx);
-            Some(())
-        };
+        players.set_ping(1, 40);
+        players.set_ping(2, 100);
+        players.set_gamemode(2, 1);
+        assert_eq!(players.by_name("jeb_").map(|player| (player.ping, player.gamemode)), Some((100, 1)));
+        assert_eq!(players.mean_ping(), Some(70));
 
-        action().is_some()
+        assert!(players.remove(1));
+        assert!(!players.remove(1));
+        assert_eq!(players.iter().map(|player| player.uuid).collect::<Vec<_>>(), vec![2]);
     }
 }
@@ -14,13 +14,35 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use tracing::Span;
+
+use crate::chat::ChatEvent;
+use crate::client::desync::Desync;
+use crate::client::pathfind::penalty::Penalties;
 use crate::client::physics::Physics;
-use crate::client::state::local::inventory::PlayerInventory;
+use crate::client::routine::Demonstration;
+use crate::client::state::local::abilities::Abilities;
+use crate::client::state::local::effects::Effects;
+use crate::client::state::local::inventory::{PlayerInventory, Window};
+use crate::client::state::local::spawn::Spawn;
+use crate::client::state::local::stats::BotStats;
+use crate::client::tasks::recover::Death;
+use crate::client::tasks::TaskError;
+use crate::client::view::View;
 use crate::protocol::{ClientInfo, Face};
+use crate::protocol::disconnect::Disconnect;
 use crate::storage::block::BlockLocation;
-use crate::types::Dimension;
+use crate::storage::blocks::raycast::AimCache;
+use crate::types::{Dimension, Displacement, GameMode};
 
+pub mod abilities;
+pub mod armor;
+pub mod effects;
 pub mod inventory;
+pub mod spawn;
+pub mod stats;
 
 pub enum TaskKind { Mine(BlockLocation, Face), Eat }
 
@@ -32,10 +54,68 @@ pub struct LocalState {
     pub bot_id: u32,
     pub physics: Physics,
     pub disconnected: bool,
+
+    /// why the server disconnected the bot. None if it did not say.
+    pub disconnect: Option<Disconnect>,
     pub inventory: PlayerInventory,
+
+    /// the window the server opened (i.e., a chest). None if only the inventory is open.
+    pub window: Option<Window>,
     pub info: ClientInfo,
     pub alive: bool,
     pub dimension: Dimension,
+    pub stats: BotStats,
+
+    /// potion effects and the like the server says we have
+    pub effects: Effects,
+
+    /// whether the server lets us fly and whether we are
+    pub abilities: Abilities,
+
+    /// survival unless the server says otherwise
+    gamemode: GameMode,
+
+    /// what the bot is watching a player do. None if it is not recording.
+    pub demonstration: Option<Demonstration>,
+
+    /// the tick the bot last lost health. Used to not log out while in combat.
+    pub last_hurt: Option<usize>,
+
+    /// the tick and velocity of the last time the server knocked the bot back
+    pub last_knockback: Option<(usize, Displacement)>,
+
+    /// chat received since the last tick, for the [`crate::client::controller::Controller`] of the bot
+    pub chat: Vec<ChatEvent>,
+
+    /// where the bot looked to interact with blocks
+    pub aim: AimCache,
+
+    /// signs that the blocks we know are out of sync with the server
+    pub desync: Desync,
+
+    /// blocks the bot got stuck at, which paths avoid for a while
+    pub penalties: Penalties,
+
+    /// why the running task gave up. Taken when the task ends.
+    pub failure: Option<TaskError>,
+
+    /// the last task which gave up and why
+    pub last_failure: Option<String>,
+
+    /// where the bot died with items until it respawns and goes back for them
+    pub death: Option<Death>,
+
+    /// where the bot believes it reappears after dying
+    pub spawn: Spawn,
+
+    /// how many chunks around the bot the server sends
+    pub view: View,
+
+    /// attaches the name and id of the bot to everything logged while it is entered
+    pub span: Span,
+
+    /// what tasks pick at random with (i.e., which way to strafe). Seeded with [LocalState::seed] to replay a run.
+    pub rng: StdRng,
 }
 
 impl LocalState {
@@ -56,10 +136,76 @@ impl LocalState {
             bot_id,
             physics: Physics::default(),
             disconnected: false,
+            disconnect: None,
             inventory: PlayerInventory::default(),
+            window: None,
             alive: true,
             dimension: Dimension::Overworld,
+            stats: BotStats::default(),
+            effects: Effects::default(),
+            abilities: Abilities::default(),
+            gamemode: GameMode::default(),
+            demonstration: None,
+            last_hurt: None,
+            last_knockback: None,
+            chat: Vec::new(),
+            aim: AimCache::default(),
+            desync: Desync::default(),
+            penalties: Penalties::default(),
+            failure: None,
+            last_failure: None,
+            death: None,
+            spawn: Spawn::default(),
+            view: View::default(),
+            span: info_span!("bot", name = %info.username, id = bot_id),
+            rng: StdRng::from_entropy(),
             info,
         }
     }
+
+    /// derive everything the bot does at random from `seed`, so a simulated run goes the same way each time
+    pub fn seed(&mut self, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        self.physics.seed(rng.gen());
+        self.rng = rng;
+    }
+
+    pub fn gamemode(&self) -> GameMode {
+        self.gamemode
+    }
+
+    pub fn set_gamemode(&mut self, gamemode: GameMode) {
+        if gamemode != self.gamemode {
+            info!(from = ?self.gamemode, to = ?gamemode, "changed gamemode");
+        }
+        self.gamemode = gamemode;
+    }
+
+    /// Start or stop flying like creative mode does. Starting only works if the server lets us. The server is told in
+    /// [`crate::client::bot::Bot::run_sync`].
+    pub fn set_flying(&mut self, flying: bool) -> bool {
+        if flying && !self.abilities.may_fly {
+            return false;
+        }
+
+        if flying {
+            self.physics.start_flying();
+        } else {
+            self.physics.stop_flying();
+        }
+        true
+    }
+
+    /// The server changed what we may do. If it no longer lets us fly we fall like vanilla.
+    pub fn on_abilities(&mut self, abilities: Abilities) {
+        self.abilities = abilities;
+        self.physics.set_fly_speed(abilities.fly_speed);
+
+        if abilities.flying && abilities.may_fly {
+            self.physics.start_flying();
+        } else if self.physics.flying() {
+            debug!(may_fly = abilities.may_fly, "the server stopped us flying");
+            self.physics.stop_flying();
+        }
+    }
 }
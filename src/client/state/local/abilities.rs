@@ -0,0 +1,95 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! What the server lets the player do, i.e., fly in creative mode (Player Abilities)
+
+const INVULNERABLE: u8 = 0x01;
+const FLYING: u8 = 0x02;
+const MAY_FLY: u8 = 0x04;
+const CREATIVE: u8 = 0x08;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Abilities {
+    pub invulnerable: bool,
+
+    /// whether we are flying. The server is told when this changes.
+    pub flying: bool,
+
+    /// whether the server lets us fly
+    pub may_fly: bool,
+
+    /// blocks are broken instantly and placing does not use them up
+    pub creative: bool,
+
+    /// vanilla flies at 0.05 which is about 11 blocks/s
+    pub fly_speed: f32,
+    pub walk_speed: f32,
+}
+
+impl Default for Abilities {
+    fn default() -> Self {
+        Abilities {
+            invulnerable: false,
+            flying: false,
+            may_fly: false,
+            creative: false,
+            fly_speed: 0.05,
+            walk_speed: 0.1,
+        }
+    }
+}
+
+impl Abilities {
+    pub fn from_flags(flags: u8, fly_speed: f32, walk_speed: f32) -> Abilities {
+        Abilities {
+            invulnerable: flags & INVULNERABLE != 0,
+            flying: flags & FLYING != 0,
+            may_fly: flags & MAY_FLY != 0,
+            creative: flags & CREATIVE != 0,
+            fly_speed,
+            walk_speed,
+        }
+    }
+
+    pub fn flags(&self) -> u8 {
+        let mut flags = 0;
+        for (set, flag) in [(self.invulnerable, INVULNERABLE), (self.flying, FLYING), (self.may_fly, MAY_FLY), (self.creative, CREATIVE)] {
+            if set {
+                flags |= flag;
+            }
+        }
+        flags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::local::abilities::Abilities;
+
+    #[test]
+    fn test_flags() {
+        // creative mode
+        let abilities = Abilities::from_flags(0x0D, 0.05, 0.1);
+        assert!(abilities.invulnerable && abilities.may_fly && abilities.creative);
+        assert!(!abilities.flying);
+        assert_eq!(abilities.flags(), 0x0D);
+
+        let flying = Abilities { flying: true, ..abilities };
+        assert_eq!(flying.flags(), 0x0F);
+
+        assert_eq!(Abilities::default().flags(), 0);
+    }
+}
@@ -0,0 +1,227 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Recognizing armor and deciding which pieces to wear
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::state::local::inventory::ItemStack;
+use crate::storage::block::BlockKind;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArmorSlot {
+    Head,
+    Chest,
+    Legs,
+    Feet,
+}
+
+impl ArmorSlot {
+    pub const ALL: [ArmorSlot; 4] = [ArmorSlot::Head, ArmorSlot::Chest, ArmorSlot::Legs, ArmorSlot::Feet];
+
+    /// the slot in the player window (5-8)
+    pub fn window_slot(self) -> usize {
+        5 + self as usize
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ArmorMat {
+    Leather,
+    Chain,
+    Iron,
+    Gold,
+    Diamond,
+    Elytra,
+}
+
+impl ArmorMat {
+    /// multiplied with the slot's factor for the max durability
+    fn durability(self) -> u16 {
+        match self {
+            ArmorMat::Leather => 5,
+            ArmorMat::Chain | ArmorMat::Iron => 15,
+            ArmorMat::Gold => 7,
+            ArmorMat::Diamond => 33,
+            ArmorMat::Elytra => 27,
+        }
+    }
+
+    /// head, chest, legs, feet
+    fn defense(self) -> [u32; 4] {
+        match self {
+            ArmorMat::Leather => [1, 3, 2, 1],
+            ArmorMat::Chain => [2, 5, 4, 1],
+            ArmorMat::Iron => [2, 6, 5, 2],
+            ArmorMat::Gold => [2, 5, 3, 1],
+            ArmorMat::Diamond => [3, 8, 6, 3],
+            ArmorMat::Elytra => [0; 4],
+        }
+    }
+}
+
+// 1.12 enchantment ids
+const PROTECTION: u16 = 0;
+const FIRE_PROTECTION: u16 = 1;
+const FEATHER_FALLING: u16 = 2;
+const BLAST_PROTECTION: u16 = 3;
+const PROJECTILE_PROTECTION: u16 = 4;
+const BINDING_CURSE: u16 = 10;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Armor {
+    pub slot: ArmorSlot,
+    pub material: ArmorMat,
+}
+
+impl Armor {
+    pub const ELYTRA: BlockKind = BlockKind(443);
+
+    pub fn of(kind: BlockKind) -> Option<Armor> {
+        use ArmorMat::*;
+
+        let id = kind.id();
+        let material = match id {
+            298..=301 => Leather,
+            302..=305 => Chain,
+            306..=309 => Iron,
+            310..=313 => Diamond,
+            314..=317 => Gold,
+            443 => return Some(Armor { slot: ArmorSlot::Chest, material: Elytra }),
+            _ => return None
+        };
+
+        // every material has a helmet, chestplate, leggings and boots in that order
+        let slot = ArmorSlot::ALL[((id - 298) % 4) as usize];
+        Some(Armor { slot, material })
+    }
+
+    pub fn max_durability(self) -> u16 {
+        let factor = match self.slot {
+            ArmorSlot::Head => 11,
+            ArmorSlot::Chest => 16,
+            ArmorSlot::Legs => 15,
+            ArmorSlot::Feet => 13,
+        };
+        self.material.durability() * factor
+    }
+
+    pub fn defense(self) -> u32 {
+        self.material.defense()[self.slot as usize]
+    }
+
+    /// only diamond armor is tough in 1.12
+    pub fn toughness(self) -> u32 {
+        if self.material == ArmorMat::Diamond { 2 } else { 0 }
+    }
+}
+
+/// How much we want to wear `stack`. None if it is not armor we should put on by ourselves: elytras are only worn when
+/// asked for, and curse of binding pieces could never be taken off again.
+pub fn score(stack: &ItemStack) -> Option<u32> {
+    let armor = Armor::of(stack.kind)?;
+    if armor.material == ArmorMat::Elytra {
+        return None;
+    }
+
+    let enchantments = stack.nbt.as_ref().and_then(|nbt| nbt.ench.as_ref()).map(Vec::as_slice).unwrap_or_default();
+
+    let mut bonus = 0;
+    for ench in enchantments {
+        bonus += match ench.id {
+            BINDING_CURSE => return None,
+            PROTECTION => 4 * ench.lvl as u32,
+            FIRE_PROTECTION | FEATHER_FALLING | BLAST_PROTECTION | PROJECTILE_PROTECTION => 2 * ench.lvl as u32,
+            _ => 0
+        };
+    }
+
+    // a point of armor is 4% less damage, a level of protection 4% (but capped) so weigh them alike
+    Some(armor.defense() * 10 + armor.toughness() * 5 + bonus * 10 / 4)
+}
+
+/// how many more times `stack` can be damaged before it breaks. None if it is not armor.
+pub fn durability_left(stack: &ItemStack) -> Option<u16> {
+    let armor = Armor::of(stack.kind)?;
+    Some(armor.max_durability().saturating_sub(stack.damage))
+}
+
+#[derive(Clone, Debug)]
+pub struct ArmorConfig {
+    /// warn when a worn piece can take at most this many more hits
+    pub warn_at: u16,
+
+    /// take pieces off when they can take at most this many more hits so they do not break. None to wear them until
+    /// they break.
+    pub unequip_at: Option<u16>,
+}
+
+impl Default for ArmorConfig {
+    fn default() -> Self {
+        Self {
+            warn_at: 10,
+            unequip_at: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::local::armor::{Armor, ArmorMat, ArmorSlot, durability_left, score};
+    use crate::client::state::local::inventory::ItemStack;
+    use crate::storage::block::BlockKind;
+    use crate::types::{Enchantment, ItemNbt};
+
+    fn enchanted(id: u32, ench: Vec<Enchantment>) -> ItemStack {
+        ItemStack::new(BlockKind(id), 1, 0, Some(ItemNbt { ench: Some(ench), ..ItemNbt::default() }))
+    }
+
+    #[test]
+    fn test_recognize() {
+        assert_eq!(Armor::of(BlockKind(298)), Some(Armor { slot: ArmorSlot::Head, material: ArmorMat::Leather }));
+        assert_eq!(Armor::of(BlockKind(311)), Some(Armor { slot: ArmorSlot::Chest, material: ArmorMat::Diamond }));
+        assert_eq!(Armor::of(BlockKind(309)), Some(Armor { slot: ArmorSlot::Feet, material: ArmorMat::Iron }));
+        assert_eq!(Armor::of(BlockKind(316)), Some(Armor { slot: ArmorSlot::Legs, material: ArmorMat::Gold }));
+        assert_eq!(Armor::of(Armor::ELYTRA).unwrap().slot, ArmorSlot::Chest);
+        assert_eq!(Armor::of(BlockKind::STONE), None);
+
+        // vanilla durabilities
+        assert_eq!(Armor::of(BlockKind(310)).unwrap().max_durability(), 363);
+        assert_eq!(Armor::of(BlockKind(301)).unwrap().max_durability(), 65);
+        assert_eq!(Armor::of(Armor::ELYTRA).unwrap().max_durability(), 432);
+
+        assert_eq!(durability_left(&ItemStack::new(BlockKind(310), 1, 360, None)), Some(3));
+    }
+
+    #[test]
+    fn test_score() {
+        let iron = ItemStack::new(BlockKind(307), 1, 0, None);
+        let diamond = ItemStack::new(BlockKind(311), 1, 0, None);
+        let gold = ItemStack::new(BlockKind(315), 1, 0, None);
+        assert!(score(&diamond) > score(&iron));
+        assert!(score(&iron) > score(&gold));
+
+        // protection IV iron beats plain diamond
+        let protected = enchanted(307, vec![Enchantment { id: 0, lvl: 4 }]);
+        assert!(score(&protected) > score(&diamond));
+
+        // never put on by ourselves
+        assert_eq!(score(&enchanted(311, vec![Enchantment { id: 10, lvl: 1 }])), None);
+        assert_eq!(score(&ItemStack::new(Armor::ELYTRA, 1, 0, None)), None);
+        assert_eq!(score(&ItemStack::new(BlockKind::STONE, 1, 0, None)), None);
+    }
+}
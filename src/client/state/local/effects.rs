@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// A status effect from a potion, beacon or the like. Only the ones that change what the bot does are named.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Effect {
+    Speed,
+    Slowness,
+    Haste,
+    MiningFatigue,
+    JumpBoost,
+    Other(u8),
+}
+
+impl From<u8> for Effect {
+    fn from(id: u8) -> Self {
+        match id {
+            1 => Effect::Speed,
+            2 => Effect::Slowness,
+            3 => Effect::Haste,
+            4 => Effect::MiningFatigue,
+            8 => Effect::JumpBoost,
+            id => Effect::Other(id),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ActiveEffect {
+    /// one less than the level (Speed II has an amplifier of 1)
+    pub amplifier: u8,
+
+    /// ticks until the effect wears off
+    pub ticks: u32,
+}
+
+/// The effects the server says the bot has. They count down every tick.
+#[derive(Clone, Debug, Default)]
+pub struct Effects {
+    active: HashMap<Effect, ActiveEffect>,
+}
+
+impl Effects {
+    /// a new effect or a stronger or longer one replacing the old
+    pub fn add(&mut self, effect: Effect, amplifier: u8, ticks: u32) {
+        self.active.insert(effect, ActiveEffect { amplifier, ticks });
+    }
+
+    pub fn remove(&mut self, effect: Effect) {
+        self.active.remove(&effect);
+    }
+
+    /// dying clears every effect
+    pub fn clear(&mut self) {
+        self.active.clear();
+    }
+
+    pub fn has(&self, effect: Effect) -> bool {
+        self.active.contains_key(&effect)
+    }
+
+    pub fn get(&self, effect: Effect) -> Option<ActiveEffect> {
+        self.active.get(&effect).copied()
+    }
+
+    /// the level of `effect` (Speed II is 2), 0 if we do not have it
+    pub fn level(&self, effect: Effect) -> u8 {
+        self.get(effect).map_or(0, |active| active.amplifier.saturating_add(1))
+    }
+
+    /// how much faster blocks break with haste and how much slower with mining fatigue
+    pub fn dig_multiplier(&self) -> f64 {
+        let haste = 1.0 + 0.2 * self.level(Effect::Haste) as f64;
+        let fatigue = match self.get(Effect::MiningFatigue).map(|active| active.amplifier) {
+            None => 1.0,
+            Some(0) => 0.3,
+            Some(1) => 0.09,
+            Some(2) => 0.0027,
+            Some(_) => 0.00081,
+        };
+        haste * fatigue
+    }
+
+    /// count down a tick and drop the effects which wore off
+    pub fn tick(&mut self) {
+        self.active.retain(|_, active| {
+            active.ticks = active.ticks.saturating_sub(1);
+            active.ticks > 0
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::local::effects::{Effect, Effects};
+
+    #[test]
+    fn test_countdown() {
+        let mut effects = Effects::default();
+        effects.add(Effect::from(1), 1, 3);
+        effects.add(Effect::from(25), 0, 100);
+
+        assert!(effects.has(Effect::Speed));
+        assert_eq!(effects.level(Effect::Speed), 2);
+        assert_eq!(effects.level(Effect::Slowness), 0);
+        assert!(effects.has(Effect::Other(25)));
+
+        effects.tick();
+        effects.tick();
+        assert!(effects.has(Effect::Speed));
+        effects.tick();
+        assert!(!effects.has(Effect::Speed));
+        assert!(effects.has(Effect::Other(25)));
+
+        effects.remove(Effect::Other(25));
+        assert!(!effects.has(Effect::Other(25)));
+    }
+
+    #[test]
+    fn test_dig_multiplier() {
+        let mut effects = Effects::default();
+        assert!((effects.dig_multiplier() - 1.0).abs() < 1e-9);
+
+        effects.add(Effect::Haste, 1, 100);
+        assert!((effects.dig_multiplier() - 1.4).abs() < 1e-9);
+
+        effects.remove(Effect::Haste);
+        effects.add(Effect::MiningFatigue, 0, 100);
+        assert!((effects.dig_multiplier() - 0.3).abs() < 1e-9);
+    }
+}
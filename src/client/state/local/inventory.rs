@@ -14,13 +14,19 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::collections::HashMap;
+use std::str::FromStr;
+
 use crate::bootstrap::block_data::BlockData;
 use crate::client::physics::tools::{Tool, ToolMat};
-use crate::protocol::{InterfaceOut, InvAction};
+use crate::client::state::local::armor::{self, Armor, ArmorConfig, ArmorSlot};
+use crate::client::state::local::effects::Effects;
+use crate::error::{err, Error};
+use crate::protocol::{Hand, InterfaceOut, InvAction, MouseButton};
 use crate::storage::block::BlockKind;
 use crate::types::{ItemNbt, Slot};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ItemStack {
     pub kind: BlockKind,
     pub count: u8,
@@ -47,10 +53,139 @@ impl ItemStack {
     }
 }
 
+/// the first hotbar slot of the player window
+const HOTBAR: usize = 36;
+
+/// the first main inventory slot of the player window
+const MAIN: usize = 9;
+
+/// the off-hand slot of the player window
+pub const OFFHAND: usize = 45;
+
+/// the most items of a kind one slot holds
+const STACK_SIZE: u8 = 64;
+
+/// ores, chests and spawners, and coal, diamonds, iron and gold ingots, redstone, emeralds and quartz
+const DEFAULT_KEEP: &[u32] = &[14, 15, 16, 21, 56, 73, 74, 129, 153, 54, 146, 130, 52, 263, 264, 265, 266, 331, 388, 406];
+
+/// dirt, gravel, sand, netherrack, stone and cobblestone
+const DEFAULT_TOSS: &[u32] = &[3, 13, 12, 87, 1, 4];
+
+/// What to do when the inventory runs out of room
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FullPolicy {
+    /// drop the least valuable stacks
+    Toss,
+
+    /// walk to the chest at the waypoint, put what we mined in it and come back
+    Deposit(String),
+}
+
+impl FromStr for FullPolicy {
+    type Err = Error;
+
+    /// `toss` or `deposit <waypoint>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["toss"] => Ok(FullPolicy::Toss),
+            ["deposit", waypoint] => Ok(FullPolicy::Deposit(waypoint.to_string())),
+            _ => Err(err(&format!("unknown inventory policy {}", s))),
+        }
+    }
+}
+
+/// Which items are worth the room in the inventory
+#[derive(Clone, Debug)]
+pub struct InventoryPolicy {
+    /// kinds which are never dropped
+    pub keep: Vec<BlockKind>,
+
+    /// kinds which are dropped before anything else, the first one first
+    pub toss: Vec<BlockKind>,
+
+    /// make room when fewer slots than this have room (see [`PlayerInventory::room`])
+    pub min_free: usize,
+
+    pub full: FullPolicy,
+}
+
+impl Default for InventoryPolicy {
+    fn default() -> Self {
+        Self {
+            keep: DEFAULT_KEEP.iter().map(|&id| BlockKind(id)).collect(),
+            toss: DEFAULT_TOSS.iter().map(|&id| BlockKind(id)).collect(),
+            min_free: 2,
+            full: FullPolicy::Toss,
+        }
+    }
+}
+
+impl InventoryPolicy {
+    /// The order stacks of `kind` are dropped in: the kinds on the toss list first, then any other block. None if
+    /// they are never dropped, i.e., kept kinds and items which are not blocks (tools, food, ...).
+    fn toss_rank(&self, kind: BlockKind) -> Option<usize> {
+        if self.keep.contains(&kind) {
+            return None;
+        }
+
+        match self.toss.iter().position(|&toss| toss == kind) {
+            Some(position) => Some(position),
+            None if kind.id() < 256 => Some(self.toss.len()),
+            None => None,
+        }
+    }
+
+    /// whether `kind` is something we mined, which is put in a chest when the inventory is full
+    pub fn is_loot(&self, kind: BlockKind) -> bool {
+        self.keep.contains(&kind) || self.toss.contains(&kind) || kind.id() < 256
+    }
+}
+
+/// A window opened by the server, such as a chest
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Window {
+    pub id: u8,
+
+    /// how many slots the window has before the player inventory
+    pub slots: usize,
+}
+
+impl Window {
+    /// the slot of the window which shows player inventory slot `idx` (from the main inventory or hotbar)
+    pub fn slot(&self, idx: usize) -> u16 {
+        (idx - MAIN + self.slots) as u16
+    }
+}
+
 #[derive(Debug)]
 pub struct PlayerInventory {
     slots: [Option<ItemStack>; 46],
+
+    /// the hotbar slot the server thinks we hold
     selected: u8,
+
+    /// the server changed a slot since [`PlayerInventory::take_changed`] was last called
+    changed: bool,
+
+    pub armor: ArmorConfig,
+
+    pub policy: InventoryPolicy,
+
+    /// the armor slots we already warned about breaking
+    warned: [bool; 4],
+}
+
+/// A worn piece of armor which is about to break
+#[derive(Debug, PartialEq)]
+pub struct ArmorWarning {
+    pub slot: ArmorSlot,
+    pub kind: BlockKind,
+
+    /// how many more hits it can take
+    pub left: u16,
+
+    /// whether we took it off
+    pub unequipped: bool,
 }
 
 impl Default for PlayerInventory {
@@ -59,6 +194,10 @@ impl Default for PlayerInventory {
         Self {
             slots: [NONE; 46],
             selected: 0,
+            changed: false,
+            armor: ArmorConfig::default(),
+            policy: InventoryPolicy::default(),
+            warned: [false; 4],
         }
     }
 }
@@ -99,27 +238,165 @@ impl PlayerInventory {
         self.hotbar()[self.selected as usize].as_ref()
     }
 
-    pub fn change_slot(&mut self, idx: u8, out: &mut impl InterfaceOut) {
+    pub fn offhand(&self) -> Option<&ItemStack> {
+        self.slots[OFFHAND].as_ref()
+    }
+
+    pub fn in_hand(&self, hand: Hand) -> Option<&ItemStack> {
+        self.slots[self.hand_slot(hand)].as_ref()
+    }
+
+    /// the slot of the player window `hand` holds
+    pub fn hand_slot(&self, hand: Hand) -> usize {
+        match hand {
+            Hand::Main => HOTBAR + self.selected as usize,
+            Hand::Off => OFFHAND,
+        }
+    }
+
+    /// The hand holding `kind`, for using it (e.g., raising a shield). The main hand is preferred like vanilla does.
+    pub fn hand_holding(&self, kind: BlockKind) -> Option<Hand> {
+        IntoIterator::into_iter([Hand::Main, Hand::Off])
+            .find(|&hand| matches!(&self.slots[self.hand_slot(hand)], Some(stack) if stack.kind == kind))
+    }
+
+    /// one item in `hand` was used up
+    pub fn consume(&mut self, hand: Hand) {
+        let slot = &mut self.slots[self.hand_slot(hand)];
+        if let Some(stack) = slot {
+            stack.count = stack.count.saturating_sub(1);
+            if stack.count == 0 {
+                *slot = None;
+            }
+        }
+    }
+
+    /// swap the held item with the off-hand
+    pub fn swap_offhand(&mut self, out: &mut impl InterfaceOut) {
+        let held = self.hand_slot(Hand::Main);
+        self.slots.swap(held, OFFHAND);
+        out.inventory_action(InvAction::SwapOffhand);
+    }
+
+    /// Put a `kind` in the off-hand unless one is there already. An item in the hotbar is swapped in by briefly
+    /// selecting it, one in the rest of the inventory by swapping it into the held slot first. Either way the held item
+    /// stays the same. False if there is no `kind`.
+    pub fn equip_offhand(&mut self, kind: BlockKind, out: &mut impl InterfaceOut) -> bool {
+        if matches!(self.offhand(), Some(stack) if stack.kind == kind) {
+            return true;
+        }
+
+        let found = (HOTBAR..OFFHAND).chain(9..HOTBAR)
+            .find(|&idx| matches!(&self.slots[idx], Some(stack) if stack.kind == kind));
+
+        let idx = match found {
+            Some(idx) => idx,
+            None => return false
+        };
+
+        let selected = self.selected;
+        if idx >= HOTBAR {
+            self.select_slot((idx - HOTBAR) as u8, out);
+            self.swap_offhand(out);
+            self.select_slot(selected, out);
+        } else {
+            let held = self.hand_slot(Hand::Main);
+            out.inventory_action(InvAction::SwapHotbar(idx as u16, selected));
+            self.slots.swap(idx, held);
+            self.swap_offhand(out);
+            out.inventory_action(InvAction::SwapHotbar(idx as u16, selected));
+            self.slots.swap(idx, held);
+        }
+        true
+    }
+
+    /// Hold hotbar slot `idx`. Nothing is sent if it is held already, as switching more often than a player can looks
+    /// suspicious to anti cheats.
+    pub fn select_slot(&mut self, idx: u8, out: &mut impl InterfaceOut) {
         if self.selected != idx {
             self.selected = idx;
             out.change_slot(idx);
         }
     }
 
-    pub fn switch_block(&mut self, out: &mut impl InterfaceOut) {
-        self.switch_selector(out, |kind| kind.throw_away_block());
+    /// the server made us hold hotbar slot `idx` (i.e., when joining)
+    pub fn on_held_slot(&mut self, idx: u8) {
+        self.selected = idx;
+    }
+
+    /// Creative mode: put `count` of the item `id` in slot `idx` out of thin air, replacing what is there
+    pub fn conjure(&mut self, idx: usize, id: u32, count: u8, out: &mut impl InterfaceOut) {
+        let stack = ItemStack::new(BlockKind(id), count, 0, None);
+        out.inventory_action(InvAction::Creative(idx as u16, Some(stack.clone())));
+        self.add(idx, stack);
+    }
+
+    /// Like [`PlayerInventory::hold_item`], but in creative mode a stack of the item placing `fallback` is conjured into
+    /// the held slot if there is nothing which `matches`
+    pub fn hold_or_conjure(&mut self, matches: impl FnMut(BlockKind) -> bool, fallback: BlockKind, creative: bool, out: &mut impl InterfaceOut) -> bool {
+        if self.hold_item(matches, out) {
+            return true;
+        }
+
+        match fallback.item_id() {
+            Some(id) if creative => {
+                self.conjure(self.hand_slot(Hand::Main), id, 64, out);
+                true
+            }
+            _ => false
+        }
+    }
+
+    /// Hold an item of a kind which `matches`: the held one, one in the hotbar or one in the rest of the inventory, which
+    /// is swapped into the held slot. False if there is none.
+    pub fn hold_item(&mut self, mut matches: impl FnMut(BlockKind) -> bool, out: &mut impl InterfaceOut) -> bool {
+        let mut is_match = |stack: &Option<ItemStack>| stack.as_ref().map_or(false, |stack| matches(stack.kind));
+
+        let held = self.hand_slot(Hand::Main);
+        if is_match(&self.slots[held]) {
+            return true;
+        }
+
+        let found = (HOTBAR..OFFHAND).chain(MAIN..HOTBAR).find(|&idx| is_match(&self.slots[idx]));
+
+        match found {
+            Some(idx) if idx >= HOTBAR => self.select_slot((idx - HOTBAR) as u8, out),
+            Some(idx) => {
+                out.inventory_action(InvAction::SwapHotbar(idx as u16, self.selected));
+                self.slots.swap(idx, held);
+            }
+            None => return false
+        }
+        true
+    }
+
+    /// hold a block to build with, conjured in creative mode
+    pub fn switch_block(&mut self, creative: bool, out: &mut impl InterfaceOut) {
+        self.hold_or_conjure(|kind| kind.throw_away_block(), BlockKind::COBBLESTONE, creative, out);
     }
 
     /// true if successful
     pub fn switch_food(&mut self, data: &BlockData, out: &mut impl InterfaceOut) -> bool {
-        self.switch_selector(out, |kind| data.is_food(kind.id()))
+        self.hold_item(|kind| data.is_food(kind.id()), out)
     }
 
     pub fn switch_bucket(&mut self, out: &mut impl InterfaceOut) {
-        self.switch_selector(out, |kind| kind.id() == 325 || kind.id() == 326);
+        self.hold_item(|kind| kind == BlockKind::BUCKET || kind == BlockKind::WATER_BUCKET, out);
+    }
+
+    /// whether a water bucket is in the hotbar to break a fall with
+    pub fn has_water_bucket(&self) -> bool {
+        self.hotbar().iter().flatten().any(|stack| stack.kind == BlockKind::WATER_BUCKET)
     }
 
     pub fn switch_tool(&mut self, kind: BlockKind, data: &BlockData, out: &mut impl InterfaceOut) -> Tool {
+        let (best_idx, best_tool) = self.best_tool(kind, data);
+        self.select_slot(best_idx as u8, out);
+        best_tool
+    }
+
+    /// the hotbar slot of the tool which mines `kind` fastest
+    pub fn best_tool(&self, kind: BlockKind, data: &BlockData) -> (usize, Tool) {
         let tools = self.hotbar().iter()
             .enumerate()
             .map(|(idx, item_stack)| {
@@ -130,8 +407,9 @@ impl PlayerInventory {
                 (idx, tool)
             });
 
-        let (best_idx, best_tool) = tools.min_by_key(move |(_, tool)| {
-            let wait_time = tool.wait_time(kind, false, false, data);
+        tools.min_by_key(move |(_, tool)| {
+            // effects slow down or speed up every tool alike, so they do not change which is best
+            let wait_time = kind.break_ticks(tool, false, false, &Effects::default(), data);
 
             // bias towards a hand (so we do not lose durability)
             if tool.material == ToolMat::Hand {
@@ -139,34 +417,462 @@ impl PlayerInventory {
             } else {
                 wait_time + 1
             }
-        }).unwrap();
+        }).unwrap()
+    }
 
-        self.change_slot(best_idx as u8, out);
-        best_tool
+    pub fn worn(&self, slot: ArmorSlot) -> Option<&ItemStack> {
+        self.slots[slot.window_slot()].as_ref()
+    }
+
+    /// whether a worn elytra can be flown with. Vanilla stops gliding when it has 1 durability left.
+    pub fn elytra(&self) -> bool {
+        self.worn(ArmorSlot::Chest)
+            .filter(|stack| stack.kind == Armor::ELYTRA)
+            .and_then(armor::durability_left)
+            .map_or(false, |left| left > 1)
+    }
+
+    /// whether a piece would be taken off again right away for being about to break
+    fn too_damaged(&self, stack: &ItemStack) -> bool {
+        match (self.armor.unequip_at, armor::durability_left(stack)) {
+            (Some(unequip_at), Some(left)) => left <= unequip_at,
+            _ => false
+        }
+    }
+
+    /// Put on the best armor we have in every armor slot (see [`armor::score`]). A piece goes onto an empty slot with a
+    /// shift click. Otherwise it is picked up, clicked onto the armor slot (picking up the piece it replaces) and the
+    /// replaced piece is put where the new one was. Returns how many pieces were put on.
+    pub fn equip_armor(&mut self, out: &mut impl InterfaceOut) -> usize {
+        let mut equipped = 0;
+
+        for slot in ArmorSlot::ALL {
+            let target = slot.window_slot();
+
+            let current = match self.slots[target].as_ref() {
+                None => None,
+                Some(worn) => match armor::score(worn) {
+                    Some(score) => Some(score),
+
+                    // an elytra or a cursed piece is left alone
+                    None => continue
+                }
+            };
+
+            let best = (9..OFFHAND)
+                .filter_map(|idx| {
+                    let stack = self.slots[idx].as_ref()?;
+                    let is_slot = Armor::of(stack.kind)?.slot == slot;
+                    let score = armor::score(stack)?;
+                    (is_slot && !self.too_damaged(stack)).then(|| (idx, score))
+                })
+                .max_by_key(|&(_, score)| score)
+                .filter(|&(_, score)| Some(score) > current);
+
+            let idx = match best {
+                Some((idx, _)) => idx,
+                None => continue
+            };
+
+            let stack = self.slots[idx].take();
+            match self.slots[target].take() {
+                None => {
+                    out.inventory_action(InvAction::ShiftClick(idx as u16, MouseButton::Left, stack.clone()));
+                }
+                Some(worn) => {
+                    out.inventory_action(InvAction::Click(idx as u16, MouseButton::Left, stack.clone()));
+                    out.inventory_action(InvAction::Click(target as u16, MouseButton::Left, Some(worn.clone())));
+                    out.inventory_action(InvAction::Click(idx as u16, MouseButton::Left, None));
+                    self.slots[idx] = Some(worn);
+                }
+            }
+            self.slots[target] = stack;
+            self.warned[slot as usize] = false;
+            equipped += 1;
+        }
+
+        equipped
     }
 
-    pub fn switch_selector(&mut self, out: &mut impl InterfaceOut, mut block: impl FnMut(BlockKind) -> bool) -> bool {
-        let block_idx = self.hotbar().iter()
+    /// Take off the piece in `slot` with a shift click, which puts it in the first free slot of the inventory. False if
+    /// nothing is worn or there is no room.
+    pub fn unequip(&mut self, slot: ArmorSlot, out: &mut impl InterfaceOut) -> bool {
+        let target = slot.window_slot();
+        let free = (9..OFFHAND).find(|&idx| self.slots[idx].is_none());
+
+        match (self.slots[target].is_some(), free) {
+            (true, Some(free)) => {
+                let stack = self.slots[target].take();
+                out.inventory_action(InvAction::ShiftClick(target as u16, MouseButton::Left, stack.clone()));
+                self.slots[free] = stack;
+                true
+            }
+            _ => false
+        }
+    }
+
+    /// Worn pieces which can take at most [`ArmorConfig::warn_at`] more hits. Each piece is only reported once. Pieces at
+    /// [`ArmorConfig::unequip_at`] are taken off.
+    pub fn check_armor(&mut self, out: &mut impl InterfaceOut) -> Vec<ArmorWarning> {
+        let mut warnings = Vec::new();
+
+        for slot in ArmorSlot::ALL {
+            let (kind, left) = match self.worn(slot).and_then(|stack| Some((stack.kind, armor::durability_left(stack)?))) {
+                Some(worn) => worn,
+                None => {
+                    self.warned[slot as usize] = false;
+                    continue;
+                }
+            };
+
+            let unequip = matches!(self.armor.unequip_at, Some(unequip_at) if left <= unequip_at);
+            let unequipped = unequip && self.unequip(slot, out);
+
+            if left > self.armor.warn_at {
+                self.warned[slot as usize] = false;
+            } else if !self.warned[slot as usize] || unequipped {
+                self.warned[slot as usize] = !unequipped;
+                warnings.push(ArmorWarning { slot, kind, left, unequipped });
+            }
+        }
+
+        warnings
+    }
+
+    /// true once after the server changed a slot
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// the main inventory and hotbar stacks with their slots
+    pub fn stored(&self) -> impl Iterator<Item=(usize, &ItemStack)> {
+        self.slots[MAIN..OFFHAND].iter()
             .enumerate()
-            .filter_map(|(idx, item_stack)| {
-                let item_stack = item_stack.as_ref()?;
-                block(item_stack.kind).then(|| idx)
+            .filter_map(|(idx, stack)| Some((idx + MAIN, stack.as_ref()?)))
+    }
+
+    /// How many of each kind we carry, i.e., everything which drops when we die: the crafting grid, armor, main
+    /// inventory, hotbar and off-hand.
+    pub fn carried(&self) -> HashMap<BlockKind, u32> {
+        let mut counts = HashMap::new();
+        for stack in self.slots[1..].iter().flatten() {
+            *counts.entry(stack.kind).or_default() += stack.count as u32;
+        }
+        counts
+    }
+
+    /// the slot a picked up item goes into if it does not stack: the first empty one of the hotbar, else of the main
+    /// inventory
+    pub fn free_slot(&self) -> Option<usize> {
+        (HOTBAR..OFFHAND).chain(MAIN..HOTBAR).find(|&idx| self.slots[idx].is_none())
+    }
+
+    /// no empty slot in the main inventory or hotbar
+    pub fn is_full(&self) -> bool {
+        self.slots[MAIN..OFFHAND].iter().all(Option::is_some)
+    }
+
+    /// How many slots of the main inventory and hotbar can take more items: the empty ones and the stacks of kept
+    /// kinds which are not full, as picking up more of those fills them up first.
+    pub fn room(&self) -> usize {
+        self.slots[MAIN..OFFHAND].iter()
+            .filter(|slot| match slot {
+                None => true,
+                Some(stack) => stack.count < STACK_SIZE && self.policy.keep.contains(&stack.kind),
             })
-            .next();
+            .count()
+    }
 
-        if let Some(idx) = block_idx {
-            self.change_slot(idx as u8, out);
-            true
-        } else {
-            false
+    /// fewer slots have room than the policy wants
+    pub fn needs_room(&self) -> bool {
+        self.room() < self.policy.min_free
+    }
+
+    /// Drop the least valuable stack (see [`InventoryPolicy`]) if we [need room](Self::needs_room). One stack of the
+    /// block we pillar and bridge with is always kept. Only one stack is dropped a call as anti cheats do not like
+    /// several at once. Returns the slot which was dropped.
+    pub fn make_room(&mut self, out: &mut impl InterfaceOut) -> Option<usize> {
+        if !self.needs_room() {
+            return None;
         }
+
+        let building = self.stored()
+            .filter(|(_, stack)| stack.kind.throw_away_block())
+            .max_by_key(|(_, stack)| stack.count)
+            .map(|(idx, _)| idx);
+
+        let (idx, _) = self.stored()
+            .filter(|&(idx, _)| Some(idx) != building)
+            .filter_map(|(idx, stack)| Some((idx, (self.policy.toss_rank(stack.kind)?, stack.count))))
+            .min_by_key(|&(_, rank)| rank)?;
+
+        out.inventory_action(InvAction::CtrlQ(idx as u16));
+        self.slots[idx] = None;
+        Some(idx)
     }
 
     pub fn remove(&mut self, idx: usize) {
         self.slots[idx] = None;
+        self.changed = true;
     }
 
     pub fn add(&mut self, idx: usize, stack: ItemStack) {
         self.slots[idx] = Some(stack);
+        self.changed = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use swarm_bot_packets::read::ByteReader;
+    use swarm_bot_packets::types::VarInt;
+
+    use crate::client::state::local::armor::{Armor, ArmorConfig, ArmorSlot};
+    use crate::client::state::local::inventory::{ArmorWarning, FullPolicy, ItemStack, OFFHAND, PlayerInventory};
+    use crate::protocol::Hand;
+    use crate::protocol::v340::Interface340;
+    use crate::storage::block::BlockKind;
+
+    // 1.12 serverbound ids
+    const CLICK_WINDOW: u32 = 0x07;
+    const PLAYER_DIG: u32 = 0x14;
+    const HELD_ITEM_CHANGE: u32 = 0x1a;
+
+    fn received(rx: &mut tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>) -> Vec<u32> {
+        let mut ids = Vec::new();
+        while let Some(Some(packet)) = rx.recv().now_or_never() {
+            let mut reader = ByteReader::new(packet);
+            let _len: VarInt = reader.read();
+            let VarInt(id) = reader.read();
+            ids.push(id as u32);
+        }
+        ids
+    }
+
+    fn stack(kind: BlockKind) -> ItemStack {
+        ItemStack::new(kind, 1, 0, None)
+    }
+
+    fn armor(id: u32, damage: u16) -> ItemStack {
+        ItemStack::new(BlockKind(id), 1, damage, None)
+    }
+
+    #[test]
+    fn test_equip_armor() {
+        let (mut out, mut rx) = Interface340::test();
+        let mut inventory = PlayerInventory::default();
+
+        // iron helmet and chestplate in the inventory, gold chestplate worn, diamond boots in the hotbar
+        inventory.add(10, armor(306, 0));
+        inventory.add(11, armor(307, 0));
+        inventory.add(ArmorSlot::Chest.window_slot(), armor(315, 0));
+        inventory.add(40, armor(313, 0));
+        assert!(inventory.take_changed());
+        assert!(!inventory.take_changed());
+
+        assert_eq!(inventory.equip_armor(&mut out), 3);
+
+        // shift clicks onto the empty head and feet slots, three clicks to swap the chestplates
+        assert_eq!(received(&mut rx), vec![CLICK_WINDOW; 5]);
+        assert_eq!(inventory.worn(ArmorSlot::Head).unwrap().kind, BlockKind(306));
+        assert_eq!(inventory.worn(ArmorSlot::Chest).unwrap().kind, BlockKind(307));
+        assert_eq!(inventory.worn(ArmorSlot::Feet).unwrap().kind, BlockKind(313));
+        assert!(inventory.worn(ArmorSlot::Legs).is_none());
+        assert_eq!(inventory.slots[11].as_ref().unwrap().kind, BlockKind(315));
+
+        // nothing better left. Our own moves do not count as changes.
+        assert_eq!(inventory.equip_armor(&mut out), 0);
+        assert!(received(&mut rx).is_empty());
+        assert!(!inventory.take_changed());
+
+        // an elytra is never swapped for a chestplate
+        inventory.add(ArmorSlot::Chest.window_slot(), armor(Armor::ELYTRA.id(), 0));
+        inventory.add(12, armor(311, 0));
+        assert_eq!(inventory.equip_armor(&mut out), 0);
+        assert!(inventory.elytra());
+
+        // but it cannot be flown with when it is about to break
+        inventory.add(ArmorSlot::Chest.window_slot(), armor(Armor::ELYTRA.id(), 431));
+        assert!(!inventory.elytra());
+    }
+
+    #[test]
+    fn test_armor_breaking() {
+        let (mut out, mut rx) = Interface340::test();
+        let mut inventory = PlayerInventory {
+            armor: ArmorConfig { warn_at: 10, unequip_at: Some(2) },
+            ..PlayerInventory::default()
+        };
+
+        // diamond boots have 429 durability
+        let feet = ArmorSlot::Feet.window_slot();
+        inventory.add(feet, armor(313, 420));
+        let warnings = inventory.check_armor(&mut out);
+        assert_eq!(warnings, vec![ArmorWarning { slot: ArmorSlot::Feet, kind: BlockKind(313), left: 9, unequipped: false }]);
+
+        // only warned once
+        inventory.add(feet, armor(313, 421));
+        assert!(inventory.check_armor(&mut out).is_empty());
+        assert!(received(&mut rx).is_empty());
+
+        // taken off before breaking and not put back on
+        inventory.add(feet, armor(313, 427));
+        let warnings = inventory.check_armor(&mut out);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].unequipped);
+        assert_eq!(received(&mut rx), vec![CLICK_WINDOW]);
+        assert!(inventory.worn(ArmorSlot::Feet).is_none());
+        assert_eq!(inventory.slots[9].as_ref().unwrap().damage, 427);
+
+        assert_eq!(inventory.equip_armor(&mut out), 0);
+    }
+
+    #[test]
+    fn test_equip_from_hotbar() {
+        let (mut out, mut rx) = Interface340::test();
+        let mut inventory = PlayerInventory::default();
+        inventory.add(36, stack(BlockKind::STONE));
+        inventory.add(40, stack(BlockKind::TOTEM));
+        inventory.add(OFFHAND, stack(BlockKind::SHIELD));
+
+        assert!(inventory.equip_offhand(BlockKind::TOTEM, &mut out));
+        assert_eq!(received(&mut rx), vec![HELD_ITEM_CHANGE, PLAYER_DIG, HELD_ITEM_CHANGE]);
+
+        // the totem and shield traded places and we still hold the stone
+        assert_eq!(inventory.offhand().unwrap().kind, BlockKind::TOTEM);
+        assert_eq!(inventory.hotbar()[4].as_ref().unwrap().kind, BlockKind::SHIELD);
+        assert_eq!(inventory.current().unwrap().kind, BlockKind::STONE);
+
+        // nothing to do once equipped
+        assert!(inventory.equip_offhand(BlockKind::TOTEM, &mut out));
+        assert!(received(&mut rx).is_empty());
+    }
+
+    #[test]
+    fn test_equip_from_inventory() {
+        let (mut out, mut rx) = Interface340::test();
+        let mut inventory = PlayerInventory::default();
+        inventory.add(36, stack(BlockKind::STONE));
+        inventory.add(20, stack(BlockKind::TOTEM));
+
+        assert!(inventory.equip_offhand(BlockKind::TOTEM, &mut out));
+        assert_eq!(received(&mut rx), vec![CLICK_WINDOW, PLAYER_DIG, CLICK_WINDOW]);
+        assert_eq!(inventory.offhand().unwrap().kind, BlockKind::TOTEM);
+        assert_eq!(inventory.current().unwrap().kind, BlockKind::STONE);
+        assert!(inventory.slots[20].is_none());
+
+        // the totem pops and there is no other
+        assert_eq!(inventory.hand_holding(BlockKind::TOTEM), Some(Hand::Off));
+        inventory.consume(Hand::Off);
+        assert!(inventory.offhand().is_none());
+        assert!(!inventory.equip_offhand(BlockKind::TOTEM, &mut out));
+        assert!(received(&mut rx).is_empty());
+    }
+
+    #[test]
+    fn test_hold_item() {
+        let (mut out, mut rx) = Interface340::test();
+        let mut inventory = PlayerInventory::default();
+        inventory.add(36, stack(BlockKind::STONE));
+        inventory.add(38, stack(BlockKind::BUCKET));
+        inventory.add(20, stack(BlockKind::TOTEM));
+
+        // already held
+        for _ in 0..5 {
+            assert!(inventory.hold_item(|kind| kind == BlockKind::STONE, &mut out));
+        }
+        assert!(received(&mut rx).is_empty());
+
+        // in the hotbar: switched to once
+        for _ in 0..5 {
+            assert!(inventory.hold_item(|kind| kind == BlockKind::BUCKET, &mut out));
+        }
+        assert_eq!(received(&mut rx), vec![HELD_ITEM_CHANGE]);
+        assert_eq!(inventory.current().unwrap().kind, BlockKind::BUCKET);
+
+        // in the main inventory: swapped with the held bucket once
+        for _ in 0..5 {
+            assert!(inventory.hold_item(|kind| kind == BlockKind::TOTEM, &mut out));
+        }
+        assert_eq!(received(&mut rx), vec![CLICK_WINDOW]);
+        assert_eq!(inventory.current().unwrap().kind, BlockKind::TOTEM);
+        assert_eq!(inventory.slots[20].as_ref().unwrap().kind, BlockKind::BUCKET);
+
+        assert!(!inventory.hold_item(|kind| kind == BlockKind::SHIELD, &mut out));
+        assert!(received(&mut rx).is_empty());
+
+        // the server switched us back to the first slot
+        inventory.on_held_slot(0);
+        assert_eq!(inventory.current().unwrap().kind, BlockKind::STONE);
+        inventory.select_slot(0, &mut out);
+        assert!(received(&mut rx).is_empty());
+    }
+
+    const DIAMOND: BlockKind = BlockKind(264);
+
+    /// full stacks of diamonds in every slot but the ones of `stacks` (id and count, nothing if the count is 0)
+    fn filled(stacks: &[(usize, u32, u8)], min_free: usize) -> PlayerInventory {
+        let mut inventory = PlayerInventory::default();
+        inventory.policy.min_free = min_free;
+        for idx in 9..45 {
+            inventory.add(idx, ItemStack::new(DIAMOND, 64, 0, None));
+        }
+        for &(idx, id, count) in stacks {
+            inventory.slots[idx] = (count > 0).then(|| ItemStack::new(BlockKind(id), count, 0, None));
+        }
+        inventory
+    }
+
+    #[test]
+    fn test_make_room() {
+        // the stacks, how many slots should have room and the slots dropped in order
+        let cases: &[(&[(usize, u32, u8)], usize, &[usize])] = &[
+            // only valuables
+            (&[], 2, &[]),
+
+            // dirt and gravel are on the toss list before cobblestone, and one stack of cobblestone is kept
+            (&[(10, 3, 64), (20, 4, 64), (30, 4, 10), (40, 13, 5)], 2, &[10, 40]),
+            (&[(20, 4, 64), (30, 4, 10)], 3, &[30]),
+
+            // stacks of kept kinds which are not full have room
+            (&[(9, 264, 30), (11, 15, 10), (12, 3, 64)], 2, &[]),
+            (&[(9, 264, 30), (12, 3, 64)], 2, &[12]),
+
+            // blocks which are not on either list go after the toss list, smaller stacks first. Tools never go.
+            (&[(9, 270, 1), (10, 5, 64), (11, 20, 3), (12, 13, 64)], 3, &[12, 11, 10]),
+
+            // there is already room
+            (&[(10, 3, 64), (11, 0, 0), (12, 0, 0)], 2, &[]),
+            (&[(10, 3, 64)], 0, &[]),
+        ];
+
+        for &(stacks, min_free, expected) in cases {
+            let (mut out, mut rx) = Interface340::test();
+            let mut inventory = filled(stacks, min_free);
+
+            let mut dropped = Vec::new();
+            while let Some(idx) = inventory.make_room(&mut out) {
+                dropped.push(idx);
+            }
+
+            assert_eq!(dropped, expected, "{:?}", stacks);
+            assert_eq!(received(&mut rx), vec![CLICK_WINDOW; expected.len()]);
+
+            // valuables are never dropped
+            let before = filled(stacks, min_free);
+            for idx in dropped {
+                let kind = before.slots[idx].as_ref().unwrap().kind;
+                assert!(!before.policy.keep.contains(&kind));
+            }
+        }
+    }
+
+    #[test]
+    fn test_full_policy() {
+        assert_eq!("toss".parse::<FullPolicy>().unwrap(), FullPolicy::Toss);
+        assert_eq!("deposit chest".parse::<FullPolicy>().unwrap(), FullPolicy::Deposit("chest".to_string()));
+        assert!("deposit".parse::<FullPolicy>().is_err());
     }
 }
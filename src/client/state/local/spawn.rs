@@ -0,0 +1,303 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Where the bot reappears after dying and setting that with a bed
+
+use std::fmt::{Display, Formatter};
+use std::mem;
+
+use crate::chat::{ChatEvent, ChatKind};
+use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+use crate::types::Location;
+
+/// how far (in blocks) from its bed a player can reappear. Vanilla looks for room around the bed.
+const BED_SLACK: f64 = 3.0;
+
+/// the answers to using a bed we keep
+const MAX_RESPONSES: usize = 4;
+
+/// The translation keys (1.12 and 1.16) and English texts (for plugins which translate themselves) of what the server
+/// says after using a bed.
+const RESPONSES: &[(&str, BedResponse)] = &[
+    ("tile.bed.noSleep", BedResponse::Failed(BedError::NotNight)),
+    ("block.minecraft.bed.no_sleep", BedResponse::Failed(BedError::NotNight)),
+    ("You can only sleep at night", BedResponse::Failed(BedError::NotNight)),
+    ("You can sleep only at night", BedResponse::Failed(BedError::NotNight)),
+    ("tile.bed.notSafe", BedResponse::Failed(BedError::NotSafe)),
+    ("block.minecraft.bed.not_safe", BedResponse::Failed(BedError::NotSafe)),
+    ("there are monsters nearby", BedResponse::Failed(BedError::NotSafe)),
+    ("tile.bed.tooFarAway", BedResponse::Failed(BedError::TooFar)),
+    ("block.minecraft.bed.too_far_away", BedResponse::Failed(BedError::TooFar)),
+    ("the bed is too far away", BedResponse::Failed(BedError::TooFar)),
+    ("tile.bed.occupied", BedResponse::Failed(BedError::Occupied)),
+    ("block.minecraft.bed.occupied", BedResponse::Failed(BedError::Occupied)),
+    ("This bed is occupied", BedResponse::Failed(BedError::Occupied)),
+    ("block.minecraft.bed.obstructed", BedResponse::Failed(BedError::Obstructed)),
+    ("This bed is obstructed", BedResponse::Failed(BedError::Obstructed)),
+    ("block.minecraft.set_spawn", BedResponse::SpawnSet),
+    ("Respawn point set", BedResponse::SpawnSet),
+];
+
+/// why using a bed did not set the spawn point
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BedError {
+    /// 1.12 only sets the spawn point when sleeping, which is only possible at night
+    NotNight,
+    NotSafe,
+    TooFar,
+    Occupied,
+    Obstructed,
+    NotABed,
+    NoAnswer,
+}
+
+impl Display for BedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            BedError::NotNight => "it is not night",
+            BedError::NotSafe => "monsters are nearby",
+            BedError::TooFar => "it is too far away",
+            BedError::Occupied => "someone is in it",
+            BedError::Obstructed => "it is obstructed",
+            BedError::NotABed => "there is no bed",
+            BedError::NoAnswer => "the server did not answer",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// what the server answered to us using a bed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BedResponse {
+    /// we lay down. 1.12 sets the spawn point once we get up again.
+    Slept,
+
+    /// 1.16 says so when the spawn point changes, even during the day
+    SpawnSet,
+    Failed(BedError),
+}
+
+impl BedResponse {
+    /// the answer to using a bed `event` is, if it is one
+    pub fn parse(event: &ChatEvent) -> Option<BedResponse> {
+        if event.kind != ChatKind::System {
+            return None;
+        }
+
+        let key = event.component.translate.as_deref();
+        RESPONSES.iter()
+            .find(|&&(matches, _)| key == Some(matches) || event.text.contains(matches))
+            .map(|&(_, response)| response)
+    }
+}
+
+/// The head of the bed one half of which is `state` at `location`. Vanilla keeps the head as the spawn point, but
+/// either half can be clicked. None if `state` is not a bed.
+pub fn bed_head(location: BlockLocation, state: BlockState) -> Option<BlockLocation> {
+    let (head, other) = bed_halves(location, state)?;
+    Some(if head { location } else { other })
+}
+
+/// the other half of the bed one half of which is `state` at `location`
+pub fn bed_other_half(location: BlockLocation, state: BlockState) -> Option<BlockLocation> {
+    bed_halves(location, state).map(|(_, other)| other)
+}
+
+/// whether `location` is the head and where the other half is
+fn bed_halves(location: BlockLocation, state: BlockState) -> Option<(bool, BlockLocation)> {
+    if state.kind() != BlockKind::BED {
+        return None;
+    }
+
+    // the lowest two bits are where the head is from the foot: south, west, north, east
+    let meta = state.metadata();
+    let (dx, dz) = match meta & 0b11 {
+        0 => (0, 1),
+        1 => (-1, 0),
+        2 => (0, -1),
+        _ => (1, 0),
+    };
+
+    let head = meta & 0b1000 != 0;
+    let sign = if head { -1 } else { 1 };
+    Some((head, BlockLocation::new(location.x + sign * dx, location.y, location.z + sign * dz)))
+}
+
+/// where the bot believes it reappears after dying
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SpawnPoint {
+    /// the spawn of the world. Players reappear somewhere around it.
+    World(BlockLocation),
+
+    /// the head of the bed we last set the spawn point with
+    Bed(BlockLocation),
+
+    /// where we reappeared last time. Servers with a `/spawn` put players there instead.
+    Observed(BlockLocation),
+}
+
+impl SpawnPoint {
+    pub fn location(self) -> BlockLocation {
+        match self {
+            SpawnPoint::World(location) | SpawnPoint::Bed(location) | SpawnPoint::Observed(location) => location
+        }
+    }
+}
+
+impl Display for SpawnPoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpawnPoint::World(location) => write!(f, "the world spawn at {}", location),
+            SpawnPoint::Bed(location) => write!(f, "the bed at {}", location),
+            SpawnPoint::Observed(location) => write!(f, "{}", location),
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Spawn {
+    /// None until the server tells us the spawn of the world
+    point: Option<SpawnPoint>,
+
+    /// we died and the server has not put us anywhere since
+    respawning: bool,
+
+    /// the latest answers to using a bed with the tick they came in, oldest first
+    responses: Vec<(usize, BedResponse)>,
+}
+
+impl Spawn {
+    pub fn point(&self) -> Option<SpawnPoint> {
+        self.point
+    }
+
+    /// The server told us the spawn of the world. A bed or where we reappeared are better guesses so they stay.
+    pub fn on_world_spawn(&mut self, location: BlockLocation) {
+        if matches!(self.point, None | Some(SpawnPoint::World(_))) {
+            self.point = Some(SpawnPoint::World(location));
+        }
+    }
+
+    pub fn set_bed(&mut self, head: BlockLocation) {
+        info!(bed = %head, "set the spawn point");
+        self.point = Some(SpawnPoint::Bed(head));
+    }
+
+    pub fn on_death(&mut self) {
+        self.respawning = true;
+    }
+
+    /// The server put us at `location`. The first time after dying that is where we spawn. If it is not by our bed the
+    /// bed is gone or the server has a spawn of its own.
+    pub fn on_move(&mut self, location: Location) {
+        if !mem::take(&mut self.respawning) {
+            return;
+        }
+
+        let at = BlockLocation::from(location);
+        match self.point {
+            Some(SpawnPoint::Bed(bed)) if bed.dist(at) <= BED_SLACK => {}
+            before => {
+                debug!(?before, now = %at, "reappeared somewhere else than expected");
+                self.point = Some(SpawnPoint::Observed(at));
+            }
+        }
+    }
+
+    pub fn on_response(&mut self, tick: usize, response: BedResponse) {
+        debug!(?response, "the server answered to using a bed");
+        if self.responses.len() == MAX_RESPONSES {
+            self.responses.remove(0);
+        }
+        self.responses.push((tick, response));
+    }
+
+    /// the answers which came in at or after `tick`, oldest first
+    pub fn responses_since(&self, tick: usize) -> impl Iterator<Item=BedResponse> + '_ {
+        self.responses.iter()
+            .filter(move |&&(at, _)| at >= tick)
+            .map(|&(_, response)| response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::{ChatEvent, Component};
+    use crate::client::state::local::spawn::{bed_head, bed_other_half, BedError, BedResponse, Spawn, SpawnPoint};
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::types::Location;
+
+    #[test]
+    fn test_parse() {
+        let parse = |json: &str| BedResponse::parse(&ChatEvent::from(Component::parse(json)));
+
+        assert_eq!(parse(r#"{"translate":"tile.bed.noSleep"}"#), Some(BedResponse::Failed(BedError::NotNight)));
+        assert_eq!(parse(r#"{"translate":"block.minecraft.bed.not_safe"}"#), Some(BedResponse::Failed(BedError::NotSafe)));
+        assert_eq!(parse(r#"{"translate":"block.minecraft.set_spawn"}"#), Some(BedResponse::SpawnSet));
+        assert_eq!(parse(r#"{"text":"§cThis bed is occupied"}"#), Some(BedResponse::Failed(BedError::Occupied)));
+        assert_eq!(parse(r#"{"text":"welcome back"}"#), None);
+
+        // players cannot fake it
+        assert_eq!(parse(r#"{"text":"<Notch> Respawn point set"}"#), None);
+    }
+
+    #[test]
+    fn test_halves() {
+        let foot = BlockLocation::new(10, 64, 10);
+        let head = BlockLocation::new(10, 64, 11);
+
+        // facing south
+        let foot_state = BlockState::from(BlockKind::BED.id(), 0);
+        let head_state = BlockState::from(BlockKind::BED.id(), 0b1000);
+
+        assert_eq!(bed_head(foot, foot_state), Some(head));
+        assert_eq!(bed_head(head, head_state), Some(head));
+        assert_eq!(bed_other_half(foot, foot_state), Some(head));
+        assert_eq!(bed_other_half(head, head_state), Some(foot));
+
+        // facing east
+        let foot_state = BlockState::from(BlockKind::BED.id(), 3);
+        assert_eq!(bed_head(foot, foot_state), Some(BlockLocation::new(11, 64, 10)));
+
+        assert_eq!(bed_head(foot, BlockState::STONE), None);
+    }
+
+    #[test]
+    fn test_respawn() {
+        let bed = BlockLocation::new(100, 64, 100);
+        let mut spawn = Spawn::default();
+        spawn.on_world_spawn(BlockLocation::new(0, 64, 0));
+        spawn.set_bed(bed);
+
+        // the world spawn does not replace the bed
+        spawn.on_world_spawn(BlockLocation::new(5, 64, 5));
+        assert_eq!(spawn.point(), Some(SpawnPoint::Bed(bed)));
+
+        // only the first move after dying counts
+        spawn.on_move(Location::new(0.5, 64.0, 0.5));
+        assert_eq!(spawn.point(), Some(SpawnPoint::Bed(bed)));
+
+        spawn.on_death();
+        spawn.on_move(Location::new(101.5, 64.0, 100.5));
+        assert_eq!(spawn.point(), Some(SpawnPoint::Bed(bed)));
+
+        // a plugin sent us to its spawn
+        spawn.on_death();
+        spawn.on_move(Location::new(-300.5, 70.0, 20.5));
+        spawn.on_move(Location::new(10.5, 64.0, 10.5));
+        assert_eq!(spawn.point(), Some(SpawnPoint::Observed(BlockLocation::new(-301, 70, 20))));
+    }
+}
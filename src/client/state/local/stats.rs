@@ -0,0 +1,80 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+use crate::client::metrics::METRICS;
+use crate::storage::block::BlockKind;
+
+/// What a bot has done since the stats were last taken. Aggregated into a [`crate::client::digest::Digest`].
+#[derive(Default, Clone, Debug)]
+pub struct BotStats {
+    pub ticks: u64,
+    pub mined: HashMap<BlockKind, u64>,
+    pub placed: HashMap<BlockKind, u64>,
+
+    /// blocks traveled keyed by the name of the task that was running ("idle" if there was none)
+    pub distance: HashMap<&'static str, f64>,
+
+    /// the cause of each death ("unknown" if the server did not tell us)
+    pub deaths: Vec<String>,
+
+    /// the raw reason of each disconnect
+    pub disconnects: Vec<String>,
+}
+
+impl BotStats {
+    pub fn mined(&mut self, kind: BlockKind) {
+        METRICS.blocks_mined.inc();
+        *self.mined.entry(kind).or_default() += 1;
+    }
+
+    pub fn placed(&mut self, kind: BlockKind) {
+        *self.placed.entry(kind).or_default() += 1;
+    }
+
+    pub fn traveled(&mut self, mode: &'static str, distance: f64) {
+        *self.distance.entry(mode).or_default() += distance;
+    }
+
+    pub fn died(&mut self, cause: Option<String>) {
+        self.deaths.push(cause.unwrap_or_else(|| "unknown".to_string()));
+    }
+
+    pub fn disconnected(&mut self, reason: &str) {
+        METRICS.disconnects.inc();
+        self.disconnects.push(reason.to_string());
+    }
+
+    pub fn merge(&mut self, other: BotStats) {
+        self.ticks += other.ticks;
+
+        for (kind, count) in other.mined {
+            *self.mined.entry(kind).or_default() += count;
+        }
+
+        for (kind, count) in other.placed {
+            *self.placed.entry(kind).or_default() += count;
+        }
+
+        for (mode, distance) in other.distance {
+            self.traveled(mode, distance);
+        }
+
+        self.deaths.extend(other.deaths);
+        self.disconnects.extend(other.disconnects);
+    }
+}
@@ -0,0 +1,430 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Changing how many bots are online while the swarm is running. Accounts which are not in use wait in a reserve.
+//! `#swarm add <n>` logs in accounts from the reserve and `#swarm remove <n>` (or `#swarm remove @<name>...`) winds
+//! bots down and puts their accounts back.
+
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::num::ParseIntError;
+use std::rc::Rc;
+
+/// how long after taking damage a bot is in combat. Servers with combat tagging punish logging out in this window.
+pub const COMBAT_TAG_TICKS: usize = 20 * 15;
+
+/// bots still in combat after winding down this long are disconnected anyway
+pub const MAX_WIND_DOWN_TICKS: usize = 20 * 60;
+
+/// the same request this many ticks apart is one request read by several bots
+const DUPLICATE_TICKS: usize = 20 * 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Scale {
+    /// log in this many more bots
+    Add(usize),
+
+    /// wind down this many bots
+    Remove(usize),
+
+    /// wind down the bots with these usernames
+    RemoveNamed(Vec<String>),
+
+    /// only report the counts
+    Status,
+}
+
+impl Scale {
+    /// parse `add <n>`, `remove <n>`, `remove @<name>...` or `status`. None if the arguments are none of these.
+    pub fn parse(args: &[&str]) -> Result<Option<Scale>, ParseIntError> {
+        let scale = match args {
+            ["add", n] => Scale::Add(n.parse()?),
+            ["remove", names @ ..] if !names.is_empty() && names.iter().all(|name| name.starts_with('@')) => {
+                Scale::RemoveNamed(names.iter().map(|name| name[1..].to_string()).collect())
+            }
+            ["remove", n] => Scale::Remove(n.parse()?),
+            ["status"] | [] => Scale::Status,
+            _ => return Ok(None)
+        };
+        Ok(Some(scale))
+    }
+}
+
+/// Requests to scale the swarm which have not been handled yet. A request in chat is read by every bot, so identical
+/// requests from the same sender shortly after each other are only queued once.
+#[derive(Debug, Default)]
+pub struct ScaleRequests {
+    pending: Vec<Scale>,
+
+    /// (sender, request, tick) of recent requests
+    recent: VecDeque<(Option<String>, Scale, usize)>,
+}
+
+impl ScaleRequests {
+    pub fn push(&mut self, sender: Option<&str>, scale: Scale, tick: usize) {
+        while matches!(self.recent.front(), Some((_, _, at)) if at + DUPLICATE_TICKS < tick) {
+            self.recent.pop_front();
+        }
+
+        let sender = sender.map(str::to_string);
+        if self.recent.iter().any(|(from, request, _)| from == &sender && request == &scale) {
+            return;
+        }
+
+        self.recent.push_back((sender, scale.clone(), tick));
+        self.pending.push(scale);
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item=Scale> + '_ {
+        self.pending.drain(..)
+    }
+}
+
+/// The counts reported whenever the swarm changes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SwarmStatus {
+    /// bots online which are not winding down
+    pub online: usize,
+    pub leaving: usize,
+    pub logging_in: usize,
+    pub target: usize,
+    pub reserve: usize,
+}
+
+impl Display for SwarmStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{} bots online, {} logging in, {} leaving, {} in reserve", self.online, self.target, self.logging_in, self.leaving, self.reserve)
+    }
+}
+
+/// Keeps the number of bots at a target. `A` is the account a bot logs in with.
+pub struct Swarm<A> {
+    /// accounts which are not in use
+    reserve: VecDeque<A>,
+
+    /// how many bots should be online
+    target: usize,
+
+    /// logins which have started but not finished, including reconnects waiting for their delay. Decremented by the
+    /// login task when it finishes.
+    logging_in: Rc<Cell<usize>>,
+
+    /// username -> the tick (of the bot) it started winding down
+    leaving: HashMap<String, usize>,
+}
+
+impl<A> Swarm<A> {
+    pub fn new(target: usize) -> Swarm<A> {
+        Swarm {
+            reserve: VecDeque::new(),
+            target,
+            logging_in: Rc::new(Cell::new(0)),
+            leaving: HashMap::new(),
+        }
+    }
+
+    /// add an account which is not in use to the reserve
+    pub fn offer(&mut self, account: A) {
+        self.reserve.push_back(account);
+    }
+
+    /// the counter of logins in progress. Whoever finishes a login (successful or not) decrements it.
+    pub fn logging_in(&self) -> Rc<Cell<usize>> {
+        self.logging_in.clone()
+    }
+
+    /// a login (or reconnect) which does not come from [`Swarm::logins`] started
+    pub fn login_started(&self) {
+        self.logging_in.set(self.logging_in.get() + 1);
+    }
+
+    pub fn is_leaving(&self, username: &str) -> bool {
+        self.leaving.contains_key(username)
+    }
+
+    /// Change the target. Returns the bots of `online` which start winding down right away. Bots over the target are
+    /// wound down by [`Swarm::wind_down`].
+    pub fn apply(&mut self, scale: Scale, online: &[(&str, usize)]) -> Vec<String> {
+        match scale {
+            Scale::Add(count) => {
+                self.target += count;
+                Vec::new()
+            }
+            Scale::Remove(count) => {
+                self.target = self.target.saturating_sub(count);
+                Vec::new()
+            }
+            Scale::RemoveNamed(names) => {
+                let mut removed = Vec::new();
+                for &(username, tick) in online {
+                    if names.iter().any(|name| name == username) && !self.is_leaving(username) {
+                        self.leaving.insert(username.to_string(), tick);
+                        self.target = self.target.saturating_sub(1);
+                        removed.push(username.to_string());
+                    }
+                }
+                removed
+            }
+            Scale::Status => Vec::new()
+        }
+    }
+
+    /// Start winding down the newest bots of `online` (username, tick of the bot) until the target is met
+    pub fn wind_down(&mut self, online: &[(&str, usize)]) -> Vec<String> {
+        let staying = online.iter().filter(|(username, _)| !self.is_leaving(username)).count();
+        let excess = staying.saturating_sub(self.target);
+
+        let leaving: Vec<_> = online.iter().rev()
+            .filter(|(username, _)| !self.is_leaving(username))
+            .take(excess)
+            .map(|&(username, tick)| (username.to_string(), tick))
+            .collect();
+
+        let names = leaving.iter().map(|(username, _)| username.clone()).collect();
+        self.leaving.extend(leaving);
+        names
+    }
+
+    /// Whether a winding down bot can disconnect. It waits until it is out of combat unless it has waited for too long.
+    pub fn can_leave(&self, username: &str, tick: usize, last_hurt: Option<usize>) -> bool {
+        let since = match self.leaving.get(username) {
+            Some(&since) => since,
+            None => return false
+        };
+
+        let in_combat = last_hurt.map_or(false, |hurt| tick < hurt + COMBAT_TAG_TICKS);
+        !in_combat || tick >= since + MAX_WIND_DOWN_TICKS
+    }
+
+    /// A winding down bot disconnected. Its account goes back to the reserve.
+    pub fn left(&mut self, username: &str, account: A) {
+        self.leaving.remove(username);
+        self.reserve.push_back(account);
+    }
+
+    /// The accounts to log in so `staying` (online bots which are not winding down) plus logins in progress meet the
+    /// target. Each counts as a login in progress.
+    pub fn logins(&mut self, staying: usize) -> Vec<A> {
+        let missing = self.target.saturating_sub(staying + self.logging_in.get());
+        let count = missing.min(self.reserve.len());
+        self.logging_in.set(self.logging_in.get() + count);
+        self.reserve.drain(..count).collect()
+    }
+
+    pub fn status(&self, online: usize) -> SwarmStatus {
+        SwarmStatus {
+            online: online - self.leaving.len().min(online),
+            leaving: self.leaving.len(),
+            logging_in: self.logging_in.get(),
+            target: self.target,
+            reserve: self.reserve.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::global::mine_alloc::MineAlloc;
+    use crate::client::swarm::{COMBAT_TAG_TICKS, MAX_WIND_DOWN_TICKS, Scale, ScaleRequests, Swarm};
+    use crate::storage::block::BlockLocation2D;
+
+    struct MockBot {
+        name: String,
+        id: u32,
+        ticks: usize,
+        last_hurt: Option<usize>,
+        region: Option<BlockLocation2D>,
+        mining_for: usize,
+    }
+
+    /// ticks it takes a mock bot to mine a region
+    const MINE_TICKS: usize = 3;
+
+    struct Sim {
+        swarm: Swarm<String>,
+        alloc: MineAlloc,
+        bots: Vec<MockBot>,
+        mined: Vec<BlockLocation2D>,
+        id_on: u32,
+    }
+
+    impl Sim {
+        fn online(&self) -> Vec<(&str, usize)> {
+            self.bots.iter().map(|bot| (bot.name.as_str(), bot.ticks)).collect()
+        }
+
+        fn staying(&self) -> usize {
+            self.bots.iter().filter(|bot| !self.swarm.is_leaving(&bot.name)).count()
+        }
+
+        fn start_leaving(&mut self, names: Vec<String>) {
+            for bot in self.bots.iter_mut().filter(|bot| names.contains(&bot.name)) {
+                self.alloc.release(bot.id);
+                bot.region = None;
+            }
+        }
+
+        fn scale(&mut self, scale: Scale) {
+            let online: Vec<_> = self.bots.iter().map(|bot| (bot.name.clone(), bot.ticks)).collect();
+            let online: Vec<_> = online.iter().map(|(name, ticks)| (name.as_str(), *ticks)).collect();
+            let leaving = self.swarm.apply(scale, &online);
+            self.start_leaving(leaving);
+        }
+
+        fn tick(&mut self) {
+            // logins finish right away
+            for name in self.swarm.logins(self.staying()) {
+                let counter = self.swarm.logging_in();
+                counter.set(counter.get() - 1);
+                self.bots.push(MockBot { name, id: self.id_on, ticks: 0, last_hurt: None, region: None, mining_for: 0 });
+                self.id_on += 1;
+            }
+
+            let online: Vec<_> = self.online().into_iter().map(|(name, ticks)| (name.to_string(), ticks)).collect();
+            let online: Vec<_> = online.iter().map(|(name, ticks)| (name.as_str(), *ticks)).collect();
+            let leaving = self.swarm.wind_down(&online);
+            self.start_leaving(leaving);
+
+            let swarm = &mut self.swarm;
+            let mut left = Vec::new();
+            self.bots.retain(|bot| {
+                let leave = swarm.can_leave(&bot.name, bot.ticks, bot.last_hurt);
+                if leave {
+                    left.push(bot.name.clone());
+                }
+                !leave
+            });
+            for name in left {
+                self.swarm.left(&name.clone(), name);
+            }
+
+            for bot in &mut self.bots {
+                bot.ticks += 1;
+                if self.swarm.is_leaving(&bot.name) {
+                    continue;
+                }
+
+                bot.mining_for += 1;
+                if bot.region.is_none() || bot.mining_for >= MINE_TICKS {
+                    self.mined.extend(bot.region.take());
+                    bot.region = self.alloc.obtain_region(bot.id);
+                    bot.mining_for = 0;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_scale_while_mining() {
+        let mut swarm = Swarm::new(5);
+        for i in 0..12 {
+            swarm.offer(format!("bot{}", i));
+        }
+
+        let mut alloc = MineAlloc::default();
+        alloc.mine(BlockLocation2D::new(0, 0), BlockLocation2D::new(69, 69), None);
+        let mut regions: Vec<_> = alloc.regions().collect();
+        assert_eq!(regions.len(), 100);
+
+        let mut sim = Sim { swarm, alloc, bots: Vec::new(), mined: Vec::new(), id_on: 0 };
+
+        sim.tick();
+        assert_eq!(sim.bots.len(), 5);
+        assert_eq!(sim.alloc.claimed(), 5);
+
+        sim.tick();
+        sim.scale(Scale::Add(5));
+        sim.tick();
+        assert_eq!(sim.swarm.status(sim.bots.len()).online, 10);
+        assert_eq!(sim.swarm.status(sim.bots.len()).reserve, 2);
+
+        sim.tick();
+
+        // one of the newest bots was hit, so it has to stay until it is out of combat
+        let hurt = sim.bots.last_mut().unwrap();
+        hurt.last_hurt = Some(hurt.ticks);
+        let hurt = hurt.name.clone();
+
+        sim.scale(Scale::Remove(7));
+        sim.tick();
+        let status = sim.swarm.status(sim.bots.len());
+        assert_eq!(status.online, 3);
+        assert_eq!(status.leaving, 1);
+        assert_eq!(status.reserve, 8);
+        assert!(sim.swarm.is_leaving(&hurt));
+
+        // only the bots staying hold a region
+        assert_eq!(sim.alloc.claimed(), 3);
+
+        for _ in 0..COMBAT_TAG_TICKS {
+            sim.tick();
+        }
+        assert_eq!(sim.bots.len(), 3);
+        assert_eq!(sim.swarm.status(3).reserve, 9);
+
+        // the rest of the regions are mined without losing or repeating one
+        while sim.alloc.regions().next().is_some() || sim.alloc.claimed() > 0 {
+            sim.tick();
+        }
+
+        sim.mined.sort_by_key(|loc| (loc.x, loc.z));
+        regions.sort_by_key(|loc| (loc.x, loc.z));
+        assert_eq!(sim.mined, regions);
+    }
+
+    #[test]
+    fn test_remove_named() {
+        let mut swarm = Swarm::new(3);
+        let online = [("a", 10), ("b", 10), ("c", 10)];
+
+        let leaving = swarm.apply(Scale::RemoveNamed(vec!["b".to_string(), "x".to_string()]), &online);
+        assert_eq!(leaving, vec!["b".to_string()]);
+        assert_eq!(swarm.status(3).target, 2);
+
+        // the target is met, so nobody else leaves
+        assert!(swarm.wind_down(&online).is_empty());
+
+        // in combat for too long
+        assert!(!swarm.can_leave("b", 20, Some(15)));
+        assert!(swarm.can_leave("b", 10 + MAX_WIND_DOWN_TICKS, Some(10 + MAX_WIND_DOWN_TICKS)));
+        assert!(!swarm.can_leave("a", 20, None));
+
+        swarm.left("b", "b".to_string());
+        assert_eq!(swarm.status(2), crate::client::swarm::SwarmStatus { online: 2, leaving: 0, logging_in: 0, target: 2, reserve: 1 });
+    }
+
+    #[test]
+    fn test_parse_and_dedup() {
+        assert_eq!(Scale::parse(&["add", "5"]), Ok(Some(Scale::Add(5))));
+        assert_eq!(Scale::parse(&["remove", "@a", "@b"]), Ok(Some(Scale::RemoveNamed(vec!["a".to_string(), "b".to_string()]))));
+        assert_eq!(Scale::parse(&["remove", "2"]), Ok(Some(Scale::Remove(2))));
+        assert_eq!(Scale::parse(&["other"]), Ok(None));
+        assert!(Scale::parse(&["add", "many"]).is_err());
+
+        let mut requests = ScaleRequests::default();
+
+        // every bot reads the same message
+        for tick in 0..3 {
+            requests.push(Some("player"), Scale::Add(2), tick);
+        }
+        requests.push(Some("other"), Scale::Add(2), 3);
+        assert_eq!(requests.drain().count(), 2);
+
+        requests.push(Some("player"), Scale::Add(2), 100);
+        assert_eq!(requests.drain().collect::<Vec<_>>(), vec![Scale::Add(2)]);
+    }
+}
@@ -14,6 +14,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::client::estimate::{Plan, TaskEstimate, WALK_PER_TICK};
 use crate::client::pathfind::moves::CardinalDirection;
 use crate::client::physics::Line;
 use crate::client::physics::speed::Speed;
@@ -21,7 +22,7 @@ use crate::client::state::global::GlobalState;
 use crate::client::state::local::LocalState;
 use crate::client::tasks::TaskTrait;
 use crate::protocol::{Face, InterfaceOut};
-use crate::storage::block::BlockLocation;
+use crate::storage::block::{BlockKind, BlockLocation};
 use crate::types::{Direction, Displacement};
 
 pub struct BridgeTask {
@@ -37,6 +38,18 @@ impl BridgeTask {
     }
 }
 
+impl Plan for BridgeTask {
+    fn plan(&self, local: &LocalState, _global: &GlobalState) -> TaskEstimate {
+        let count = self.count as usize;
+        let ticks = (count as f64 / WALK_PER_TICK).round() as usize;
+
+        let mut estimate = TaskEstimate::default();
+        estimate.place(BlockKind::COBBLESTONE, count, ticks, local);
+        estimate.distance += count as f64;
+        estimate
+    }
+}
+
 impl TaskTrait for BridgeTask {
     fn tick(&mut self, _out: &mut impl InterfaceOut, local: &mut LocalState, _global: &mut GlobalState) -> bool {
         let displacement = Displacement::from(self.direction.unit_change());
@@ -76,3 +89,34 @@ impl TaskTrait for BridgeTask {
         self.count == 0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::pathfind::moves::CardinalDirection;
+    use crate::client::sim::SimulatedClient;
+    use crate::client::tasks::bridge::BridgeTask;
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+
+    #[test]
+    fn test_bridge() {
+        // standing on a block one above the floor so falling off would show
+        let mut world = WorldBlocks::flat();
+        world.set_block(BlockLocation::new(0, 1, 0), BlockState::STONE);
+
+        let mut sim = SimulatedClient::new(world, BlockLocation::new(0, 2, 0).center_bottom());
+        sim.give(0, BlockKind::COBBLESTONE, 64);
+
+        let task = BridgeTask::new(5, CardinalDirection::North, sim.local());
+        sim.schedule(task);
+        sim.run_task(200);
+        sim.run_ticks(20);
+
+        for x in 1..=5 {
+            assert_eq!(sim.global.blocks.get_block_kind(BlockLocation::new(x, 1, 0)), Some(BlockKind::COBBLESTONE));
+        }
+
+        assert!(sim.positions.iter().all(|location| location.y > 1.99));
+        assert_eq!(sim.local().inventory.current().map(|held| held.count), Some(59));
+    }
+}
@@ -0,0 +1,477 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Melee combat against one entity, the way a player fights in 1.12: face the target, close in, attack when the
+//! attack cooldown has recharged and time attacks on the falling part of a jump so they are criticals.
+
+use std::time::Instant;
+
+use float_ord::FloatOrd;
+use rand::Rng;
+
+use crate::client::pathfind::implementations::novehicle::TravelProblem;
+use crate::client::physics::{Line, Strafe};
+use crate::client::physics::speed::Speed;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::navigate::{BlockTravelNearTask, NavigateProblem};
+use crate::client::tasks::TaskTrait;
+use crate::protocol::InterfaceOut;
+use crate::storage::block::{BlockLocation, BlockLocation2D};
+use crate::storage::entities::EntityKind;
+use crate::types::{Direction, Displacement, Location};
+
+/// further than this we path find to the target instead of walking straight at it
+const CHASE_DIST: f64 = 6.0;
+
+/// the path is recalculated if the target moved this far from where it was going to
+const REPATH_DIST: f64 = 3.0;
+
+/// start jumping this many ticks before the cooldown is over so we are falling when it is
+const JUMP_LEAD: u32 = 5;
+
+/// how long we wait for a critical after the cooldown is over before attacking anyway
+const CRIT_PATIENCE: u32 = 10;
+
+/// where we aim at relative to the feet of the target. About the middle of a zombie or player.
+const AIM: Displacement = Displacement::new(0.0, 1.0, 0.0);
+
+/// Who to fight
+#[derive(Clone, Debug)]
+pub enum Target {
+    Entity(u32),
+
+    /// the closest player that is not in the list. Chosen once and then kept.
+    NearestPlayer { whitelist: Vec<String> },
+}
+
+#[derive(Clone, Debug)]
+pub struct CombatConfig {
+    /// ticks between attacks. If None, the time the held weapon needs to fully recharge.
+    pub cooldown: Option<u32>,
+
+    /// jump so attacks land while falling
+    pub crits: bool,
+
+    /// strafe left and right while in melee range so we are harder to hit
+    pub strafe: bool,
+
+    /// the furthest (feet to feet) we attack from. The server rejects attacks further than 3 blocks if it does not
+    /// think we can see the target.
+    pub reach: f64,
+
+    /// the task ends if the target gets further away than this
+    pub give_up: f64,
+
+    /// switch to the best weapon of the hotbar first
+    pub switch_weapon: bool,
+}
+
+impl Default for CombatConfig {
+    fn default() -> Self {
+        Self {
+            cooldown: None,
+            crits: true,
+            strafe: true,
+            reach: 3.0,
+            give_up: 48.0,
+            switch_weapon: true,
+        }
+    }
+}
+
+/// damage and attacks per second of the item (1.12)
+pub fn weapon_stats(id: u32) -> (f64, f64) {
+    match id {
+        268 | 283 => (4.0, 1.6), // wooden and golden sword
+        272 => (5.0, 1.6),
+        267 => (6.0, 1.6),
+        276 => (7.0, 1.6),
+        271 | 286 => (7.0, 0.8), // wooden and golden axe
+        275 => (9.0, 0.8),
+        258 => (9.0, 0.9),
+        279 => (9.0, 1.0),
+        _ => (1.0, 4.0)
+    }
+}
+
+/// ticks until an attack with the item does full damage again
+pub fn cooldown_ticks(id: u32) -> u32 {
+    let (_, speed) = weapon_stats(id);
+    (20.0 / speed).ceil() as u32
+}
+
+pub struct CombatTask {
+    target: Target,
+    config: CombatConfig,
+
+    /// the entity we are fighting once the target resolved to one
+    entity: Option<u32>,
+    cooldown: u32,
+
+    /// ticks since we last attacked
+    since_attack: u32,
+    first: bool,
+    strafe: Option<Strafe>,
+    strafe_ticks: u32,
+
+    /// the path to the target and where it was when the path was made
+    chase: Option<(BlockTravelNearTask, Location)>,
+}
+
+impl CombatTask {
+    pub fn new(target: Target, config: CombatConfig) -> CombatTask {
+        let entity = match target {
+            Target::Entity(id) => Some(id),
+            Target::NearestPlayer { .. } => None,
+        };
+
+        CombatTask {
+            target,
+            entity,
+            cooldown: config.cooldown.unwrap_or_else(|| cooldown_ticks(0)),
+            config,
+            since_attack: u32::MAX / 2,
+            first: true,
+            strafe: None,
+            strafe_ticks: 0,
+            chase: None,
+        }
+    }
+
+    fn resolve(&mut self, local: &LocalState, global: &GlobalState) -> Option<u32> {
+        if let Some(id) = self.entity {
+            return Some(id);
+        }
+
+        let whitelist = match &self.target {
+            Target::NearestPlayer { whitelist } => whitelist,
+            Target::Entity(id) => return Some(*id),
+        };
+
+        let me = local.physics.location();
+        let (id, _) = global.entities.iter()
+            .filter(|(&id, data)| id != local.info.entity_id && data.owner.is_some())
+            .filter_map(|(id, data)| match data.kind {
+                EntityKind::Player { uuid } => {
+                    let name = global.players.iter().find(|player| player.uuid == uuid).map(|player| player.name.as_str());
+                    let allowed = name.map_or(false, |name| whitelist.iter().any(|white| white.eq_ignore_ascii_case(name)));
+                    (!allowed).then(|| (*id, data.location.dist2(me)))
+                }
+                _ => None,
+            })
+            .min_by_key(|(_, dist2)| FloatOrd(*dist2))?;
+
+        self.entity = Some(id);
+        Some(id)
+    }
+
+    /// the best weapon of the hotbar by damage per second
+    fn switch_weapon(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState) {
+        let best = local.inventory.hotbar().iter()
+            .enumerate()
+            .map(|(idx, stack)| (idx, stack.as_ref().map_or(0, |stack| stack.kind.id())))
+            .max_by_key(|&(_, id)| {
+                let (damage, speed) = weapon_stats(id);
+                FloatOrd(damage * speed)
+            });
+
+        if let Some((idx, _)) = best {
+            local.inventory.select_slot(idx as u8, out);
+        }
+    }
+
+    /// whether the server will accept an attack from here: close enough and nothing in the way from our eyes to the target
+    fn can_hit(&self, local: &LocalState, global: &GlobalState, target: Location) -> bool {
+        let me = local.physics.location();
+        if me.dist2(target) > self.config.reach * self.config.reach {
+            return false;
+        }
+
+        let eye = me + local.physics.eye_height();
+        let aim = target + AIM;
+        let displacement = aim - eye;
+        global.blocks.raycast(eye, Direction::from(displacement), displacement.mag()).is_none()
+    }
+
+    fn chase(&mut self, local: &mut LocalState, global: &mut GlobalState, out: &mut impl InterfaceOut, target: Location) {
+        let repath = match &self.chase {
+            None => true,
+            Some((_, goal)) => goal.dist2(target) > REPATH_DIST * REPATH_DIST,
+        };
+
+        if repath {
+            let near = self.config.reach - 0.5;
+            let goal = BlockLocation2D::from(BlockLocation::from(target));
+            let problem = TravelProblem::navigate_near_block(local.physics.location().into(), goal, near * near, false);
+            self.chase = Some((NavigateProblem::from(problem), target));
+        }
+
+        if let Some((nav, _)) = self.chase.as_mut() {
+            if nav.tick(out, local, global) {
+                self.chase = None;
+            }
+        }
+    }
+
+    fn melee(&mut self, local: &mut LocalState, target: Location) {
+        let me = local.physics.location();
+        let mut flat = target;
+        flat.y = me.y;
+
+        local.physics.look_at(target + AIM);
+
+        // sprinting cancels criticals
+        let speed = if self.config.crits { Speed::WALK } else { Speed::SPRINT };
+
+        if me.dist2(flat) > (self.config.reach - 0.5).powi(2) {
+            local.physics.line(Line::Forward);
+            local.physics.speed(speed);
+        } else if self.config.strafe {
+            if self.strafe_ticks == 0 {
+                let rng = &mut local.rng;
+                self.strafe = match rng.gen_range(0..3) {
+                    0 => Some(Strafe::Left),
+                    1 => Some(Strafe::Right),
+                    _ => None
+                };
+                self.strafe_ticks = rng.gen_range(8..25);
+            }
+            self.strafe_ticks -= 1;
+
+            if let Some(strafe) = self.strafe {
+                local.physics.strafe(strafe);
+                local.physics.speed(Speed::WALK);
+            }
+        }
+    }
+}
+
+impl TaskTrait for CombatTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        let id = match self.resolve(local, global) {
+            Some(id) => id,
+            None => return true,
+        };
+
+        // a target without an owner has not been seen by any bot since it was destroyed
+        let target = match global.entities.by_id(id) {
+            Some(data) if data.owner.is_some() => data.location,
+            _ => return true,
+        };
+
+        let me = local.physics.location();
+        if me.dist2(target) > self.config.give_up * self.config.give_up {
+            return true;
+        }
+
+        if self.first {
+            self.first = false;
+            if self.config.switch_weapon {
+                self.switch_weapon(out, local);
+            }
+            let held = local.inventory.current().map_or(0, |stack| stack.kind.id());
+            self.cooldown = self.config.cooldown.unwrap_or_else(|| cooldown_ticks(held));
+        }
+
+        self.since_attack = self.since_attack.saturating_add(1);
+
+        if me.dist2(target) > CHASE_DIST * CHASE_DIST {
+            self.chase(local, global, out, target);
+            return false;
+        }
+
+        self.chase = None;
+        self.melee(local, target);
+
+        let can_hit = self.can_hit(local, global, target);
+        let ready = self.since_attack >= self.cooldown;
+
+        if self.config.crits && can_hit && local.physics.on_ground() && self.since_attack + JUMP_LEAD >= self.cooldown {
+            local.physics.jump();
+        }
+
+        let falling = !local.physics.on_ground() && local.physics.velocity().dy < 0.0;
+        let crit = !self.config.crits || falling || self.since_attack >= self.cooldown + CRIT_PATIENCE;
+
+        // melee already turns us towards the target, so only hit once the head is there
+        if ready && crit && can_hit && local.physics.aimed() {
+            local.physics.look_at(target + AIM);
+            out.look(local.physics.direction());
+            out.attack_entity(id);
+            out.swing_arm();
+            self.since_attack = 0;
+        }
+
+        false
+    }
+
+    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+        if let Some((nav, _)) = self.chase.as_mut() {
+            nav.expensive(end_at, local, global);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+    use more_asserts::*;
+    use swarm_bot_packets::read::ByteReader;
+    use swarm_bot_packets::types::VarInt;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    use crate::client::state::global::GlobalState;
+    use crate::client::state::global::world_players::Player;
+    use crate::client::state::local::inventory::ItemStack;
+    use crate::client::state::local::LocalState;
+    use crate::client::tasks::combat::{CombatConfig, CombatTask, cooldown_ticks, Target};
+    use crate::client::tasks::TaskTrait;
+    use crate::protocol::v340::Interface340;
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::storage::entities::EntityKind;
+    use crate::types::Location;
+
+    const ZOMBIE: u32 = 5;
+
+    /// Use Entity in 1.12.2
+    const USE_ENTITY: i32 = 0x0A;
+
+    fn attacks(rx: &mut UnboundedReceiver<Vec<u8>>) -> usize {
+        let mut count = 0;
+        while let Some(Some(packet)) = rx.recv().now_or_never() {
+            let mut reader = ByteReader::new(packet);
+            let _len: VarInt = reader.read();
+            let VarInt(id) = reader.read();
+            if id == USE_ENTITY {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    fn arena() -> (LocalState, GlobalState) {
+        let mut local = LocalState::mock();
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+
+        local.physics.teleport(Location::new(0.5, 1.0, 0.5));
+        local.inventory.add(36, ItemStack::new(BlockKind::from(276), 1, 0, None));
+        global.entities.put_entity(ZOMBIE, 0, Location::new(2.5, 1.0, 0.5), EntityKind::Normal);
+        (local, global)
+    }
+
+    fn config() -> CombatConfig {
+        CombatConfig { strafe: false, ..CombatConfig::default() }
+    }
+
+    #[test]
+    fn test_cooldown() {
+        // diamond sword, diamond axe and the hand
+        assert_eq!(cooldown_ticks(276), 13);
+        assert_eq!(cooldown_ticks(279), 20);
+        assert_eq!(cooldown_ticks(0), 5);
+    }
+
+    #[test]
+    fn test_crits() {
+        let (mut out, mut rx) = Interface340::test();
+        let (mut local, mut global) = arena();
+        let mut task = CombatTask::new(Target::Entity(ZOMBIE), config());
+
+        let mut hits = Vec::new();
+        for tick in 0..200 {
+            assert!(!task.tick(&mut out, &mut local, &mut global));
+
+            if attacks(&mut rx) > 0 {
+                let falling = !local.physics.on_ground() && local.physics.velocity().dy < 0.0;
+                let dist = local.physics.location().dist2(Location::new(2.5, 1.0, 0.5)).sqrt();
+                hits.push((tick, falling, dist));
+            }
+
+            local.physics.tick(&mut global.blocks, &local.inventory);
+        }
+
+        // a crit every cooldown plus the jump, which takes 12 ticks
+        assert_ge!(hits.len(), 8);
+
+        for pair in hits.windows(2) {
+            assert_ge!(pair[1].0 - pair[0].0, 13, "attacked before the cooldown recharged");
+        }
+
+        for &(_, _, dist) in &hits {
+            assert_le!(dist, 3.0);
+        }
+
+        // the first attack is right away, all others should be criticals
+        assert!(hits.iter().skip(1).all(|&(_, falling, _)| falling), "{:?}", hits);
+    }
+
+    #[test]
+    fn test_line_of_sight() {
+        let (mut out, mut rx) = Interface340::test();
+        let (mut local, mut global) = arena();
+
+        for y in 1..=3 {
+            for z in -3..=3 {
+                global.blocks.set_block(BlockLocation::new(1, y, z), BlockState::STONE);
+            }
+        }
+
+        let mut task = CombatTask::new(Target::Entity(ZOMBIE), config());
+        for _ in 0..100 {
+            task.tick(&mut out, &mut local, &mut global);
+            local.physics.tick(&mut global.blocks, &local.inventory);
+        }
+
+        assert_eq!(attacks(&mut rx), 0);
+    }
+
+    #[test]
+    fn test_break_off() {
+        let (mut out, _rx) = Interface340::test();
+        let (mut local, mut global) = arena();
+        let mut task = CombatTask::new(Target::Entity(ZOMBIE), config());
+
+        assert!(!task.tick(&mut out, &mut local, &mut global));
+
+        // despawned
+        global.entities.remove_entity(ZOMBIE, 0);
+        assert!(task.tick(&mut out, &mut local, &mut global));
+
+        // out of range
+        global.entities.put_entity(ZOMBIE, 0, Location::new(100.5, 1.0, 0.5), EntityKind::Normal);
+        let mut task = CombatTask::new(Target::Entity(ZOMBIE), config());
+        assert!(task.tick(&mut out, &mut local, &mut global));
+    }
+
+    #[test]
+    fn test_nearest_player() {
+        let (local, mut global) = arena();
+
+        global.players.add(Player::new("friend", 1));
+        global.players.add(Player::new("enemy", 2));
+        global.entities.put_entity(10, 0, Location::new(1.5, 1.0, 0.5), EntityKind::Player { uuid: 1 });
+        global.entities.put_entity(11, 0, Location::new(8.5, 1.0, 0.5), EntityKind::Player { uuid: 2 });
+
+        let mut task = CombatTask::new(Target::NearestPlayer { whitelist: vec!["Friend".to_string()] }, config());
+        assert_eq!(task.resolve(&local, &global), Some(11));
+
+        // the target is kept even if someone else comes closer
+        global.entities.put_entity(12, 0, Location::new(0.5, 1.0, 1.5), EntityKind::Player { uuid: 3 });
+        assert_eq!(task.resolve(&local, &global), Some(11));
+    }
+}
@@ -45,6 +45,11 @@ impl TaskTrait for CompoundTask {
         while let Some(front) = self.tasks.front_mut() {
             let finished = front.tick(out, local, global);
 
+            // the rest would start from the wrong place
+            if finished && local.failure.is_some() {
+                return true;
+            }
+
             if finished {
                 self.tasks.pop_front();
             } else {
@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::Instant;
+
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::TaskTrait;
+use crate::protocol::InterfaceOut;
+
+/// A task defined outside the crate. [`crate::client::tasks::Task`] is an enum so other crates cannot add to it, but
+/// they can implement this and schedule it as a [`CustomTask`].
+pub trait DynTask: Send {
+    /// the name of the task for logging and recordings
+    fn name(&self) -> &'static str;
+
+    /// return true if done
+    fn tick(&mut self, out: &mut dyn InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool;
+
+    /// like [`TaskTrait::expensive`], done on another thread and it must return by `end_by`
+    fn expensive(&mut self, _end_by: Instant, _local: &mut LocalState, _global: &GlobalState) {}
+}
+
+pub struct CustomTask {
+    inner: Box<dyn DynTask>,
+}
+
+impl CustomTask {
+    pub fn new(task: impl DynTask + 'static) -> CustomTask {
+        CustomTask { inner: Box::new(task) }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+impl TaskTrait for CustomTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        self.inner.tick(out, local, global)
+    }
+
+    fn expensive(&mut self, end_by: Instant, local: &mut LocalState, global: &GlobalState) {
+        self.inner.expensive(end_by, local, global);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::bot::ActionState;
+    use crate::client::state::global::GlobalState;
+    use crate::client::state::local::LocalState;
+    use crate::client::tasks::custom::{CustomTask, DynTask};
+    use crate::protocol::InterfaceOut;
+    use crate::protocol::v340::Interface340;
+
+    /// says hi a few times
+    struct Greet {
+        left: usize,
+    }
+
+    impl DynTask for Greet {
+        fn name(&self) -> &'static str {
+            "Greet"
+        }
+
+        fn tick(&mut self, out: &mut dyn InterfaceOut, _local: &mut LocalState, _global: &mut GlobalState) -> bool {
+            out.send_chat("hi");
+            self.left -= 1;
+            self.left == 0
+        }
+    }
+
+    #[test]
+    fn test_custom() {
+        let (mut out, _rx) = Interface340::test();
+        let mut local = LocalState::mock();
+        let mut global = GlobalState::init();
+
+        let mut actions = ActionState::default();
+        actions.schedule(CustomTask::new(Greet { left: 3 }));
+        assert_eq!(actions.current().map(|(_, name)| name), Some("Greet"));
+
+        assert_eq!(actions.tick(&mut out, &mut local, &mut global), None);
+        assert_eq!(actions.tick(&mut out, &mut local, &mut global), None);
+        assert_eq!(actions.tick(&mut out, &mut local, &mut global), Some("Greet"));
+        assert!(actions.current().is_none());
+    }
+}
@@ -0,0 +1,111 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::inventory::{ItemStack, PlayerInventory};
+use crate::client::state::local::LocalState;
+use crate::client::tasks::TaskTrait;
+use crate::protocol::{Face, InterfaceOut, InvAction};
+use crate::storage::block::{BlockKind, BlockLocation};
+
+/// how long we wait for the server to open the chest
+const OPEN_TICKS: usize = 20;
+
+/// Open a chest next to the bot and shift click items into it, one stack a tick
+pub struct DepositTask {
+    chest: BlockLocation,
+
+    /// the items which are deposited. Everything if empty.
+    kinds: Vec<BlockKind>,
+
+    /// one stack of this is kept (i.e., seeds to replant)
+    keep: Option<BlockKind>,
+
+    /// the tick we right clicked the chest
+    opened_at: Option<usize>,
+}
+
+impl DepositTask {
+    pub fn new(chest: BlockLocation, kinds: Vec<BlockKind>, keep: Option<BlockKind>) -> DepositTask {
+        DepositTask {
+            chest,
+            kinds,
+            keep,
+            opened_at: None,
+        }
+    }
+
+    /// the next slot to deposit and the stack in it. None if there is nothing (more) to deposit.
+    pub fn next_slot(&self, inventory: &PlayerInventory) -> Option<(usize, ItemStack)> {
+        let mut kept = false;
+        inventory.stored()
+            .find(|(_, stack)| {
+                if !kept && Some(stack.kind) == self.keep {
+                    kept = true;
+                    return false;
+                }
+                self.kinds.is_empty() || self.kinds.contains(&stack.kind)
+            })
+            .map(|(idx, stack)| (idx, stack.clone()))
+    }
+}
+
+impl TaskTrait for DepositTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        let window = match local.window {
+            Some(window) => window,
+            None => {
+                match self.opened_at {
+                    None => {
+                        // any face we can see opens it
+                        let eye = local.physics.location() + local.physics.eye_height();
+                        let (look, face) = match local.aim.aim(&global.blocks, eye, self.chest, None) {
+                            Ok(aim) => (aim.point, aim.face),
+                            Err(_) => (self.chest.faces()[Face::PosY as usize], Face::PosY),
+                        };
+
+                        local.physics.look_at(look);
+                        if local.physics.aimed() {
+                            out.place_block(self.chest, face);
+                            out.swing_arm();
+                            self.opened_at = Some(local.ticks);
+                        }
+                    }
+                    Some(opened_at) if local.ticks - opened_at > OPEN_TICKS => {
+                        warn!(chest = %self.chest, "could not open the chest");
+                        return true;
+                    }
+                    Some(_) => {}
+                }
+                return false;
+            }
+        };
+
+        match self.next_slot(&local.inventory) {
+            Some((idx, stack)) => {
+                // the server does not tell us about slots we clicked ourselves
+                out.inventory_action(InvAction::WindowShiftClick(window.id, window.slot(idx), Some(stack)));
+                local.inventory.remove(idx);
+                false
+            }
+            None => {
+                out.close_window(window.id);
+                local.window = None;
+                true
+            }
+        }
+    }
+}
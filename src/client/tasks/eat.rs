@@ -17,7 +17,7 @@
 use crate::client::state::global::GlobalState;
 use crate::client::state::local::LocalState;
 use crate::client::tasks::TaskTrait;
-use crate::protocol::InterfaceOut;
+use crate::protocol::{Hand, InterfaceOut};
 
 pub struct EatTask {
     ticks: usize,
@@ -33,15 +33,17 @@ impl Default for EatTask {
 }
 
 impl TaskTrait for EatTask {
-    fn tick(&mut self, out: &mut impl InterfaceOut, _: &mut LocalState, _: &mut GlobalState) -> bool {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, _: &mut GlobalState) -> bool {
 
         // start eating
         if self.ticks == TICKS {
-            out.use_item();
+            out.use_item(Hand::Main);
+            local.physics.set_using_item(true);
         }
 
         if self.ticks == 0 {
             out.finish_eating();
+            local.physics.set_using_item(false);
             true
         } else {
             self.ticks -= 1;
@@ -49,3 +51,33 @@ impl TaskTrait for EatTask {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::sim::{Call, SimulatedClient};
+    use crate::client::tasks::eat::EatTask;
+    use crate::protocol::Hand;
+    use crate::storage::block::{BlockKind, BlockLocation};
+    use crate::storage::blocks::WorldBlocks;
+
+    const BREAD: BlockKind = BlockKind(297);
+
+    #[test]
+    fn test_eat() {
+        let mut sim = SimulatedClient::new(WorldBlocks::flat(), BlockLocation::new(0, 1, 0).center_bottom());
+        sim.give(0, BREAD, 3);
+        sim.local().food = 10;
+
+        sim.schedule(EatTask::default());
+        sim.run_task(100);
+
+        let started = sim.out().ticks_of(&Call::UseItem(Hand::Main));
+        let finished = sim.out().ticks_of(&Call::FinishEating);
+        assert_eq!(started.len(), 1);
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0] - started[0], 40);
+
+        assert_eq!(sim.local().food, 20);
+        assert_eq!(sim.local().inventory.current().map(|held| held.count), Some(2));
+    }
+}
@@ -18,7 +18,7 @@
 use crate::client::state::global::GlobalState;
 use crate::client::state::local::LocalState;
 use crate::client::tasks::TaskTrait;
-use crate::protocol::InterfaceOut;
+use crate::protocol::{Hand, InterfaceOut};
 use crate::storage::block::{BlockLocation, BlockState, SimpleType};
 
 #[derive(Default)]
@@ -29,6 +29,33 @@ pub struct FallBucketTask {
     place_loc: Option<BlockLocation>,
 }
 
+impl FallBucketTask {
+    /// whether we are falling further onto a block than the pathfinder would drop without a water bucket. Falls do
+    /// not hurt in creative mode.
+    pub fn needed(local: &LocalState, global: &GlobalState) -> bool {
+        let physics = &local.physics;
+        if local.gamemode().creative() || physics.on_ground() || physics.velocity().dy >= 0.0 || !local.inventory.has_water_bucket() {
+            return false;
+        }
+
+        let location = physics.location();
+        let feet = BlockLocation::from(location);
+
+        // landing in water does not hurt
+        let landing = (0..feet.y).rev()
+            .map(|y| BlockLocation::new(feet.x, y, feet.z))
+            .find(|&loc| global.blocks.get_block_simple(loc).map_or(false, |kind| kind != SimpleType::WalkThrough));
+
+        match landing {
+            Some(landing) if global.blocks.get_block_simple(landing) == Some(SimpleType::Solid) => {
+                let fall = physics.fall_distance() + location.y - (f64::from(landing.y) + 1.0);
+                fall > f64::from(global.config.path.max_fall)
+            }
+            _ => false
+        }
+    }
+}
+
 impl TaskTrait for FallBucketTask {
     fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
         const BUCKET_LEAVE_TICKS: u32 = 10;
@@ -40,7 +67,7 @@ impl TaskTrait for FallBucketTask {
 
             // we msut wait or else a) anti cheat might be flagged b) we might remove the water before we land
             if self.ticks_since_place == BUCKET_LEAVE_TICKS {
-                out.use_item();
+                out.use_item(Hand::Main);
                 // out.place_block(place_loc, Face::PosY);
                 global.blocks.set_block(place_loc.above(), BlockState::AIR);
             }
@@ -76,7 +103,7 @@ impl TaskTrait for FallBucketTask {
                         return true;
                     }
 
-                    out.use_item();
+                    out.use_item(Hand::Main);
                     // out.place_block(location, Face::PosY);
                     global.blocks.set_block(location.above(), BlockState::WATER);
                     self.place_loc = Some(location);
@@ -91,3 +118,32 @@ impl TaskTrait for FallBucketTask {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::sim::{Call, SimulatedClient};
+    use crate::client::tasks::fall_bucket::FallBucketTask;
+    use crate::protocol::Hand;
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+
+    #[test]
+    fn test_fall() {
+        let mut sim = SimulatedClient::new(WorldBlocks::flat(), BlockLocation::new(0, 50, 0).center_bottom());
+        sim.give(0, BlockKind::WATER_BUCKET, 1);
+        sim.schedule(FallBucketTask::default());
+        sim.run_task(200);
+
+        // the water is placed right before landing and picked back up once the bot is in it
+        let uses = sim.out().ticks_of(&Call::UseItem(Hand::Main));
+        assert_eq!(uses.len(), 2);
+
+        let height = sim.positions[uses[0]].y - 1.0;
+        assert!(height > 0.0 && height < 3.4, "placed the water {} blocks above the ground", height);
+        assert_eq!(uses[1] - uses[0], 10);
+
+        assert_eq!(sim.fall_damage, 0.0);
+        assert_eq!(sim.global.blocks.get_block_exact(BlockLocation::new(0, 1, 0)), Some(BlockState::AIR));
+        assert!(sim.local().inventory.has_water_bucket());
+    }
+}
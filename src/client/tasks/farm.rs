@@ -0,0 +1,250 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::Instant;
+
+use crate::client::afk::Area;
+use crate::client::snapshot::SavedTask;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::{TaskTrait, CompoundTask, DelayTask};
+use crate::client::tasks::deposit::DepositTask;
+use crate::client::tasks::navigate::{BlockReachTask, BlockTravelTask};
+use crate::protocol::{Face, Hand, InterfaceOut, Mine};
+use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+use crate::storage::block::properties::Crop;
+use crate::storage::blocks::WorldBlocks;
+
+/// how long to wait before scanning the field again when nothing is grown
+const IDLE_TICKS: u32 = 100;
+
+/// Break a grown crop the bot is standing in and plant a new one
+pub struct HarvestTask {
+    location: BlockLocation,
+    kind: BlockKind,
+    crop: Crop,
+    harvested: bool,
+}
+
+impl HarvestTask {
+    pub fn new(location: BlockLocation, kind: BlockKind, crop: Crop) -> HarvestTask {
+        HarvestTask { location, kind, crop, harvested: false }
+    }
+}
+
+impl TaskTrait for HarvestTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        if !self.harvested {
+            // someone else harvested it while we were walking there
+            if !matches!(global.blocks.get_block_exact(self.location), Some(state) if state.kind() == self.kind && state.is_grown()) {
+                return true;
+            }
+
+            // crops break right away so the server does not wait for us to finish digging
+            local.physics.look_at(self.location.center_bottom());
+            if !local.physics.aimed() {
+                return false;
+            }
+
+            out.mine(self.location, Mine::Start, Face::PosY);
+            local.stats.mined(self.kind);
+            global.predict(local.bot_id, self.location, BlockState::AIR);
+            self.harvested = true;
+            return false;
+        }
+
+        let soil = self.location.below();
+        if global.blocks.get_block_kind(soil) != Some(self.crop.soil) {
+            return true;
+        }
+
+        if !local.inventory.hold_item(|kind| kind == self.crop.seed, out) {
+            warn!(location = %self.location, "no seeds in the hotbar to replant");
+            return true;
+        }
+
+        local.physics.look_at(soil.faces()[Face::PosY as usize]);
+        if !local.physics.aimed() {
+            return false;
+        }
+
+        out.place_block(soil, Face::PosY);
+        out.swing_arm();
+        local.inventory.consume(Hand::Main);
+        global.predict(local.bot_id, self.location, BlockState::from(self.kind.id(), 0));
+        true
+    }
+}
+
+/// Harvest and replant a field forever. Produce is deposited into a chest when the inventory is full.
+pub struct FarmTask {
+    region: Area,
+    kind: BlockKind,
+    crop: Crop,
+    chest: Option<BlockLocation>,
+    current: Option<CompoundTask>,
+}
+
+impl FarmTask {
+    /// None if `kind` is not a crop
+    pub fn new(region: Area, kind: BlockKind, chest: Option<BlockLocation>) -> Option<FarmTask> {
+        let crop = kind.crop()?;
+        Some(FarmTask {
+            region,
+            kind,
+            crop,
+            chest,
+            current: None,
+        })
+    }
+
+    pub fn save(&self) -> SavedTask {
+        let Area { min, max } = self.region;
+        SavedTask::Farm { min, max, crop: self.kind.id(), chest: self.chest }
+    }
+
+    /// the grown crops of the field
+    pub fn grown(&self, world: &WorldBlocks) -> Vec<BlockLocation> {
+        let Area { min, max } = self.region;
+        let center = BlockLocation::new((min.x + max.x) / 2, min.y, (min.z + max.z) / 2);
+        let radius = ((max.x - min.x).max(max.z - min.z) / 2 + 1).min(u8::MAX as i32) as u8;
+
+        (min.y..=max.y)
+            .filter_map(|y| world.y_slice(BlockLocation { y, ..center }, radius, |state| state.kind() == self.kind && state.is_grown()))
+            .flatten()
+            .filter(|&location| self.region.contains(location))
+            .collect()
+    }
+
+    fn next(&self, local: &LocalState, global: &GlobalState) -> CompoundTask {
+        let mut task = CompoundTask::default();
+
+        // landing on farmland tramples it
+        let no_jump = self.region.expand(1);
+        let start = BlockLocation::from(local.physics.location());
+
+        if local.inventory.is_full() {
+            if let Some(chest) = self.chest {
+                let deposit = DepositTask::new(chest, self.crop.drops.to_vec(), Some(self.crop.seed));
+                if deposit.next_slot(&local.inventory).is_some() {
+                    task.add(BlockReachTask::new(chest, local, global).no_jump(no_jump));
+                    task.add(deposit);
+                    return task;
+                }
+            }
+        }
+
+        let closest = self.grown(&global.blocks).into_iter()
+            .min_by_key(|location| location.dist2(start) as u64);
+
+        match closest {
+            Some(location) => {
+                task.add(BlockTravelTask::new(location, local).no_jump(no_jump));
+                task.add(HarvestTask::new(location, self.kind, self.crop));
+            }
+            None => {
+                task.add(DelayTask(IDLE_TICKS));
+            }
+        }
+
+        task
+    }
+}
+
+impl TaskTrait for FarmTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        if let Some(current) = self.current.as_mut() {
+            if !current.tick(out, local, global) {
+                return false;
+            }
+        }
+
+        self.current = Some(self.next(local, global));
+        false
+    }
+
+    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+        if let Some(current) = self.current.as_mut() {
+            current.expensive(end_at, local, global);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::afk::Area;
+    use crate::client::state::global::GlobalState;
+    use crate::client::state::local::inventory::ItemStack;
+    use crate::client::state::local::LocalState;
+    use crate::client::tasks::farm::{FarmTask, HarvestTask};
+    use crate::client::tasks::TaskTrait;
+    use crate::protocol::v340::Interface340;
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+
+    const WHEAT: BlockKind = BlockKind(59);
+    const SEEDS: BlockKind = BlockKind(295);
+
+    /// a row of wheat on farmland. The middle one is not grown.
+    fn field() -> GlobalState {
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+        for x in 0..3 {
+            global.blocks.set_block(BlockLocation::new(x, 0, 0), BlockState::from(60, 0));
+            let age = if x == 1 { 3 } else { 7 };
+            global.blocks.set_block(BlockLocation::new(x, 1, 0), BlockState::from(WHEAT.id(), age));
+        }
+        global
+    }
+
+    #[test]
+    fn test_grown() {
+        let global = field();
+        let region = Area::new(BlockLocation::new(0, 1, 0), BlockLocation::new(2, 1, 0));
+        let farm = FarmTask::new(region, WHEAT, None).unwrap();
+
+        let mut grown = farm.grown(&global.blocks);
+        grown.sort_by_key(|location| location.x);
+        assert_eq!(grown, vec![BlockLocation::new(0, 1, 0), BlockLocation::new(2, 1, 0)]);
+
+        // stone is not a crop
+        assert!(FarmTask::new(region, BlockKind(1), None).is_none());
+    }
+
+    #[test]
+    fn test_harvest() {
+        let (mut out, _rx) = Interface340::test();
+        let mut global = field();
+        let mut local = LocalState::mock();
+        local.inventory.add(37, ItemStack::new(SEEDS, 2, 0, None));
+
+        let location = BlockLocation::new(0, 1, 0);
+        let mut task = HarvestTask::new(location, WHEAT, WHEAT.crop().unwrap());
+
+        assert!(!task.tick(&mut out, &mut local, &mut global));
+        assert_eq!(global.blocks.get_block_exact(location), Some(BlockState::AIR));
+
+        // replanted with one of the seeds
+        assert!(task.tick(&mut out, &mut local, &mut global));
+        assert_eq!(global.blocks.get_block_exact(location), Some(BlockState::from(WHEAT.id(), 0)));
+        assert_eq!(local.inventory.current().map(|stack| stack.count), Some(1));
+
+        // the crop growing next to it is left alone
+        let mut task = HarvestTask::new(BlockLocation::new(1, 1, 0), WHEAT, WHEAT.crop().unwrap());
+        assert!(task.tick(&mut out, &mut local, &mut global));
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(1, 1, 0)), Some(BlockState::from(WHEAT.id(), 3)));
+    }
+}
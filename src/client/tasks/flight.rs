@@ -0,0 +1,218 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Flying to a location like in creative mode, where the server lets us
+
+use std::collections::VecDeque;
+
+use crate::client::pathfind::flight::flight_path;
+use crate::client::physics::Line;
+use crate::client::physics::speed::Speed;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::{TaskError, TaskTrait};
+use crate::client::tasks::fall_bucket::FallBucketTask;
+use crate::protocol::InterfaceOut;
+use crate::types::{Direction, Displacement, Location};
+
+/// how close to a point of the path counts as there
+const REACHED: f64 = 0.5;
+
+/// Horizontal speed is kept at 91% a tick, so coasting at `v` goes about `v * 0.91 / 0.09` further
+const COAST: f64 = 10.1;
+
+/// vertical speed is kept at 60% a tick, so it coasts `v * 0.6 / 0.4` further
+const COAST_Y: f64 = 1.5;
+
+/// sprint when further than this from the next point
+const SPRINT_DIST: f64 = 16.0;
+
+/// Fly to `goal` if the server lets us, see [`flight_path`]. Touching down at the goal ends the flight. If the server
+/// stops us flying on the way the fall is broken with a water bucket (if we have one) and the task ends on the ground.
+pub struct FlightTask {
+    goal: Location,
+
+    /// the points left to fly through. None until the first tick.
+    points: Option<VecDeque<Location>>,
+
+    /// breaking the fall if the server stopped the flight
+    fall: Option<FallBucketTask>,
+}
+
+impl FlightTask {
+    pub fn new(goal: Location) -> FlightTask {
+        FlightTask { goal, points: None, fall: None }
+    }
+
+    /// fall (or stand) once we are not flying anymore. True once we are on the ground.
+    fn land(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        if self.fall.is_none() && FallBucketTask::needed(local, global) {
+            self.fall = Some(FallBucketTask::default());
+        }
+
+        if let Some(fall) = self.fall.as_mut() {
+            if !fall.tick(out, local, global) {
+                return false;
+            }
+            self.fall = None;
+        }
+
+        local.physics.on_ground()
+    }
+}
+
+impl TaskTrait for FlightTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        let location = local.physics.location();
+
+        let points = match self.points.as_mut() {
+            Some(points) => points,
+            None => {
+                let path = flight_path(location, self.goal, &global.blocks);
+                return match path {
+                    Some(path) if local.set_flying(true) => {
+                        self.points = Some(path.into());
+                        false
+                    }
+                    _ => {
+                        local.failure = Some(TaskError::Unreachable { location: self.goal.into() });
+                        true
+                    }
+                };
+            }
+        };
+
+        if !local.physics.flying() {
+            return self.land(out, local, global);
+        }
+
+        while points.len() > 1 && (points[0] - location).mag() < REACHED {
+            points.pop_front();
+        }
+
+        let target = points[0];
+        let to_target = target - location;
+        let horizontal = Displacement::new(to_target.dx, 0., to_target.dz);
+        let dist = horizontal.mag();
+        let velocity = local.physics.velocity();
+
+        if dist > 0.01 {
+            let yaw = Direction::from(horizontal).yaw;
+            local.physics.look(Direction { yaw, pitch: 0.0 });
+
+            let towards = horizontal.normalize();
+            let along = velocity.dx * towards.dx + velocity.dz * towards.dz;
+
+            // speed up until coasting would get us there, then brake
+            if dist > along * COAST {
+                local.physics.line(Line::Forward);
+                local.physics.speed(if dist > SPRINT_DIST { Speed::SPRINT } else { Speed::WALK });
+            } else if along > 0.0 {
+                local.physics.line(Line::Backward);
+                local.physics.speed(Speed::WALK);
+            }
+        }
+
+        let landing = points.len() == 1 && dist < REACHED;
+        let stop = velocity.dy * COAST_Y;
+
+        // touching the ground at the goal ends the flight
+        if landing || to_target.dy < stop - 0.05 {
+            local.physics.descend();
+        } else if to_target.dy > stop + 0.05 {
+            local.physics.jump();
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::sim::{Call, SimulatedClient};
+    use crate::client::state::local::abilities::Abilities;
+    use crate::client::tasks::flight::FlightTask;
+    use crate::protocol::Hand;
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::types::Location;
+
+    /// the flat world with a wall `height` blocks high at x = 10
+    fn walled(height: i16) -> WorldBlocks {
+        let mut world = WorldBlocks::flat();
+        for y in 1..=height {
+            for z in -3..=3 {
+                world.set_block(BlockLocation::new(10, y, z), BlockState::STONE);
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn test_flight() {
+        let goal = Location::new(20.5, 1., 0.5);
+        let mut sim = SimulatedClient::new(walled(5), Location::new(0.5, 1., 0.5));
+
+        // the server does not let us
+        sim.schedule(FlightTask::new(goal));
+        sim.run_task(10);
+        assert!(sim.local().failure.is_none() && sim.local().last_failure.is_some());
+        assert!(sim.out().ticks_of(&Call::Fly(true)).is_empty());
+
+        sim.local().abilities.may_fly = true;
+        sim.schedule(FlightTask::new(goal));
+        sim.run_task(400);
+
+        // over the wall and down at the goal without falling
+        let highest = sim.positions.iter().map(|loc| loc.y).fold(0.0, f64::max);
+        assert!(highest > 6.0);
+        assert!(sim.local().physics.on_ground());
+        assert!(!sim.local().physics.flying());
+        assert!(sim.local().physics.location().dist2(goal) < 1.0);
+        assert_eq!(sim.fall_damage, 0.0);
+
+        // the server was told when we took off and when we landed
+        assert_eq!(sim.out().ticks_of(&Call::Fly(true)).len(), 1);
+        assert_eq!(sim.out().ticks_of(&Call::Fly(false)).len(), 1);
+    }
+
+    #[test]
+    fn test_flight_revoked() {
+        let goal = Location::new(40.5, 1., 0.5);
+        let mut sim = SimulatedClient::new(walled(20), Location::new(0.5, 1., 0.5));
+        sim.give(0, BlockKind::WATER_BUCKET, 1);
+        sim.local().abilities.may_fly = true;
+        sim.schedule(FlightTask::new(goal));
+
+        // on the way down to the goal, far too high to drop
+        let mut ticks = 0;
+        while sim.local().physics.location().x < 40.0 || sim.local().physics.location().y > 15.0 {
+            sim.tick();
+            ticks += 1;
+            assert!(ticks < 400);
+        }
+        assert!(sim.local().physics.flying());
+
+        sim.local().on_abilities(Abilities::default());
+        sim.run_task(200);
+
+        // gravity took over and the water broke the fall
+        assert!(!sim.local().physics.flying());
+        assert!(sim.local().physics.on_ground());
+        assert_eq!(sim.fall_damage, 0.0);
+        assert_eq!(sim.out().ticks_of(&Call::UseItem(Hand::Main)).len(), 2);
+    }
+}
@@ -0,0 +1,207 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::TaskTrait;
+use crate::protocol::{Hand, InterfaceOut};
+use crate::storage::block::{BlockKind, BlockLocation};
+use crate::storage::blocks::WorldBlocks;
+use crate::types::{Direction, Displacement, Location};
+
+/// how long a firework pushes. Vanilla picks between 10 and 22 ticks for a rocket of flight duration 1.
+const BOOST_TICKS: u32 = 16;
+
+/// a firework is used when flying slower than this (blocks a tick)
+const BOOST_SPEED: f64 = 0.9;
+
+/// how high above the highest block on the way we fly
+const CLEARANCE: f64 = 12.0;
+
+/// how far ahead the terrain is checked
+const LOOK_AHEAD: f64 = 64.0;
+
+/// how far (horizontally) from the goal we start gliding down
+const DESCEND_DIST: f64 = 48.0;
+
+/// how far (horizontally) from the goal we pitch up to lose speed before touching down
+const FLARE_DIST: f64 = 10.0;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Stage {
+    TakeOff,
+    Cruise,
+    Land,
+}
+
+/// Fly to `goal` with an elytra. Jumps and starts gliding at the top of the jump, climbs to stay clear of the terrain
+/// ahead (using fireworks in the hotbar when slow), and glides down to land near the goal.
+pub struct FlyTask {
+    goal: BlockLocation,
+    stage: Stage,
+}
+
+impl FlyTask {
+    pub fn new(goal: BlockLocation) -> FlyTask {
+        FlyTask { goal, stage: Stage::TakeOff }
+    }
+
+    /// [`CLEARANCE`] above the highest known block between `from` and [`LOOK_AHEAD`] blocks towards the goal. Every
+    /// column on the way is checked so thin walls are not missed. Chunks which are not loaded are assumed to be empty.
+    fn cruise_height(&self, from: Location, world: &WorldBlocks) -> f64 {
+        let goal = self.goal.center_bottom();
+        let to_goal = Displacement::new(goal.x - from.x, 0., goal.z - from.z);
+        let dist = to_goal.mag().min(LOOK_AHEAD);
+
+        let samples = dist as usize;
+        let step = if samples == 0 { Displacement::default() } else { to_goal.normalize() };
+
+        let highest = (0..=samples)
+            .map(|i| BlockLocation::from(from + step * i as f64))
+            .filter_map(|loc| world.first_below(BlockLocation::new(loc.x, 256, loc.z)))
+            .map(|(loc, _)| loc.y as f64 + 1.0)
+            .fold(goal.y, f64::max);
+
+        highest + CLEARANCE
+    }
+}
+
+impl TaskTrait for FlyTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        let location = local.physics.location();
+        let goal = self.goal.center_bottom();
+        let horizontal = Displacement::new(goal.x - location.x, 0., goal.z - location.z);
+        let dist = horizontal.mag();
+
+        if !local.physics.gliding() {
+            // we came down (or hit water) before reaching the goal
+            if self.stage == Stage::Cruise {
+                self.stage = Stage::TakeOff;
+            }
+
+            if self.stage == Stage::Land {
+                return true;
+            }
+
+            if !local.inventory.elytra() {
+                warn!(goal = %self.goal, "cannot fly without an elytra");
+                return true;
+            }
+
+            // the server only lets us glide once we are falling
+            if local.physics.on_ground() {
+                local.physics.jump();
+            } else if local.physics.velocity().dy < 0.0 && local.physics.start_gliding() {
+                out.start_gliding();
+                self.stage = Stage::Cruise;
+            }
+            return false;
+        }
+
+        if self.stage == Stage::Cruise && dist < DESCEND_DIST {
+            self.stage = Stage::Land;
+        }
+
+        let yaw = Direction::from(horizontal).yaw;
+
+        let direction = match self.stage {
+            // nose up to lose speed right before touching down
+            Stage::Land if dist < FLARE_DIST => Direction { yaw, pitch: -30.0 },
+            Stage::Land => Direction::from(goal - location),
+            _ => {
+                let height = self.cruise_height(location, &global.blocks);
+
+                // climb when below the cruising height and glide level above it
+                let pitch = ((location.y - height) * 3.0).clamp(-40.0, 5.0) as f32;
+
+                let velocity = local.physics.velocity();
+                let slow = velocity.dx.hypot(velocity.dz) < BOOST_SPEED;
+                let low = location.y < height - CLEARANCE / 2.0;
+
+                if (slow || low) && !local.physics.boosting() && local.inventory.hold_item(|kind| kind == BlockKind::FIREWORK, out) {
+                    out.use_item(Hand::Main);
+                    local.inventory.consume(Hand::Main);
+                    local.physics.boost(BOOST_TICKS);
+                }
+
+                Direction { yaw, pitch }
+            }
+        };
+
+        local.physics.look(direction);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::state::global::GlobalState;
+    use crate::client::state::local::armor::{Armor, ArmorSlot};
+    use crate::client::state::local::inventory::ItemStack;
+    use crate::client::state::local::LocalState;
+    use crate::client::tasks::fly::FlyTask;
+    use crate::client::tasks::TaskTrait;
+    use crate::protocol::v340::Interface340;
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::types::Location;
+
+    #[test]
+    fn test_fly() {
+        let (mut out, _rx) = Interface340::test();
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+
+        // a wall in the way
+        for y in 1..16 {
+            for z in -5..=5 {
+                global.blocks.set_block(BlockLocation::new(0, y, z), BlockState::STONE);
+            }
+        }
+
+        let mut local = LocalState::mock();
+        local.physics.teleport(Location::new(-80.5, 1., 0.5));
+        local.inventory.add(ArmorSlot::Chest.window_slot(), ItemStack::new(Armor::ELYTRA, 1, 0, None));
+        local.inventory.add(36, ItemStack::new(BlockKind::FIREWORK, 16, 0, None));
+
+        let goal = BlockLocation::new(60, 1, 0);
+        let mut task = FlyTask::new(goal);
+
+        let mut ticks = 0;
+        let mut highest = 0_f64;
+        while !task.tick(&mut out, &mut local, &mut global) {
+            local.physics.tick(&mut global.blocks, &local.inventory);
+            highest = highest.max(local.physics.location().y);
+            ticks += 1;
+            assert!(ticks < 1000, "still flying at {}", local.physics.location());
+        }
+
+        // flew over the wall and landed close to the goal
+        let location = local.physics.location();
+        assert!(highest > 16.0);
+        assert!(local.physics.on_ground());
+        assert!((location - goal.center_bottom()).mag() < 16.0, "landed at {}", location);
+
+        let fireworks = local.inventory.hotbar()[0].as_ref().map_or(0, |stack| stack.count);
+        assert!(fireworks < 16);
+
+        // no elytra
+        let mut local = LocalState::mock();
+        local.physics.teleport(Location::new(0.5, 1., 0.5));
+        assert!(FlyTask::new(goal).tick(&mut out, &mut local, &mut global));
+        assert!(!local.physics.gliding());
+    }
+}
@@ -0,0 +1,241 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use float_ord::FloatOrd;
+
+use crate::client::follow::{Follower, FollowResult};
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::navigate::BlockTravelTask;
+use crate::client::tasks::TaskTrait;
+use crate::protocol::InterfaceOut;
+use crate::storage::block::{BlockLocation, SimpleType};
+use crate::storage::blocks::WorldBlocks;
+use crate::types::Location;
+
+/// breadcrumbs between a follower and the one in front of it if `#follow-leader` is not given a spacing
+pub const DEFAULT_SPACING: usize = 3;
+
+/// farther than this (in blocks) from the next breadcrumb we pathfind back to the trail
+const REJOIN_DIST: f64 = 5.0;
+
+/// Follow the player `leader` along the route it actually took instead of pathfinding to it. The leader's position is
+/// recorded as a trail of breadcrumbs (one a block) which the bot walks, staying `spacing` breadcrumbs behind the bot
+/// in front of it so the followers string out in a line. A bot which falls off the trail or gets stuck pathfinds to the
+/// closest breadcrumb and continues from there. This only ends when cancelled.
+pub struct FollowLeaderTask {
+    leader: String,
+    spacing: usize,
+
+    /// the blocks the leader stood on which we have not reached yet, oldest first
+    trail: VecDeque<BlockLocation>,
+
+    /// the last block recorded so there is only one breadcrumb a block
+    last: Option<BlockLocation>,
+
+    /// walks the first `given` breadcrumbs of the trail
+    follower: Option<Follower>,
+    given: usize,
+
+    /// the index of the breadcrumb we are pathfinding back to
+    rejoin: Option<(usize, BlockTravelTask)>,
+}
+
+impl FollowLeaderTask {
+    pub fn new(leader: &str, spacing: usize) -> FollowLeaderTask {
+        FollowLeaderTask {
+            leader: leader.to_string(),
+            spacing,
+            trail: VecDeque::new(),
+            last: None,
+            follower: None,
+            given: 0,
+            rejoin: None,
+        }
+    }
+
+    /// where the leader is. None if no bot can see it.
+    fn leader_location(&self, global: &mut GlobalState) -> Option<Location> {
+        let uuid = global.player_by_name(&self.leader)?.uuid;
+        let id = global.entities.by_player_uuid(uuid)?;
+        global.entities.by_id(id).map(|entity| entity.location)
+    }
+
+    fn record(&mut self, location: Location, world: &WorldBlocks) {
+        let block = BlockLocation::from(location);
+        if self.last == Some(block) {
+            return;
+        }
+
+        // jumping or falling. The block the leader lands on is recorded instead.
+        if world.get_block_simple(block.below()) != Some(SimpleType::Solid) {
+            return;
+        }
+
+        self.last = Some(block);
+        self.trail.push_back(block);
+    }
+
+    /// pathfind to the closest of the first `allowed` breadcrumbs
+    fn start_rejoin(&mut self, local: &LocalState, allowed: usize) {
+        let location = local.physics.location();
+        let closest = self.trail.iter()
+            .take(allowed)
+            .enumerate()
+            .min_by_key(|(_, crumb)| FloatOrd(crumb.center_bottom().dist2(location)));
+
+        if let Some((idx, &crumb)) = closest {
+            debug!(leader = %self.leader, %crumb, "rejoining the trail");
+            self.follower = None;
+            self.given = 0;
+            self.rejoin = Some((idx, BlockTravelTask::new(crumb, local)));
+        }
+    }
+}
+
+impl TaskTrait for FollowLeaderTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        if let Some(location) = self.leader_location(global) {
+            self.record(location, &global.blocks);
+        }
+
+        // the breadcrumbs we can walk to without getting closer to the bot in front than `spacing`
+        let place = global.caravans.place(&self.leader, local.bot_id, global.ticks);
+        let allowed = self.trail.len().saturating_sub(self.spacing * (place + 1));
+
+        if let Some((idx, rejoin)) = self.rejoin.as_mut() {
+            if rejoin.tick(out, local, global) {
+                self.trail.drain(..=*idx);
+                self.rejoin = None;
+            }
+            return false;
+        }
+
+        // knocked away, fell off a jump, or not on the trail yet
+        let location = local.physics.location();
+        let off = self.trail.front().map_or(false, |crumb| crumb.center_bottom().dist2(location) > REJOIN_DIST * REJOIN_DIST);
+        if allowed > 0 && off {
+            self.start_rejoin(local, allowed);
+            return false;
+        }
+
+        // hand the follower the breadcrumbs which are now far enough from the bot in front
+        match self.follower.as_mut() {
+            Some(follower) => {
+                for &crumb in self.trail.range(self.given..allowed.max(self.given)) {
+                    follower.push(crumb.center_bottom());
+                }
+            }
+            None => {
+                self.follower = Follower::along(self.trail.range(..allowed).map(|crumb| crumb.center_bottom()).collect());
+            }
+        }
+        self.given = self.given.max(allowed);
+
+        let follower = match self.follower.as_mut() {
+            None => return false,
+            Some(follower) => follower
+        };
+
+        let result = follower.follow(local, global);
+        let left = follower.points().len();
+
+        // forget the breadcrumbs we reached
+        self.trail.drain(..self.given - left);
+        self.given = left;
+
+        match result {
+            FollowResult::InProgress => {}
+            FollowResult::Finished => self.follower = None,
+            FollowResult::Failed => {
+                warn!(leader = %self.leader, "stuck following");
+                self.follower = None;
+                self.given = 0;
+                let allowed = self.trail.len().saturating_sub(self.spacing * (place + 1));
+                self.start_rejoin(local, allowed);
+            }
+        }
+
+        false
+    }
+
+    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+        if let Some((_, rejoin)) = self.rejoin.as_mut() {
+            rejoin.expensive(end_at, local, global);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::client::state::global::GlobalState;
+    use crate::client::state::global::world_players::Player;
+    use crate::client::state::local::LocalState;
+    use crate::client::tasks::follow_leader::FollowLeaderTask;
+    use crate::client::tasks::TaskTrait;
+    use crate::protocol::v340::Interface340;
+    use crate::storage::block::BlockLocation;
+    use crate::storage::blocks::WorldBlocks;
+    use crate::storage::entities::EntityKind;
+
+    const LEADER_ID: u32 = 99;
+    const LEADER_UUID: u128 = 5;
+
+    /// the leader walks 20 blocks east and turns north for 10 blocks. The follower walks the same way and stops
+    /// `spacing` blocks behind.
+    #[test]
+    fn test_follow_leader() {
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+        global.players.add(Player::new("leader", LEADER_UUID));
+
+        let route: Vec<_> = (0..=20).map(|x| BlockLocation::new(x, 1, 0))
+            .chain((1..=10).map(|z| BlockLocation::new(20, 1, z)))
+            .collect();
+
+        let mut local = LocalState::mock();
+        local.physics.teleport(BlockLocation::new(-3, 1, 0).center_bottom());
+
+        let (mut out, _rx) = Interface340::test();
+        let mut task = FollowLeaderTask::new("leader", 3);
+
+        for ticks in 0..route.len() * 5 + 200 {
+            // about walking speed
+            let leader = route[(ticks / 5).min(route.len() - 1)];
+            global.entities.put_entity(LEADER_ID, 1, leader.center_bottom(), EntityKind::Player { uuid: LEADER_UUID });
+            global.ticks = ticks;
+
+            task.expensive(Instant::now() + Duration::from_millis(50), &mut local, &global);
+            assert!(!task.tick(&mut out, &mut local, &mut global));
+            local.physics.tick(&mut global.blocks, &local.inventory);
+
+            // the follower cuts no corners
+            let location = local.physics.location();
+            let on_route = route.iter()
+                .map(|block| block.center_bottom())
+                .any(|crumb| (crumb.x - location.x).powi(2) + (crumb.z - location.z).powi(2) < 1.0);
+            assert!(on_route || location.x < 0.0, "left the route at {}", location);
+        }
+
+        let end = BlockLocation::new(20, 1, 7).center_bottom();
+        assert!(local.physics.location().dist2(end) < 1.0, "stopped at {}", local.physics.location());
+    }
+}
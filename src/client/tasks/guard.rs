@@ -0,0 +1,273 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `#guard`: stand at a post and fight what comes within a radius of it. The most threatening mob (the closest to the
+//! post, then to the bot) goes first, guards share the mobs out between them and nobody is chased far from the post.
+
+use std::time::Instant;
+
+use float_ord::FloatOrd;
+
+use crate::client::snapshot::SavedTask;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::combat::{CombatConfig, CombatTask, Target};
+use crate::client::tasks::navigate::BlockTravelTask;
+use crate::client::tasks::TaskTrait;
+use crate::protocol::InterfaceOut;
+use crate::storage::block::BlockLocation;
+use crate::storage::entities::{EntityData, EntityKind};
+use crate::types::Location;
+
+/// targets and guards further than this many radii from the post are let go
+const LEASH: f64 = 1.5;
+
+/// a guard this close to the post does not walk back to it
+const AT_POST: f64 = 1.5;
+
+/// how long a guard waits before trying to get back to a post it could not get to again
+const RETRY_TICKS: usize = 20 * 5;
+
+/// a guard at its post turns a quarter this often
+const TURN_TICKS: usize = 20 * 2;
+
+pub struct GuardTask {
+    post: BlockLocation,
+    radius: f64,
+
+    /// the players who are left alone. None leaves every player alone.
+    players: Option<Vec<String>>,
+
+    /// the entity being fought
+    fight: Option<(u32, CombatTask)>,
+
+    /// walking back to the post
+    back: Option<BlockTravelTask>,
+
+    /// the tick to try getting back to the post again after failing to
+    retry_at: usize,
+    idle_ticks: usize,
+}
+
+impl GuardTask {
+    pub fn new(post: BlockLocation, radius: f64, players: Option<Vec<String>>) -> GuardTask {
+        GuardTask {
+            post,
+            radius,
+            players,
+            fight: None,
+            back: None,
+            retry_at: 0,
+            idle_ticks: 0,
+        }
+    }
+
+    pub fn save(&self) -> SavedTask {
+        SavedTask::Guard { post: self.post, radius: self.radius, players: self.players.clone() }
+    }
+
+    /// Whether the guard fights `entity`. The bot itself, the other bots of the swarm and the players on the list are
+    /// never fought, nor are players whose name is not known.
+    fn enemy(&self, entity: u32, data: &EntityData, local: &LocalState, global: &GlobalState) -> bool {
+        match data.kind {
+            EntityKind::Hostile => true,
+            EntityKind::Player { uuid } => {
+                let whitelist = match &self.players {
+                    Some(whitelist) => whitelist,
+                    None => return false,
+                };
+                if entity == local.info.entity_id || uuid == local.info.uuid.0 || global.members.contains(&uuid) {
+                    return false;
+                }
+
+                let name = global.players.iter().find(|player| player.uuid == uuid).map(|player| player.name.as_str());
+                name.map_or(false, |name| !whitelist.iter().any(|white| white.eq_ignore_ascii_case(name)))
+            }
+            _ => false,
+        }
+    }
+
+    /// The enemy within the radius to fight next. Enemies no other guard fights go first, then the closest to the post
+    /// (by the block) and then the closest to us.
+    fn pick(&self, local: &LocalState, global: &GlobalState) -> Option<u32> {
+        let post = self.post.center_bottom();
+        let me = local.physics.location();
+
+        global.entities.iter()
+            .filter(|(_, data)| data.owner.is_some() && data.location.dist2(post) <= self.radius * self.radius)
+            .filter(|&(&id, data)| self.enemy(id, data, local, global))
+            .map(|(&id, data)| {
+                let claimed = global.guards.claimed_by(id, local.bot_id, global.ticks).is_some();
+                let threat = (data.location.dist2(post).sqrt() as u64, FloatOrd(data.location.dist2(me)));
+                (claimed, threat, id)
+            })
+            .min_by_key(|&(claimed, threat, _)| (claimed, threat))
+            .map(|(_, _, id)| id)
+    }
+
+    /// Keep fighting. True once the fight is over: the target is gone or one of us left the leash.
+    fn keep_fighting(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        let (id, fight) = match self.fight.as_mut() {
+            Some((id, fight)) => (*id, fight),
+            None => return true,
+        };
+
+        let post = self.post.center_bottom();
+        let leash = self.radius * LEASH;
+        let target = global.entities.by_id(id).filter(|data| data.owner.is_some()).map(|data| data.location);
+
+        let inside = |location: Location| location.dist2(post) <= leash * leash;
+        if !target.map_or(false, inside) || !inside(local.physics.location()) {
+            debug!(id, "let the target go");
+            return true;
+        }
+
+        global.guards.claim(id, local.bot_id, global.ticks);
+        fight.tick(out, local, global)
+    }
+}
+
+impl TaskTrait for GuardTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        if self.fight.is_some() {
+            if !self.keep_fighting(out, local, global) {
+                return false;
+            }
+            global.guards.release(local.bot_id);
+            self.fight = None;
+        }
+
+        if let Some(id) = self.pick(local, global) {
+            debug!(id, "fighting");
+            global.guards.claim(id, local.bot_id, global.ticks);
+            let config = CombatConfig { give_up: self.radius * LEASH * 2.0, ..CombatConfig::default() };
+            self.fight = Some((id, CombatTask::new(Target::Entity(id), config)));
+            self.back = None;
+            return false;
+        }
+
+        // back to the post after every fight
+        if let Some(back) = self.back.as_mut() {
+            if !back.tick(out, local, global) {
+                return false;
+            }
+            if let Some(failure) = local.failure.take() {
+                debug!(%failure, "could not get back to the post");
+                self.retry_at = local.ticks + RETRY_TICKS;
+            }
+            self.back = None;
+        }
+
+        let post = self.post.center_bottom();
+        if local.physics.location().dist2(post) > AT_POST * AT_POST && local.ticks >= self.retry_at {
+            self.back = Some(BlockTravelTask::new(self.post, local));
+            return false;
+        }
+
+        // look around while waiting
+        self.idle_ticks += 1;
+        if self.idle_ticks % TURN_TICKS == 0 {
+            let mut direction = local.physics.direction();
+            direction.yaw += 90.0;
+            direction.pitch = 0.0;
+            local.physics.look(direction);
+        }
+        false
+    }
+
+    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+        if let Some((_, fight)) = self.fight.as_mut() {
+            fight.expensive(end_at, local, global);
+        } else if let Some(back) = self.back.as_mut() {
+            back.expensive(end_at, local, global);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::client::sim::SimulatedSwarm;
+    use crate::client::state::global::world_players::Player;
+    use crate::client::tasks::guard::GuardTask;
+    use crate::storage::block::BlockLocation;
+    use crate::storage::blocks::WorldBlocks;
+    use crate::storage::entities::EntityKind;
+    use crate::types::Location;
+
+    const ZOMBIES: [u32; 4] = [10, 11, 12, 13];
+    const FAR_ZOMBIE: u32 = 14;
+    const FRIEND: u32 = 20;
+    const ALT: u32 = 21;
+    const INTRUDER: u32 = 22;
+
+    #[test]
+    fn test_guard() {
+        let bots = [Location::new(0.5, 1.0, 0.5), Location::new(1.5, 1.0, 0.5), Location::new(0.5, 1.0, 1.5)];
+        let mut swarm = SimulatedSwarm::new(WorldBlocks::flat(), &bots);
+
+        let zombies = [(5.5, 0.5), (-4.5, 0.5), (0.5, 5.5), (0.5, -4.5)];
+        for (&id, &(x, z)) in ZOMBIES.iter().zip(zombies.iter()) {
+            swarm.spawn_mob(id, Location::new(x, 1.0, z), EntityKind::Hostile, 3);
+        }
+        swarm.spawn_mob(FAR_ZOMBIE, Location::new(20.5, 1.0, 0.5), EntityKind::Hostile, 3);
+
+        // a whitelisted player, a player of the swarm and a player who is neither
+        let global = &mut swarm.global;
+        global.players.add(Player::new("friend", 1));
+        global.players.add(Player::new("alt", 2));
+        global.players.add(Player::new("intruder", 3));
+        global.members.insert(2);
+        global.entities.put_entity(FRIEND, 0, Location::new(3.5, 1.0, 3.5), EntityKind::Player { uuid: 1 });
+        global.entities.put_entity(ALT, 0, Location::new(-3.5, 1.0, 3.5), EntityKind::Player { uuid: 2 });
+        swarm.spawn_mob(INTRUDER, Location::new(-4.5, 1.0, -4.5), EntityKind::Player { uuid: 3 }, 3);
+
+        let post = BlockLocation::new(0, 1, 0);
+        for client in &mut swarm.clients {
+            client.schedule(GuardTask::new(post, 10.0, Some(vec!["Friend".to_string()])));
+        }
+
+        // every guard goes for a different enemy
+        swarm.tick();
+        let ticks = swarm.global.ticks;
+        let claimed: HashSet<_> = ZOMBIES.iter().chain(&[INTRUDER])
+            .filter_map(|&id| swarm.global.guards.claimed_by(id, u32::MAX, ticks))
+            .collect();
+        assert_eq!(claimed.len(), 3);
+
+        let mut ticks = 0;
+        while ZOMBIES.iter().chain(&[INTRUDER]).any(|&id| swarm.alive(id)) {
+            assert!(ticks < 20 * 60, "the enemies were not killed in time");
+            swarm.tick();
+            ticks += 1;
+        }
+
+        let attacked: HashSet<_> = swarm.clients.iter().flat_map(|client| client.attacked.iter().copied()).collect();
+        for id in ZOMBIES.iter().chain(&[INTRUDER]) {
+            assert!(attacked.contains(id));
+        }
+        for id in &[FAR_ZOMBIE, FRIEND, ALT] {
+            assert!(!attacked.contains(id));
+        }
+
+        // nobody left the leash
+        let center = post.center_bottom();
+        for client in &swarm.clients {
+            assert!(client.positions.iter().all(|location| location.dist2(center) <= 15.0 * 15.0));
+        }
+    }
+}
@@ -33,21 +33,23 @@ impl HitEntityTask {
 impl TaskTrait for HitEntityTask {
     fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
 
-        let mut action = || {
-            let entity_loc = global.entities.by_id(self.id)?.location;
-
-            // hit the head
-            let target_loc = entity_loc + Displacement::EYE_HEIGHT;
+        let entity_loc = match global.entities.by_id(self.id) {
+            None => return true,
+            Some(entity) => entity.location
+        };
 
-            local.physics.look_at(target_loc);
-            out.look(local.physics.direction());
-            out.attack_entity(self.id);
-            out.swing_arm();
+        // hit the head
+        let target_loc = entity_loc + Displacement::EYE_HEIGHT;
 
-            Some(())
-        };
+        // keep turning until the head is on the target
+        local.physics.look_at(target_loc);
+        if !local.physics.aimed() {
+            return false;
+        }
 
-        action();
+        out.look(local.physics.direction());
+        out.attack_entity(self.id);
+        out.swing_arm();
         true
     }
 }
@@ -0,0 +1,54 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use crate::client::physics::Line;
+use crate::client::physics::speed::Speed;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::TaskTrait;
+use crate::protocol::InterfaceOut;
+use crate::storage::block::BlockLocation;
+
+/// Stay in the center of a block forever (station-keeping), sneaking so the bot cannot be pushed off. Used for AFK
+/// spots. Ends only when another task replaces it.
+pub struct HoldTask {
+    location: BlockLocation,
+}
+
+impl HoldTask {
+    pub fn new(location: BlockLocation) -> HoldTask {
+        HoldTask { location }
+    }
+}
+
+impl TaskTrait for HoldTask {
+    fn tick(&mut self, _out: &mut impl InterfaceOut, local: &mut LocalState, _global: &mut GlobalState) -> bool {
+        const THR: f64 = 0.1;
+
+        let loc = local.physics.location();
+        let mut center = self.location.center_bottom();
+        center.y = loc.y;
+
+        local.physics.set_sneaking(true);
+
+        if loc.dist2(center) > THR * THR {
+            local.physics.look_at(center);
+            local.physics.line(Line::Forward);
+            local.physics.speed(Speed::WALK);
+        }
+
+        false
+    }
+}
@@ -0,0 +1,250 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Placing torches where mobs could spawn so a parked swarm is not attacked at night.
+
+use std::collections::HashSet;
+use std::time::Instant;
+
+use float_ord::FloatOrd;
+
+use crate::client::snapshot::SavedTask;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::{CompoundTask, TaskTrait};
+use crate::client::tasks::navigate::BlockReachTask;
+use crate::protocol::{Face, Hand, InterfaceOut};
+use crate::storage::block::{BlockKind, BlockLocation, BlockState, SimpleType};
+use crate::storage::blocks::light::SPAWN_LIGHT;
+
+/// how far around the bot `#light-up` looks for dark spots
+pub const DEFAULT_RADIUS: u8 = 16;
+
+/// the metadata of a torch standing on the floor
+const STANDING: u16 = 5;
+
+/// Put a torch on the floor of `spot`, which the bot has to be able to reach
+pub struct PlaceTorchTask {
+    spot: BlockLocation,
+}
+
+impl PlaceTorchTask {
+    pub fn new(spot: BlockLocation) -> PlaceTorchTask {
+        PlaceTorchTask { spot }
+    }
+}
+
+impl TaskTrait for PlaceTorchTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        // lit up or built over while we were walking there
+        let dark = matches!(global.blocks.night_light(self.spot), Some(light) if light < SPAWN_LIGHT);
+        if !dark || global.blocks.get_block_simple(self.spot) != Some(SimpleType::WalkThrough) {
+            return true;
+        }
+
+        let creative = local.gamemode().creative();
+        if !local.inventory.hold_or_conjure(|kind| kind == BlockKind::TORCH, BlockKind::TORCH, creative, out) {
+            return true;
+        }
+
+        let floor = self.spot.below();
+        local.physics.look_at(floor.faces()[Face::PosY as usize]);
+        if !local.physics.aimed() {
+            return false;
+        }
+
+        out.place_block(floor, Face::PosY);
+        out.swing_arm();
+        if !creative {
+            local.inventory.consume(Hand::Main);
+        }
+        local.stats.placed(BlockKind::TORCH);
+        global.predict(local.bot_id, self.spot, BlockState::from(BlockKind::TORCH.id(), STANDING));
+        true
+    }
+}
+
+/// Torch the dark spots (see [`WorldBlocks::dark_spots`](crate::storage::blocks::WorldBlocks::dark_spots)) within
+/// `radius` of `origin`, the closest to the bot first, until there are none left or the bot runs out of torches.
+/// Spots the bot cannot get to are skipped. Needs the world to keep light.
+pub struct LightUpTask {
+    origin: BlockLocation,
+    radius: u8,
+
+    /// the spots we went to, so one we could not light up is not tried again
+    tried: HashSet<BlockLocation>,
+    lit: usize,
+    current: Option<(BlockLocation, CompoundTask)>,
+}
+
+impl LightUpTask {
+    pub fn new(origin: BlockLocation, radius: u8) -> LightUpTask {
+        LightUpTask {
+            origin,
+            radius,
+            tried: HashSet::new(),
+            lit: 0,
+            current: None,
+        }
+    }
+
+    pub fn save(&self) -> SavedTask {
+        SavedTask::LightUp { origin: self.origin, radius: self.radius }
+    }
+
+    fn report(&self, out: &mut impl InterfaceOut, reason: Option<&str>) {
+        info!(lit = self.lit, ?reason, "done lighting up");
+        let mut message = format!("placed {} torches", self.lit);
+        if let Some(reason) = reason {
+            message = format!("{}, {}", message, reason);
+        }
+        out.send_chat(&message);
+    }
+}
+
+impl TaskTrait for LightUpTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        if let Some((spot, current)) = self.current.as_mut() {
+            if !current.tick(out, local, global) {
+                return false;
+            }
+
+            if let Some(failure) = local.failure.take() {
+                debug!(%failure, %spot, "skipped a dark spot");
+            }
+            if global.blocks.get_block_kind(*spot) == Some(BlockKind::TORCH) {
+                self.lit += 1;
+            }
+            self.current = None;
+        }
+
+        let me = local.physics.location();
+        let tried = &self.tried;
+        let next = global.blocks.dark_spots(self.origin, self.radius).into_iter()
+            .filter(|spot| !tried.contains(spot))
+            .min_by_key(|spot| FloatOrd(spot.center_bottom().dist2(me)));
+
+        let spot = match next {
+            Some(spot) => spot,
+            None => {
+                self.report(out, None);
+                return true;
+            }
+        };
+
+        if !local.gamemode().creative() && !local.inventory.carried().contains_key(&BlockKind::TORCH) {
+            self.report(out, Some("I ran out of torches"));
+            return true;
+        }
+
+        self.tried.insert(spot);
+        let mut task = CompoundTask::default();
+        task.add(BlockReachTask::new(spot.below(), local, global))
+            .add(PlaceTorchTask::new(spot));
+        self.current = Some((spot, task));
+        false
+    }
+
+    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+        if let Some((_, current)) = self.current.as_mut() {
+            current.expensive(end_at, local, global);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::client::recording::Event;
+    use crate::client::sim::{Call, SimulatedClient};
+    use crate::client::tasks::light_up::LightUpTask;
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+    use crate::storage::chunk::{ChunkColumn, ChunkData, HighMemoryChunkSection, Palette, SectionLight};
+
+    /// pitch black chunks with a stone floor at y = 0
+    fn cave() -> WorldBlocks {
+        let mut states = [BlockState::AIR; 4096];
+        states[..256].fill(BlockState::STONE);
+
+        let mut world = WorldBlocks::default();
+        world.keep_light(true);
+        for (x, z) in [(-1, -1), (-1, 0), (0, -1), (0, 0)] {
+            let light = SectionLight::new([0; 2048], Some([0; 2048]));
+            let mut data = ChunkData::<HighMemoryChunkSection>::default();
+            data.sections[0] = Some(Arc::new(HighMemoryChunkSection::new(Palette::from_states(&states)).with_light(Some(light))));
+            world.add_column(ChunkLocation(x, z), ChunkColumn::HighMemory { data });
+        }
+        world
+    }
+
+    fn chat(sim: &SimulatedClient) -> Vec<String> {
+        sim.out().calls.iter()
+            .filter_map(|(_, call)| match call {
+                Call::Chat(message) => Some(message.clone()),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_light_up() {
+        let origin = BlockLocation::new(0, 1, 0);
+        let mut sim = SimulatedClient::new(cave(), BlockLocation::new(-6, 1, -6).center_bottom());
+        sim.give(0, BlockKind::TORCH, 64);
+
+        assert!(!sim.global.blocks.dark_spots(origin, 8).is_empty());
+        sim.schedule(LightUpTask::new(origin, 8));
+        sim.run_task(3000);
+
+        assert!(sim.global.blocks.dark_spots(origin, 8).is_empty());
+        let torches = sim.global.blocks.select(origin, 4, |state| state.kind() == BlockKind::TORCH).count();
+        assert!((4..=25).contains(&torches), "{} torches", torches);
+        assert_eq!(chat(&sim), vec![format!("placed {} torches", torches)]);
+    }
+
+    #[test]
+    fn test_rejected() {
+        let origin = BlockLocation::new(0, 1, 0);
+        let mut sim = SimulatedClient::new(cave(), origin.center_bottom());
+        sim.give(0, BlockKind::TORCH, 64);
+
+        // the server sends back air for every torch
+        for spot in sim.global.blocks.dark_spots(origin, 3) {
+            sim.protect(spot);
+        }
+
+        sim.schedule(LightUpTask::new(origin, 3));
+        sim.run_task(200);
+
+        assert_eq!(sim.global.blocks.select(origin, 4, |state| state.kind() == BlockKind::TORCH).count(), 0);
+        assert!(sim.global.expectations.take_rejected(sim.bot.state.bot_id).is_empty());
+
+        assert!(sim.bot.events.iter().any(|event| matches!(event, Event::ActionRejected { actual: Some(0), .. })));
+        assert!(sim.bot.events.iter().any(|event| matches!(event, Event::TaskFailed { reason, .. } if reason.starts_with("the server did not let me"))));
+    }
+
+    #[test]
+    fn test_out_of_torches() {
+        let origin = BlockLocation::new(0, 1, 0);
+        let mut sim = SimulatedClient::new(cave(), origin.center_bottom());
+        sim.schedule(LightUpTask::new(origin, 8));
+        sim.run_task(10);
+
+        assert_eq!(chat(&sim), vec!["placed 0 torches, I ran out of torches".to_string()]);
+    }
+}
@@ -17,11 +17,13 @@
 
 
 
+use crate::client::estimate::{Plan, TaskEstimate};
 use crate::client::state::global::GlobalState;
 use crate::client::state::local::LocalState;
 use crate::client::tasks::TaskTrait;
 use crate::protocol::{Face, InterfaceOut, Mine};
-use crate::storage::block::{BlockLocation, BlockState};
+use crate::storage::block::{BlockLocation, BlockState, SimpleType};
+use crate::storage::blocks::ChunkLocation;
 
 pub struct MineTask {
     ticks: usize,
@@ -36,12 +38,20 @@ impl MineTask {
 
         let tool = local.inventory.switch_tool(kind, &global.block_data, out);
 
-        let ticks = tool.wait_time(kind, false, true, &global.block_data) + 1;
+        // a lagging server takes longer to break the block. Creative mode breaks it as soon as we start.
+        let ticks = match local.gamemode().creative() {
+            true => 0,
+            false => global.lag_ticks(kind.break_ticks(&tool, true, false, &local.effects, &global.block_data)) + 1,
+        };
+
+        // the server rejects digging a face we cannot see
+        let eye = local.physics.location() + local.physics.eye_height();
+        let face = local.aim.aim(&global.blocks, eye, location, None).map_or(Face::PosY, |aim| aim.face);
 
         Self {
             ticks,
             location,
-            face: Face::PosY,
+            face,
             first: true,
         }
     }
@@ -51,11 +61,44 @@ impl MineTask {
     }
 }
 
+impl Plan for MineTask {
+    fn plan(&self, local: &LocalState, global: &GlobalState) -> TaskEstimate {
+        let mut estimate = TaskEstimate::default();
+        estimate.mine(self.location, local, global);
+        estimate
+    }
+}
+
 impl TaskTrait for MineTask {
     fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
-        let look_loc = self.location.faces()[self.face as usize];
+        // someone else mined it or a piston moved it, so there is nothing left to dig
+        if let Some(change) = global.block_changed(self.location) {
+            if matches!(change.new.simple_type(), SimpleType::WalkThrough | SimpleType::Water) {
+                if !self.first {
+                    out.mine(self.location, Mine::Cancel, self.face);
+                }
+                return true;
+            }
+        }
+
+        // the block might not be what we think it is, wait for the server to resend it
+        if global.blocks.is_stale(ChunkLocation::from(self.location)) {
+            return false;
+        }
+
+        // strict servers check that the ray we look along hits the face we dig
+        let eye = local.physics.location() + local.physics.eye_height();
+        let look_loc = match local.aim.aim(&global.blocks, eye, self.location, Some(self.face)) {
+            Ok(aim) => aim.point,
+            Err(_) => self.location.faces()[self.face as usize],
+        };
         local.physics.look_at(look_loc);
 
+        // wait for the head to turn before digging
+        if self.first && !local.physics.aimed() {
+            return false;
+        }
+
         if self.first {
             out.swing_arm();
             self.first = false;
@@ -66,8 +109,14 @@ impl TaskTrait for MineTask {
 
         out.swing_arm();
         if self.ticks == 0 {
-            out.mine(self.location, Mine::Finished, self.face);
-            global.blocks.set_block(self.location, BlockState::AIR);
+            if !local.gamemode().creative() {
+                out.mine(self.location, Mine::Finished, self.face);
+            }
+            if let Some(kind) = global.blocks.get_block_kind(self.location) {
+                local.stats.mined(kind);
+            }
+            global.predict(local.bot_id, self.location, BlockState::AIR);
+            local.desync.mined(self.location, local.ticks);
             true
         } else {
             self.ticks -= 1;
@@ -75,3 +124,47 @@ impl TaskTrait for MineTask {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::sim::{Call, SimulatedClient};
+    use crate::client::tasks::mine::MineTask;
+    use crate::protocol::Mine;
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::types::GameMode;
+
+    /// mine the stone next to the bot by hand. Returns the ticks it took and the digs sent.
+    fn mine(gamemode: GameMode) -> (usize, Vec<Mine>) {
+        let mut sim = SimulatedClient::new(WorldBlocks::flat(), BlockLocation::new(0, 1, 0).center_bottom());
+        let location = BlockLocation::new(1, 1, 0);
+        sim.global.blocks.set_block(location, BlockState::STONE);
+        sim.local().set_gamemode(gamemode);
+
+        let task = MineTask::new(location, &mut sim.bot.out, &mut sim.bot.state, &sim.global);
+        sim.schedule(task);
+        let ticks = sim.run_task(400);
+        assert_eq!(sim.global.blocks.get_block_exact(location), Some(BlockState::AIR));
+
+        let digs = sim.out().calls.iter()
+            .filter_map(|(_, call)| match call {
+                Call::Mine { mine, .. } => Some(*mine),
+                _ => None
+            })
+            .collect();
+        (ticks, digs)
+    }
+
+    #[test]
+    fn test_creative() {
+        // stone takes 7.5 seconds by hand
+        let (ticks, digs) = mine(GameMode::Survival);
+        assert!(ticks > 150, "{} ticks", ticks);
+        assert_eq!(digs, vec![Mine::Start, Mine::Finished]);
+
+        // and no time at all in creative mode, where starting to dig breaks it
+        let (ticks, digs) = mine(GameMode::Creative);
+        assert!(ticks < 20, "{} ticks", ticks);
+        assert_eq!(digs, vec![Mine::Start]);
+    }
+}
@@ -15,6 +15,7 @@
  */
 
 use crate::client::state::global::GlobalState;
+use crate::client::state::global::mine_alloc::MineAlloc;
 use crate::client::state::local::LocalState;
 use crate::client::tasks::compound::CompoundTask;
 use crate::client::tasks::delay::DelayTask;
@@ -26,27 +27,46 @@ use crate::client::tasks::mine_layer::MineLayer;
 use crate::client::tasks::stream::TaskStream;
 use crate::client::tasks::Task;
 use crate::protocol::InterfaceOut;
-use crate::storage::block::BlockLocation;
+use crate::storage::block::{BlockLocation, BlockLocation2D};
+use crate::storage::blocks::diff::DiffReport;
 
-pub struct MineColumn;
+#[derive(Default)]
+pub struct MineColumn {
+    /// how many times the column was gone over again because blocks were left
+    retries: u32,
+}
 
 impl MineColumn {
     pub const MIN_MINE_LOC: i16 = 11;
+
+    /// how many times to go back up for blocks which were left (i.e., missed or placed back by a player)
+    const MAX_RETRIES: u32 = 2;
+
+    /// the blocks of the region the bot is in which should be mined but are not
+    pub fn leftover(local: &LocalState, global: &GlobalState) -> DiffReport {
+        let center = BlockLocation2D::from(BlockLocation::from(local.physics.location()));
+        let locations = MineAlloc::locations(center).filter(|location| location.y >= Self::MIN_MINE_LOC);
+        global.blocks.diff_clear(locations, |state| !state.kind().mineable(&global.block_data))
+    }
 }
 
 impl TaskStream for MineColumn {
-    fn poll(&mut self, _out: &mut impl InterfaceOut, local: &mut LocalState, _global: &mut GlobalState) -> Option<Task> {
+    fn poll(&mut self, _out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> Option<Task> {
         let mine_loc = BlockLocation::from(local.physics.location()).below();
         if mine_loc.y >= Self::MIN_MINE_LOC {
             let mut compound = CompoundTask::default();
 
             compound
-                .add(LazyStream::from(MineLayer))
+                .add(LazyStream::from(MineLayer::default()))
                 .add(DelayTask(5))
                 .add(FallBucketTask::default())
                 .add(LazyTask::from(GoMineTop));
 
             Some(compound.into())
+        } else if self.retries < Self::MAX_RETRIES && !Self::leftover(local, global).is_complete() {
+            self.retries += 1;
+            info!(retry = self.retries, "blocks left in the column, going back up");
+            Some(LazyTask::from(GoMineTop).into())
         } else {
             None
         }
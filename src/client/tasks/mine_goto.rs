@@ -25,9 +25,10 @@ use crate::storage::block::BlockLocation;
 
 pub struct GoMineTop;
 
-impl Lazy for GoMineTop {
-    fn create(&self, local: &mut LocalState, global: &GlobalState) -> Task {
-        let BlockLocation { x, y, z } = local.physics.location().into();
+impl GoMineTop {
+    /// the highest mineable block in the region around `location` which is not below it
+    pub fn highest(location: BlockLocation, global: &GlobalState) -> i16 {
+        let BlockLocation { x, y, z } = location;
         let mut highest_y = y - 1;
 
         for on_y in y..256 {
@@ -43,6 +44,14 @@ impl Lazy for GoMineTop {
             }
         }
 
+        highest_y
+    }
+}
+
+impl Lazy for GoMineTop {
+    fn create(&self, local: &mut LocalState, global: &GlobalState) -> Task {
+        let highest_y = Self::highest(local.physics.location().into(), global);
+
         // println!("highest y {}", highest_y);
 
         PillarAndMineTask::pillar_and_mine(highest_y as u32).into()
@@ -17,22 +17,76 @@
 use float_ord::FloatOrd;
 
 use crate::client::state::global::GlobalState;
+use crate::client::state::local::inventory::FullPolicy;
 use crate::client::state::local::LocalState;
+use crate::client::tasks::compound::CompoundTask;
+use crate::client::tasks::deposit::DepositTask;
 use crate::client::tasks::lazy_stream::LazyStream;
 use crate::client::tasks::mine::MineTask;
+use crate::client::tasks::navigate::{BlockReachTask, BlockTravelTask};
 use crate::client::tasks::stream::TaskStream;
 use crate::client::tasks::Task;
 use crate::protocol::InterfaceOut;
-use crate::storage::block::BlockLocation;
+use crate::storage::block::{BlockKind, BlockLocation};
 
 pub type MineLayerTask = LazyStream<MineLayer>;
 
-pub struct MineLayer;
+/// how close an item has to be for the server to give it to us
+const PICKUP_REACH: f64 = 1.5;
+
+#[derive(Default)]
+pub struct MineLayer {
+    /// whether we went to the chest for this layer already. If we still need room after that (i.e., the chest is
+    /// full) we drop items instead.
+    deposited: bool,
+}
+
+impl MineLayer {
+    /// Make room if the inventory is running out of it or a drop next to us is not picked up: drop a stack (see
+    /// [`PlayerInventory::make_room`](crate::client::state::local::inventory::PlayerInventory::make_room)) or go put
+    /// what we mined in the chest at the deposit waypoint and come back.
+    fn make_room(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &GlobalState) -> Option<Task> {
+        let location = local.physics.location();
+        let inventory = &local.inventory;
+        let missed = inventory.is_full() && global.entities.items_near(location, PICKUP_REACH).next().is_some();
+        if !missed && !inventory.needs_room() {
+            return None;
+        }
+
+        if let (FullPolicy::Deposit(waypoint), false) = (&inventory.policy.full, self.deposited) {
+            let kinds: Vec<_> = inventory.carried().into_iter()
+                .map(|(kind, _)| kind)
+                .filter(|&kind| inventory.policy.is_loot(kind))
+                .collect();
+
+            // a deposit without kinds would empty the whole inventory
+            match global.waypoints.resolve(waypoint, local.dimension) {
+                Ok(chest) if !kinds.is_empty() => {
+                    self.deposited = true;
+                    let mut compound = CompoundTask::default();
+                    compound.add(BlockReachTask::new(chest, local, global))
+                        .add(DepositTask::new(chest, kinds, Some(BlockKind::COBBLESTONE)))
+                        .add(BlockTravelTask::new(BlockLocation::from(location), local));
+                    return Some(compound.into());
+                }
+                Ok(_) => {}
+                Err(error) => warn!(%error, "cannot deposit, dropping items instead"),
+            }
+        }
+
+        local.inventory.make_room(out);
+        None
+    }
+}
 
 impl TaskStream for MineLayer {
     fn poll(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> Option<Task> {
         const RADIUS: u8 = 3;
 
+        if let Some(deposit) = self.make_room(out, local, global) {
+            return Some(deposit);
+        }
+
         let origin_loc = BlockLocation::from(local.physics.location()).below();
 
         let block_to_mine = global.blocks
@@ -15,22 +15,78 @@
  */
 
 
+use crate::client::estimate::{PILLAR_TICKS, Plan, Risk, TaskEstimate};
 use crate::client::pathfind::implementations::novehicle::TravelProblem;
 use crate::client::state::global::GlobalState;
+use crate::client::state::global::mine_alloc::MineAlloc;
 use crate::client::state::local::LocalState;
 use crate::client::tasks::Task;
 use crate::client::tasks::compound::CompoundTask;
 use crate::client::tasks::lazy::LazyTask;
+use crate::client::tasks::mine_column::MineColumn;
+use crate::client::tasks::mine_goto::GoMineTop;
 use crate::client::tasks::navigate::NavigateProblem;
 use crate::client::tasks::safe_mine_coord::SafeMineRegion;
 use crate::client::tasks::stream::TaskStream;
 use crate::protocol::InterfaceOut;
+use crate::storage::block::{BlockKind, BlockLocation, BlockLocation2D};
+use crate::types::Location;
 
 pub struct MineRegion;
 
+impl MineRegion {
+    /// the delay after every layer of [`MineColumn`]
+    const LAYER_DELAY: usize = 5;
+
+    /// Plan clearing `regions` one after the other as [`SafeMineRegion`] would: walk to the center, pillar up to the
+    /// highest block and mine layer by layer down to [`MineColumn::MIN_MINE_LOC`]. Regions with fluids are skipped.
+    pub fn plan_regions(regions: impl Iterator<Item=BlockLocation2D>, local: &LocalState, global: &GlobalState) -> TaskEstimate {
+        let mut estimate = TaskEstimate::default();
+        let mut at = local.physics.location();
+
+        for center in regions {
+            let goal = Location::new(center.x as f64 + 0.5, at.y, center.z as f64 + 0.5);
+            estimate.travel(at, goal, local.physics.can_sprint(), global);
+            at = goal;
+
+            if let Some(location) = SafeMineRegion::fluid(center, global) {
+                let lava = matches!(global.blocks.get_block_kind(location).map(|kind| kind.id()), Some(10..=11));
+                estimate.risk(Risk::Fluid { location, lava });
+                continue;
+            }
+
+            let standing = BlockLocation::from(at);
+            let highest = GoMineTop::highest(standing, global);
+
+            // the pillar is in the region so it is mined again
+            let pillar = (highest + 1 - standing.y).max(0) as usize;
+            estimate.place(BlockKind::COBBLESTONE, pillar, pillar * PILLAR_TICKS, local);
+            estimate.jump(pillar);
+
+            for location in MineAlloc::locations(center).filter(|loc| (MineColumn::MIN_MINE_LOC..=highest).contains(&loc.y)) {
+                estimate.mine(location, local, global);
+            }
+            *estimate.produced.entry(BlockKind::COBBLESTONE.id()).or_default() += pillar;
+
+            let layers = (highest - MineColumn::MIN_MINE_LOC + 1).max(0) as usize;
+            estimate.wait(layers * Self::LAYER_DELAY);
+
+            at.y = MineColumn::MIN_MINE_LOC as f64;
+        }
+
+        estimate
+    }
+}
+
+impl Plan for MineRegion {
+    fn plan(&self, local: &LocalState, global: &GlobalState) -> TaskEstimate {
+        Self::plan_regions(global.mine.regions(), local, global)
+    }
+}
+
 impl TaskStream for MineRegion {
     fn poll(&mut self, _out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> Option<Task> {
-        let goal = global.mine.obtain_region()?;
+        let goal = global.mine.obtain_region(local.bot_id)?;
         let start = local.physics.location();
 
         let mut compound = CompoundTask::default();
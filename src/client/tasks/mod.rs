@@ -13,11 +13,13 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
+use std::fmt::{Display, Formatter};
 use std::time::Instant;
 
 use bridge::*;
 use center::CenterTask;
 use compound::*;
+use custom::*;
 use delay::*;
 use eat::*;
 use fall_bucket::*;
@@ -31,14 +33,33 @@ use navigate::*;
 use pillar::*;
 use pillar_and_mine::*;
 use hit_entity::*;
+use routine::*;
+use hold::*;
+use combat::*;
+use refresh::*;
+use deposit::*;
+use farm::*;
+use flight::*;
+use fly::*;
+use follow_leader::*;
+use guard::GuardTask;
+use light_up::*;
+use read_signs::*;
+use recover::RecoverItemsTask;
+use set_spawn::SetSpawnTask;
+use travel::TravelTask;
+use write_book::*;
 
+use crate::client::snapshot::SavedTask;
 use crate::client::state::global::GlobalState;
 use crate::client::state::local::LocalState;
+use crate::client::state::local::spawn::BedError;
 use crate::client::tasks::lazy_stream::LazyStream;
 use crate::client::tasks::navigate::{BlockTravelTask, ChunkTravelTask};
 use crate::client::tasks::safe_mine_coord::SafeMineRegion;
 use crate::protocol::InterfaceOut;
 use crate::client::tasks::attack_entity::AttackEntity;
+use crate::storage::block::BlockLocation;
 
 pub mod delay;
 pub mod compound;
@@ -60,6 +81,23 @@ pub mod safe_mine_coord;
 pub mod bridge;
 pub mod fall_bucket;
 pub mod center;
+pub mod routine;
+pub mod hold;
+pub mod combat;
+pub mod refresh;
+pub mod deposit;
+pub mod farm;
+pub mod fly;
+pub mod flight;
+pub mod follow_leader;
+pub mod guard;
+pub mod light_up;
+pub mod read_signs;
+pub mod recover;
+pub mod set_spawn;
+pub mod travel;
+pub mod write_book;
+pub mod custom;
 
 #[enum_dispatch]
 pub trait TaskTrait {
@@ -74,6 +112,38 @@ pub trait TaskTrait {
     fn expensive(&mut self, _end_by: Instant, _local: &mut LocalState, _global: &GlobalState) {}
 }
 
+/// Why a task gave up before it was done. A task sets [`LocalState::failure`] and returns true, which ends the task it
+/// is part of as well.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TaskError {
+    /// the bot could not move along its path no matter what it tried
+    Stuck { location: BlockLocation },
+
+    /// there is nowhere to stand to reach the block
+    Unreachable { location: BlockLocation },
+
+    /// using the bed did not set the spawn point
+    Bed { bed: BlockLocation, error: BedError },
+
+    /// every way there goes through chunks which cannot be crossed
+    NoRoute { goal: BlockLocation },
+
+    /// the server did not let us mine or place the block
+    Rejected { location: BlockLocation },
+}
+
+impl Display for TaskError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskError::Stuck { location } => write!(f, "stuck at {}", location),
+            TaskError::Unreachable { location } => write!(f, "nowhere to stand to reach {}", location),
+            TaskError::Bed { bed, error } => write!(f, "could not use the bed at {}: {}", bed, error),
+            TaskError::NoRoute { goal } => write!(f, "no way to get to {}", goal),
+            TaskError::Rejected { location } => write!(f, "the server did not let me change {}", location),
+        }
+    }
+}
+
 pub type GoMineTopTask = LazyTask<GoMineTop>;
 pub type MineRegionTask = LazyStream<MineRegion>;
 pub type SafeMineRegionTask = LazyTask<SafeMineRegion>;
@@ -95,10 +165,93 @@ pub enum Task {
     MineTask,
     BlockTravelNearTask,
     BlockTravelTask,
+    BlockReachTask,
     ChunkTravelTask,
     PillarTask,
     DelayTask,
     PillarAndMineTask,
     MineLayerTask,
     FallBucketTask,
+    RoutineTask,
+    HoldTask,
+    CombatTask,
+    RefreshTask,
+    DepositTask,
+    HarvestTask,
+    FarmTask,
+    FlyTask,
+    FlightTask,
+    FollowLeaderTask,
+    ReadSignsTask,
+    WriteBookTask,
+    RecoverItemsTask,
+    SetSpawnTask,
+    TravelTask,
+    PlaceTorchTask,
+    LightUpTask,
+    GuardTask,
+    CustomTask,
+}
+
+impl Task {
+    /// the name of the task variant. Used for logging and recordings.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Task::CompoundTask(_) => "CompoundTask",
+            Task::AttackEntityTask(_) => "AttackEntityTask",
+            Task::HitEntityTask(_) => "HitEntityTask",
+            Task::EatTask(_) => "EatTask",
+            Task::MineRegionTask(_) => "MineRegionTask",
+            Task::SafeMineRegionTask(_) => "SafeMineRegionTask",
+            Task::CenterTask(_) => "CenterTask",
+            Task::BridgeTask(_) => "BridgeTask",
+            Task::GoMineTopTask(_) => "GoMineTopTask",
+            Task::MineColumnTask(_) => "MineColumnTask",
+            Task::MineTask(_) => "MineTask",
+            Task::BlockTravelNearTask(_) => "BlockTravelNearTask",
+            Task::BlockTravelTask(_) => "BlockTravelTask",
+            Task::BlockReachTask(_) => "BlockReachTask",
+            Task::ChunkTravelTask(_) => "ChunkTravelTask",
+            Task::PillarTask(_) => "PillarTask",
+            Task::DelayTask(_) => "DelayTask",
+            Task::PillarAndMineTask(_) => "PillarAndMineTask",
+            Task::MineLayerTask(_) => "MineLayerTask",
+            Task::FallBucketTask(_) => "FallBucketTask",
+            Task::RoutineTask(_) => "RoutineTask",
+            Task::HoldTask(_) => "HoldTask",
+            Task::CombatTask(_) => "CombatTask",
+            Task::RefreshTask(_) => "RefreshTask",
+            Task::DepositTask(_) => "DepositTask",
+            Task::HarvestTask(_) => "HarvestTask",
+            Task::FarmTask(_) => "FarmTask",
+            Task::FlyTask(_) => "FlyTask",
+            Task::FlightTask(_) => "FlightTask",
+            Task::FollowLeaderTask(_) => "FollowLeaderTask",
+            Task::ReadSignsTask(_) => "ReadSignsTask",
+            Task::WriteBookTask(_) => "WriteBookTask",
+            Task::RecoverItemsTask(_) => "RecoverItemsTask",
+            Task::SetSpawnTask(_) => "SetSpawnTask",
+            Task::TravelTask(_) => "TravelTask",
+            Task::PlaceTorchTask(_) => "PlaceTorchTask",
+            Task::LightUpTask(_) => "LightUpTask",
+            Task::GuardTask(_) => "GuardTask",
+            Task::CustomTask(task) => task.name(),
+        }
+    }
+
+    /// What to keep of the task to carry on with it after a restart. None if it cannot be resumed.
+    pub fn save(&self, local: &LocalState) -> Option<SavedTask> {
+        match self {
+            Task::BlockTravelTask(task) => task.save(),
+            Task::TravelTask(task) => Some(task.save()),
+            Task::MineRegionTask(_) => Some(SavedTask::Mine),
+            Task::RoutineTask(task) => Some(task.save()),
+            Task::FarmTask(task) => Some(task.save()),
+            Task::LightUpTask(task) => Some(task.save()),
+            Task::GuardTask(task) => Some(task.save()),
+            Task::SetSpawnTask(task) => Some(task.save()),
+            Task::RecoverItemsTask(task) => task.save(local),
+            _ => None,
+        }
+    }
 }
@@ -16,28 +16,49 @@
 
 use std::time::Instant;
 
+use rand::Rng;
+
+use crate::client::afk::Area;
+use crate::client::estimate::{Plan, TaskEstimate};
 use crate::client::follow::{Follower, FollowResult};
+use crate::client::follow::stuck::{JITTER_TICKS, Recovery, StuckDetector};
 use crate::client::pathfind::context::MoveNode;
 use crate::client::pathfind::implementations::{PlayerProblem, Problem};
-use crate::client::pathfind::implementations::novehicle::{BlockGoalCheck, BlockHeuristic, BlockNearGoalCheck, CenterChunkGoalCheck, ChunkHeuristic, TravelProblem};
+use crate::client::pathfind::implementations::novehicle::{AnyOfGoalCheck, BlockGoalCheck, BlockHeuristic, BlockNearGoalCheck, CenterChunkGoalCheck, ChunkHeuristic, TravelProblem};
+use crate::client::pathfind::reach::reach_positions;
 use crate::client::pathfind::traits::{GoalCheck, Heuristic};
+use crate::client::physics::Strafe;
+use crate::client::snapshot::SavedTask;
 use crate::client::state::global::GlobalState;
+use crate::client::state::global::reservations::HORIZON;
 use crate::client::state::local::LocalState;
-use crate::client::tasks::TaskTrait;
+use crate::client::tasks::fall_bucket::FallBucketTask;
+use crate::client::tasks::flight::FlightTask;
+use crate::client::tasks::{TaskError, TaskTrait};
 use crate::client::timing::Increment;
 use crate::protocol::InterfaceOut;
 use crate::storage::block::BlockLocation;
 use crate::storage::blocks::ChunkLocation;
+use crate::storage::blocks::raycast::REACH;
+use crate::types::Location;
+
+/// how long to wait for other bots before looking for a way around them
+const MAX_WAIT_TICKS: usize = 20 * 5;
 
 pub type ChunkTravelTask = NavigateProblem<ChunkHeuristic, CenterChunkGoalCheck>;
 pub type BlockTravelTask = NavigateProblem<BlockHeuristic, BlockGoalCheck>;
 pub type BlockTravelNearTask = NavigateProblem<BlockHeuristic, BlockNearGoalCheck>;
+pub type BlockReachTask = NavigateProblem<BlockHeuristic, AnyOfGoalCheck>;
 
 impl ChunkTravelTask {
     pub fn new(goal: ChunkLocation, local: &LocalState) -> Self {
         let start = local.physics.location().into();
         let problem = TravelProblem::navigate_center_chunk(start, goal);
-        problem.into()
+        let mut task = Self::from(problem);
+
+        let center = BlockLocation::new((goal.0 << 4) + 8, start.y, (goal.1 << 4) + 8);
+        task.goal = Some(center.center_bottom());
+        task
     }
 }
 
@@ -45,42 +66,136 @@ impl BlockTravelTask {
     pub fn new(goal: BlockLocation, local: &LocalState) -> Self {
         let start = local.physics.location().into();
         let problem = TravelProblem::navigate_block(start, goal);
-        problem.into()
+        let mut task = Self::from(problem);
+        task.goal = Some(goal.center_bottom());
+        task
+    }
+
+    pub fn save(&self) -> Option<SavedTask> {
+        let goal = BlockLocation::from(self.goal?);
+        Some(SavedTask::Goto { goal, fly: self.prefer_flying })
+    }
+}
+
+impl BlockReachTask {
+    /// Go somewhere `target` can be reached from (see [`reach_positions`]). Fails on the first tick if there is no
+    /// such place.
+    pub fn new(target: BlockLocation, local: &LocalState, global: &GlobalState) -> Self {
+        let start = local.physics.location().into();
+        let positions: Vec<_> = reach_positions(target, &global.blocks, REACH).map(|(feet, _)| feet).collect();
+        let closest = positions.first().copied();
+
+        let problem = TravelProblem::navigate_any_of(start, target, positions);
+        let mut task = Self::from(problem);
+        task.goal = closest.map(|feet| feet.center_bottom());
+        if closest.is_none() {
+            task.failure = Some(TaskError::Unreachable { location: target });
+        }
+        task
     }
 }
 
 pub struct NavigateProblem<H: Heuristic, G: GoalCheck> {
     calculate: bool,
+
+    /// whether the path was started from where we are. A queued task was created somewhere else.
+    started: bool,
+
     problem: Box<PlayerProblem<H, G>>,
     follower: Option<Follower>,
+
+    /// breaking a fall, either planned or not
+    fall: Option<FallBucketTask>,
+
+    /// where the task ends up. Only used for planning.
+    goal: Option<Location>,
+
+    /// whether following the path goes anywhere
+    stuck: StuckDetector,
+
+    /// the ticks left jumping and strafing to `side` to get loose
+    jitter: usize,
+    side: Strafe,
+
+    /// the task cannot be done at all, reported on the first tick
+    failure: Option<TaskError>,
+
+    /// the ticks we have been waiting for other bots to get out of the way
+    waited: usize,
+
+    /// fly to the goal instead if the server lets us, decided on the first tick
+    prefer_flying: bool,
+    flight: Option<FlightTask>,
+}
+
+impl<H: Heuristic + Send + Sync, G: GoalCheck + Send + Sync> NavigateProblem<H, G> {
+    /// avoid jumping or falling in `area`, e.g., so farmland is not trampled
+    pub fn no_jump(mut self, area: Area) -> Self {
+        self.problem.set_no_jump(area);
+        self
+    }
+
+    /// Fly to the goal if the server lets us and there is a way through the air (see [`FlightTask`]). We walk from
+    /// wherever the flight ended.
+    pub fn prefer_flying(mut self) -> Self {
+        self.prefer_flying = true;
+        self
+    }
+
+    /// whether we are flying or about to, so there is no point finding a path on the ground yet
+    fn flying(&self, local: &LocalState) -> bool {
+        self.flight.is_some() || self.prefer_flying && local.abilities.may_fly && self.goal.is_some()
+    }
 }
 
 impl<H: Heuristic, G: GoalCheck> From<PlayerProblem<H, G>> for NavigateProblem<H, G> {
     fn from(problem: PlayerProblem<H, G>) -> Self {
         Self {
             calculate: true,
+            started: false,
             problem: box problem,
             follower: None,
+            fall: None,
+            goal: None,
+            stuck: StuckDetector::default(),
+            jitter: 0,
+            side: Strafe::Left,
+            failure: None,
+            waited: 0,
+            prefer_flying: false,
+            flight: None,
         }
     }
 }
 
-impl<H: Heuristic + Send + Sync, G: GoalCheck + Send + Sync> TaskTrait for NavigateProblem<H, G> {
-    fn tick(&mut self, _out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+impl<H: Heuristic, G: GoalCheck> Plan for NavigateProblem<H, G> {
+    /// the path is not known until it is calculated, so this assumes a straight line
+    fn plan(&self, local: &LocalState, global: &GlobalState) -> TaskEstimate {
+        let mut estimate = TaskEstimate::default();
+        if let Some(goal) = self.goal {
+            estimate.travel(local.physics.location(), goal, local.physics.can_sprint(), global);
+        }
+        estimate
+    }
+}
+
+impl<H: Heuristic + Send + Sync, G: GoalCheck + Send + Sync> NavigateProblem<H, G> {
+    /// true if the end of the path was reached
+    fn follow(&mut self, local: &mut LocalState, global: &mut GlobalState) -> bool {
         let follower = match self.follower.as_mut() {
             None => return false,
             Some(inner) => inner
         };
 
         if follower.should_recalc() {
-            println!("recalc");
+            debug!("recalculating the path");
             self.problem.recalc(MoveNode::simple(local.physics.location().into()));
             self.calculate = true;
         }
 
         match follower.follow(local, global) {
             FollowResult::Failed => {
-                println!("failed");
+                debug!("could not follow the path");
                 self.follower = None;
                 self.problem.recalc(MoveNode::simple(local.physics.location().into()));
                 self.calculate = true;
@@ -88,17 +203,179 @@ impl<H: Heuristic + Send + Sync, G: GoalCheck + Send + Sync> TaskTrait for Navig
             }
             FollowResult::InProgress => false,
             FollowResult::Finished => {
-                println!("finished!");
+                debug!("reached the end of the path");
                 true
             }
         }
     }
 
+    /// Whether to wait for another bot in the way (see [`Reservations`](crate::client::state::global::reservations::Reservations)).
+    /// Waiting too long finds a path around the blocks ahead.
+    fn wait(&mut self, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        let at = BlockLocation::from(local.physics.location());
+        let ahead: Vec<_> = self.follower.as_ref()
+            .map(|follower| follower.points().iter().take(HORIZON).map(|&point| BlockLocation::from(point)).collect())
+            .unwrap_or_default();
+
+        let blocker = match global.reservations.claim(local.bot_id, at, &ahead, global.ticks) {
+            None => {
+                self.waited = 0;
+                return false;
+            }
+            Some(blocker) => blocker
+        };
+
+        self.waited += 1;
+        if self.waited < MAX_WAIT_TICKS {
+            return true;
+        }
+
+        debug!(%at, blocker, "waited too long for another bot, finding another path");
+        let config = &global.config.path.stuck;
+        for block in ahead.into_iter().filter(|&block| block != at) {
+            local.penalties.add(block, config.penalty, local.ticks, config.penalty_ticks);
+        }
+
+        self.waited = 0;
+        self.follower = None;
+        self.problem.recalc(MoveNode::simple(at));
+        self.calculate = true;
+        false
+    }
+
+    /// Get loose if following the path goes nowhere: jump around, then path around the block we cannot get to and
+    /// at last give up. Returns true if we gave up.
+    fn unstick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &GlobalState) -> bool {
+        let follower = match self.follower.as_ref() {
+            None => {
+                self.stuck.pause();
+                return false;
+            }
+            Some(inner) => inner
+        };
+
+        if self.jitter > 0 {
+            self.jitter -= 1;
+            local.physics.jump();
+            local.physics.strafe(self.side);
+        }
+
+        let config = &global.config.path.stuck;
+        let location = local.physics.location();
+
+        match self.stuck.check(location, config) {
+            None => false,
+            Some(Recovery::Jitter) => {
+                debug!(%location, "stuck, jumping around");
+                self.jitter = JITTER_TICKS;
+                self.side = if local.rng.gen() { Strafe::Left } else { Strafe::Right };
+                false
+            }
+            Some(Recovery::Reroute) => {
+                let blocked = follower.points().front().map_or_else(|| BlockLocation::from(location), |&next| BlockLocation::from(next));
+                debug!(%location, %blocked, "stuck, finding another path");
+
+                local.penalties.add(blocked, config.penalty, local.ticks, config.penalty_ticks);
+                self.follower = None;
+                self.problem.recalc(MoveNode::simple(location.into()));
+                self.calculate = true;
+                false
+            }
+            Some(Recovery::Abort) => {
+                let location = BlockLocation::from(location);
+                warn!(%location, "stuck, giving up");
+                out.send_chat(&format!("I am stuck at {}", location));
+                local.failure = Some(TaskError::Stuck { location });
+                true
+            }
+        }
+    }
+}
+
+impl<H: Heuristic + Send + Sync, G: GoalCheck + Send + Sync> TaskTrait for NavigateProblem<H, G> {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        if let Some(failure) = self.failure.take() {
+            local.failure = Some(failure);
+            return true;
+        }
+
+        if self.prefer_flying {
+            if self.flying(local) {
+                self.flight = self.goal.map(FlightTask::new);
+            }
+            self.prefer_flying = false;
+        }
+
+        if let Some(flight) = self.flight.as_mut() {
+            if !flight.tick(out, local, global) {
+                return false;
+            }
+
+            if let Some(failure) = local.failure.take() {
+                debug!(%failure, "could not fly, walking instead");
+            }
+
+            // walk the rest from wherever we came down
+            self.flight = None;
+            self.follower = None;
+            self.started = false;
+            self.calculate = true;
+        }
+
+        let airborne = !local.physics.on_ground();
+
+        // a planned drop needs the bucket as soon as we leave the ground, an unplanned one once we are falling too
+        // far. There is only ever one bucket task so the water is never placed twice.
+        let planned = self.follower.as_ref().map_or(false, Follower::mlg);
+        if self.fall.is_none() && airborne && (planned || FallBucketTask::needed(local, global)) {
+            self.fall = Some(FallBucketTask::default());
+        }
+
+        // a fall cannot be stopped to let someone by
+        if self.fall.is_none() && self.wait(local, global) {
+            self.stuck.pause();
+            return false;
+        }
+
+        // drifting sideways while falling could miss the water
+        let finished = if self.fall.is_some() && airborne {
+            false
+        } else {
+            self.follow(local, global)
+        };
+
+        // standing still to pick the water back up is not being stuck
+        if self.fall.is_some() {
+            self.stuck.pause();
+        } else if !finished && self.unstick(out, local, global) {
+            return true;
+        }
+
+        // after following so the bucket decides where we look
+        if let Some(fall) = self.fall.as_mut() {
+            if fall.tick(out, local, global) {
+                self.fall = None;
+            }
+        }
+
+        // the water has to be picked back up before we are done
+        let done = finished && self.fall.is_none();
+        if done {
+            global.reservations.release(local.bot_id);
+        }
+        done
+    }
+
     fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
-        if !self.calculate {
+        if !self.calculate || self.failure.is_some() || self.flying(local) {
             return;
         }
 
+        if !self.started {
+            self.started = true;
+            self.problem.recalc(MoveNode::simple(local.physics.location().into()));
+        }
+
         let res = self.problem.iterate_until(end_at, local, global);
         match res {
             Increment::Finished(res) => {
@@ -114,3 +391,207 @@ impl<H: Heuristic + Send + Sync, G: GoalCheck + Send + Sync> TaskTrait for Navig
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::client::pathfind::reach::reach_positions;
+    use crate::client::physics::SPRINT_SPEED;
+    use crate::client::sim::{SimulatedClient, SimulatedSwarm};
+    use crate::client::state::global::GlobalState;
+    use crate::client::state::local::inventory::ItemStack;
+    use crate::client::state::local::LocalState;
+    use crate::client::tasks::navigate::{BlockReachTask, BlockTravelTask};
+    use crate::client::tasks::TaskTrait;
+    use crate::protocol::v340::Interface340;
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState, SimpleType};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::storage::blocks::raycast::REACH;
+
+    /// a 30 block drop is only possible by placing water before landing
+    #[test]
+    fn test_cliff() {
+        let mut world = WorldBlocks::flat();
+        for x in -3..=2 {
+            for z in -3..=3 {
+                for y in 1..=30 {
+                    world.set_block(BlockLocation::new(x, y, z), BlockState::STONE);
+                }
+            }
+        }
+
+        let mut global = GlobalState::init();
+        global.blocks = world;
+
+        let mut local = LocalState::mock();
+        local.inventory.add(36, ItemStack::new(BlockKind::WATER_BUCKET, 1, 0, None));
+        local.physics.teleport(BlockLocation::new(0, 31, 0).center_bottom());
+
+        let (mut out, _rx) = Interface340::test();
+        let goal = BlockLocation::new(5, 1, 0);
+        let mut task = BlockTravelTask::new(goal, &local);
+
+        let mut ticks = 0;
+        loop {
+            task.expensive(Instant::now() + Duration::from_millis(50), &mut local, &global);
+            if task.tick(&mut out, &mut local, &mut global) {
+                break;
+            }
+
+            let actions = local.physics.tick(&mut global.blocks, &local.inventory);
+            assert_eq!(actions.fall_damage, 0.0, "hurt landing at {}", local.physics.location());
+
+            ticks += 1;
+            assert!(ticks < 20 * 60, "never got down");
+        }
+
+        assert!(local.physics.location().dist2(goal.center_bottom()) < 1.0);
+
+        // the water was picked back up
+        for x in 3..=12 {
+            for z in -2..=2 {
+                assert_ne!(global.blocks.get_block_simple(BlockLocation::new(x, 1, z)), Some(SimpleType::Water));
+            }
+        }
+    }
+
+    /// a block placed in the way after the path was found gets the bot stuck until it goes around
+    #[test]
+    fn test_blocked_corridor() {
+        let mut world = WorldBlocks::flat();
+
+        // a wall with a passage straight ahead and one off to the side
+        for z in -20..=20 {
+            for y in 1..=3 {
+                let passage = (z == 0 || z == 3) && y < 3;
+                if !passage {
+                    world.set_block(BlockLocation::new(5, y, z), BlockState::STONE);
+                }
+            }
+        }
+
+        let mut sim = SimulatedClient::new(world, BlockLocation::new(0, 1, 0).center_bottom());
+        let goal = BlockLocation::new(10, 1, 0);
+        let task = BlockTravelTask::new(goal, sim.local());
+        sim.schedule(task);
+
+        // the path goes through the passage straight ahead, which is then blocked
+        sim.run_ticks(3);
+        sim.global.blocks.set_block(BlockLocation::new(5, 1, 0), BlockState::STONE);
+        sim.global.blocks.set_block(BlockLocation::new(5, 2, 0), BlockState::STONE);
+
+        sim.run_task(20 * 30);
+
+        let location = sim.local().physics.location();
+        assert!(location.dist2(goal.center_bottom()) < 1.0, "ended at {}", location);
+
+        let side_passage = sim.positions.iter().any(|at| (4.5..5.5).contains(&at.x) && (3.0..4.0).contains(&at.z));
+        assert!(side_passage);
+
+        assert!(!sim.local().penalties.is_empty());
+        assert_eq!(sim.local().last_failure, None);
+    }
+
+    /// a long straight path is walked at sprinting speed the whole way instead of steering for every block
+    #[test]
+    fn test_sprint_speed() {
+        let mut world = WorldBlocks::default();
+        for x in -5..=205 {
+            for z in -5..=5 {
+                world.set_block(BlockLocation::new(x, 0, z), BlockState::STONE);
+            }
+        }
+
+        let start = BlockLocation::new(0, 1, 0).center_bottom();
+        let mut sim = SimulatedClient::new(world, start);
+        let task = BlockTravelTask::new(BlockLocation::new(200, 1, 0), sim.local());
+        sim.schedule(task);
+        sim.run_task(20 * 60);
+
+        // finding the path is not part of walking it
+        let moved = sim.positions.iter().position(|&at| at != start).unwrap();
+        let ticks = (sim.positions.len() - moved) as f64;
+        let ideal = 200.0 / SPRINT_SPEED;
+        assert!((ticks - ideal).abs() < ideal * 0.05, "took {} ticks, sprinting takes {:.0}", ticks, ideal);
+
+        // no weaving from block to block
+        assert!(sim.positions.iter().all(|at| (at.z - 0.5).abs() < 0.5));
+    }
+
+    /// a block inside of stone cannot be reached from anywhere, so the task gives up right away
+    #[test]
+    fn test_unreachable() {
+        let mut world = WorldBlocks::flat();
+        for x in -3..=3 {
+            for z in -3..=3 {
+                for y in 1..=6 {
+                    world.set_block(BlockLocation::new(x, y, z), BlockState::STONE);
+                }
+            }
+        }
+
+        let mut sim = SimulatedClient::new(world, BlockLocation::new(10, 1, 0).center_bottom());
+        let target = BlockLocation::new(0, 3, 0);
+        let task = BlockReachTask::new(target, &sim.bot.state, &sim.global);
+        sim.schedule(task);
+
+        assert_eq!(sim.run_task(5), 1);
+        assert_eq!(sim.local().last_failure.as_deref(), Some("BlockReachTask nowhere to stand to reach [0, 3, 0]"));
+    }
+
+    #[test]
+    fn test_reach() {
+        let mut world = WorldBlocks::flat();
+        let target = BlockLocation::new(0, 1, 0);
+        world.set_block(target, BlockState::STONE);
+
+        let mut sim = SimulatedClient::new(world, BlockLocation::new(12, 1, 3).center_bottom());
+        let task = BlockReachTask::new(target, &sim.bot.state, &sim.global);
+        sim.schedule(task);
+        sim.run_task(20 * 30);
+
+        let feet = BlockLocation::from(sim.local().physics.location());
+        assert!(reach_positions(target, &sim.global.blocks, REACH).any(|(at, _)| at == feet), "cannot reach from {}", feet);
+        assert_eq!(sim.local().last_failure, None);
+    }
+
+    /// bots filing through a corridor take turns instead of shoving each other off their paths
+    #[test]
+    fn test_corridor_swarm() {
+        let mut world = WorldBlocks::flat();
+
+        // a wall five blocks thick with a corridor one block wide through it
+        for x in 3..=7 {
+            for z in (-20..=20).filter(|&z| z != 0) {
+                for y in 1..=3 {
+                    world.set_block(BlockLocation::new(x, y, z), BlockState::STONE);
+                }
+            }
+        }
+
+        // four abreast in front of the wall. The first bots through go the furthest so no one has to pass them.
+        let place = |i: i32, x: i32| BlockLocation::new(x, 1, i % 4 - 2);
+        let starts: Vec<_> = (0..20).map(|i| place(i, -4 - i / 4).center_bottom()).collect();
+        let goals: Vec<_> = (0..20).map(|i| place(i, 16 - i / 4)).collect();
+
+        let mut swarm = SimulatedSwarm::new(world, &starts);
+        for (client, &goal) in swarm.clients.iter_mut().zip(&goals) {
+            let task = BlockTravelTask::new(goal, client.local());
+            client.schedule(task);
+        }
+
+        swarm.run_tasks(20 * 180);
+
+        for (id, (client, goal)) in swarm.clients.iter_mut().zip(&goals).enumerate() {
+            let location = client.local().physics.location();
+            assert!(location.dist2(goal.center_bottom()) < 1.0, "bot {} ended at {}", id, location);
+            assert_eq!(client.local().last_failure, None, "bot {}", id);
+        }
+
+        // the pushes would add up to moving a bot about a block
+        for (id, shoved) in swarm.shoved.iter().enumerate() {
+            assert!(*shoved < 0.4, "bot {} was shoved by {}", id, shoved);
+        }
+    }
+}
@@ -14,11 +14,12 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::client::estimate::{PILLAR_TICKS, Plan, TaskEstimate};
 use crate::client::state::global::GlobalState;
 use crate::client::state::local::LocalState;
 use crate::client::tasks::TaskTrait;
 use crate::protocol::InterfaceOut;
-use crate::storage::block::{BlockLocation, SimpleType};
+use crate::storage::block::{BlockKind, BlockLocation, SimpleType};
 use crate::types::{Direction, Displacement};
 
 pub struct PillarTask {
@@ -28,7 +29,7 @@ pub struct PillarTask {
 
 impl PillarTask {
     pub fn new(dest_y: u32) -> PillarTask {
-        println!("pillar dest {}", dest_y);
+        debug!(dest_y, "pillar");
         Self {
             dest_y,
             jumped: false,
@@ -36,9 +37,20 @@ impl PillarTask {
     }
 }
 
+impl Plan for PillarTask {
+    fn plan(&self, local: &LocalState, _global: &GlobalState) -> TaskEstimate {
+        let height = (self.dest_y as usize).saturating_sub(local.physics.location().y as usize);
+
+        let mut estimate = TaskEstimate::default();
+        estimate.place(BlockKind::COBBLESTONE, height, height * PILLAR_TICKS, local);
+        estimate.jump(height);
+        estimate
+    }
+}
+
 impl TaskTrait for PillarTask {
     fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
-        local.inventory.switch_block(out);
+        local.inventory.switch_block(local.gamemode().creative(), out);
 
         // equal OR GREATER because we don't want to pillar if we are higher than we need to be
         if local.physics.location().y as u32 >= self.dest_y {
@@ -76,3 +88,31 @@ impl TaskTrait for PillarTask {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::sim::SimulatedClient;
+    use crate::client::tasks::pillar::PillarTask;
+    use crate::storage::block::{BlockKind, BlockLocation};
+    use crate::storage::blocks::WorldBlocks;
+
+    #[test]
+    fn test_pillar() {
+        let mut sim = SimulatedClient::new(WorldBlocks::flat(), BlockLocation::new(0, 1, 0).center_bottom());
+        sim.give(0, BlockKind::COBBLESTONE, 64);
+        sim.schedule(PillarTask::new(5));
+        sim.run_task(200);
+
+        // the task is done in the air above the last block
+        sim.run_ticks(20);
+
+        for y in 1..5 {
+            assert_eq!(sim.global.blocks.get_block_kind(BlockLocation::new(0, y, 0)), Some(BlockKind::COBBLESTONE));
+        }
+
+        let location = sim.local().physics.location();
+        assert!((location.y - 5.0).abs() < 0.01, "ended at {}", location);
+        assert_eq!(sim.local().inventory.current().map(|held| held.count), Some(60));
+        assert_eq!(sim.fall_damage, 0.0);
+    }
+}
@@ -68,7 +68,7 @@ impl TaskStream for PillarOrMine {
             if let Some(&position) = set.iter().next() {
                 mine_task!(position)
             } else {
-                local.inventory.switch_block(out);
+                local.inventory.switch_block(local.gamemode().creative(), out);
                 Some(PillarTask::new(current_height + 1).into())
             }
         }
@@ -0,0 +1,66 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use itertools::Itertools;
+
+use crate::client::afk::Area;
+use crate::client::reply::SignRow;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::TaskTrait;
+use crate::protocol::InterfaceOut;
+
+/// Say the text of every loaded sign in `region` in chat. It is whispered to `to` if set (i.e., whoever asked).
+pub struct ReadSignsTask {
+    region: Area,
+    to: Option<String>,
+}
+
+impl ReadSignsTask {
+    pub fn new(region: Area, to: Option<String>) -> ReadSignsTask {
+        ReadSignsTask { region, to }
+    }
+
+    /// the signs in the region ordered by location so the order does not change between reads
+    pub fn signs(region: Area, global: &GlobalState) -> Vec<SignRow> {
+        global.blocks.signs()
+            .filter(|(location, _)| region.contains(*location))
+            .map(|(location, lines)| SignRow { location, lines: lines.clone() })
+            .sorted_by_key(|row| (row.location.x, row.location.y, row.location.z))
+            .collect()
+    }
+}
+
+impl TaskTrait for ReadSignsTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, _local: &mut LocalState, global: &mut GlobalState) -> bool {
+        let signs = ReadSignsTask::signs(self.region, global);
+
+        let lines: Vec<_> = if signs.is_empty() {
+            vec!["no signs".to_string()]
+        } else {
+            signs.iter().map(SignRow::line).collect()
+        };
+
+        for line in lines {
+            match self.to.as_ref() {
+                Some(to) => out.send_chat(&format!("/msg {} {}", to, line)),
+                None => out.send_chat(&line)
+            }
+        }
+
+        true
+    }
+}
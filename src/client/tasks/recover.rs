@@ -0,0 +1,367 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Going back for the items dropped when dying before they despawn.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime};
+
+use float_ord::FloatOrd;
+
+use crate::client::physics::SPRINT_SPEED;
+use crate::client::reply::block_name;
+use crate::client::snapshot::{SavedTask, unix_secs};
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::navigate::BlockTravelTask;
+use crate::client::tasks::TaskTrait;
+use crate::protocol::InterfaceOut;
+use crate::storage::block::{BlockKind, BlockLocation};
+use crate::types::{Dimension, Location};
+
+/// the server ticks a dropped item lasts
+const DESPAWN_TICKS: usize = 6000;
+
+#[derive(Clone, Debug)]
+pub struct RecoverConfig {
+    /// how far (in blocks) from where the bot died items are looked for
+    pub radius: f64,
+}
+
+impl Default for RecoverConfig {
+    fn default() -> Self {
+        Self {
+            radius: 8.0
+        }
+    }
+}
+
+/// where the bot died and what it dropped
+#[derive(Clone, Debug)]
+pub struct Death {
+    pub location: Location,
+    pub dimension: Dimension,
+
+    /// the tick ([`LocalState::ticks`]) the bot died in. The items despawn counting from here.
+    pub tick: usize,
+    pub items: HashMap<BlockKind, u32>,
+}
+
+impl Death {
+    /// None if the bot did not carry anything
+    pub fn new(local: &LocalState) -> Option<Death> {
+        let items = local.inventory.carried();
+        (!items.is_empty()).then(|| Death {
+            location: local.physics.location(),
+            dimension: local.dimension,
+            tick: local.ticks,
+            items,
+        })
+    }
+
+    /// Whether sprinting from `from` gets us there before the items despawn. We only know where we reappear in the
+    /// overworld.
+    pub fn in_time(&self, from: BlockLocation, global: &GlobalState) -> bool {
+        let ticks = from.center_bottom().dist2(self.location).sqrt() / SPRINT_SPEED;
+        self.dimension != Dimension::Overworld || ticks <= global.lag_ticks(DESPAWN_TICKS) as f64
+    }
+}
+
+enum Goal {
+    /// near enough to where we died to see the items
+    Death,
+    Item(u32),
+}
+
+/// Walk back to where the bot died and pick up the items within [`RecoverConfig::radius`] of it, closest to the bot
+/// first. Gives up once the items would have despawned or if the bot cannot get there. What was and was not picked up
+/// is said in chat at the end.
+pub struct RecoverItemsTask {
+    death: Death,
+    radius: f64,
+
+    /// the ticks from [`Death::tick`] until the items despawn. Fewer for a death from before a restart.
+    despawn_ticks: usize,
+
+    /// what we carried when the task started. Everything more of a kind we dropped counts as recovered.
+    before: Option<HashMap<BlockKind, u32>>,
+
+    /// items we went to. Ones still there could not be picked up.
+    visited: HashSet<u32>,
+    travel: Option<(Goal, BlockTravelTask)>,
+    arrived: bool,
+}
+
+impl RecoverItemsTask {
+    pub fn new(death: Death, radius: f64) -> RecoverItemsTask {
+        RecoverItemsTask {
+            death,
+            radius,
+            despawn_ticks: DESPAWN_TICKS,
+            before: None,
+            visited: HashSet::new(),
+            travel: None,
+            arrived: false,
+        }
+    }
+
+    /// Go back for items dropped before a restart, which despawn `left` from now
+    pub fn resume(location: Location, dimension: Dimension, items: HashMap<BlockKind, u32>, left: Duration, local: &LocalState, radius: f64) -> RecoverItemsTask {
+        let death = Death { location, dimension, tick: local.ticks, items };
+        let left = (left.as_millis() / 50) as usize;
+        RecoverItemsTask { despawn_ticks: left.min(DESPAWN_TICKS), ..RecoverItemsTask::new(death, radius) }
+    }
+
+    /// None if the items despawned already
+    pub fn save(&self, local: &LocalState) -> Option<SavedTask> {
+        let left = (self.death.tick + self.despawn_ticks).checked_sub(local.ticks).filter(|&left| left > 0)?;
+        let despawn = SystemTime::now() + Duration::from_millis(left as u64 * 50);
+        Some(SavedTask::Recover {
+            location: self.death.location,
+            dimension: self.death.dimension,
+            items: self.death.items.iter().map(|(kind, &count)| (kind.id(), count)).collect(),
+            despawn: unix_secs(despawn),
+        })
+    }
+
+    fn report(&self, out: &mut impl InterfaceOut, local: &LocalState, global: &GlobalState, reason: Option<&str>) {
+        let now = local.inventory.carried();
+        let count = |counts: Option<&HashMap<BlockKind, u32>>, kind: BlockKind| counts.and_then(|counts| counts.get(&kind)).copied().unwrap_or(0);
+
+        let mut total = 0;
+        let mut recovered = 0;
+        let mut missing = Vec::new();
+        for (&kind, &dropped) in &self.death.items {
+            let got = count(Some(&now), kind).saturating_sub(count(self.before.as_ref(), kind)).min(dropped);
+            total += dropped;
+            recovered += got;
+            if got < dropped {
+                missing.push((kind, dropped - got));
+            }
+        }
+        missing.sort_by_key(|(kind, _)| kind.id());
+
+        info!(recovered, total, ?reason, "done recovering items");
+
+        let mut message = format!("recovered {} of {} items", recovered, total);
+        if let Some(reason) = reason {
+            message = format!("{}, {}", message, reason);
+        }
+        if !missing.is_empty() {
+            let missing: Vec<_> = missing.iter()
+                .map(|&(kind, count)| format!("{} {}", count, block_name(&global.block_data, kind)))
+                .collect();
+            message = format!("{}. missing {}", message, missing.join(", "));
+        }
+        out.send_chat(&message);
+    }
+}
+
+impl TaskTrait for RecoverItemsTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        if self.before.is_none() {
+            if self.death.dimension != local.dimension {
+                out.send_chat(&format!("I died in the {} so I cannot go back for my items", self.death.dimension));
+                return true;
+            }
+            self.before = Some(local.inventory.carried());
+        }
+
+        if local.ticks >= self.death.tick + global.lag_ticks(self.despawn_ticks) {
+            self.report(out, local, global, Some("the rest despawned"));
+            return true;
+        }
+
+        // someone else picked the item up
+        if let Some((Goal::Item(id), _)) = &self.travel {
+            if global.entities.by_id(*id).is_none() {
+                self.visited.insert(*id);
+                self.travel = None;
+            }
+        }
+
+        if let Some((goal, travel)) = self.travel.as_mut() {
+            if !travel.tick(out, local, global) {
+                return false;
+            }
+
+            let failure = local.failure.take();
+            match *goal {
+                Goal::Death => {
+                    let near = local.physics.location().dist2(self.death.location) <= self.radius * self.radius;
+                    if failure.is_some() || !near {
+                        let at = BlockLocation::from(self.death.location);
+                        self.report(out, local, global, Some(&format!("I could not get to where I died at {}", at)));
+                        return true;
+                    }
+                    self.arrived = true;
+                }
+                Goal::Item(id) => {
+                    self.visited.insert(id);
+                }
+            }
+            self.travel = None;
+        }
+
+        if local.inventory.is_full() {
+            self.report(out, local, global, Some("my inventory is full"));
+            return true;
+        }
+
+        let me = local.physics.location();
+        if !self.arrived && me.dist2(self.death.location) > self.radius * self.radius {
+            let task = BlockTravelTask::new(BlockLocation::from(self.death.location), local);
+            self.travel = Some((Goal::Death, task));
+            return false;
+        }
+        self.arrived = true;
+
+        let visited = &self.visited;
+        let next = global.entities.items_near(self.death.location, self.radius)
+            .filter(|(id, _)| !visited.contains(id))
+            .min_by_key(|(_, location)| FloatOrd(location.dist2(me)));
+
+        match next {
+            Some((id, location)) => {
+                let task = BlockTravelTask::new(BlockLocation::from(location), local);
+                self.travel = Some((Goal::Item(id), task));
+                false
+            }
+            None => {
+                self.report(out, local, global, None);
+                true
+            }
+        }
+    }
+
+    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+        if let Some((_, travel)) = self.travel.as_mut() {
+            travel.expensive(end_at, local, global);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::client::bot::ActionState;
+    use crate::client::sim::{Call, SimulatedClient};
+    use crate::client::state::global::GlobalState;
+    use crate::client::state::local::inventory::ItemStack;
+    use crate::client::tasks::delay::DelayTask;
+    use crate::client::tasks::eat::EatTask;
+    use crate::client::tasks::recover::{Death, RecoverItemsTask};
+    use crate::storage::block::{BlockKind, BlockLocation};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::types::{Dimension, Location};
+
+    fn death(location: Location, dimension: Dimension, items: &[(BlockKind, u32)]) -> Death {
+        Death { location, dimension, tick: 0, items: items.iter().copied().collect::<HashMap<_, _>>() }
+    }
+
+    fn chat(sim: &SimulatedClient) -> Vec<String> {
+        sim.out().calls.iter()
+            .filter_map(|(_, call)| match call {
+                Call::Chat(message) => Some(message.clone()),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sweep() {
+        let mut sim = SimulatedClient::new(WorldBlocks::flat(), BlockLocation::new(-11, 1, 0).center_bottom());
+        let died_at = BlockLocation::new(0, 1, 0).center_bottom();
+
+        // b is closer to where we died than c, but c is closer once we picked up a
+        let a = sim.drop_item(Location::new(3.5, 1.0, 0.5), ItemStack::new(BlockKind::COBBLESTONE, 10, 0, None));
+        let b = sim.drop_item(Location::new(-5.5, 1.0, 3.5), ItemStack::new(BlockKind::COBBLESTONE, 10, 0, None));
+        let c = sim.drop_item(Location::new(6.5, 1.0, -4.5), ItemStack::new(BlockKind::COBBLESTONE, 10, 0, None));
+
+        // too far away
+        sim.drop_item(Location::new(20.5, 1.0, 0.5), ItemStack::new(BlockKind::DIRT, 5, 0, None));
+
+        let death = death(died_at, Dimension::Overworld, &[(BlockKind::COBBLESTONE, 30), (BlockKind::DIRT, 5)]);
+        sim.schedule(RecoverItemsTask::new(death, 8.0));
+        sim.run_task(1000);
+
+        assert_eq!(sim.picked_up, vec![a, c, b]);
+        assert_eq!(chat(&sim), vec!["recovered 30 of 35 items. missing 5 dirt".to_string()]);
+    }
+
+    #[test]
+    fn test_despawn() {
+        let mut sim = SimulatedClient::new(WorldBlocks::flat(), BlockLocation::new(0, 1, 0).center_bottom());
+        let died_at = BlockLocation::new(60, 1, 0).center_bottom();
+        sim.drop_item(died_at, ItemStack::new(BlockKind::COBBLESTONE, 10, 0, None));
+
+        // the bot took a while to respawn. The items despawn 6000 ticks after the death, not after the task started.
+        sim.local().ticks = 5950;
+        sim.schedule(RecoverItemsTask::new(death(died_at, Dimension::Overworld, &[(BlockKind::COBBLESTONE, 10)]), 8.0));
+        let ticks = sim.run_task(1000);
+
+        assert!((50..=52).contains(&ticks), "took {} ticks", ticks);
+        assert!(sim.picked_up.is_empty());
+        assert_eq!(chat(&sim), vec!["recovered 0 of 10 items, the rest despawned. missing 10 cobblestone".to_string()]);
+    }
+
+    #[test]
+    fn test_other_dimension() {
+        let mut sim = SimulatedClient::new(WorldBlocks::flat(), BlockLocation::new(0, 1, 0).center_bottom());
+        let died_at = BlockLocation::new(5, 1, 0).center_bottom();
+        sim.drop_item(died_at, ItemStack::new(BlockKind::COBBLESTONE, 10, 0, None));
+
+        sim.schedule(RecoverItemsTask::new(death(died_at, Dimension::Nether, &[(BlockKind::COBBLESTONE, 10)]), 8.0));
+        assert_eq!(sim.run_task(10), 1);
+        assert_eq!(chat(&sim), vec!["I died in the nether so I cannot go back for my items".to_string()]);
+    }
+
+    #[test]
+    fn test_in_time() {
+        let global = GlobalState::init();
+        let died_at = BlockLocation::new(0, 64, 0).center_bottom();
+
+        // sprinting covers about 1680 blocks before the items despawn
+        let death = death(died_at, Dimension::Overworld, &[(BlockKind::DIRT, 1)]);
+        assert!(death.in_time(BlockLocation::new(1000, 64, 0), &global));
+        assert!(!death.in_time(BlockLocation::new(0, 64, 3000), &global));
+
+        // where we reappear in the overworld says nothing about the way back to the nether
+        let death = Death { dimension: Dimension::Nether, ..death };
+        assert!(death.in_time(BlockLocation::new(0, 64, 3000), &global));
+    }
+
+    #[test]
+    fn test_priority() {
+        let recover = || RecoverItemsTask::new(death(Location::default(), Dimension::Overworld, &[(BlockKind::DIRT, 1)]), 8.0);
+
+        // before queued work
+        let mut actions = ActionState::default();
+        actions.schedule(DelayTask::new(5));
+        actions.prioritize(recover());
+        assert_eq!(actions.current().map(|(_, name)| name), Some("RecoverItemsTask"));
+        assert_eq!(actions.queued().collect::<Vec<_>>(), vec!["DelayTask"]);
+
+        // after a reflex
+        let mut actions = ActionState::default();
+        actions.schedule(EatTask::default());
+        actions.schedule(DelayTask::new(5));
+        actions.prioritize(recover());
+        assert_eq!(actions.current().map(|(_, name)| name), Some("EatTask"));
+        assert_eq!(actions.queued().collect::<Vec<_>>(), vec!["RecoverItemsTask", "DelayTask"]);
+    }
+}
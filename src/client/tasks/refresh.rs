@@ -0,0 +1,169 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use float_ord::FloatOrd;
+
+use crate::client::physics::Line;
+use crate::client::physics::speed::Speed;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::TaskTrait;
+use crate::protocol::InterfaceOut;
+use crate::storage::blocks::ChunkLocation;
+use crate::types::{Displacement, Location};
+
+/// how close to a point we have to be to have walked to it
+const ARRIVE_DIST: f64 = 0.3;
+
+/// walking to a point stops after this long (something is in the way)
+const MAX_WALK_TICKS: usize = 20 * 5;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Step {
+    Start,
+    Out,
+    Back,
+    Waiting,
+}
+
+/// Get the server to resend the chunks around the bot. 1.12 has no packet to ask for a chunk again, but the server
+/// sends chunks which come into view, so the bot briefly steps across the closest chunk border and back. The
+/// columns are marked stale (and exact block queries wait) until they are resent.
+pub struct RefreshTask {
+    radius: i32,
+    step: Step,
+
+    /// ticks spent on the current step
+    ticks: usize,
+
+    /// where the bot was when the refresh started
+    origin: Location,
+
+    /// just across the chunk border closest to the origin
+    across: Location,
+
+    /// the columns which have not been resent
+    stale: Vec<ChunkLocation>,
+}
+
+impl RefreshTask {
+    pub fn new(radius: i32, local: &LocalState) -> RefreshTask {
+        let origin = local.physics.location();
+        RefreshTask {
+            radius,
+            step: Step::Start,
+            ticks: 0,
+            origin,
+            across: across_border(origin),
+            stale: Vec::new(),
+        }
+    }
+
+    fn next(&mut self, step: Step) {
+        self.step = step;
+        self.ticks = 0;
+    }
+}
+
+/// the point half a block across the chunk border closest to `location`
+fn across_border(location: Location) -> Location {
+    let in_x = location.x.rem_euclid(16.0);
+    let in_z = location.z.rem_euclid(16.0);
+
+    let options = [
+        Displacement::new(-in_x - 0.5, 0.0, 0.0),
+        Displacement::new(16.0 - in_x + 0.5, 0.0, 0.0),
+        Displacement::new(0.0, 0.0, -in_z - 0.5),
+        Displacement::new(0.0, 0.0, 16.0 - in_z + 0.5),
+    ];
+
+    let closest = options.iter().min_by_key(|displacement| FloatOrd(displacement.mag2())).unwrap();
+    location + *closest
+}
+
+/// walk towards `to`. Returns true once we are there.
+fn walk(local: &mut LocalState, to: Location) -> bool {
+    let displacement = (to - local.physics.location()).make_dy(0.0);
+    if displacement.mag2() < ARRIVE_DIST * ARRIVE_DIST {
+        return true;
+    }
+
+    let eye = local.physics.eye_height();
+    local.physics.look_at(to + eye);
+    local.physics.line(Line::Forward);
+    local.physics.speed(Speed::WALK);
+    false
+}
+
+impl TaskTrait for RefreshTask {
+    fn tick(&mut self, _out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        self.ticks += 1;
+
+        match self.step {
+            Step::Start => {
+                let ChunkLocation(x, z) = ChunkLocation::from(self.origin);
+                for dx in -self.radius..=self.radius {
+                    for dz in -self.radius..=self.radius {
+                        let location = ChunkLocation(x + dx, z + dz);
+                        if global.blocks.mark_stale(location) {
+                            self.stale.push(location);
+                        }
+                    }
+                }
+
+                info!(chunks = self.stale.len(), x, z, "refreshing chunks");
+                self.next(Step::Out);
+                walk(local, self.across);
+            }
+            Step::Out => {
+                if walk(local, self.across) || self.ticks > MAX_WALK_TICKS {
+                    self.next(Step::Back);
+                    walk(local, self.origin);
+                }
+            }
+            Step::Back => {
+                if walk(local, self.origin) || self.ticks > MAX_WALK_TICKS {
+                    self.next(Step::Waiting);
+                }
+            }
+            Step::Waiting => {}
+        }
+
+        if self.step != Step::Waiting {
+            return false;
+        }
+
+        let blocks = &global.blocks;
+        self.stale.retain(|&location| blocks.is_stale(location));
+
+        if self.stale.is_empty() {
+            info!("chunks were resent");
+            return true;
+        }
+
+        if self.ticks > global.config.resync.timeout {
+            warn!(chunks = self.stale.len(), "chunks were not resent");
+
+            // do not keep everyone waiting on chunks which are not coming
+            for &location in &self.stale {
+                global.blocks.mark_fresh(location);
+            }
+            local.desync.refresh_failed();
+            return true;
+        }
+
+        false
+    }
+}
@@ -0,0 +1,123 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::client::routine::{Routine, Step};
+use crate::client::snapshot::SavedTask;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::{Task, TaskTrait};
+use crate::client::tasks::mine::MineTask;
+use crate::client::tasks::navigate::BlockTravelTask;
+use crate::protocol::{Face, InterfaceOut};
+use crate::storage::block::BlockState;
+
+/// Executes the steps of a [`Routine`] in order. The task for a step is only created when the step starts since it
+/// depends on where the bot is at that point.
+pub struct RoutineTask {
+    steps: VecDeque<Step>,
+
+    /// the step in progress and its task
+    current: Option<(Step, Box<Task>)>,
+}
+
+impl RoutineTask {
+    pub fn new(routine: &Routine) -> RoutineTask {
+        RoutineTask {
+            steps: routine.steps.iter().copied().collect(),
+            current: None,
+        }
+    }
+
+    /// the steps left, the one in progress first
+    pub fn save(&self) -> SavedTask {
+        let current = self.current.as_ref().map(|(step, _)| *step);
+        SavedTask::Routine { steps: current.into_iter().chain(self.steps.iter().copied()).collect() }
+    }
+
+    /// Start the step. Returns the task to run for it, or None if the step finished right away.
+    fn start(step: Step, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> Option<Task> {
+        match step {
+            Step::Goto { location } => Some(BlockTravelTask::new(location, local).into()),
+            Step::Break { location } => {
+                // someone else might have already mined it
+                let kind = global.blocks.get_block_kind(location)?;
+                if kind == BlockState::AIR.kind() {
+                    return None;
+                }
+                Some(MineTask::new(location, out, local, global).into())
+            }
+            Step::Place { location } => {
+                let against = location.below();
+                let eye = local.physics.location() + local.physics.eye_height();
+                let look = match local.aim.aim(&global.blocks, eye, against, Some(Face::PosY)) {
+                    Ok(aim) => aim.point,
+                    Err(_) => against.faces()[Face::PosY as usize],
+                };
+
+                local.inventory.switch_block(local.gamemode().creative(), out);
+                local.physics.look_at(look);
+                out.swing_arm();
+                out.place_block(against, Face::PosY);
+                None
+            }
+            Step::Use { location } => {
+                // doors, levers and chests can be used from any face we can see
+                let eye = local.physics.location() + local.physics.eye_height();
+                let (look, face) = match local.aim.aim(&global.blocks, eye, location, None) {
+                    Ok(aim) => (aim.point, aim.face),
+                    Err(err) => {
+                        debug!(%location, error = %err, "using the block anyway");
+                        (location.center_bottom(), Face::PosY)
+                    }
+                };
+
+                local.physics.look_at(look);
+                out.swing_arm();
+                out.place_block(location, face);
+                None
+            }
+        }
+    }
+}
+
+impl TaskTrait for RoutineTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        loop {
+            if let Some((_, task)) = self.current.as_mut() {
+                if !task.tick(out, local, global) {
+                    return false;
+                }
+                self.current = None;
+            }
+
+            let step = match self.steps.pop_front() {
+                None => return true,
+                Some(step) => step
+            };
+
+            self.current = RoutineTask::start(step, out, local, global).map(|task| (step, Box::new(task)));
+        }
+    }
+
+    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+        if let Some((_, task)) = self.current.as_mut() {
+            task.expensive(end_at, local, global);
+        }
+    }
+}
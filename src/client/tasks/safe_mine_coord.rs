@@ -30,38 +30,39 @@ use crate::storage::block::{BlockLocation, BlockLocation2D};
 
 pub struct SafeMineRegion;
 
-impl Lazy for SafeMineRegion {
-    fn create(&self, local: &mut LocalState, global: &GlobalState) -> Task {
-        let location = BlockLocation::from(local.physics.location());
-        let center = BlockLocation2D::from(location);
-
-        // if we should skip this region. For example, if there is water or lava we will want to avoid it
-        let avoid = MineAlloc::locations_extra(center)
-            .any(|loc| {
+impl SafeMineRegion {
+    /// water or lava in (or right next to) the region, which we want to avoid
+    pub fn fluid(center: BlockLocation2D, global: &GlobalState) -> Option<BlockLocation> {
+        MineAlloc::locations_extra(center)
+            .find(|&loc| {
 
                 // there is often lava under bedrock that we don't really care about
                 if loc.y < MineColumn::MIN_MINE_LOC {
                     return false;
                 }
 
-                match global.blocks.get_block_exact(loc).map(|x| x.kind().id()) {
-                    // water or lava
-                    Some(8..=11) => {
-                        println!("skipping region {}, {} because of {:?} at {}", center.x, center.z, global.blocks.get_block_exact(loc), loc);
-                        true
-                    }
-                    _ => false
-                }
-            });
+                matches!(global.blocks.get_block_exact(loc).map(|x| x.kind().id()), Some(8..=11))
+            })
+    }
+}
+
+impl Lazy for SafeMineRegion {
+    fn create(&self, local: &mut LocalState, global: &GlobalState) -> Task {
+        let location = BlockLocation::from(local.physics.location());
+        let center = BlockLocation2D::from(location);
+
+        // if we should skip this region. For example, if there is water or lava we will want to avoid it
+        let avoid = Self::fluid(center, global);
 
-        if avoid {
+        if let Some(loc) = avoid {
+            info!(x = center.x, z = center.z, block = ?global.blocks.get_block_exact(loc), location = %loc, "skipping region");
             DelayTask(0).into()
         } else {
             let mut compound = CompoundTask::default();
 
             compound.add(CenterTask)
                 .add(LazyTask::from(GoMineTop))
-                .add(LazyStream::from(MineColumn));
+                .add(LazyStream::from(MineColumn::default()));
 
             compound.into()
         }
@@ -0,0 +1,236 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Using a bed so the bot reappears there after dying
+
+use std::time::Instant;
+
+use crate::client::snapshot::SavedTask;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::state::local::spawn::{bed_head, bed_other_half, BedError, BedResponse, SpawnPoint};
+use crate::client::tasks::navigate::BlockReachTask;
+use crate::client::tasks::{TaskError, TaskTrait};
+use crate::protocol::{Face, InterfaceOut};
+use crate::storage::block::BlockLocation;
+
+/// how long we wait for the server to answer using the bed
+const ANSWER_TICKS: usize = 20;
+
+enum Step {
+    Start,
+    Reach(BlockReachTask),
+    Use,
+
+    /// the tick we used the bed
+    Wait(usize),
+}
+
+/// Walk to the bed one half of which is at `bed` and use it. If the server does not let us (i.e., 1.12 during the day)
+/// this fails with [`TaskError::Bed`] saying why.
+pub struct SetSpawnTask {
+    bed: BlockLocation,
+    step: Step,
+
+    /// whether we went for the other half because this one could not be reached
+    other_half: bool,
+}
+
+impl SetSpawnTask {
+    pub fn new(bed: BlockLocation) -> SetSpawnTask {
+        SetSpawnTask {
+            bed,
+            step: Step::Start,
+            other_half: false,
+        }
+    }
+
+    pub fn save(&self) -> SavedTask {
+        SavedTask::Bed { bed: self.bed }
+    }
+
+    fn fail(&self, local: &mut LocalState, error: BedError) -> bool {
+        local.failure = Some(TaskError::Bed { bed: self.bed, error });
+        true
+    }
+}
+
+impl TaskTrait for SetSpawnTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        let state = global.blocks.get_block_exact(self.bed);
+        let head = match state.and_then(|state| bed_head(self.bed, state)) {
+            Some(head) => head,
+            None => return self.fail(local, BedError::NotABed)
+        };
+
+        match &mut self.step {
+            Step::Start => {
+                if local.spawn.point() == Some(SpawnPoint::Bed(head)) {
+                    debug!(bed = %head, "the spawn point is already at the bed");
+                    return true;
+                }
+                self.step = Step::Reach(BlockReachTask::new(self.bed, local, global));
+            }
+            Step::Reach(reach) => {
+                if !reach.tick(out, local, global) {
+                    return false;
+                }
+
+                match local.failure.take() {
+                    None => self.step = Step::Use,
+                    Some(failure) => {
+                        // a bed is two blocks, there may be room next to the other one
+                        let other = state.and_then(|state| bed_other_half(self.bed, state));
+                        match other {
+                            Some(other) if !self.other_half => {
+                                self.other_half = true;
+                                self.bed = other;
+                                self.step = Step::Reach(BlockReachTask::new(other, local, global));
+                            }
+                            _ => {
+                                local.failure = Some(failure);
+                                return true;
+                            }
+                        }
+                    }
+                }
+            }
+            Step::Use => {
+                let eye = local.physics.location() + local.physics.eye_height();
+                let (look, face) = match local.aim.aim(&global.blocks, eye, self.bed, None) {
+                    Ok(aim) => (aim.point, aim.face),
+                    Err(_) => (self.bed.faces()[Face::PosY as usize], Face::PosY),
+                };
+
+                local.physics.look_at(look);
+                if local.physics.aimed() {
+                    out.place_block(self.bed, face);
+                    out.swing_arm();
+                    self.step = Step::Wait(local.ticks);
+                }
+            }
+            Step::Wait(used_at) => {
+                let used_at = *used_at;
+                let responses: Vec<_> = local.spawn.responses_since(used_at).collect();
+
+                // 1.16 sets the spawn point even if it does not let us sleep
+                if responses.iter().any(|response| matches!(response, BedResponse::Slept | BedResponse::SpawnSet)) {
+                    out.leave_bed();
+                    local.spawn.set_bed(head);
+                    return true;
+                }
+
+                let failed = responses.iter().find_map(|response| match response {
+                    BedResponse::Failed(error) => Some(*error),
+                    _ => None
+                });
+
+                if let Some(error) = failed {
+                    return self.fail(local, error);
+                }
+
+                if local.ticks - used_at > global.lag_ticks(ANSWER_TICKS) {
+                    return self.fail(local, BedError::NoAnswer);
+                }
+            }
+        }
+
+        false
+    }
+
+    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+        if let Step::Reach(reach) = &mut self.step {
+            reach.expensive(end_at, local, global);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::sim::{Call, SimulatedClient};
+    use crate::client::state::local::spawn::{BedError, BedResponse, SpawnPoint};
+    use crate::client::tasks::set_spawn::SetSpawnTask;
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+
+    /// the foot of a bed with its head to the east
+    const FOOT: BlockLocation = BlockLocation { x: 5, y: 1, z: 0 };
+    const HEAD: BlockLocation = BlockLocation { x: 6, y: 1, z: 0 };
+
+    fn bed_sim() -> SimulatedClient {
+        let mut world = WorldBlocks::flat();
+        world.set_block(FOOT, BlockState::from(BlockKind::BED.id(), 3));
+        world.set_block(HEAD, BlockState::from(BlockKind::BED.id(), 0b1011));
+        SimulatedClient::new(world, BlockLocation::new(0, 1, 0).center_bottom())
+    }
+
+    /// walk to the bed and use it. The server answers with `responses`.
+    fn answered(sim: &mut SimulatedClient, responses: &[BedResponse]) {
+        sim.schedule(SetSpawnTask::new(FOOT));
+
+        let used = |sim: &SimulatedClient| sim.out().calls.iter().any(|(_, call)| matches!(call, Call::PlaceBlock { .. }));
+        for _ in 0..200 {
+            if used(sim) {
+                break;
+            }
+            sim.tick();
+        }
+        assert!(used(sim), "the bed was not used");
+
+        let ticks = sim.local().ticks;
+        for &response in responses {
+            sim.local().spawn.on_response(ticks, response);
+        }
+        sim.run_task(100);
+    }
+
+    #[test]
+    fn test_set_spawn() {
+        // 1.12 lets us sleep at night
+        let mut sim = bed_sim();
+        answered(&mut sim, &[BedResponse::Slept]);
+        assert_eq!(sim.local().spawn.point(), Some(SpawnPoint::Bed(HEAD)));
+        assert!(sim.out().calls.iter().any(|(_, call)| call == &Call::LeaveBed));
+        assert_eq!(sim.local().last_failure, None);
+
+        // 1.16 sets the spawn point during the day as well
+        let mut sim = bed_sim();
+        answered(&mut sim, &[BedResponse::SpawnSet, BedResponse::Failed(BedError::NotNight)]);
+        assert_eq!(sim.local().spawn.point(), Some(SpawnPoint::Bed(HEAD)));
+        assert_eq!(sim.local().last_failure, None);
+    }
+
+    #[test]
+    fn test_failed() {
+        let mut sim = bed_sim();
+        answered(&mut sim, &[BedResponse::Failed(BedError::NotNight)]);
+        assert_eq!(sim.local().spawn.point(), None);
+        assert_eq!(sim.local().last_failure, Some(format!("SetSpawnTask could not use the bed at {}: it is not night", FOOT)));
+
+        let mut sim = bed_sim();
+        answered(&mut sim, &[]);
+        assert_eq!(sim.local().last_failure, Some(format!("SetSpawnTask could not use the bed at {}: the server did not answer", FOOT)));
+    }
+
+    #[test]
+    fn test_already_set() {
+        let mut sim = bed_sim();
+        sim.local().spawn.set_bed(HEAD);
+        sim.schedule(SetSpawnTask::new(FOOT));
+        assert_eq!(sim.run_task(10), 1);
+        assert!(!sim.out().calls.iter().any(|(_, call)| matches!(call, Call::PlaceBlock { .. })));
+    }
+}
@@ -0,0 +1,327 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Going far away (thousands of blocks) in stretches of a route planned over whole chunks (see [`coarse`])
+
+use std::collections::{HashSet, VecDeque};
+use std::time::Instant;
+
+use crate::client::pathfind::coarse;
+use crate::client::pathfind::coarse::{Cell, crossable};
+use crate::client::snapshot::SavedTask;
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::navigate::{BlockTravelTask, ChunkTravelTask};
+use crate::client::tasks::{TaskError, TaskTrait};
+use crate::protocol::InterfaceOut;
+use crate::storage::block::BlockLocation;
+use crate::storage::blocks::ChunkLocation;
+
+/// how many stretches in a row may fail before we give up
+const MAX_FAILURES: usize = 5;
+
+/// how we get to the next chunk of the route
+enum Leg {
+    Chunk(ChunkTravelTask),
+
+    /// the chunk of the goal, where we go to the goal itself
+    Goal(BlockTravelTask),
+}
+
+impl Leg {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        match self {
+            Leg::Chunk(task) => task.tick(out, local, global),
+            Leg::Goal(task) => task.tick(out, local, global),
+        }
+    }
+
+    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+        match self {
+            Leg::Chunk(task) => task.expensive(end_at, local, global),
+            Leg::Goal(task) => task.expensive(end_at, local, global),
+        }
+    }
+}
+
+/// Go to `goal` however far away it is. The route is planned over chunks and walked a stretch at a time so the path
+/// finding only ever looks at the blocks up to the next stop. Chunks on the route which turn out not to be crossable
+/// once they are loaded (an ocean, a cliff) are planned around.
+pub struct TravelTask {
+    goal: BlockLocation,
+
+    /// the chunks the route goes through, from where it was planned
+    corridor: Vec<ChunkLocation>,
+
+    /// the indices of the corridor still to stop at
+    waypoints: VecDeque<usize>,
+
+    /// the index of the corridor we got to last
+    reached: usize,
+
+    /// the index of the corridor we are going to and how
+    leg: Option<(usize, Leg)>,
+
+    /// the chunks of the corridor which were judged since they were loaded
+    checked: HashSet<ChunkLocation>,
+
+    /// the chunks the route must not go through
+    avoid: HashSet<ChunkLocation>,
+
+    /// the route has to be planned (again) from where we are
+    plan: bool,
+
+    /// the stretches which failed in a row
+    failures: usize,
+
+    failure: Option<TaskError>,
+}
+
+impl TravelTask {
+    pub fn new(goal: BlockLocation) -> TravelTask {
+        TravelTask {
+            goal,
+            corridor: Vec::new(),
+            waypoints: VecDeque::new(),
+            reached: 0,
+            leg: None,
+            checked: HashSet::new(),
+            avoid: HashSet::new(),
+            plan: true,
+            failures: 0,
+            failure: None,
+        }
+    }
+
+    pub fn save(&self) -> SavedTask {
+        SavedTask::Travel { goal: self.goal }
+    }
+
+    fn replan(&mut self, local: &LocalState, global: &GlobalState) {
+        self.plan = false;
+
+        let from = ChunkLocation::from(local.physics.location());
+        let config = &global.config.travel;
+        let corridor = match coarse::plan(&global.blocks, from, ChunkLocation::from(self.goal), &self.avoid, config) {
+            None => {
+                self.failure = Some(TaskError::NoRoute { goal: self.goal });
+                return;
+            }
+            Some(corridor) => corridor
+        };
+
+        debug!(chunks = corridor.len(), avoided = self.avoid.len(), "planned the route");
+        let waypoints: VecDeque<_> = coarse::waypoints(&corridor, config.spacing).into();
+
+        // keep going where we were going if it is still on the way to the next stop
+        if let Some((target, leg)) = self.leg.take() {
+            let chunk = self.corridor[target];
+            let first = waypoints[0];
+            if let Some(target) = corridor[..=first].iter().position(|&on| on == chunk) {
+                self.leg = Some((target, leg));
+            }
+        }
+
+        self.corridor = corridor;
+        self.waypoints = waypoints;
+        self.reached = 0;
+        self.checked.clear();
+    }
+
+    /// the first chunk ahead on the corridor which cannot be crossed now that it is loaded
+    fn blocked_ahead(&mut self, global: &GlobalState) -> Option<ChunkLocation> {
+        let config = &global.config.travel;
+
+        // we go to the chunk of the goal no matter what it is like
+        let last = self.corridor.len() - 1;
+
+        for idx in self.reached + 1..last {
+            let chunk = self.corridor[idx];
+            if self.checked.contains(&chunk) {
+                continue;
+            }
+
+            let cell = Cell::judge(&global.blocks, chunk, config);
+            if cell == Cell::Unknown {
+                continue;
+            }
+
+            self.checked.insert(chunk);
+            let before = Cell::judge(&global.blocks, self.corridor[idx - 1], config);
+            if !crossable(before, cell, config) {
+                return Some(chunk);
+            }
+        }
+
+        None
+    }
+
+    /// Go to the furthest loaded chunk up to the next stop. A path to a chunk which is not loaded can only be found by
+    /// running into the unloaded chunks, which means looking at every block we can get to first.
+    fn next_leg(&self, local: &LocalState, global: &GlobalState) -> (usize, Leg) {
+        let waypoint = self.waypoints[0];
+        let target = (self.reached + 1..=waypoint).rev()
+            .find(|&idx| global.blocks.heightmap(self.corridor[idx]).is_some())
+            .unwrap_or(waypoint);
+
+        let chunk = self.corridor[target];
+        let leg = if target == self.corridor.len() - 1 && chunk == ChunkLocation::from(self.goal) {
+            Leg::Goal(BlockTravelTask::new(self.goal, local))
+        } else {
+            Leg::Chunk(ChunkTravelTask::new(chunk, local))
+        };
+
+        (target, leg)
+    }
+}
+
+impl TaskTrait for TravelTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, global: &mut GlobalState) -> bool {
+        if let Some(failure) = self.failure.take() {
+            local.failure = Some(failure);
+            return true;
+        }
+
+        // planned in the expensive part
+        if self.plan {
+            return false;
+        }
+
+        if let Some(blocked) = self.blocked_ahead(global) {
+            debug!(?blocked, "the route goes through a chunk which cannot be crossed, planning again");
+            self.avoid.insert(blocked);
+            self.plan = true;
+            return false;
+        }
+
+        if self.leg.is_none() {
+            self.leg = Some(self.next_leg(local, global));
+        }
+
+        let (target, leg) = self.leg.as_mut().unwrap();
+        if !leg.tick(out, local, global) {
+            return false;
+        }
+
+        let target = *target;
+        let to_goal = matches!(leg, Leg::Goal(_));
+        self.leg = None;
+
+        if let Some(failure) = local.failure.take() {
+            self.failures += 1;
+            if to_goal || self.failures >= MAX_FAILURES {
+                local.failure = Some(failure);
+                return true;
+            }
+
+            let chunk = self.corridor[target];
+            debug!(%failure, ?chunk, "could not get to the next chunk of the route, planning again");
+            self.avoid.insert(chunk);
+            self.plan = true;
+            return false;
+        }
+
+        if to_goal {
+            return true;
+        }
+
+        self.failures = 0;
+        self.reached = target;
+        if self.waypoints[0] == target {
+            self.waypoints.pop_front();
+        }
+
+        // the route ended short of the goal as it was too far to plan all of it
+        if self.waypoints.is_empty() {
+            self.plan = true;
+        }
+
+        false
+    }
+
+    fn expensive(&mut self, end_at: Instant, local: &mut LocalState, global: &GlobalState) {
+        if self.plan && self.failure.is_none() {
+            self.replan(local, global);
+        }
+
+        if let Some((_, leg)) = self.leg.as_mut() {
+            leg.expensive(end_at, local, global);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::client::sim::SimulatedClient;
+    use crate::client::tasks::travel::TravelTask;
+    use crate::client::tasks::TaskTrait;
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+
+    /// stone at y=0 in the chunks from `from` to `to`
+    fn ground(from: ChunkLocation, to: ChunkLocation) -> WorldBlocks {
+        let mut world = WorldBlocks::default();
+        for x in from.0 << 4..(to.0 + 1) << 4 {
+            for z in from.1 << 4..(to.1 + 1) << 4 {
+                world.set_block(BlockLocation::new(x, 0, z), BlockState::STONE);
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn test_travel() {
+        let world = ground(ChunkLocation(-1, -1), ChunkLocation(12, 1));
+        let mut sim = SimulatedClient::new(world, BlockLocation::new(8, 1, 8).center_bottom());
+        sim.global.config.travel.spacing = 64;
+
+        let goal = BlockLocation::new(200, 1, 8);
+        sim.schedule(TravelTask::new(goal));
+        sim.run_task(3000);
+
+        assert_eq!(BlockLocation::from(sim.local().physics.location()), goal);
+        assert_eq!(sim.local().last_failure, None);
+    }
+
+    #[test]
+    fn test_repair() {
+        let world = ground(ChunkLocation(-1, -1), ChunkLocation(3, 1));
+        let mut sim = SimulatedClient::new(world, BlockLocation::new(8, 1, 8).center_bottom());
+
+        let mut task = TravelTask::new(BlockLocation::new(1000, 1, 8));
+        let end_at = Instant::now() + Duration::from_millis(50);
+        task.expensive(end_at, &mut sim.bot.state, &sim.global);
+        assert!(task.corridor.contains(&ChunkLocation(5, 0)));
+        assert!(!task.tick(&mut sim.bot.out, &mut sim.bot.state, &mut sim.global));
+
+        // the chunk streams in and turns out to be ocean
+        for x in 80..96 {
+            for z in 0..16 {
+                sim.global.blocks.set_block(BlockLocation::new(x, 0, z), BlockState::STONE);
+                sim.global.blocks.set_block(BlockLocation::new(x, 1, z), BlockState::from(9, 0));
+            }
+        }
+
+        assert!(!task.tick(&mut sim.bot.out, &mut sim.bot.state, &mut sim.global));
+        assert!(task.avoid.contains(&ChunkLocation(5, 0)));
+
+        task.expensive(end_at, &mut sim.bot.state, &sim.global);
+        assert!(!task.corridor.contains(&ChunkLocation(5, 0)));
+        assert_eq!(task.corridor.last(), Some(&ChunkLocation(62, 0)));
+    }
+}
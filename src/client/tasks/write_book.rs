@@ -0,0 +1,82 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::client::state::global::GlobalState;
+use crate::client::state::local::LocalState;
+use crate::client::tasks::TaskTrait;
+use crate::protocol::InterfaceOut;
+use crate::storage::block::BlockKind;
+
+/// the most pages the vanilla client lets a book have
+const MAX_PAGES: usize = 50;
+
+/// the most characters the vanilla client lets a page have
+const MAX_PAGE_LEN: usize = 256;
+
+/// the most characters the vanilla client lets a title have
+const MAX_TITLE_LEN: usize = 16;
+
+/// Write `pages` into a writable book in the hotbar. With a title the book is signed as well (and cannot be edited
+/// anymore).
+pub struct WriteBookTask {
+    pages: Vec<String>,
+    title: Option<String>,
+}
+
+fn truncate(text: &str, len: usize) -> String {
+    text.chars().take(len).collect()
+}
+
+impl WriteBookTask {
+    pub fn new(pages: Vec<String>, title: Option<String>) -> WriteBookTask {
+        let pages = pages.iter()
+            .take(MAX_PAGES)
+            .map(|page| truncate(page, MAX_PAGE_LEN))
+            .collect();
+        let title = title.map(|title| truncate(&title, MAX_TITLE_LEN));
+        WriteBookTask { pages, title }
+    }
+}
+
+impl TaskTrait for WriteBookTask {
+    fn tick(&mut self, out: &mut impl InterfaceOut, local: &mut LocalState, _global: &mut GlobalState) -> bool {
+        if !local.inventory.hold_item(|kind| kind == BlockKind::WRITABLE_BOOK, out) {
+            warn!("no writable book in the hotbar");
+            return true;
+        }
+
+        match self.title.as_ref() {
+            Some(title) => out.sign_book(&self.pages, title, &local.info.username),
+            None => out.edit_book(&self.pages)
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::tasks::write_book::WriteBookTask;
+
+    #[test]
+    fn test_limits() {
+        let pages = vec!["a".repeat(300); 60];
+        let task = WriteBookTask::new(pages, Some("a very long title indeed".to_string()));
+        assert_eq!(task.pages.len(), 50);
+        assert!(task.pages.iter().all(|page| page.len() == 256));
+        assert_eq!(task.title.as_deref(), Some("a very long titl"));
+    }
+}
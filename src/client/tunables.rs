@@ -0,0 +1,283 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Settings which can change while the bots are online. They are read from `--config` at startup and again on
+//! `#reload-config`, so tuning them does not need every account to log in again.
+
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::bootstrap::schedule::LoginConfig;
+use crate::client::desync::ResyncConfig;
+use crate::client::find::FindConfig;
+use crate::client::pathfind::coarse::TravelConfig;
+use crate::client::pathfind::context::PathConfig;
+use crate::client::physics::rotation::RotationConfig;
+use crate::client::state::global::world_players::PanicConfig;
+use crate::client::state::local::armor::ArmorConfig;
+use crate::client::state::local::inventory::InventoryPolicy;
+use crate::client::tasks::recover::RecoverConfig;
+use crate::client::view::ViewConfig;
+use crate::error::{err, Res};
+use crate::protocol::budget::{Bucket, BudgetConfig};
+use crate::protocol::chat_queue::ChatConfig;
+use crate::storage::block::BlockKind;
+use crate::storage::blocks::index::IndexConfig;
+
+/// settings which are only read at startup (the connections, accounts and files are set up by then)
+const FIXED: &[&str] = &[
+    "host", "port", "version", "count", "reserve", "users-file", "proxies-file", "login-concurrency",
+    "disconnect-patterns", "base-weights", "permissions", "record", "digest", "digest-webhook", "servers",
+];
+
+#[derive(Clone, Default)]
+pub struct Tunables {
+    pub chat: ChatConfig,
+    pub budget: BudgetConfig,
+    pub rotation: RotationConfig,
+    pub armor: ArmorConfig,
+    pub inventory: InventoryPolicy,
+    pub login: LoginConfig,
+    pub resync: ResyncConfig,
+    pub find: FindConfig,
+    pub path: PathConfig,
+    pub index: IndexConfig,
+    pub recover: RecoverConfig,
+    pub travel: TravelConfig,
+    pub view: ViewConfig,
+    pub panic: PanicConfig,
+}
+
+fn parse<T: FromStr>(line: &str, value: &str) -> Res<T> {
+    value.parse().map_err(|_| err(&format!("invalid value in {}", line)))
+}
+
+fn millis(line: &str, value: &str) -> Res<Duration> {
+    parse(line, value).map(Duration::from_millis)
+}
+
+/// block ids separated by commas or `none`
+fn kinds(line: &str, value: &str) -> Res<Vec<BlockKind>> {
+    if value == "none" {
+        return Ok(Vec::new());
+    }
+    value.split(',').map(|id| parse(line, id.trim()).map(BlockKind)).collect()
+}
+
+/// names separated by commas or `none`
+fn names(value: &str) -> Vec<String> {
+    if value == "none" {
+        return Vec::new();
+    }
+    value.split(',').map(str::trim).filter(|name| !name.is_empty()).map(String::from).collect()
+}
+
+/// `none` turns the setting off
+fn optional<T: FromStr>(line: &str, value: &str) -> Res<Option<T>> {
+    match value {
+        "none" => Ok(None),
+        value => parse(line, value).map(Some)
+    }
+}
+
+/// actions a second, `none` for no limit at all
+fn rate(line: &str, value: &str, bucket: &mut Bucket) -> Res {
+    match optional(line, value)? {
+        Some(rate) => bucket.rate = rate,
+        None => *bucket = Bucket::UNLIMITED,
+    }
+    Ok(())
+}
+
+impl Tunables {
+    /// `base` (the command line options) with the settings in `path` taking precedence. Settings which cannot change
+    /// while running are ignored with a warning.
+    pub fn load(path: &Path, base: &Tunables) -> Res<Tunables> {
+        let mut tunables = base.clone();
+        for key in tunables.apply(&fs::read_to_string(path)?)? {
+            warn!(key, "this setting cannot change while running and is ignored. Set it on the command line.");
+        }
+        Ok(tunables)
+    }
+
+    /// Change the settings in `file`. Every line is `<setting>: <value>` with the name of the command line option
+    /// (durations in milliseconds). Empty lines and lines starting with `#` are ignored.
+    /// Returns the settings which were skipped as they cannot change while running.
+    fn apply<'a>(&mut self, file: &'a str) -> Res<Vec<&'a str>> {
+        let mut fixed = Vec::new();
+
+        for line in file.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (key, value) = line.split_once(':').ok_or_else(|| err(&format!("expected <setting>: <value> but got {}", line)))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "chat-interval" => self.chat.min_interval = millis(line, value)?,
+                "chat-jitter" => self.chat.jitter = millis(line, value)?,
+                "chat-dedup" => self.chat.dedup_window = optional(line, value)?.map(Duration::from_millis),
+                "chat-strip-formatting" => self.chat.strip_formatting = parse(line, value)?,
+                "place-rate" => rate(line, value, &mut self.budget.place)?,
+                "place-burst" => self.budget.place.burst = parse(line, value)?,
+                "dig-rate" => rate(line, value, &mut self.budget.dig)?,
+                "dig-burst" => self.budget.dig.burst = parse(line, value)?,
+                "click-rate" => rate(line, value, &mut self.budget.click)?,
+                "click-burst" => self.budget.click.burst = parse(line, value)?,
+                "swing-rate" => rate(line, value, &mut self.budget.swing)?,
+                "swing-burst" => self.budget.swing.burst = parse(line, value)?,
+                "chat-rate" => rate(line, value, &mut self.budget.chat)?,
+                "chat-burst" => self.budget.chat.burst = parse(line, value)?,
+                "max-rotation" => self.rotation.max_degrees = parse(line, value)?,
+                "rotation-noise" => self.rotation.noise = parse(line, value)?,
+                "rotation-tolerance" => self.rotation.tolerance = parse(line, value)?,
+                "armor-warn" => self.armor.warn_at = parse(line, value)?,
+                "armor-unequip" => self.armor.unequip_at = optional(line, value)?,
+                "inventory-keep" => self.inventory.keep = kinds(line, value)?,
+                "inventory-toss" => self.inventory.toss = kinds(line, value)?,
+                "inventory-min-free" => self.inventory.min_free = parse(line, value)?,
+                "inventory-full" => self.inventory.full = parse(line, value)?,
+                "delay" => self.login.min_delay = millis(line, value)?,
+                "login-jitter" => self.login.jitter = millis(line, value)?,
+                "login-attempts" => self.login.max_attempts = parse(line, value)?,
+                "login-backoff" => self.login.backoff = millis(line, value)?,
+                "login-max-backoff" => self.login.max_backoff = millis(line, value)?,
+                "restart-delay" => self.login.restart_delay = millis(line, value)?,
+                "resync-radius" => self.resync.radius = parse(line, value)?,
+                "resync-threshold" => self.resync.threshold = parse(line, value)?,
+                "resync-window" => self.resync.window = parse(line, value)?,
+                "resync-timeout" => self.resync.timeout = parse(line, value)?,
+                "resync-severe" => self.resync.severe = optional(line, value)?,
+                "missing-column" => self.resync.missing_column = parse(line, value)?,
+                "find-chunks" => self.find.max_chunks = parse(line, value)?,
+                "parkour" => self.path.parkour = parse(line, value)?,
+                "max-fall" => self.path.max_fall = parse(line, value)?,
+                "stuck-ticks" => self.path.stuck.window = parse(line, value)?,
+                "stuck-distance" => self.path.stuck.min_distance = parse(line, value)?,
+                "stuck-attempts" => self.path.stuck.max_attempts = parse(line, value)?,
+                "lookahead" => self.path.lookahead = parse(line, value)?,
+                "index-kinds" => self.index.kinds = parse(line, value)?,
+                "index-budget" => self.index.budget = millis(line, value)?,
+                "keep-light" => self.index.light = parse(line, value)?,
+                "compact-chunks" => self.index.compact = parse(line, value)?,
+                "recover-radius" => self.recover.radius = parse(line, value)?,
+                "travel-spacing" => self.travel.spacing = parse(line, value)?,
+                "travel-max-climb" => self.travel.max_climb = parse(line, value)?,
+                "view-distance" => self.view.distance = parse(line, value)?,
+                "idle-view-distance" => self.view.idle = optional(line, value)?,
+                "idle-view-ticks" => self.view.idle_after = parse(line, value)?,
+                "keep-margin" => self.view.keep_margin = parse(line, value)?,
+                "panic-list" => self.panic.names = names(value),
+                "panic-disconnect" => self.panic.disconnect = parse(line, value)?,
+                key if FIXED.contains(&key) => fixed.push(key),
+                key => return Err(err(&format!("unknown setting {}", key))),
+            }
+        }
+
+        Ok(fixed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::client::state::local::inventory::FullPolicy;
+    use crate::client::tunables::Tunables;
+    use crate::protocol::budget::{Bucket, BudgetConfig};
+    use crate::storage::block::BlockKind;
+    use crate::storage::blocks::MissingColumn;
+
+    #[test]
+    fn test_apply() {
+        let mut tunables = Tunables::default();
+        tunables.armor.unequip_at = Some(5);
+
+        let file = "
+            # slower chat for a strict server
+            chat-interval: 2500
+            chat-dedup: none
+            place-rate: 4
+            place-burst: 1
+            click-rate: none
+            max-rotation: 20.5
+            armor-unequip: none
+            inventory-toss: 3, 4
+            inventory-full: deposit chest
+            login-backoff: 10000
+            resync-severe: 12
+            missing-column: drop
+            parkour: false
+            stuck-ticks: 60
+            lookahead: 2.5
+            index-kinds: 56,52
+            keep-light: true
+            compact-chunks: true
+            recover-radius: 12
+            travel-spacing: 128
+            idle-view-distance: 2
+            keep-margin: 0
+            panic-list: Dinnerbone, jeb_
+        ";
+
+        assert_eq!(tunables.apply(file).unwrap(), Vec::<&str>::new());
+        assert_eq!(tunables.chat.min_interval, Duration::from_millis(2500));
+        assert_eq!(tunables.chat.dedup_window, None);
+        assert_eq!(tunables.budget.place, Bucket::new(4.0, 1.0));
+        assert_eq!(tunables.budget.click, Bucket::UNLIMITED);
+        assert_eq!(tunables.budget.swing, BudgetConfig::default().swing);
+        assert_eq!(tunables.rotation.max_degrees, 20.5);
+        assert_eq!(tunables.armor.unequip_at, None);
+        assert_eq!(tunables.inventory.toss, vec![BlockKind(3), BlockKind::COBBLESTONE]);
+        assert_eq!(tunables.inventory.full, FullPolicy::Deposit("chest".to_string()));
+        assert_eq!(tunables.login.backoff, Duration::from_secs(10));
+        assert_eq!(tunables.resync.severe, Some(12));
+        assert_eq!(tunables.resync.missing_column, MissingColumn::Drop);
+        assert!(!tunables.path.parkour);
+        assert_eq!(tunables.path.stuck.window, 60);
+        assert_eq!(tunables.path.lookahead, 2.5);
+        assert!(tunables.index.kinds.contains(BlockKind::MOB_SPAWNER));
+        assert!(!tunables.index.kinds.contains(BlockKind(16)));
+        assert!(tunables.index.light);
+        assert!(tunables.index.compact);
+        assert_eq!(tunables.recover.radius, 12.0);
+        assert_eq!(tunables.travel.spacing, 128);
+        assert_eq!(tunables.view.idle, Some(2));
+        assert_eq!(tunables.view.keep_margin, 0);
+        assert_eq!(tunables.panic.names, vec!["Dinnerbone", "jeb_"]);
+        assert!(!tunables.panic.disconnect);
+
+        // untouched settings keep their value
+        assert_eq!(tunables.find.max_chunks, 256);
+        assert_eq!(tunables.view.distance, 10);
+    }
+
+    #[test]
+    fn test_fixed() {
+        let mut tunables = Tunables::default();
+        let fixed = tunables.apply("host: example.com\nfind-chunks: 16\nlogin-concurrency: 50").unwrap();
+        assert_eq!(fixed, vec!["host", "login-concurrency"]);
+        assert_eq!(tunables.find.max_chunks, 16);
+        assert_eq!(tunables.login.max_concurrent, 5);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut tunables = Tunables::default();
+        assert!(tunables.apply("chat-interval: soon").is_err());
+        assert!(tunables.apply("chat-intervall: 100").is_err());
+        assert!(tunables.apply("chat-interval 100").is_err());
+    }
+}
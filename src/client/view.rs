@@ -0,0 +1,130 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! How many chunks around a bot the server sends. Bots without a task see less, so the server sends them (and the
+//! world keeps) fewer columns. Columns are also dropped as soon as the server unloads them for every bot (see
+//! [`WorldBlocks::release`](crate::storage::blocks::WorldBlocks::release)).
+
+use std::time::Duration;
+
+use crate::storage::blocks::ChunkLocation;
+
+/// how often the columns no bot sees any more are dropped
+pub const EVICT_INTERVAL: Duration = Duration::from_secs(10);
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ViewConfig {
+    /// the view distance in chunks while a task runs
+    pub distance: u8,
+
+    /// the view distance of bots without a task. None keeps `distance`.
+    pub idle: Option<u8>,
+
+    /// how many ticks a bot has no task before it counts as idle
+    pub idle_after: usize,
+
+    /// columns this many chunks past the view distance of every bot are still kept, so a bot walking along the edge
+    /// of its view does not drop and get the same columns over and over
+    pub keep_margin: i32,
+}
+
+impl Default for ViewConfig {
+    fn default() -> Self {
+        Self {
+            distance: 10,
+            idle: Some(3),
+            idle_after: 20 * 30,
+            keep_margin: 1,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct View {
+    /// the distance the server was told. None until it was told.
+    sent: Option<u8>,
+
+    /// the ticks since a task last ran
+    idle_ticks: usize,
+}
+
+impl View {
+    /// the distance the server was told
+    pub fn distance(&self) -> Option<u8> {
+        self.sent
+    }
+
+    /// Called every tick with whether a task runs. Returns the distance to tell the server if it changed.
+    pub fn tick(&mut self, busy: bool, config: &ViewConfig) -> Option<u8> {
+        self.idle_ticks = if busy { 0 } else { self.idle_ticks.saturating_add(1) };
+
+        let distance = match config.idle {
+            Some(idle) if self.idle_ticks > config.idle_after => idle,
+            _ => config.distance,
+        };
+
+        if self.sent == Some(distance) {
+            return None;
+        }
+        self.sent = Some(distance);
+        Some(distance)
+    }
+}
+
+/// whether the server sends `column` to a player in `center` seeing `distance` chunks (a square around it)
+pub fn in_view(center: ChunkLocation, distance: i32, column: ChunkLocation) -> bool {
+    (column.0 - center.0).abs() <= distance && (column.1 - center.1).abs() <= distance
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::view::{in_view, View, ViewConfig};
+    use crate::storage::blocks::ChunkLocation;
+
+    #[test]
+    fn test_idle() {
+        let config = ViewConfig { distance: 8, idle: Some(2), idle_after: 10, keep_margin: 1 };
+        let mut view = View::default();
+
+        // the server is told right away and then only on changes
+        assert_eq!(view.tick(true, &config), Some(8));
+        assert_eq!(view.tick(true, &config), None);
+
+        // a short break between tasks keeps the distance
+        for _ in 0..10 {
+            assert_eq!(view.tick(false, &config), None);
+        }
+        assert_eq!(view.tick(false, &config), Some(2));
+        assert_eq!(view.distance(), Some(2));
+        assert_eq!(view.tick(false, &config), None);
+
+        assert_eq!(view.tick(true, &config), Some(8));
+
+        let always = ViewConfig { idle: None, ..config };
+        for _ in 0..100 {
+            assert_eq!(view.tick(false, &always), None);
+        }
+    }
+
+    #[test]
+    fn test_in_view() {
+        let center = ChunkLocation(3, -2);
+        assert!(in_view(center, 2, ChunkLocation(5, -4)));
+        assert!(!in_view(center, 2, ChunkLocation(6, -2)));
+        assert!(in_view(center, 0, center));
+    }
+}
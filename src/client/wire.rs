@@ -0,0 +1,291 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! How WebSocket clients talk to us. Without a hello everything is JSON text frames, exactly as before versioning
+//! existed. A client can send `{"path": "hello", "version": 1, "encoding": "message_pack", "subscriptions": [..]}`
+//! (or connect with the [`SUBPROTOCOL`] WebSocket subprotocol) to get binary MessagePack frames and to subscribe to
+//! the position and event streams.
+//!
+//! Every message is built as a [`Value`] from the same serde types no matter how it is encoded.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+pub mod msgpack;
+
+/// the version of the message schema. Bumped whenever a message changes in a way old clients would misread.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// the oldest version we can still talk
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// a WebSocket subprotocol which starts the connection in MessagePack mode at [`PROTOCOL_VERSION`]
+pub const SUBPROTOCOL: &str = "swarmbot.msgpack.v1";
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    Json,
+    MessagePack,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Json
+    }
+}
+
+impl Encoding {
+    pub fn frame(self, value: &Value) -> Message {
+        match self {
+            Encoding::Json => Message::Text(value.to_string()),
+            Encoding::MessagePack => Message::Binary(msgpack::encode(value)),
+        }
+    }
+}
+
+/// What a message is about. Replies to commands are always sent; the other topics have to be subscribed to.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    /// the location of every bot, every tick
+    Position,
+
+    /// tasks starting, finishing and being cancelled
+    Event,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Subscription {
+    pub topic: Topic,
+
+    /// at most one message per bot this often. Everything is sent if 0.
+    #[serde(default)]
+    pub interval_ms: u64,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct Hello {
+    pub version: u32,
+
+    #[serde(default)]
+    pub encoding: Encoding,
+
+    #[serde(default)]
+    pub subscriptions: Vec<Subscription>,
+}
+
+/// Which stream messages a connection receives and how often
+#[derive(Debug, Default)]
+pub struct Filter {
+    intervals: HashMap<Topic, Duration>,
+
+    /// when a message of the topic was last sent for a bot
+    last: HashMap<(Topic, String), Instant>,
+}
+
+impl Filter {
+    pub fn new(subscriptions: &[Subscription]) -> Filter {
+        let intervals = subscriptions.iter()
+            .map(|sub| (sub.topic, Duration::from_millis(sub.interval_ms)))
+            .collect();
+
+        Filter { intervals, last: HashMap::new() }
+    }
+
+    pub fn subscribed(&self, topic: Topic) -> bool {
+        self.intervals.contains_key(&topic)
+    }
+
+    /// whether a message of `topic` about `bot` should be sent at `now`. Admitting a message starts a new interval.
+    pub fn admit(&mut self, topic: Topic, bot: &str, now: Instant) -> bool {
+        let interval = match self.intervals.get(&topic) {
+            None => return false,
+            Some(interval) => *interval,
+        };
+
+        if interval == Duration::ZERO {
+            return true;
+        }
+
+        match self.last.get_mut(&(topic, bot.to_string())) {
+            Some(last) if now.duration_since(*last) < interval => false,
+            Some(last) => {
+                *last = now;
+                true
+            }
+            None => {
+                self.last.insert((topic, bot.to_string()), now);
+                true
+            }
+        }
+    }
+}
+
+/// The state of one WebSocket connection
+#[derive(Debug, Default)]
+pub struct Connection {
+    /// 0 until the client said hello
+    pub version: u32,
+    pub encoding: Encoding,
+    pub filter: Filter,
+}
+
+impl Connection {
+    /// a connection which asked for [`SUBPROTOCOL`] when connecting
+    pub fn subprotocol() -> Connection {
+        Connection {
+            version: PROTOCOL_VERSION,
+            encoding: Encoding::MessagePack,
+            filter: Filter::default(),
+        }
+    }
+
+    /// Agree on the version. A client newer than us has to talk our version, a client older than
+    /// [`MIN_PROTOCOL_VERSION`] is refused and the connection stays as it was. Returns the reply to send.
+    pub fn hello(&mut self, hello: Hello) -> Value {
+        if hello.version < MIN_PROTOCOL_VERSION {
+            return json!({
+                "type": "hello",
+                "ok": false,
+                "error": format!("unsupported protocol version {}, supported are {} to {}", hello.version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION),
+                "min_version": MIN_PROTOCOL_VERSION,
+                "max_version": PROTOCOL_VERSION,
+            });
+        }
+
+        self.version = hello.version.min(PROTOCOL_VERSION);
+        self.encoding = hello.encoding;
+        self.filter = Filter::new(&hello.subscriptions);
+
+        json!({
+            "type": "hello",
+            "ok": true,
+            "version": self.version,
+            "encoding": self.encoding,
+            "subscriptions": hello.subscriptions,
+        })
+    }
+}
+
+/// Decode a frame sent by a client. None for frames which are not messages (pings, closing) or cannot be decoded.
+pub fn decode(message: Message) -> Option<Value> {
+    match message {
+        Message::Text(text) => serde_json::from_str(&text).ok(),
+        Message::Binary(data) => msgpack::decode(&data).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use crate::client::wire::{Connection, decode, Encoding, Filter, Hello, msgpack, PROTOCOL_VERSION, Subscription, Topic};
+
+    fn hello(value: serde_json::Value) -> Hello {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_hello() {
+        let mut connection = Connection::default();
+        assert_eq!(connection.encoding, Encoding::Json);
+
+        let reply = connection.hello(hello(json!({
+            "version": 1,
+            "encoding": "message_pack",
+            "subscriptions": [{"topic": "position", "interval_ms": 1000}, {"topic": "event"}]
+        })));
+
+        assert_eq!(reply, json!({
+            "type": "hello",
+            "ok": true,
+            "version": 1,
+            "encoding": "message_pack",
+            "subscriptions": [{"topic": "position", "interval_ms": 1000}, {"topic": "event", "interval_ms": 0}]
+        }));
+        assert_eq!(connection.encoding, Encoding::MessagePack);
+        assert!(connection.filter.subscribed(Topic::Position));
+
+        // the reply is sent in the new encoding
+        match connection.encoding.frame(&reply) {
+            Message::Binary(data) => assert_eq!(msgpack::decode(&data).unwrap(), reply),
+            other => panic!("expected a binary frame, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        // a client from the future has to talk our version
+        let mut connection = Connection::default();
+        let reply = connection.hello(hello(json!({"version": PROTOCOL_VERSION + 3})));
+        assert_eq!(reply["ok"], json!(true));
+        assert_eq!(reply["version"], PROTOCOL_VERSION);
+        assert_eq!(connection.version, PROTOCOL_VERSION);
+
+        // a client which is too old is refused and nothing changes
+        let mut connection = Connection::default();
+        let reply = connection.hello(hello(json!({"version": 0, "encoding": "message_pack", "subscriptions": [{"topic": "event"}]})));
+        assert_eq!(reply["ok"], json!(false));
+        assert_eq!(reply["max_version"], PROTOCOL_VERSION);
+        assert_eq!(connection.encoding, Encoding::Json);
+        assert!(!connection.filter.subscribed(Topic::Event));
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut filter = Filter::new(&[
+            Subscription { topic: Topic::Position, interval_ms: 1000 },
+            Subscription { topic: Topic::Event, interval_ms: 0 },
+        ]);
+
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        // positions at 1 Hz per bot
+        assert!(filter.admit(Topic::Position, "a", at(0)));
+        assert!(!filter.admit(Topic::Position, "a", at(50)));
+        assert!(filter.admit(Topic::Position, "b", at(50)));
+        assert!(!filter.admit(Topic::Position, "a", at(999)));
+        assert!(filter.admit(Topic::Position, "a", at(1000)));
+        assert!(!filter.admit(Topic::Position, "b", at(1000)));
+
+        // events right away
+        assert!(filter.admit(Topic::Event, "a", at(1000)));
+        assert!(filter.admit(Topic::Event, "a", at(1000)));
+
+        // nothing is subscribed without a hello
+        let mut filter = Filter::default();
+        assert!(!filter.admit(Topic::Position, "a", at(0)));
+        assert!(!filter.admit(Topic::Event, "a", at(0)));
+    }
+
+    #[test]
+    fn test_decode() {
+        let value = json!({"path": "run", "name": "health"});
+        assert_eq!(decode(Message::Text(value.to_string())), Some(value.clone()));
+        assert_eq!(decode(Message::Binary(msgpack::encode(&value))), Some(value));
+        assert_eq!(decode(Message::Text("not json".to_string())), None);
+        assert_eq!(decode(Message::Ping(vec![])), None);
+    }
+}
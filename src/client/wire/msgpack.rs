@@ -0,0 +1,291 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! MessagePack for the WebSocket API. Messages are serde types turned into a [`Value`] first, so both encodings
+//! share the exact same schema.
+//!
+//! https://github.com/msgpack/msgpack/blob/master/spec.md
+
+use std::convert::TryInto;
+
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+
+/// how deeply arrays and maps may be nested in a decoded message
+const MAX_DEPTH: usize = 64;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum MsgPackError {
+    #[error("unexpected end of messagepack data")]
+    UnexpectedEof,
+
+    #[error("unsupported messagepack marker {0:#x}")]
+    Unsupported(u8),
+
+    #[error("messagepack map keys must be strings")]
+    KeyNotString,
+
+    #[error("messagepack string is not utf-8")]
+    Utf8,
+
+    #[error("messagepack float {0} cannot be represented")]
+    Float(f64),
+
+    #[error("messagepack data is nested too deeply")]
+    TooDeep,
+
+    #[error("{0} bytes left over after the messagepack value")]
+    Trailing(usize),
+}
+
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    write(value, &mut out);
+    out
+}
+
+fn write_len(len: usize, fix: u8, fix_max: usize, markers: [u8; 3], out: &mut Vec<u8>) {
+    if len <= fix_max {
+        out.push(fix | len as u8);
+    } else if len <= u8::MAX as usize && markers[0] != 0 {
+        out.push(markers[0]);
+        out.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(markers[1]);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(markers[2]);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+fn write(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(false) => out.push(0xc2),
+        Value::Bool(true) => out.push(0xc3),
+        Value::Number(number) => {
+            if let Some(int) = number.as_u64() {
+                match int {
+                    0..=0x7f => out.push(int as u8),
+                    0x80..=0xff => out.extend_from_slice(&[0xcc, int as u8]),
+                    0x100..=0xffff => {
+                        out.push(0xcd);
+                        out.extend_from_slice(&(int as u16).to_be_bytes());
+                    }
+                    0x1_0000..=0xffff_ffff => {
+                        out.push(0xce);
+                        out.extend_from_slice(&(int as u32).to_be_bytes());
+                    }
+                    _ => {
+                        out.push(0xcf);
+                        out.extend_from_slice(&int.to_be_bytes());
+                    }
+                }
+            } else if let Some(int) = number.as_i64() {
+                // only negative numbers are left
+                if int >= -32 {
+                    out.push(int as i8 as u8);
+                } else if int >= i8::MIN as i64 {
+                    out.extend_from_slice(&[0xd0, int as i8 as u8]);
+                } else if int >= i16::MIN as i64 {
+                    out.push(0xd1);
+                    out.extend_from_slice(&(int as i16).to_be_bytes());
+                } else if int >= i32::MIN as i64 {
+                    out.push(0xd2);
+                    out.extend_from_slice(&(int as i32).to_be_bytes());
+                } else {
+                    out.push(0xd3);
+                    out.extend_from_slice(&int.to_be_bytes());
+                }
+            } else {
+                out.push(0xcb);
+                out.extend_from_slice(&number.as_f64().unwrap_or_default().to_be_bytes());
+            }
+        }
+        Value::String(string) => {
+            write_len(string.len(), 0xa0, 31, [0xd9, 0xda, 0xdb], out);
+            out.extend_from_slice(string.as_bytes());
+        }
+        Value::Array(array) => {
+            write_len(array.len(), 0x90, 15, [0, 0xdc, 0xdd], out);
+            for elem in array {
+                write(elem, out);
+            }
+        }
+        Value::Object(map) => {
+            write_len(map.len(), 0x80, 15, [0, 0xde, 0xdf], out);
+            for (key, value) in map {
+                write(&Value::String(key.clone()), out);
+                write(value, out);
+            }
+        }
+    }
+}
+
+/// Decode a single value. Binary and extension types are not used by the API and are rejected.
+pub fn decode(data: &[u8]) -> Result<Value, MsgPackError> {
+    let mut reader = Reader { data };
+    let value = reader.value(0)?;
+    match reader.data.len() {
+        0 => Ok(value),
+        left => Err(MsgPackError::Trailing(left)),
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MsgPackError> {
+        if self.data.len() < len {
+            return Err(MsgPackError::UnexpectedEof);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], MsgPackError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    /// the length following a str8/16/32, array16/32 or map16/32 marker
+    fn len(&mut self, bytes: usize) -> Result<usize, MsgPackError> {
+        Ok(match bytes {
+            1 => self.array::<1>()?[0] as usize,
+            2 => u16::from_be_bytes(self.array()?) as usize,
+            _ => u32::from_be_bytes(self.array()?) as usize,
+        })
+    }
+
+    fn string(&mut self, len: usize) -> Result<String, MsgPackError> {
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| MsgPackError::Utf8)
+    }
+
+    fn float(value: f64) -> Result<Value, MsgPackError> {
+        Number::from_f64(value).map(Value::Number).ok_or(MsgPackError::Float(value))
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, MsgPackError> {
+        if depth > MAX_DEPTH {
+            return Err(MsgPackError::TooDeep);
+        }
+
+        let marker = self.array::<1>()?[0];
+        let value = match marker {
+            0x00..=0x7f => Value::from(marker),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize, depth)?,
+            0x90..=0x9f => self.elems((marker & 0x0f) as usize, depth)?,
+            0xa0..=0xbf => Value::String(self.string((marker & 0x1f) as usize)?),
+            0xc0 => Value::Null,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xca => Self::float(f32::from_be_bytes(self.array()?) as f64)?,
+            0xcb => Self::float(f64::from_be_bytes(self.array()?))?,
+            0xcc => Value::from(self.array::<1>()?[0]),
+            0xcd => Value::from(u16::from_be_bytes(self.array()?)),
+            0xce => Value::from(u32::from_be_bytes(self.array()?)),
+            0xcf => Value::from(u64::from_be_bytes(self.array()?)),
+            0xd0 => Value::from(self.array::<1>()?[0] as i8),
+            0xd1 => Value::from(i16::from_be_bytes(self.array()?)),
+            0xd2 => Value::from(i32::from_be_bytes(self.array()?)),
+            0xd3 => Value::from(i64::from_be_bytes(self.array()?)),
+            0xd9 | 0xda | 0xdb => {
+                let len = self.len(1 << (marker - 0xd9))?;
+                Value::String(self.string(len)?)
+            }
+            0xdc | 0xdd => {
+                let len = self.len(2 << (marker - 0xdc))?;
+                self.elems(len, depth)?
+            }
+            0xde | 0xdf => {
+                let len = self.len(2 << (marker - 0xde))?;
+                self.map(len, depth)?
+            }
+            0xe0..=0xff => Value::from(marker as i8),
+            marker => return Err(MsgPackError::Unsupported(marker)),
+        };
+        Ok(value)
+    }
+
+    fn elems(&mut self, len: usize, depth: usize) -> Result<Value, MsgPackError> {
+        // every element is at least one byte, so a huge length cannot make us allocate much
+        if len > self.data.len() {
+            return Err(MsgPackError::UnexpectedEof);
+        }
+
+        let mut elems = Vec::with_capacity(len);
+        for _ in 0..len {
+            elems.push(self.value(depth + 1)?);
+        }
+        Ok(Value::Array(elems))
+    }
+
+    fn map(&mut self, len: usize, depth: usize) -> Result<Value, MsgPackError> {
+        let mut map = Map::new();
+        for _ in 0..len {
+            let key = match self.value(depth + 1)? {
+                Value::String(key) => key,
+                _ => return Err(MsgPackError::KeyNotString),
+            };
+            let value = self.value(depth + 1)?;
+            map.insert(key, value);
+        }
+        Ok(Value::Object(map))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::client::wire::msgpack::{decode, encode, MsgPackError};
+
+    #[test]
+    fn test_round_trip() {
+        let value = json!({
+            "type": "position",
+            "bot": "bob",
+            "location": {"x": 1.5, "y": 64.0, "z": -2.5},
+            "ints": [0, 127, 128, 255, 256, 65535, 65536, 4294967296_u64, -1, -32, -33, -128, -129, -32768, -32769, -2147483649_i64],
+            "flags": [true, false, null],
+            "long": "a string which is longer than thirty-one bytes",
+        });
+
+        assert_eq!(decode(&encode(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn test_spec_bytes() {
+        // {"compact": true, "schema": 0} from msgpack.org
+        let value = json!({"compact": true, "schema": 0});
+        let bytes = [0x82, 0xa7, b'c', b'o', b'm', b'p', b'a', b'c', b't', 0xc3, 0xa6, b's', b'c', b'h', b'e', b'm', b'a', 0x00];
+        assert_eq!(encode(&value), bytes);
+        assert_eq!(decode(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(decode(&[0xdc, 0xff, 0xff]), Err(MsgPackError::UnexpectedEof));
+        assert_eq!(decode(&[0xa5, b'a']), Err(MsgPackError::UnexpectedEof));
+        assert_eq!(decode(&[0xc4, 0x00]), Err(MsgPackError::Unsupported(0xc4)));
+        assert_eq!(decode(&[0x81, 0x01, 0x01]), Err(MsgPackError::KeyNotString));
+        assert_eq!(decode(&[0x01, 0x02]), Err(MsgPackError::Trailing(1)));
+        assert_eq!(decode(&[0x91; 100]), Err(MsgPackError::TooDeep));
+    }
+}
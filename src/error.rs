@@ -20,6 +20,10 @@ use reqwest::StatusCode;
 
 use swarm_bot_packets::types::PacketState;
 
+use crate::client::tasks::TaskError;
+use crate::nbt::NbtError;
+use crate::storage::block::BlockLocation;
+
 pub type Res<T = ()> = Result<T, Error>;
 pub type ResBox<T = ()> = Result<T, Box<dyn std::error::Error>>;
 pub type ResContext<T = ()> = Result<T, ErrorContext<Error>>;
@@ -39,32 +43,67 @@ pub enum Error {
     },
     Simple(String),
     Mojang(MojangErr),
+
+    /// the server disconnected us while logging in
+    Disconnect(String),
+    Nbt(NbtError),
 }
 
+impl std::error::Error for Error {}
+
 impl From<serde_json::Error> for Error {
     fn from(err: serde_json::Error) -> Self {
         Self::Serde(err)
     }
 }
 
+/// A failed request to Mojang, with the endpoint and the proxy it went through
 #[derive(Debug)]
-pub enum MojangErr {
+pub struct MojangErr {
+    pub endpoint: &'static str,
+    pub proxy: String,
+    pub kind: MojangErrKind,
+}
+
+#[derive(Debug)]
+pub enum MojangErrKind {
+    /// Mojang refused the request, i.e., a wrong password or an expired session. Never retried.
     InvalidCredentials {
         error_code: StatusCode,
         info: Option<String>,
     },
+
+    /// 429 Too Many Requests
+    RateLimited,
+    Timeout,
+
+    /// a 5xx status
+    Server(StatusCode),
+
+    /// the proxy or Mojang could not be reached
+    Connection(String),
+
+    /// the response was not the JSON we expected
+    Malformed(String),
 }
 
 impl Display for MojangErr {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            MojangErr::InvalidCredentials { error_code, info } => {
-                f.write_fmt(format_args!("mojang err #{} info {}", error_code, info.clone().unwrap_or_default()))
+        write!(f, "mojang {} through {}: ", self.endpoint, self.proxy)?;
+        match &self.kind {
+            MojangErrKind::InvalidCredentials { error_code, info } => {
+                f.write_fmt(format_args!("err #{} info {}", error_code, info.clone().unwrap_or_default()))
             }
+            MojangErrKind::RateLimited => f.write_str("rate limited"),
+            MojangErrKind::Timeout => f.write_str("timed out"),
+            MojangErrKind::Server(status) => write!(f, "server error {}", status),
+            MojangErrKind::Connection(reason) => write!(f, "could not connect ({})", reason),
+            MojangErrKind::Malformed(reason) => write!(f, "malformed response ({})", reason),
         }
     }
 }
 
+impl std::error::Error for MojangErr {}
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -77,7 +116,9 @@ impl Display for Error {
             Error::Mojang(inner) => std::fmt::Display::fmt(inner, f),
             Error::WrongPacket { state, actual, expected } => f.write_fmt(format_args!("wrong packet. Expected ID {}, got {} in state {}", expected, actual, state)),
             Error::Resolve(r) => std::fmt::Display::fmt(r, f),
-            Error::Serde(s) => std::fmt::Display::fmt(s, f)
+            Error::Serde(s) => std::fmt::Display::fmt(s, f),
+            Error::Disconnect(reason) => f.write_fmt(format_args!("disconnected while logging in: {}", reason)),
+            Error::Nbt(nbt) => std::fmt::Display::fmt(nbt, f),
         }
     }
 }
@@ -98,6 +139,12 @@ impl From<trust_dns_resolver::error::ResolveError> for Error {
     }
 }
 
+impl From<NbtError> for Error {
+    fn from(err: NbtError) -> Self {
+        Self::Nbt(err)
+    }
+}
+
 impl From<MojangErr> for Error {
     fn from(err: MojangErr) -> Self {
         Self::Mojang(err)
@@ -168,3 +215,122 @@ impl<T, E: Into<Error>> HasContext<T, Error> for Result<T, E> {
         }
     }
 }
+
+/// the step of connecting (or playing) a bot was at when something failed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Phase {
+    /// getting a session or joining it at Mojang
+    Auth,
+
+    /// connecting to the server through the proxy
+    Connect,
+    Encryption,
+
+    /// the handshake, compression and logging in
+    Join,
+    Play,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Phase::Auth => "auth",
+            Phase::Connect => "connect",
+            Phase::Encryption => "encryption",
+            Phase::Join => "join",
+            Phase::Play => "play",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Something the world could not answer
+#[derive(Debug, thiserror::Error)]
+pub enum WorldError {
+    #[error("chunk {x} {z} is not loaded")]
+    MissingChunk { x: i32, z: i32 },
+
+    #[error("{location} is outside of the world")]
+    InvalidLocation { location: BlockLocation },
+}
+
+/// An error with enough context to tell which bot (and which proxy or phase) it came from by the message alone
+#[derive(Debug, thiserror::Error)]
+pub enum SwarmError {
+    #[error("{account}: {source}")]
+    Auth { account: String, source: MojangErr },
+
+    #[error("{account}: could not connect through proxy {proxy}: {source}")]
+    Proxy { account: String, proxy: String, source: std::io::Error },
+
+    #[error("{account}: {phase} failed{}: {source}", at_packet(.packet))]
+    Protocol { account: String, phase: Phase, packet: Option<u32>, source: Error },
+
+    #[error("task {name} failed: {reason}")]
+    Task { name: &'static str, reason: TaskError },
+
+    #[error(transparent)]
+    World(#[from] WorldError),
+}
+
+fn at_packet(packet: &Option<u32>) -> String {
+    packet.map(|id| format!(" at packet {:#04x}", id)).unwrap_or_default()
+}
+
+impl SwarmError {
+    /// `error` of `account` in `phase`. Errors of Mojang are auth errors whatever the phase.
+    pub fn new(account: &str, phase: Phase, error: Error) -> SwarmError {
+        let account = account.to_string();
+        match error {
+            Error::Mojang(source) => SwarmError::Auth { account, source },
+            source => {
+                let packet = match source {
+                    Error::WrongPacket { actual, .. } => Some(actual),
+                    _ => None,
+                };
+                SwarmError::Protocol { account, phase, packet, source }
+            }
+        }
+    }
+}
+
+/// Attaches the account (and phase) to errors at the point they are known, i.e., `mojang.join(..).await.ctx_account(name)?`
+pub trait SwarmContext<T>: Sized {
+    fn ctx_phase(self, account: &str, phase: Phase) -> Result<T, SwarmError>;
+
+    /// an error getting the session of `account`
+    fn ctx_account(self, account: &str) -> Result<T, SwarmError> {
+        self.ctx_phase(account, Phase::Auth)
+    }
+}
+
+impl<T, E: Into<Error>> SwarmContext<T> for Result<T, E> {
+    fn ctx_phase(self, account: &str, phase: Phase) -> Result<T, SwarmError> {
+        self.map_err(|error| SwarmError::new(account, phase, error.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use swarm_bot_packets::types::PacketState;
+
+    use crate::error::{Error, MojangErr, MojangErrKind, Phase, SwarmContext, SwarmError};
+
+    #[test]
+    fn test_context() {
+        let rejected: Result<(), Error> = Err(MojangErr { endpoint: "join", proxy: "10.0.0.1:1080".to_string(), kind: MojangErrKind::InvalidCredentials { error_code: StatusCode::FORBIDDEN, info: None } }.into());
+        let err = rejected.ctx_phase("abc", Phase::Encryption).unwrap_err();
+        assert!(matches!(err, SwarmError::Auth { .. }));
+        assert_eq!(err.to_string(), "abc: mojang join through 10.0.0.1:1080: err #403 Forbidden info ");
+
+        let wrong: Result<(), Error> = Err(Error::WrongPacket { state: PacketState::Login, expected: 2, actual: 0x1a });
+        let err = wrong.ctx_phase("abc", Phase::Join).unwrap_err();
+        assert!(matches!(err, SwarmError::Protocol { phase: Phase::Join, packet: Some(0x1a), .. }));
+        assert!(err.to_string().starts_with("abc: join failed at packet 0x1a: wrong packet"));
+
+        let dead = SwarmError::Proxy { account: "abc".to_string(), proxy: "10.0.0.2:1080".to_string(), source: std::io::ErrorKind::TimedOut.into() };
+        assert_eq!(dead.to_string(), "abc: could not connect through proxy 10.0.0.2:1080: timed out");
+    }
+}
@@ -0,0 +1,415 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Starting a swarm from code rather than the command line, see [`Swarm`]
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use futures::FutureExt;
+use futures::future::LocalBoxFuture;
+use tokio::sync::mpsc::Receiver;
+use tracing::Instrument;
+
+use crate::bootstrap::{Address, CSVUser, Proxy};
+use crate::bootstrap::dns::normalize_address;
+use crate::bootstrap::cache::SessionKey;
+use crate::bootstrap::mojang::MojangConfig;
+use crate::bootstrap::proxy::{CHECK_INTERVAL, CHECK_TIMEOUT, ProxyPool};
+use crate::bootstrap::servers::{Accounts, offline_users, ServerTarget, Split};
+use crate::bootstrap::storage::{ProxyUser, UserCache};
+use crate::client::bases::BaseConfig;
+use crate::client::commands::CommandHub;
+use crate::client::controller::{Controller, ControllerFactory};
+use crate::client::digest::DigestOptions;
+use crate::client::permissions::Permissions;
+use crate::client::runner::{Runner, RunnerOptions};
+use crate::client::shutdown::Shutdown;
+use crate::client::snapshot::Snapshot;
+use crate::client::state::global::waypoints::Waypoints;
+use crate::client::tunables::Tunables;
+use crate::error::{err, HasContext, ResContext};
+use crate::protocol::{self, ClientInfo};
+
+/// where sessions are cached unless [`Swarm::sessions`] says otherwise
+pub const SESSIONS_FILE: &str = "sessions.json";
+
+/// where `#waypoint`s are saved unless [`Swarm::waypoints`] says otherwise
+pub const WAYPOINTS_FILE: &str = "waypoints.json";
+
+/// where the task queues are saved unless [`Swarm::snapshot`] says otherwise
+pub const SNAPSHOT_FILE: &str = "snapshot.json";
+
+/// Logs bots into a server (or several, see [`Swarm::server`]) and runs them until Ctrl-C or SIGTERM. Has to run on a
+/// tokio [`LocalSet`](tokio::task::LocalSet) as connections are not `Send`.
+///
+/// ```no_run
+/// # async fn start(users: Vec<swarm_bot::CSVUser>, proxies: Vec<swarm_bot::Proxy>) {
+/// swarm_bot::Swarm::new("localhost")
+///     .accounts(users)
+///     .proxies(proxies)
+///     .bots(5)
+///     .run()
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub struct Swarm {
+    host: String,
+    port: u16,
+
+    /// the protocol version, 0 to ping the server for it
+    version: usize,
+    users: Vec<CSVUser>,
+    proxies: Vec<Proxy>,
+    bots: usize,
+    reserve: usize,
+    sessions: PathBuf,
+    fresh_auth: bool,
+    sessions_key: Option<SessionKey>,
+    mojang: MojangConfig,
+    config: Tunables,
+    config_file: Option<PathBuf>,
+    recording_dir: Option<PathBuf>,
+    digest: DigestOptions,
+    bases: BaseConfig,
+    permissions: Permissions,
+    waypoints: PathBuf,
+    snapshot: PathBuf,
+    resume: bool,
+    controllers: Option<ControllerFactory>,
+    servers: Vec<ServerTarget>,
+}
+
+impl Swarm {
+    /// one bot for `host` on the default port, in the version the server reports
+    pub fn new(host: impl Into<String>) -> Swarm {
+        Swarm {
+            host: host.into(),
+            port: 25565,
+            version: 0,
+            users: Vec::new(),
+            proxies: Vec::new(),
+            bots: 1,
+            reserve: 0,
+            sessions: PathBuf::from(SESSIONS_FILE),
+            fresh_auth: false,
+            sessions_key: None,
+            mojang: MojangConfig::default(),
+            config: Tunables::default(),
+            config_file: None,
+            recording_dir: None,
+            digest: DigestOptions::default(),
+            bases: BaseConfig::default(),
+            permissions: Permissions::default(),
+            waypoints: PathBuf::from(WAYPOINTS_FILE),
+            snapshot: PathBuf::from(SNAPSHOT_FILE),
+            resume: false,
+            controllers: None,
+            servers: Vec::new(),
+        }
+    }
+
+    pub fn port(mut self, port: u16) -> Swarm {
+        self.port = port;
+        self
+    }
+
+    /// the protocol version (340 for 1.12.2 or 754 for 1.16.5). 0 pings the server for it.
+    pub fn version(mut self, version: usize) -> Swarm {
+        self.version = version;
+        self
+    }
+
+    /// the Mojang accounts to log in with
+    pub fn accounts(mut self, users: Vec<CSVUser>) -> Swarm {
+        self.users = users;
+        self
+    }
+
+    /// the proxies to connect through. Only those which work are used.
+    pub fn proxies(mut self, proxies: Vec<Proxy>) -> Swarm {
+        self.proxies = proxies;
+        self
+    }
+
+    /// how many bots to keep online
+    pub fn bots(mut self, count: usize) -> Swarm {
+        self.bots = count;
+        self
+    }
+
+    /// how many more accounts to authenticate for `#swarm add` and replacing banned bots
+    pub fn reserve(mut self, count: usize) -> Swarm {
+        self.reserve = count;
+        self
+    }
+
+    /// where sessions are cached. A `fresh` login ignores the cache.
+    pub fn sessions(mut self, path: impl Into<PathBuf>, fresh: bool) -> Swarm {
+        self.sessions = path.into();
+        self.fresh_auth = fresh;
+        self
+    }
+
+    /// encrypt the sessions file with a key derived from `passphrase`
+    pub fn sessions_key(mut self, passphrase: &str) -> Swarm {
+        self.sessions_key = Some(SessionKey::new(passphrase));
+        self
+    }
+
+    /// timeouts and retries of requests to Mojang
+    pub fn mojang(mut self, mojang: MojangConfig) -> Swarm {
+        self.mojang = mojang;
+        self
+    }
+
+    /// the settings which `#reload-config` can change
+    pub fn config(mut self, config: Tunables) -> Swarm {
+        self.config = config;
+        self
+    }
+
+    /// a file of settings taking precedence over [`Swarm::config`], read again on `#reload-config`
+    pub fn config_file(mut self, path: impl Into<PathBuf>) -> Swarm {
+        self.config_file = Some(path.into());
+        self
+    }
+
+    /// record the session of every bot in `dir`
+    pub fn record(mut self, dir: impl Into<PathBuf>) -> Swarm {
+        self.recording_dir = Some(dir.into());
+        self
+    }
+
+    pub fn digest(mut self, digest: DigestOptions) -> Swarm {
+        self.digest = digest;
+        self
+    }
+
+    pub fn bases(mut self, bases: BaseConfig) -> Swarm {
+        self.bases = bases;
+        self
+    }
+
+    /// who may command the bots through chat
+    pub fn permissions(mut self, permissions: Permissions) -> Swarm {
+        self.permissions = permissions;
+        self
+    }
+
+    /// where the waypoints set with `#waypoint` are saved and read from on start
+    pub fn waypoints(mut self, path: impl Into<PathBuf>) -> Swarm {
+        self.waypoints = path.into();
+        self
+    }
+
+    /// where the task queues are saved on shutdown and every few minutes. With `resume` the bots carry on with the
+    /// tasks saved there by the last run.
+    pub fn snapshot(mut self, path: impl Into<PathBuf>, resume: bool) -> Swarm {
+        self.snapshot = path.into();
+        self.resume = resume;
+        self
+    }
+
+    /// drive each bot with the [`Controller`] `make` returns for it
+    pub fn controller(mut self, make: impl FnMut(&ClientInfo) -> Box<dyn Controller> + 'static) -> Swarm {
+        self.controllers = Some(Box::new(make));
+        self
+    }
+
+    /// Run some of the bots on another server. Each server has its own world and bots while the proxies and sessions
+    /// are shared. Once a server is added, the host, port, version and bot count of the swarm itself are not used.
+    pub fn server(mut self, server: ServerTarget) -> Swarm {
+        self.servers.push(server);
+        self
+    }
+
+    /// the servers added with [`Swarm::server`], or the one given to [`Swarm::new`] if there are none
+    async fn targets(&mut self) -> Vec<(ServerTarget, Address)> {
+        let servers = if self.servers.is_empty() {
+            vec![ServerTarget { port: self.port, version: self.version, ..ServerTarget::new(&self.host, &self.host, Accounts::Count(self.bots)) }]
+        } else {
+            std::mem::take(&mut self.servers)
+        };
+
+        let mut targets = Vec::with_capacity(servers.len());
+        for server in servers {
+            let address = normalize_address(&server.host, server.port).await;
+            targets.push((server, address));
+        }
+        targets
+    }
+
+    /// only authenticate the accounts (and cache the sessions) without logging in
+    pub async fn load(mut self) -> ResContext {
+        let servers = self.targets().await;
+        let (_, users) = self.obtain_users(&servers).await?;
+        for mut users in users {
+            while users.recv().await.is_some() {
+                // empty
+            }
+        }
+        Ok(())
+    }
+
+    /// log the bots in and run them until a shutdown
+    pub async fn run(mut self) -> ResContext {
+        let mut servers = self.targets().await;
+
+        for (server, address) in &mut servers {
+            if server.version == 0 {
+                let status = protocol::ping(&address.host, address.port).await
+                    .context(|| format!("could not detect the version of {}", String::from(&*address)))?;
+                info!(address = %String::from(&*address), version = %status.version, protocol = status.protocol, "detected the version");
+                server.version = status.supported_version().context_str("could not pick a version")?;
+            }
+        }
+
+        let config = match self.config_file.as_ref() {
+            None => self.config.clone(),
+            Some(path) => Tunables::load(path, &self.config).context(|| format!("could not load config {}", path.display()))?,
+        };
+
+        let (proxies, users) = self.obtain_users(&servers).await?;
+
+        // the runners share the websocket server and the signal listener
+        let hub = CommandHub::init().await.context_str("could not listen for commands")?;
+        let shutdown = Shutdown::listen();
+
+        let rate_limit = self.mojang.rate_limit.clone();
+        let controllers = self.controllers.take().map(|make| Rc::new(RefCell::new(make)));
+        let scoped = servers.len() > 1;
+
+        let mut runs: Vec<LocalBoxFuture<'static, ResContext>> = Vec::with_capacity(servers.len());
+        for ((server, address), users) in servers.into_iter().zip(users) {
+            // the servers have different worlds, so they do not share waypoints, digests or snapshots
+            let (waypoints, digest, snapshot) = if scoped {
+                let digest = DigestOptions {
+                    dir: self.digest.dir.as_ref().map(|dir| dir.join(&server.name)),
+                    webhook: self.digest.webhook.clone(),
+                };
+                (prefixed(&self.waypoints, &server.name), digest, prefixed(&self.snapshot, &server.name))
+            } else {
+                (self.waypoints.clone(), self.digest.clone(), self.snapshot.clone())
+            };
+            let waypoints = Waypoints::load(waypoints.clone()).context(|| format!("could not read {}", waypoints.display()))?;
+
+            let resume = if self.resume {
+                let resume = Snapshot::load(&snapshot).context(|| format!("could not read {}", snapshot.display()))?;
+                info!(path = %snapshot.display(), bots = resume.bots.len(), regions = resume.mine.len(), "resuming");
+                resume
+            } else {
+                Snapshot::default()
+            };
+
+            let controllers = controllers.clone().map(|make| -> ControllerFactory {
+                Box::new(move |info: &ClientInfo| (&mut *make.borrow_mut())(info))
+            });
+
+            let opts = RunnerOptions {
+                config: config.clone(),
+                config_file: self.config_file.clone(),
+                cli_config: self.config.clone(),
+                recording_dir: self.recording_dir.clone(),
+                digest,
+                proxies: proxies.clone(),
+                rate_limit: rate_limit.clone(),
+                bases: self.bases.clone(),
+                permissions: self.permissions.clone(),
+                waypoints,
+                snapshot,
+                resume,
+                bots: server.bots(),
+                controllers,
+                commands: hub.server(&server.name),
+                shutdown: shutdown.clone(),
+            };
+
+            let span = if scoped { info_span!("server", name = %server.name) } else { tracing::Span::none() };
+            let run = match server.version {
+                340 => Runner::<protocol::v340::Protocol>::run(address, users, opts).map(|res| res.context_str("Error starting up 1.12")).boxed_local(), // 1.12
+                754 => Runner::<protocol::v754::Protocol>::run(address, users, opts).map(|res| res.context_str("Error starting up 1.16")).boxed_local(), // 1.16.5
+                version => return Err(err(&format!("version {} does not exist", version))).context_str("Error starting up"),
+            };
+            runs.push(run.instrument(span).boxed_local());
+        }
+
+        futures::future::join_all(runs).await.into_iter().collect()
+    }
+
+    /// Check the proxies and authenticate the accounts of the online servers. Bots are logged in as users come in,
+    /// each server taking them from its own receiver.
+    async fn obtain_users(&mut self, servers: &[(ServerTarget, Address)]) -> ResContext<(ProxyPool, Vec<Receiver<ProxyUser>>)> {
+        // only healthy proxies are handed out. They are checked against the first server.
+        info!(count = self.proxies.len(), "checking proxies");
+        let target = String::from(&servers[0].1);
+        let pool = ProxyPool::new(std::mem::take(&mut self.proxies));
+        pool.check(&target, CHECK_TIMEOUT).await;
+        pool.spawn_checks(target, CHECK_INTERVAL);
+
+        let targets: Vec<_> = servers.iter().map(|(server, _)| server.clone()).collect();
+        let mut split = Split::new(&targets, self.reserve);
+        let (sessions, offline) = split.divide(std::mem::take(&mut self.users));
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = targets.iter().map(|_| tokio::sync::mpsc::channel(32)).unzip();
+
+        // users of offline servers need no session
+        for ((server, users), tx) in targets.iter().zip(offline).zip(&senders) {
+            if server.online || users.is_empty() {
+                continue;
+            }
+
+            let users = offline_users(users, &pool, &self.mojang);
+            let tx = tx.clone();
+            tokio::task::spawn_local(async move {
+                for user in users {
+                    if tx.send(user).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let count = split.sessions();
+        if count > 0 {
+            info!(fresh = self.fresh_auth, file = %self.sessions.display(), "reading sessions");
+            let cache = UserCache::load(self.sessions.clone(), self.fresh_auth, self.sessions_key.clone()).context(|| format!("could not read {}", self.sessions.display()))?;
+
+            info!("obtaining users");
+            let mut users = cache.obtain_users(count, sessions, pool.clone(), self.mojang.clone());
+            tokio::task::spawn_local(async move {
+                while let Some(user) = users.recv().await {
+                    match split.route(&user.user.email) {
+                        Some(idx) => {
+                            let _ = senders[idx].send(user).await;
+                        }
+                        None => debug!(email = %user.user.email, "no server wants the account"),
+                    }
+                }
+            });
+        }
+
+        Ok((pool, receivers))
+    }
+}
+
+/// `path` with the file name prefixed by `name`, i.e., `hub-waypoints.json`
+fn prefixed(path: &Path, name: &str) -> PathBuf {
+    let file = path.file_name().map_or_else(|| name.to_string(), |file| format!("{}-{}", name, file.to_string_lossy()));
+    path.with_file_name(file)
+}
@@ -0,0 +1,72 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! SwarmBot as a library: log a swarm of bots into a server and drive them with your own tasks and chat handlers.
+//!
+//! [`Swarm`] logs the bots in and runs the game loop. A [`Controller`] made for each bot schedules tasks (anything
+//! implementing [`TaskTrait`], or [`DynTask`] for tasks defined outside the crate) and sees the chat. The game loop
+//! has to run on a tokio [`LocalSet`](tokio::task::LocalSet).
+
+#![allow(dead_code)]
+#![allow(incomplete_features)]
+#![deny(unused_must_use)]
+#![feature(in_band_lifetimes)]
+#![feature(path_try_exists)]
+#![feature(const_evaluatable_checked)]
+#![feature(const_generics)]
+#![feature(min_type_alias_impl_trait)]
+#![feature(once_cell)]
+#![feature(step_trait)]
+#![feature(option_get_or_insert_default)]
+#![feature(array_zip)]
+#![feature(test)]
+#![feature(box_syntax)]
+#![feature(default_free_fn)]
+#![feature(bool_to_option)]
+
+#[macro_use]
+extern crate enum_dispatch;
+extern crate serde;
+#[macro_use]
+extern crate swarm_bot_packets;
+extern crate test;
+#[macro_use]
+extern crate thiserror;
+#[macro_use]
+extern crate tracing;
+
+pub use bootstrap::{Address, CSVUser, Proxy};
+pub use client::controller::Controller;
+pub use client::state::global::GlobalState;
+pub use client::state::local::LocalState;
+pub use client::tasks::{Task, TaskTrait};
+pub use client::tasks::custom::{CustomTask, DynTask};
+pub use launch::Swarm;
+pub use protocol::{EventQueue, InterfaceOut, Minecraft};
+pub use storage::block::{BlockKind, BlockLocation, BlockState};
+pub use storage::blocks::WorldBlocks;
+
+pub mod error;
+pub mod bootstrap;
+pub mod chat;
+pub mod protocol;
+mod term;
+pub mod client;
+pub mod storage;
+mod schematic;
+mod nbt;
+pub mod types;
+pub mod launch;
@@ -13,110 +13,248 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
-#![allow(dead_code)]
-#![allow(incomplete_features)]
-#![deny(unused_must_use)]
-#![feature(in_band_lifetimes)]
-#![feature(path_try_exists)]
-#![feature(const_evaluatable_checked)]
-#![feature(const_generics)]
-#![feature(min_type_alias_impl_trait)]
-#![feature(once_cell)]
-#![feature(step_trait)]
-#![feature(option_get_or_insert_default)]
-#![feature(array_zip)]
-#![feature(test)]
-#![feature(box_syntax)]
-#![feature(default_free_fn)]
-#![feature(bool_to_option)]
-
-#[macro_use]
-extern crate enum_dispatch;
-extern crate serde;
-#[macro_use]
-extern crate swarm_bot_packets;
-extern crate test;
-#[macro_use]
-extern crate thiserror;
-
 use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use tokio::runtime::Runtime;
 use tokio::task;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
 
-use crate::bootstrap::Connection;
-use crate::bootstrap::dns::normalize_address;
-use crate::bootstrap::opts::Opts;
-use crate::bootstrap::storage::UserCache;
-use crate::client::runner::{Runner, RunnerOptions};
-use crate::error::{HasContext, ResContext};
-
-
-mod error;
-mod bootstrap;
-mod protocol;
-mod term;
-mod client;
-mod storage;
-mod schematic;
-mod types;
+use swarm_bot::{Address, Swarm};
+use swarm_bot::bootstrap;
+use swarm_bot::bootstrap::cache::SESSIONS_KEY_VAR;
+use swarm_bot::bootstrap::dns::normalize_address;
+use swarm_bot::bootstrap::mojang::MojangConfig;
+use swarm_bot::bootstrap::opts::Opts;
+use swarm_bot::bootstrap::schedule::LoginConfig;
+use swarm_bot::bootstrap::servers::ServerTarget;
+use swarm_bot::client::bases::BaseConfig;
+use swarm_bot::client::digest::DigestOptions;
+use swarm_bot::client::find::FindConfig;
+use swarm_bot::client::permissions::Permissions;
+use swarm_bot::client::physics::rotation::RotationConfig;
+use swarm_bot::client::recording::replay::print_timeline;
+use swarm_bot::client::sim;
+use swarm_bot::client::state::local::armor::ArmorConfig;
+use swarm_bot::client::tunables::Tunables;
+use swarm_bot::error::{err, HasContext, Res, ResContext};
+use swarm_bot::launch::{SESSIONS_FILE, SNAPSHOT_FILE};
+use swarm_bot::protocol::{self, ServerStatus};
+use swarm_bot::protocol::chat_queue::ChatConfig;
+use swarm_bot::protocol::disconnect::DisconnectPatterns;
+use swarm_bot::storage::blocks::archive::{self, Bounds};
 
 fn main() {
 
+    // RUST_LOG picks what is logged, i.e., `info,[bot{name=abc}]=debug` for everything of bot abc
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::fmt().with_env_filter(filter).init();
+
     // create the single-threaded async runtime
     let rt = Runtime::new().unwrap();
     let local = task::LocalSet::new();
     local.block_on(&rt, async move {
         match run().await {
-            // this should never happen as this should be an infinite loop
-            Ok(_) => println!("Program exited without errors somehow"),
+            // after a shutdown
+            Ok(_) => println!("done"),
 
             // print the error in non-debug fashion
             Err(err) => println!("{}", err)
         }
     });
+
+    // readers of connections the server has not closed yet are not waited for
+    rt.shutdown_timeout(Duration::from_secs(1));
 }
 
 
+/// Ping the server directly or through the first proxy in `proxies_file` that works
+async fn server_status(address: &Address, proxies_file: Option<&str>) -> Res<ServerStatus> {
+    let proxies_file = match proxies_file {
+        None => return protocol::ping(&address.host, address.port).await,
+        Some(proxies_file) => proxies_file,
+    };
+
+    let proxies = bootstrap::csv::read_proxies(File::open(proxies_file)?)?;
+
+    let mut last_err = err("there are no proxies");
+    for proxy in &proxies {
+        match protocol::ping_through(&address.host, address.port, proxy).await {
+            Ok(status) => return Ok(status),
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
 async fn run() -> ResContext {
-    let Opts { users_file, proxies_file, host, count, version, port, delay, load } = Opts::get();
+    let Opts { users_file, proxies_file, disconnect_patterns, base_weights, permissions, find_chunks, config, fresh_auth, resume, host, count, reserve, mut version, port, delay, login_jitter, login_concurrency, login_attempts, mojang_timeout, mojang_retries, msa_client_id, chat_interval, chat_jitter, chat_strip_formatting, armor_warn, armor_unequip, max_rotation, load, record, replay, script, baseline, replay_last, world_file, cache_pack, cache_bounds, cache_unpack, digest, digest_webhook, ping, expect_protocol, players_below, ping_proxy, servers } = Opts::get();
+
+    if let (Some(seed), Some(script)) = (replay.as_ref(), script) {
+        let seed: u64 = seed.parse().map_err(|_| err("the seed is not a number")).context_str("invalid --replay")?;
+        return sim::replay::replay(Path::new(&script), seed, baseline.as_deref().map(Path::new))
+            .context(|| format!("could not replay {} with seed {}", script, seed));
+    }
+
+    if let Some(replay) = replay {
+        return print_timeline(Path::new(&replay), replay_last).context(|| format!("could not replay {}", replay));
+    }
+
+    if let Some(out) = cache_pack {
+        let bounds = cache_bounds.map(|bounds| bounds.parse::<Bounds>()).transpose().context_str("invalid --cache-bounds")?;
+        let manifest = archive::pack(Path::new(&world_file), Path::new(&out), host.as_deref().unwrap_or_default(), bounds)
+            .context(|| format!("could not pack {} into {}", world_file, out))?;
+        println!("packed {} columns into {}", manifest.columns, out);
+        return Ok(());
+    }
+
+    if let Some(file) = cache_unpack {
+        let unpacked = archive::unpack(Path::new(&file), Path::new(&world_file), host.as_deref().unwrap_or_default())
+            .context(|| format!("could not unpack {} into {}", file, world_file))?;
+        println!("{} columns added, {} replaced and {} kept ({} were unpacked before)", unpacked.added, unpacked.replaced, unpacked.kept, unpacked.skipped);
+        return Ok(());
+    }
+
+    let servers = match servers {
+        None => Vec::new(),
+        Some(path) => ServerTarget::load(&path).context(|| format!("could not load servers {}", path))?,
+    };
+
+    // clap makes sure we have a host or servers if we are not replaying
+    let host = host.unwrap_or_default();
 
     let address = normalize_address(&host, port).await;
 
-    // A list of users we will login
-    let mut proxy_users = {
-        println!("reading {}", users_file);
-        let csv_file = File::open(&users_file).context(|| format!("could not open users file {}", users_file))?;
-        let csv_users = bootstrap::csv::read_users(csv_file).context_str("could not open users file")?;
+    // the swarm pings each of the servers itself
+    if ping || (version == 0 && servers.is_empty()) {
+        let proxies = if ping_proxy { Some(proxies_file.as_str()) } else { None };
+        let status = server_status(&address, proxies).await;
+
+        if ping {
+            let unmet = match &status {
+                Ok(status) => {
+                    println!("{}", status);
+                    status.unmet(expect_protocol, players_below)
+                }
+                Err(err) => Some(format!("could not ping {} -- {}", String::from(&address), err)),
+            };
+
+            if let Some(unmet) = unmet {
+                println!("{}", unmet);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+
+        let status = status.context(|| format!("could not detect the version of {}", String::from(&address)))?;
+        info!(address = %String::from(&address), version = %status.version, protocol = status.protocol, "detected the version");
+        version = status.supported_version().context_str("could not pick a version")?;
+    }
 
-        println!("reading {}", proxies_file);
-        let proxies_file = File::open(&proxies_file).context(|| format!("could not open proxies file {}", proxies_file))?;
-        let proxies = bootstrap::csv::read_proxies(proxies_file).context_str("could not open proxies file")?;
+    info!(file = %proxies_file, "reading proxies");
+    let proxies = File::open(&proxies_file).context(|| format!("could not open proxies file {}", proxies_file))?;
+    let proxies = bootstrap::csv::read_proxies(proxies).context_str("could not open proxies file")?;
 
-        println!("reading cache.db");
-        let cache = UserCache::load("cache.db".into());
+    info!(file = %users_file, "reading users");
+    let users = File::open(&users_file).context(|| format!("could not open users file {}", users_file))?;
+    let users = bootstrap::csv::read_users(users).context_str("could not open users file")?;
 
-        println!("obtaining users from cache");
-        cache.obtain_users(count, csv_users, proxies)
+    let mojang = MojangConfig {
+        connect_timeout: Duration::from_millis(mojang_timeout),
+        timeout: Duration::from_millis(mojang_timeout),
+        retries: mojang_retries,
+        microsoft_client_id: msa_client_id,
+        ..MojangConfig::default()
     };
 
+    let mut swarm = Swarm::new(host)
+        .port(port)
+        .version(version)
+        .accounts(users)
+        .proxies(proxies)
+        .bots(count)
+        .reserve(reserve)
+        .sessions(SESSIONS_FILE, fresh_auth)
+        .snapshot(SNAPSHOT_FILE, resume)
+        .mojang(mojang);
+
+    if let Ok(passphrase) = std::env::var(SESSIONS_KEY_VAR) {
+        swarm = swarm.sessions_key(&passphrase);
+    }
+
+    for server in servers {
+        swarm = swarm.server(server);
+    }
+
     if load {
-        while proxy_users.recv().await.is_some() {
-            // empty
-        }
-        return Ok(());
-    } else {
-        // taking the users and generating connections to the Minecraft server
-        let connections = Connection::stream(address, proxy_users);
+        return swarm.load().await;
+    }
 
-        let opts = RunnerOptions { delay_millis: delay };
+    let disconnects = match disconnect_patterns {
+        None => DisconnectPatterns::default(),
+        Some(path) => DisconnectPatterns::load(&path).context(|| format!("could not load disconnect patterns {}", path))?,
+    };
 
-        match version {
-            340 => Runner::<protocol::v340::Protocol>::run(connections, opts).await.context_str("Error starting up 1.12")?, // 1.12
-            _ => { panic!("version {} does not exist", version) }
-        }
+    let login = LoginConfig {
+        max_concurrent: login_concurrency,
+        min_delay: Duration::from_millis(delay),
+        jitter: Duration::from_millis(login_jitter),
+        max_attempts: login_attempts,
+        disconnects,
+        ..LoginConfig::default()
+    };
+
+    let chat = ChatConfig {
+        min_interval: Duration::from_millis(chat_interval),
+        jitter: Duration::from_millis(chat_jitter),
+        strip_formatting: chat_strip_formatting,
+        ..ChatConfig::default()
+    };
+
+    let digest = DigestOptions {
+        dir: digest.map(PathBuf::from),
+        webhook: digest_webhook,
+    };
+
+    let armor = ArmorConfig {
+        warn_at: armor_warn,
+        unequip_at: armor_unequip,
+    };
+
+    let rotation = RotationConfig {
+        max_degrees: max_rotation,
+        ..RotationConfig::default()
+    };
+
+    let bases = match base_weights {
+        None => BaseConfig::default(),
+        Some(path) => BaseConfig::load(&path).context(|| format!("could not load base weights {}", path))?,
+    };
+
+    let permissions = match permissions {
+        None => Permissions::default(),
+        Some(path) => Permissions::load(&path).context(|| format!("could not load permissions {}", path))?,
+    };
+
+    let find = FindConfig {
+        max_chunks: find_chunks,
+    };
+
+    swarm = swarm
+        .config(Tunables { login, chat, armor, rotation, find, ..Tunables::default() })
+        .digest(digest)
+        .bases(bases)
+        .permissions(permissions);
+
+    if let Some(config) = config {
+        swarm = swarm.config_file(config);
     }
 
+    if let Some(record) = record {
+        swarm = swarm.record(record);
+    }
 
-    Ok(())
+    swarm.run().await
 }
@@ -0,0 +1,789 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A hardened NBT reader. All NBT we read (slots, schematics) goes through here so malformed or adversarial data
+//! results in an error (or a partial tree in lenient mode) instead of a panic or running out of memory.
+//!
+//! https://wiki.vg/NBT
+
+use std::borrow::Cow;
+use std::convert::TryInto;
+use std::mem::size_of;
+
+use thiserror::Error;
+
+const END: u8 = 0;
+const BYTE: u8 = 1;
+const SHORT: u8 = 2;
+const INT: u8 = 3;
+const LONG: u8 = 4;
+const FLOAT: u8 = 5;
+const DOUBLE: u8 = 6;
+const BYTE_ARRAY: u8 = 7;
+const STRING: u8 = 8;
+const LIST: u8 = 9;
+const COMPOUND: u8 = 10;
+const INT_ARRAY: u8 = 11;
+const LONG_ARRAY: u8 = 12;
+
+/// what every tag is charged against [`NbtLimits::max_size`] on top of its payload, so many tiny tags cannot use
+/// much more memory than the limit
+const TAG_COST: usize = size_of::<Tag>() + size_of::<Cow<str>>();
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum NbtError {
+    #[error("unexpected end of nbt data")]
+    UnexpectedEof,
+
+    #[error("unknown nbt tag {0}")]
+    UnknownTag(u8),
+
+    #[error("root nbt tag is {0}, not a compound")]
+    NotCompound(u8),
+
+    #[error("negative nbt length {0}")]
+    NegativeLength(i32),
+
+    #[error("nbt is nested deeper than {limit}")]
+    TooDeep { limit: usize },
+
+    #[error("nbt is larger than {limit} bytes")]
+    TooLarge { limit: usize },
+
+    #[error("nbt string or array has length {len} which is longer than {limit}")]
+    TooLong { len: usize, limit: usize },
+
+    #[error("nbt is missing {0}")]
+    Missing(&'static str),
+}
+
+impl NbtError {
+    /// whether the extent of the bad data is known, so parsing can continue after it
+    fn skippable(&self) -> bool {
+        matches!(self, NbtError::TooDeep {..} | NbtError::TooLong {..})
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NbtLimits {
+    /// how deep compounds and lists can be nested
+    pub max_depth: usize,
+
+    /// the total size of the data plus a fixed cost per tag
+    pub max_size: usize,
+
+    pub max_string: usize,
+
+    /// the maximum amount of elements in a byte, int or long array
+    pub max_array: usize,
+
+    /// Skip subtrees which are too deep or too long instead of failing. If the data is corrupt such that parsing
+    /// cannot continue (unknown tag, truncated) everything read before the corruption is kept.
+    pub lenient: bool,
+}
+
+impl NbtLimits {
+    /// Data sent by a server. Vanilla limits network NBT to 2 MiB and a depth of 512.
+    pub const NETWORK: NbtLimits = NbtLimits {
+        max_depth: 512,
+        max_size: 2 * 1024 * 1024,
+        max_string: 32767,
+        max_array: 1 << 20,
+        lenient: true,
+    };
+
+    /// Trusted local files such as schematics. These can be large but should not be corrupt.
+    pub const FILE: NbtLimits = NbtLimits {
+        max_depth: 512,
+        max_size: 512 * 1024 * 1024,
+        max_string: u16::MAX as usize,
+        max_array: 1 << 28,
+        lenient: false,
+    };
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag<'a> {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+
+    /// borrowed from the data which was parsed
+    ByteArray(&'a [u8]),
+    String(Cow<'a, str>),
+    List(Vec<Tag<'a>>),
+    Compound(Compound<'a>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl<'a> Tag<'a> {
+    pub fn as_i8(&self) -> Option<i8> {
+        match self {
+            Tag::Byte(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    pub fn as_i16(&self) -> Option<i16> {
+        match self {
+            Tag::Short(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            Tag::Int(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            Tag::ByteArray(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag<'a>]> {
+        match self {
+            Tag::List(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound<'a>> {
+        match self {
+            Tag::Compound(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_i32s(&self) -> Option<&[i32]> {
+        match self {
+            Tag::IntArray(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_i64s(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(value) => Some(value),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compound<'a> {
+    entries: Vec<(Cow<'a, str>, Tag<'a>)>,
+}
+
+impl<'a> Compound<'a> {
+    pub fn get(&self, name: &str) -> Option<&Tag<'a>> {
+        self.entries.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, tag)| tag)
+    }
+
+    /// the entries in the order they were read
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &Tag<'a>)> {
+        self.entries.iter().map(|(key, tag)| (key.as_ref(), tag))
+    }
+}
+
+/// The result of [`read`]
+#[derive(Debug)]
+pub struct Nbt<'a> {
+    pub name: Cow<'a, str>,
+    pub root: Compound<'a>,
+
+    /// how many bytes were read
+    pub len: usize,
+
+    /// how many subtrees were skipped (lenient mode only)
+    pub skipped: usize,
+
+    /// Why parsing stopped early (lenient mode only). If this is set `root` only contains what was read before the
+    /// error and `len` cannot be trusted.
+    pub truncated: Option<NbtError>,
+}
+
+/// Read a named root compound from the start of `data`
+pub fn read<'a>(data: &'a [u8], limits: &NbtLimits) -> Result<Nbt<'a>, NbtError> {
+    let mut parser = Parser {
+        data,
+        pos: 0,
+        used: 0,
+        limits,
+        skipped: 0,
+        fatal: None,
+    };
+
+    let id = parser.u8()?;
+    if id != COMPOUND {
+        return Err(NbtError::NotCompound(id));
+    }
+
+    let name = parser.string()?.unwrap_or_default();
+    let root = parser.compound(1)?;
+
+    Ok(Nbt {
+        name,
+        root,
+        len: parser.pos,
+        skipped: parser.skipped,
+        truncated: parser.fatal,
+    })
+}
+
+#[derive(Copy, Clone)]
+enum Frame {
+    Compound,
+    List { elem: u8, left: usize },
+}
+
+struct Parser<'a, 'b> {
+    data: &'a [u8],
+    pos: usize,
+
+    /// what has been charged against the size limit
+    used: usize,
+    limits: &'b NbtLimits,
+    skipped: usize,
+
+    /// the error which stopped a lenient parse
+    fatal: Option<NbtError>,
+}
+
+impl<'a, 'b> Parser<'a, 'b> {
+    fn charge(&mut self, amount: usize) -> Result<(), NbtError> {
+        self.used = self.used.saturating_add(amount);
+        if self.used > self.limits.max_size {
+            return Err(NbtError::TooLarge { limit: self.limits.max_size });
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], NbtError> {
+        self.charge(len)?;
+        let end = self.pos.checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(NbtError::UnexpectedEof)?;
+
+        let res = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(res)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], NbtError> {
+        let mut res = [0; N];
+        res.copy_from_slice(self.take(N)?);
+        Ok(res)
+    }
+
+    fn u8(&mut self) -> Result<u8, NbtError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, NbtError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, NbtError> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    fn len(&mut self) -> Result<usize, NbtError> {
+        match self.i32()? {
+            len if len < 0 => Err(NbtError::NegativeLength(len)),
+            len => Ok(len as usize),
+        }
+    }
+
+    /// In lenient mode remember `err` so the caller can return what it has read so far.
+    fn recover(&mut self, err: NbtError) -> Result<(), NbtError> {
+        if self.limits.lenient {
+            self.fatal = Some(err);
+            Ok(())
+        } else {
+            Err(err)
+        }
+    }
+
+    /// Called when a limit is exceeded by a subtree of known extent. In lenient mode the subtree is skipped.
+    fn exceeded(&mut self, err: NbtError, skip: impl FnOnce(&mut Self) -> Result<(), NbtError>) -> Result<(), NbtError> {
+        debug_assert!(err.skippable());
+        if self.limits.lenient {
+            skip(self)?;
+            self.skipped += 1;
+            Ok(())
+        } else {
+            Err(err)
+        }
+    }
+
+    /// None if the string was too long and skipped. Strings are not valid UTF-8 if they contain characters Java
+    /// encodes differently (modified UTF-8) so these are read lossily.
+    fn string(&mut self) -> Result<Option<Cow<'a, str>>, NbtError> {
+        let len = self.u16()? as usize;
+        if len > self.limits.max_string {
+            let limit = self.limits.max_string;
+            self.exceeded(NbtError::TooLong { len, limit }, |parser| parser.take(len).map(drop))?;
+            return Ok(None);
+        }
+
+        Ok(Some(String::from_utf8_lossy(self.take(len)?)))
+    }
+
+    /// The length of an array with elements of `size` bytes. None if the array was too long and skipped.
+    fn array_len(&mut self, size: usize) -> Result<Option<usize>, NbtError> {
+        let len = self.len()?;
+        if len > self.limits.max_array {
+            let limit = self.limits.max_array;
+            self.exceeded(NbtError::TooLong { len, limit }, |parser| parser.take(len * size).map(drop))?;
+            return Ok(None);
+        }
+        Ok(Some(len))
+    }
+
+    /// The payload of a tag. `depth` is how deeply nested the tag is. None if the tag was skipped.
+    fn payload(&mut self, id: u8, depth: usize) -> Result<Option<Tag<'a>>, NbtError> {
+        self.charge(TAG_COST)?;
+
+        let tag = match id {
+            BYTE => Tag::Byte(i8::from_be_bytes(self.array()?)),
+            SHORT => Tag::Short(i16::from_be_bytes(self.array()?)),
+            INT => Tag::Int(self.i32()?),
+            LONG => Tag::Long(i64::from_be_bytes(self.array()?)),
+            FLOAT => Tag::Float(f32::from_be_bytes(self.array()?)),
+            DOUBLE => Tag::Double(f64::from_be_bytes(self.array()?)),
+            BYTE_ARRAY => match self.array_len(1)? {
+                Some(len) => Tag::ByteArray(self.take(len)?),
+                None => return Ok(None),
+            },
+            STRING => match self.string()? {
+                Some(string) => Tag::String(string),
+                None => return Ok(None),
+            },
+            LIST | COMPOUND if depth > self.limits.max_depth => {
+                let limit = self.limits.max_depth;
+                self.exceeded(NbtError::TooDeep { limit }, |parser| parser.skip(id))?;
+                return Ok(None);
+            }
+            LIST => Tag::List(self.list(depth)?),
+            COMPOUND => Tag::Compound(self.compound(depth)?),
+            INT_ARRAY => match self.array_len(4)? {
+                Some(len) => Tag::IntArray(self.take(len * 4)?.chunks_exact(4).map(|bytes| i32::from_be_bytes(bytes.try_into().unwrap())).collect()),
+                None => return Ok(None),
+            },
+            LONG_ARRAY => match self.array_len(8)? {
+                Some(len) => Tag::LongArray(self.take(len * 8)?.chunks_exact(8).map(|bytes| i64::from_be_bytes(bytes.try_into().unwrap())).collect()),
+                None => return Ok(None),
+            },
+            id => return Err(NbtError::UnknownTag(id)),
+        };
+
+        Ok(Some(tag))
+    }
+
+    fn list(&mut self, depth: usize) -> Result<Vec<Tag<'a>>, NbtError> {
+        let elem = self.u8()?;
+        let len = self.len()?;
+
+        if elem == END {
+            return Ok(Vec::new());
+        }
+
+        // every element is at least a byte so do not trust the length beyond that
+        let mut list = Vec::with_capacity(len.min(self.data.len() - self.pos));
+
+        for _ in 0..len {
+            match self.payload(elem, depth + 1) {
+                Ok(Some(tag)) => list.push(tag),
+                Ok(None) => {}
+                Err(err) => {
+                    self.recover(err)?;
+                    break;
+                }
+            }
+
+            if self.fatal.is_some() {
+                break;
+            }
+        }
+
+        Ok(list)
+    }
+
+    fn compound(&mut self, depth: usize) -> Result<Compound<'a>, NbtError> {
+        let mut compound = Compound::default();
+
+        loop {
+            let res = self.u8().and_then(|id| {
+                if id == END {
+                    return Ok(None);
+                }
+                let name = self.string()?;
+                let tag = self.payload(id, depth + 1)?;
+                Ok(Some(name.zip(tag)))
+            });
+
+            match res {
+                Ok(None) => break,
+                Ok(Some(Some(entry))) => compound.entries.push(entry),
+                Ok(Some(None)) => {}
+                Err(err) => {
+                    self.recover(err)?;
+                    break;
+                }
+            }
+
+            if self.fatal.is_some() {
+                break;
+            }
+        }
+
+        Ok(compound)
+    }
+
+    /// Skip the payload of a tag without building it. This does not recurse so it works however deep the data is.
+    fn skip(&mut self, id: u8) -> Result<(), NbtError> {
+        let mut stack = Vec::new();
+        self.skip_one(id, &mut stack)?;
+
+        while let Some(frame) = stack.last().copied() {
+            match frame {
+                Frame::Compound => {
+                    let id = self.u8()?;
+                    if id == END {
+                        stack.pop();
+                    } else {
+                        let len = self.u16()? as usize;
+                        self.take(len)?;
+                        self.skip_one(id, &mut stack)?;
+                    }
+                }
+                Frame::List { elem, left } => {
+                    if left == 0 {
+                        stack.pop();
+                    } else {
+                        let idx = stack.len() - 1;
+                        stack[idx] = Frame::List { elem, left: left - 1 };
+                        self.skip_one(elem, &mut stack)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn skip_one(&mut self, id: u8, stack: &mut Vec<Frame>) -> Result<(), NbtError> {
+        let len = match id {
+            BYTE => 1,
+            SHORT => 2,
+            INT | FLOAT => 4,
+            LONG | DOUBLE => 8,
+            BYTE_ARRAY => self.len()?,
+            STRING => self.u16()? as usize,
+            INT_ARRAY => self.len()?.checked_mul(4).ok_or(NbtError::TooLarge { limit: self.limits.max_size })?,
+            LONG_ARRAY => self.len()?.checked_mul(8).ok_or(NbtError::TooLarge { limit: self.limits.max_size })?,
+            LIST => {
+                let elem = self.u8()?;
+                let len = self.len()?;
+                let left = if elem == END { 0 } else { len };
+                self.charge(size_of::<Frame>())?;
+                stack.push(Frame::List { elem, left });
+                0
+            }
+            COMPOUND => {
+                self.charge(size_of::<Frame>())?;
+                stack.push(Frame::Compound);
+                0
+            }
+            id => return Err(NbtError::UnknownTag(id)),
+        };
+
+        self.take(len)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Read;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use crate::nbt::{Compound, NbtError, NbtLimits, read, Tag};
+
+    /// encode `tag` (without name or id)
+    fn encode(tag: &Tag, out: &mut Vec<u8>) {
+        match tag {
+            Tag::Byte(value) => out.extend(value.to_be_bytes()),
+            Tag::Short(value) => out.extend(value.to_be_bytes()),
+            Tag::Int(value) => out.extend(value.to_be_bytes()),
+            Tag::Long(value) => out.extend(value.to_be_bytes()),
+            Tag::Float(value) => out.extend(value.to_be_bytes()),
+            Tag::Double(value) => out.extend(value.to_be_bytes()),
+            Tag::ByteArray(value) => {
+                out.extend((value.len() as i32).to_be_bytes());
+                out.extend(value.iter());
+            }
+            Tag::String(value) => {
+                out.extend((value.len() as u16).to_be_bytes());
+                out.extend(value.as_bytes());
+            }
+            Tag::List(list) => {
+                out.push(list.first().map_or(0, id));
+                out.extend((list.len() as i32).to_be_bytes());
+                for tag in list {
+                    encode(tag, out);
+                }
+            }
+            Tag::Compound(compound) => {
+                for (name, tag) in &compound.entries {
+                    out.push(id(tag));
+                    encode(&Tag::String(name.clone()), out);
+                    encode(tag, out);
+                }
+                out.push(0);
+            }
+            Tag::IntArray(value) => {
+                out.extend((value.len() as i32).to_be_bytes());
+                value.iter().for_each(|v| out.extend(v.to_be_bytes()));
+            }
+            Tag::LongArray(value) => {
+                out.extend((value.len() as i32).to_be_bytes());
+                value.iter().for_each(|v| out.extend(v.to_be_bytes()));
+            }
+        }
+    }
+
+    fn id(tag: &Tag) -> u8 {
+        match tag {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    fn root(compound: Compound) -> Vec<u8> {
+        let mut out = vec![10, 0, 0];
+        encode(&Tag::Compound(compound), &mut out);
+        out
+    }
+
+    fn compound<'a>(entries: Vec<(&'a str, Tag<'a>)>) -> Compound<'a> {
+        Compound { entries: entries.into_iter().map(|(name, tag)| (name.into(), tag)).collect() }
+    }
+
+    /// a list nested `depth` times
+    fn nested(depth: usize) -> Vec<u8> {
+        let mut out = vec![10, 0, 0, 9, 0, 1, b'a'];
+        for _ in 0..depth {
+            out.extend([9, 0, 0, 0, 1]);
+        }
+        out.extend([0, 0, 0, 0, 0, 0]);
+        out
+    }
+
+    const STRICT: NbtLimits = NbtLimits { lenient: false, ..NbtLimits::NETWORK };
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = [1_u8, 2, 3];
+        let tree = compound(vec![
+            ("byte", Tag::Byte(-1)),
+            ("string", Tag::String("hello".into())),
+            ("bytes", Tag::ByteArray(&bytes)),
+            ("list", Tag::List(vec![Tag::Int(1), Tag::Int(2)])),
+            ("nested", Tag::Compound(compound(vec![("long", Tag::LongArray(vec![i64::MIN]))]))),
+        ]);
+
+        let data = root(tree.clone());
+        let nbt = read(&data, &NbtLimits::NETWORK).unwrap();
+
+        assert_eq!(nbt.root, tree);
+        assert_eq!(nbt.len, data.len());
+        assert_eq!(nbt.truncated, None);
+
+        // byte arrays are borrowed from the input
+        let bytes = nbt.root.get("bytes").unwrap().as_bytes().unwrap();
+        assert!(data.as_ptr_range().contains(&bytes.as_ptr()));
+    }
+
+    #[test]
+    fn test_depth() {
+        let data = nested(1000);
+        assert_eq!(read(&data, &STRICT).unwrap_err(), NbtError::TooDeep { limit: 512 });
+
+        // the deep list is skipped but what comes after it is kept
+        let mut data = data;
+        data.pop();
+        data.extend([1, 0, 1, b'b', 7, 0]);
+
+        let nbt = read(&data, &NbtLimits::NETWORK).unwrap();
+        assert_eq!(nbt.skipped, 1);
+        assert_eq!(nbt.truncated, None);
+        assert_eq!(nbt.root.get("b"), Some(&Tag::Byte(7)));
+        assert_eq!(nbt.len, data.len());
+    }
+
+    #[test]
+    fn test_declared_lengths() {
+        // a byte array claiming to be 2 GiB with no data
+        let data = [10, 0, 0, 7, 0, 1, b'a', 0x7F, 0xFF, 0xFF, 0xFF];
+        assert_eq!(read(&data, &STRICT).unwrap_err(), NbtError::TooLong { len: i32::MAX as usize, limit: 1 << 20 });
+
+        // a list claiming 2 billion compounds
+        let data = [10, 0, 0, 9, 0, 1, b'a', 10, 0x7F, 0xFF, 0xFF, 0xFF, 0];
+        let nbt = read(&data, &NbtLimits::NETWORK).unwrap();
+        assert_eq!(nbt.truncated, Some(NbtError::UnexpectedEof));
+
+        let data = [10, 0, 0, 7, 0, 1, b'a', 0xFF, 0xFF, 0xFF, 0xFF];
+        assert_eq!(read(&data, &STRICT).unwrap_err(), NbtError::NegativeLength(-1));
+    }
+
+    #[test]
+    fn test_lenient_keeps_good_entries() {
+        let tree = compound(vec![("good", Tag::Short(3))]);
+        let mut data = root(tree);
+        data.pop();
+
+        // an unknown tag in a later entry
+        data.extend([42, 0, 1, b'x', 1, 2, 3]);
+
+        assert_eq!(read(&data, &STRICT).unwrap_err(), NbtError::UnknownTag(42));
+
+        let nbt = read(&data, &NbtLimits::NETWORK).unwrap();
+        assert_eq!(nbt.root.get("good").and_then(Tag::as_i16), Some(3));
+        assert_eq!(nbt.truncated, Some(NbtError::UnknownTag(42)));
+    }
+
+    #[test]
+    fn test_size_limit() {
+        // many tiny tags
+        let list = Tag::List(vec![Tag::Byte(0); 100_000]);
+        let data = root(compound(vec![("a", list)]));
+
+        let limits = NbtLimits { max_size: 1024 * 1024, ..NbtLimits::NETWORK };
+        let nbt = read(&data, &limits).unwrap();
+        assert_eq!(nbt.truncated, Some(NbtError::TooLarge { limit: 1024 * 1024 }));
+    }
+
+    /// Every file in the corpus of hostile NBT must be handled without a panic with both limits
+    #[test]
+    fn test_corpus() {
+        let mut count = 0;
+        for entry in std::fs::read_dir("test-data/nbt").unwrap() {
+            let mut data = Vec::new();
+            OpenOptions::new().read(true).open(entry.unwrap().path()).unwrap().read_to_end(&mut data).unwrap();
+
+            let _ = read(&data, &NbtLimits::NETWORK);
+            let _ = read(&data, &NbtLimits::FILE);
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    fn random_tag<'a>(rng: &mut StdRng, depth: usize, bytes: &'a [u8]) -> Tag<'a> {
+        let kind = if depth > 6 { rng.gen_range(0..8) } else { rng.gen_range(0..12) };
+        match kind {
+            0 => Tag::Byte(rng.gen()),
+            1 => Tag::Short(rng.gen()),
+            2 => Tag::Int(rng.gen()),
+            3 => Tag::Long(rng.gen()),
+            4 => Tag::Float(rng.gen()),
+            5 => Tag::Double(rng.gen()),
+            6 => Tag::String("a".repeat(rng.gen_range(0..20)).into()),
+            7 => Tag::ByteArray(&bytes[..rng.gen_range(0..bytes.len())]),
+            8 | 9 => {
+                let len = rng.gen_range(0..5);
+                let elem = random_tag(rng, depth + 1, bytes);
+                Tag::List(vec![elem; len])
+            }
+            10 => Tag::IntArray(vec![rng.gen(); rng.gen_range(0..10)]),
+            _ => {
+                let len = rng.gen_range(0..5);
+                let entries = (0..len).map(|_| ("key", random_tag(rng, depth + 1, bytes))).collect();
+                Tag::Compound(compound(entries))
+            }
+        }
+    }
+
+    /// Random trees round trip, and corrupting them (flipping bytes, truncating, inserting absurd lengths) never
+    /// panics and never uses more than the limit.
+    #[test]
+    fn test_hostile() {
+        let mut rng = StdRng::seed_from_u64(791);
+        let bytes = [0_u8; 64];
+
+        let limits = NbtLimits { max_size: 64 * 1024, max_depth: 16, ..NbtLimits::NETWORK };
+
+        for _ in 0..2000 {
+            let tree = match random_tag(&mut rng, 0, &bytes) {
+                Tag::Compound(compound) => compound,
+                other => compound(vec![("root", other)]),
+            };
+
+            let mut data = root(tree.clone());
+            assert_eq!(read(&data, &NbtLimits::NETWORK).unwrap().root, tree);
+
+            for _ in 0..rng.gen_range(1..4) {
+                let idx = rng.gen_range(0..data.len());
+                match rng.gen_range(0..3) {
+                    0 => data[idx] = rng.gen(),
+                    1 => data.truncate(idx.max(1)),
+                    _ => {
+                        let at = idx.min(data.len());
+                        let insert = [0x7F, 0xFF, 0xFF, 0xFF];
+                        data.splice(at..at, insert);
+                    }
+                }
+            }
+
+            for limits in [&limits, &NbtLimits { lenient: false, ..limits.clone() }] {
+                if let Ok(nbt) = read(&data, limits) {
+                    assert!(nbt.len <= data.len());
+                }
+            }
+        }
+    }
+}
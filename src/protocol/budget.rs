@@ -0,0 +1,275 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Pacing the actions of a bot. Anti-cheats flag players by how many blocks, clicks and swings they do in total rather
+//! than by any single action, so each kind of action has a token bucket and actions over it wait for a later tick.
+
+use std::collections::VecDeque;
+
+/// the kinds of actions with a budget of their own
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ActionKind {
+    Place,
+
+    /// starting to dig a block
+    Dig,
+
+    /// a click in a window
+    Click,
+    Swing,
+    Chat,
+}
+
+const KINDS: usize = 5;
+const TICKS_PER_SECOND: f32 = 20.0;
+
+impl ActionKind {
+    const ALL: [ActionKind; KINDS] = [ActionKind::Place, ActionKind::Dig, ActionKind::Click, ActionKind::Swing, ActionKind::Chat];
+}
+
+/// a token bucket holding at most `burst` actions which refills at `rate` actions per second
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bucket {
+    pub rate: f32,
+    pub burst: f32,
+}
+
+impl Bucket {
+    /// never runs out
+    pub const UNLIMITED: Bucket = Bucket::new(f32::INFINITY, f32::INFINITY);
+
+    pub const fn new(rate: f32, burst: f32) -> Bucket {
+        Bucket { rate, burst }
+    }
+}
+
+/// How many actions of each kind a bot may do. The defaults are about what an attentive player does: holding right
+/// click places a block every 4 ticks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BudgetConfig {
+    pub place: Bucket,
+    pub dig: Bucket,
+    pub click: Bucket,
+    pub swing: Bucket,
+    pub chat: Bucket,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        BudgetConfig {
+            place: Bucket::new(5.0, 2.0),
+            dig: Bucket::new(5.0, 2.0),
+            click: Bucket::new(8.0, 4.0),
+            swing: Bucket::new(10.0, 4.0),
+            chat: Bucket::new(1.0, 2.0),
+        }
+    }
+}
+
+impl BudgetConfig {
+    /// every action goes out right away
+    pub fn unlimited() -> BudgetConfig {
+        BudgetConfig {
+            place: Bucket::UNLIMITED,
+            dig: Bucket::UNLIMITED,
+            click: Bucket::UNLIMITED,
+            swing: Bucket::UNLIMITED,
+            chat: Bucket::UNLIMITED,
+        }
+    }
+
+    fn bucket(&self, kind: ActionKind) -> Bucket {
+        match kind {
+            ActionKind::Place => self.place,
+            ActionKind::Dig => self.dig,
+            ActionKind::Click => self.click,
+            ActionKind::Swing => self.swing,
+            ActionKind::Chat => self.chat,
+        }
+    }
+}
+
+/// The budget of a bot and the actions `A` waiting for it. Actions go out in the order they were made, so once one
+/// waits every later one waits behind it, even those without a budget (an item switch must not overtake the place
+/// before it).
+#[derive(Debug)]
+pub struct ActionBudget<A> {
+    config: BudgetConfig,
+    tokens: [f32; KINDS],
+    waiting: VecDeque<(Option<ActionKind>, A)>,
+}
+
+impl<A> Default for ActionBudget<A> {
+    fn default() -> Self {
+        ActionBudget::new(BudgetConfig::default())
+    }
+}
+
+impl<A> ActionBudget<A> {
+    /// every bucket starts full
+    pub fn new(config: BudgetConfig) -> ActionBudget<A> {
+        let mut tokens = [0.0; KINDS];
+        for (tokens, &kind) in tokens.iter_mut().zip(&ActionKind::ALL) {
+            *tokens = config.bucket(kind).burst;
+        }
+
+        ActionBudget {
+            config,
+            tokens,
+            waiting: VecDeque::new(),
+        }
+    }
+
+    /// buckets holding more than the new burst are cut down to it
+    pub fn set_config(&mut self, config: BudgetConfig) {
+        for (tokens, &kind) in self.tokens.iter_mut().zip(&ActionKind::ALL) {
+            *tokens = tokens.min(config.bucket(kind).burst);
+        }
+        self.config = config;
+    }
+
+    /// whether the bucket of `kind` has an action left
+    pub fn ready(&self, kind: ActionKind) -> bool {
+        self.tokens[kind as usize] >= 1.0
+    }
+
+    /// take an action out of the bucket of `kind`
+    pub fn spend(&mut self, kind: ActionKind) {
+        self.tokens[kind as usize] -= 1.0;
+    }
+
+    /// Whether an action of `kind` (None for actions without a budget) can go out now, which takes it out of the
+    /// bucket. False while other actions wait.
+    pub fn try_take(&mut self, kind: Option<ActionKind>) -> bool {
+        if !self.waiting.is_empty() {
+            return false;
+        }
+
+        match kind {
+            None => true,
+            Some(kind) if self.ready(kind) => {
+                self.spend(kind);
+                true
+            }
+            Some(_) => false,
+        }
+    }
+
+    /// keep `action` until a tick when the budget allows it
+    pub fn defer(&mut self, kind: Option<ActionKind>, action: A) {
+        self.waiting.push_back((kind, action));
+    }
+
+    /// A tick went by: refill the buckets and return the waiting actions which fit into the budget now, in order
+    pub fn tick(&mut self) -> Vec<A> {
+        for &kind in &ActionKind::ALL {
+            let Bucket { rate, burst } = self.config.bucket(kind);
+            let tokens = &mut self.tokens[kind as usize];
+            *tokens = (*tokens + rate / TICKS_PER_SECOND).min(burst);
+        }
+
+        let mut ready = Vec::new();
+        while let Some(&(kind, _)) = self.waiting.front() {
+            match kind {
+                Some(kind) if !self.ready(kind) => break,
+                Some(kind) => self.spend(kind),
+                None => {}
+            }
+            ready.extend(self.waiting.pop_front().map(|(_, action)| action));
+        }
+        ready
+    }
+
+    /// how many actions wait for the budget
+    pub fn waiting(&self) -> usize {
+        self.waiting.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::budget::{ActionBudget, ActionKind, Bucket, BudgetConfig};
+
+    fn config(place: Bucket) -> BudgetConfig {
+        BudgetConfig { place, ..BudgetConfig::default() }
+    }
+
+    #[test]
+    fn test_refill() {
+        // one place every 2 ticks, two at once
+        let mut budget = ActionBudget::<u32>::new(config(Bucket::new(10.0, 2.0)));
+        assert!(budget.try_take(Some(ActionKind::Place)));
+        assert!(budget.try_take(Some(ActionKind::Place)));
+        assert!(!budget.try_take(Some(ActionKind::Place)));
+
+        // other kinds have buckets of their own
+        assert!(budget.try_take(Some(ActionKind::Swing)));
+
+        budget.defer(Some(ActionKind::Place), 1);
+        budget.defer(Some(ActionKind::Place), 2);
+        assert!(budget.tick().is_empty());
+        assert_eq!(budget.tick(), vec![1]);
+        assert!(budget.tick().is_empty());
+        assert_eq!(budget.tick(), vec![2]);
+        assert_eq!(budget.waiting(), 0);
+
+        // an idle bucket does not fill up beyond the burst
+        for _ in 0..100 {
+            budget.tick();
+        }
+        assert!(budget.try_take(Some(ActionKind::Place)));
+        assert!(budget.try_take(Some(ActionKind::Place)));
+        assert!(!budget.try_take(Some(ActionKind::Place)));
+    }
+
+    #[test]
+    fn test_order() {
+        let mut budget = ActionBudget::new(config(Bucket::new(10.0, 1.0)));
+        assert!(budget.try_take(Some(ActionKind::Place)));
+        budget.defer(Some(ActionKind::Place), "place");
+
+        // actions without a budget and with a full bucket still wait behind the place
+        assert!(!budget.try_take(None));
+        budget.defer(None, "slot");
+        assert!(!budget.try_take(Some(ActionKind::Swing)));
+        budget.defer(Some(ActionKind::Swing), "swing");
+        assert_eq!(budget.waiting(), 3);
+
+        assert!(budget.tick().is_empty());
+        assert_eq!(budget.tick(), vec!["place", "slot", "swing"]);
+        assert!(budget.try_take(None));
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let mut budget = ActionBudget::<()>::new(BudgetConfig::unlimited());
+        for _ in 0..1000 {
+            assert!(budget.try_take(Some(ActionKind::Click)));
+        }
+        budget.tick();
+        assert!(budget.ready(ActionKind::Click));
+    }
+
+    #[test]
+    fn test_set_config() {
+        let mut budget = ActionBudget::<()>::default();
+        assert!(budget.ready(ActionKind::Click));
+
+        budget.set_config(BudgetConfig { click: Bucket::new(8.0, 0.0), ..BudgetConfig::default() });
+        assert!(!budget.ready(ActionKind::Click));
+        assert!(budget.ready(ActionKind::Place));
+    }
+}
@@ -0,0 +1,309 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::chat::format::{Format, SECTION, strip_codes};
+
+/// the longest chat message the server accepts. Longer messages get the client kicked.
+pub const MAX_LENGTH: usize = 256;
+
+#[derive(Clone, Debug)]
+pub struct ChatConfig {
+    /// the minimum time between two messages
+    pub min_interval: Duration,
+
+    /// a random amount of time up to this is added to `min_interval`
+    pub jitter: Duration,
+
+    /// a message identical to one queued less than this long ago is dropped. None to send every message.
+    pub dedup_window: Option<Duration>,
+
+    /// remove legacy `§` codes from every message, for servers which kick players sending them
+    pub strip_formatting: bool,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            min_interval: Duration::from_millis(1000),
+            jitter: Duration::from_millis(250),
+            dedup_window: Some(Duration::from_secs(5)),
+            strip_formatting: false,
+        }
+    }
+}
+
+/// Outgoing chat of a bot. Servers kick clients which chat too fast, so messages are queued and sent at most once
+/// every [`ChatConfig::min_interval`]. Messages longer than [`MAX_LENGTH`] are split into multiple messages.
+///
+/// All methods take the current time so tests do not depend on the clock.
+#[derive(Debug, Default)]
+pub struct ChatQueue {
+    config: ChatConfig,
+    queue: VecDeque<String>,
+
+    /// messages queued within the dedup window and when they were queued
+    recent: VecDeque<(Instant, String)>,
+
+    /// the earliest time the next message may be sent
+    next_send: Option<Instant>,
+}
+
+/// Split `message` into parts of at most `max` chars. Splits happen at spaces unless a single word is too long.
+///
+/// Legacy `§` codes count towards the length as the server sees them, are never split from their code char, and the
+/// formatting in effect where a part ends is repeated at the start of the next one.
+pub fn split(message: &str, max: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+
+    // the formatting at the end of `current`
+    let mut format = Format::default();
+
+    let mut current = String::new();
+    let mut current_len = 0;
+    let mut has_words = false;
+
+    for word in message.split(' ').filter(|word| !word.is_empty()) {
+        let mut word: Vec<char> = word.chars().collect();
+
+        if has_words && current_len + 1 + word.len() > max {
+            parts.push(std::mem::take(&mut current));
+            current = format.codes();
+            current_len = current.chars().count();
+            has_words = false;
+        }
+
+        if has_words {
+            current.push(' ');
+            current_len += 1;
+        }
+
+        // words longer than a whole message are split wherever they have to be
+        while current_len + word.len() > max {
+            let room = max.saturating_sub(current_len);
+            let mut idx = room;
+            if idx > 0 && word[idx - 1] == SECTION {
+                idx -= 1;
+            }
+
+            // not even one char fits after the repeated formatting
+            if idx == 0 && current_len > 0 && !has_words {
+                current.clear();
+                current_len = 0;
+                continue;
+            }
+            let idx = idx.max(1);
+
+            let piece: String = word.drain(..idx).collect();
+            format.apply_all(&piece);
+            current.push_str(&piece);
+            parts.push(std::mem::take(&mut current));
+
+            current = format.codes();
+            current_len = current.chars().count();
+            has_words = false;
+        }
+
+        let word: String = word.into_iter().collect();
+        format.apply_all(&word);
+        current_len += word.chars().count();
+        current.push_str(&word);
+        has_words = true;
+    }
+
+    if has_words {
+        parts.push(current);
+    }
+
+    parts
+}
+
+impl ChatQueue {
+    pub fn new(config: ChatConfig) -> ChatQueue {
+        ChatQueue {
+            config,
+            ..ChatQueue::default()
+        }
+    }
+
+    /// the wait before the next message already decided stays as it is
+    pub fn set_config(&mut self, config: ChatConfig) {
+        self.config = config;
+    }
+
+    pub fn push(&mut self, message: &str, now: Instant) {
+        let stripped;
+        let message = if self.config.strip_formatting {
+            stripped = strip_codes(message);
+            stripped.as_str()
+        } else {
+            message
+        };
+
+        if let Some(window) = self.config.dedup_window {
+            while matches!(self.recent.front(), Some((at, _)) if now.duration_since(*at) >= window) {
+                self.recent.pop_front();
+            }
+
+            if self.recent.iter().any(|(_, recent)| recent == message) {
+                return;
+            }
+            self.recent.push_back((now, message.to_string()));
+        }
+
+        self.queue.extend(split(message, MAX_LENGTH));
+    }
+
+    /// The message to send now if there is one and the rate limit allows it
+    pub fn poll(&mut self, now: Instant) -> Option<String> {
+        if matches!(self.next_send, Some(next_send) if now < next_send) {
+            return None;
+        }
+
+        let message = self.queue.pop_front()?;
+
+        let jitter = match self.config.jitter.as_millis() as u64 {
+            0 => Duration::ZERO,
+            millis => Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+        };
+        self.next_send = Some(now + self.config.min_interval + jitter);
+
+        Some(message)
+    }
+
+    /// how many messages are waiting to be sent
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::chat::format::{Msg, strip_codes};
+    use crate::protocol::chat_queue::{ChatConfig, ChatQueue, MAX_LENGTH, split};
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("hello world", 5), vec!["hello", "world"]);
+        assert_eq!(split("a b c d", 3), vec!["a b", "c d"]);
+        assert_eq!(split("  spaced   out  ", 100), vec!["spaced out"]);
+        assert_eq!(split("abcdefgh ij", 3), vec!["abc", "def", "gh", "ij"]);
+        assert!(split("", 10).is_empty());
+
+        // multi byte chars count as one
+        assert_eq!(split("ééé ééé", 3), vec!["ééé", "ééé"]);
+
+        let long = "word ".repeat(100);
+        let parts = split(&long, MAX_LENGTH);
+        assert_eq!(parts.len(), 2);
+        assert!(parts.iter().all(|part| part.len() <= MAX_LENGTH && !part.ends_with(' ')));
+        assert_eq!(parts.join(" "), long.trim());
+    }
+
+    #[test]
+    fn test_split_colored() {
+        let len = |part: &String| part.chars().count();
+
+        // codes count towards the limit
+        let exact = format!("§a{}", "x".repeat(MAX_LENGTH - 2));
+        assert_eq!(split(&exact, MAX_LENGTH), vec![exact.clone()]);
+
+        let over = format!("§a{}", "x".repeat(MAX_LENGTH - 1));
+        let parts = split(&over, MAX_LENGTH);
+        assert_eq!(parts, vec![exact, "§ax".to_string()]);
+
+        // a code right at the limit is not separated from its char
+        let msg = format!("{}§cred", "x".repeat(MAX_LENGTH - 1));
+        assert_eq!(split(&msg, MAX_LENGTH), vec!["x".repeat(MAX_LENGTH - 1), "§cred".to_string()]);
+
+        // the color (and formatting) carries over to the next part
+        let msg = Msg::new().green("done ").gold(&"word ".repeat(60)).bold().gray("(37 blocks)").to_legacy();
+        let parts = split(&msg, MAX_LENGTH);
+        assert_eq!(parts.len(), 2);
+        assert!(parts.iter().all(|part| len(part) <= MAX_LENGTH));
+        assert!(parts[1].starts_with("§6§lword"));
+        assert!(parts[1].ends_with("§7(37 blocks)"));
+
+        let plain: Vec<_> = parts.iter().map(|part| strip_codes(part)).collect();
+        assert_eq!(plain.join(" "), Msg::new().green("done ").gold(&"word ".repeat(60)).gray("(37 blocks)").to_plain());
+
+        // tiny limits still make progress
+        assert_eq!(split("§aabc", 3), vec!["§aa", "§ab", "§ac"]);
+    }
+
+    #[test]
+    fn test_strip_formatting() {
+        let config = ChatConfig { strip_formatting: true, jitter: Duration::ZERO, ..ChatConfig::default() };
+        let mut queue = ChatQueue::new(config);
+        let now = Instant::now();
+        queue.push(&Msg::error("could not mine").to_legacy(), now);
+        assert_eq!(queue.poll(now).as_deref(), Some("could not mine"));
+    }
+
+    #[test]
+    fn test_spacing() {
+        let config = ChatConfig {
+            min_interval: Duration::from_millis(1000),
+            jitter: Duration::from_millis(100),
+            dedup_window: Some(Duration::from_secs(5)),
+            ..ChatConfig::default()
+        };
+
+        let start = Instant::now();
+        let mut queue = ChatQueue::new(config);
+
+        queue.push("first", start);
+        queue.push("second", start);
+        queue.push(&"x".repeat(MAX_LENGTH + 10), start);
+
+        // a duplicate within the window is dropped
+        queue.push("second", start + Duration::from_secs(1));
+        assert_eq!(queue.len(), 4);
+
+        let mut sent = Vec::new();
+        let mut now = start;
+        while now < start + Duration::from_secs(10) {
+            if let Some(message) = queue.poll(now) {
+                sent.push((now, message));
+            }
+            // a tick
+            now += Duration::from_millis(50);
+        }
+
+        let messages: Vec<_> = sent.iter().map(|(_, message)| message.len()).collect();
+        assert_eq!(messages, vec![5, 6, MAX_LENGTH, 10]);
+
+        for pair in sent.windows(2) {
+            let between = pair[1].0 - pair[0].0;
+            assert!(between >= Duration::from_millis(1000), "messages sent {:?} apart", between);
+            assert!(between <= Duration::from_millis(1150), "messages sent {:?} apart", between);
+        }
+
+        // once the window passed the same message can be sent again
+        queue.push("second", now);
+        assert_eq!(queue.poll(now).as_deref(), Some("second"));
+    }
+}
@@ -0,0 +1,298 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Why the server disconnected a bot, classified from the reason it sent (at login or while playing) so the bot can
+//! react accordingly: banned accounts are not reconnected, restarts are retried soon, and throttling backs off.
+
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::chat::Component;
+use crate::error::{err, Error, Res};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    Banned,
+    Throttled,
+
+    /// too many connections from the same address, or the server is full
+    TooManyConnections,
+
+    /// the server is shutting down or restarting
+    ServerClosed,
+
+    /// kicked by an anti-cheat plugin (or vanilla's movement checks)
+    AntiCheat,
+    TimedOut,
+
+    /// kicked by a person or a plugin for some other reason
+    Kicked,
+
+    /// we left ourselves to have the server resend the world because it was out of sync
+    Resync,
+    Other,
+}
+
+/// What to do after a disconnect
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Reconnect {
+    Never,
+
+    /// after a short fixed delay
+    Soon,
+
+    /// after an exponentially increasing delay
+    Backoff,
+}
+
+impl DisconnectReason {
+    const ALL: [DisconnectReason; 9] = [
+        DisconnectReason::Banned,
+        DisconnectReason::Throttled,
+        DisconnectReason::TooManyConnections,
+        DisconnectReason::ServerClosed,
+        DisconnectReason::AntiCheat,
+        DisconnectReason::TimedOut,
+        DisconnectReason::Kicked,
+        DisconnectReason::Resync,
+        DisconnectReason::Other,
+    ];
+
+    /// a short human readable name
+    pub fn name(self) -> &'static str {
+        match self {
+            DisconnectReason::Banned => "banned",
+            DisconnectReason::Throttled => "throttled",
+            DisconnectReason::TooManyConnections => "too many connections",
+            DisconnectReason::ServerClosed => "server closed",
+            DisconnectReason::AntiCheat => "anti-cheat",
+            DisconnectReason::TimedOut => "timed out",
+            DisconnectReason::Kicked => "kicked",
+            DisconnectReason::Resync => "resync",
+            DisconnectReason::Other => "other",
+        }
+    }
+
+    pub fn reconnect(self) -> Reconnect {
+        match self {
+            DisconnectReason::Banned | DisconnectReason::Kicked | DisconnectReason::Other => Reconnect::Never,
+            DisconnectReason::ServerClosed | DisconnectReason::TimedOut | DisconnectReason::Resync => Reconnect::Soon,
+            DisconnectReason::Throttled | DisconnectReason::TooManyConnections | DisconnectReason::AntiCheat => Reconnect::Backoff,
+        }
+    }
+}
+
+impl FromStr for DisconnectReason {
+    type Err = Error;
+
+    /// the snake case name, e.g., `anti_cheat`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DisconnectReason::ALL.iter().copied()
+            .find(|reason| reason.name().replace(|c: char| !c.is_alphanumeric(), "_") == s)
+            .ok_or_else(|| err(&format!("unknown disconnect reason {}", s)))
+    }
+}
+
+/// A classified disconnect
+#[derive(Clone, Debug)]
+pub struct Disconnect {
+    pub reason: DisconnectReason,
+
+    /// the plain text of the reason the server sent
+    pub text: String,
+
+    /// the reason as the server sent it (usually chat json)
+    pub raw: String,
+}
+
+/// Case insensitive substrings of disconnect messages mapped to reasons. The first matching pattern wins.
+#[derive(Clone, Debug)]
+pub struct DisconnectPatterns {
+    patterns: Vec<(String, DisconnectReason)>,
+}
+
+impl Default for DisconnectPatterns {
+    fn default() -> Self {
+        use DisconnectReason::*;
+
+        const DEFAULTS: [(&str, DisconnectReason); 24] = [
+            ("banned", Banned),
+            ("ban ", Banned),
+            ("blacklisted", Banned),
+            ("too many connections", TooManyConnections),
+            ("already connected", TooManyConnections),
+            ("server is full", TooManyConnections),
+            ("throttled", Throttled),
+            ("wait before reconnecting", Throttled),
+            ("logging in too fast", Throttled),
+            ("server closed", ServerClosed),
+            ("shutting down", ServerClosed),
+            ("restarting", ServerClosed),
+            ("flying is not enabled", AntiCheat),
+            ("illegal", AntiCheat),
+            ("hacking", AntiCheat),
+            ("cheat", AntiCheat),
+            ("unfair advantage", AntiCheat),
+            ("invalid move", AntiCheat),
+            ("moved too quickly", AntiCheat),
+            ("timed out", TimedOut),
+            ("timeout", TimedOut),
+            ("kicked", Kicked),
+            ("spam", Kicked),
+            ("afk", Kicked),
+        ];
+
+        DisconnectPatterns {
+            patterns: DEFAULTS.iter().map(|(pattern, reason)| (pattern.to_string(), *reason)).collect()
+        }
+    }
+}
+
+impl DisconnectPatterns {
+    /// The default patterns with the patterns in `path` taking precedence. Every line is `<reason>: <pattern>`, e.g.,
+    /// `anti_cheat: you moved too fast`. Empty lines and lines starting with `#` are ignored.
+    pub fn load(path: &str) -> Res<DisconnectPatterns> {
+        let file = fs::read_to_string(path)?;
+
+        let mut patterns = Vec::new();
+        for line in file.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (reason, pattern) = line.split_once(':').ok_or_else(|| err(&format!("expected <reason>: <pattern> but got {}", line)))?;
+            patterns.push((pattern.trim().to_lowercase(), reason.trim().parse()?));
+        }
+
+        patterns.extend(DisconnectPatterns::default().patterns);
+        Ok(DisconnectPatterns { patterns })
+    }
+
+    /// Classify the raw reason (chat json or plain text) the server sent
+    pub fn classify(&self, raw: &str) -> Disconnect {
+        let text = Component::parse(raw).to_plain_text();
+        let lower = text.to_lowercase();
+
+        let reason = self.patterns.iter()
+            .find(|(pattern, _)| lower.contains(pattern.as_str()))
+            .map(|(_, reason)| *reason)
+            .unwrap_or(DisconnectReason::Other);
+
+        Disconnect { reason, text, raw: raw.to_string() }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Kick {
+    pub reason: DisconnectReason,
+    pub text: String,
+    pub at: SystemTime,
+}
+
+/// Every disconnect of every account during this run. Clones share the same history.
+#[derive(Clone, Debug, Default)]
+pub struct KickHistory {
+    inner: Arc<Mutex<HashMap<String, Vec<Kick>>>>,
+}
+
+impl KickHistory {
+    pub fn record(&self, account: &str, disconnect: &Disconnect) {
+        let kick = Kick { reason: disconnect.reason, text: disconnect.text.clone(), at: SystemTime::now() };
+        self.inner.lock().unwrap().entry(account.to_string()).or_default().push(kick);
+    }
+
+    /// how many times the account was disconnected
+    pub fn count(&self, account: &str) -> usize {
+        self.inner.lock().unwrap().get(account).map_or(0, Vec::len)
+    }
+
+    /// the kicks of every account sorted by account
+    pub fn accounts(&self) -> Vec<(String, Vec<Kick>)> {
+        let inner = self.inner.lock().unwrap();
+        let mut accounts: Vec<_> = inner.iter().map(|(account, kicks)| (account.clone(), kicks.clone())).collect();
+        accounts.sort_by(|(a, _), (b, _)| a.cmp(b));
+        accounts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::protocol::disconnect::{DisconnectPatterns, DisconnectReason, KickHistory, Reconnect};
+
+    #[test]
+    fn test_classify() {
+        let patterns = DisconnectPatterns::default();
+        let classify = |raw: &str| patterns.classify(raw).reason;
+
+        assert_eq!(classify(r#"{"text":"You are banned from this server!\nReason: §cHacking"}"#), DisconnectReason::Banned);
+        assert_eq!(classify(r#"{"translate":"multiplayer.disconnect.server_shutdown","with":[],"text":"Server closed"}"#), DisconnectReason::ServerClosed);
+        assert_eq!(classify("Connection throttled! Please wait before reconnecting."), DisconnectReason::Throttled);
+        assert_eq!(classify(r#"{"extra":[{"text":"Too many connections from your IP"}],"text":""}"#), DisconnectReason::TooManyConnections);
+        assert_eq!(classify(r#"{"text":"Flying is not enabled on this server"}"#), DisconnectReason::AntiCheat);
+        assert_eq!(classify("Timed out"), DisconnectReason::TimedOut);
+        assert_eq!(classify("You have been kicked"), DisconnectReason::Kicked);
+        assert_eq!(classify("something else"), DisconnectReason::Other);
+
+        let disconnect = patterns.classify(r#"{"text":"§cServer closed"}"#);
+        assert_eq!(disconnect.text, "Server closed");
+        assert_eq!(disconnect.reason.reconnect(), Reconnect::Soon);
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("disconnect-patterns-{}.txt", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(file, "# the anti-cheat of our server").unwrap();
+        writeln!(file, "anti_cheat: Sent too many packets").unwrap();
+        writeln!(file, "server_closed: you have been kicked for a restart").unwrap();
+        drop(file);
+
+        let patterns = DisconnectPatterns::load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(patterns.classify("You sent too many packets").reason, DisconnectReason::AntiCheat);
+
+        // earlier patterns win over the default "kicked"
+        assert_eq!(patterns.classify("You have been kicked for a restart").reason, DisconnectReason::ServerClosed);
+        assert_eq!(patterns.classify("You are banned").reason, DisconnectReason::Banned);
+
+        assert!("not_a_reason".parse::<DisconnectReason>().is_err());
+        assert_eq!("too_many_connections".parse::<DisconnectReason>().unwrap(), DisconnectReason::TooManyConnections);
+    }
+
+    #[test]
+    fn test_history() {
+        let history = KickHistory::default();
+        let patterns = DisconnectPatterns::default();
+
+        history.record("bob", &patterns.classify("Timed out"));
+        // clones share the history
+        let clone = history.clone();
+        clone.record("alice", &patterns.classify("You are banned"));
+        history.record("bob", &patterns.classify("You are banned"));
+
+        assert_eq!(history.count("bob"), 2);
+        assert_eq!(history.count("carol"), 0);
+
+        let accounts = history.accounts();
+        assert_eq!(accounts[0].0, "alice");
+        assert_eq!(accounts[1].1.last().unwrap().reason, DisconnectReason::Banned);
+    }
+}
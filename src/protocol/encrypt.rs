@@ -18,6 +18,7 @@ use rand::random;
 use rand::rngs::OsRng;
 use rsa::{BigUint, PaddingScheme, PublicKey, RSAPublicKey};
 
+/// Encrypts the shared secret while logging in. Pure Rust whichever cipher feature is enabled.
 pub struct Rsa {
     key: RSAPublicKey,
 }
@@ -54,3 +55,28 @@ pub fn rand_bits() -> [u8; 16] {
 
     arr
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+    use rsa::{PaddingScheme, PublicKeyParts, RSAPrivateKey};
+
+    use crate::protocol::encrypt::{rand_bits, Rsa};
+
+    /// the server's public key arrives as DER and the shared secret is encrypted with PKCS#1 v1.5
+    #[test]
+    fn test_rsa() {
+        let private = RSAPrivateKey::new(&mut OsRng, 1024).unwrap();
+
+        // the integers are signed in DER, so like the server does the modulus needs a leading zero
+        let n = [&[0], private.n().to_bytes_be().as_slice()].concat();
+        let der = rsa_der::public_key_to_der(&n, &private.e().to_bytes_be());
+
+        let secret = rand_bits();
+        let encrypted = Rsa::from_der(&der).encrypt(&secret).unwrap();
+        assert_eq!(encrypted.len(), 128);
+
+        let decrypted = private.decrypt(PaddingScheme::new_pkcs1v15_encrypt(), &encrypted).unwrap();
+        assert_eq!(decrypted, secret);
+    }
+}
@@ -0,0 +1,180 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! AES-128-CFB8, the stream cipher of the connection once logged in. Pure Rust with the `rust-crypto` feature
+//! (default) or OpenSSL with the `openssl` feature, which wins if both are enabled. Each cipher is used for one
+//! direction only, like the reader and the writer do.
+
+#[cfg(not(any(feature = "rust-crypto", feature = "openssl")))]
+compile_error!("enable the rust-crypto or openssl feature for the encryption of the connection");
+
+#[cfg(feature = "openssl")]
+pub use self::openssl::Cfb8;
+#[cfg(all(feature = "rust-crypto", not(feature = "openssl")))]
+pub use self::rust_crypto::Cfb8;
+
+#[cfg(feature = "rust-crypto")]
+#[cfg_attr(all(feature = "openssl", not(test)), allow(dead_code))]
+mod rust_crypto {
+    use aes::Aes128;
+    use aes::cipher::{AsyncStreamCipher, NewCipher};
+
+    /// https://github.com/RustCrypto/block-ciphers/issues/28
+    /// https://docs.rs/cfb-mode/0.7.1/cfb_mode/
+    pub struct Cfb8 {
+        cipher: cfb8::Cfb8<Aes128>,
+    }
+
+    impl Cfb8 {
+        pub fn new(key: &[u8], iv: &[u8]) -> Cfb8 {
+            Cfb8 {
+                cipher: cfb8::Cfb8::new_from_slices(key, iv).unwrap(),
+            }
+        }
+
+        pub fn encrypt(&mut self, data: &mut [u8]) {
+            self.cipher.encrypt(data);
+        }
+
+        pub fn decrypt(&mut self, data: &mut [u8]) {
+            self.cipher.decrypt(data);
+        }
+    }
+}
+
+#[cfg(feature = "openssl")]
+mod openssl {
+    use ::openssl::symm::{Cipher, Crypter, Mode};
+
+    pub struct Cfb8 {
+        encrypter: Crypter,
+        decrypter: Crypter,
+
+        /// OpenSSL does not work in place
+        scratch: Vec<u8>,
+    }
+
+    impl Cfb8 {
+        pub fn new(key: &[u8], iv: &[u8]) -> Cfb8 {
+            let crypter = |mode| Crypter::new(Cipher::aes_128_cfb8(), mode, key, Some(iv)).unwrap();
+            Cfb8 {
+                encrypter: crypter(Mode::Encrypt),
+                decrypter: crypter(Mode::Decrypt),
+                scratch: Vec::new(),
+            }
+        }
+
+        fn update(crypter: &mut Crypter, scratch: &mut Vec<u8>, data: &mut [u8]) {
+            // OpenSSL wants room for one more block, which is a single byte for CFB8
+            scratch.resize(data.len() + 1, 0);
+            let len = crypter.update(data, scratch).unwrap();
+            data.copy_from_slice(&scratch[..len]);
+        }
+
+        pub fn encrypt(&mut self, data: &mut [u8]) {
+            Self::update(&mut self.encrypter, &mut self.scratch, data);
+        }
+
+        pub fn decrypt(&mut self, data: &mut [u8]) {
+            Self::update(&mut self.decrypter, &mut self.scratch, data);
+        }
+    }
+}
+
+/// The same vectors for every implementation so they are byte-compatible with each other and vanilla servers
+#[cfg(test)]
+macro_rules! suite {
+    ($name: ident, $cipher: ty) => {
+        mod $name {
+            type Cfb8 = $cipher;
+
+            const KEY: [u8; 16] = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+
+            /// the ciphertext OpenSSL (EVP_aes_128_cfb8) produces with the key as the iv. CFB128 would give
+            /// 125cffb60ca7... so this also checks the segment size.
+            const HANDSHAKE: &str = "1245a5300172cdee4356c48e849e53b62929a5e965708c9d92";
+
+            fn hex(bytes: &[u8]) -> String {
+                bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+            }
+
+            /// NIST SP 800-38A F.3.7 (CFB8-AES128.Encrypt)
+            #[test]
+            fn test_nist() {
+                let iv: Vec<u8> = (0..16).collect();
+
+                let mut data = [0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a, 0xae, 0x2d];
+                Cfb8::new(&KEY, &iv).encrypt(&mut data);
+                assert_eq!(hex(&data), "3b79424c9c0dd436bace9e0ed4586a4f32b9");
+
+                Cfb8::new(&KEY, &iv).decrypt(&mut data);
+                assert_eq!(hex(&data), "6bc1bee22e409f96e93d7e117393172aae2d");
+            }
+
+            /// packets are encrypted as they are written so the result must not depend on how the stream is split
+            #[test]
+            fn test_split() {
+                let mut data = b"minecraft login handshake".to_vec();
+                Cfb8::new(&KEY, &KEY).encrypt(&mut data);
+                assert_eq!(hex(&data), HANDSHAKE);
+
+                let mut split = b"minecraft login handshake".to_vec();
+                let mut cipher = Cfb8::new(&KEY, &KEY);
+                let (a, rest) = split.split_at_mut(1);
+                let (b, c) = rest.split_at_mut(10);
+                cipher.encrypt(a);
+                cipher.encrypt(&mut []);
+                cipher.encrypt(b);
+                cipher.encrypt(c);
+                assert_eq!(hex(&split), HANDSHAKE);
+
+                let mut cipher = Cfb8::new(&KEY, &KEY);
+                cipher.decrypt(&mut split[..7]);
+                cipher.decrypt(&mut split[7..]);
+                assert_eq!(split, b"minecraft login handshake");
+            }
+
+            /// both ends of a connection, each encrypting one chunk at a time before the other decrypts it
+            #[test]
+            fn test_interleaved() {
+                let mut writer = Cfb8::new(&KEY, &KEY);
+                let mut reader = Cfb8::new(&KEY, &KEY);
+
+                let message: Vec<u8> = (0..=255).collect();
+                let mut sent = Vec::new();
+
+                for chunk in message.chunks(37) {
+                    let mut chunk = chunk.to_vec();
+                    writer.encrypt(&mut chunk);
+                    sent.extend_from_slice(&chunk);
+
+                    reader.decrypt(&mut chunk);
+                    assert_eq!(chunk, &message[sent.len() - chunk.len()..sent.len()]);
+                }
+
+                let mut one_shot = message.clone();
+                Cfb8::new(&KEY, &KEY).encrypt(&mut one_shot);
+                assert_eq!(sent, one_shot);
+            }
+        }
+    };
+}
+
+#[cfg(all(test, feature = "rust-crypto"))]
+suite!(rust_crypto_tests, crate::protocol::io::cipher::rust_crypto::Cfb8);
+
+#[cfg(all(test, feature = "openssl"))]
+suite!(openssl_tests, crate::protocol::io::cipher::openssl::Cfb8);
@@ -14,31 +14,28 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::io::{Read, Write};
+use std::io::Write;
 
-use aes::Aes128;
-use aes::cipher::{AsyncStreamCipher, NewCipher};
-use cfb8::Cfb8;
-use flate2::Compression;
-use flate2::read::ZlibDecoder;
+use flate2::{Compression, Decompress, FlushDecompress, Status};
 use flate2::write::ZlibEncoder;
 
+use crate::error::{err, Res};
+
+use crate::protocol::io::cipher::Cfb8;
+
+mod cipher;
 pub mod reader;
 pub mod writer;
 
-type AesCfb8 = Cfb8<Aes128>;
-
-/// https://github.com/RustCrypto/block-ciphers/issues/28
-/// https://docs.rs/cfb-mode/0.7.1/cfb_mode/
 /// as per https://wiki.vg/Protocol_Encryption#Symmetric_Encryption the key and iv are the same
 struct Aes {
-    cipher: AesCfb8,
+    cipher: Cfb8,
 }
 
 impl Aes {
     pub fn new(key: &[u8]) -> Aes {
         Aes {
-            cipher: AesCfb8::new_from_slices(key, key).unwrap(),
+            cipher: Cfb8::new(key, key),
         }
     }
 
@@ -56,19 +53,13 @@ struct ZLib {
     threshold: u32,
 }
 
-const EXPANSION: f64 = 1.5;
-
 /// https://wiki.vg/Protocol#With_compression
 impl ZLib {
-    fn new(threshold: u32) -> ZLib {
-        ZLib {
-            threshold
-        }
-    }
-    pub fn decompress(&self, input: &[u8]) -> Vec<u8> {
-        let mut buf = Vec::with_capacity((input.len() as f64 * EXPANSION) as usize);
-        ZlibDecoder::new(input).read_to_end(&mut buf).unwrap();
-        buf
+    /// None if the threshold is negative, which turns compression off
+    fn new(threshold: i32) -> Option<ZLib> {
+        (threshold >= 0).then(|| ZLib {
+            threshold: threshold as u32
+        })
     }
 
     pub fn compress(&self, input: &[u8]) -> tokio::io::Result<Vec<u8>> {
@@ -77,3 +68,113 @@ impl ZLib {
         e.finish()
     }
 }
+
+/// Inflates the packets of one connection. Chunk packets are big and frequent so the zlib context is reset for every
+/// packet instead of allocated again.
+struct Inflate {
+    context: Decompress,
+}
+
+impl Inflate {
+    fn new() -> Inflate {
+        Inflate {
+            context: Decompress::new(true)
+        }
+    }
+
+    /// decompress `input` into `output`, which is as long as the server says the packet is
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Res {
+        self.context.reset(true);
+        let status = self.context.decompress(input, output, FlushDecompress::Finish).map_err(|_| err("invalid zlib data"))?;
+
+        let written = self.context.total_out() as usize;
+        if status != Status::StreamEnd || written != output.len() {
+            return Err(err(&format!("packet did not inflate to {} bytes", output.len())));
+        }
+
+        Ok(())
+    }
+}
+
+/// the number of bytes `value` takes as a VarInt
+fn var_int_len(value: i32) -> usize {
+    let bits = 32 - (value as u32).leading_zeros();
+    (bits.max(1) as usize + 6) / 7
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::time::Instant;
+
+    use flate2::read::ZlibDecoder;
+
+    use swarm_bot_packets::types::VarInt;
+    use swarm_bot_packets::write::ByteWriter;
+
+    use crate::protocol::io::{Inflate, var_int_len, ZLib};
+
+    #[test]
+    fn test_var_int_len() {
+        for value in [0, 1, 127, 128, 255, 16383, 16384, 2097151, 2097152, i32::MAX, -1, i32::MIN] {
+            let mut writer = ByteWriter::new();
+            writer.write(VarInt(value));
+            assert_eq!(var_int_len(value), writer.freeze().len(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_inflate() {
+        let zlib = ZLib::new(256).unwrap();
+        let mut inflate = Inflate::new();
+
+        // the context is reused between packets
+        for len in [300, 5000, 300] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 7) as u8).collect();
+            let compressed = zlib.compress(&data).unwrap();
+            let mut output = vec![0; len];
+            inflate.decompress(&compressed, &mut output).unwrap();
+            assert_eq!(output, data);
+        }
+
+        // the server lied about the size
+        let compressed = zlib.compress(&[1, 2, 3]).unwrap();
+        assert!(inflate.decompress(&compressed, &mut [0; 4]).is_err());
+        assert!(inflate.decompress(&compressed, &mut [0; 2]).is_err());
+        assert!(inflate.decompress(&[1, 2, 3], &mut [0; 3]).is_err());
+
+        assert!(ZLib::new(-1).is_none());
+        assert_eq!(ZLib::new(0).unwrap().threshold, 0);
+    }
+
+    /// decodes 2000 chunk sized packets with a new decoder for each packet and with [`Inflate`].
+    /// Run with `cargo test --release -- --ignored --nocapture bench_inflate`.
+    #[test]
+    #[ignore]
+    fn bench_inflate() {
+        let zlib = ZLib::new(256).unwrap();
+
+        // a chunk column is mostly a few repeated block states
+        let packets: Vec<(Vec<u8>, usize)> = (0..2000).map(|seed: usize| {
+            let data: Vec<u8> = (0..40_000).map(|i: usize| ((i / 64 + seed) % 13) as u8).collect();
+            (zlib.compress(&data).unwrap(), data.len())
+        }).collect();
+
+        let start = Instant::now();
+        for (compressed, len) in &packets {
+            let mut output = Vec::with_capacity(compressed.len() * 3 / 2);
+            ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut output).unwrap();
+            assert_eq!(output.len(), *len);
+        }
+        println!("a decoder per packet: {:?}", start.elapsed());
+
+        let mut inflate = Inflate::new();
+        let mut output = Vec::new();
+        let start = Instant::now();
+        for (compressed, len) in &packets {
+            output.resize(*len, 0);
+            inflate.decompress(compressed, &mut output).unwrap();
+        }
+        println!("reused context: {:?}", start.elapsed());
+    }
+}
@@ -14,27 +14,70 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::convert::TryFrom;
 use std::pin::Pin;
+use std::sync::mpsc::Sender;
 use std::task::{Context, Poll};
+use std::time::Instant;
 
+use bytes::BytesMut;
 use tokio::io::{AsyncRead, AsyncReadExt, BufReader, ReadBuf};
 use tokio::net::tcp::OwnedReadHalf;
 
-use swarm_bot_packets::read::{ByteReadable, ByteReader, LenRead};
-use swarm_bot_packets::types::{Packet, RawVec, VarInt};
+use swarm_bot_packets::read::{ByteReadable, ByteReader};
+use swarm_bot_packets::types::{Packet, PacketState, VarInt};
+use swarm_bot_packets::write::ByteWritable;
 
-use crate::error::Error::WrongPacket;
-use crate::error::Res;
-use crate::protocol::io::{Aes, ZLib};
+use crate::bootstrap::tunnel::TunnelRead;
+use crate::client::metrics::METRICS;
+use crate::error::Error::{Disconnect, WrongPacket};
+use crate::error::{err, Phase, Res, SwarmError};
+use crate::protocol::io::{Aes, Inflate, var_int_len};
+use crate::protocol::io::writer::PacketWriteChannel;
+use crate::protocol::tick::TickTimer;
 use crate::types::PacketData;
 
+/// The packets of a protocol version which [`PacketReader::spawn`] deals with itself
+pub struct Immediate<K> {
+    /// the id of clientbound Keep Alive
+    pub keep_alive: u32,
+
+    /// the id of Time Update. Its first field has to be the world age.
+    pub time_update: u32,
+
+    /// the serverbound Keep Alive answering an id
+    pub reply: fn(u64) -> K,
+}
+
 pub struct PacketReader {
     reader: EncryptedReader,
-    compression: Option<ZLib>,
+
+    /// None until the server enables compression
+    compression: Option<Inflate>,
+
+    /// the compressed data of the last packet. Reused as only the inflated packet is passed on.
+    compressed: Vec<u8>,
+
+    /// Packets are read (or inflated) into this buffer and split off without copying. Once the game loop dropped
+    /// them the memory is used again, so in the steady state reading a packet does not allocate.
+    pool: BytesMut,
+}
+
+/// enough for the packets of a few ticks. Chunk packets larger than this get their own allocation.
+const POOL_CAPACITY: usize = 64 * 1024;
+
+/// the largest packet (or inflated packet) vanilla accepts
+const MAX_PACKET_LEN: usize = 1 << 23;
+
+/// a length the server sent, checked before anything is allocated for it
+fn checked_len(len: i32) -> Res<usize> {
+    usize::try_from(len).ok()
+        .filter(|&len| len <= MAX_PACKET_LEN)
+        .ok_or_else(|| err(&format!("packet length {} is not between 0 and {}", len, MAX_PACKET_LEN)))
 }
 
 struct EncryptedReader {
-    reader: BufReader<OwnedReadHalf>,
+    reader: BufReader<TunnelRead>,
     cipher: Option<Aes>,
 }
 
@@ -55,6 +98,12 @@ impl AsyncRead for EncryptedReader {
 
 impl From<OwnedReadHalf> for PacketReader {
     fn from(read: OwnedReadHalf) -> Self {
+        PacketReader::from(Box::new(read) as TunnelRead)
+    }
+}
+
+impl From<TunnelRead> for PacketReader {
+    fn from(read: TunnelRead) -> Self {
         let reader = BufReader::new(read);
 
         let reader = EncryptedReader {
@@ -65,6 +114,8 @@ impl From<OwnedReadHalf> for PacketReader {
         PacketReader {
             reader,
             compression: None,
+            compressed: Vec::new(),
+            pool: BytesMut::with_capacity(POOL_CAPACITY),
         }
     }
 }
@@ -75,8 +126,14 @@ impl PacketReader {
         self.reader.cipher = Some(Aes::new(key));
     }
 
-    pub fn compression(&mut self, threshold: u32) {
-        self.compression = Some(ZLib::new(threshold))
+    /// Takes effect for the next packet. The threshold only matters when writing: a packet below it arrives with a
+    /// data length of 0 and is not inflated. A negative threshold turns compression off.
+    pub fn compression(&mut self, threshold: i32) {
+        if threshold < 0 {
+            self.compression = None;
+        } else if self.compression.is_none() {
+            self.compression = Some(Inflate::new());
+        }
     }
 
     pub async fn read(&mut self) -> Res<PacketData> {
@@ -87,23 +144,46 @@ impl PacketReader {
             let len = VarInt::read_async(Pin::new(&mut self.reader)).await;
             let len = len.0;
             if len != 0 {
-                pkt_len = len as usize;
+                pkt_len = checked_len(len)?;
                 break;
             }
         }
 
-        // the raw bytes with length determined by pkt_len
-        let mut data = vec![0_u8; pkt_len];
-        self.reader.read_exact(&mut data).await.unwrap();
+        METRICS.packets_in.inc();
+        METRICS.bytes_in.add((var_int_len(pkt_len as i32) + pkt_len) as u64);
 
-        let mut reader = ByteReader::new(data);
+        // reclaims the pool if no packets from it are alive, otherwise allocates a new one
+        let packet_len = match self.compression.as_mut() {
+            None => {
+                self.pool.reserve(pkt_len);
+                self.pool.resize(pkt_len, 0);
+                self.reader.read_exact(&mut self.pool).await?;
+                pkt_len
+            }
+            Some(inflate) => {
+                let VarInt(data_len) = VarInt::read_async(Pin::new(&mut self.reader)).await;
+                let left = pkt_len.checked_sub(var_int_len(data_len)).ok_or_else(|| err("packet shorter than its data length"))?;
+                let data_len = checked_len(data_len)?;
+
+                if data_len == 0 {
+                    // below the threshold
+                    self.pool.reserve(left);
+                    self.pool.resize(left, 0);
+                    self.reader.read_exact(&mut self.pool).await?;
+                    left
+                } else {
+                    self.compressed.resize(left, 0);
+                    self.reader.read_exact(&mut self.compressed).await?;
 
-        let data = match self.compression.as_ref() {
-            None => packet_reader(&mut reader, pkt_len),
-            Some(zlib) => packet_reader_compressed(&mut reader, zlib, pkt_len)
+                    self.pool.reserve(data_len);
+                    self.pool.resize(data_len, 0);
+                    inflate.decompress(&self.compressed, &mut self.pool)?;
+                    data_len
+                }
+            }
         };
 
-        let mut reader = ByteReader::new(data);
+        let mut reader = ByteReader::from_bytes(self.pool.split_to(packet_len).freeze());
         let VarInt(id) = reader.read();
 
         Ok(PacketData {
@@ -112,14 +192,51 @@ impl PacketReader {
         })
     }
 
+    /// Read packets on a worker thread of the runtime (not the thread of the game loop) until the connection closes and
+    /// send them to `tx`. Keep alives are answered through `out` as soon as they are read, so a game loop busy with
+    /// pathfinding cannot get the bot timed out, and are not passed on. Time Updates are timed in `ticks` on arrival.
+    /// `inspect` sees every packet first. Errors reading are logged with the `account` they happened to.
+    pub fn spawn<K>(mut self, account: String, immediate: Immediate<K>, mut out: PacketWriteChannel, ticks: TickTimer, tx: Sender<PacketData>, mut inspect: impl FnMut(&PacketData) + Send + 'static)
+        where K: Packet + ByteWritable + 'static {
+        tokio::task::spawn(async move {
+            loop {
+                let packet = match self.read().await {
+                    Ok(packet) => packet,
+                    Err(error) => {
+                        // the queue sees the connection closed once `tx` is dropped
+                        warn!(error = %SwarmError::new(&account, Phase::Play, error), "stopped reading");
+                        return;
+                    }
+                };
+                inspect(&packet);
+
+                if packet.id == immediate.keep_alive {
+                    let id: u64 = packet.reader.clone().read();
+                    out.write((immediate.reply)(id));
+                    continue;
+                }
+
+                if packet.id == immediate.time_update {
+                    let world_age: u64 = packet.reader.clone().read();
+                    ticks.record(world_age as i64, Instant::now());
+                }
+
+                if tx.send(packet).is_err() {
+                    // the other end is stopped and should have printed the error
+                    return;
+                }
+            }
+        });
+    }
+
     pub async fn read_exact_packet<T>(&mut self) -> Res<T> where T: Packet, T: ByteReadable {
         let PacketData { id, mut reader } = self.read().await?;
 
-        // if id == 0 && T::STATE == PacketState::Login {
-        //     let Disconnect {reason} = reader.read();
-        //     println!("disconnected because {}", reason);
-        //     // return Err(crate::Error::Disconnect(reason))
-        // }
+        // the login disconnect packet always has id 0
+        if id == 0 && T::STATE == PacketState::Login && T::ID != 0 {
+            let reason: String = reader.read();
+            return Err(Disconnect(reason));
+        }
 
         if id != T::ID {
             Err(WrongPacket {
@@ -134,21 +251,268 @@ impl PacketReader {
     }
 }
 
-fn packet_reader_compressed(reader: &mut ByteReader, zlib: &ZLib, len: usize) -> Vec<u8> {
-    let data: LenRead<VarInt> = reader.read_with_len();
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    use more_asserts::*;
+    use swarm_bot_packets::types::{Packet, VarInt};
+    use swarm_bot_packets::write::ByteWriter;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::bootstrap::tunnel::{TunnelRead, TunnelWrite};
+    use crate::protocol::io::reader::{Immediate, PacketReader};
+    use crate::protocol::io::writer::PacketWriter;
+    use crate::protocol::io::ZLib;
+    use crate::protocol::tick::TickTimer;
+    use crate::protocol::v340::{clientbound, serverbound};
+
+    /// A fake server sends a keep alive while the game loop is stuck. The answer must not wait for the game loop. Time
+    /// is paused, so it only passes while every task waits and a reply which needed the game loop would time out.
+    #[test]
+    fn test_keep_alive_stalled() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            tokio::time::pause();
+
+            let (client, mut server) = tokio::io::duplex(1024);
+            let (read, write) = tokio::io::split(client);
 
-    let len_left = len - data.len;
+            // the game loop never takes a packet, like when it is stuck in expensive pathfinding
+            let (tx, rx) = std::sync::mpsc::channel();
+            let ticks = TickTimer::default();
 
-    let RawVec(inner) = reader.read_like(&len_left);
+            let immediate = Immediate {
+                keep_alive: clientbound::KeepAlive::ID,
+                time_update: clientbound::TimeUpdate::ID,
+                reply: |id| serverbound::KeepAlive { id },
+            };
 
-    if data.value.0 == 0 {
-        inner
-    } else {
-        zlib.decompress(&inner)
+            let out = PacketWriter::from(Box::new(write) as TunnelWrite).into_channel();
+            PacketReader::from(Box::new(read) as TunnelRead).spawn("bot".to_string(), immediate, out, ticks.clone(), tx, |_| {});
+
+            // length 9, keep alive, id 1234
+            let mut keep_alive = vec![9, clientbound::KeepAlive::ID as u8];
+            keep_alive.extend_from_slice(&1234_u64.to_be_bytes());
+            server.write_all(&keep_alive).await.unwrap();
+
+            let mut reply = [0_u8; 10];
+            tokio::time::timeout(Duration::from_secs(1), server.read_exact(&mut reply)).await
+                .expect("the keep alive was not answered")
+                .unwrap();
+
+            assert_eq!(reply[..2], [9, serverbound::KeepAlive::ID as u8]);
+            assert_eq!(reply[2..], 1234_u64.to_be_bytes());
+            assert!(rx.try_recv().is_err());
+
+            // time updates are timed by the reader and still passed on, keep alives are not
+            let mut time_update = vec![17, clientbound::TimeUpdate::ID as u8];
+            time_update.extend_from_slice(&100_u64.to_be_bytes());
+            time_update.extend_from_slice(&6000_u64.to_be_bytes());
+            server.write_all(&time_update).await.unwrap();
+
+            let mut packet = None;
+            for _ in 0..100 {
+                if let Ok(received) = rx.try_recv() {
+                    packet = Some(received);
+                    break;
+                }
+                tokio::time::advance(Duration::from_millis(10)).await;
+            }
+
+            assert_eq!(packet.expect("the time update was not passed on").id, clientbound::TimeUpdate::ID);
+            assert!(rx.try_recv().is_err());
+            assert_eq!(ticks.tps(), None);
+        });
     }
-}
 
-fn packet_reader(byte_reader: &mut ByteReader, len: usize) -> Vec<u8> {
-    let RawVec(inner) = byte_reader.read_like(&len);
-    inner
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = Cell::new(0);
+    }
+
+    /// counts the allocations of each thread so tests running in parallel do not interfere
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(Cell::get)
+    }
+
+    fn frame(parts: &[&[u8]]) -> Vec<u8> {
+        let body: Vec<u8> = parts.concat();
+        let mut writer = ByteWriter::new();
+        writer.write(VarInt(body.len() as i32));
+        let mut frame = writer.freeze();
+        frame.extend_from_slice(&body);
+        frame
+    }
+
+    fn var_int(value: i32) -> Vec<u8> {
+        let mut writer = ByteWriter::new();
+        writer.write(VarInt(value));
+        writer.freeze()
+    }
+
+    /// a connection to a fake server which sends `stream`. The last reader reads what the client writes.
+    async fn connect(stream: Vec<u8>) -> (PacketReader, PacketWriter, PacketReader) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let client = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        let (server_read, mut server_write) = server.into_split();
+
+        // not awaited as a long stream does not fit in the socket buffers
+        tokio::task::spawn(async move {
+            server_write.write_all(&stream).await.unwrap();
+        });
+
+        let (read, write) = client.into_split();
+        (PacketReader::from(read), PacketWriter::from(write), PacketReader::from(server_read))
+    }
+
+    #[test]
+    fn test_compression_threshold() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+        let zlib = ZLib::new(64).unwrap();
+        let big: Vec<u8> = std::iter::once(0x07).chain((0..100).map(|i| i as u8)).collect();
+        let compressed = zlib.compress(&big).unwrap();
+
+        let stream = [
+            // before Set Compression
+            frame(&[&[0x05, 0xAA, 0xBB]]),
+
+            // below the threshold: a data length of 0 and not inflated
+            frame(&[&[0x00], &[0x06, 0xCC, 0xDD]]),
+
+            // above the threshold
+            frame(&[&var_int(big.len() as i32), &compressed]),
+
+            // compression turned off again
+            frame(&[&[0x08, 0x01, 0x02]]),
+        ].concat();
+
+        rt.block_on(async move {
+            let (mut reader, mut writer, mut server) = connect(stream).await;
+
+            let mut packet = reader.read().await.unwrap();
+            assert_eq!(packet.id, 0x05);
+            assert_eq!(packet.reader.read::<[u8; 2]>(), [0xAA, 0xBB]);
+
+            // the change applies to the very next packet
+            reader.compression(64);
+
+            let mut packet = reader.read().await.unwrap();
+            assert_eq!(packet.id, 0x06);
+            assert_eq!(packet.reader.read::<[u8; 2]>(), [0xCC, 0xDD]);
+            assert!(packet.reader.is_empty());
+
+            let packet = reader.read().await.unwrap();
+            assert_eq!(packet.id, 0x07);
+            assert_eq!(packet.reader.remaining(), &big[1..]);
+
+            reader.compression(-1);
+
+            let mut packet = reader.read().await.unwrap();
+            assert_eq!(packet.id, 0x08);
+            assert_eq!(packet.reader.read::<[u8; 2]>(), [0x01, 0x02]);
+
+            // what we write above the threshold can be read back
+            writer.compression(0);
+            server.compression(0);
+            writer.write(serverbound::KeepAlive { id: 1234 }).await.unwrap();
+
+            let mut packet = server.read().await.unwrap();
+            assert_eq!(packet.id, serverbound::KeepAlive::ID);
+            assert_eq!(packet.reader.read::<u64>(), 1234);
+        });
+    }
+
+    /// lengths a server could send to make us allocate too much are errors before anything is allocated
+    #[test]
+    fn test_bad_lengths() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+        rt.block_on(async move {
+            let (mut reader, ..) = connect(var_int(-1)).await;
+            assert!(reader.read().await.is_err());
+
+            let (mut reader, ..) = connect(var_int((1 << 23) + 1)).await;
+            assert!(reader.read().await.is_err());
+
+            // the inflated length of a compressed packet
+            let (mut reader, ..) = connect(frame(&[&var_int(-5), &[0x01, 0x02]])).await;
+            reader.compression(0);
+            assert!(reader.read().await.is_err());
+
+            let (mut reader, ..) = connect(frame(&[&var_int(i32::MAX), &[0x01, 0x02]])).await;
+            reader.compression(0);
+            assert!(reader.read().await.is_err());
+        });
+    }
+
+    /// A session of small packets and chunk packets, some compressed. Once the buffers have grown, reading does not
+    /// allocate for each packet and the packets are the same bytes that were sent.
+    #[test]
+    fn test_read_allocations() {
+        const PACKETS: usize = 2000;
+        const WARM_UP: usize = 20;
+
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let zlib = ZLib::new(256).unwrap();
+
+        let packets: Vec<Vec<u8>> = (0..PACKETS).map(|i| {
+            let len = if i % 10 == 0 { 20_000 } else { 10 + i % 50 };
+            std::iter::once(0x20).chain((0..len).map(|j| ((i + j / 64) % 251) as u8)).collect()
+        }).collect();
+
+        let stream: Vec<u8> = packets.iter().flat_map(|packet| {
+            if packet.len() < 256 {
+                frame(&[&[0x00], packet])
+            } else {
+                frame(&[&var_int(packet.len() as i32), &zlib.compress(packet).unwrap()])
+            }
+        }).collect();
+
+        rt.block_on(async move {
+            let (mut reader, ..) = connect(stream).await;
+            reader.compression(256);
+
+            let mut before = 0;
+            for (i, expected) in packets.iter().enumerate() {
+                if i == WARM_UP {
+                    before = allocations();
+                }
+
+                let packet = reader.read().await.unwrap();
+                assert_eq!(packet.id, 0x20);
+                assert_eq!(packet.reader.remaining(), &expected[1..], "packet {}", i);
+            }
+
+            let per_packet = (allocations() - before) as f64 / (PACKETS - WARM_UP) as f64;
+            assert_lt!(per_packet, 0.1);
+        });
+    }
 }
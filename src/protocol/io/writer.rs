@@ -14,17 +14,21 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-
+use std::future::Future;
+use std::sync::Arc;
 
 use tokio::io::AsyncWriteExt;
 use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::Notify;
 use tokio::sync::mpsc::UnboundedSender;
 
 use swarm_bot_packets::types::{Packet, RawVec, VarInt};
 use swarm_bot_packets::write::{ByteWritable, ByteWritableLike, ByteWriter};
 
+use crate::bootstrap::tunnel::TunnelWrite;
+use crate::client::metrics::METRICS;
 use crate::error::Res;
-use crate::protocol::io::{Aes, ZLib};
+use crate::protocol::io::{Aes, var_int_len, ZLib};
 
 pub struct PacketWriter {
     writer: EncryptedWriter,
@@ -32,7 +36,7 @@ pub struct PacketWriter {
 }
 
 struct EncryptedWriter {
-    writer: OwnedWriteHalf,
+    writer: TunnelWrite,
     cipher: Option<Aes>,
 }
 
@@ -44,10 +48,22 @@ impl EncryptedWriter {
         self.writer.write_all(data).await?;
         Ok(())
     }
+
+    /// close our side of the connection so the server sees a disconnect instead of a timeout
+    pub async fn shutdown(&mut self) -> Res<()> {
+        self.writer.shutdown().await?;
+        Ok(())
+    }
 }
 
 impl From<OwnedWriteHalf> for PacketWriter {
     fn from(writer: OwnedWriteHalf) -> PacketWriter {
+        PacketWriter::from(Box::new(writer) as TunnelWrite)
+    }
+}
+
+impl From<TunnelWrite> for PacketWriter {
+    fn from(writer: TunnelWrite) -> PacketWriter {
         let writer = EncryptedWriter {
             writer,
             cipher: None,
@@ -73,16 +89,42 @@ fn data<T: Packet + ByteWritable>(packet: T, compression: &Option<ZLib>) -> Vec<
     writer.freeze()
 }
 
+#[derive(Clone)]
 pub struct PacketWriteChannel {
     tx: UnboundedSender<Vec<u8>>,
     compression: Option<ZLib>,
+
+    /// notified once the connection is closed by [`PacketWriteChannel::close`]
+    closed: Arc<Notify>,
 }
 
 impl PacketWriteChannel {
+    /// a channel which is not connected to a server. Every written packet can be read from the receiver.
+    #[cfg(test)]
+    pub fn test() -> (PacketWriteChannel, tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        (PacketWriteChannel { tx, compression: None, closed: Arc::default() }, rx)
+    }
+
     pub fn write<T: Packet + ByteWritable>(&mut self, packet: T) {
+        METRICS.packets_out.inc();
         let data = data(packet, &self.compression);
 
-        self.tx.send(data).unwrap();
+        // nothing is sent once the connection is closed
+        let _ = self.tx.send(data);
+    }
+
+    /// Write the packets which are queued and close the connection. Resolves once it is closed.
+    pub fn close(&self) -> impl Future<Output=()> + 'static {
+        let closed = self.closed.clone();
+
+        // an empty message as every packet starts with its length
+        let sent = self.tx.send(Vec::new()).is_ok();
+        async move {
+            if sent {
+                closed.notified().await;
+            }
+        }
     }
 }
 
@@ -91,12 +133,14 @@ impl PacketWriter {
         self.writer.cipher = Some(Aes::new(key));
     }
 
-    pub fn compression(&mut self, threshold: u32) {
-        self.compression = Some(ZLib::new(threshold))
+    /// Takes effect for the next packet. A negative threshold turns compression off.
+    pub fn compression(&mut self, threshold: i32) {
+        self.compression = ZLib::new(threshold)
     }
 
 
     pub async fn write<T: Packet + ByteWritable>(&mut self, packet: T) -> Res {
+        METRICS.packets_out.inc();
         let mut data = data(packet, &self.compression);
         self.writer.write_all(&mut data).await
     }
@@ -105,16 +149,29 @@ impl PacketWriter {
         let compression = self.compression;
         let mut writer = self.writer;
         let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
-
-        tokio::task::spawn_local(async move {
-            while let Some(mut elem) = rx.recv().await {
-                writer.write_all(&mut elem).await.unwrap();
-            }
-        });
+        let closed = Arc::new(Notify::new());
+
+        // not on the thread of the game loop so answers to keep alives go out right away
+        {
+            let closed = closed.clone();
+            tokio::task::spawn(async move {
+                while let Some(mut elem) = rx.recv().await {
+                    if elem.is_empty() {
+                        if let Err(err) = writer.shutdown().await {
+                            warn!(error = %err, "could not close the connection");
+                        }
+                        closed.notify_one();
+                        return;
+                    }
+                    writer.write_all(&mut elem).await.unwrap();
+                }
+            });
+        }
 
         PacketWriteChannel {
             tx,
             compression,
+            closed,
         }
     }
 }
@@ -172,7 +229,9 @@ impl ByteWritableLike for CompletePacket {
                         .write(data);
                 } else {
                     let data: RawVec = zlib.compress(&data.inner()).unwrap().into();
-                    let compressed_len: VarInt = (data.len() as i32 + uncompressed_len).into();
+
+                    // the packet length counts the data length VarInt, not the uncompressed size
+                    let compressed_len = VarInt((data.len() + var_int_len(uncompressed_len)) as i32);
                     writer.write(compressed_len)
                         .write(VarInt(uncompressed_len))
                         .write(data);
@@ -0,0 +1,128 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use swarm_bot_packets::types::{Packet, PacketState, UUID, VarInt};
+
+use crate::bootstrap::{Address, Connection};
+use crate::bootstrap::mojang::calc_hash;
+use crate::bootstrap::storage::ValidUser;
+use crate::error::{Error, Phase, SwarmContext, SwarmError};
+use crate::error::Error::WrongPacket;
+use crate::protocol::encrypt::{rand_bits, Rsa};
+use crate::protocol::io::reader::PacketReader;
+use crate::protocol::io::writer::PacketWriter;
+use crate::protocol::v340::clientbound::{Disconnect, EncryptionRequest, LoginSuccess, SetCompression};
+use crate::protocol::v340::serverbound::{EncryptionResponse, Handshake, HandshakeNextState, LoginStart};
+
+/// A connection which has finished the login state and is now in the play state
+pub struct LoggedIn {
+    pub reader: PacketReader,
+    pub writer: PacketWriter,
+    pub username: String,
+    pub uuid: UUID,
+}
+
+/// Handshake, encryption and compression. The login packets have not changed between 1.12 and 1.16 apart from the
+/// format of the uuid in [`LoginSuccess`], so we skip its body and use the uuid we already know.
+/// Errors name the account and the phase they happened in.
+pub async fn login(conn: Connection, protocol_version: i32) -> Result<LoggedIn, SwarmError> {
+    let Connection { user, address, mojang, read, write, .. } = conn;
+    let ValidUser { username, uuid, access_id, .. } = user;
+
+    let Address { host, port } = address;
+    let uuid = UUID::from(&uuid);
+
+    let mut reader = PacketReader::from(read);
+    let mut writer = PacketWriter::from(write);
+
+
+    // START: handshake
+    writer.write(Handshake {
+        protocol_version: VarInt(protocol_version),
+        host,
+        port,
+        next_state: HandshakeNextState::Login,
+    }).await.ctx_phase(&username, Phase::Join)?;
+
+
+    // START: login
+    writer.write(LoginStart {
+        username: username.clone()
+    }).await.ctx_phase(&username, Phase::Join)?;
+
+    let mut data = reader.read().await.ctx_phase(&username, Phase::Join)?;
+
+    // offline mode servers skip encryption
+    if data.id == EncryptionRequest::ID {
+        let EncryptionRequest { public_key_der, verify_token, server_id } = data.read();
+
+        let rsa = Rsa::from_der(&public_key_der);
+
+        let shared_secret = rand_bits();
+
+        let encrypted_ss = rsa.encrypt(&shared_secret).unwrap();
+        let encrypted_verify = rsa.encrypt(&verify_token).unwrap();
+
+        // Mojang online mode requests
+        let hash = calc_hash(&server_id, &shared_secret, &public_key_der);
+        mojang.join(uuid, &hash, &access_id).await.ctx_account(&username)?;
+
+        // id = 1
+        writer.write(EncryptionResponse {
+            shared_secret: encrypted_ss,
+            verify_token: encrypted_verify,
+        }).await.ctx_phase(&username, Phase::Encryption)?;
+
+        // we now do everything encrypted
+        writer.encryption(&shared_secret);
+        reader.encryption(&shared_secret);
+
+        data = reader.read().await.ctx_phase(&username, Phase::Encryption)?;
+    }
+
+    // set compression or login success. The threshold may change again, it applies from the packet after.
+    while data.id == SetCompression::ID {
+        let SetCompression { threshold: VarInt(threshold) } = data.read();
+
+        reader.compression(threshold);
+        writer.compression(threshold);
+
+        data = reader.read().await.ctx_phase(&username, Phase::Join)?;
+    }
+
+    match data.id {
+        LoginSuccess::ID => {}
+        Disconnect::ID => {
+            let Disconnect { reason } = data.read();
+            return Err(SwarmError::new(&username, Phase::Join, Error::Disconnect(reason)));
+        }
+        actual => {
+            let wrong = WrongPacket {
+                state: PacketState::Login,
+                expected: LoginSuccess::ID,
+                actual,
+            };
+            return Err(SwarmError::new(&username, Phase::Join, wrong));
+        }
+    }
+
+    Ok(LoggedIn {
+        reader,
+        writer,
+        username,
+        uuid,
+    })
+}
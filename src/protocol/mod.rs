@@ -14,23 +14,42 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::future::Future;
+use std::pin::Pin;
+
+use serde::{Deserialize, Serialize};
+
 use swarm_bot_packets::types::UUID;
 
 use crate::bootstrap::Connection;
 use crate::client::pathfind::moves::CardinalDirection;
 use crate::client::processor::InterfaceIn;
+use crate::client::state::local::abilities::Abilities;
 use crate::client::state::local::inventory::ItemStack;
-use crate::error::Res;
+use crate::error::SwarmError;
+use crate::protocol::budget::BudgetConfig;
+use crate::protocol::chat_queue::ChatConfig;
 use crate::storage::block::BlockLocation;
 use crate::types::{Direction, Location};
 
 pub mod v340;
+pub mod v754;
 
+pub mod budget;
+pub mod chat_queue;
+pub mod disconnect;
 mod io;
+mod login;
+mod movement;
+mod ping;
+pub mod tick;
+
+pub use ping::{ping, ping_through, ServerStatus};
+pub use v340::serverbound::Hand;
 mod transform;
 mod encrypt;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub enum Mine {
     Start,
@@ -39,7 +58,7 @@ pub enum Mine {
 }
 
 #[repr(u8)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Face {
     NegY,
     PosY,
@@ -118,20 +137,53 @@ pub enum InvAction {
     Q(u16),
     // drop full stack
     CtrlQ(u16),
-    Click(u16, MouseButton, ItemStack),
-    ShiftClick(u16, MouseButton, ItemStack),
+    /// the slot and the item in it before clicking
+    Click(u16, MouseButton, Option<ItemStack>),
+    ShiftClick(u16, MouseButton, Option<ItemStack>),
+
+    /// swap the slot with a hotbar slot (0-8) like pressing a number key over it
+    SwapHotbar(u16, u8),
+
+    /// swap the held item with the off-hand (the F key)
+    SwapOffhand,
+
+    /// shift click a slot of an open window (i.e., moving an item into a chest) and the item in it before clicking
+    WindowShiftClick(u8, u16, Option<ItemStack>),
+
+    /// put the item in the slot out of thin air (creative mode)
+    Creative(u16, Option<ItemStack>),
 }
 
+/// resolves once a connection is closed, see [`InterfaceOut::close`]
+pub type Closing = Pin<Box<dyn Future<Output=()>>>;
+
 pub trait InterfaceOut {
     fn place_block(&mut self, against: BlockLocation, face: Face);
     fn attack_entity(&mut self, id: u32);
     fn send_chat(&mut self, message: &str);
+
+    /// how fast chat is sent from now on. Messages already queued are kept.
+    fn set_chat_config(&mut self, config: ChatConfig);
+
+    /// how many actions of each kind may go out. Actions which already wait are kept.
+    fn set_budget(&mut self, config: BudgetConfig);
+
+    /// Send the actions which waited for the budget and fit into it now. The tick loop calls this once every tick.
+    fn flush_budget(&mut self);
+
+    /// how many actions wait for the budget. Tasks do not go on until the actions they made are sent.
+    fn deferred(&self) -> usize;
+
+    /// how many chunks around us the server should send (Client Settings). Only sent if it changed.
+    fn set_view_distance(&mut self, chunks: u8);
+
     fn inventory_action(&mut self, action: InvAction);
     fn swing_arm(&mut self);
+    /// stop using the held item. Finishes eating, shoots a bow or lowers a shield.
     fn finish_eating(&mut self);
 
     /// default right click https://wiki.vg/index.php?title=Protocol&oldid=14204#Chunk_Data
-    fn use_item(&mut self);
+    fn use_item(&mut self, hand: Hand);
 
     fn change_slot(&mut self, number: u8);
     fn mine(&mut self, location: BlockLocation, mine: Mine, face: Face);
@@ -139,13 +191,41 @@ pub trait InterfaceOut {
     fn teleport(&mut self, location: Location);
     fn look(&mut self, direction: Direction);
     fn teleport_and_look(&mut self, location: Location, direction: Direction, on_ground: bool);
+
+    /// start or stop sneaking (Entity Action)
+    fn sneak(&mut self, sneaking: bool);
+
+    /// start or stop sprinting (Entity Action)
+    fn sprint(&mut self, sprinting: bool);
+
+    /// start flying with an elytra (Entity Action). The server stops it by itself once we land.
+    fn start_gliding(&mut self);
+
+    /// get out of the bed we sleep in (Entity Action). 1.12 sets the spawn point at the bed then.
+    fn leave_bed(&mut self);
+
+    /// tell the server whether we are flying like in creative mode (Player Abilities)
+    fn abilities(&mut self, abilities: Abilities);
+
+    /// close a window which the server opened (i.e., a chest)
+    fn close_window(&mut self, window: u8);
+
+    /// save `pages` in the writable book which is held
+    fn edit_book(&mut self, pages: &[String]);
+
+    /// save `pages` in the writable book which is held and sign it, which turns it into a written book
+    fn sign_book(&mut self, pages: &[String], title: &str, author: &str);
+
+    /// Send the packets which are queued and close the connection. Chat and actions still waiting for their turn are
+    /// not sent.
+    fn close(&mut self) -> Closing;
 }
 
 #[async_trait::async_trait]
 pub trait Minecraft: Sized {
     type Queue: EventQueue;
     type Interface: InterfaceOut;
-    async fn login(conn: Connection) -> Res<Login<Self::Queue, Self::Interface>>;
+    async fn login(conn: Connection) -> Result<Login<Self::Queue, Self::Interface>, SwarmError>;
 }
 
 pub trait EventQueue {
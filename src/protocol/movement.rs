@@ -0,0 +1,243 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::types::{Direction, DirectionOrigin, Location};
+
+/// vanilla sends the position if it changed by more than sqrt(9e-4) = 0.03 blocks
+const MOVE_EPSILON2: f64 = 9.0E-4;
+
+/// vanilla sends the position at least this often (in ticks), even when standing still
+const POSITION_INTERVAL: u32 = 20;
+
+/// The movement packet to send in a tick
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MovementPacket {
+    PositionAndRotation,
+    Position,
+    Rotation,
+
+    /// the flying packet which only has the on ground flag
+    OnGround,
+    Nothing,
+}
+
+/// The serverbound movement packets of a protocol version
+pub trait MovementOut {
+    fn position_and_rotation(&self, location: Location, direction: Direction, on_ground: bool);
+    fn position(&self, location: Location, on_ground: bool);
+    fn rotation(&self, direction: Direction, on_ground: bool);
+    fn on_ground(&self, on_ground: bool);
+}
+
+/// Decides which movement packet to send every tick the same way the vanilla client does
+/// (`EntityPlayerSP::onUpdateWalkingPlayer`). While moving the position is sent every tick. When standing still it
+/// is only sent every [`POSITION_INTERVAL`] ticks, with rotation-only packets when the bot looks around and the
+/// on-ground-only packet when it lands or falls off something.
+///
+/// Packets which are sent outside of the tick (teleport confirms, direct `teleport`/`look` calls) must be reported
+/// with [`MovementTracker::sent`] so the next tick does not repeat them.
+#[derive(Debug, Default)]
+pub struct MovementTracker {
+    location: Location,
+    direction: Direction,
+    on_ground: bool,
+
+    /// ticks since the position was last sent
+    ticks: u32,
+
+    /// false until anything was sent. The first tick always sends everything.
+    synced: bool,
+}
+
+impl MovementTracker {
+    /// the last direction the server was told about
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn tick(&mut self, location: Location, direction: Direction, on_ground: bool) -> MovementPacket {
+        self.ticks += 1;
+
+        let moved = !self.synced || self.location.dist2(location) > MOVE_EPSILON2 || self.ticks >= POSITION_INTERVAL;
+
+        #[allow(clippy::float_cmp)]
+        let rotated = !self.synced || self.direction.yaw != direction.yaw || self.direction.pitch != direction.pitch;
+
+        let packet = match (moved, rotated) {
+            (true, true) => MovementPacket::PositionAndRotation,
+            (true, false) => MovementPacket::Position,
+            (false, true) => MovementPacket::Rotation,
+            (false, false) if on_ground != self.on_ground => MovementPacket::OnGround,
+            (false, false) => MovementPacket::Nothing,
+        };
+
+        if moved {
+            self.location = location;
+            self.ticks = 0;
+        }
+
+        if rotated {
+            self.direction = direction;
+        }
+
+        self.on_ground = on_ground;
+        self.synced = true;
+
+        packet
+    }
+
+    /// send the movement packet of this tick (see [`MovementTracker::tick`])
+    pub fn send_tick(&mut self, location: Location, direction: Direction, on_ground: bool, out: &impl MovementOut) {
+        match self.tick(location, direction, on_ground) {
+            MovementPacket::PositionAndRotation => out.position_and_rotation(location, direction, on_ground),
+            MovementPacket::Position => out.position(location, on_ground),
+            MovementPacket::Rotation => out.rotation(direction, on_ground),
+            MovementPacket::OnGround => out.on_ground(on_ground),
+            MovementPacket::Nothing => {}
+        }
+    }
+
+    /// The vanilla client answers a server teleport with its full position and rotation right away instead of
+    /// waiting for the next tick. Returns where the server says we look.
+    pub fn resync(&mut self, location: Location, rotation: DirectionOrigin, out: &impl MovementOut) -> Direction {
+        let mut direction = self.direction;
+        rotation.yaw.apply(&mut direction.yaw);
+        rotation.pitch.apply(&mut direction.pitch);
+
+        self.sent(Some(location), Some(direction));
+        out.position_and_rotation(location, direction, false);
+        direction
+    }
+
+    /// a movement packet was sent outside of [`MovementTracker::tick`]
+    pub fn sent(&mut self, location: Option<Location>, direction: Option<Direction>) {
+        if let Some(location) = location {
+            self.location = location;
+            self.ticks = 0;
+        }
+
+        if let Some(direction) = direction {
+            self.direction = direction;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::protocol::movement::{MovementOut, MovementPacket, MovementTracker};
+    use crate::types::{Direction, DirectionOrigin, Location, Origin};
+
+    /// the packets a version would have written
+    #[derive(Default)]
+    struct Written(RefCell<Vec<MovementPacket>>);
+
+    impl MovementOut for Written {
+        fn position_and_rotation(&self, _location: Location, _direction: Direction, _on_ground: bool) {
+            self.0.borrow_mut().push(MovementPacket::PositionAndRotation);
+        }
+
+        fn position(&self, _location: Location, _on_ground: bool) {
+            self.0.borrow_mut().push(MovementPacket::Position);
+        }
+
+        fn rotation(&self, _direction: Direction, _on_ground: bool) {
+            self.0.borrow_mut().push(MovementPacket::Rotation);
+        }
+
+        fn on_ground(&self, _on_ground: bool) {
+            self.0.borrow_mut().push(MovementPacket::OnGround);
+        }
+    }
+
+    #[test]
+    fn test_idle() {
+        let mut tracker = MovementTracker::default();
+        let location = Location::new(0.5, 64.0, 0.5);
+
+        assert_eq!(tracker.tick(location, Direction::default(), true), MovementPacket::PositionAndRotation);
+
+        // standing still: the position every 20 ticks and nothing in between
+        for _ in 0..3 {
+            for _ in 0..19 {
+                assert_eq!(tracker.tick(location, Direction::default(), true), MovementPacket::Nothing);
+            }
+            assert_eq!(tracker.tick(location, Direction::default(), true), MovementPacket::Position);
+        }
+    }
+
+    #[test]
+    fn test_small_moves() {
+        let mut tracker = MovementTracker::default();
+        let mut location = Location::new(0.0, 64.0, 0.0);
+        tracker.tick(location, Direction::default(), true);
+
+        // 0.02 blocks is below the epsilon
+        location.x += 0.02;
+        assert_eq!(tracker.tick(location, Direction::default(), true), MovementPacket::Nothing);
+
+        // but it adds up because the comparison is with the last sent position
+        location.x += 0.02;
+        assert_eq!(tracker.tick(location, Direction::default(), true), MovementPacket::Position);
+    }
+
+    #[test]
+    fn test_rotation_and_ground() {
+        let mut tracker = MovementTracker::default();
+        let location = Location::new(0.0, 64.0, 0.0);
+        tracker.tick(location, Direction::default(), true);
+
+        let looking = Direction { yaw: 90.0, pitch: 0.0 };
+        assert_eq!(tracker.tick(location, looking, true), MovementPacket::Rotation);
+        assert_eq!(tracker.tick(location, looking, true), MovementPacket::Nothing);
+
+        assert_eq!(tracker.tick(location, looking, false), MovementPacket::OnGround);
+        assert_eq!(tracker.tick(location, looking, false), MovementPacket::Nothing);
+
+        let moved = Location::new(1.0, 64.0, 0.0);
+        assert_eq!(tracker.tick(moved, Direction::default(), true), MovementPacket::PositionAndRotation);
+    }
+
+    #[test]
+    fn test_sent() {
+        let mut tracker = MovementTracker::default();
+        tracker.tick(Location::default(), Direction::default(), true);
+
+        // a teleport resync was sent so there is nothing new to tell the server
+        let location = Location::new(10.0, 70.0, 10.0);
+        tracker.sent(Some(location), Some(Direction::DOWN));
+        assert_eq!(tracker.tick(location, Direction::DOWN, true), MovementPacket::Nothing);
+    }
+
+    #[test]
+    fn test_resync() {
+        let mut tracker = MovementTracker::default();
+        let out = Written::default();
+        let looking = Direction { yaw: 90.0, pitch: 10.0 };
+        tracker.send_tick(Location::default(), Direction::default(), true, &out);
+        tracker.send_tick(Location::default(), looking, true, &out);
+
+        // the server teleports us and turns the head relative to where we look
+        let location = Location::new(10.0, 70.0, 10.0);
+        let direction = tracker.resync(location, DirectionOrigin { yaw: Origin::Rel(5.0), pitch: Origin::Abs(0.0) }, &out);
+        assert_eq!((direction.yaw, direction.pitch), (95.0, 0.0));
+
+        // the next tick does not repeat it
+        tracker.send_tick(location, direction, true, &out);
+        assert_eq!(out.0.into_inner(), vec![MovementPacket::PositionAndRotation, MovementPacket::Rotation, MovementPacket::PositionAndRotation]);
+    }
+}
@@ -0,0 +1,310 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tokio::net::TcpStream;
+
+use swarm_bot_packets::{Packet, Readable, Writable};
+use swarm_bot_packets::types::VarInt;
+use swarm_bot_packets::write::{ByteWritable, ByteWriter};
+
+use crate::bootstrap::Proxy;
+use crate::bootstrap::tunnel::{self, Tunnel};
+use crate::chat::Component;
+use crate::chat::format::strip_codes;
+use crate::error::{err, Res};
+use crate::protocol::io::reader::PacketReader;
+use crate::protocol::io::writer::PacketWriter;
+use crate::protocol::v340::serverbound::{Handshake, HandshakeNextState};
+
+/// how long connecting and both round trips may take
+pub const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Packet)]
+#[packet(0x00, Status)]
+struct Request;
+
+impl ByteWritable for Request {
+    fn write_to_bytes(self, _writer: &mut ByteWriter) {}
+}
+
+#[derive(Packet, Writable)]
+#[packet(0x01, Status)]
+struct Ping {
+    payload: u64,
+}
+
+#[derive(Packet, Readable)]
+#[packet(0x00, Status)]
+struct Response {
+    json: String,
+}
+
+#[derive(Packet, Readable)]
+#[packet(0x01, Status)]
+struct Pong {
+    payload: u64,
+}
+
+/// What a server shows in the multiplayer server list
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerStatus {
+    /// e.g., "1.12.2" or "Paper 1.16.5"
+    pub version: String,
+    pub protocol: i32,
+    pub online: i32,
+    pub max: i32,
+
+    /// some of the players online. Many servers hide this.
+    pub sample: Vec<String>,
+
+    /// the message of the day without formatting
+    pub motd: String,
+
+    /// round trip time of the ping packet
+    pub latency: Duration,
+}
+
+#[derive(Deserialize)]
+struct RawVersion {
+    name: String,
+    protocol: i32,
+}
+
+#[derive(Deserialize)]
+struct RawSample {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RawPlayers {
+    max: i32,
+    online: i32,
+    #[serde(default)]
+    sample: Vec<RawSample>,
+}
+
+#[derive(Deserialize)]
+struct RawStatus {
+    version: RawVersion,
+    players: Option<RawPlayers>,
+
+    /// a chat component, a plain string on older servers
+    #[serde(default)]
+    description: Component,
+}
+
+impl ServerStatus {
+    /// parse the json of the status response. The latency is left at zero.
+    pub fn parse(json: &str) -> Res<ServerStatus> {
+        let RawStatus { version, players, description } = serde_json::from_str(json)?;
+        let players = players.unwrap_or(RawPlayers { max: 0, online: 0, sample: Vec::new() });
+
+        Ok(ServerStatus {
+            version: strip_codes(&version.name),
+            protocol: version.protocol,
+            online: players.online,
+            max: players.max,
+            sample: players.sample.into_iter().map(|player| strip_codes(&player.name)).collect(),
+            motd: description.to_plain_text().trim().to_string(),
+            latency: Duration::ZERO,
+        })
+    }
+}
+
+impl Display for ServerStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} (protocol {})", self.version, self.protocol)?;
+        writeln!(f, "{}/{} players online", self.online, self.max)?;
+        if !self.sample.is_empty() {
+            writeln!(f, "including {}", self.sample.join(", "))?;
+        }
+        writeln!(f, "motd: {}", self.motd)?;
+        write!(f, "latency: {}ms", self.latency.as_millis())
+    }
+}
+
+impl ServerStatus {
+    /// Why the server is not ready for a login wave: it does not run `protocol` or has `players_below` or more
+    /// players online. None if it is ready.
+    pub fn unmet(&self, protocol: Option<i32>, players_below: Option<i32>) -> Option<String> {
+        if let Some(protocol) = protocol {
+            if self.protocol != protocol {
+                return Some(format!("server runs protocol {}, not {}", self.protocol, protocol));
+            }
+        }
+
+        if let Some(below) = players_below {
+            if self.online >= below {
+                return Some(format!("{} players are online, need fewer than {}", self.online, below));
+            }
+        }
+
+        None
+    }
+
+    /// The version to log in with: the protocol the server reports if there is a [`Minecraft`] for it
+    ///
+    /// [`Minecraft`]: crate::protocol::Minecraft
+    pub fn supported_version(&self) -> Res<usize> {
+        match self.protocol {
+            340 => Ok(340),
+            754 => Ok(754),
+            protocol => Err(err(&format!("{} (protocol {}) is not supported, only 1.12.2 (340) and 1.16.5 (754) are", self.version, protocol)))
+        }
+    }
+}
+
+async fn status(tunnel: Tunnel, host: &str, port: u16) -> Res<ServerStatus> {
+    let (read, write) = tunnel.into_split();
+    let mut reader = PacketReader::from(read);
+    let mut writer = PacketWriter::from(write);
+
+    writer.write(Handshake {
+        // -1 by convention when the client does not know the version yet
+        protocol_version: VarInt(-1),
+        host: host.to_string(),
+        port,
+        next_state: HandshakeNextState::Status,
+    }).await?;
+
+    writer.write(Request).await?;
+    let Response { json } = reader.read_exact_packet().await?;
+    let mut status = ServerStatus::parse(&json)?;
+
+    let payload = rand::random();
+    let start = Instant::now();
+    writer.write(Ping { payload }).await?;
+    let pong: Pong = reader.read_exact_packet().await?;
+    status.latency = start.elapsed();
+
+    if pong.payload != payload {
+        return Err(err("server answered the ping with the wrong payload"));
+    }
+
+    Ok(status)
+}
+
+async fn timeout<T>(future: impl std::future::Future<Output=Res<T>>) -> Res<T> {
+    tokio::time::timeout(PING_TIMEOUT, future).await.map_err(|_| err("timed out pinging the server"))?
+}
+
+/// Ask the server for its status like the multiplayer server list does
+pub async fn ping(host: &str, port: u16) -> Res<ServerStatus> {
+    timeout(async {
+        let stream = TcpStream::connect((host, port)).await?;
+        status(Tunnel::Tcp(stream), host, port).await
+    }).await
+}
+
+/// [`ping`] through a proxy
+pub async fn ping_through(host: &str, port: u16, proxy: &Proxy) -> Res<ServerStatus> {
+    timeout(async {
+        let target = format!("{}:{}", host, port);
+        let tunnel = tunnel::connect(proxy, &target).await?;
+        status(tunnel, host, port).await
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use crate::protocol::ping::{ping, ServerStatus};
+
+    #[test]
+    fn test_parse() {
+        let json = r#"{
+            "version": {"name": "Paper 1.12.2", "protocol": 340},
+            "players": {"max": 100, "online": 2, "sample": [{"name": "§aNotch", "id": "069a79f4-44e9-4726-a5be-fca90e38aaf5"}]},
+            "description": {"text": "", "extra": [{"text": "A ", "color": "gold"}, {"text": "§lSwarm", "bold": true}, " server"]},
+            "favicon": "data:image/png;base64,AAAA"
+        }"#;
+
+        let status = ServerStatus::parse(json).unwrap();
+        assert_eq!(status.version, "Paper 1.12.2");
+        assert_eq!(status.protocol, 340);
+        assert_eq!((status.online, status.max), (2, 100));
+        assert_eq!(status.sample, vec!["Notch".to_string()]);
+        assert_eq!(status.motd, "A Swarm server");
+        assert_eq!(status.supported_version().unwrap(), 340);
+
+        // old servers use a plain string and may leave out the players
+        let json = r#"{"version": {"name": "1.8.9", "protocol": 47}, "description": "§cHello"}"#;
+        let status = ServerStatus::parse(json).unwrap();
+        assert_eq!(status.motd, "Hello");
+        assert_eq!(status.max, 0);
+        assert_eq!(status.supported_version().unwrap_err().to_string(), "1.8.9 (protocol 47) is not supported, only 1.12.2 (340) and 1.16.5 (754) are");
+
+        assert!(ServerStatus::parse("{}").is_err());
+    }
+
+    #[test]
+    fn test_unmet() {
+        let json = r#"{"version": {"name": "1.12.2", "protocol": 340}, "players": {"max": 100, "online": 10}}"#;
+        let status = ServerStatus::parse(json).unwrap();
+
+        assert_eq!(status.unmet(None, None), None);
+        assert_eq!(status.unmet(Some(340), Some(11)), None);
+        assert!(status.unmet(Some(754), None).is_some());
+        assert!(status.unmet(Some(340), Some(10)).is_some());
+    }
+
+    /// a fake server which answers one status request
+    #[test]
+    fn test_ping() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let local = tokio::task::LocalSet::new();
+
+        local.block_on(&rt, async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+
+            tokio::task::spawn_local(async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+
+                let json = r#"{"version":{"name":"1.12.2","protocol":340},"players":{"max":20,"online":0},"description":{"text":"hi"}}"#;
+                let mut response = vec![0x00, json.len() as u8];
+                response.extend_from_slice(json.as_bytes());
+
+                // handshake + request, then answer. Every packet here is shorter than 128 bytes so the length is
+                // a single byte.
+                for _ in 0..2 {
+                    let len = socket.read_u8().await.unwrap();
+                    let mut packet = vec![0_u8; len as usize];
+                    socket.read_exact(&mut packet).await.unwrap();
+                }
+                assert!(response.len() < 128);
+
+                socket.write_all(&[response.len() as u8]).await.unwrap();
+                socket.write_all(&response).await.unwrap();
+
+                // echo the ping back as the pong (same length, both id 1)
+                let mut ping = [0_u8; 10];
+                socket.read_exact(&mut ping).await.unwrap();
+                socket.write_all(&ping).await.unwrap();
+            });
+
+            let status = ping("127.0.0.1", port).await.unwrap();
+            assert_eq!(status.protocol, 340);
+            assert_eq!(status.motd, "hi");
+        });
+    }
+}
@@ -0,0 +1,107 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// how much a new interval moves the estimate
+const SMOOTHING: f64 = 0.3;
+
+/// the rate of a server which keeps up
+pub const MAX_TPS: f64 = 20.0;
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// (world age, arrival) of the previous Time Update
+    last: Option<(i64, Instant)>,
+    tps: Option<f64>,
+}
+
+/// Estimates how many ticks a second the server runs from the world age in Time Update packets (sent every 20 ticks)
+/// and when they arrive. Arrivals should be recorded as soon as the packet is read so a busy game loop does not look
+/// like a lagging server.
+///
+/// Clones share the estimate.
+#[derive(Clone, Debug, Default)]
+pub struct TickTimer {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl TickTimer {
+    pub fn record(&self, world_age: i64, at: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some((last_age, last_at)) = inner.last {
+            let secs = at.saturating_duration_since(last_at).as_secs_f64();
+            let ticks = world_age - last_age;
+
+            // packets which arrive bunched up after a lag spike would look faster than the server can run
+            if secs > 0.0 && ticks > 0 {
+                let sample = (ticks as f64 / secs).min(MAX_TPS);
+                inner.tps = Some(match inner.tps {
+                    None => sample,
+                    Some(tps) => tps + (sample - tps) * SMOOTHING,
+                });
+            }
+        }
+
+        inner.last = Some((world_age, at));
+    }
+
+    /// None until two Time Updates arrived
+    pub fn tps(&self) -> Option<f64> {
+        self.inner.lock().unwrap().tps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use more_asserts::*;
+
+    use crate::protocol::tick::TickTimer;
+
+    #[test]
+    fn test_tps() {
+        let timer = TickTimer::default();
+        let start = Instant::now();
+        let second = |secs: f64| start + Duration::from_secs_f64(secs);
+
+        timer.record(0, start);
+        assert_eq!(timer.tps(), None);
+
+        // 20 ticks every second
+        for i in 1..=5 {
+            timer.record(i * 20, second(i as f64));
+        }
+        assert_le!((timer.tps().unwrap() - 20.0).abs(), 0.001);
+
+        // the server slows down to 10 tps. Two packets in the same instant are ignored.
+        timer.record(120, second(5.0));
+        for i in 1..=20 {
+            timer.record(120 + i * 20, second(5.0 + 2.0 * i as f64));
+        }
+        assert_le!((timer.tps().unwrap() - 10.0).abs(), 0.01);
+
+        // a burst after a lag spike does not go over 20
+        timer.record(600, second(45.1));
+        assert_le!(timer.tps().unwrap(), 20.0);
+
+        // clones share the estimate
+        assert_eq!(timer.clone().tps(), timer.tps());
+    }
+}
@@ -15,6 +15,7 @@
  */
 
 use std::cmp::max;
+use std::sync::Arc;
 
 use itertools::Itertools;
 
@@ -22,9 +23,13 @@ use swarm_bot_packets::{Packet, Readable, Writable};
 use swarm_bot_packets::read::{ByteReadable, ByteReadableLike, ByteReader};
 use swarm_bot_packets::types::{BitField, Identifier, RawVec, UUID, UUIDHyphenated, VarInt, VarUInt};
 
+use crate::chat::Component;
+use crate::client::state::global::world_players;
+use crate::nbt::NbtLimits;
 use crate::storage::block::{BlockLocation, BlockState};
-use crate::storage::chunk::{ChunkColumn, ChunkData, HighMemoryChunkSection, Palette};
-use crate::types::{Chat, Dimension, Direction, DirectionOrigin, Location, LocationFloat, LocationOrigin, Position};
+use crate::storage::chunk::{ChunkColumn, ChunkData, HighMemoryChunkSection, Palette, SectionLight};
+use crate::storage::tile_entity::TileEntity;
+use crate::types::{Dimension, Direction, DirectionOrigin, Displacement, GameMode, Location, LocationFloat, LocationOrigin, Position};
 
 #[derive(Packet, Readable)]
 #[packet(0x00, Login)]
@@ -33,27 +38,6 @@ pub struct Disconnect {
 }
 
 
-#[derive(Debug)]
-pub enum GameMode {
-    Survival,
-    Creative,
-    Adventure,
-    Spectator,
-}
-
-impl ByteReadable for GameMode {
-    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
-        use GameMode::*;
-        let val: u8 = byte_reader.read();
-        match val {
-            0 => Survival,
-            1 => Creative,
-            2 => Adventure,
-            3 => Spectator,
-            val => panic!("gamemode {} is not valid", val)
-        }
-    }
-}
 
 #[derive(Debug)]
 pub struct Record {
@@ -70,7 +54,7 @@ impl ByteReadable for Record {
         Self {
             x: horizontal >> 4,
             y,
-            z: horizontal << 4,
+            z: horizontal & 0x0F,
             block_state: byte_reader.read(),
         }
     }
@@ -105,6 +89,26 @@ pub struct Respawn {
     pub level_type: String,
 }
 
+/// The server stopped sending the column to us (i.e., as it is out of view)
+#[derive(Packet, Debug, Readable)]
+#[packet(0x1d, Play)]
+pub struct UnloadChunk {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+}
+
+/// Rain, credits and the like. Only the gamemode changing is of interest.
+#[derive(Packet, Debug, Readable)]
+#[packet(0x1e, Play)]
+pub struct ChangeGameState {
+    pub reason: u8,
+    pub value: f32,
+}
+
+impl ChangeGameState {
+    pub const CHANGE_GAMEMODE: u8 = 3;
+}
+
 #[derive(Packet, Readable)]
 #[packet(0x03, Login)]
 pub struct SetCompression {
@@ -134,7 +138,14 @@ pub struct AddPlayer {
     pub properties: Vec<PlayerProperty>,
     pub gamemode: VarInt,
     pub ping: VarInt,
-    pub display_name: Option<Chat>,
+    pub display_name: Option<Component>,
+}
+
+impl AddPlayer {
+    pub fn into_player(self, uuid: u128) -> world_players::Player {
+        let AddPlayer { name, gamemode: VarInt(gamemode), ping: VarInt(ping), .. } = self;
+        world_players::Player { name, uuid, ping: ping.max(0) as u32, gamemode: gamemode as u8 }
+    }
 }
 
 impl ByteReadable for AddPlayer {
@@ -151,7 +162,7 @@ pub enum PlayerListType {
     AddPlayer(AddPlayer),
     UpdateGamemode(VarInt),
     UpdateLatency(VarInt),
-    UpdateDisplayName(Option<Chat>),
+    UpdateDisplayName(Option<Component>),
     RemovePlayer,
 }
 
@@ -207,6 +218,26 @@ impl ByteReadable for PlayerListItem {
     }
 }
 
+/// Only the entity dead event is of interest to us, entering and ending combat are ignored
+#[derive(Debug, Packet)]
+#[packet(0x2D, Play)]
+pub struct CombatEvent {
+    /// raw chat json of the death message
+    pub death_message: Option<String>,
+}
+
+impl ByteReadable for CombatEvent {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let VarUInt(event) = byte_reader.read();
+        let death_message = (event == 2).then(|| {
+            let _player_id: VarInt = byte_reader.read();
+            let _entity_id: i32 = byte_reader.read();
+            byte_reader.read()
+        });
+        Self { death_message }
+    }
+}
+
 #[derive(Debug, Clone, Packet, Writable, Readable)]
 #[packet(0x01, Login)]
 pub struct EncryptionRequest {
@@ -236,7 +267,7 @@ pub mod entity {
     use swarm_bot_packets::*;
     use swarm_bot_packets::types::{Angle, UUID, VarInt};
 
-    use crate::types::{Location, ShortLoc};
+    use crate::types::{Displacement, Location, ShortLoc};
 
     #[derive(Packet, Debug, Readable)]
     #[packet(0x03, Play)]
@@ -253,6 +284,37 @@ pub mod entity {
         pub velocity_z: i16,
     }
 
+    impl LivingSpawn {
+        /// The types of the mobs which attack players on sight. Zombie pigmen and endermen only do when provoked.
+        pub const HOSTILE: &[i32] = &[
+            4, 5, 6, 23, 27, 34, 35, 36, 37, 50, 51, 52, 53, 54, 55, 56, 59, 60, 61, 62, 63, 64, 66, 67, 68, 69,
+        ];
+    }
+
+    /// Spawn Object. What `data` means depends on the type.
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x00, Play)]
+    pub struct ObjectSpawn {
+        pub entity_id: VarInt,
+        pub object_uuid: UUID,
+        pub type_id: u8,
+        pub location: Location,
+        pub pitch: Angle,
+        pub yaw: Angle,
+        pub data: i32,
+        pub velocity_x: i16,
+        pub velocity_y: i16,
+        pub velocity_z: i16,
+    }
+
+    impl ObjectSpawn {
+        /// `data` is the block id | metadata << 12
+        pub const FALLING_BLOCK: u8 = 70;
+
+        /// a dropped item. Which item is only sent in the metadata after.
+        pub const ITEM: u8 = 2;
+    }
+
     #[derive(Packet, Debug, Readable)]
     #[packet(0x05, Play)]
     pub struct PlayerSpawn {
@@ -301,6 +363,45 @@ pub mod entity {
         pub pitch: Angle,
         pub on_ground: bool,
     }
+
+    /// Entity Velocity. Sent for our own entity when we are knocked back.
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x3e, Play)]
+    pub struct Velocity {
+        pub entity_id: VarInt,
+        pub velocity_x: i16,
+        pub velocity_y: i16,
+        pub velocity_z: i16,
+    }
+
+    impl Velocity {
+        /// the velocity in blocks per tick. It is sent in 1/8000 blocks per tick.
+        pub fn velocity(&self) -> Displacement {
+            const UNITS: f64 = 8000.0;
+            Displacement::new(f64::from(self.velocity_x) / UNITS, f64::from(self.velocity_y) / UNITS, f64::from(self.velocity_z) / UNITS)
+        }
+    }
+
+    /// Entity Effect. A potion effect started or changed.
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x4f, Play)]
+    pub struct Effect {
+        pub entity_id: VarInt,
+        pub effect_id: u8,
+        pub amplifier: u8,
+
+        /// in ticks
+        pub duration: VarInt,
+        pub flags: u8,
+    }
+
+    /// Remove Entity Effect. A potion effect was taken away (i.e., by drinking milk).
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x33, Play)]
+    pub struct RemoveEffect {
+        pub entity_id: VarInt,
+        pub effect_id: u8,
+    }
 }
 
 
@@ -323,7 +424,7 @@ pub struct PluginMessage {
 #[derive(Packet, Debug, Readable)]
 #[packet(0x0f, Play)]
 pub struct ChatMessage {
-    pub chat: Chat,
+    pub chat: Component,
     pub position: u8,
 }
 
@@ -340,7 +441,9 @@ pub struct Explosion {
     pub location: Location,
     pub radius: f32,
     pub records: Vec<BlockLocation>,
-    // TODO: velocity
+
+    /// added to the velocity of the player receiving the packet
+    pub velocity: Displacement,
 }
 
 impl ByteReadable for Explosion {
@@ -354,6 +457,12 @@ impl ByteReadable for Explosion {
             records.0
         };
 
+        let velocity = {
+            let dx: f32 = byte_reader.read();
+            let dy: f32 = byte_reader.read();
+            let dz: f32 = byte_reader.read();
+            Displacement::new(dx as f64, dy as f64, dz as f64)
+        };
 
         let origin_block = BlockLocation::from_flts(location.x, location.y, location.z);
         let location: Location = location.into();
@@ -366,6 +475,7 @@ impl ByteReadable for Explosion {
             location,
             radius,
             records,
+            velocity,
         }
     }
 }
@@ -388,18 +498,108 @@ pub struct UpdateHealth {
     pub food_saturation: f32,
 }
 
+/// where players without a bed spawn (and compasses point)
+#[derive(Packet, Debug, Readable)]
+#[packet(0x46, Play)]
+pub struct SpawnPosition {
+    pub location: Position,
+}
+
+/// a player lay down in the bed at `location`
+#[derive(Packet, Debug, Readable)]
+#[packet(0x30, Play)]
+pub struct UseBed {
+    pub entity_id: VarInt,
+    pub location: Position,
+}
+
+/// what the player may do, i.e., fly. The flags are those of [`crate::client::state::local::abilities::Abilities`].
+#[derive(Packet, Debug, Readable)]
+#[packet(0x2C, Play)]
+pub struct PlayerAbilities {
+    pub flags: u8,
+    pub fly_speed: f32,
+    pub walk_speed: f32,
+}
+
+/// https://wiki.vg/Entity_statuses
+#[derive(Packet, Debug, Readable)]
+#[packet(0x1b, Play)]
+pub struct EntityStatus {
+    pub entity_id: i32,
+    pub status: u8,
+}
+
+impl EntityStatus {
+    /// a totem of undying was used
+    pub const TOTEM: u8 = 35;
+}
+
 #[derive(Packet, Debug, Readable)]
 #[packet(0x1f, Play)]
 pub struct KeepAlive {
     pub id: u64,
 }
 
+/// sent every 20 ticks
+#[derive(Packet, Debug, Readable)]
+#[packet(0x47, Play)]
+pub struct TimeUpdate {
+    pub world_age: u64,
+    pub time_of_day: u64,
+}
+
 #[derive(Packet, Debug, Readable)]
 #[packet(0x1a, Play)]
 pub struct PlayDisconnect {
     pub reason: String,
 }
 
+/// the server changed the held hotbar slot
+#[derive(Packet, Debug, Readable)]
+#[packet(0x3a, Play)]
+pub struct HeldItemChange {
+    pub slot: i8,
+}
+
+/// Read a block entity. Some(None) if it has no id or location. None if the nbt cannot be read, in which case where it
+/// ends is not known.
+fn read_tile_entity(byte_reader: &mut ByteReader) -> Option<Option<(BlockLocation, TileEntity)>> {
+    let (entity, len) = match crate::nbt::read(byte_reader.remaining(), &NbtLimits::NETWORK) {
+        Ok(nbt) if nbt.truncated.is_none() => (TileEntity::parse(&nbt.root), nbt.len),
+        _ => return None
+    };
+
+    byte_reader.advance(len);
+    Some(entity)
+}
+
+/// A block entity changed (i.e., the text of a sign or the mob of a spawner)
+#[derive(Packet, Debug)]
+#[packet(0x09, Play)]
+pub struct UpdateBlockEntity {
+    pub location: Position,
+
+    /// None if the nbt could not be read
+    pub entity: Option<TileEntity>,
+}
+
+impl UpdateBlockEntity {
+    pub const SET_SIGN_TEXT: u8 = 9;
+}
+
+impl ByteReadable for UpdateBlockEntity {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let location = byte_reader.read();
+
+        // every action sends the whole block entity
+        let _action: u8 = byte_reader.read();
+        let entity = read_tile_entity(byte_reader).flatten().map(|(_, entity)| entity);
+
+        UpdateBlockEntity { location, entity }
+    }
+}
+
 // #[derive(Packet)]
 // #[packet(0x20, Play)]
 
@@ -410,6 +610,9 @@ pub struct ChunkColumnPacket {
     pub chunk_z: i32,
     pub new_chunk: bool,
     pub column: ChunkColumn,
+
+    /// the block entities in the sections which were sent
+    pub tile_entities: Vec<(BlockLocation, TileEntity)>,
 }
 
 impl ByteReadableLike for ChunkColumnPacket {
@@ -420,16 +623,18 @@ impl ByteReadableLike for ChunkColumnPacket {
         let chunk_z = byte_reader.read();
         let ground_up_continuous: bool = byte_reader.read();
         let VarUInt(mut primary_bitmask) = byte_reader.read();
-        let _size: VarUInt = byte_reader.read();
+        let VarUInt(size) = byte_reader.read();
+        let data_end = byte_reader.len().saturating_sub(size as usize);
 
-        const INIT: Option<HighMemoryChunkSection> = None;
+        const INIT: Option<Arc<HighMemoryChunkSection>> = None;
         let mut sections = [INIT; 16];
 
         let mut idx = 0;
         while primary_bitmask != 0 {
             if primary_bitmask & 0b1 == 1 {
                 let section: ChunkSection = byte_reader.read_like(param);
-                sections[idx] = Some(HighMemoryChunkSection::new(section.palette));
+                let light = SectionLight::new(section.block_light, section.sky_light);
+                sections[idx] = Some(Arc::new(HighMemoryChunkSection::new(section.palette).with_light(Some(light))));
             }
             primary_bitmask >>= 1;
             idx += 1;
@@ -443,11 +648,23 @@ impl ByteReadableLike for ChunkColumnPacket {
             data
         };
 
+        // the biomes are skipped to get to the block entities
+        byte_reader.advance(byte_reader.len().saturating_sub(data_end));
+        let VarInt(count) = if byte_reader.is_empty() { VarInt(0) } else { byte_reader.read() };
+        let mut tile_entities = Vec::new();
+        for _ in 0..count {
+            match read_tile_entity(byte_reader) {
+                Some(entity) => tile_entities.extend(entity),
+                None => break
+            }
+        }
+
         ChunkColumnPacket {
             chunk_x,
             chunk_z,
             new_chunk: ground_up_continuous,
             column,
+            tile_entities,
         }
     }
 }
@@ -479,6 +696,12 @@ pub mod window {
         // TODO: entity id if EntityHorse
     }
 
+    #[derive(Packet, Debug, Readable)]
+    #[packet(0x12, Play)]
+    pub struct Close {
+        pub window_id: u8,
+    }
+
     #[derive(Packet, Readable)]
     #[packet(0x14, Play)]
     pub struct Items {
@@ -493,12 +716,9 @@ pub mod window {
 
 pub struct ChunkSection {
     palette: crate::storage::chunk::Palette,
-
-    #[allow(unused)]
     block_light: [u8; 2048],
 
-    // TODO: fix overworld
-    #[allow(unused)]
+    /// only sent in the overworld
     sky_light: Option<[u8; 2048]>,
 }
 
@@ -14,33 +14,42 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 use std::sync::mpsc::TryRecvError;
+use std::time::Instant;
 
-use swarm_bot_packets::types::{PacketState, UUID, VarInt};
+use swarm_bot_packets::types::VarInt;
 use swarm_bot_packets::types::Packet;
 use swarm_bot_packets::write::ByteWritable;
 
-use crate::bootstrap::{Address, Connection};
-use crate::bootstrap::mojang::calc_hash;
-use crate::bootstrap::storage::ValidUser;
+use crate::chat::ChatEvent;
+use crate::bootstrap::Connection;
+use crate::client::metrics::METRICS;
 use crate::client::processor::InterfaceIn;
-use crate::error::{err, Res};
-use crate::error::Error::WrongPacket;
-use crate::protocol::{ClientInfo, EventQueue, Face, InterfaceOut, InvAction, Login, Mine, Minecraft};
-use crate::protocol::encrypt::{rand_bits, Rsa};
-use crate::protocol::io::reader::PacketReader;
-use crate::protocol::io::writer::{PacketWriteChannel, PacketWriter};
-use crate::protocol::v340::clientbound::{JoinGame, LoginSuccess};
-use crate::protocol::v340::serverbound::{ClientStatusAction, DigStatus, Hand, HandshakeNextState, InteractEntityKind};
-use crate::storage::block::{BlockLocation, BlockState};
+use crate::client::state::local::abilities::Abilities;
+use crate::error::{err, Phase, SwarmContext, SwarmError};
+use crate::protocol::{ClientInfo, Closing, EventQueue, Face, InterfaceOut, InvAction, Login, Mine, Minecraft, MouseButton};
+use crate::protocol::io::reader::Immediate;
+use crate::protocol::io::writer::PacketWriteChannel;
+use crate::protocol::budget::{ActionBudget, ActionKind, BudgetConfig};
+use crate::protocol::chat_queue::{ChatConfig, ChatQueue};
+use crate::protocol::disconnect::DisconnectPatterns;
+use crate::protocol::movement::{MovementOut, MovementTracker};
+use crate::protocol::login::{self, LoggedIn};
+use crate::protocol::tick::TickTimer;
+use crate::protocol::v340::clientbound::JoinGame;
+use crate::protocol::v340::serverbound::{Action, ClientStatusAction, DigStatus, Hand, InteractEntityKind};
+use crate::storage::block::{BlockKind, BlockLocation, BlockState};
 use crate::storage::blocks::ChunkLocation;
 use crate::storage::entities::EntityKind;
-use crate::types::{Dimension, Direction, Location, PacketData, Slot};
+use crate::types::{Dimension, Direction, DirectionOrigin, GameMode, ItemNbt, Location, PacketData, Slot};
 
-mod clientbound;
-mod serverbound;
+pub(super) mod clientbound;
+pub(super) mod serverbound;
+
+#[cfg(test)]
+pub mod server;
 
 pub struct EventQueue340 {
     rx: std::sync::mpsc::Receiver<PacketData>,
@@ -50,10 +59,32 @@ pub struct EventQueue340 {
 
     /// we need to store state because sometimes death packets occur twice and we only want to send one event
     alive: bool,
+
+    /// the combat event with the death message is sent before the health update
+    death_cause: Option<String>,
+
+    disconnects: DisconnectPatterns,
+
+    /// the id and number of (non player) slots of the window the server opened
+    window: Option<(u8, usize)>,
+
+    /// filled in by the reader
+    ticks: TickTimer,
+}
+
+/// The death message is a translatable chat component such as `death.attack.lava`. We use the key as the cause.
+pub(super) fn death_cause(message: &str) -> String {
+    let json: serde_json::Value = serde_json::from_str(message).unwrap_or_default();
+    json.get("translate")
+        .and_then(|key| key.as_str())
+        .map(|key| key.trim_start_matches("death.").to_string())
+        .unwrap_or_else(|| message.to_string())
 }
 
 impl EventQueue for EventQueue340 {
     fn flush(&mut self, processor: &mut impl InterfaceIn) {
+        self.out.flush_chat(Instant::now());
+
         loop {
             match self.rx.try_recv() {
                 Ok(data) => {
@@ -74,21 +105,32 @@ impl EventQueue for EventQueue340 {
 }
 
 impl EventQueue340 {
+    /// The player inventory slot of a window slot. An open window (i.e., a chest) has its own slots first, followed
+    /// by the main inventory and the hotbar. The armor and off-hand slots are only in the player window (0).
+    fn player_slot(&self, window_id: u8, slot: usize) -> Option<usize> {
+        match self.window {
+            _ if window_id == 0 => Some(slot),
+            Some((id, slots)) if id == window_id && slot >= slots => Some(slot - slots + 9),
+            _ => None
+        }
+    }
+
     fn process_packet(&mut self, mut data: PacketData, processor: &mut impl InterfaceIn) {
         use clientbound::*;
         match data.id {
             JoinGame::ID => {
-                let JoinGame { dimension, .. } = data.read();
+                let JoinGame { dimension, game_mode, .. } = data.read();
                 processor.on_join();
+                processor.on_gamemode(game_mode);
                 processor.on_dimension_change(dimension);
             }
 
             window::Set::ID => {
                 let window::Set { window_id, slot: idx, data } = data.read();
-                if window_id == 0 {
+                if let Some(idx) = self.player_slot(window_id, idx as usize) {
                     match data.into() {
-                        None => processor.on_lose_item(idx as usize),
-                        Some(item_stack) => processor.on_pickup_item(idx as usize, item_stack)
+                        None => processor.on_lose_item(idx),
+                        Some(item_stack) => processor.on_pickup_item(idx, item_stack)
                     }
                 }
             }
@@ -96,8 +138,8 @@ impl EventQueue340 {
             window::Items::ID => {
                 let window::Items { window_id, slots } = data.read();
 
-                if window_id == 0 { // is player inventory
-                    for (idx, slot) in slots.0.into_iter().enumerate() {
+                for (idx, slot) in slots.0.into_iter().enumerate() {
+                    if let Some(idx) = self.player_slot(window_id, idx) {
                         match slot.into() {
                             None => processor.on_lose_item(idx),
                             Some(item_stack) => processor.on_pickup_item(idx, item_stack)
@@ -106,24 +148,42 @@ impl EventQueue340 {
                 }
             }
 
+            window::Open::ID => {
+                let window::Open { window_id, slot_count, .. } = data.read();
+                self.window = Some((window_id, slot_count as usize));
+                processor.on_open_window(window_id, slot_count as usize);
+            }
+
+            window::Close::ID => {
+                let window::Close { window_id } = data.read();
+                self.window = None;
+                processor.on_close_window(window_id);
+            }
+
+            HeldItemChange::ID => {
+                let HeldItemChange { slot } = data.read();
+                processor.on_held_slot(slot as u8);
+            }
+
             BlockChange::ID => {
                 let BlockChange { block_id, location } = data.read();
                 processor.on_block_change(location, BlockState(block_id.0 as u32));
             }
-            Explosion::ID => {
-                let Explosion { records, .. } = data.read();
-
-                for record in records {
-                    processor.on_block_change(record, BlockState::AIR);
+            UpdateBlockEntity::ID => {
+                let UpdateBlockEntity { location, entity } = data.read();
+                if let Some(entity) = entity {
+                    processor.on_tile_entity(location, entity);
                 }
             }
-            KeepAlive::ID => {
-                // auto keep alive
-                let KeepAlive { id } = data.read();
-
-                self.out.write(serverbound::KeepAlive {
-                    id
-                });
+            Explosion::ID => {
+                let Explosion { records, velocity, .. } = data.read();
+                processor.on_explosion(records, velocity);
+            }
+            TimeUpdate::ID => {
+                // keep alives are answered by the reader
+                if let Some(tps) = self.ticks.tps() {
+                    processor.on_server_tps(tps);
+                }
             }
             entity::RelativeMove::ID => {
                 let entity::RelativeMove { entity_id, loc, .. } = data.read();
@@ -143,9 +203,31 @@ impl EventQueue340 {
                 let entity::Teleport { entity_id, location, .. } = data.read();
                 processor.on_entity_move(entity_id.into(), location.into());
             }
+            entity::Velocity::ID => {
+                let velocity: entity::Velocity = data.read();
+                processor.on_entity_velocity(velocity.entity_id.into(), velocity.velocity());
+            }
+            entity::Effect::ID => {
+                let entity::Effect { entity_id, effect_id, amplifier, duration, .. } = data.read();
+                processor.on_entity_effect(entity_id.into(), effect_id.into(), amplifier, duration.0.max(0) as u32);
+            }
+            entity::RemoveEffect::ID => {
+                let entity::RemoveEffect { entity_id, effect_id } = data.read();
+                processor.on_remove_entity_effect(entity_id.into(), effect_id.into());
+            }
             entity::LivingSpawn::ID => {
-                let entity::LivingSpawn { entity_id, location, .. } = data.read();
-                processor.on_entity_spawn(entity_id.into(), location, EntityKind::Normal);
+                let entity::LivingSpawn { entity_id, type_id, location, .. } = data.read();
+                let kind = if entity::LivingSpawn::HOSTILE.contains(&type_id.0) { EntityKind::Hostile } else { EntityKind::Normal };
+                processor.on_entity_spawn(entity_id.into(), location, kind);
+            }
+            entity::ObjectSpawn::ID => {
+                let entity::ObjectSpawn { entity_id, type_id, location, data, .. } = data.read();
+                if type_id == entity::ObjectSpawn::FALLING_BLOCK {
+                    let state = BlockState::from((data & 0xFFF) as u32, (data >> 12) as u16);
+                    processor.on_entity_spawn(entity_id.into(), location, EntityKind::FallingBlock { state });
+                } else if type_id == entity::ObjectSpawn::ITEM {
+                    processor.on_entity_spawn(entity_id.into(), location, EntityKind::Item);
+                }
             }
             entity::PlayerSpawn::ID => {
                 let entity::PlayerSpawn { entity_id, location, player_uuid, .. } = data.read();
@@ -157,21 +239,64 @@ impl EventQueue340 {
                     processor.on_update_health(health, food.0 as u8);
                     self.alive = true;
                 } else if self.alive {
-                    processor.on_death();
+                    processor.on_death(self.death_cause.take());
                     self.alive = false;
                 }
             }
+            SpawnPosition::ID => {
+                let SpawnPosition { location } = data.read();
+                processor.on_spawn_position(location);
+            }
+            UseBed::ID => {
+                let UseBed { entity_id, .. } = data.read();
+                if entity_id.0 as u32 == self.out.entity_id {
+                    processor.on_sleep();
+                }
+            }
+            PlayerAbilities::ID => {
+                let PlayerAbilities { flags, fly_speed, walk_speed } = data.read();
+                processor.on_abilities(Abilities::from_flags(flags, fly_speed, walk_speed));
+            }
+            EntityStatus::ID => {
+                let EntityStatus { entity_id, status } = data.read();
+                if entity_id as u32 == self.out.entity_id && status == EntityStatus::TOTEM {
+                    processor.on_totem_pop();
+                }
+            }
+            CombatEvent::ID => {
+                let CombatEvent { death_message } = data.read();
+                if let Some(message) = death_message {
+                    self.death_cause = Some(death_cause(&message));
+                }
+            }
             Respawn::ID => {
-                let Respawn { dimension, .. } = data.read();
+                let Respawn { dimension, gamemode, .. } = data.read();
+                processor.on_gamemode(gamemode);
                 processor.on_dimension_change(dimension);
                 self.dimension = dimension;
             }
+            ChangeGameState::ID => {
+                let ChangeGameState { reason, value } = data.read();
+                if reason == ChangeGameState::CHANGE_GAMEMODE {
+                    if let Some(gamemode) = GameMode::from_id(value as u8) {
+                        processor.on_gamemode(gamemode);
+                    }
+                }
+            }
 
             // need to do this because the chunk packet is read differently based on dimension
             clientbound::CHUNK_PKT_ID => {
                 let overworld = self.dimension == Dimension::Overworld;
-                let ChunkColumnPacket { chunk_x, chunk_z, column, new_chunk } = data.reader.read_like(&overworld);
+                let ChunkColumnPacket { chunk_x, chunk_z, column, new_chunk, tile_entities } = data.reader.read_like(&overworld);
+                METRICS.chunks_in.inc();
                 processor.on_recv_chunk(ChunkLocation(chunk_x, chunk_z), column, new_chunk);
+                for (location, entity) in tile_entities {
+                    processor.on_tile_entity(location, entity);
+                }
+            }
+            UnloadChunk::ID => {
+                let UnloadChunk { chunk_x, chunk_z } = data.read();
+                processor.on_unload_chunk(ChunkLocation(chunk_x, chunk_z));
             }
             MultiBlock::ID => {
                 let MultiBlock { chunk_x, chunk_z, records } = data.read();
@@ -185,29 +310,34 @@ impl EventQueue340 {
                 }
             }
             PlayerPositionAndLook::ID => {
-                let PlayerPositionAndLook { location, rotation: _, teleport_id } = data.read();
+                let PlayerPositionAndLook { location, rotation, teleport_id } = data.read();
 
                 self.location.apply_change(location);
-                processor.on_move(self.location);
 
                 // "accept" the packet
                 self.out.write(serverbound::TeleportConfirm {
                     teleport_id
                 });
+                let direction = self.out.resync(self.location, rotation);
+                processor.on_move(self.location, direction);
             }
             PlayDisconnect::ID => {
                 let PlayDisconnect { reason } = data.read();
-                processor.on_disconnect(&reason);
+                processor.on_disconnect(self.disconnects.classify(&reason));
             }
             PlayerListItem::ID => {
                 let PlayerListItem { players } = data.read();
                 for Player { uuid, list_type } in players {
                     match list_type {
                         PlayerListType::AddPlayer(add) => {
-                            processor.on_player_join(uuid.0, add.name);
+                            processor.on_player_join(add.into_player(uuid.0));
+                        }
+                        PlayerListType::UpdateGamemode(VarInt(gamemode)) => {
+                            processor.on_player_update(uuid.0, None, Some(gamemode as u8));
+                        }
+                        PlayerListType::UpdateLatency(VarInt(ping)) => {
+                            processor.on_player_update(uuid.0, Some(ping.max(0) as u32), None);
                         }
-                        PlayerListType::UpdateGamemode(_) => {}
-                        PlayerListType::UpdateLatency(_) => {}
                         PlayerListType::UpdateDisplayName(_) => {}
                         PlayerListType::RemovePlayer => {
                             processor.on_player_leave(uuid.0)
@@ -218,31 +348,58 @@ impl EventQueue340 {
             // ignore
             ChatMessage::ID => {
                 let ChatMessage { chat, position: _ } = data.read();
-                processor.on_chat(chat);
+                processor.on_chat(ChatEvent::from(chat));
             }
             _ => {}
         }
     }
 }
 
+/// an action which waits for the budget
+type Deferred = Box<dyn FnOnce(&mut PacketWriteChannel)>;
+
 #[derive(Clone)]
 pub struct Interface340 {
     tx: Rc<RefCell<PacketWriteChannel>>,
+    movement: Rc<RefCell<MovementTracker>>,
+    chat: Rc<RefCell<ChatQueue>>,
+    budget: Rc<RefCell<ActionBudget<Deferred>>>,
+
+    /// the view distance the server was told. None until it was told.
+    view_distance: Rc<Cell<Option<u8>>>,
+
+    /// our entity id, which Entity Action packets refer to
+    entity_id: u32,
     inv_action_id: u16,
 }
 
 impl Interface340 {
-    fn new(tx: PacketWriteChannel) -> Interface340 {
+    fn new(tx: PacketWriteChannel, chat: ChatConfig, entity_id: u32) -> Interface340 {
         Interface340 {
             tx: Rc::new(RefCell::new(tx)),
+            movement: Rc::default(),
+            chat: Rc::new(RefCell::new(ChatQueue::new(chat))),
+            budget: Rc::default(),
+            view_distance: Rc::default(),
+            entity_id,
             inv_action_id: 0,
         }
     }
 
-    fn click(&mut self, slot: u16, button: impl Into<u8>, mode: i32, clicked: impl Into<Slot>) {
+    /// An interface which is not connected to anything. The packets it would send can be read from the receiver. The
+    /// budget is unlimited so every action is written right away.
+    #[cfg(test)]
+    pub fn test() -> (Interface340, tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>) {
+        let (tx, rx) = PacketWriteChannel::test();
+        let mut out = Interface340::new(tx, ChatConfig::default(), 0);
+        out.set_budget(BudgetConfig::unlimited());
+        (out, rx)
+    }
+
+    fn click(&mut self, window_id: u8, slot: u16, button: impl Into<u8>, mode: i32, clicked: impl Into<Slot>) {
         let action_number = self.inv_action_id;
         let to_send = serverbound::ClickWindow {
-            window_id: 0,
+            window_id,
             slot,
             button: button.into(),
             action_number,
@@ -250,7 +407,7 @@ impl Interface340 {
             clicked: clicked.into(),
         };
 
-        self.write(to_send);
+        self.act(Some(ActionKind::Click), to_send);
 
         self.inv_action_id += 1;
     }
@@ -259,13 +416,73 @@ impl Interface340 {
     fn write<T: Packet + ByteWritable>(&self, packet: T) {
         self.tx.borrow_mut().write(packet)
     }
+
+    /// Write `packet` now if the budget of `kind` allows it (None if it has no budget) and no action waits. Otherwise
+    /// it waits for [`InterfaceOut::flush_budget`]. Movement is written right away as it is sent every tick anyway.
+    fn act<T: Packet + ByteWritable + 'static>(&self, kind: Option<ActionKind>, packet: T) {
+        let mut budget = self.budget.borrow_mut();
+        if budget.try_take(kind) {
+            self.write(packet);
+        } else {
+            budget.defer(kind, Box::new(move |tx: &mut PacketWriteChannel| tx.write(packet)));
+        }
+    }
+
+    /// see [`MovementTracker::resync`]
+    fn resync(&self, location: Location, rotation: DirectionOrigin) -> Direction {
+        self.movement.borrow_mut().resync(location, rotation, self)
+    }
+
+    /// send the next queued chat message if the rate limit and the chat budget allow it
+    fn flush_chat(&self, now: Instant) {
+        let mut budget = self.budget.borrow_mut();
+        if !budget.ready(ActionKind::Chat) {
+            return;
+        }
+
+        let message = self.chat.borrow_mut().poll(now);
+        if let Some(message) = message {
+            budget.spend(ActionKind::Chat);
+            self.write(serverbound::ChatMessage { message });
+        }
+    }
+}
+
+impl MovementOut for Interface340 {
+    fn position_and_rotation(&self, location: Location, direction: Direction, on_ground: bool) {
+        self.write(serverbound::PlayerPositionAndRotation {
+            location,
+            direction,
+            on_ground,
+        });
+    }
+
+    fn position(&self, location: Location, on_ground: bool) {
+        self.write(serverbound::PlayerPosition {
+            location,
+            on_ground,
+        });
+    }
+
+    fn rotation(&self, direction: Direction, on_ground: bool) {
+        self.write(serverbound::PlayerLook {
+            direction,
+            on_ground,
+        });
+    }
+
+    fn on_ground(&self, on_ground: bool) {
+        self.write(serverbound::PlayerMovement {
+            on_ground
+        });
+    }
 }
 
 impl InterfaceOut for Interface340 {
     fn place_block(&mut self, against: BlockLocation, face: Face) {
         let face = VarInt(face as i32);
 
-        self.write(serverbound::PlaceBlock {
+        self.act(Some(ActionKind::Place), serverbound::PlaceBlock {
             location: against,
             face,
             hand: Hand::Main,
@@ -278,45 +495,78 @@ impl InterfaceOut for Interface340 {
     }
 
     fn attack_entity(&mut self, id: u32) {
-        self.write(serverbound::InteractEntity {
+        self.act(None, serverbound::InteractEntity {
             id: id.into(),
             kind: InteractEntityKind::Attack,
         })
     }
 
     fn send_chat(&mut self, message: &str) {
-        self.write(serverbound::ChatMessage {
-            message: message.to_string()
-        });
+        // sent from the tick loop so the bot does not get kicked for spamming
+        self.chat.borrow_mut().push(message, Instant::now());
+    }
+
+    fn set_chat_config(&mut self, config: ChatConfig) {
+        self.chat.borrow_mut().set_config(config);
+    }
+
+    fn set_budget(&mut self, config: BudgetConfig) {
+        self.budget.borrow_mut().set_config(config);
+    }
+
+    fn flush_budget(&mut self) {
+        let ready = self.budget.borrow_mut().tick();
+        let mut tx = self.tx.borrow_mut();
+        for action in ready {
+            action(&mut *tx);
+        }
+    }
+
+    fn deferred(&self) -> usize {
+        self.budget.borrow().waiting()
+    }
+
+    fn set_view_distance(&mut self, chunks: u8) {
+        if self.view_distance.replace(Some(chunks)) != Some(chunks) {
+            self.write(serverbound::ClientSettings::new(chunks));
+        }
     }
 
     fn inventory_action(&mut self, action: InvAction) {
         match action {
-            InvAction::Q(slot) => self.click(slot, 0, 4, Slot::EMPTY),
-            InvAction::CtrlQ(slot) => self.click(slot, 1, 4, Slot::EMPTY),
-            InvAction::Click(slot, button, clicked) => self.click(slot, button, 0, clicked),
-            InvAction::ShiftClick(slot, button, clicked) => self.click(slot, button, 1, clicked),
+            InvAction::Q(slot) => self.click(0, slot, 0, 4, Slot::EMPTY),
+            InvAction::CtrlQ(slot) => self.click(0, slot, 1, 4, Slot::EMPTY),
+            InvAction::Click(slot, button, clicked) => self.click(0, slot, button, 0, clicked),
+            InvAction::ShiftClick(slot, button, clicked) => self.click(0, slot, button, 1, clicked),
+
+            // the vanilla client sends an empty clicked item for number key swaps
+            InvAction::SwapHotbar(slot, hotbar) => self.click(0, slot, hotbar, 2, Slot::EMPTY),
+            InvAction::SwapOffhand => self.act(None, serverbound::PlayerDig::status(DigStatus::SwapItem)),
+            InvAction::WindowShiftClick(window, slot, clicked) => self.click(window, slot, MouseButton::Left, 1, clicked),
+            InvAction::Creative(slot, stack) => {
+                self.act(Some(ActionKind::Click), serverbound::CreativeInventoryAction { slot, clicked: stack.into() });
+            }
         }
     }
 
     fn swing_arm(&mut self) {
-        self.write(serverbound::ArmAnimation {
+        self.act(Some(ActionKind::Swing), serverbound::ArmAnimation {
             hand: Hand::Main
         });
     }
 
     fn finish_eating(&mut self) {
-        self.write(serverbound::PlayerDig::status(DigStatus::ShootArrowOrFinishEat));
+        self.act(None, serverbound::PlayerDig::status(DigStatus::ShootArrowOrFinishEat));
     }
 
-    fn use_item(&mut self) {
-        self.write(serverbound::UseItem {
-            hand: Hand::Main
+    fn use_item(&mut self, hand: Hand) {
+        self.act(None, serverbound::UseItem {
+            hand
         });
     }
 
     fn change_slot(&mut self, number: u8) {
-        self.write(serverbound::ChangeSlot {
+        self.act(None, serverbound::ChangeSlot {
             slot: number as u16
         })
     }
@@ -332,7 +582,9 @@ impl InterfaceOut for Interface340 {
             self.swing_arm();
         }
 
-        self.write(serverbound::PlayerDig {
+        // only starting to dig counts, finishing or cancelling it is part of the same action
+        let kind = if status == DigStatus::Started { Some(ActionKind::Dig) } else { None };
+        self.act(kind, serverbound::PlayerDig {
             status,
             position,
             face: face as u8,
@@ -340,12 +592,13 @@ impl InterfaceOut for Interface340 {
     }
 
     fn respawn(&mut self) {
-        self.write(serverbound::ClientStatus {
+        self.act(None, serverbound::ClientStatus {
             action: ClientStatusAction::Respawn
         });
     }
 
     fn teleport(&mut self, location: Location) {
+        self.movement.borrow_mut().sent(Some(location), None);
         self.write(serverbound::PlayerPosition {
             location,
             on_ground: true,
@@ -353,6 +606,7 @@ impl InterfaceOut for Interface340 {
     }
 
     fn look(&mut self, direction: Direction) {
+        self.movement.borrow_mut().sent(None, Some(direction));
         self.write(serverbound::PlayerLook {
             direction,
             on_ground: false,
@@ -360,125 +614,138 @@ impl InterfaceOut for Interface340 {
     }
 
     fn teleport_and_look(&mut self, location: Location, direction: Direction, on_ground: bool) {
-        self.write(serverbound::PlayerPositionAndRotation {
-            location,
-            direction,
-            on_ground,
-        })
+        self.movement.borrow_mut().send_tick(location, direction, on_ground, &*self);
     }
-}
-
-pub struct Protocol;
-
-#[async_trait::async_trait]
-impl Minecraft for Protocol {
-    type Queue = EventQueue340;
-    type Interface = Interface340;
-
-    async fn login(conn: Connection) -> Res<Login<EventQueue340, Interface340>> {
-        let Connection { user, address, mojang, read, write } = conn;
-        let ValidUser { username, uuid, access_id, .. } = user;
-
-        let Address { host, port } = address;
-        let uuid = UUID::from(&uuid);
-
-        let mut reader = PacketReader::from(read);
-        let mut writer = PacketWriter::from(write);
-
-
-        // START: handshake
-        writer.write(serverbound::Handshake {
-            protocol_version: VarInt(340),
-            host,
-            port,
-            next_state: HandshakeNextState::Login,
-        }).await?;
 
+    fn sneak(&mut self, sneaking: bool) {
+        self.act(None, serverbound::EntityAction {
+            entity_id: VarInt(self.entity_id as i32),
+            action: if sneaking { Action::SneakStart } else { Action::SneakStop },
+            jump_boost: VarInt(0),
+        });
+    }
 
-        // START: login
-        writer.write(serverbound::LoginStart {
-            username: username.clone()
-        }).await?;
+    fn sprint(&mut self, sprinting: bool) {
+        self.act(None, serverbound::EntityAction {
+            entity_id: VarInt(self.entity_id as i32),
+            action: if sprinting { Action::SprintStart } else { Action::SprintStop },
+            jump_boost: VarInt(0),
+        });
+    }
 
-        let clientbound::EncryptionRequest { public_key_der, verify_token, server_id } = reader.read_exact_packet().await?;
+    fn start_gliding(&mut self) {
+        self.act(None, serverbound::EntityAction {
+            entity_id: VarInt(self.entity_id as i32),
+            action: Action::ElytraFlyStart,
+            jump_boost: VarInt(0),
+        });
+    }
 
-        let rsa = Rsa::from_der(&public_key_der);
+    fn leave_bed(&mut self) {
+        self.act(None, serverbound::EntityAction {
+            entity_id: VarInt(self.entity_id as i32),
+            action: Action::LeaveBed,
+            jump_boost: VarInt(0),
+        });
+    }
 
-        let shared_secret = rand_bits();
+    fn abilities(&mut self, abilities: Abilities) {
+        self.act(None, serverbound::PlayerAbilities {
+            flags: abilities.flags(),
+            fly_speed: abilities.fly_speed,
+            walk_speed: abilities.walk_speed,
+        });
+    }
 
-        let encrypted_ss = rsa.encrypt(&shared_secret).unwrap();
-        let encrypted_verify = rsa.encrypt(&verify_token).unwrap();
+    fn close_window(&mut self, window: u8) {
+        self.act(None, serverbound::CloseWindow {
+            window_id: window
+        });
+    }
 
-        // Mojang online mode requests
-        let hash = calc_hash(&server_id, &shared_secret, &public_key_der);
-        mojang.join(uuid, &hash, &access_id).await?;
+    fn edit_book(&mut self, pages: &[String]) {
+        self.act(None, serverbound::BookMessage {
+            channel: serverbound::BookMessage::EDIT.to_string(),
+            book: book(pages, None),
+        });
+    }
 
-        // id = 1
-        writer.write(serverbound::EncryptionResponse {
-            shared_secret: encrypted_ss,
-            verify_token: encrypted_verify,
-        }).await?;
+    fn sign_book(&mut self, pages: &[String], title: &str, author: &str) {
+        self.act(None, serverbound::BookMessage {
+            channel: serverbound::BookMessage::SIGN.to_string(),
+            book: book(pages, Some((title, author))),
+        });
+    }
 
-        // writer.flush().await;
+    fn close(&mut self) -> Closing {
+        Box::pin(self.tx.borrow().close())
+    }
+}
 
-        // we now do everything encrypted
-        writer.encryption(&shared_secret);
-        reader.encryption(&shared_secret);
+pub struct Protocol;
 
+/// the writable book (with `pages`) which is held, as the vanilla client sends it when it edits or signs (`title` and
+/// `author`) a book. The server sets the author of a signed book itself.
+fn book(pages: &[String], signature: Option<(&str, &str)>) -> Slot {
+    let (title, author) = match signature {
+        None => (None, None),
+        Some((title, author)) => (Some(title.to_string()), Some(author.to_string()))
+    };
+    Slot {
+        block_id: BlockKind::WRITABLE_BOOK.id() as i16,
+        item_count: Some(1),
+        item_damage: Some(0),
+        nbt: Some(ItemNbt {
+            pages: Some(pages.to_vec()),
+            title,
+            author,
+            ..ItemNbt::default()
+        }),
+    }
+}
 
-        // set compression or login success
-        let mut data = reader.read().await?;
+fn keep_alive(id: u64) -> serverbound::KeepAlive {
+    serverbound::KeepAlive { id }
+}
 
-        let LoginSuccess { .. } = match data.id {
-            clientbound::SetCompression::ID => {
-                let clientbound::SetCompression { threshold } = data.read();
+/// what the reader answers by itself
+const IMMEDIATE: Immediate<serverbound::KeepAlive> = Immediate {
+    keep_alive: clientbound::KeepAlive::ID,
+    time_update: clientbound::TimeUpdate::ID,
+    reply: keep_alive,
+};
 
-                reader.compression(threshold.into());
-                writer.compression(threshold.into());
+#[async_trait::async_trait]
+impl Minecraft for Protocol {
+    type Queue = EventQueue340;
+    type Interface = Interface340;
 
-                reader.read_exact_packet().await?
-            }
-            clientbound::LoginSuccess::ID => {
-                data.reader.read()
-            }
-            actual => {
-                return Err(WrongPacket {
-                    state: PacketState::Login,
-                    expected: LoginSuccess::ID,
-                    actual,
-                });
-            }
-        };
+    async fn login(conn: Connection) -> Result<Login<EventQueue340, Interface340>, SwarmError> {
+        let chat = conn.chat.clone();
+        let disconnects = conn.disconnects.clone();
+        let LoggedIn { reader, writer, username, uuid } = login::login(conn, 340).await?;
 
         let (tx, rx) = std::sync::mpsc::channel();
         let (os_tx, os_rx) = tokio::sync::oneshot::channel();
 
-        tokio::task::spawn_local(async move {
-            let mut oneshot = Some(os_tx);
-            loop {
-                let packet = reader.read().await.unwrap();
-                if packet.id == clientbound::JoinGame::ID {
-                    if let Some(os_tx) = oneshot.take() {
-                        let mut packet = packet.clone();
-                        let processed: JoinGame = packet.read();
-                        os_tx.send((processed.entity_id, processed.dimension)).unwrap();
-                    }
-                }
-                match tx.send(packet) {
-                    Ok(..) => {}
-                    Err(..) => {
-                        // the other end is stopped and should have printed the error
-                        return;
-                    }
+        let out_tx = writer.into_channel();
+        let ticks = TickTimer::default();
+
+        let mut oneshot = Some(os_tx);
+        reader.spawn(username.clone(), IMMEDIATE, out_tx.clone(), ticks.clone(), tx, move |packet| {
+            if packet.id == clientbound::JoinGame::ID {
+                if let Some(os_tx) = oneshot.take() {
+                    let processed: JoinGame = packet.clone().read();
+                    os_tx.send((processed.entity_id, processed.dimension)).unwrap();
                 }
             }
         });
 
-        let tx = writer.into_channel();
+        let tx = out_tx;
 
-        let (entity_id, dimension) = os_rx.await.map_err(|_| err("disconnected before join game packet"))?;
+        let (entity_id, dimension) = os_rx.await.map_err(|_| err("disconnected before join game packet")).ctx_phase(&username, Phase::Join)?;
 
-        let out = Interface340::new(tx);
+        let out = Interface340::new(tx, chat, entity_id);
 
         let queue = EventQueue340 {
             rx,
@@ -486,6 +753,10 @@ impl Minecraft for Protocol {
             out: out.clone(),
             location: Default::default(),
             alive: true,
+            death_cause: None,
+            disconnects,
+            window: None,
+            ticks,
         };
 
         let login = Login {
@@ -501,3 +772,641 @@ impl Minecraft for Protocol {
         Ok(login)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use assert_matches::assert_matches;
+    use futures::FutureExt;
+
+    use swarm_bot_packets::read::ByteReader;
+    use swarm_bot_packets::types::{Packet, VarInt};
+    use swarm_bot_packets::write::ByteWriter;
+
+    use crate::client::bot::{ActionState, Bot};
+    use crate::client::recording::Recording;
+    use crate::client::tasks::delay::DelayTask;
+    use crate::client::processor::{BlockChanged, SimpleInterfaceIn};
+    use crate::client::state::global::GlobalState;
+    use crate::client::state::local::inventory::{ItemStack, Window};
+    use crate::client::state::local::LocalState;
+    use crate::client::tasks::deposit::DepositTask;
+    use crate::client::tasks::TaskTrait;
+    use crate::protocol::{EventQueue, Face, InterfaceOut};
+    use crate::protocol::chat_queue::ChatConfig;
+    use crate::protocol::disconnect::DisconnectPatterns;
+    use crate::protocol::io::writer::PacketWriteChannel;
+    use crate::protocol::tick::TickTimer;
+    use crate::protocol::v340::{clientbound, EventQueue340, Interface340, serverbound};
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+    use crate::storage::entities::EntityKind;
+    use crate::types::{Dimension, Direction, DirectionOrigin, Location, Origin, PacketData};
+
+    /// the ids of the packets the server received
+    fn received(rx: &mut tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>) -> Vec<u32> {
+        let mut ids = Vec::new();
+        while let Some(Some(packet)) = rx.recv().now_or_never() {
+            let mut reader = ByteReader::new(packet);
+            let _len: VarInt = reader.read();
+            let VarInt(id) = reader.read();
+            ids.push(id as u32);
+        }
+        ids
+    }
+
+    #[test]
+    fn test_movement_cadence() {
+        use serverbound::{PlayerLook, PlayerMovement, PlayerPosition, PlayerPositionAndRotation, TeleportConfirm};
+
+        let (tx, mut rx) = PacketWriteChannel::test();
+        let mut out = Interface340::new(tx, ChatConfig::default(), 1);
+
+        let mut location = Location::new(0.5, 64.0, 0.5);
+        let mut direction = Direction::default();
+
+        // join, then stand still for 40 ticks
+        for _ in 0..41 {
+            out.teleport_and_look(location, direction, true);
+        }
+        let mut expected = vec![PlayerPositionAndRotation::ID];
+        expected.extend([PlayerPosition::ID; 2]);
+        assert_eq!(received(&mut rx), expected);
+
+        // walk for 5 ticks
+        for _ in 0..5 {
+            location.x += 0.2;
+            out.teleport_and_look(location, direction, true);
+        }
+        assert_eq!(received(&mut rx), vec![PlayerPosition::ID; 5]);
+
+        // look around without moving, then jump
+        direction.yaw = 45.0;
+        out.teleport_and_look(location, direction, true);
+        out.teleport_and_look(location, direction, true);
+        out.teleport_and_look(location, direction, false);
+        assert_eq!(received(&mut rx), vec![PlayerLook::ID, PlayerMovement::ID]);
+
+        // a teleport from the server is confirmed and resynced right away and not repeated on the next tick
+        location = Location::new(100.0, 70.0, 100.0);
+        out.write(TeleportConfirm { teleport_id: VarInt(1) });
+        out.resync(location, DirectionOrigin { yaw: Origin::Rel(0.0), pitch: Origin::Rel(0.0) });
+        out.teleport_and_look(location, direction, false);
+        assert_eq!(received(&mut rx), vec![TeleportConfirm::ID, PlayerPositionAndRotation::ID]);
+    }
+
+    #[test]
+    fn test_chat_queue() {
+        let (tx, mut rx) = PacketWriteChannel::test();
+        let mut out = Interface340::new(tx, ChatConfig::default(), 1);
+
+        // nothing is written until the tick loop flushes the queue
+        out.send_chat("hello");
+        out.send_chat("there");
+        assert!(received(&mut rx).is_empty());
+
+        let now = Instant::now();
+        out.flush_chat(now);
+        out.flush_chat(now);
+        assert_eq!(received(&mut rx), vec![serverbound::ChatMessage::ID]);
+
+        out.flush_chat(now + Duration::from_secs(2));
+        assert_eq!(received(&mut rx), vec![serverbound::ChatMessage::ID]);
+    }
+
+    #[test]
+    fn test_budget() {
+        let (tx, mut rx) = PacketWriteChannel::test();
+        let mut out = Interface340::new(tx, ChatConfig::default(), 1);
+
+        // a task spamming places gets two out right away and the rest one every 4 ticks (5 a second)
+        for _ in 0..100 {
+            out.place_block(BlockLocation::new(0, 64, 0), Face::PosY);
+        }
+        assert_eq!(received(&mut rx), vec![serverbound::PlaceBlock::ID; 2]);
+        assert_eq!(out.deferred(), 98);
+
+        // the slot change waits behind the places it was made after
+        out.change_slot(3);
+        assert_eq!(out.deferred(), 99);
+
+        let mut sent_at = Vec::new();
+        for tick in 1..=400 {
+            out.flush_budget();
+            for id in received(&mut rx) {
+                sent_at.push((tick, id));
+            }
+        }
+
+        let places: Vec<_> = sent_at.iter().filter(|(_, id)| *id == serverbound::PlaceBlock::ID).map(|(tick, _)| *tick).collect();
+        assert_eq!(places.len(), 98);
+        assert_eq!(places[0], 4);
+        assert!(places.windows(2).all(|pair| pair[1] - pair[0] == 4));
+        assert_eq!(*places.last().unwrap(), 392);
+
+        assert_eq!(sent_at.last(), Some(&(392, serverbound::ChangeSlot::ID)));
+        assert_eq!(out.deferred(), 0);
+    }
+
+    type Queue = (std::sync::mpsc::Sender<PacketData>, EventQueue340, Interface340, tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>);
+
+    /// a queue which processes the packets sent to the returned channel. The packets it writes go to the receiver.
+    fn queue() -> Queue {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (out, packets) = Interface340::test();
+        let queue = EventQueue340 {
+            rx,
+            out: out.clone(),
+            location: Location::default(),
+            dimension: Dimension::Overworld,
+            alive: true,
+            death_cause: None,
+            disconnects: DisconnectPatterns::default(),
+            window: None,
+            ticks: TickTimer::default(),
+        };
+        (tx, queue, out, packets)
+    }
+
+    #[test]
+    fn test_block_changes() {
+        let (tx, mut queue, out, _packets) = queue();
+
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+        let mut local = LocalState::mock();
+        let mut actions = ActionState::default();
+        let mut out = out;
+
+        let stone = BlockState::STONE.0 as u8;
+        let cobblestone = (4 << 4) as u8;
+
+        // chunk (1, -1). Records are (x << 4 | z, y, state)
+        let mut multi = Vec::new();
+        multi.extend_from_slice(&1_i32.to_be_bytes());
+        multi.extend_from_slice(&(-1_i32).to_be_bytes());
+        multi.push(3);
+        multi.extend_from_slice(&[3 << 4 | 12, 5, stone]);
+        multi.extend_from_slice(&[15 << 4, 64, cobblestone]);
+        multi.extend_from_slice(&[15, 1, stone]);
+        tx.send(PacketData { id: clientbound::MultiBlock::ID, reader: ByteReader::new(multi) }).unwrap();
+
+        // back to air. The second time is another bot receiving the same change.
+        let position = |x: i64, y: i64, z: i64| (((x & 0x3FF_FFFF) << 38) | ((y & 0xFFF) << 26) | (z & 0x3FF_FFFF)) as u64;
+        for _ in 0..2 {
+            let mut single = position(19, 5, -4).to_be_bytes().to_vec();
+            single.push(0);
+            tx.send(PacketData { id: clientbound::BlockChange::ID, reader: ByteReader::new(single) }).unwrap();
+        }
+
+        let mut processor = SimpleInterfaceIn::new(&mut local, &mut actions, &mut global, &mut out);
+        queue.flush(&mut processor);
+
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(19, 5, -4)), Some(BlockState::AIR));
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(31, 64, -16)), Some(BlockState(cobblestone as u32)));
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(16, 1, -1)), Some(BlockState::STONE));
+
+        let changes: Vec<_> = global.block_changes().collect();
+        assert_eq!(changes, vec![
+            BlockChanged { location: BlockLocation::new(19, 5, -4), old: Some(BlockState::AIR), new: BlockState::STONE },
+            BlockChanged { location: BlockLocation::new(31, 64, -16), old: Some(BlockState::AIR), new: BlockState(cobblestone as u32) },
+            BlockChanged { location: BlockLocation::new(16, 1, -1), old: Some(BlockState::AIR), new: BlockState::STONE },
+            BlockChanged { location: BlockLocation::new(19, 5, -4), old: Some(BlockState::STONE), new: BlockState::AIR },
+        ]);
+        assert_eq!(global.block_changed(BlockLocation::new(19, 5, -4)).unwrap().new, BlockState::AIR);
+
+        // changes are forgotten after a tick
+        global.ticks += 2;
+        global.prune_changes();
+        assert_eq!(global.block_changes().count(), 0);
+    }
+
+    #[test]
+    fn test_explosion_and_falling_blocks() {
+        let (tx, mut queue, mut out, _packets) = queue();
+
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+        for location in [BlockLocation::new(2, 1, 2), BlockLocation::new(3, 1, 2), BlockLocation::new(2, 2, 2), BlockLocation::new(2, 1, 3)] {
+            global.blocks.set_block(location, BlockState::STONE);
+        }
+        let mut local = LocalState::mock();
+        let mut actions = ActionState::default();
+
+        // an explosion at (2.5, 1.5, 2.5) destroying three of the blocks
+        let mut explosion = Vec::new();
+        for value in [2.5_f32, 1.5, 2.5, 3.0] {
+            explosion.extend_from_slice(&value.to_be_bytes());
+        }
+        explosion.extend_from_slice(&3_i32.to_be_bytes());
+        for offset in [[0_i8, 0, 0], [1, 0, 0], [0, 1, 0]] {
+            explosion.extend(offset.iter().map(|&value| value as u8));
+        }
+        for value in [0.5_f32, 0.25, -0.5] {
+            explosion.extend_from_slice(&value.to_be_bytes());
+        }
+        tx.send(PacketData { id: clientbound::Explosion::ID, reader: ByteReader::new(explosion) }).unwrap();
+
+        // sand (id 12) stopping at y = 1 on the floor and gravel (id 13, metadata 0) destroyed mid-air
+        let falling = |id: u8, kind: i32, y: f64| {
+            let mut spawn = vec![id];
+            spawn.extend_from_slice(&[0; 16]);
+            spawn.push(clientbound::entity::ObjectSpawn::FALLING_BLOCK);
+            for value in [5.5, y, 5.5] {
+                spawn.extend_from_slice(&f64::to_be_bytes(value));
+            }
+            spawn.extend_from_slice(&[0, 0]);
+            spawn.extend_from_slice(&kind.to_be_bytes());
+            spawn.extend_from_slice(&[0; 6]);
+            PacketData { id: clientbound::entity::ObjectSpawn::ID, reader: ByteReader::new(spawn) }
+        };
+        tx.send(falling(7, 12, 0.9999)).unwrap();
+        tx.send(falling(8, 13, 4.0)).unwrap();
+        tx.send(PacketData { id: clientbound::entity::Destroy::ID, reader: ByteReader::new(vec![2, 7, 8]) }).unwrap();
+
+        let before = local.physics.velocity();
+        let mut processor = SimpleInterfaceIn::new(&mut local, &mut actions, &mut global, &mut out);
+        queue.flush(&mut processor);
+
+        for location in [BlockLocation::new(2, 1, 2), BlockLocation::new(3, 1, 2), BlockLocation::new(2, 2, 2)] {
+            assert_eq!(global.blocks.get_block_exact(location), Some(BlockState::AIR), "{}", location);
+        }
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(2, 1, 3)), Some(BlockState::STONE));
+
+        let pushed = local.physics.velocity() - before;
+        assert!((pushed.dx - 0.5).abs() < 1e-6);
+        assert!((pushed.dy - 0.25).abs() < 1e-6);
+        assert!((pushed.dz + 0.5).abs() < 1e-6);
+        assert!(!local.physics.on_ground());
+
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(5, 1, 5)), Some(BlockState::from(12, 0)));
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(5, 4, 5)), Some(BlockState::AIR));
+        assert!(global.entities.by_id(7).is_none());
+    }
+
+    #[test]
+    fn test_knockback() {
+        let (tx, mut queue, mut out, _packets) = queue();
+
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+        global.entities.put_entity(5, 0, Location::new(3.5, 1.0, 0.5), EntityKind::Normal);
+
+        let mut local = LocalState::mock();
+        let mut actions = ActionState::default();
+        local.physics.teleport(Location::new(0.5, 1.0, 0.5));
+        local.physics.tick(&mut global.blocks, &local.inventory);
+        assert!(local.physics.on_ground());
+
+        // (0.4, 0.36, -0.2) blocks per tick for us and something else for entity 5
+        let velocity = |id: u8, [x, y, z]: [i16; 3]| {
+            let mut data = vec![id];
+            for value in [x, y, z] {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            PacketData { id: clientbound::entity::Velocity::ID, reader: ByteReader::new(data) }
+        };
+        tx.send(velocity(5, [800, 0, 0])).unwrap();
+        tx.send(velocity(0, [3200, 2880, -1600])).unwrap();
+
+        let mut processor = SimpleInterfaceIn::new(&mut local, &mut actions, &mut global, &mut out);
+        queue.flush(&mut processor);
+
+        assert!((global.entities.by_id(5).unwrap().velocity.dx - 0.1).abs() < 1e-9);
+        assert_eq!(local.last_knockback.map(|(tick, _)| tick), Some(local.ticks));
+
+        // the first tick moves by the velocity, then drag slows it down and gravity pulls it down
+        let mut expected = local.physics.location();
+        let (mut dx, mut dy, mut dz) = (0.4, 0.36, -0.2);
+        for tick in 0..4 {
+            local.physics.tick(&mut global.blocks, &local.inventory);
+            expected.x += dx;
+            expected.y += dy;
+            expected.z += dz;
+
+            let location = local.physics.location();
+            assert!(location.dist2(expected) < 1e-9, "tick {}: {} instead of {}", tick, location, expected);
+
+            dx *= 0.91;
+            dz *= 0.91;
+            dy = (dy - 0.08) * 0.98;
+        }
+
+        // and it lands again
+        for _ in 0..20 {
+            local.physics.tick(&mut global.blocks, &local.inventory);
+        }
+        assert!(local.physics.on_ground());
+        assert!((local.physics.location().y - 1.0).abs() < 1e-6);
+    }
+
+    /// a chunk data packet of a column with only a bottom section, in which y = 0 is `floor` and the rest is air
+    fn chunk(x: i32, z: i32, floor: BlockState) -> PacketData {
+        let mut data = Vec::new();
+        data.extend_from_slice(&x.to_be_bytes());
+        data.extend_from_slice(&z.to_be_bytes());
+
+        // ground-up continuous, only section 0, the size (which is not read)
+        data.extend_from_slice(&[1, 1, 0]);
+
+        // 4 bits per block and a palette of air and the floor
+        data.extend_from_slice(&[4, 2, 0, floor.0 as u8]);
+
+        // 256 longs. The first 16 are the 256 blocks at y = 0.
+        data.extend_from_slice(&[0x80, 0x02]);
+        for _ in 0..16 {
+            data.extend_from_slice(&0x1111_1111_1111_1111_u64.to_be_bytes());
+        }
+        data.extend(std::iter::repeat(0).take(240 * 8));
+
+        // block and sky light
+        data.extend(std::iter::repeat(0).take(2048 * 2));
+        PacketData { id: clientbound::CHUNK_PKT_ID, reader: ByteReader::new(data) }
+    }
+
+    /// the server moving the player to `location`
+    fn position(location: Location) -> PacketData {
+        let mut data = Vec::new();
+        for value in [location.x, location.y, location.z] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&[0, 1]);
+        PacketData { id: clientbound::PlayerPositionAndLook::ID, reader: ByteReader::new(data) }
+    }
+
+    #[test]
+    fn test_refresh_chunks() {
+        let (tx, queue, out, _packets) = queue();
+
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+
+        // a block the server does not have
+        let ghost = BlockLocation::new(8, 2, 8);
+        global.blocks.set_block(ghost, BlockState::STONE);
+
+        let mut actions = ActionState::default();
+        actions.schedule(DelayTask::new(1000));
+
+        let mut bot = Bot {
+            state: LocalState::mock(),
+            actions,
+            queue,
+            out,
+            recording: Recording::off(),
+            last_task: None,
+            sneaking: false,
+            sprinting: false,
+            events: Vec::new(),
+            controller: None,
+        };
+
+        // the server keeps putting us where we think the ghost block is
+        for _ in 0..global.config.resync.threshold {
+            tx.send(position(Location::new(8.5, 1.0, 8.5))).unwrap();
+        }
+        tx.send(position(Location::new(13.5, 1.0, 8.5))).unwrap();
+
+        let mut processor = SimpleInterfaceIn::new(&mut bot.state, &mut bot.actions, &mut global, &mut bot.out);
+        bot.queue.flush(&mut processor);
+
+        // the current task is suspended while stepping across the chunk border at x = 16
+        let mut max_x = f64::MIN;
+        for _ in 0..100 {
+            bot.run_sync(&mut global);
+            max_x = max_x.max(bot.state.physics.location().x);
+        }
+
+        assert_matches!(bot.actions.current(), Some((_, "RefreshTask")));
+        assert!(max_x > 16.0, "did not cross the border ({})", max_x);
+        assert!(bot.state.physics.location().dist2(Location::new(13.5, 1.0, 8.5)) < 0.5 * 0.5);
+
+        let radius = global.config.resync.radius;
+        let columns: Vec<_> = (-radius..=radius).flat_map(|x| (-radius..=radius).map(move |z| ChunkLocation(x, z))).collect();
+        assert!(columns.iter().all(|&column| global.blocks.is_stale(column)));
+
+        // the server resends the columns
+        for &ChunkLocation(x, z) in &columns {
+            tx.send(chunk(x, z, BlockState(4 << 4))).unwrap();
+        }
+        let mut processor = SimpleInterfaceIn::new(&mut bot.state, &mut bot.actions, &mut global, &mut bot.out);
+        bot.queue.flush(&mut processor);
+
+        bot.run_sync(&mut global);
+        assert_matches!(bot.actions.current(), Some((_, "DelayTask")));
+
+        assert!(columns.iter().all(|&column| !global.blocks.is_stale(column)));
+        assert_eq!(global.blocks.get_block_exact(ghost), Some(BlockState::AIR));
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(-20, 0, 30)), Some(BlockState(4 << 4)));
+
+        // outside of the radius nothing changed
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(-50, 0, 0)), Some(BlockState::STONE));
+    }
+
+    #[test]
+    fn test_deposit() {
+        let (tx, mut queue, mut out, mut packets) = queue();
+
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+        let mut local = LocalState::mock();
+        let mut actions = ActionState::default();
+
+        let (wheat, seeds, cobblestone) = (BlockKind(296), BlockKind(295), BlockKind(4));
+        local.inventory.add(9, ItemStack::new(wheat, 64, 0, None));
+        local.inventory.add(10, ItemStack::new(seeds, 20, 0, None));
+        local.inventory.add(11, ItemStack::new(seeds, 64, 0, None));
+        local.inventory.add(12, ItemStack::new(cobblestone, 64, 0, None));
+
+        let chest = BlockLocation::new(2, 1, 0);
+        let mut task = DepositTask::new(chest, vec![wheat, seeds], Some(seeds));
+
+        // right clicking the chest does not do anything until the server opens it
+        assert!(!task.tick(&mut out, &mut local, &mut global));
+        assert!(!task.tick(&mut out, &mut local, &mut global));
+        assert_eq!(received(&mut packets), vec![serverbound::PlaceBlock::ID, serverbound::ArmAnimation::ID]);
+
+        let string = |value: &str| {
+            let mut bytes = vec![value.len() as u8];
+            bytes.extend_from_slice(value.as_bytes());
+            bytes
+        };
+        let mut open = vec![1];
+        open.extend(string("minecraft:chest"));
+        open.extend(string("{\"text\":\"Chest\"}"));
+        open.push(27);
+        tx.send(PacketData { id: clientbound::window::Open::ID, reader: ByteReader::new(open) }).unwrap();
+
+        let mut processor = SimpleInterfaceIn::new(&mut local, &mut actions, &mut global, &mut out);
+        queue.flush(&mut processor);
+        assert_eq!(local.window, Some(Window { id: 1, slots: 27 }));
+
+        // the wheat and the second stack of seeds. The first stack of seeds is kept to replant.
+        let mut clicked = Vec::new();
+        while !task.tick(&mut out, &mut local, &mut global) {
+            let mut packet = packets.recv().now_or_never().unwrap().map(ByteReader::new).unwrap();
+            let _len: VarInt = packet.read();
+            let VarInt(id) = packet.read();
+            assert_eq!(id as u32, serverbound::ClickWindow::ID);
+
+            let window: u8 = packet.read();
+            let slot: u16 = packet.read();
+            clicked.push((window, slot));
+        }
+
+        assert_eq!(clicked, vec![(1, 27), (1, 29)]);
+        assert_eq!(received(&mut packets), vec![serverbound::CloseWindow::ID]);
+        assert_eq!(local.window, None);
+
+        let kinds: Vec<_> = local.inventory.stored().map(|(idx, stack)| (idx, stack.kind)).collect();
+        assert_eq!(kinds, vec![(10, seeds), (12, cobblestone)]);
+
+        // the player slots of a chest window are the inventory slots
+        tx.send(PacketData { id: clientbound::window::Open::ID, reader: ByteReader::new(vec![2, 0, 0, 27]) }).unwrap();
+        tx.send(PacketData { id: clientbound::window::Set::ID, reader: ByteReader::new(vec![2, 0, 30, 0xFF, 0xFF]) }).unwrap();
+        let mut processor = SimpleInterfaceIn::new(&mut local, &mut actions, &mut global, &mut out);
+        queue.flush(&mut processor);
+        assert_eq!(local.inventory.stored().count(), 1);
+    }
+
+    #[test]
+    fn test_dimension_change() {
+        let (tx, mut queue, mut out, _packets) = queue();
+
+        let mut global = GlobalState::init();
+        global.blocks = WorldBlocks::flat();
+        global.entities.put_entity(5, 0, Location::new(2.5, 1.0, 0.5), EntityKind::Normal);
+        let mut local = LocalState::mock();
+        let mut actions = ActionState::default();
+        actions.schedule(DelayTask::new(1000));
+
+        let respawn = |dimension: i32| {
+            let mut bytes = dimension.to_be_bytes().to_vec();
+            bytes.extend_from_slice(&[0, 0, 7]);
+            bytes.extend_from_slice(b"default");
+            PacketData { id: clientbound::Respawn::ID, reader: ByteReader::new(bytes) }
+        };
+
+        tx.send(respawn(-1)).unwrap();
+        let mut processor = SimpleInterfaceIn::new(&mut local, &mut actions, &mut global, &mut out);
+        queue.flush(&mut processor);
+
+        // the overworld is not in the nether
+        assert_eq!(local.dimension, Dimension::Nether);
+        assert_eq!(global.dimension, Dimension::Nether);
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(0, 0, 0)), None);
+        assert!(global.entities.by_id(5).is_none());
+        assert!(actions.current().is_none());
+
+        // dying in the nether does not forget it
+        global.blocks.set_block(BlockLocation::new(0, 0, 0), BlockState(87 << 4));
+        tx.send(respawn(-1)).unwrap();
+        tx.send(respawn(0)).unwrap();
+        let mut processor = SimpleInterfaceIn::new(&mut local, &mut actions, &mut global, &mut out);
+        queue.flush(&mut processor);
+
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(0, 0, 0)), Some(BlockState::STONE));
+        assert_eq!(global.entities.by_id(5).unwrap().owner, None);
+
+        global.enter(Dimension::Nether);
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(0, 0, 0)), Some(BlockState(87 << 4)));
+
+        assert_eq!(Dimension::Nether.translate(Location::new(10.0, 64.0, -4.0), Dimension::Overworld), Location::new(80.0, 64.0, -32.0));
+    }
+
+    /// Player List Item doing `action` to every player, given by their uuid and the fields of the action
+    fn player_list(action: i32, players: &[(u128, Vec<u8>)]) -> PacketData {
+        let mut body = ByteWriter::new();
+        body.write(VarInt(action)).write(VarInt(players.len() as i32));
+        for (uuid, fields) in players {
+            body.write(*uuid).write(&fields[..]);
+        }
+        PacketData { id: clientbound::PlayerListItem::ID, reader: ByteReader::new(body.freeze()) }
+    }
+
+    /// the fields of adding a survival player without properties or a display name
+    fn added(name: &str, ping: i32) -> Vec<u8> {
+        let mut fields = ByteWriter::new();
+        fields.write(name.to_string()).write(VarInt(0)).write(VarInt(0)).write(VarInt(ping)).write(false);
+        fields.freeze()
+    }
+
+    fn var_int(value: i32) -> Vec<u8> {
+        let mut fields = ByteWriter::new();
+        fields.write(VarInt(value));
+        fields.freeze()
+    }
+
+    #[test]
+    fn test_player_list() {
+        let (tx, mut queue, mut out, _packets) = queue();
+
+        let mut global = GlobalState::init();
+        global.config.panic.names = vec!["dinnerbone".to_string()];
+        let mut local = LocalState::mock();
+        let mut actions = ActionState::default();
+        actions.schedule(DelayTask::new(1000));
+
+        // the list a bot gets on login, then a new ping for jeb_ and Notch switching to creative
+        tx.send(player_list(0, &[(1, added("Notch", 40)), (2, added("jeb_", 80))])).unwrap();
+        tx.send(player_list(2, &[(2, var_int(120))])).unwrap();
+        tx.send(player_list(1, &[(1, var_int(1))])).unwrap();
+        queue.flush(&mut SimpleInterfaceIn::new(&mut local, &mut actions, &mut global, &mut out));
+
+        let players: Vec<_> = global.players.iter().map(|player| (player.name.as_str(), player.ping, player.gamemode)).collect();
+        assert_eq!(players, vec![("Notch", 40, 1), ("jeb_", 120, 0)]);
+        assert_eq!(global.player_by_name("JEB_").map(|player| player.uuid), Some(2));
+        assert_eq!(global.players.mean_ping(), Some(80));
+        assert!(actions.current().is_some());
+
+        // Notch leaves and Dinnerbone of the panic list joins
+        tx.send(player_list(4, &[(1, Vec::new())])).unwrap();
+        tx.send(player_list(0, &[(3, added("Dinnerbone", 20))])).unwrap();
+        queue.flush(&mut SimpleInterfaceIn::new(&mut local, &mut actions, &mut global, &mut out));
+
+        let names: Vec<_> = global.players.iter().map(|player| player.name.as_str()).collect();
+        assert_eq!(names, vec!["jeb_", "Dinnerbone"]);
+        assert!(global.player_by_name("Notch").is_none());
+        assert!(actions.current().is_none());
+        assert!(!local.disconnected);
+
+        // another bot told about the same join leaves the server as well
+        global.config.panic.disconnect = true;
+        tx.send(player_list(0, &[(3, added("Dinnerbone", 20))])).unwrap();
+        queue.flush(&mut SimpleInterfaceIn::new(&mut local, &mut actions, &mut global, &mut out));
+
+        assert_eq!(global.players.len(), 2);
+        assert!(local.disconnected);
+    }
+
+    #[test]
+    fn test_creative_slot() {
+        let (mut out, mut rx) = Interface340::test();
+        let mut local = LocalState::mock();
+
+        // a diamond sword in the first hotbar slot and the item of wheat crops, the seeds
+        local.inventory.conjure(36, 276, 1, &mut out);
+        assert!(local.inventory.hold_or_conjure(|_| false, BlockKind(59), true, &mut out));
+        assert!(!local.inventory.hold_or_conjure(|_| false, BlockKind(59), false, &mut out));
+
+        let mut slots = Vec::new();
+        while let Some(Some(packet)) = rx.recv().now_or_never() {
+            let mut reader = ByteReader::new(packet);
+            let _len: VarInt = reader.read();
+            let VarInt(id) = reader.read();
+            assert_eq!(id, serverbound::CreativeInventoryAction::ID as i32);
+
+            let slot: u16 = reader.read();
+            let item: i16 = reader.read();
+            let count: u8 = reader.read();
+            let damage: u16 = reader.read();
+            let nbt: u8 = reader.read();
+            slots.push((slot, item, count, damage, nbt));
+        }
+
+        assert_eq!(slots, vec![(36, 276, 1, 0, 0), (36, 295, 64, 0, 0)]);
+        assert_eq!(local.inventory.current().map(|stack| (stack.kind, stack.count)), Some((BlockKind(295), 64)));
+    }
+}
@@ -0,0 +1,651 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A minimal 1.12.2 server for tests. It logs a client in (offline mode), sends it the chunks of a world and where it
+//! spawns, and records every packet the client sends afterwards.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::net::tcp::OwnedWriteHalf;
+
+use swarm_bot_packets::types::{Packet, VarInt};
+use swarm_bot_packets::write::ByteWriter;
+
+use crate::bootstrap::{Address, Connection, Proxy, ProxyKind};
+use crate::bootstrap::mojang::{Mojang, MojangConfig};
+use crate::bootstrap::proxy::ProxyPool;
+use crate::bootstrap::storage::ValidUser;
+use crate::bootstrap::tunnel::Tunnel;
+use crate::chat::Component;
+use crate::error::{err, Res};
+use crate::protocol::chat_queue::ChatConfig;
+use crate::protocol::disconnect::DisconnectPatterns;
+use crate::protocol::io::reader::PacketReader;
+use crate::protocol::v340::{clientbound, serverbound};
+use crate::storage::block::{BlockLocation, BlockState};
+use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+use crate::storage::sign::SignText;
+use crate::types::{Location, PacketData};
+
+/// the entity id of the player
+pub const ENTITY_ID: u32 = 42;
+
+/// the teleport id of the spawn position
+pub const SPAWN_TELEPORT: i32 = 1;
+
+/// the direct (global) palette of 1.12 uses 13 bits per block
+const DIRECT_BITS: usize = 13;
+
+/// the view distance of the last Client Settings in `packets`. None if the client did not send any.
+pub fn view_distance(packets: &[PacketData]) -> Option<u8> {
+    packets.iter().rev()
+        .find(|packet| packet.id == serverbound::ClientSettings::ID)
+        .map(|packet| {
+            let mut reader = packet.reader.clone();
+            let _locale: String = reader.read();
+            reader.read()
+        })
+}
+
+/// The packets the client sent since it logged in, in order. Clones share the packets.
+#[derive(Clone, Default)]
+pub struct Received {
+    packets: Arc<Mutex<Vec<PacketData>>>,
+}
+
+impl Received {
+    pub fn packets(&self) -> Vec<PacketData> {
+        self.packets.lock().unwrap().clone()
+    }
+
+    pub fn ids(&self) -> Vec<u32> {
+        self.packets.lock().unwrap().iter().map(|packet| packet.id).collect()
+    }
+
+    /// the view distance the client last asked for
+    pub fn view_distance(&self) -> Option<u8> {
+        view_distance(&self.packets.lock().unwrap())
+    }
+
+    /// Block until `done` holds for the packets received so far. False if it did not within `timeout`.
+    pub fn wait(&self, timeout: Duration, done: impl Fn(&[PacketData]) -> bool) -> bool {
+        let start = Instant::now();
+        loop {
+            if done(&self.packets.lock().unwrap()) {
+                return true;
+            }
+            if start.elapsed() > timeout {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
+
+pub struct TestServer {
+    listener: TcpListener,
+    world: WorldBlocks,
+
+    /// the columns of the world which are sent on join
+    chunks: Vec<ChunkLocation>,
+    spawn: Location,
+}
+
+/// A client which is logged in. Packets can be sent to it while the ones it sends are recorded.
+pub struct Session {
+    writer: OwnedWriteHalf,
+    pub protocol_version: i32,
+    pub username: String,
+    pub received: Received,
+}
+
+/// `uuid` in the 8-4-4-4-12 format of Login Success
+fn hyphenated(uuid: u128) -> String {
+    let hex = format!("{:032x}", uuid);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// the packet length and id in front of `body`, uncompressed
+fn frame(id: u32, body: ByteWriter) -> Vec<u8> {
+    let mut packet = ByteWriter::new();
+    packet.write(VarInt(id as i32)).write(&body.freeze()[..]);
+    let packet = packet.freeze();
+
+    let mut frame = ByteWriter::new();
+    frame.write(VarInt(packet.len() as i32)).write(&packet[..]);
+    frame.freeze()
+}
+
+/// a sign block entity as vanilla stores it, the lines being chat json
+#[derive(Serialize)]
+struct SignEntity {
+    id: &'static str,
+    x: i32,
+    y: i32,
+    z: i32,
+
+    #[serde(rename = "Text1")]
+    text1: String,
+
+    #[serde(rename = "Text2")]
+    text2: String,
+
+    #[serde(rename = "Text3")]
+    text3: String,
+
+    #[serde(rename = "Text4")]
+    text4: String,
+}
+
+impl SignEntity {
+    fn nbt(location: BlockLocation, text: &SignText) -> Vec<u8> {
+        let line = |idx: usize| serde_json::to_string(&Component::text(text[idx].clone())).unwrap();
+        let entity = SignEntity {
+            id: "minecraft:sign",
+            x: location.x,
+            y: location.y as i32,
+            z: location.z,
+            text1: line(0),
+            text2: line(1),
+            text3: line(2),
+            text4: line(3),
+        };
+
+        let mut data = Vec::new();
+        nbt::to_writer(&mut data, &entity, None).unwrap();
+        data
+    }
+}
+
+/// a Chunk Data packet of the whole column with full light. Sections which are only air are left out.
+fn chunk_column(world: &WorldBlocks, location: ChunkLocation) -> ByteWriter {
+    let ChunkLocation(chunk_x, chunk_z) = location;
+
+    let mut sections = ByteWriter::new();
+    let mut bitmask = 0;
+    for section in 0..16_i16 {
+        let mut states = [BlockState::AIR; 4096];
+        for (idx, state) in states.iter_mut().enumerate() {
+            let x = (chunk_x << 4) + (idx & 15) as i32;
+            let z = (chunk_z << 4) + ((idx >> 4) & 15) as i32;
+            let y = (section << 4) + (idx >> 8) as i16;
+            *state = world.get_block_exact(BlockLocation::new(x, y, z)).unwrap_or(BlockState::AIR);
+        }
+
+        if states.iter().all(|&state| state == BlockState::AIR) {
+            continue;
+        }
+        bitmask |= 1 << section;
+
+        // a value can start in one long and end in the next
+        let mut storage = [0_u64; 4096 * DIRECT_BITS / 64];
+        for (idx, state) in states.iter().enumerate() {
+            let value = state.0 as u64;
+            let start = idx * DIRECT_BITS / 64;
+            let offset = idx * DIRECT_BITS % 64;
+            storage[start] |= value << offset;
+            if offset + DIRECT_BITS > 64 {
+                storage[start + 1] |= value >> (64 - offset);
+            }
+        }
+
+        // no palette entries as the palette is direct
+        sections.write(DIRECT_BITS as u8).write(VarInt(0)).write(VarInt(storage.len() as i32));
+        for &long in storage.iter() {
+            sections.write(long);
+        }
+
+        // block and sky light
+        sections.write(&[0xFF_u8; 2048][..]).write(&[0xFF_u8; 2048][..]);
+    }
+
+    // every biome is plains
+    sections.write(&[1_u8; 256][..]);
+    let data = sections.freeze();
+
+    let signs: Vec<_> = world.signs()
+        .filter(|(sign, _)| ChunkLocation::from(*sign) == location)
+        .collect();
+
+    let mut column = ByteWriter::new();
+    column.write(&chunk_x.to_be_bytes()[..])
+        .write(&chunk_z.to_be_bytes()[..])
+        .write(true)
+        .write(VarInt(bitmask))
+        .write(VarInt(data.len() as i32))
+        .write(&data[..])
+        .write(VarInt(signs.len() as i32));
+
+    for (sign, text) in signs {
+        column.write(&SignEntity::nbt(sign, text)[..]);
+    }
+    column
+}
+
+impl TestServer {
+    /// Listen on a free port of localhost. A client is sent the `chunks` of `world` and spawns at `spawn`.
+    pub async fn bind(world: WorldBlocks, chunks: Vec<ChunkLocation>, spawn: Location) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        TestServer {
+            listener,
+            world,
+            chunks,
+            spawn,
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().unwrap().port()
+    }
+
+    /// A connection of `username` to the server, like [`Connection::connect`] without going through a proxy. The proxy
+    /// of the Mojang client is never used as the server is in offline mode.
+    pub async fn connect(&self, username: &str, uuid: u128) -> Connection {
+        let port = self.port();
+        let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let (read, write) = Tunnel::Tcp(stream).into_split();
+
+        let proxy = Proxy { kind: ProxyKind::Socks5, host: "127.0.0.1".to_string(), port: 1080, user: String::new(), pass: String::new() };
+        let lease = ProxyPool::new(vec![proxy]).assign(username).unwrap();
+
+        Connection {
+            user: ValidUser {
+                email: format!("{}@localhost", username),
+                username: username.to_string(),
+                last_checked: 0,
+                uuid: format!("{:032x}", uuid),
+                access_id: String::new(),
+                client_id: String::new(),
+                refresh_token: None,
+                obtained: 0,
+            },
+            address: Address { host: "127.0.0.1".to_string(), port },
+            mojang: Mojang::proxied(&lease, &MojangConfig::default()).unwrap(),
+            read,
+            write,
+            chat: ChatConfig::default(),
+            disconnects: DisconnectPatterns::default(),
+        }
+    }
+
+    /// Accept a client and log it in: an offline mode login, Join Game, the chunks and the spawn position. From Login
+    /// Success on every packet of the client is recorded.
+    pub async fn accept(self) -> Res<Session> {
+        let (stream, _) = self.listener.accept().await?;
+        let (read, writer) = stream.into_split();
+        let mut reader = PacketReader::from(read);
+
+        let mut handshake = reader.read().await?;
+        if handshake.id != 0 {
+            return Err(err("expected a handshake"));
+        }
+        let VarInt(protocol_version) = handshake.read();
+
+        let mut login_start = reader.read().await?;
+        if login_start.id != 0 {
+            return Err(err("expected login start"));
+        }
+        let username: String = login_start.read();
+
+        let mut session = Session {
+            writer,
+            protocol_version,
+            username: username.clone(),
+            received: Received::default(),
+        };
+
+        // offline mode skips encryption and the uuid does not matter
+        let mut success = ByteWriter::new();
+        success.write(hyphenated(0)).write(username);
+        session.send(clientbound::LoginSuccess::ID, success).await?;
+
+        let received = session.received.clone();
+        tokio::task::spawn(async move {
+            while let Ok(packet) = reader.read().await {
+                received.packets.lock().unwrap().push(packet);
+            }
+        });
+
+        // survival in the overworld on normal difficulty
+        let mut join = ByteWriter::new();
+        join.write(&ENTITY_ID.to_be_bytes()[..])
+            .write(0_u8)
+            .write(&0_i32.to_be_bytes()[..])
+            .write(2_u8)
+            .write(20_u8)
+            .write(String::from("default"))
+            .write(false);
+        session.send(clientbound::JoinGame::ID, join).await?;
+
+        for &location in &self.chunks {
+            session.send(clientbound::CHUNK_PKT_ID, chunk_column(&self.world, location)).await?;
+        }
+
+        session.teleport(self.spawn, SPAWN_TELEPORT).await?;
+        Ok(session)
+    }
+}
+
+impl Session {
+    pub async fn send(&mut self, id: u32, body: ByteWriter) -> Res {
+        self.writer.write_all(&frame(id, body)).await?;
+        Ok(())
+    }
+
+    pub async fn keep_alive(&mut self, id: u64) -> Res {
+        let mut body = ByteWriter::new();
+        body.write(id);
+        self.send(clientbound::KeepAlive::ID, body).await
+    }
+
+    /// Send the columns of `world` which a server sends a player in `center`: the ones within the view distance the
+    /// client asked for, or within `default` if it did not ask. Returns how many were sent.
+    pub async fn send_view(&mut self, world: &WorldBlocks, center: ChunkLocation, default: u8) -> Res<usize> {
+        let distance = self.received.view_distance().unwrap_or(default) as i32;
+        let ChunkLocation(center_x, center_z) = center;
+
+        let mut sent = 0;
+        for x in center_x - distance..=center_x + distance {
+            for z in center_z - distance..=center_z + distance {
+                self.send(clientbound::CHUNK_PKT_ID, chunk_column(world, ChunkLocation(x, z))).await?;
+                sent += 1;
+            }
+        }
+        Ok(sent)
+    }
+
+    /// change the text of the sign at `location` (Update Block Entity)
+    pub async fn update_sign(&mut self, location: BlockLocation, text: &SignText) -> Res {
+        let mut body = ByteWriter::new();
+        body.write(location)
+            .write(clientbound::UpdateBlockEntity::SET_SIGN_TEXT)
+            .write(&SignEntity::nbt(location, text)[..]);
+        self.send(clientbound::UpdateBlockEntity::ID, body).await
+    }
+
+    /// move the player to `location` (absolute, looking north)
+    pub async fn teleport(&mut self, location: Location, teleport_id: i32) -> Res {
+        let mut body = ByteWriter::new();
+        body.write(location.x).write(location.y).write(location.z)
+            .write(0.0_f32)
+            .write(0.0_f32)
+            .write(0_u8)
+            .write(VarInt(teleport_id));
+        self.send(clientbound::PlayerPositionAndLook::ID, body).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use more_asserts::*;
+    use tokio::runtime::Runtime;
+
+    use swarm_bot_packets::types::{Packet, VarInt};
+
+    use crate::client::bot::{ActionState, Bot};
+    use crate::client::processor::SimpleInterfaceIn;
+    use crate::client::recording::Recording;
+    use crate::client::state::global::GlobalState;
+    use crate::client::state::local::inventory::ItemStack;
+    use crate::client::state::local::LocalState;
+    use crate::client::tasks::delay::DelayTask;
+    use crate::client::tasks::navigate::BlockTravelTask;
+    use crate::client::tasks::TaskTrait;
+    use crate::client::tasks::write_book::WriteBookTask;
+    use crate::client::view::ViewConfig;
+    use crate::protocol::{EventQueue, Login, Minecraft};
+    use crate::protocol::v340::{EventQueue340, Interface340, Protocol, serverbound};
+    use crate::protocol::v340::server::{ENTITY_ID, Session, SPAWN_TELEPORT, TestServer, view_distance};
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+    use crate::storage::sign::SignText;
+    use crate::storage::tile_entity::TileEntity;
+    use crate::types::{Location, PacketData, Slot};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    const UUID: u128 = 0x069a79f444e94726a5befca90e38aaf5;
+
+    fn spawn() -> Location {
+        Location::new(0.5, 1.0, 0.5)
+    }
+
+    fn runtime() -> Runtime {
+        tokio::runtime::Builder::new_multi_thread().worker_threads(2).enable_all().build().unwrap()
+    }
+
+    /// log in to a server with the flat world in the 5x5 columns around the spawn
+    fn join(rt: &Runtime) -> (Login<EventQueue340, Interface340>, Session) {
+        join_world(rt, WorldBlocks::flat())
+    }
+
+    /// log in to a server with `world` in the 5x5 columns around the spawn
+    fn join_world(rt: &Runtime, world: WorldBlocks) -> (Login<EventQueue340, Interface340>, Session) {
+        rt.block_on(async {
+            let chunks = (-2..=2).flat_map(|x| (-2..=2).map(move |z| ChunkLocation(x, z))).collect();
+            let server = TestServer::bind(world, chunks, spawn()).await;
+            let conn = server.connect("bot", UUID).await;
+
+            let session = tokio::task::spawn(server.accept());
+            let login = Protocol::login(conn).await.unwrap();
+            (login, session.await.unwrap().unwrap())
+        })
+    }
+
+    /// process packets until the bot is at the spawn. The chunks are sent before it.
+    fn spawned(login: Login<EventQueue340, Interface340>) -> (Bot<EventQueue340, Interface340>, GlobalState) {
+        let Login { queue, out, info } = login;
+        let mut bot = Bot {
+            state: LocalState::new(0, info),
+            actions: ActionState::default(),
+            queue,
+            out,
+            recording: Recording::off(),
+            last_task: None,
+            sneaking: false,
+            sprinting: false,
+            events: Vec::new(),
+            controller: None,
+        };
+        let mut global = GlobalState::init();
+
+        let start = Instant::now();
+        while bot.state.physics.location() != spawn() {
+            assert_lt!(start.elapsed(), TIMEOUT, "never spawned");
+            let mut processor = SimpleInterfaceIn::new(&mut bot.state, &mut bot.actions, &mut global, &mut bot.out);
+            bot.queue.flush(&mut processor);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        (bot, global)
+    }
+
+    /// where the client told the server it is
+    fn positions(packets: &[PacketData]) -> Vec<Location> {
+        packets.iter()
+            .filter(|packet| packet.id == serverbound::PlayerPosition::ID || packet.id == serverbound::PlayerPositionAndRotation::ID)
+            .map(|packet| packet.reader.clone().read())
+            .collect()
+    }
+
+    #[test]
+    fn test_login() {
+        let rt = runtime();
+        let (login, session) = join(&rt);
+
+        assert_eq!(login.info.username, "bot");
+        assert_eq!(login.info.uuid.0, UUID);
+        assert_eq!(login.info.entity_id, ENTITY_ID);
+        assert_eq!(session.protocol_version, 340);
+        assert_eq!(session.username, "bot");
+
+        let (_bot, global) = spawned(login);
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(-32, 0, 47)), Some(BlockState::STONE));
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(0, 1, 0)), Some(BlockState::AIR));
+        assert_eq!(global.blocks.get_block_exact(BlockLocation::new(48, 0, 0)), None);
+
+        // the spawn position is accepted
+        let confirmed = session.received.wait(TIMEOUT, |packets| {
+            packets.iter().any(|packet| packet.id == serverbound::TeleportConfirm::ID && packet.reader.clone().read::<VarInt>().0 == SPAWN_TELEPORT)
+        });
+        assert!(confirmed, "received {:?}", session.received.ids());
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let rt = runtime();
+        let (_login, mut session) = join(&rt);
+
+        // answered by the reader, the game loop does not run at all
+        rt.block_on(session.keep_alive(77)).unwrap();
+        let answered = session.received.wait(TIMEOUT, |packets| {
+            packets.iter().any(|packet| packet.id == serverbound::KeepAlive::ID && packet.reader.clone().read::<u64>() == 77)
+        });
+        assert!(answered, "received {:?}", session.received.ids());
+    }
+
+    #[test]
+    fn test_goto() {
+        let rt = runtime();
+        let (login, session) = join(&rt);
+        let (mut bot, mut global) = spawned(login);
+
+        let goal = BlockLocation::new(6, 1, 4);
+        bot.actions.schedule(BlockTravelTask::new(goal, &bot.state));
+
+        for _ in 0..20 * 20 {
+            let mut processor = SimpleInterfaceIn::new(&mut bot.state, &mut bot.actions, &mut global, &mut bot.out);
+            bot.queue.flush(&mut processor);
+            bot.run_sync(&mut global);
+            bot.actions.expensive(Instant::now() + Duration::from_millis(20), &mut bot.state, &global);
+
+            if bot.actions.current().is_none() {
+                break;
+            }
+        }
+        assert!(bot.actions.current().is_none(), "stuck at {}", bot.state.physics.location());
+
+        let arrived = |location: &Location| location.dist2(goal.center_bottom()) < 1.0;
+        assert!(session.received.wait(TIMEOUT, |packets| positions(packets).last().map_or(false, |location| arrived(location))));
+
+        // walking from the spawn without the server having to correct anything
+        let positions = positions(&session.received.packets());
+        assert_gt!(positions.len(), 5);
+        assert_lt!(positions[0].dist2(spawn()), 0.01);
+        for pair in positions.windows(2) {
+            assert_lt!(pair[0].dist2(pair[1]), 1.0, "jumped from {} to {}", pair[0], pair[1]);
+            assert_ge!(pair[1].y, 1.0);
+        }
+    }
+
+    fn sign(lines: [&str; 4]) -> SignText {
+        [lines[0].to_string(), lines[1].to_string(), lines[2].to_string(), lines[3].to_string()]
+    }
+
+    #[test]
+    fn test_signs() {
+        let rt = runtime();
+
+        let location = BlockLocation::new(3, 1, 2);
+        let mut world = WorldBlocks::flat();
+        world.set_block(location, BlockState::from(BlockKind::STANDING_SIGN.id(), 0));
+        world.set_tile_entity(location, TileEntity::Sign(sign(["home", "", "100 64 -20", ""])));
+
+        let (login, mut session) = join_world(&rt, world);
+        let (mut bot, mut global) = spawned(login);
+        assert_eq!(global.blocks.sign_text(location), Some(sign(["home", "", "100 64 -20", ""])));
+
+        // edited while the bot is there
+        let moved = sign(["moved", "to", "-5 70 9", ""]);
+        rt.block_on(session.update_sign(location, &moved)).unwrap();
+
+        let start = Instant::now();
+        while global.blocks.sign_text(location).as_ref() != Some(&moved) {
+            assert_lt!(start.elapsed(), TIMEOUT, "sign never changed");
+            let mut processor = SimpleInterfaceIn::new(&mut bot.state, &mut bot.actions, &mut global, &mut bot.out);
+            bot.queue.flush(&mut processor);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_sign_book() {
+        let rt = runtime();
+        let (login, session) = join(&rt);
+        let (mut bot, mut global) = spawned(login);
+
+        bot.state.inventory.hotbar_mut()[3] = Some(ItemStack::new(BlockKind::WRITABLE_BOOK, 1, 0, None));
+
+        let pages = vec!["day 1".to_string(), "found a village".to_string()];
+        let mut task = WriteBookTask::new(pages.clone(), Some("log".to_string()));
+        assert!(task.tick(&mut bot.out, &mut bot.state, &mut global));
+
+        let signed = session.received.wait(TIMEOUT, |packets| packets.iter().any(|packet| packet.id == serverbound::BookMessage::ID));
+        assert!(signed, "received {:?}", session.received.ids());
+
+        let packets = session.received.packets();
+        let held = packets.iter().position(|packet| packet.id == serverbound::ChangeSlot::ID).expect("book not held");
+        let message = packets.iter().position(|packet| packet.id == serverbound::BookMessage::ID).unwrap();
+        assert_lt!(held, message);
+
+        let mut reader = packets[message].reader.clone();
+        let channel: String = reader.read();
+        let book: Slot = reader.read();
+        assert_eq!(channel, serverbound::BookMessage::SIGN);
+        assert_eq!(book.block_id, BlockKind::WRITABLE_BOOK.id() as i16);
+
+        let nbt = book.nbt.unwrap();
+        assert_eq!(nbt.pages, Some(pages));
+        assert_eq!(nbt.title.as_deref(), Some("log"));
+        assert_eq!(nbt.author.as_deref(), Some("bot"));
+    }
+
+    #[test]
+    fn test_view_distance() {
+        let rt = runtime();
+        let (login, mut session) = join(&rt);
+        let (mut bot, mut global) = spawned(login);
+        global.config.view = ViewConfig { distance: 3, idle: Some(1), idle_after: 10, keep_margin: 1 };
+
+        let world = WorldBlocks::flat();
+        let center = ChunkLocation(0, 0);
+
+        // a task runs, so the bot sees the whole distance
+        bot.actions.schedule(DelayTask::new(5));
+        bot.run_sync(&mut global);
+        assert!(session.received.wait(TIMEOUT, |packets| view_distance(packets) == Some(3)));
+        let busy = rt.block_on(session.send_view(&world, center, 10)).unwrap();
+        assert_eq!(busy, 7 * 7);
+
+        // idle for a while
+        for _ in 0..30 {
+            bot.run_sync(&mut global);
+        }
+        assert!(session.received.wait(TIMEOUT, |packets| view_distance(packets) == Some(1)));
+        let idle = rt.block_on(session.send_view(&world, center, 10)).unwrap();
+        assert_eq!(idle, 3 * 3);
+
+        // the settings are only sent when they change
+        let settings = session.received.ids().into_iter().filter(|&id| id == serverbound::ClientSettings::ID).count();
+        assert_eq!(settings, 2);
+    }
+}
@@ -14,6 +14,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use serde::{Deserialize, Serialize};
 use swarm_bot_packets::*;
 use swarm_bot_packets::types::VarInt;
 use swarm_bot_packets::write::{ByteWritable, ByteWriter};
@@ -66,6 +67,40 @@ pub struct ClientStatus {
 }
 
 
+/// The settings of the client. The server sends the chunks within `view_distance` of the player (capped at its own
+/// view distance).
+#[derive(Writable, Packet)]
+#[packet(0x04, Play)]
+pub struct ClientSettings {
+    pub locale: String,
+    pub view_distance: u8,
+
+    /// 0 shows all chat
+    pub chat_mode: VarInt,
+    pub chat_colors: bool,
+
+    /// a bit for each part of the skin which is shown
+    pub skin_parts: u8,
+
+    /// 1 is the right hand
+    pub main_hand: VarInt,
+}
+
+impl ClientSettings {
+    /// the settings of a vanilla client seeing `view_distance` chunks
+    pub fn new(view_distance: u8) -> ClientSettings {
+        ClientSettings {
+            locale: "en_US".to_string(),
+            view_distance,
+            chat_mode: VarInt(0),
+            chat_colors: true,
+            skin_parts: 0x7F,
+            main_hand: VarInt(1),
+        }
+    }
+}
+
+
 #[derive(Writable, Packet)]
 #[packet(0x0d, Play)]
 pub struct PlayerPosition {
@@ -107,6 +142,26 @@ pub struct ClickWindow {
     pub clicked: Slot,
 }
 
+#[derive(Writable, Packet)]
+#[packet(0x08, Play)]
+pub struct CloseWindow {
+    pub window_id: u8,
+}
+
+
+/// A plugin message with the book in the main hand. The channel is [`BookMessage::EDIT`] to save the pages or
+/// [`BookMessage::SIGN`] to also sign it.
+#[derive(Writable, Packet)]
+#[packet(0x09, Play)]
+pub struct BookMessage {
+    pub channel: String,
+    pub book: Slot,
+}
+
+impl BookMessage {
+    pub const EDIT: &str = "MC|BEdit";
+    pub const SIGN: &str = "MC|BSign";
+}
 
 #[derive(Writable, Default)]
 pub struct BlockCursor {
@@ -153,11 +208,20 @@ pub struct EntityAction {
     pub jump_boost: VarInt,
 }
 
+/// start or stop flying. The server ignores the speeds.
 #[derive(Writable, Packet)]
-#[packet(0x15, Play)]
+#[packet(0x13, Play)]
+pub struct PlayerAbilities {
+    pub flags: u8,
+    pub fly_speed: f32,
+    pub walk_speed: f32,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x0c, Play)]
 pub struct PlayerMovement {
     /// True if the client is on the ground, false otherwise.
-    on_ground: bool,
+    pub on_ground: bool,
 }
 
 #[derive(Writable, Packet)]
@@ -197,6 +261,14 @@ impl PlayerDig {
     }
 }
 
+/// Set a slot of the player window to any item, only in creative mode
+#[derive(Writable, Packet)]
+#[packet(0x1b, Play)]
+pub struct CreativeInventoryAction {
+    pub slot: u16,
+    pub clicked: Slot,
+}
+
 pub type ChangeSlot = HeldItemChange;
 
 #[derive(Writable, Packet)]
@@ -228,8 +300,7 @@ pub struct KeepAlive {
 }
 
 
-#[derive(EnumWritable, Debug)]
-#[allow(dead_code)]
+#[derive(EnumWritable, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Hand {
     Main,
     Off,
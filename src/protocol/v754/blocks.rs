@@ -0,0 +1,211 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Translation of 1.16.5 (global palette) block states to the 1.12 `id << 4 | meta` states the rest of the bot uses.
+//!
+//! Only the blocks which also exist in 1.12 and are commonly found while walking around are mapped. Anything else
+//! becomes bedrock so the pathfinder treats it as solid and does not try to mine it.
+
+use crate::storage::block::BlockState;
+
+#[derive(Copy, Clone)]
+enum Meta {
+    /// every state in the range has the same metadata
+    Fixed(u8),
+
+    /// the metadata is the offset in the range
+    Seq,
+
+    /// the metadata is the offset in the range divided by the number of states of each variant
+    Div(u32),
+}
+
+use Meta::*;
+
+const UNKNOWN: BlockState = BlockState(7 << 4);
+
+/// (first state, last state, 1.12 id, metadata). Sorted and non-overlapping.
+const TABLE: &[(u32, u32, u32, Meta)] = &[
+    (0, 0, 0, Fixed(0)), // air
+    (1, 7, 1, Seq), // stone, granite, diorite, andesite (+ polished)
+    (8, 9, 2, Fixed(0)), // grass block
+    (10, 10, 3, Fixed(0)), // dirt
+    (11, 11, 3, Fixed(1)), // coarse dirt
+    (12, 13, 3, Fixed(2)), // podzol
+    (14, 14, 4, Fixed(0)), // cobblestone
+    (15, 20, 5, Seq), // planks
+    (21, 32, 6, Div(2)), // saplings
+    (33, 33, 7, Fixed(0)), // bedrock
+    (34, 49, 9, Seq), // water
+    (50, 65, 11, Seq), // lava
+    (66, 66, 12, Fixed(0)), // sand
+    (67, 67, 12, Fixed(1)), // red sand
+    (68, 68, 13, Fixed(0)), // gravel
+    (69, 69, 14, Fixed(0)), // gold ore
+    (70, 70, 15, Fixed(0)), // iron ore
+    (71, 71, 16, Fixed(0)), // coal ore
+    (72, 72, 14, Fixed(0)), // nether gold ore
+    (73, 84, 17, Div(3)), // oak, spruce, birch, jungle logs
+    (85, 90, 162, Div(3)), // acacia, dark oak logs
+    (91, 108, 17, Fixed(0)), // stripped logs
+    (109, 120, 17, Fixed(12)), // wood
+    (121, 126, 162, Fixed(12)), // wood
+    (127, 144, 17, Fixed(12)), // stripped wood
+    (145, 200, 18, Div(14)), // oak, spruce, birch, jungle leaves
+    (201, 228, 161, Div(14)), // acacia, dark oak leaves
+    (229, 229, 19, Fixed(0)), // sponge
+    (230, 230, 19, Fixed(1)), // wet sponge
+    (231, 231, 20, Fixed(0)), // glass
+    (232, 232, 21, Fixed(0)), // lapis ore
+    (233, 233, 22, Fixed(0)), // lapis block
+    (234, 245, 23, Fixed(0)), // dispenser
+    (246, 248, 24, Seq), // sandstone
+    (249, 1048, 25, Fixed(0)), // note block
+    (1049, 1304, 26, Fixed(0)), // beds
+    (1305, 1316, 27, Fixed(0)), // powered rail
+    (1317, 1328, 28, Fixed(0)), // detector rail
+    (1329, 1340, 29, Fixed(0)), // sticky piston
+    (1341, 1341, 30, Fixed(0)), // cobweb
+    (1342, 1342, 31, Fixed(1)), // grass
+    (1343, 1343, 31, Fixed(2)), // fern
+    (1344, 1344, 32, Fixed(0)), // dead bush
+    (1345, 1347, 9, Fixed(0)), // seagrass
+    (1348, 1359, 33, Fixed(0)), // piston
+    (1360, 1383, 34, Fixed(0)), // piston head
+    (1384, 1399, 35, Seq), // wool
+    (1400, 1411, 36, Fixed(0)), // moving piston
+    (1412, 1412, 37, Fixed(0)), // dandelion
+    (1413, 1421, 38, Seq), // flowers
+    (1422, 1424, 38, Fixed(0)), // flowers not in 1.12
+    (1425, 1425, 39, Fixed(0)), // brown mushroom
+    (1426, 1426, 40, Fixed(0)), // red mushroom
+    (1427, 1427, 41, Fixed(0)), // gold block
+    (1428, 1428, 42, Fixed(0)), // iron block
+    (1429, 1429, 45, Fixed(0)), // bricks
+    (1430, 1431, 46, Fixed(0)), // tnt
+    (1432, 1432, 47, Fixed(0)), // bookshelf
+    (1433, 1433, 48, Fixed(0)), // mossy cobblestone
+    (1434, 1434, 49, Fixed(0)), // obsidian
+    (1435, 1435, 50, Fixed(5)), // torch
+    (1436, 1439, 50, Fixed(1)), // wall torch
+    (1440, 1952, 51, Fixed(0)), // fire, soul fire
+    (1953, 1953, 52, Fixed(0)), // spawner
+    (1954, 2033, 53, Fixed(0)), // oak stairs
+    (2034, 2057, 54, Fixed(0)), // chest
+    (2058, 3353, 55, Fixed(0)), // redstone wire
+    (3354, 3354, 56, Fixed(0)), // diamond ore
+    (3355, 3355, 57, Fixed(0)), // diamond block
+    (3356, 3356, 58, Fixed(0)), // crafting table
+    (3357, 3364, 59, Seq), // wheat
+    (3365, 3372, 60, Fixed(0)), // farmland
+    (3373, 3380, 61, Fixed(0)), // furnace
+    (3381, 3572, 63, Fixed(0)), // signs
+    (3573, 3636, 64, Fixed(0)), // oak door
+    (3637, 3644, 65, Fixed(0)), // ladder
+    (3645, 3654, 66, Fixed(0)), // rail
+    (3655, 3734, 67, Fixed(0)), // cobblestone stairs
+    (3735, 3782, 68, Fixed(0)), // wall signs
+    (3783, 3806, 69, Fixed(0)), // lever
+    (3807, 3808, 70, Fixed(0)), // stone pressure plate
+    (3809, 3872, 71, Fixed(0)), // iron door
+    (3873, 3884, 72, Fixed(0)), // wooden pressure plates
+    (3885, 3886, 73, Fixed(0)), // redstone ore
+    (3887, 3896, 76, Fixed(0)), // redstone torches
+    (3897, 3920, 77, Fixed(0)), // stone button
+    (3921, 3928, 78, Seq), // snow
+    (3929, 3929, 79, Fixed(0)), // ice
+    (3930, 3930, 80, Fixed(0)), // snow block
+    (3931, 3946, 81, Fixed(0)), // cactus
+    (3947, 3947, 82, Fixed(0)), // clay
+    (3948, 3963, 83, Fixed(0)), // sugar cane
+    (3964, 3965, 84, Fixed(0)), // jukebox
+    (3966, 3997, 85, Fixed(0)), // oak fence
+    (3998, 3998, 86, Fixed(0)), // pumpkin
+    (3999, 3999, 87, Fixed(0)), // netherrack
+    (4000, 4001, 88, Fixed(0)), // soul sand, soul soil
+    (4002, 4007, 1, Fixed(0)), // basalt
+    (4008, 4008, 50, Fixed(5)), // soul torch
+    (4009, 4012, 50, Fixed(1)), // soul wall torch
+    (4013, 4013, 89, Fixed(0)), // glowstone
+    (4014, 4015, 90, Fixed(0)), // nether portal
+    (4016, 4019, 86, Fixed(0)), // carved pumpkin
+    (4020, 4023, 91, Fixed(0)), // jack o'lantern
+    (4024, 4030, 92, Fixed(0)), // cake
+    (4031, 4094, 93, Fixed(0)), // repeater
+    (4095, 4110, 95, Seq), // stained glass
+    (9665, 9666, 0, Fixed(0)), // void air, cave air
+    (9667, 9668, 9, Fixed(0)), // bubble column
+];
+
+/// The 1.12 equivalent of a 1.16.5 block state
+pub fn to_legacy(state: u32) -> BlockState {
+    let idx = match TABLE.binary_search_by(|&(first, last, ..)| {
+        if last < state {
+            std::cmp::Ordering::Less
+        } else if first > state {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    }) {
+        Ok(idx) => idx,
+        Err(_) => return UNKNOWN
+    };
+
+    let (first, _, id, meta) = TABLE[idx];
+    let meta = match meta {
+        Fixed(meta) => meta as u32,
+        Seq => state - first,
+        Div(n) => (state - first) / n,
+    };
+
+    BlockState::from(id, meta as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::v754::blocks::{TABLE, to_legacy};
+    use crate::storage::block::BlockState;
+
+    #[test]
+    fn test_table_sorted() {
+        for pair in TABLE.windows(2) {
+            let (_, last, ..) = pair[0];
+            let (first, ..) = pair[1];
+            assert!(last < first, "{} overlaps {}", last, first);
+        }
+        assert!(TABLE.iter().all(|&(first, last, ..)| first <= last));
+    }
+
+    #[test]
+    fn test_to_legacy() {
+        assert_eq!(to_legacy(0), BlockState::AIR);
+        assert_eq!(to_legacy(1), BlockState::from(1, 0));
+        assert_eq!(to_legacy(2), BlockState::from(1, 1)); // granite
+        assert_eq!(to_legacy(9), BlockState::from(2, 0));
+        assert_eq!(to_legacy(34), BlockState::from(9, 0)); // still water
+        assert_eq!(to_legacy(40), BlockState::from(9, 6));
+        assert_eq!(to_legacy(74), BlockState::from(17, 0)); // oak log, y axis
+        assert_eq!(to_legacy(80), BlockState::from(17, 2)); // birch log
+        assert_eq!(to_legacy(88), BlockState::from(162, 1)); // dark oak log
+        assert_eq!(to_legacy(173), BlockState::from(18, 2)); // birch leaves
+        assert_eq!(to_legacy(9666), BlockState::AIR);
+
+        // unknown blocks are solid
+        assert_eq!(to_legacy(5000), BlockState::from(7, 0));
+        assert_eq!(to_legacy(u32::MAX), BlockState::from(7, 0));
+    }
+}
@@ -0,0 +1,444 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::Arc;
+
+use swarm_bot_packets::{Packet, Readable};
+use swarm_bot_packets::read::{ByteReadable, ByteReader};
+use swarm_bot_packets::types::{Angle, Identifier, UUID, VarInt, VarUInt};
+
+use crate::chat::Component;
+use crate::nbt::{NbtLimits, Tag};
+use crate::protocol::v340::clientbound as v340;
+use crate::protocol::v340::clientbound::Player;
+use crate::protocol::v754::{blocks, Position};
+use crate::storage::block::{BlockLocation, BlockState};
+use crate::storage::chunk::{ChunkColumn, ChunkData, HighMemoryChunkSection, Palette};
+use crate::types::{Dimension, DirectionOrigin, Displacement, GameMode, Location, LocationOrigin};
+
+/// Skip an nbt compound which we are not interested in
+fn skip_nbt(byte_reader: &mut ByteReader) {
+    let len = match crate::nbt::read(byte_reader.remaining(), &NbtLimits::NETWORK) {
+        Ok(nbt) if nbt.truncated.is_none() => nbt.len,
+        _ => byte_reader.len()
+    };
+    byte_reader.advance(len);
+}
+
+/// The dimension type nbt has the effects of the dimension, which tells us whether we are in the nether or end
+/// even if the world has a custom name
+fn read_dimension(byte_reader: &mut ByteReader) -> Dimension {
+    let (dimension, len) = match crate::nbt::read(byte_reader.remaining(), &NbtLimits::NETWORK) {
+        Ok(nbt) => {
+            let dimension = match nbt.root.get("effects").and_then(Tag::as_str) {
+                Some("minecraft:the_nether") => Dimension::Nether,
+                Some("minecraft:the_end") => Dimension::End,
+                _ => Dimension::Overworld
+            };
+            let len = if nbt.truncated.is_some() { byte_reader.len() } else { nbt.len };
+            (dimension, len)
+        }
+        Err(..) => (Dimension::Overworld, byte_reader.len())
+    };
+    byte_reader.advance(len);
+    dimension
+}
+
+#[derive(Packet, Debug)]
+#[packet(0x24, Play)]
+pub struct JoinGame {
+    pub entity_id: u32,
+    pub game_mode: GameMode,
+    pub dimension: Dimension,
+    pub world_name: Identifier,
+}
+
+impl ByteReadable for JoinGame {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let entity_id = byte_reader.read();
+        let _hardcore: bool = byte_reader.read();
+        let game_mode = byte_reader.read();
+        let _previous_game_mode: u8 = byte_reader.read();
+        let _worlds: Vec<Identifier> = byte_reader.read();
+
+        // the dimension codec (registry of all dimension types and biomes)
+        skip_nbt(byte_reader);
+
+        let dimension = read_dimension(byte_reader);
+        let world_name = byte_reader.read();
+
+        // the rest (seed, view distance, ...) is not needed
+        Self { entity_id, game_mode, dimension, world_name }
+    }
+}
+
+#[derive(Packet, Debug)]
+#[packet(0x39, Play)]
+pub struct Respawn {
+    pub dimension: Dimension,
+    pub world_name: Identifier,
+    pub gamemode: GameMode,
+}
+
+impl ByteReadable for Respawn {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let dimension = read_dimension(byte_reader);
+        let world_name = byte_reader.read();
+        let _hashed_seed: u64 = byte_reader.read();
+        let gamemode = byte_reader.read();
+        Self { dimension, world_name, gamemode }
+    }
+}
+
+/// Like the 1.12 [`UnloadChunk`](v340::UnloadChunk), at another id
+#[derive(Packet, Debug, Readable)]
+#[packet(0x1C, Play)]
+pub struct UnloadChunk {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+}
+
+/// Like the 1.12 [`ChangeGameState`](v340::ChangeGameState), at another id
+#[derive(Packet, Debug, Readable)]
+#[packet(0x1D, Play)]
+pub struct ChangeGameState {
+    pub reason: u8,
+    pub value: f32,
+}
+
+impl ChangeGameState {
+    pub const CHANGE_GAMEMODE: u8 = v340::ChangeGameState::CHANGE_GAMEMODE;
+}
+
+#[derive(Packet, Debug, Readable)]
+#[packet(0x0B, Play)]
+pub struct BlockChange {
+    pub location: Position,
+    pub block_id: VarInt,
+}
+
+/// A VarLong. Only needed for [`MultiBlock`].
+#[derive(Debug)]
+pub struct VarLong(pub i64);
+
+impl ByteReadable for VarLong {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let mut val = 0_u64;
+        for size in 0..10 {
+            let b: u8 = byte_reader.read();
+            val |= ((b & 0x7F) as u64) << (size * 7);
+            if b & 0x80 == 0 {
+                break;
+            }
+        }
+        VarLong(val as i64)
+    }
+}
+
+#[derive(Packet, Debug)]
+#[packet(0x3B, Play)]
+pub struct MultiBlock {
+    pub records: Vec<(BlockLocation, BlockState)>,
+}
+
+impl ByteReadable for MultiBlock {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        // x: 22 bits, z: 22 bits, y: 20 bits
+        let section: i64 = byte_reader.read::<u64>() as i64;
+        let section_x = (section >> 42) as i32;
+        let section_y = (section << 44 >> 44) as i32;
+        let section_z = (section << 22 >> 42) as i32;
+
+        let _trust_edges: bool = byte_reader.read();
+        let changes: Vec<VarLong> = byte_reader.read();
+
+        let records = changes.into_iter().map(|VarLong(change)| {
+            let state = (change >> 12) as u32;
+            let x = ((change >> 8) & 0xF) as i32;
+            let z = ((change >> 4) & 0xF) as i32;
+            let y = (change & 0xF) as i32;
+
+            let location = BlockLocation::new((section_x << 4) + x, ((section_y << 4) + y) as i16, (section_z << 4) + z);
+            (location, blocks::to_legacy(state))
+        }).collect();
+
+        Self { records }
+    }
+}
+
+#[derive(Packet, Debug, Readable)]
+#[packet(0x1F, Play)]
+pub struct KeepAlive {
+    pub id: u64,
+}
+
+/// sent every 20 ticks
+#[derive(Packet, Debug, Readable)]
+#[packet(0x4E, Play)]
+pub struct TimeUpdate {
+    pub world_age: u64,
+    pub time_of_day: u64,
+}
+
+#[derive(Packet, Debug, Readable)]
+#[packet(0x19, Play)]
+pub struct PlayDisconnect {
+    pub reason: String,
+}
+
+/// the server changed the held hotbar slot
+#[derive(Packet, Debug, Readable)]
+#[packet(0x3F, Play)]
+pub struct HeldItemChange {
+    pub slot: i8,
+}
+
+#[derive(Packet, Debug, Readable)]
+#[packet(0x0E, Play)]
+pub struct ChatMessage {
+    /// 1.16 uses translatable components for player messages
+    pub chat: Component,
+    pub position: u8,
+    pub sender: UUID,
+}
+
+#[derive(Packet, Debug, Readable)]
+#[packet(0x49, Play)]
+pub struct UpdateHealth {
+    pub health: f32,
+    pub food: VarInt,
+    pub food_saturation: f32,
+}
+
+/// where players without a bed spawn (and compasses point)
+#[derive(Packet, Debug, Readable)]
+#[packet(0x42, Play)]
+pub struct SpawnPosition {
+    pub location: Position,
+}
+
+/// Same layout as 1.12
+#[derive(Packet, Debug, Readable)]
+#[packet(0x30, Play)]
+pub struct PlayerAbilities {
+    pub flags: u8,
+    pub fly_speed: f32,
+    pub walk_speed: f32,
+}
+
+/// Same layout as 1.12
+#[derive(Debug, Packet)]
+#[packet(0x31, Play)]
+pub struct CombatEvent {
+    pub death_message: Option<String>,
+}
+
+impl ByteReadable for CombatEvent {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let v340::CombatEvent { death_message } = byte_reader.read();
+        Self { death_message }
+    }
+}
+
+/// Same layout as 1.12
+#[derive(Packet, Debug)]
+#[packet(0x34, Play)]
+pub struct PlayerPositionAndLook {
+    pub location: LocationOrigin,
+    pub rotation: DirectionOrigin,
+    pub teleport_id: VarInt,
+}
+
+impl ByteReadable for PlayerPositionAndLook {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let v340::PlayerPositionAndLook { location, rotation, teleport_id } = byte_reader.read();
+        Self { location, rotation, teleport_id }
+    }
+}
+
+/// Same layout as 1.12
+#[derive(Packet, Debug)]
+#[packet(0x1A, Play)]
+pub struct EntityStatus {
+    pub entity_id: i32,
+    pub status: u8,
+}
+
+impl ByteReadable for EntityStatus {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let v340::EntityStatus { entity_id, status } = byte_reader.read();
+        Self { entity_id, status }
+    }
+}
+
+impl EntityStatus {
+    pub const TOTEM: u8 = v340::EntityStatus::TOTEM;
+}
+
+/// Same layout as 1.12
+#[derive(Debug, Packet)]
+#[packet(0x32, Play)]
+pub struct PlayerInfo {
+    pub players: Vec<Player>,
+}
+
+impl ByteReadable for PlayerInfo {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let v340::PlayerListItem { players } = byte_reader.read();
+        Self { players }
+    }
+}
+
+/// Same layout as 1.12
+#[derive(Packet, Debug)]
+#[packet(0x1B, Play)]
+pub struct Explosion {
+    pub records: Vec<BlockLocation>,
+    pub velocity: Displacement,
+}
+
+impl ByteReadable for Explosion {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let v340::Explosion { records, velocity, .. } = byte_reader.read();
+        Self { records, velocity }
+    }
+}
+
+/// Spawn Entity. Unlike 1.12 the type is a VarInt.
+#[derive(Packet, Debug, Readable)]
+#[packet(0x00, Play)]
+pub struct ObjectSpawn {
+    pub entity_id: VarInt,
+    pub object_uuid: UUID,
+    pub type_id: VarInt,
+    pub location: Location,
+    pub pitch: Angle,
+    pub yaw: Angle,
+    pub data: i32,
+    pub velocity_x: i16,
+    pub velocity_y: i16,
+    pub velocity_z: i16,
+}
+
+impl ObjectSpawn {
+    /// `data` is the global block state id
+    pub const FALLING_BLOCK: i32 = 26;
+
+    /// a dropped item. Which item is only sent in the metadata after.
+    pub const ITEM: i32 = 37;
+}
+
+/// the entity packets have not changed since 1.12, only their ids
+pub mod entity_ids {
+    pub const LIVING_SPAWN: u32 = 0x02;
+    pub const PLAYER_SPAWN: u32 = 0x04;
+    pub const RELATIVE_MOVE: u32 = 0x27;
+    pub const LOOK_AND_RELATIVE_MOVE: u32 = 0x28;
+    pub const DESTROY: u32 = 0x36;
+    pub const TELEPORT: u32 = 0x56;
+    pub const VELOCITY: u32 = 0x46;
+    pub const EFFECT: u32 = 0x59;
+    pub const REMOVE_EFFECT: u32 = 0x37;
+}
+
+/// A 1.16 chunk section. Block light and sky light are sent in a separate packet and values do not span longs.
+#[derive(Debug)]
+pub struct ChunkSection {
+    pub states: Box<[BlockState; 4096]>,
+}
+
+impl ByteReadable for ChunkSection {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let _block_count: i16 = byte_reader.read();
+        let bits_per_block: u8 = byte_reader.read();
+
+        let palette: Option<Vec<VarInt>> = (bits_per_block <= 8).then(|| byte_reader.read());
+        let bits_per_block = if palette.is_some() { bits_per_block.max(4) } else { bits_per_block } as usize;
+
+        let storage: Vec<u64> = byte_reader.read();
+
+        let bits_per_block = bits_per_block.clamp(1, 64);
+        let per_long = 64 / bits_per_block;
+        let mask = 1_u64.checked_shl(bits_per_block as u32).map_or(u64::MAX, |bit| bit - 1);
+
+        let mut states = Box::new([BlockState::AIR; 4096]);
+        for (idx, state) in states.iter_mut().enumerate() {
+            let long = storage.get(idx / per_long).copied().unwrap_or_default();
+            let value = (long >> ((idx % per_long) * bits_per_block)) & mask;
+
+            let global = match palette.as_ref() {
+                Some(palette) => palette.get(value as usize).map(|id| id.0 as u32).unwrap_or_default(),
+                None => value as u32
+            };
+
+            *state = blocks::to_legacy(global);
+        }
+
+        Self { states }
+    }
+}
+
+/// Chunk Data. Block entities are ignored.
+#[derive(Packet)]
+#[packet(0x20, Play)]
+pub struct ChunkColumnPacket {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    pub new_chunk: bool,
+    pub column: ChunkColumn,
+}
+
+impl ByteReadable for ChunkColumnPacket {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let chunk_x = byte_reader.read();
+        let chunk_z = byte_reader.read();
+        let full_chunk: bool = byte_reader.read();
+        let VarUInt(mut primary_bitmask) = byte_reader.read();
+
+        // heightmaps
+        skip_nbt(byte_reader);
+
+        if full_chunk {
+            let _biomes: Vec<VarInt> = byte_reader.read();
+        }
+
+        let _size: VarUInt = byte_reader.read();
+
+        const INIT: Option<Arc<HighMemoryChunkSection>> = None;
+        let mut sections = [INIT; 16];
+
+        let mut idx = 0;
+        while primary_bitmask != 0 && idx < 16 {
+            if primary_bitmask & 0b1 == 1 {
+                let ChunkSection { states } = byte_reader.read();
+                sections[idx] = Some(Arc::new(HighMemoryChunkSection::new(Palette::from_states(&states))));
+            }
+            primary_bitmask >>= 1;
+            idx += 1;
+        }
+
+        let column = ChunkColumn::HighMemory {
+            data: ChunkData { sections }
+        };
+
+        ChunkColumnPacket {
+            chunk_x,
+            chunk_z,
+            new_chunk: full_chunk,
+            column,
+        }
+    }
+}
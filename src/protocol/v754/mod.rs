@@ -0,0 +1,819 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Minecraft 1.16.5 (protocol 754).
+//!
+//! Only the subset of the protocol the bot needs to walk around, mine, build and talk is supported:
+//!
+//! - login (shared with 1.12)
+//! - join game, respawn and disconnect, and the gamemode they (and Change Game State) set
+//! - chunk data, block change, multi block change and explosions. 1.16 block states are translated to the 1.12 states
+//!   the rest of the bot uses (see [`blocks`])
+//! - player position and look, keep alive, health and death
+//! - player abilities, so the bot can fly where the server lets it
+//! - chat (player messages and whispers are converted to the 1.12 format)
+//! - player info and entity spawn/move/destroy
+//! - digging, placing, attacking, using items, swinging and changing the held item
+//!
+//! Item ids changed completely in 1.13 and are not translated, so window items and inventory actions are ignored.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::TryRecvError;
+use std::time::Instant;
+
+
+use swarm_bot_packets::read::{ByteReadable, ByteReader};
+use swarm_bot_packets::types::{Packet, VarInt};
+use swarm_bot_packets::write::{ByteWritable, ByteWriter};
+
+use crate::bootstrap::Connection;
+use crate::chat::ChatEvent;
+use crate::client::processor::InterfaceIn;
+use crate::client::state::local::abilities::Abilities;
+use crate::error::{err, Phase, SwarmContext, SwarmError};
+use crate::protocol::{ClientInfo, Closing, EventQueue, Face, InterfaceOut, InvAction, Login, Mine, Minecraft};
+use crate::protocol::io::reader::Immediate;
+use crate::protocol::io::writer::PacketWriteChannel;
+use crate::protocol::budget::BudgetConfig;
+use crate::protocol::chat_queue::{ChatConfig, ChatQueue};
+use crate::protocol::disconnect::DisconnectPatterns;
+use crate::protocol::movement::{MovementOut, MovementTracker};
+use crate::protocol::login::{self, LoggedIn};
+use crate::protocol::tick::TickTimer;
+use crate::protocol::v340::clientbound::{entity, Player, PlayerListType};
+use crate::protocol::v340::death_cause;
+use crate::protocol::v340::serverbound::{Action, BlockCursor, ClientStatusAction, DigStatus, Hand, InteractEntityKind};
+use crate::protocol::v754::clientbound::JoinGame;
+use crate::storage::block::BlockLocation;
+use crate::storage::blocks::ChunkLocation;
+use crate::storage::entities::EntityKind;
+use crate::types::{Direction, DirectionOrigin, GameMode, Location, PacketData};
+
+mod blocks;
+mod clientbound;
+mod serverbound;
+
+const PROTOCOL_VERSION: i32 = 754;
+
+/// A block position in the 1.14+ format: x (26 bits), z (26 bits), y (12 bits)
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Position(pub BlockLocation);
+
+impl ByteReadable for Position {
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let val = byte_reader.read::<u64>() as i64;
+
+        let x = (val >> 38) as i32;
+        let y = (val << 52 >> 52) as i16;
+        let z = (val << 26 >> 38) as i32;
+
+        Position(BlockLocation::new(x, y, z))
+    }
+}
+
+impl ByteWritable for Position {
+    fn write_to_bytes(self, writer: &mut ByteWriter) {
+        let BlockLocation { x, y, z } = self.0;
+        let write = ((x as u64 & 0x3FFFFFF) << 38) | ((z as u64 & 0x3FFFFFF) << 12) | (y as u64 & 0xFFF);
+        writer.write(write);
+    }
+}
+
+pub struct EventQueue754 {
+    rx: std::sync::mpsc::Receiver<PacketData>,
+    out: Interface754,
+    location: Location,
+
+    /// we need to store state because sometimes death packets occur twice and we only want to send one event
+    alive: bool,
+
+    /// the combat event with the death message is sent before the health update
+    death_cause: Option<String>,
+
+    disconnects: DisconnectPatterns,
+
+    /// filled in by the reader
+    ticks: TickTimer,
+}
+
+impl EventQueue for EventQueue754 {
+    fn flush(&mut self, processor: &mut impl InterfaceIn) {
+        self.out.flush_chat(Instant::now());
+
+        loop {
+            match self.rx.try_recv() {
+                Ok(data) => {
+                    self.process_packet(data, processor);
+                }
+                Err(err) => {
+                    match err {
+                        TryRecvError::Empty => {}
+                        TryRecvError::Disconnected => {
+                            processor.on_socket_close();
+                        }
+                    }
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl EventQueue754 {
+    fn process_packet(&mut self, mut data: PacketData, processor: &mut impl InterfaceIn) {
+        use clientbound::*;
+        match data.id {
+            JoinGame::ID => {
+                let JoinGame { dimension, game_mode, .. } = data.read();
+                processor.on_join();
+                processor.on_gamemode(game_mode);
+                processor.on_dimension_change(dimension);
+            }
+            Respawn::ID => {
+                let Respawn { dimension, gamemode, .. } = data.read();
+                processor.on_gamemode(gamemode);
+                processor.on_dimension_change(dimension);
+            }
+            ChangeGameState::ID => {
+                let ChangeGameState { reason, value } = data.read();
+                if reason == ChangeGameState::CHANGE_GAMEMODE {
+                    if let Some(gamemode) = GameMode::from_id(value as u8) {
+                        processor.on_gamemode(gamemode);
+                    }
+                }
+            }
+            ChunkColumnPacket::ID => {
+                let ChunkColumnPacket { chunk_x, chunk_z, column, new_chunk } = data.read();
+                processor.on_recv_chunk(ChunkLocation(chunk_x, chunk_z), column, new_chunk);
+            }
+            UnloadChunk::ID => {
+                let UnloadChunk { chunk_x, chunk_z } = data.read();
+                processor.on_unload_chunk(ChunkLocation(chunk_x, chunk_z));
+            }
+            BlockChange::ID => {
+                let BlockChange { location: Position(location), block_id } = data.read();
+                processor.on_block_change(location, blocks::to_legacy(block_id.0 as u32));
+            }
+            MultiBlock::ID => {
+                let MultiBlock { records } = data.read();
+                for (location, state) in records {
+                    processor.on_block_change(location, state);
+                }
+            }
+            EntityStatus::ID => {
+                let EntityStatus { entity_id, status } = data.read();
+                if entity_id as u32 == self.out.entity_id && status == EntityStatus::TOTEM {
+                    processor.on_totem_pop();
+                }
+            }
+            Explosion::ID => {
+                let Explosion { records, velocity } = data.read();
+                processor.on_explosion(records, velocity);
+            }
+            TimeUpdate::ID => {
+                // keep alives are answered by the reader
+                if let Some(tps) = self.ticks.tps() {
+                    processor.on_server_tps(tps);
+                }
+            }
+            entity_ids::RELATIVE_MOVE => {
+                let entity::RelativeMove { entity_id, loc, .. } = data.read();
+                processor.on_entity_move(entity_id.into(), loc.into());
+            }
+            entity_ids::LOOK_AND_RELATIVE_MOVE => {
+                let entity::LookAndRelativeMove { entity_id, loc, .. } = data.read();
+                processor.on_entity_move(entity_id.into(), loc.into());
+            }
+            entity_ids::DESTROY => {
+                let entity::Destroy { ids } = data.read();
+                for id in ids {
+                    processor.on_entity_destroy(id.into());
+                }
+            }
+            entity_ids::TELEPORT => {
+                let entity::Teleport { entity_id, location, .. } = data.read();
+                processor.on_entity_move(entity_id.into(), location.into());
+            }
+            entity_ids::VELOCITY => {
+                let velocity: entity::Velocity = data.read();
+                processor.on_entity_velocity(velocity.entity_id.into(), velocity.velocity());
+            }
+            entity_ids::EFFECT => {
+                let entity::Effect { entity_id, effect_id, amplifier, duration, .. } = data.read();
+                processor.on_entity_effect(entity_id.into(), effect_id.into(), amplifier, duration.0.max(0) as u32);
+            }
+            entity_ids::REMOVE_EFFECT => {
+                let entity::RemoveEffect { entity_id, effect_id } = data.read();
+                processor.on_remove_entity_effect(entity_id.into(), effect_id.into());
+            }
+            entity_ids::LIVING_SPAWN => {
+                let entity::LivingSpawn { entity_id, location, .. } = data.read();
+                processor.on_entity_spawn(entity_id.into(), location, EntityKind::Normal);
+            }
+            ObjectSpawn::ID => {
+                let ObjectSpawn { entity_id, type_id, location, data, .. } = data.read();
+                if type_id.0 == ObjectSpawn::FALLING_BLOCK {
+                    let state = blocks::to_legacy(data as u32);
+                    processor.on_entity_spawn(entity_id.into(), location, EntityKind::FallingBlock { state });
+                } else if type_id.0 == ObjectSpawn::ITEM {
+                    processor.on_entity_spawn(entity_id.into(), location, EntityKind::Item);
+                }
+            }
+            entity_ids::PLAYER_SPAWN => {
+                let entity::PlayerSpawn { entity_id, location, player_uuid, .. } = data.read();
+                processor.on_entity_spawn(entity_id.into(), location, EntityKind::Player { uuid: player_uuid.0 });
+            }
+            UpdateHealth::ID => {
+                let UpdateHealth { health, food, .. } = data.read();
+                if health > 0.0 {
+                    processor.on_update_health(health, food.0 as u8);
+                    self.alive = true;
+                } else if self.alive {
+                    processor.on_death(self.death_cause.take());
+                    self.alive = false;
+                }
+            }
+            SpawnPosition::ID => {
+                let SpawnPosition { location: Position(location) } = data.read();
+                processor.on_spawn_position(location);
+            }
+            PlayerAbilities::ID => {
+                let PlayerAbilities { flags, fly_speed, walk_speed } = data.read();
+                processor.on_abilities(Abilities::from_flags(flags, fly_speed, walk_speed));
+            }
+            CombatEvent::ID => {
+                let CombatEvent { death_message } = data.read();
+                if let Some(message) = death_message {
+                    self.death_cause = Some(death_cause(&message));
+                }
+            }
+            PlayerPositionAndLook::ID => {
+                let PlayerPositionAndLook { location, rotation, teleport_id } = data.read();
+
+                self.location.apply_change(location);
+
+                // "accept" the packet
+                self.out.write(serverbound::TeleportConfirm {
+                    teleport_id
+                });
+                let direction = self.out.resync(self.location, rotation);
+                processor.on_move(self.location, direction);
+            }
+            PlayDisconnect::ID => {
+                let PlayDisconnect { reason } = data.read();
+                processor.on_disconnect(self.disconnects.classify(&reason));
+            }
+            HeldItemChange::ID => {
+                let HeldItemChange { slot } = data.read();
+                processor.on_held_slot(slot as u8);
+            }
+            PlayerInfo::ID => {
+                let PlayerInfo { players } = data.read();
+                for Player { uuid, list_type } in players {
+                    match list_type {
+                        PlayerListType::AddPlayer(add) => processor.on_player_join(add.into_player(uuid.0)),
+                        PlayerListType::UpdateGamemode(VarInt(gamemode)) => processor.on_player_update(uuid.0, None, Some(gamemode as u8)),
+                        PlayerListType::UpdateLatency(VarInt(ping)) => processor.on_player_update(uuid.0, Some(ping.max(0) as u32), None),
+                        PlayerListType::RemovePlayer => processor.on_player_leave(uuid.0),
+                        _ => {}
+                    }
+                }
+            }
+            ChatMessage::ID => {
+                let ChatMessage { chat, .. } = data.read();
+                processor.on_chat(ChatEvent::from(chat));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Interface754 {
+    tx: Rc<RefCell<PacketWriteChannel>>,
+    movement: Rc<RefCell<MovementTracker>>,
+    chat: Rc<RefCell<ChatQueue>>,
+
+    /// our entity id, which Entity Action packets refer to
+    entity_id: u32,
+}
+
+impl Interface754 {
+    fn new(tx: PacketWriteChannel, chat: ChatConfig, entity_id: u32) -> Interface754 {
+        Interface754 {
+            tx: Rc::new(RefCell::new(tx)),
+            movement: Rc::default(),
+            chat: Rc::new(RefCell::new(ChatQueue::new(chat))),
+            entity_id,
+        }
+    }
+
+    #[inline]
+    fn write<T: Packet + ByteWritable>(&self, packet: T) {
+        self.tx.borrow_mut().write(packet)
+    }
+
+    /// see [`MovementTracker::resync`]
+    fn resync(&self, location: Location, rotation: DirectionOrigin) -> Direction {
+        self.movement.borrow_mut().resync(location, rotation, self)
+    }
+
+    /// send the next queued chat message if the rate limit allows it
+    fn flush_chat(&self, now: Instant) {
+        let message = self.chat.borrow_mut().poll(now);
+        if let Some(message) = message {
+            self.write(serverbound::ChatMessage { message });
+        }
+    }
+}
+
+impl MovementOut for Interface754 {
+    fn position_and_rotation(&self, location: Location, direction: Direction, on_ground: bool) {
+        self.write(serverbound::PlayerPositionAndRotation {
+            location,
+            direction,
+            on_ground,
+        });
+    }
+
+    fn position(&self, location: Location, on_ground: bool) {
+        self.write(serverbound::PlayerPosition {
+            location,
+            on_ground,
+        });
+    }
+
+    fn rotation(&self, direction: Direction, on_ground: bool) {
+        self.write(serverbound::PlayerLook {
+            direction,
+            on_ground,
+        });
+    }
+
+    fn on_ground(&self, on_ground: bool) {
+        self.write(serverbound::PlayerMovement {
+            on_ground
+        });
+    }
+}
+
+impl InterfaceOut for Interface754 {
+    fn place_block(&mut self, against: BlockLocation, face: Face) {
+        self.write(serverbound::PlaceBlock {
+            hand: Hand::Main,
+            location: Position(against),
+            face: VarInt(face as i32),
+            cursor: BlockCursor {
+                x: 1.0,
+                y: 1.5,
+                z: 0.5,
+            },
+            inside_block: false,
+        });
+    }
+
+    fn attack_entity(&mut self, id: u32) {
+        self.write(serverbound::InteractEntity {
+            id: id.into(),
+            kind: InteractEntityKind::Attack,
+            sneaking: false,
+        })
+    }
+
+    fn send_chat(&mut self, message: &str) {
+        // sent from the tick loop so the bot does not get kicked for spamming
+        self.chat.borrow_mut().push(message, Instant::now());
+    }
+
+    fn set_chat_config(&mut self, config: ChatConfig) {
+        self.chat.borrow_mut().set_config(config);
+    }
+
+    /// actions go out right away on 1.16
+    fn set_budget(&mut self, _config: BudgetConfig) {}
+
+    fn flush_budget(&mut self) {}
+
+    /// the server picks the view distance on 1.16
+    fn set_view_distance(&mut self, _chunks: u8) {}
+
+    fn deferred(&self) -> usize {
+        0
+    }
+
+    /// item ids are not translated so we cannot tell the server what we clicked. Swapping hands needs no item.
+    fn inventory_action(&mut self, action: InvAction) {
+        if let InvAction::SwapOffhand = action {
+            self.write(serverbound::PlayerDig::status(DigStatus::SwapItem));
+        }
+    }
+
+    fn swing_arm(&mut self) {
+        self.write(serverbound::ArmAnimation {
+            hand: Hand::Main
+        });
+    }
+
+    fn finish_eating(&mut self) {
+        self.write(serverbound::PlayerDig::status(DigStatus::ShootArrowOrFinishEat));
+    }
+
+    fn use_item(&mut self, hand: Hand) {
+        self.write(serverbound::UseItem {
+            hand
+        });
+    }
+
+    fn change_slot(&mut self, number: u8) {
+        self.write(serverbound::HeldItemChange {
+            slot: number as u16
+        })
+    }
+
+    fn mine(&mut self, position: BlockLocation, mine: Mine, face: Face) {
+        let status = match mine {
+            Mine::Start => DigStatus::Started,
+            Mine::Cancel => DigStatus::Cancelled,
+            Mine::Finished => DigStatus::Finished
+        };
+
+        if status == DigStatus::Started {
+            self.swing_arm();
+        }
+
+        self.write(serverbound::PlayerDig {
+            status,
+            position: Position(position),
+            face: face as u8,
+        });
+    }
+
+    fn respawn(&mut self) {
+        self.write(serverbound::ClientStatus {
+            action: ClientStatusAction::Respawn
+        });
+    }
+
+    fn teleport(&mut self, location: Location) {
+        self.movement.borrow_mut().sent(Some(location), None);
+        self.write(serverbound::PlayerPosition {
+            location,
+            on_ground: true,
+        });
+    }
+
+    fn look(&mut self, direction: Direction) {
+        self.movement.borrow_mut().sent(None, Some(direction));
+        self.write(serverbound::PlayerLook {
+            direction,
+            on_ground: false,
+        })
+    }
+
+    fn teleport_and_look(&mut self, location: Location, direction: Direction, on_ground: bool) {
+        self.movement.borrow_mut().send_tick(location, direction, on_ground, &*self);
+    }
+
+    fn sneak(&mut self, sneaking: bool) {
+        self.write(serverbound::EntityAction {
+            entity_id: VarInt(self.entity_id as i32),
+            action: if sneaking { Action::SneakStart } else { Action::SneakStop },
+            jump_boost: VarInt(0),
+        });
+    }
+
+    fn sprint(&mut self, sprinting: bool) {
+        self.write(serverbound::EntityAction {
+            entity_id: VarInt(self.entity_id as i32),
+            action: if sprinting { Action::SprintStart } else { Action::SprintStop },
+            jump_boost: VarInt(0),
+        });
+    }
+
+    fn start_gliding(&mut self) {
+        self.write(serverbound::EntityAction {
+            entity_id: VarInt(self.entity_id as i32),
+            action: Action::ElytraFlyStart,
+            jump_boost: VarInt(0),
+        });
+    }
+
+    fn leave_bed(&mut self) {
+        self.write(serverbound::EntityAction {
+            entity_id: VarInt(self.entity_id as i32),
+            action: Action::LeaveBed,
+            jump_boost: VarInt(0),
+        });
+    }
+
+    fn abilities(&mut self, abilities: Abilities) {
+        self.write(serverbound::PlayerAbilities {
+            flags: abilities.flags(),
+        });
+    }
+
+    fn close_window(&mut self, window: u8) {
+        self.write(serverbound::CloseWindow {
+            window_id: window
+        });
+    }
+
+    /// not supported: 1.16 edits books with its own packet and item ids
+    fn edit_book(&mut self, _pages: &[String]) {}
+
+    /// not supported: 1.16 edits books with its own packet and item ids
+    fn sign_book(&mut self, _pages: &[String], _title: &str, _author: &str) {}
+
+    fn close(&mut self) -> Closing {
+        Box::pin(self.tx.borrow().close())
+    }
+}
+
+pub struct Protocol;
+
+fn keep_alive(id: u64) -> serverbound::KeepAlive {
+    serverbound::KeepAlive { id }
+}
+
+/// what the reader answers by itself
+const IMMEDIATE: Immediate<serverbound::KeepAlive> = Immediate {
+    keep_alive: clientbound::KeepAlive::ID,
+    time_update: clientbound::TimeUpdate::ID,
+    reply: keep_alive,
+};
+
+#[async_trait::async_trait]
+impl Minecraft for Protocol {
+    type Queue = EventQueue754;
+    type Interface = Interface754;
+
+    async fn login(conn: Connection) -> Result<Login<EventQueue754, Interface754>, SwarmError> {
+        let chat = conn.chat.clone();
+        let disconnects = conn.disconnects.clone();
+        let LoggedIn { reader, writer, username, uuid } = login::login(conn, PROTOCOL_VERSION).await?;
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (os_tx, os_rx) = tokio::sync::oneshot::channel();
+
+        let out_tx = writer.into_channel();
+        let ticks = TickTimer::default();
+
+        let mut oneshot = Some(os_tx);
+        reader.spawn(username.clone(), IMMEDIATE, out_tx.clone(), ticks.clone(), tx, move |packet| {
+            if packet.id == JoinGame::ID {
+                if let Some(os_tx) = oneshot.take() {
+                    let processed: JoinGame = packet.clone().read();
+                    os_tx.send(processed.entity_id).unwrap();
+                }
+            }
+        });
+
+        let tx = out_tx;
+
+        let entity_id = os_rx.await.map_err(|_| err("disconnected before join game packet")).ctx_phase(&username, Phase::Join)?;
+
+        let out = Interface754::new(tx, chat, entity_id);
+
+        let queue = EventQueue754 {
+            rx,
+            out: out.clone(),
+            location: Default::default(),
+            alive: true,
+            death_cause: None,
+            disconnects,
+            ticks,
+        };
+
+        Ok(Login {
+            queue,
+            out,
+            info: ClientInfo {
+                username,
+                uuid,
+                entity_id,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nbt::{Blob, Value};
+    use swarm_bot_packets::read::ByteReader;
+    use swarm_bot_packets::types::VarInt;
+    use swarm_bot_packets::write::ByteWriter;
+
+    use crate::chat::ChatEvent;
+    use crate::protocol::v754::Position;
+    use crate::protocol::v754::clientbound::{ChangeGameState, ChatMessage, ChunkSection, JoinGame, Respawn};
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::types::{Dimension, GameMode};
+
+    fn compound(entries: Vec<(&str, Value)>) -> Value {
+        Value::Compound(entries.into_iter().map(|(name, value)| (name.to_string(), value)).collect())
+    }
+
+    /// `entries` as the unnamed root compound the server sends
+    fn root(entries: Vec<(&str, Value)>) -> Vec<u8> {
+        let mut blob = Blob::new();
+        for (name, value) in entries {
+            blob.insert(name, value).unwrap();
+        }
+        let mut out = Vec::new();
+        blob.to_writer(&mut out).unwrap();
+        out
+    }
+
+    /// the dimension type a vanilla 1.16.5 server sends for the overworld or the nether
+    fn dimension_type(nether: bool) -> Vec<(&'static str, Value)> {
+        let flag = |set: bool| Value::Byte(set as i8);
+        vec![
+            ("piglin_safe", flag(nether)),
+            ("natural", flag(!nether)),
+            ("ambient_light", Value::Float(if nether { 0.1 } else { 0.0 })),
+            ("infiniburn", Value::from(if nether { "minecraft:infiniburn_nether" } else { "minecraft:infiniburn_overworld" })),
+            ("respawn_anchor_works", flag(nether)),
+            ("has_skylight", flag(!nether)),
+            ("bed_works", flag(!nether)),
+            ("effects", Value::from(if nether { "minecraft:the_nether" } else { "minecraft:overworld" })),
+            ("has_raids", flag(!nether)),
+            ("logical_height", Value::Int(if nether { 128 } else { 256 })),
+            ("coordinate_scale", Value::Double(if nether { 8.0 } else { 1.0 })),
+            ("ultrawarm", flag(nether)),
+            ("has_ceiling", flag(nether)),
+        ]
+    }
+
+    #[test]
+    fn test_position() {
+        for &(x, y, z) in &[(0, 0, 0), (1, 2, 3), (-1, -1, -1), (33_554_431, 2047, -33_554_432), (-300, 70, 12_345)] {
+            let location = BlockLocation::new(x, y, z);
+
+            let mut writer = ByteWriter::new();
+            writer.write(Position(location));
+
+            let mut reader = ByteReader::new(writer.freeze());
+            let Position(read) = reader.read();
+            assert_eq!(read, location);
+        }
+    }
+
+    #[test]
+    fn test_chunk_section() {
+        // palette of air, stone and granite. 5 bits per block so 12 values per long and 4 unused bits
+        let palette = [0, 1, 2];
+        let bits_per_block = 5;
+        let per_long = 64 / bits_per_block;
+
+        let value = |idx: usize| (idx % 7 % 3) as u64;
+
+        let longs = (4096 + per_long - 1) / per_long;
+        let mut storage = vec![0_u64; longs];
+        for idx in 0..4096 {
+            storage[idx / per_long] |= value(idx) << ((idx % per_long) * bits_per_block);
+        }
+
+        let mut writer = ByteWriter::new();
+        writer.write(4096_i16 as u16);
+        writer.write(bits_per_block as u8);
+        writer.write(VarInt(palette.len() as i32));
+        for &id in &palette {
+            writer.write(VarInt(id));
+        }
+        writer.write(VarInt(storage.len() as i32));
+        for long in storage {
+            writer.write(long);
+        }
+
+        let mut reader = ByteReader::new(writer.freeze());
+        let ChunkSection { states } = reader.read();
+
+        let expected = [BlockState::AIR, BlockState::from(1, 0), BlockState::from(1, 1)];
+        for (idx, state) in states.iter().enumerate() {
+            assert_eq!(*state, expected[value(idx) as usize], "block {}", idx);
+        }
+    }
+
+    #[test]
+    fn test_chat() {
+        let message = r#"{"translate":"chat.type.text","with":[{"insertion":"Notch","clickEvent":{"action":"suggest_command","value":"/tell Notch "},"text":"Notch"},"hello there"]}"#;
+
+        let mut writer = ByteWriter::new();
+        writer.write(message.to_string());
+        writer.write(1_u8);
+        writer.write(0_u128);
+
+        let mut reader = ByteReader::new(writer.freeze());
+        let ChatMessage { chat, position, .. } = reader.read();
+        assert_eq!(position, 1);
+
+        let message = ChatEvent::from(chat).player_message().unwrap();
+        assert_eq!(message.player, "Notch");
+        assert_eq!(message.message, "hello there");
+    }
+
+    #[test]
+    fn test_join_game() {
+        let registry = |kind: &str, name: &str, element: Value| compound(vec![
+            ("type", Value::from(kind)),
+            ("value", Value::List(vec![compound(vec![("name", Value::from(name)), ("id", Value::Int(0)), ("element", element)])])),
+        ]);
+        let plains = compound(vec![
+            ("precipitation", Value::from("rain")),
+            ("depth", Value::Float(0.125)),
+            ("temperature", Value::Float(0.8)),
+            ("scale", Value::Float(0.05)),
+            ("downfall", Value::Float(0.4)),
+            ("category", Value::from("plains")),
+        ]);
+        let codec = root(vec![
+            ("minecraft:dimension_type", registry("minecraft:dimension_type", "minecraft:overworld", compound(dimension_type(false)))),
+            ("minecraft:worldgen/biome", registry("minecraft:worldgen/biome", "minecraft:plains", plains)),
+        ]);
+        let dimension = root(dimension_type(true));
+
+        let mut writer = ByteWriter::new();
+        writer.write(&217_i32.to_be_bytes()[..]);
+        writer.write(false);
+        writer.write(1_u8);
+
+        // no previous gamemode (-1) is a single byte, not a VarInt
+        writer.write(0xFF_u8);
+        writer.write(VarInt(3));
+        for &world in &["minecraft:overworld", "minecraft:the_nether", "minecraft:the_end"] {
+            writer.write(world.to_string());
+        }
+        writer.write(&codec[..]);
+        writer.write(&dimension[..]);
+        writer.write("minecraft:the_nether".to_string());
+        writer.write(0x8F3A_52C1_07D4_E9B6_u64);
+        writer.write(VarInt(20));
+        writer.write(VarInt(10));
+        writer.write(false);
+        writer.write(true);
+        writer.write(false);
+        writer.write(false);
+
+        let mut reader = ByteReader::new(writer.freeze());
+        let JoinGame { entity_id, game_mode, dimension, world_name } = reader.read();
+        assert_eq!(entity_id, 217);
+        assert_eq!(game_mode, GameMode::Creative);
+        assert_eq!(dimension, Dimension::Nether);
+        assert_eq!(world_name, "minecraft:the_nether");
+    }
+
+    #[test]
+    fn test_respawn() {
+        let dimension = root(dimension_type(true));
+
+        let mut writer = ByteWriter::new();
+        writer.write(&dimension[..]);
+        writer.write("minecraft:the_nether".to_string());
+
+        // the first byte of the hashed seed is not a gamemode
+        writer.write(0x8F3A_52C1_07D4_E9B6_u64);
+        writer.write(2_u8);
+        writer.write(0_u8);
+        writer.write(false);
+        writer.write(false);
+        writer.write(true);
+
+        let mut reader = ByteReader::new(writer.freeze());
+        let Respawn { dimension, world_name, gamemode } = reader.read();
+        assert_eq!(dimension, Dimension::Nether);
+        assert_eq!(world_name, "minecraft:the_nether");
+        assert_eq!(gamemode, GameMode::Adventure);
+
+        // previous gamemode, debug, flat and copy metadata are left
+        assert_eq!(reader.len(), 4);
+    }
+
+    #[test]
+    fn test_change_game_state() {
+        // reason 3 (change gamemode) with 3.0 (spectator)
+        let mut reader = ByteReader::new(vec![0x03, 0x40, 0x40, 0x00, 0x00]);
+        let ChangeGameState { reason, value } = reader.read();
+        assert_eq!(reason, ChangeGameState::CHANGE_GAMEMODE);
+        assert_eq!(GameMode::from_id(value as u8), Some(GameMode::Spectator));
+
+        // reason 7 (fade value) with 0.5
+        let mut reader = ByteReader::new(vec![0x07, 0x3F, 0x00, 0x00, 0x00]);
+        let ChangeGameState { reason, value } = reader.read();
+        assert_ne!(reason, ChangeGameState::CHANGE_GAMEMODE);
+        assert_eq!(value, 0.5);
+    }
+}
@@ -0,0 +1,150 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use swarm_bot_packets::*;
+use swarm_bot_packets::types::VarInt;
+
+use crate::protocol::v340::serverbound::{Action, BlockCursor, ClientStatusAction, DigStatus, Hand, InteractEntityKind};
+use crate::protocol::v754::Position;
+use crate::types::{Direction, Location};
+
+#[derive(Writable, Packet)]
+#[packet(0x00, Play)]
+pub struct TeleportConfirm {
+    pub teleport_id: VarInt,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x03, Play)]
+pub struct ChatMessage {
+    pub message: String,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x04, Play)]
+pub struct ClientStatus {
+    pub action: ClientStatusAction,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x0E, Play)]
+pub struct InteractEntity {
+    pub id: VarInt,
+    pub kind: InteractEntityKind,
+    pub sneaking: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x10, Play)]
+pub struct KeepAlive {
+    pub id: u64,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x12, Play)]
+pub struct PlayerPosition {
+    pub location: Location,
+    pub on_ground: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x13, Play)]
+pub struct PlayerPositionAndRotation {
+    pub location: Location,
+    pub direction: Direction,
+    pub on_ground: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x14, Play)]
+pub struct PlayerLook {
+    pub direction: Direction,
+    pub on_ground: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x15, Play)]
+pub struct PlayerMovement {
+    pub on_ground: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x1B, Play)]
+pub struct PlayerDig {
+    pub status: DigStatus,
+    pub position: Position,
+    pub face: u8,
+}
+
+impl PlayerDig {
+    pub fn status(status: DigStatus) -> PlayerDig {
+        Self {
+            status,
+            position: Position::default(),
+            face: 0,
+        }
+    }
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x0A, Play)]
+pub struct CloseWindow {
+    pub window_id: u8,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x25, Play)]
+pub struct HeldItemChange {
+    pub slot: u16,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x2C, Play)]
+pub struct ArmAnimation {
+    pub hand: Hand,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x1C, Play)]
+pub struct EntityAction {
+    /// player id
+    pub entity_id: VarInt,
+    pub action: Action,
+    pub jump_boost: VarInt,
+}
+
+/// start or stop flying. Unlike 1.12 the speeds are no longer sent.
+#[derive(Writable, Packet)]
+#[packet(0x1A, Play)]
+pub struct PlayerAbilities {
+    pub flags: u8,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x2E, Play)]
+pub struct PlaceBlock {
+    pub hand: Hand,
+    pub location: Position,
+    pub face: VarInt,
+    pub cursor: BlockCursor,
+    pub inside_block: bool,
+}
+
+#[derive(Writable, Packet)]
+#[packet(0x2F, Play)]
+pub struct UseItem {
+    pub hand: Hand,
+}
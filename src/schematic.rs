@@ -14,11 +14,23 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::io::Read;
+use std::io::{Read, Write};
 
+use flate2::read::GzDecoder;
 use serde::{Deserialize, Serialize};
 
+use crate::error::{err, Res};
+use crate::nbt;
+use crate::nbt::{Compound, NbtError, NbtLimits, Tag};
 use crate::storage::block::{BlockLocation, BlockState};
+use crate::storage::blocks::WorldBlocks;
+
+pub mod litematic;
+pub mod sponge;
+
+/// The most blocks a schematic is captured of. Ids and data take a byte each, and a larger schematic could not be
+/// loaded again with [`NbtLimits::FILE`].
+const MAX_VOLUME: u64 = NbtLimits::FILE.max_size as u64 / 2;
 
 /// https://minecraft.fandom.com/wiki/Schematic_file_format
 #[derive(Serialize, Deserialize, Debug)]
@@ -28,25 +40,118 @@ pub struct Schematic {
     pub height: i16,
     pub length: i16,
     materials: String,
+    #[serde(serialize_with = "::nbt::i8_array")]
     blocks: Vec<i8>,
+    #[serde(skip_serializing)]
     add_blocks: Option<Vec<i8>>,
+    #[serde(serialize_with = "::nbt::i8_array")]
     data: Vec<i8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     w_e_origin_x: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     w_e_origin_y: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     w_e_origin_z: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     w_e_offset_x: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     w_e_offset_y: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     w_e_offset_z: Option<i32>,
 }
 
 impl Schematic {
+    /// `width` x `height` x `length` blocks of air with the lowest corner at `origin`
+    pub fn new(origin: BlockLocation, width: i16, height: i16, length: i16) -> Schematic {
+        let volume = width as usize * height as usize * length as usize;
+        Schematic {
+            width,
+            height,
+            length,
+            materials: "Alpha".to_string(),
+            blocks: vec![0; volume],
+            add_blocks: None,
+            data: vec![0; volume],
+            w_e_origin_x: Some(origin.x),
+            w_e_origin_y: Some(origin.y as i32),
+            w_e_origin_z: Some(origin.z),
+            w_e_offset_x: Some(0),
+            w_e_offset_y: Some(0),
+            w_e_offset_z: Some(0),
+        }
+    }
+
     pub fn volume(&self) -> u64 {
         (self.width as u64) * (self.height as u64) * (self.length as u64)
     }
 
-    pub fn load(reader: &mut impl Read) -> Schematic {
-        let res: Result<Schematic, _> = nbt::from_gzip_reader(reader);
-        res.unwrap()
+    /// Load a gzipped schematic in the MCEdit, Sponge or Litematica format. Schematics are trusted local files so they are read
+    /// with [`NbtLimits::FILE`].
+    pub fn load(reader: &mut impl Read) -> Res<Schematic> {
+        let limits = &NbtLimits::FILE;
+
+        let mut data = Vec::new();
+        GzDecoder::new(reader).take(limits.max_size as u64).read_to_end(&mut data)?;
+
+        let nbt = nbt::read(&data, limits)?;
+        if nbt.root.get("Palette").is_some() {
+            return Schematic::from_sponge(&nbt.root);
+        }
+        if nbt.root.get("Regions").is_some() {
+            return Schematic::from_litematic(&nbt.root);
+        }
+        Ok(Schematic::from_nbt(&nbt.root)?)
+    }
+
+    fn from_nbt(root: &Compound) -> Result<Schematic, NbtError> {
+        let short = |name| root.get(name).and_then(Tag::as_i16).ok_or(NbtError::Missing(name));
+        let int = |name| root.get(name).and_then(Tag::as_i32);
+        let bytes = |name| root.get(name).and_then(Tag::as_bytes).map(|bytes| bytes.iter().map(|&b| b as i8).collect());
+
+        Ok(Schematic {
+            width: short("Width")?,
+            height: short("Height")?,
+            length: short("Length")?,
+            materials: root.get("Materials").and_then(Tag::as_str).ok_or(NbtError::Missing("Materials"))?.to_string(),
+            blocks: bytes("Blocks").ok_or(NbtError::Missing("Blocks"))?,
+            add_blocks: bytes("AddBlocks"),
+            data: bytes("Data").ok_or(NbtError::Missing("Data"))?,
+            w_e_origin_x: int("WEOriginX"),
+            w_e_origin_y: int("WEOriginY"),
+            w_e_origin_z: int("WEOriginZ"),
+            w_e_offset_x: int("WEOffsetX"),
+            w_e_offset_y: int("WEOffsetY"),
+            w_e_offset_z: int("WEOffsetZ"),
+        })
+    }
+
+    /// The blocks `world` knows between the corners `from` and `to` (inclusive) with the origin at the lowest corner,
+    /// and how many columns were not loaded. Save it with [`Schematic::save`]. Fails if either corner is outside the
+    /// build height, the cuboid is 32767 blocks or more across, as schematics store their size in shorts, or it has
+    /// more than [`MAX_VOLUME`] blocks.
+    pub fn capture(world: &WorldBlocks, from: BlockLocation, to: BlockLocation) -> Res<(Schematic, usize)> {
+        let min = BlockLocation::new(from.x.min(to.x), from.y.min(to.y), from.z.min(to.z));
+        let max = BlockLocation::new(from.x.max(to.x), from.y.max(to.y), from.z.max(to.z));
+        if min.y < 0 || max.y > 255 {
+            return Err(err(&format!("{} to {} is outside the build height", from, to)));
+        }
+
+        let too_large = |from: i32, to: i32| to.checked_sub(from).map_or(true, |span| span >= i16::MAX as i32);
+        if too_large(min.x, max.x) || too_large(min.z, max.z) {
+            return Err(err(&format!("{} to {} is too large for a schematic", from, to)));
+        }
+
+        let span = |from: i32, to: i32| (to - from + 1) as u64;
+        let volume = span(min.x, max.x) * span(min.y as i32, max.y as i32) * span(min.z, max.z);
+        if volume > MAX_VOLUME {
+            return Err(err(&format!("{} to {} is {} blocks, more than the {} a schematic can hold", from, to, volume, MAX_VOLUME)));
+        }
+        Ok(world.to_schematic(min, max))
+    }
+
+    /// Save the schematic gzipped. Blocks with ids above 255 (which only mods have) are not supported.
+    pub fn save(&self, writer: &mut impl Write) -> Res {
+        ::nbt::to_gzip_writer(writer, self, Some("Schematic")).map_err(|error| err(&format!("could not write schematic: {}", error)))
     }
 
     pub fn is_valid(&self) -> bool {
@@ -83,6 +188,17 @@ impl Schematic {
     }
 
 
+    /// Set the block at `location` (relative to the origin). Panics if it is outside of the schematic.
+    pub fn set_block(&mut self, location: BlockLocation, state: BlockState) {
+        let origin = self.origin().unwrap_or_default();
+        let (x, y, z) = (location.x - origin.x, location.y - origin.y, location.z - origin.z);
+        assert!((0..self.width as i32).contains(&x) && (0..self.height).contains(&y) && (0..self.length as i32).contains(&z), "{} is outside of the schematic", location);
+
+        let idx = x as usize + (z as usize + y as usize * self.length as usize) * self.width as usize;
+        self.blocks[idx] = state.id() as i8;
+        self.data[idx] = state.metadata() as i8;
+    }
+
     pub fn blocks(&self) -> impl Iterator<Item=(BlockLocation, BlockState)> + '_ {
         let origin = self.origin().unwrap_or_default();
 
@@ -169,7 +285,8 @@ mod tests {
     use more_asserts::*;
 
     use crate::schematic::Schematic;
-    use crate::storage::block::BlockLocation;
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
 
     #[test]
     fn test_load() {
@@ -178,7 +295,7 @@ mod tests {
             .open("test-data/parkour.schematic")
             .unwrap();
 
-        let schematic = Schematic::load(&mut reader);
+        let schematic = Schematic::load(&mut reader).unwrap();
 
         assert!(schematic.is_valid());
 
@@ -200,4 +317,47 @@ mod tests {
         let stained_glass = map[&BlockLocation::new(-162, 81, -357)];
         assert_eq!(stained_glass.id(), 95);
     }
+
+    #[test]
+    fn test_capture() {
+        let mut world = WorldBlocks::flat();
+        world.set_block(BlockLocation::new(5, 1, -3), BlockState::from(1, 2));
+
+        let (schematic, missing) = Schematic::capture(&world, BlockLocation::new(6, 1, 0), BlockLocation::new(4, 0, -3)).unwrap();
+        assert_eq!(missing, 0);
+        assert_eq!(schematic.origin(), Some(BlockLocation::new(4, 0, -3)));
+        assert_eq!((schematic.width(), schematic.height(), schematic.length()), (3, 2, 4));
+
+        let blocks: HashMap<_, _> = schematic.blocks().collect();
+        assert_eq!(blocks[&BlockLocation::new(5, 1, -3)], BlockState::from(1, 2));
+        assert_eq!(blocks[&BlockLocation::new(6, 0, 0)], BlockState::STONE);
+
+        assert!(Schematic::capture(&world, BlockLocation::new(0, 0, 0), BlockLocation::new(40_000, 0, 0)).is_err());
+
+        // fits in shorts but is hundreds of gigabytes
+        assert!(Schematic::capture(&world, BlockLocation::new(0, 0, 0), BlockLocation::new(32_765, 255, 32_765)).is_err());
+    }
+
+    #[test]
+    fn test_capture_inverted() {
+        let world = WorldBlocks::flat();
+
+        // the corners are swapped on every axis and the span does not fit in an i32
+        let from = BlockLocation::new(i32::MAX, 255, i32::MAX);
+        let to = BlockLocation::new(i32::MIN, 0, i32::MIN);
+        assert!(Schematic::capture(&world, from, to).is_err());
+
+        let (schematic, _) = Schematic::capture(&world, BlockLocation::new(2, 3, 2), BlockLocation::new(-2, 0, -2)).unwrap();
+        assert_eq!(schematic.origin(), Some(BlockLocation::new(-2, 0, -2)));
+        assert_eq!((schematic.width(), schematic.height(), schematic.length()), (5, 4, 5));
+    }
+
+    #[test]
+    fn test_capture_out_of_height() {
+        let world = WorldBlocks::flat();
+
+        assert!(Schematic::capture(&world, BlockLocation::new(0, -1, 0), BlockLocation::new(1, 1, 1)).is_err());
+        assert!(Schematic::capture(&world, BlockLocation::new(0, 0, 0), BlockLocation::new(1, 256, 1)).is_err());
+        assert!(Schematic::capture(&world, BlockLocation::new(0, 0, 0), BlockLocation::new(1, 255, 1)).is_ok());
+    }
 }
@@ -0,0 +1,263 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! [Litematica](https://github.com/maruohon/litematica) schematics (`.litematic`), the format most building
+//! communities share builds in. All regions are placed into one schematic covering them, with its origin where the
+//! schematic is placed from. Blocks are mapped to 1.12 like the ones of [Sponge schematics](super::sponge).
+
+use std::convert::TryFrom;
+
+use crate::error::{err, Res};
+use crate::nbt::{Compound, NbtError, Tag};
+use crate::schematic::Schematic;
+use crate::schematic::sponge::legacy;
+use crate::storage::block::{BlockLocation, BlockState};
+use crate::storage::chunk::bits_needed;
+
+/// A box of blocks. Regions can overlap, the blocks of later regions win where they are not air.
+struct Region<'a> {
+    /// the lowest corner
+    min: (i32, i32, i32),
+    size: (i32, i32, i32),
+    palette: Vec<BlockState>,
+    bits: usize,
+
+    /// indices into the palette packed across longs, in y z x order
+    states: &'a [i64],
+}
+
+fn vector(compound: &Compound, name: &'static str) -> Res<(i32, i32, i32)> {
+    let vector = compound.get(name).and_then(Tag::as_compound).ok_or(NbtError::Missing(name))?;
+    let int = |axis| vector.get(axis).and_then(Tag::as_i32).ok_or(NbtError::Missing(axis));
+    Ok((int("x")?, int("y")?, int("z")?))
+}
+
+/// a palette entry written like `minecraft:oak_stairs[facing=east,half=bottom]`
+fn block_name(entry: &Compound) -> Option<String> {
+    let name = entry.get("Name")?.as_str()?;
+    let properties = entry.get("Properties").and_then(Tag::as_compound).map(|properties| {
+        properties.iter()
+            .filter_map(|(key, value)| Some(format!("{}={}", key, value.as_str()?)))
+            .collect::<Vec<_>>()
+            .join(",")
+    });
+
+    Some(match properties {
+        Some(properties) if !properties.is_empty() => format!("{}[{}]", name, properties),
+        _ => name.to_string()
+    })
+}
+
+/// entry `idx` of `bits` bits of `states`. Entries can span two longs.
+fn packed(states: &[i64], bits: usize, idx: usize) -> Option<usize> {
+    let start = idx * bits;
+    let (long, offset) = (start / 64, start % 64);
+
+    let mut value = *states.get(long)? as u64 >> offset;
+    if offset + bits > 64 {
+        value |= (*states.get(long + 1)? as u64) << (64 - offset);
+    }
+    Some((value & ((1 << bits) - 1)) as usize)
+}
+
+impl<'a> Region<'a> {
+    fn read(region: &'a Compound<'a>, unknown: &mut Vec<String>) -> Res<Region<'a>> {
+        let position = vector(region, "Position")?;
+        let size = vector(region, "Size")?;
+
+        // a negative size goes from the position down
+        let corner = |position: i32, size: i32| if size < 0 { position + size + 1 } else { position };
+        let min = (corner(position.0, size.0), corner(position.1, size.1), corner(position.2, size.2));
+        let size = (size.0.abs(), size.1.abs(), size.2.abs());
+
+        let entries = region.get("BlockStatePalette").and_then(Tag::as_list).ok_or(NbtError::Missing("BlockStatePalette"))?;
+        let mut palette = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let name = entry.as_compound().and_then(block_name).ok_or_else(|| err("palette entry of the schematic has no name"))?;
+            palette.push(legacy(&name).unwrap_or_else(|| {
+                unknown.push(name);
+                BlockState::AIR
+            }));
+        }
+
+        let states = region.get("BlockStates").and_then(Tag::as_i64s).ok_or(NbtError::Missing("BlockStates"))?;
+        let bits = bits_needed(palette.len()).max(2) as usize;
+
+        Ok(Region { min, size, palette, bits, states })
+    }
+
+    fn max(&self) -> (i32, i32, i32) {
+        (self.min.0 + self.size.0 - 1, self.min.1 + self.size.1 - 1, self.min.2 + self.size.2 - 1)
+    }
+
+    fn volume(&self) -> usize {
+        self.size.0 as usize * self.size.1 as usize * self.size.2 as usize
+    }
+
+    /// every block with its location relative to the placement
+    fn blocks(&self) -> impl Iterator<Item=Res<(BlockLocation, BlockState)>> + '_ {
+        let (size_x, _, size_z) = (self.size.0 as usize, self.size.1 as usize, self.size.2 as usize);
+        (0..self.volume()).map(move |idx| {
+            let state = packed(self.states, self.bits, idx)
+                .and_then(|value| self.palette.get(value))
+                .ok_or_else(|| err("the block states of the schematic do not match its palette"))?;
+
+            let (x, z, y) = (idx % size_x, idx / size_x % size_z, idx / (size_x * size_z));
+            let location = BlockLocation::new(self.min.0 + x as i32, (self.min.1 + y as i32) as i16, self.min.2 + z as i32);
+            Ok((location, *state))
+        })
+    }
+}
+
+impl Schematic {
+    /// a Litematica schematic with its regions merged, converted to the MCEdit format
+    pub(super) fn from_litematic(root: &Compound) -> Res<Schematic> {
+        let regions = root.get("Regions").and_then(Tag::as_compound).ok_or(NbtError::Missing("Regions"))?;
+
+        let mut unknown = Vec::new();
+        let regions = regions.iter()
+            .map(|(_, region)| {
+                let region = region.as_compound().ok_or_else(|| err("region of the schematic is not a compound"))?;
+                Region::read(region, &mut unknown)
+            })
+            .collect::<Res<Vec<_>>>()?;
+
+        if !unknown.is_empty() {
+            unknown.sort();
+            unknown.dedup();
+            warn!(blocks = ?unknown, "the schematic has blocks which are not in 1.12, they are left out");
+        }
+
+        let first = regions.first().ok_or_else(|| err("the schematic has no regions"))?;
+        let (mut min, mut max) = (first.min, first.max());
+        for region in &regions {
+            let (low, high) = (region.min, region.max());
+            min = (min.0.min(low.0), min.1.min(low.1), min.2.min(low.2));
+            max = (max.0.max(high.0), max.1.max(high.1), max.2.max(high.2));
+        }
+
+        let side = |low: i32, high: i32| i16::try_from(high - low + 1).map_err(|_| err("the schematic is too large"));
+        let origin = BlockLocation::new(min.0, i16::try_from(min.1).map_err(|_| err("the schematic is out of the world"))?, min.2);
+        let mut schematic = Schematic::new(origin, side(min.0, max.0)?, side(min.1, max.1)?, side(min.2, max.2)?);
+
+        for region in &regions {
+            for block in region.blocks() {
+                let (location, state) = block?;
+                if state != BlockState::AIR {
+                    schematic.set_block(location, state);
+                }
+            }
+        }
+        Ok(schematic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Serialize;
+
+    use crate::schematic::Schematic;
+    use crate::schematic::litematic::packed;
+    use crate::storage::block::{BlockLocation, BlockState};
+
+    #[derive(Serialize)]
+    struct Vector {
+        x: i32,
+        y: i32,
+        z: i32,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Entry {
+        name: &'static str,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        properties: Option<HashMap<&'static str, &'static str>>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Region {
+        position: Vector,
+        size: Vector,
+        block_state_palette: Vec<Entry>,
+
+        #[serde(serialize_with = "::nbt::i64_array")]
+        block_states: Vec<i64>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Litematic {
+        version: i32,
+        regions: HashMap<&'static str, Region>,
+    }
+
+    fn entry(name: &'static str) -> Entry {
+        Entry { name, properties: None }
+    }
+
+    #[test]
+    fn test_packed() {
+        // the entry at bit 63 of 3 bits (5) spans two longs
+        let states = [i64::MIN, 0b10];
+        assert_eq!(packed(&states, 3, 21), Some(5));
+        assert_eq!(packed(&states, 3, 0), Some(0));
+        assert_eq!(packed(&states, 3, 42), None);
+    }
+
+    #[test]
+    fn test_load_litematic() {
+        let first = Region {
+            position: Vector { x: 0, y: 0, z: 0 },
+            size: Vector { x: 2, y: 1, z: 1 },
+            block_state_palette: vec![entry("minecraft:air"), entry("minecraft:granite")],
+            block_states: vec![0b0001],
+        };
+
+        // from x = 3 down to x = 2
+        let second = Region {
+            position: Vector { x: 3, y: 1, z: 0 },
+            size: Vector { x: -2, y: 1, z: 1 },
+            block_state_palette: vec![
+                entry("minecraft:air"),
+                Entry { name: "minecraft:oak_stairs", properties: Some(vec![("facing", "north"), ("half", "bottom")].into_iter().collect()) },
+                entry("minecraft:stone_bricks"),
+            ],
+            block_states: vec![0b0110],
+        };
+
+        let litematic = Litematic {
+            version: 5,
+            regions: vec![("first", first), ("second", second)].into_iter().collect(),
+        };
+
+        let mut file = Vec::new();
+        nbt::to_gzip_writer(&mut file, &litematic, None).unwrap();
+        let schematic = Schematic::load(&mut &file[..]).unwrap();
+
+        assert_eq!((schematic.width(), schematic.height(), schematic.length()), (4, 2, 1));
+        let blocks: Vec<_> = schematic.blocks().filter(|(_, state)| *state != BlockState::AIR).collect();
+        assert_eq!(blocks, vec![
+            (BlockLocation::new(0, 0, 0), BlockState::from(1, 1)),
+            (BlockLocation::new(2, 1, 0), BlockState::from(98, 0)),
+            (BlockLocation::new(3, 1, 0), BlockState::from(53, 3)),
+        ]);
+    }
+}
@@ -0,0 +1,456 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The [Sponge schematic format](https://github.com/SpongePowered/Schematic-Specification) (versions 1 and 2) which
+//! WorldEdit writes since 1.13. Blocks are stored by name and mapped to the 1.12 states the bot uses. Blocks 1.12 does
+//! not have are left out (air).
+
+use std::collections::HashMap;
+
+use crate::error::{err, Res};
+use crate::nbt::{Compound, NbtError, Tag};
+use crate::schematic::Schematic;
+use crate::storage::block::BlockState;
+
+/// the `minecraft:` names of blocks with no properties which matter for 1.12 and their (id, metadata)
+const BLOCKS: &[(&str, u32, u16)] = &[
+    ("air", 0, 0),
+    ("cave_air", 0, 0),
+    ("void_air", 0, 0),
+    ("stone", 1, 0),
+    ("granite", 1, 1),
+    ("polished_granite", 1, 2),
+    ("diorite", 1, 3),
+    ("polished_diorite", 1, 4),
+    ("andesite", 1, 5),
+    ("polished_andesite", 1, 6),
+    ("grass_block", 2, 0),
+    ("dirt", 3, 0),
+    ("coarse_dirt", 3, 1),
+    ("podzol", 3, 2),
+    ("cobblestone", 4, 0),
+    ("bedrock", 7, 0),
+    ("water", 9, 0),
+    ("lava", 11, 0),
+    ("sand", 12, 0),
+    ("red_sand", 12, 1),
+    ("gravel", 13, 0),
+    ("gold_ore", 14, 0),
+    ("iron_ore", 15, 0),
+    ("coal_ore", 16, 0),
+    ("sponge", 19, 0),
+    ("wet_sponge", 19, 1),
+    ("glass", 20, 0),
+    ("lapis_ore", 21, 0),
+    ("lapis_block", 22, 0),
+    ("sandstone", 24, 0),
+    ("chiseled_sandstone", 24, 1),
+    ("cut_sandstone", 24, 2),
+    ("note_block", 25, 0),
+    ("grass", 31, 1),
+    ("fern", 31, 2),
+    ("dead_bush", 32, 0),
+    ("dandelion", 37, 0),
+    ("poppy", 38, 0),
+    ("gold_block", 41, 0),
+    ("iron_block", 42, 0),
+    ("bricks", 45, 0),
+    ("tnt", 46, 0),
+    ("bookshelf", 47, 0),
+    ("mossy_cobblestone", 48, 0),
+    ("obsidian", 49, 0),
+    ("torch", 50, 5),
+    ("chest", 54, 0),
+    ("diamond_ore", 56, 0),
+    ("diamond_block", 57, 0),
+    ("crafting_table", 58, 0),
+    ("farmland", 60, 0),
+    ("furnace", 61, 0),
+    ("redstone_ore", 73, 0),
+    ("ice", 79, 0),
+    ("snow_block", 80, 0),
+    ("cactus", 81, 0),
+    ("clay", 82, 0),
+    ("jukebox", 84, 0),
+    ("pumpkin", 86, 0),
+    ("netherrack", 87, 0),
+    ("soul_sand", 88, 0),
+    ("glowstone", 89, 0),
+    ("stone_bricks", 98, 0),
+    ("mossy_stone_bricks", 98, 1),
+    ("cracked_stone_bricks", 98, 2),
+    ("chiseled_stone_bricks", 98, 3),
+    ("iron_bars", 101, 0),
+    ("glass_pane", 102, 0),
+    ("melon", 103, 0),
+    ("mycelium", 110, 0),
+    ("nether_bricks", 112, 0),
+    ("end_stone", 121, 0),
+    ("emerald_ore", 129, 0),
+    ("emerald_block", 133, 0),
+    ("beacon", 138, 0),
+    ("cobblestone_wall", 139, 0),
+    ("mossy_cobblestone_wall", 139, 1),
+    ("redstone_block", 152, 0),
+    ("quartz_block", 155, 0),
+    ("chiseled_quartz_block", 155, 1),
+    ("quartz_pillar", 155, 2),
+    ("slime_block", 165, 0),
+    ("prismarine", 168, 0),
+    ("prismarine_bricks", 168, 1),
+    ("dark_prismarine", 168, 2),
+    ("sea_lantern", 169, 0),
+    ("hay_block", 170, 0),
+    ("terracotta", 172, 0),
+    ("coal_block", 173, 0),
+    ("packed_ice", 174, 0),
+    ("red_sandstone", 179, 0),
+    ("chiseled_red_sandstone", 179, 1),
+    ("cut_red_sandstone", 179, 2),
+    ("purpur_block", 201, 0),
+    ("purpur_pillar", 202, 0),
+    ("end_stone_bricks", 206, 0),
+    ("magma_block", 213, 0),
+    ("nether_wart_block", 214, 0),
+    ("red_nether_bricks", 215, 0),
+    ("bone_block", 216, 0),
+];
+
+/// in the order of their 1.12 metadata
+const COLORS: &[&str] = &[
+    "white", "orange", "magenta", "light_blue", "yellow", "lime", "pink", "gray", "light_gray", "cyan", "purple", "blue",
+    "brown", "green", "red", "black",
+];
+
+/// blocks with a color as the metadata
+const COLORED: &[(&str, u32)] = &[
+    ("wool", 35),
+    ("stained_glass", 95),
+    ("terracotta", 159),
+    ("stained_glass_pane", 160),
+    ("carpet", 171),
+    ("concrete", 251),
+    ("concrete_powder", 252),
+];
+
+/// in the order of their 1.12 metadata
+const WOODS: &[&str] = &["oak", "spruce", "birch", "jungle", "acacia", "dark_oak"];
+
+/// the stairs which are not wooden
+const STAIRS: &[(&str, u32)] = &[
+    ("cobblestone", 67),
+    ("brick", 108),
+    ("stone_brick", 109),
+    ("nether_brick", 114),
+    ("sandstone", 128),
+    ("quartz", 156),
+    ("red_sandstone", 180),
+    ("purpur", 203),
+];
+
+/// the slabs which are not wooden: (material, id, metadata, id of the double slab)
+const SLABS: &[(&str, u32, u16, u32)] = &[
+    ("stone", 44, 0, 43),
+    ("smooth_stone", 44, 0, 43),
+    ("sandstone", 44, 1, 43),
+    ("petrified_oak", 44, 2, 43),
+    ("cobblestone", 44, 3, 43),
+    ("brick", 44, 4, 43),
+    ("stone_brick", 44, 5, 43),
+    ("nether_brick", 44, 6, 43),
+    ("quartz", 44, 7, 43),
+    ("red_sandstone", 182, 0, 181),
+    ("purpur", 205, 0, 204),
+];
+
+/// The 1.12 state of a block named like `minecraft:oak_stairs[facing=east,half=bottom]`. None if 1.12 does not have
+/// the block (or it is not mapped).
+pub fn legacy(block: &str) -> Option<BlockState> {
+    let (name, properties) = match block.split_once('[') {
+        Some((name, properties)) => (name, properties.trim_end_matches(']')),
+        None => (block, ""),
+    };
+    let name = name.trim_start_matches("minecraft:");
+
+    let property = |key: &str| properties.split(',')
+        .filter_map(|property| property.split_once('='))
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| value);
+
+    // the metadata of stairs and slabs which depends on their properties
+    let stairs = || {
+        let facing = match property("facing") {
+            Some("west") => 1,
+            Some("south") => 2,
+            Some("north") => 3,
+            _ => 0,
+        };
+        if property("half") == Some("top") { facing | 4 } else { facing }
+    };
+    let slab = |id: u32, meta: u16, double: u32| match property("type") {
+        Some("top") => BlockState::from(id, meta | 8),
+        Some("double") => BlockState::from(double, meta),
+        _ => BlockState::from(id, meta),
+    };
+    let axis = || match property("axis") {
+        Some("x") => 4,
+        Some("z") => 8,
+        _ => 0,
+    };
+
+    if let Some(&(_, id, meta)) = BLOCKS.iter().find(|(known, ..)| *known == name) {
+        return Some(BlockState::from(id, meta));
+    }
+
+    for (color, &color_name) in COLORS.iter().enumerate() {
+        if let Some(kind) = name.strip_prefix(color_name).and_then(|rest| rest.strip_prefix('_')) {
+            if let Some(&(_, id)) = COLORED.iter().find(|(colored, _)| *colored == kind) {
+                return Some(BlockState::from(id, color as u16));
+            }
+        }
+    }
+
+    for (wood, &wood_name) in WOODS.iter().enumerate() {
+        let kind = match name.strip_prefix(wood_name).and_then(|rest| rest.strip_prefix('_')) {
+            Some(kind) => kind,
+            None => continue
+        };
+
+        // the first four woods were there before the others were added with new ids
+        let (old, meta) = (wood < 4, wood as u16 % 4);
+        let state = match kind {
+            "planks" => BlockState::from(5, wood as u16),
+            "sapling" => BlockState::from(6, wood as u16),
+            "log" => BlockState::from(if old { 17 } else { 162 }, meta | axis()),
+            "wood" => BlockState::from(if old { 17 } else { 162 }, meta | 12),
+            "leaves" => BlockState::from(if old { 18 } else { 161 }, meta),
+            "slab" => slab(126, wood as u16, 125),
+            "stairs" => BlockState::from([53, 134, 135, 136, 163, 164][wood], stairs()),
+            "fence" => BlockState::from([85, 188, 189, 190, 192, 191][wood], 0),
+            _ => continue
+        };
+        return Some(state);
+    }
+
+    if let Some(material) = name.strip_suffix("_stairs") {
+        let &(_, id) = STAIRS.iter().find(|(known, _)| *known == material)?;
+        return Some(BlockState::from(id, stairs()));
+    }
+
+    if let Some(material) = name.strip_suffix("_slab") {
+        let &(_, id, meta, double) = SLABS.iter().find(|(known, ..)| *known == material)?;
+        return Some(slab(id, meta, double));
+    }
+
+    let facing = |east, west, south, north| match property("facing") {
+        Some("east") => east,
+        Some("west") => west,
+        Some("south") => south,
+        _ => north,
+    };
+
+    match name {
+        "wall_torch" => Some(BlockState::from(50, facing(1, 2, 3, 4))),
+        "ladder" => Some(BlockState::from(65, facing(5, 4, 3, 2))),
+        _ => None
+    }
+}
+
+fn var_int(bytes: &mut impl Iterator<Item=u8>) -> Option<i32> {
+    let mut value = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = bytes.next()?;
+        value |= ((byte & 0x7F) as i32) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+impl Schematic {
+    /// a Sponge schematic converted to the MCEdit format
+    pub(super) fn from_sponge(root: &Compound) -> Res<Schematic> {
+        match root.get("Version").and_then(Tag::as_i32) {
+            Some(1) | Some(2) => {}
+            version => return Err(err(&format!("version {:?} of Sponge schematics is not supported", version)))
+        }
+
+        let short = |name| root.get(name).and_then(Tag::as_i16).ok_or(NbtError::Missing(name));
+        let (width, height, length) = (short("Width")?, short("Height")?, short("Length")?);
+
+        let palette = root.get("Palette").and_then(Tag::as_compound).ok_or(NbtError::Missing("Palette"))?;
+        let mut states = HashMap::new();
+        let mut unknown = Vec::new();
+        for (name, idx) in palette.iter() {
+            let idx = idx.as_i32().ok_or_else(|| err(&format!("palette entry {} is not an int", name)))?;
+            let state = legacy(name).unwrap_or_else(|| {
+                unknown.push(name);
+                BlockState::AIR
+            });
+            states.insert(idx, state);
+        }
+
+        if !unknown.is_empty() {
+            warn!(blocks = ?unknown, "the schematic has blocks which are not in 1.12, they are left out");
+        }
+
+        let volume = width as u16 as usize * height as u16 as usize * length as u16 as usize;
+        let block_data = root.get("BlockData").and_then(Tag::as_bytes).ok_or(NbtError::Missing("BlockData"))?;
+
+        // every block takes at least a byte, so a size the block data cannot fill is rejected before allocating for it
+        if volume > block_data.len() {
+            return Err(err(&format!("the block data of the schematic is too short for {} blocks", volume)));
+        }
+        let mut block_data = block_data.iter().copied();
+
+        let mut blocks = Vec::with_capacity(volume);
+        let mut data = Vec::with_capacity(volume);
+        for _ in 0..volume {
+            let idx = var_int(&mut block_data).ok_or_else(|| err("the block data of the schematic is too short"))?;
+            let state = states.get(&idx).ok_or_else(|| err(&format!("block {} of the schematic is not in its palette", idx)))?;
+            blocks.push(state.id() as i8);
+            data.push(state.metadata() as i8);
+        }
+
+        // the lowest corner where the schematic was copied and where it was copied from relative to that
+        let offset = root.get("Offset").and_then(Tag::as_i32s).filter(|offset| offset.len() == 3);
+        let metadata = root.get("Metadata").and_then(Tag::as_compound);
+        let we_offset = |name| metadata.and_then(|metadata| metadata.get(name)).and_then(Tag::as_i32);
+
+        Ok(Schematic {
+            width,
+            height,
+            length,
+            materials: "Alpha".to_string(),
+            blocks,
+            add_blocks: None,
+            data,
+            w_e_origin_x: offset.map(|offset| offset[0]),
+            w_e_origin_y: offset.map(|offset| offset[1]),
+            w_e_origin_z: offset.map(|offset| offset[2]),
+            w_e_offset_x: we_offset("WEOffsetX"),
+            w_e_offset_y: we_offset("WEOffsetY"),
+            w_e_offset_z: we_offset("WEOffsetZ"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Serialize;
+
+    use crate::error::Res;
+    use crate::schematic::Schematic;
+    use crate::schematic::sponge::legacy;
+    use crate::storage::block::{BlockLocation, BlockState};
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Sponge {
+        version: i32,
+        width: i16,
+        height: i16,
+        length: i16,
+
+        #[serde(serialize_with = "::nbt::i32_array")]
+        offset: Vec<i32>,
+        palette: HashMap<&'static str, i32>,
+
+        #[serde(serialize_with = "::nbt::i8_array")]
+        block_data: Vec<i8>,
+    }
+
+    #[test]
+    fn test_legacy() {
+        assert_eq!(legacy("minecraft:air"), Some(BlockState::AIR));
+        assert_eq!(legacy("minecraft:granite"), Some(BlockState::from(1, 1)));
+        assert_eq!(legacy("minecraft:light_gray_wool"), Some(BlockState::from(35, 8)));
+        assert_eq!(legacy("minecraft:gray_wool"), Some(BlockState::from(35, 7)));
+        assert_eq!(legacy("minecraft:dark_oak_planks"), Some(BlockState::from(5, 5)));
+        assert_eq!(legacy("minecraft:acacia_log[axis=x]"), Some(BlockState::from(162, 4)));
+        assert_eq!(legacy("minecraft:oak_stairs[facing=north,half=top,shape=straight,waterlogged=false]"), Some(BlockState::from(53, 7)));
+        assert_eq!(legacy("minecraft:stone_brick_slab[type=top,waterlogged=false]"), Some(BlockState::from(44, 13)));
+        assert_eq!(legacy("minecraft:spruce_slab[type=double,waterlogged=false]"), Some(BlockState::from(125, 1)));
+        assert_eq!(legacy("minecraft:wall_torch[facing=south]"), Some(BlockState::from(50, 3)));
+        assert_eq!(legacy("minecraft:kelp"), None);
+    }
+
+    fn load(sponge: &Sponge) -> Res<Schematic> {
+        let mut file = Vec::new();
+        nbt::to_gzip_writer(&mut file, sponge, Some("Schematic")).unwrap();
+        Schematic::load(&mut &file[..])
+    }
+
+    fn granite(width: i16, height: i16, length: i16, block_data: Vec<i8>) -> Sponge {
+        Sponge {
+            version: 2,
+            width,
+            height,
+            length,
+            offset: vec![0, 0, 0],
+            palette: vec![("minecraft:air", 0), ("minecraft:granite", 1)].into_iter().collect(),
+            block_data,
+        }
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut sponge = granite(1, 1, 1, vec![1]);
+        assert!(load(&sponge).is_ok());
+
+        sponge.version = 3;
+        assert!(load(&sponge).is_err());
+    }
+
+    #[test]
+    fn test_block_data_too_short() {
+        assert!(load(&granite(2, 1, 2, vec![1, 1, 0, 1])).is_ok());
+        assert!(load(&granite(2, 1, 2, vec![1, 1, 0])).is_err());
+
+        // the size alone would be terabytes
+        assert!(load(&granite(-1, -1, -1, vec![1; 16])).is_err());
+    }
+
+    #[test]
+    fn test_load_sponge() {
+        // 2 x 1 x 2: granite and a palette index above 127 which takes two bytes, then air and kelp
+        let sponge = Sponge {
+            version: 2,
+            width: 2,
+            height: 1,
+            length: 2,
+            offset: vec![10, 64, -3],
+            palette: vec![("minecraft:air", 0), ("minecraft:granite", 1), ("minecraft:stone_bricks", 200), ("minecraft:kelp", 2)].into_iter().collect(),
+            block_data: vec![1, 200_u8 as i8, 1, 0, 2],
+        };
+
+        let mut file = Vec::new();
+        nbt::to_gzip_writer(&mut file, &sponge, Some("Schematic")).unwrap();
+        let schematic = Schematic::load(&mut &file[..]).unwrap();
+
+        assert!(schematic.is_valid());
+        let blocks: Vec<_> = schematic.blocks().collect();
+        assert_eq!(blocks, vec![
+            (BlockLocation::new(10, 64, -3), BlockState::from(1, 1)),
+            (BlockLocation::new(11, 64, -3), BlockState::from(98, 0)),
+            (BlockLocation::new(10, 64, -2), BlockState::AIR),
+            (BlockLocation::new(11, 64, -2), BlockState::AIR),
+        ]);
+    }
+}
@@ -0,0 +1,299 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Worlds saved by a 1.12 server in the [Anvil](https://minecraft.fandom.com/wiki/Anvil_file_format) format, so
+//! paths and builds can be planned against a known map before any bot is online. Worlds saved by 1.13 or later keep
+//! blocks by name and are not supported.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+use crate::error::{err, Res};
+use crate::nbt;
+use crate::nbt::{Compound, NbtError, NbtLimits, Tag};
+use crate::storage::block::{BlockLocation, BlockState};
+use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+use crate::storage::chunk::{ChunkColumn, ChunkData, HighMemoryChunkSection, Palette, SectionLight};
+use crate::storage::tile_entity::TileEntity;
+
+const SECTOR: usize = 4096;
+
+const GZIP: u8 = 1;
+const ZLIB: u8 = 2;
+const UNCOMPRESSED: u8 = 3;
+
+/// the data version of 1.13, the first to keep blocks in palettes by name
+const FLATTENING: i32 = 1451;
+
+/// a column read from a region file
+pub struct AnvilColumn {
+    pub location: ChunkLocation,
+    pub column: ChunkColumn,
+    pub tile_entities: Vec<(BlockLocation, TileEntity)>,
+}
+
+/// Read every column in the region file `data`. Columns which were never generated are not in the file.
+pub fn read_region(data: &[u8]) -> Res<Vec<AnvilColumn>> {
+    if data.len() < 2 * SECTOR {
+        return Err(err("region file is shorter than its header"));
+    }
+
+    let mut columns = Vec::new();
+    for entry in data[..SECTOR].chunks_exact(4) {
+        let offset = u32::from_be_bytes([0, entry[0], entry[1], entry[2]]) as usize * SECTOR;
+        if offset == 0 {
+            continue;
+        }
+
+        let header = data.get(offset..offset + 5).ok_or_else(|| err("column is past the end of the region file"))?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+
+        // the length counts the compression byte
+        let compressed = len.checked_sub(1)
+            .and_then(|len| data.get(offset + 5..offset + 5 + len))
+            .ok_or_else(|| err("column is past the end of the region file"))?;
+
+        let column = decompress(header[4], compressed)?;
+        columns.push(read_column(&column)?);
+    }
+    Ok(columns)
+}
+
+fn decompress(compression: u8, data: &[u8]) -> Res<Vec<u8>> {
+    let limit = NbtLimits::FILE.max_size as u64;
+    let mut out = Vec::new();
+    match compression {
+        GZIP => GzDecoder::new(data).take(limit).read_to_end(&mut out)?,
+        ZLIB => ZlibDecoder::new(data).take(limit).read_to_end(&mut out)?,
+        UNCOMPRESSED => data.take(limit).read_to_end(&mut out)?,
+        other => return Err(err(&format!("unknown compression {} of column", other)))
+    };
+    Ok(out)
+}
+
+/// Read the uncompressed NBT of a column
+pub fn read_column(data: &[u8]) -> Res<AnvilColumn> {
+    let nbt = nbt::read(data, &NbtLimits::FILE)?;
+
+    if nbt.root.get("DataVersion").and_then(Tag::as_i32).map_or(false, |version| version >= FLATTENING) {
+        return Err(err("the world was saved by 1.13 or later, only 1.12 worlds can be loaded"));
+    }
+
+    let level = nbt.root.get("Level").and_then(Tag::as_compound).ok_or(NbtError::Missing("Level"))?;
+    let int = |name| level.get(name).and_then(Tag::as_i32).ok_or(NbtError::Missing(name));
+    let list = |name| level.get(name).and_then(Tag::as_list).unwrap_or_default();
+
+    let location = ChunkLocation(int("xPos")?, int("zPos")?);
+
+    let mut data = ChunkData::<HighMemoryChunkSection>::default();
+    for section in list("Sections").iter().filter_map(Tag::as_compound) {
+        if let Some((idx, section)) = read_section(section)? {
+            data.sections[idx] = Some(Arc::new(section));
+        }
+    }
+
+    let tile_entities = list("TileEntities").iter()
+        .filter_map(Tag::as_compound)
+        .filter_map(TileEntity::parse)
+        .collect();
+
+    Ok(AnvilColumn {
+        location,
+        column: ChunkColumn::HighMemory { data },
+        tile_entities,
+    })
+}
+
+/// the index and blocks of a section, None if it is all air
+fn read_section(section: &Compound) -> Res<Option<(usize, HighMemoryChunkSection)>> {
+    let y = section.get("Y").and_then(Tag::as_i8).ok_or(NbtError::Missing("Y"))?;
+    let idx = usize::try_from(y).ok().filter(|&y| y < 16).ok_or_else(|| err(&format!("section {} is out of the world", y)))?;
+
+    let bytes = |name| section.get(name).and_then(Tag::as_bytes);
+    let nibbles = |name| bytes(name).and_then(|bytes| <[u8; 2048]>::try_from(bytes).ok());
+
+    let blocks = bytes("Blocks").filter(|blocks| blocks.len() == 4096).ok_or(NbtError::Missing("Blocks"))?;
+    let meta = nibbles("Data").ok_or(NbtError::Missing("Data"))?;
+    let add = nibbles("Add");
+
+    // both are in y z x order, like a palette
+    let mut states = [BlockState::AIR; 4096];
+    for (i, state) in states.iter_mut().enumerate() {
+        let id = blocks[i] as u32 | add.as_ref().map_or(0, |add| nibble(add, i) as u32) << 8;
+        *state = BlockState::from(id, nibble(&meta, i) as u16);
+    }
+
+    if states.iter().all(|&state| state == BlockState::AIR) {
+        return Ok(None);
+    }
+
+    let light = nibbles("BlockLight").map(|block| SectionLight::new(block, nibbles("SkyLight")));
+    let section = HighMemoryChunkSection::new(Palette::from_states(&states)).with_light(light);
+    Ok(Some((idx, section)))
+}
+
+fn nibble(nibbles: &[u8; 2048], idx: usize) -> u8 {
+    nibbles[idx >> 1] >> ((idx & 1) * 4) & 0xF
+}
+
+/// Add the columns of the region file at `path` to `world`, replacing the ones it has. Returns how many were added.
+pub fn load_region(path: &Path, world: &mut WorldBlocks) -> Res<usize> {
+    let columns = read_region(&fs::read(path)?)?;
+    let count = columns.len();
+
+    for AnvilColumn { location, column, tile_entities } in columns {
+        world.add_column(location, column);
+        for (location, entity) in tile_entities {
+            world.set_tile_entity(location, entity);
+        }
+    }
+    Ok(count)
+}
+
+/// Add the columns of every region file of the world folder `dir` to `world`. The overworld is in the folder itself,
+/// the nether in `DIM-1` and the end in `DIM1`. Returns how many columns were added.
+pub fn load_world(dir: &Path, world: &mut WorldBlocks) -> Res<usize> {
+    let mut count = 0;
+    for entry in fs::read_dir(dir.join("region"))? {
+        let path = entry?.path();
+        if path.extension().map_or(false, |extension| extension == "mca") {
+            count += load_region(&path, world)?;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use serde::Serialize;
+
+    use crate::storage::anvil::{read_region, SECTOR, ZLIB};
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+    use crate::storage::tile_entity::TileEntity;
+
+    #[derive(Serialize)]
+    struct Column {
+        #[serde(rename = "Level")]
+        level: Level,
+    }
+
+    #[derive(Serialize)]
+    struct Level {
+        #[serde(rename = "xPos")]
+        x: i32,
+
+        #[serde(rename = "zPos")]
+        z: i32,
+
+        #[serde(rename = "Sections")]
+        sections: Vec<Section>,
+
+        #[serde(rename = "TileEntities")]
+        tile_entities: Vec<Entity>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Section {
+        y: i8,
+
+        #[serde(serialize_with = "::nbt::i8_array")]
+        blocks: Vec<i8>,
+
+        #[serde(serialize_with = "::nbt::i8_array")]
+        data: Vec<i8>,
+    }
+
+    #[derive(Serialize)]
+    struct Entity {
+        id: &'static str,
+        x: i32,
+        y: i32,
+        z: i32,
+    }
+
+    /// a region file with the column at (1, -2) only
+    fn region() -> Vec<u8> {
+        let mut blocks = vec![0; 4096];
+        let mut data = vec![0; 2048];
+
+        // granite (stone:1) at x 3, y 20, z 5 of the column
+        let idx = 4 * 256 + 5 * 16 + 3;
+        blocks[idx] = 1;
+        data[idx >> 1] = 1 << 4;
+
+        let column = Column {
+            level: Level {
+                x: 1,
+                z: -2,
+                sections: vec![
+                    Section { y: 0, blocks: vec![0; 4096], data: vec![0; 2048] },
+                    Section { y: 1, blocks, data },
+                ],
+                tile_entities: vec![Entity { id: "minecraft:sign", x: 16, y: 64, z: -32 }],
+            },
+        };
+
+        let mut nbt = Vec::new();
+        nbt::to_writer(&mut nbt, &column, None).unwrap();
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&nbt).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut region = vec![0; 2 * SECTOR];
+        let entry = (1 + (-2 & 31) * 32) as usize;
+        region[entry * 4..entry * 4 + 4].copy_from_slice(&[0, 0, 2, 1]);
+        region.extend_from_slice(&(compressed.len() as u32 + 1).to_be_bytes());
+        region.push(ZLIB);
+        region.extend_from_slice(&compressed);
+        region
+    }
+
+    #[test]
+    fn test_read_region() {
+        let columns = read_region(&region()).unwrap();
+        assert_eq!(columns.len(), 1);
+        assert_eq!(columns[0].location, ChunkLocation(1, -2));
+
+        let mut world = WorldBlocks::default();
+        for column in columns {
+            world.add_column(column.location, column.column);
+            for (location, entity) in column.tile_entities {
+                world.set_tile_entity(location, entity);
+            }
+        }
+
+        assert_eq!(world.get_block_exact(BlockLocation::new(19, 20, -27)), Some(BlockState::from(1, 1)));
+        assert_eq!(world.get_block_exact(BlockLocation::new(19, 21, -27)), Some(BlockState::AIR));
+
+        // the all-air section is left out like the server leaves it out
+        assert!(world.get_real_column(ChunkLocation(1, -2)).unwrap().sections[0].is_none());
+        assert!(matches!(world.tile_entity(BlockLocation::new(16, 64, -32)), Some(TileEntity::Sign(_))));
+
+        assert!(read_region(&[0; SECTOR]).is_err());
+    }
+}
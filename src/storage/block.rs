@@ -14,15 +14,21 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::convert::TryFrom;
 use std::fmt::{Debug, Display, Formatter};
 use std::ops::Add;
 
 use serde::{Deserialize, Serialize};
 
-use crate::bootstrap::block_data::{Block, BlockData};
+use crate::bootstrap::block_data::{Block, BlockData, Material};
+use crate::client::bot::{ProcessError, WrongArgCount};
+use crate::client::physics::tools::{Tool, ToolKind};
 use crate::client::pathfind::moves::Change;
+use crate::client::state::local::effects::Effects;
 use crate::types::{Displacement, Location};
 
+pub mod properties;
+
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
 #[repr(transparent)]
 pub struct BlockKind(pub u32);
@@ -38,9 +44,26 @@ impl BlockKind {
     pub const LADDER: BlockKind = BlockKind(65);
     pub const LEAVES: BlockKind = BlockKind(18);
     pub const FLOWING_WATER: BlockKind = BlockKind(8);
+    pub const AIR: BlockKind = BlockKind(0);
     pub const STONE: BlockKind = BlockKind(1);
     pub const DIRT: BlockKind = BlockKind(3);
     pub const GLASS: BlockKind = BlockKind(20);
+    pub const COBBLESTONE: BlockKind = BlockKind(4);
+    pub const STANDING_SIGN: BlockKind = BlockKind(63);
+    pub const WALL_SIGN: BlockKind = BlockKind(68);
+    pub const MOB_SPAWNER: BlockKind = BlockKind(52);
+    pub const FURNACE: BlockKind = BlockKind(61);
+    pub const LIT_FURNACE: BlockKind = BlockKind(62);
+    pub const BED: BlockKind = BlockKind(26);
+    pub const TORCH: BlockKind = BlockKind(50);
+
+    // items
+    pub const SHIELD: BlockKind = BlockKind(442);
+    pub const TOTEM: BlockKind = BlockKind(449);
+    pub const FIREWORK: BlockKind = BlockKind(401);
+    pub const BUCKET: BlockKind = BlockKind(325);
+    pub const WATER_BUCKET: BlockKind = BlockKind(326);
+    pub const WRITABLE_BOOK: BlockKind = BlockKind(386);
 
     #[inline]
     pub fn id(self) -> u32 {
@@ -56,6 +79,119 @@ impl BlockKind {
         blocks.by_id(self.0).unwrap_or_else(|| panic!("no block for id {}", self.0))
     }
 
+    /// the kind of tool which breaks the block faster than a hand. None if no tool does (i.e., glass).
+    pub fn best_tool(&self, blocks: &BlockData) -> Option<ToolKind> {
+        let block = self.data(blocks);
+
+        // the tools which can harvest it, if we know them (prismarine gives some blocks the wrong material)
+        let harvest = block.harvest_tools.iter()
+            .map(|&id| Tool::by_id(id).kind)
+            .find(|&kind| kind != ToolKind::Generic);
+
+        if harvest.is_some() {
+            return harvest;
+        }
+
+        match block.material {
+            Material::Rock => Some(ToolKind::Pickaxe),
+            Material::Wood => Some(ToolKind::Axe),
+            Material::Dirt => Some(ToolKind::Shovel),
+            Material::Web => Some(ToolKind::Sword),
+            Material::Plant | Material::Wool | Material::Generic => None, // plants and wool need shears
+        }
+    }
+
+    /// whether breaking the block with `tool` drops anything. Blocks like stone need a pickaxe of a high enough tier.
+    pub fn can_harvest(&self, tool: &Tool, blocks: &BlockData) -> bool {
+        let block = self.data(blocks);
+        block.harvest_tools.is_empty() || block.harvest_tools.contains(&tool.id)
+    }
+
+    /// whether breaking the block drops the block itself (stone drops cobblestone)
+    pub fn drops_self(&self, blocks: &BlockData) -> bool {
+        self.data(blocks).drops_self
+    }
+
+    /// The ticks it takes to break the block with `tool` (and haste or mining fatigue), 0 if it breaks instantly.
+    /// https://minecraft.fandom.com/wiki/Breaking#Speed
+    pub fn break_ticks(&self, tool: &Tool, on_ground: bool, in_water: bool, effects: &Effects, blocks: &BlockData) -> usize {
+        let hardness = self.hardness(blocks).unwrap_or(f64::INFINITY).max(0.0);
+
+        let mut speed = 1.0;
+
+        // a pickaxe of too low a tier still breaks rock faster, it just does not drop anything
+        if self.best_tool(blocks) == Some(tool.kind) {
+            speed = tool.material.strength();
+
+            let efficiency = tool.efficiency().unwrap_or(0);
+            if efficiency > 0 {
+                speed += (efficiency.pow(2) + 1) as f64;
+            }
+        }
+
+        speed *= effects.dig_multiplier();
+
+        if in_water { speed /= 5.0; }
+        if !on_ground { speed /= 5.0; }
+
+        let divisor = if self.can_harvest(tool, blocks) { 30.0 } else { 100.0 };
+
+        // the damage adds up every tick until it reaches 1
+        let damage = speed / hardness / divisor;
+
+        if damage >= 1.0 {
+            0
+        } else {
+            (1.0 / damage).ceil() as usize
+        }
+    }
+
+    /// The id of the item which places the block (1.12). Most blocks are their own item, but crops are placed with
+    /// seeds, signs and doors with an item of another id and so on. Items (256 and over) are returned as they are. None
+    /// if no item places the block (i.e., fire or portals).
+    pub fn item_id(self) -> Option<u32> {
+        let id = match self.0 {
+            0 | 34 | 36 | 51 | 90 | 119 => return None,
+            8 | 9 => 326, // water and lava buckets
+            10 | 11 => 327,
+            26 => 355, // bed
+            43 => 44, // double slabs
+            55 => 331, // redstone
+            59 => 295, // wheat seeds
+            62 => 61, // lit furnace
+            63 | 68 => 323, // sign
+            64 => 324, // doors
+            71 => 330,
+            74 => 73, // lit redstone ore
+            75 => 76, // redstone torch
+            83 => 338, // sugar canes
+            92 => 354, // cake
+            93 | 94 => 356, // repeater
+            104 => 361, // pumpkin and melon seeds
+            105 => 362,
+            115 => 372, // nether wart
+            117 => 379, // brewing stand
+            118 => 380, // cauldron
+            124 => 123, // lit redstone lamp
+            125 => 126, // double wooden slab
+            127 => 351, // cocoa beans
+            132 => 287, // string
+            140 => 390, // flower pot
+            141 => 391, // carrot
+            142 => 392, // potato
+            144 => 397, // skull
+            149 | 150 => 404, // comparator
+            176 | 177 => 425, // banner
+            178 => 151, // inverted daylight detector
+            181 => 182, // double red sandstone slab
+            193..=197 => self.0 + 234, // spruce to dark oak doors
+            204 => 205, // double purpur slab
+            207 => 435, // beetroot seeds
+            id => id,
+        };
+        Some(id)
+    }
+
     pub fn throw_away_block(self) -> bool {
         // cobblestone
         matches!(self.id(), 4)
@@ -74,6 +210,18 @@ impl BlockKind {
         }
     }
 
+    /// the block light the block gives off
+    pub fn emitted_light(self) -> u8 {
+        match self.0 {
+            10 | 11 | 51 | 89 | 91 | 119 | 124 | 138 | 169 => 15, // lava, fire, glowstone, jack o'lantern, end portal, lit redstone lamp, beacon, sea lantern
+            50 | 62 | 198 => 14, // torch, lit furnace, end rod
+            90 => 11, // nether portal
+            74 | 94 => 9, // lit redstone ore and repeater
+            76 => 7, // redstone torch
+            _ => 0
+        }
+    }
+
     pub fn slip(&self) -> f64 {
         match self.0 {
             266 => 0.989, // blue ice
@@ -112,6 +260,10 @@ impl BlockState {
     }
 
     pub fn simple_type(&self) -> SimpleType {
+        if let Some(simple) = self.decoded_simple_type() {
+            return simple;
+        }
+
         if self.full_block() {
             return SimpleType::Solid;
         }
@@ -232,6 +384,21 @@ impl BlockLocation2D {
     }
 }
 
+impl TryFrom<&[&str]> for BlockLocation {
+    type Error = ProcessError;
+
+    fn try_from(value: &[&str]) -> Result<Self, Self::Error> {
+        if let [a, b, c] = value {
+            let x = a.parse()?;
+            let y = b.parse()?;
+            let z = c.parse()?;
+            Ok(BlockLocation::new(x, y, z))
+        } else {
+            Err(WrongArgCount::new(3).into())
+        }
+    }
+}
+
 impl From<Change> for BlockLocation {
     fn from(change: Change) -> Self {
         Self {
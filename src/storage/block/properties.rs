@@ -0,0 +1,376 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Decoding the 4 bit metadata of the 1.12 block families whose collision depends on it: slabs, stairs, doors,
+//! fence gates and trapdoors.
+
+use crate::protocol::Face;
+use crate::storage::block::{BlockApprox, BlockKind, BlockState, SimpleType};
+
+/// The part of a block a player collides with, in sixteenths of a block
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Shape {
+    Empty,
+
+    /// from the bottom of the block up to this height
+    Bottom(u8),
+
+    /// from this far below the top of the block up to the top
+    Top(u8),
+    Full,
+}
+
+impl Shape {
+    pub const SLAB: u8 = 8;
+    pub const TRAPDOOR: u8 = 3;
+
+    /// The shape of a block we only know the simple type of. None for blocks we avoid without knowing their shape.
+    pub fn of_simple(simple: SimpleType) -> Option<Shape> {
+        match simple {
+            SimpleType::Solid => Some(Shape::Full),
+            SimpleType::Water | SimpleType::WalkThrough => Some(Shape::Empty),
+            SimpleType::Avoid => None,
+        }
+    }
+
+    /// the lowest and highest point of the shape relative to the bottom of the block
+    pub fn range(self) -> Option<(f64, f64)> {
+        match self {
+            Shape::Empty => None,
+            Shape::Bottom(height) => Some((0.0, height as f64 / 16.0)),
+            Shape::Top(height) => Some((1.0 - height as f64 / 16.0, 1.0)),
+            Shape::Full => Some((0.0, 1.0)),
+        }
+    }
+
+    /// if a point `dy` above the bottom of the block is inside the shape
+    pub fn contains(self, dy: f64) -> bool {
+        self.range().map_or(false, |(min, max)| min <= dy && dy < max)
+    }
+
+    /// if the shape overlaps the heights `from` to `to` above the bottom of the block
+    pub fn overlaps(self, from: f64, to: f64) -> bool {
+        self.range().map_or(false, |(min, max)| from < max && min < to)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Half {
+    Bottom,
+    Top,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Slab {
+    Bottom,
+    Top,
+    Double,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Stairs {
+    /// the direction the full side faces. A player walking up the stairs walks the opposite way.
+    pub facing: Face,
+
+    /// Top if upside down
+    pub half: Half,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Hinge {
+    Left,
+    Right,
+}
+
+/// The two halves of a door know different things. Whether the door is open is only stored in the lower half.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Door {
+    Lower { facing: Face, open: bool },
+    Upper { hinge: Hinge, powered: bool },
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FenceGate {
+    pub facing: Face,
+    pub open: bool,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Trapdoor {
+    pub facing: Face,
+    pub open: bool,
+    pub half: Half,
+}
+
+/// the horizontal faces in the order most 1.12 metadata uses: south, west, north, east
+fn horizontal(value: u8) -> Face {
+    [Face::PosZ, Face::NegX, Face::NegZ, Face::PosX][value as usize & 0b11]
+}
+
+/// A plant which is harvested when fully grown and replanted from an item
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Crop {
+    /// the age (metadata) of the fully grown crop
+    pub max_age: u8,
+
+    /// the item which plants the crop
+    pub seed: BlockKind,
+
+    /// the block the crop grows on
+    pub soil: BlockKind,
+
+    /// the items a harvested crop drops (including the seed)
+    pub drops: &'static [BlockKind],
+}
+
+fn half(top: bool) -> Half {
+    if top { Half::Top } else { Half::Bottom }
+}
+
+impl BlockKind {
+    pub fn is_slab(&self) -> bool {
+        matches!(self.0, 43 | 44 | 125 | 126 | 181 | 182 | 204 | 205)
+    }
+
+    pub fn is_stairs(&self) -> bool {
+        matches!(self.0, 53 | 67 | 108 | 109 | 114 | 128 | 134..=136 | 156 | 163 | 164 | 180 | 203)
+    }
+
+    pub fn is_door(&self) -> bool {
+        matches!(self.0, 64 | 71 | 193..=197)
+    }
+
+    pub fn is_fence_gate(&self) -> bool {
+        matches!(self.0, 107 | 183..=187)
+    }
+
+    pub fn is_trapdoor(&self) -> bool {
+        matches!(self.0, 96 | 167)
+    }
+
+    pub fn crop(&self) -> Option<Crop> {
+        const FARMLAND: BlockKind = BlockKind(60);
+        const SOUL_SAND: BlockKind = BlockKind(88);
+
+        let crop = |max_age, seed: u32, soil, drops| Crop { max_age, seed: BlockKind(seed), soil, drops };
+
+        let crop = match self.0 {
+            59 => crop(7, 295, FARMLAND, &[BlockKind(296), BlockKind(295)]), // wheat
+            141 => crop(7, 391, FARMLAND, &[BlockKind(391)]), // carrots
+            142 => crop(7, 392, FARMLAND, &[BlockKind(392), BlockKind(394)]), // potatoes (and poisonous ones)
+            207 => crop(3, 435, FARMLAND, &[BlockKind(434), BlockKind(435)]), // beetroots
+            115 => crop(3, 372, SOUL_SAND, &[BlockKind(372)]), // nether wart
+            _ => return None
+        };
+        Some(crop)
+    }
+}
+
+impl BlockState {
+    pub fn slab(&self) -> Option<Slab> {
+        match self.id() {
+            43 | 125 | 181 | 204 => Some(Slab::Double),
+            44 | 126 | 182 | 205 if self.metadata() & 0b1000 != 0 => Some(Slab::Top),
+            44 | 126 | 182 | 205 => Some(Slab::Bottom),
+            _ => None
+        }
+    }
+
+    pub fn stairs(&self) -> Option<Stairs> {
+        self.kind().is_stairs().then(|| {
+            let facing = [Face::PosX, Face::NegX, Face::PosZ, Face::NegZ][self.metadata() as usize & 0b11];
+            Stairs { facing, half: half(self.metadata() & 0b100 != 0) }
+        })
+    }
+
+    pub fn door(&self) -> Option<Door> {
+        if !self.kind().is_door() {
+            return None;
+        }
+
+        let metadata = self.metadata();
+        let door = if metadata & 0b1000 == 0 {
+            let facing = [Face::PosX, Face::PosZ, Face::NegX, Face::NegZ][metadata as usize & 0b11];
+            Door::Lower { facing, open: metadata & 0b100 != 0 }
+        } else {
+            let hinge = if metadata & 0b1 == 0 { Hinge::Left } else { Hinge::Right };
+            Door::Upper { hinge, powered: metadata & 0b10 != 0 }
+        };
+        Some(door)
+    }
+
+    pub fn fence_gate(&self) -> Option<FenceGate> {
+        self.kind().is_fence_gate().then(|| FenceGate {
+            facing: horizontal(self.metadata()),
+            open: self.metadata() & 0b100 != 0,
+        })
+    }
+
+    pub fn trapdoor(&self) -> Option<Trapdoor> {
+        self.kind().is_trapdoor().then(|| {
+            let facing = [Face::NegZ, Face::PosZ, Face::NegX, Face::PosX][self.metadata() as usize & 0b11];
+            Trapdoor { facing, open: self.metadata() & 0b100 != 0, half: half(self.metadata() & 0b1000 != 0) }
+        })
+    }
+
+    /// how far a crop has grown, from 0 to [`Crop::max_age`]
+    pub fn crop_age(&self) -> Option<u8> {
+        let crop = self.kind().crop()?;
+        Some(self.metadata().min(crop.max_age))
+    }
+
+    /// a crop which is ready to be harvested
+    pub fn is_grown(&self) -> bool {
+        match (self.kind().crop(), self.crop_age()) {
+            (Some(crop), Some(age)) => age == crop.max_age,
+            _ => false
+        }
+    }
+
+    /// The simple type of the families above, which depends on the metadata. A bottom slab is avoided because the
+    /// player stands in its block instead of on top of it. The upper half of a door is walked through: whether the
+    /// door is open is only known from the lower half, which already blocks closed doors.
+    pub(super) fn decoded_simple_type(&self) -> Option<SimpleType> {
+        let passable = |open: bool| if open { SimpleType::WalkThrough } else { SimpleType::Avoid };
+
+        if let Some(slab) = self.slab() {
+            return Some(if slab == Slab::Bottom { SimpleType::Avoid } else { SimpleType::Solid });
+        }
+
+        if self.kind().is_stairs() {
+            return Some(SimpleType::Solid);
+        }
+
+        let simple = match self.door() {
+            Some(Door::Lower { open, .. }) => passable(open),
+            Some(Door::Upper { .. }) => SimpleType::WalkThrough,
+            None => {
+                let open = self.fence_gate().map(|gate| gate.open)
+                    .or_else(|| self.trapdoor().map(|trapdoor| trapdoor.open))?;
+                passable(open)
+            }
+        };
+
+        Some(simple)
+    }
+
+    /// What a player collides with. Stairs count as full blocks: the pathfinder jumps onto them. A closed fence gate
+    /// is taller than a block, so like other blocks we avoid without knowing their shape it is None.
+    pub fn shape(&self) -> Option<Shape> {
+        if let Some(slab) = self.slab() {
+            return Some(match slab {
+                Slab::Bottom => Shape::Bottom(Shape::SLAB),
+                Slab::Top => Shape::Top(Shape::SLAB),
+                Slab::Double => Shape::Full,
+            });
+        }
+
+        if let Some(trapdoor) = self.trapdoor() {
+            return Some(match trapdoor {
+                Trapdoor { open: true, .. } => Shape::Empty,
+                Trapdoor { half: Half::Bottom, .. } => Shape::Bottom(Shape::TRAPDOOR),
+                Trapdoor { half: Half::Top, .. } => Shape::Top(Shape::TRAPDOOR),
+            });
+        }
+
+        if let Some(Door::Lower { open: false, .. }) = self.door() {
+            return Some(Shape::Full);
+        }
+
+        Shape::of_simple(self.simple_type())
+    }
+}
+
+impl BlockApprox {
+    pub fn shape(&self) -> Option<Shape> {
+        match self {
+            BlockApprox::Realized(state) => state.shape(),
+            BlockApprox::Estimate(simple) => Shape::of_simple(*simple),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::Face;
+    use crate::storage::block::{BlockKind, BlockState, SimpleType};
+    use crate::storage::block::properties::{Door, FenceGate, Half, Hinge, Shape, Slab, Stairs, Trapdoor};
+
+    #[test]
+    fn test_decode() {
+        let stone_slab = |metadata| BlockState::from(44, metadata);
+        assert_eq!(stone_slab(0).slab(), Some(Slab::Bottom));
+        assert_eq!(stone_slab(8).slab(), Some(Slab::Top));
+        assert_eq!(BlockState::from(43, 8).slab(), Some(Slab::Double));
+        assert_eq!(BlockState::STONE.slab(), None);
+
+        assert_eq!(BlockState::from(67, 0b101).stairs(), Some(Stairs { facing: Face::NegX, half: Half::Top }));
+
+        assert_eq!(BlockState::from(64, 0b0101).door(), Some(Door::Lower { facing: Face::PosZ, open: true }));
+        assert_eq!(BlockState::from(64, 0b1001).door(), Some(Door::Upper { hinge: Hinge::Right, powered: false }));
+
+        assert_eq!(BlockState::from(107, 0b110).fence_gate(), Some(FenceGate { facing: Face::NegZ, open: true }));
+        assert_eq!(BlockState::from(96, 0b1011).trapdoor(), Some(Trapdoor { facing: Face::PosX, open: false, half: Half::Top }));
+    }
+
+    #[test]
+    fn test_crops() {
+        let wheat = |age| BlockState::from(59, age);
+        assert_eq!(wheat(3).crop_age(), Some(3));
+        assert!(!wheat(6).is_grown());
+        assert!(wheat(7).is_grown());
+        assert_eq!(wheat(0).kind().crop().unwrap().seed, BlockKind(295));
+
+        // beetroots and nether wart only have 4 stages
+        assert!(BlockState::from(207, 3).is_grown());
+        assert!(BlockState::from(115, 3).is_grown());
+        assert_eq!(BlockState::from(115, 0).kind().crop().unwrap().soil, BlockKind(88));
+
+        assert_eq!(BlockState::STONE.crop_age(), None);
+        assert!(!BlockState::STONE.is_grown());
+    }
+
+    #[test]
+    fn test_collision() {
+        let bottom = BlockState::from(44, 0);
+        assert_eq!(bottom.simple_type(), SimpleType::Avoid);
+        assert_eq!(bottom.shape(), Some(Shape::Bottom(8)));
+        assert!(bottom.shape().unwrap().contains(0.25));
+        assert!(!bottom.shape().unwrap().contains(0.5));
+
+        let top = BlockState::from(126, 8);
+        assert_eq!(top.simple_type(), SimpleType::Solid);
+        assert!(top.shape().unwrap().overlaps(0.4, 1.2));
+        assert!(!top.shape().unwrap().overlaps(0.0, 0.5));
+
+        assert_eq!(BlockState::from(53, 2).simple_type(), SimpleType::Solid);
+
+        // closed and open gates and doors
+        assert_eq!(BlockState::from(107, 0).simple_type(), SimpleType::Avoid);
+        assert_eq!(BlockState::from(107, 0).shape(), None);
+        assert_eq!(BlockState::from(107, 4).simple_type(), SimpleType::WalkThrough);
+        assert_eq!(BlockState::from(71, 0).simple_type(), SimpleType::Avoid);
+        assert_eq!(BlockState::from(71, 4).simple_type(), SimpleType::WalkThrough);
+        assert_eq!(BlockState::from(71, 8).simple_type(), SimpleType::WalkThrough);
+
+        assert_eq!(BlockState::from(96, 0).shape(), Some(Shape::Bottom(3)));
+        assert_eq!(BlockState::from(96, 4).shape(), Some(Shape::Empty));
+
+        // blocks without metadata dependent collision are unchanged
+        assert_eq!(BlockState::STONE.shape(), Some(Shape::Full));
+        assert_eq!(BlockState::AIR.shape(), Some(Shape::Empty));
+        assert_eq!(BlockState::from(85, 0).shape(), None);
+    }
+}
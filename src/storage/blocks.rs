@@ -16,21 +16,41 @@
 
 use std::collections::{BinaryHeap, HashMap};
 use std::convert::TryFrom;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use float_ord::FloatOrd;
 use rand::{Rng, SeedableRng};
 use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
 
 use crate::client::bot::{ProcessError, WrongArgCount};
 use crate::client::pathfind::MinHeapNode;
+use crate::error::{err, Error};
 use crate::schematic::Schematic;
 use crate::storage::block::{BlockApprox, BlockKind, BlockLocation, BlockState, SimpleType};
+use crate::storage::block::properties::Shape;
+use crate::storage::blocks::heightmap::{Heightmap, Surface};
+use crate::storage::blocks::index::{ChunkIndex, IndexedKinds};
 use crate::storage::chunk::{ChunkColumn, ChunkData, HighMemoryChunkSection};
+use crate::storage::composition::Composition;
+use crate::storage::sign::SignText;
+use crate::storage::tile_entity::TileEntity;
 use crate::types::Location;
 
+pub mod archive;
 pub mod cache;
-
-#[derive(Copy, Clone, Hash, Eq, PartialEq)]
+pub mod diff;
+pub mod export;
+pub mod heightmap;
+pub mod index;
+pub mod light;
+pub mod persist;
+pub mod raycast;
+
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct ChunkLocation(pub i32, pub i32);
 
 impl TryFrom<&[&str]> for ChunkLocation {
@@ -60,9 +80,115 @@ impl From<Location> for ChunkLocation {
     }
 }
 
-#[derive(Default)]
+/// What to do with a partial update of a column which is not loaded (e.g., it was sent right after a respawn)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MissingColumn {
+    /// load the sections which were sent. The others are air until the whole column is sent.
+    Insert,
+
+    /// ignore the update
+    Drop,
+}
+
+impl Default for MissingColumn {
+    fn default() -> Self {
+        MissingColumn::Insert
+    }
+}
+
+impl FromStr for MissingColumn {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "insert" => Ok(MissingColumn::Insert),
+            "drop" => Ok(MissingColumn::Drop),
+            other => Err(err(&format!("unknown missing column policy {}", other))),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Chunk {
+    /// the world generation this chunk was last mutated in
+    generation: u64,
+    column: Arc<ChunkColumn>,
+
+    /// the column is suspected to be out of sync and the server was made to resend it
+    stale: bool,
+
+    /// the block entities of the column (i.e., the text of signs)
+    tile_entities: HashMap<BlockLocation, TileEntity>,
+
+    /// where the indexed blocks of the column are. None until the column is scanned.
+    index: Option<ChunkIndex>,
+
+    /// the top of the column, kept up to date with every change
+    heightmap: Arc<Heightmap>,
+
+    /// the ids of the bots the server sends the column to. It is dropped once the last of them unloads it.
+    holders: Vec<u32>,
+
+    /// when (unix seconds) the server last sent the column. Merging saved worlds keeps the newest column.
+    seen: u64,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default()
+}
+
+/// All the blocks the bot knows about.
+///
+/// Storage is structurally shared: cloning (or [`WorldBlocks::snapshot`]) is `O(1)`. Mutations copy-on-write
+/// the path of the persistent chunk map, the chunk, the column and the section they touch if (and only if) a
+/// snapshot still references them. The other columns stay shared.
+#[derive(Default, Clone)]
 pub struct WorldBlocks {
-    storage: HashMap<ChunkLocation, ChunkColumn>,
+    storage: im::HashMap<ChunkLocation, Arc<Chunk>>,
+
+    /// incremented on every mutation
+    generation: u64,
+
+    /// the kinds of blocks the columns are indexed for
+    indexed: IndexedKinds,
+
+    /// whether columns keep the light the server sends. It about doubles the memory of a column.
+    keep_light: bool,
+
+    /// whether sections are repacked into as few bits as they need when they arrive
+    compact: bool,
+}
+
+/// A read-only view of [`WorldBlocks`] at a point in time. It is `Send + Sync` so it can be scanned on another
+/// thread while the world it came from keeps being mutated.
+#[derive(Clone)]
+pub struct WorldSnapshot {
+    world: WorldBlocks,
+}
+
+impl WorldSnapshot {
+    /// the generation of the world when the snapshot was taken
+    pub fn generation(&self) -> u64 {
+        self.world.generation
+    }
+
+    /// true if `world` has been mutated since the snapshot was taken
+    pub fn is_stale(&self, world: &WorldBlocks) -> bool {
+        world.generation != self.world.generation
+    }
+
+    /// true if the chunk at `location` has been mutated (or added/removed) since the snapshot was taken
+    pub fn is_chunk_stale(&self, world: &WorldBlocks, location: ChunkLocation) -> bool {
+        self.world.chunk_generation(location) != world.chunk_generation(location)
+    }
+}
+
+impl Deref for WorldSnapshot {
+    type Target = WorldBlocks;
+
+    fn deref(&self) -> &Self::Target {
+        &self.world
+    }
 }
 
 struct HeapIter<T> {
@@ -103,6 +229,37 @@ impl WorldBlocks {
         world
     }
 
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// the number of loaded chunk columns
+    pub fn chunk_count(&self) -> usize {
+        self.storage.len()
+    }
+
+    pub fn chunk_generation(&self, location: ChunkLocation) -> Option<u64> {
+        self.storage.get(&location).map(|chunk| chunk.generation)
+    }
+
+    pub fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot {
+            world: self.clone()
+        }
+    }
+
+    fn column(&self, location: ChunkLocation) -> Option<&ChunkColumn> {
+        self.storage.get(&location).map(|chunk| chunk.column.as_ref())
+    }
+
+    /// bump the generation and get a mutable column, cloning whatever is shared with a snapshot
+    fn column_mut(&mut self, location: ChunkLocation) -> Option<&mut ChunkColumn> {
+        let chunk = self.storage.get_mut(&location).map(Arc::make_mut)?;
+        self.generation += 1;
+        chunk.generation = self.generation;
+        Some(Arc::make_mut(&mut chunk.column))
+    }
+
     pub fn first_below(&self, location: BlockLocation) -> Option<(BlockLocation, BlockState)> {
         (0..location.y).rev()
             .map(|y| BlockLocation::new(location.x, y, location.z))
@@ -185,12 +342,186 @@ impl WorldBlocks {
         }
     }
 
+    /// Repack the sections of the columns which are added or updated from now on into as few bits as they need (see
+    /// [`Palette::packed`](crate::storage::chunk::Palette::packed)). It saves memory for some time spent as columns
+    /// arrive. Off by default.
+    pub fn compact_sections(&mut self, compact: bool) {
+        self.compact = compact;
+    }
+
     pub fn add_column(&mut self, location: ChunkLocation, column: ChunkColumn) {
-        self.storage.insert(location, column);
+        self.insert_column(location, column, unix_now());
     }
 
-    pub fn modify_column(&mut self, location: ChunkLocation, column: ChunkColumn) {
-        self.storage.get_mut(&location).unwrap().modify(column);
+    /// add the column the server sent at `seen`
+    fn insert_column(&mut self, location: ChunkLocation, mut column: ChunkColumn, seen: u64) {
+        if !self.keep_light {
+            column.strip_light();
+        }
+        if self.compact {
+            column.compact();
+        }
+
+        self.generation += 1;
+        let holders = self.storage.get(&location).map(|chunk| chunk.holders.clone()).unwrap_or_default();
+        let chunk = Chunk {
+            generation: self.generation,
+            heightmap: Arc::new(Heightmap::scan(&column)),
+            column: Arc::new(column),
+            stale: false,
+            tile_entities: HashMap::new(),
+            index: None,
+            holders,
+            seen,
+        };
+        self.storage.insert(location, Arc::new(chunk));
+    }
+
+    /// Drop the columns for which `keep` is false. Returns how many were dropped.
+    pub fn retain_columns(&mut self, mut keep: impl FnMut(ChunkLocation) -> bool) -> usize {
+        if self.storage.keys().all(|&location| keep(location)) {
+            return 0;
+        }
+
+        let before = self.storage.len();
+        self.storage.retain(|&location, _| keep(location));
+        self.generation += 1;
+        before - self.storage.len()
+    }
+
+    /// Drop the columns more than `radius` columns away from `center` on either axis. Returns how many were dropped.
+    pub fn retain_near(&mut self, center: ChunkLocation, radius: i32) -> usize {
+        let ChunkLocation(center_x, center_z) = center;
+        self.retain_columns(|ChunkLocation(x, z)| (x - center_x).abs() <= radius && (z - center_z).abs() <= radius)
+    }
+
+    /// the server sends the column at `location` to the bot `bot`
+    pub fn hold(&mut self, location: ChunkLocation, bot: u32) {
+        let held = self.storage.get(&location).map_or(true, |chunk| chunk.holders.contains(&bot));
+        if !held {
+            self.storage.get_mut(&location).map(Arc::make_mut).unwrap().holders.push(bot);
+        }
+    }
+
+    /// The server stopped sending the column at `location` to the bot `bot`. The column is dropped if it does not send
+    /// it to another bot either. Returns whether it was dropped.
+    pub fn release(&mut self, location: ChunkLocation, bot: u32) -> bool {
+        let last = match self.storage.get(&location) {
+            Some(chunk) => chunk.holders.iter().all(|&holder| holder == bot),
+            None => return false,
+        };
+
+        if last {
+            self.storage.remove(&location);
+            self.generation += 1;
+        } else {
+            self.storage.get_mut(&location).map(Arc::make_mut).unwrap().holders.retain(|&holder| holder != bot);
+        }
+        last
+    }
+
+    /// The bot `bot` is gone: release every column it holds. Returns how many were dropped.
+    pub fn release_all(&mut self, bot: u32) -> usize {
+        let held: Vec<_> = self.storage.iter()
+            .filter(|(_, chunk)| chunk.holders.contains(&bot))
+            .map(|(&location, _)| location)
+            .collect();
+        held.into_iter().filter(|&location| self.release(location, bot)).count()
+    }
+
+    /// Mark the column at `location` as out of sync until the server sends all of it again. Returns false if it is not
+    /// loaded.
+    pub fn mark_stale(&mut self, location: ChunkLocation) -> bool {
+        match self.storage.get_mut(&location).map(Arc::make_mut) {
+            Some(chunk) => {
+                chunk.stale = true;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// trust the column at `location` again without it being resent
+    pub fn mark_fresh(&mut self, location: ChunkLocation) {
+        if let Some(chunk) = self.storage.get_mut(&location).map(Arc::make_mut) {
+            chunk.stale = false;
+        }
+    }
+
+    pub fn is_stale(&self, location: ChunkLocation) -> bool {
+        self.storage.get(&location).map_or(false, |chunk| chunk.stale)
+    }
+
+    /// Apply a partial update to the column at `location` (see [`ChunkColumn::modify`]). Returns false if the column
+    /// is not loaded and `missing` dropped the update.
+    pub fn modify_column(&mut self, location: ChunkLocation, mut column: ChunkColumn, missing: MissingColumn) -> bool {
+        if !self.keep_light {
+            column.strip_light();
+        }
+        if self.compact {
+            column.compact();
+        }
+
+        match self.column_mut(location) {
+            Some(loaded) => loaded.modify(column),
+            None if missing == MissingColumn::Insert => self.add_column(location, column),
+            None => return false,
+        }
+        self.unindex(location);
+
+        let chunk = self.storage.get_mut(&location).map(Arc::make_mut).unwrap();
+        chunk.heightmap = Arc::new(Heightmap::scan(&chunk.column));
+        chunk.seen = unix_now();
+        true
+    }
+
+    /// the column at `location` has to be scanned again for the index
+    fn unindex(&mut self, location: ChunkLocation) {
+        if let Some(chunk) = self.storage.get_mut(&location).map(Arc::make_mut) {
+            chunk.index = None;
+        }
+    }
+
+    /// the top of every column of blocks of the chunk column at `location`
+    pub fn heightmap(&self, location: ChunkLocation) -> Option<&Heightmap> {
+        self.storage.get(&location).map(|chunk| chunk.heightmap.as_ref())
+    }
+
+    /// the top of the column of blocks at `location` (the y does not matter)
+    pub fn surface(&self, location: BlockLocation) -> Option<Surface> {
+        let heightmap = self.heightmap(ChunkLocation::from(location))?;
+        Some(heightmap.surface((location.x & 15) as u8, (location.z & 15) as u8))
+    }
+
+    pub fn column_composition(&self, location: ChunkLocation) -> Option<Composition> {
+        self.column(location).map(ChunkColumn::composition)
+    }
+
+    pub fn section_composition(&self, location: ChunkLocation, idx: usize) -> Option<Composition> {
+        self.column(location).map(|column| column.section_composition(idx))
+    }
+
+    /// the blocks in the loaded columns from `from` to `to` (inclusive, in any order)
+    pub fn area_composition(&self, from: ChunkLocation, to: ChunkLocation) -> Composition {
+        let (min_x, max_x) = (from.0.min(to.0), from.0.max(to.0));
+        let (min_z, max_z) = (from.1.min(to.1), from.1.max(to.1));
+
+        let mut composition = Composition::default();
+        for (location, chunk) in self.storage.iter() {
+            if (min_x..=max_x).contains(&location.0) && (min_z..=max_z).contains(&location.1) {
+                composition.add(&chunk.column.composition());
+            }
+        }
+        composition
+    }
+
+    /// the blocks in every loaded column
+    pub fn composition(&self) -> Composition {
+        let mut composition = Composition::default();
+        for chunk in self.storage.values() {
+            composition.add(&chunk.column.composition());
+        }
+        composition
     }
 
     pub fn get_block(&self, location: BlockLocation) -> Option<BlockApprox> {
@@ -206,7 +537,7 @@ impl WorldBlocks {
         let chunk_z = chunk_z as i32;
 
         let loc = ChunkLocation(chunk_x, chunk_z);
-        let column = self.storage.get(&loc)?;
+        let column = self.column(loc)?;
 
         // this *should* be either the void or the sky (at least pre-1.17)
         // we do this check here because we want to return None if there is no chunk column in that position
@@ -220,7 +551,7 @@ impl WorldBlocks {
 
     pub fn closest_in_chunk(&'a self, origin: BlockLocation, selector: impl FnMut(BlockState) -> bool + 'a + Copy) -> Option<BlockLocation> {
         let loc = ChunkLocation::from(origin);
-        let chunk = self.storage.get(&loc)?;
+        let chunk = self.column(loc)?;
 
         if let ChunkColumn::HighMemory { data } = chunk {
             block_chunk_iter(&loc, data, selector).min_by_key(|&location| FloatOrd(origin.dist2(location)))
@@ -234,9 +565,10 @@ impl WorldBlocks {
             .min_by_key(|loc| FloatOrd(loc.dist2(origin)))
     }
 
-    pub fn closest_iter(&'a self, origin: BlockLocation, selector: impl FnMut(BlockState) -> bool + 'a + Copy) -> impl Iterator<Item=BlockLocation> + 'a {
+    /// the selected blocks in the `max_chunks` columns closest to `origin`, closest first
+    pub fn closest_iter(&'a self, origin: BlockLocation, max_chunks: usize, selector: impl FnMut(BlockState) -> bool + 'a + Copy) -> impl Iterator<Item=BlockLocation> + 'a {
         // we use a heap to reduce complexity in case we do not need to use all values
-        let heap = self.select(origin, usize::MAX, selector)
+        let heap = self.select(origin, max_chunks, selector)
             .map(|loc| MinHeapNode::new(loc, loc.dist2(origin)))
             .collect();
 
@@ -244,36 +576,53 @@ impl WorldBlocks {
         iterator.map(|node| node.contents)
     }
 
-    fn real_chunks(&self) -> impl Iterator<Item=(&ChunkLocation, &ChunkData<HighMemoryChunkSection>)> + '_ {
+    fn real_chunks(&self) -> impl Iterator<Item=(&ChunkLocation, &Chunk, &ChunkData<HighMemoryChunkSection>)> + '_ {
         self.storage.iter()
-            .filter_map(|(loc, column)| {
-                match column {
+            .filter_map(|(loc, chunk)| {
+                match chunk.column.as_ref() {
                     ChunkColumn::HighMemory { data } => {
-                        Some((loc, data))
+                        Some((loc, &**chunk, data))
                     }
                     _ => { None }
                 }
             })
     }
 
-    pub fn select(&'a self, _around: BlockLocation, max_chunks: usize, selector: impl FnMut(BlockState) -> bool + 'a + Copy) -> impl Iterator<Item=BlockLocation> + 'a {
-        self.real_chunks()
+    /// the columns which are known exactly, the closest to `around` first
+    fn real_chunks_around(&self, around: BlockLocation) -> Vec<(&ChunkLocation, &Chunk, &ChunkData<HighMemoryChunkSection>)> {
+        let ChunkLocation(center_x, center_z) = ChunkLocation::from(around);
+        let mut chunks: Vec<_> = self.real_chunks().collect();
+        chunks.sort_by_key(|(ChunkLocation(x, z), ..)| {
+            let dx = *x as i64 - center_x as i64;
+            let dz = *z as i64 - center_z as i64;
+            (dx * dx + dz * dz, *x, *z)
+        });
+        chunks
+    }
+
+    /// The selected blocks in the `max_chunks` loaded columns closest to `around`. The columns are searched outwards
+    /// but the blocks of a column are not in any order.
+    pub fn select(&'a self, around: BlockLocation, max_chunks: usize, selector: impl FnMut(BlockState) -> bool + 'a + Copy) -> impl Iterator<Item=BlockLocation> + 'a {
+        self.real_chunks_around(around)
+            .into_iter()
             .take(max_chunks)
-            .flat_map(move |(loc, column)| {
+            .flat_map(move |(loc, _, column)| {
                 block_chunk_iter(loc, column, selector)
             })
     }
 
     pub fn get_real_column(&self, location: ChunkLocation) -> Option<&ChunkData<HighMemoryChunkSection>> {
-        let res = self.storage.get(&location)?;
+        let res = self.column(location)?;
         match res {
             ChunkColumn::HighMemory { data } => Some(data),
             _ => None
         }
     }
 
+    /// Changes made through this are not in the [`heightmap`](Self::heightmap) of the column.
     pub fn get_real_column_mut(&mut self, location: ChunkLocation) -> Option<&mut ChunkData<HighMemoryChunkSection>> {
-        let res = self.storage.get_mut(&location)?;
+        self.unindex(location);
+        let res = self.column_mut(location)?;
         match res {
             ChunkColumn::HighMemory { data } => Some(data),
             _ => None
@@ -297,8 +646,74 @@ impl WorldBlocks {
 
         let loc = ChunkLocation(chunk_x, chunk_z);
 
-        let column = self.storage.entry(loc).or_default();
-        column.set_block(x, y, z, block);
+        if self.column(loc).is_none() {
+            self.add_column(loc, ChunkColumn::default());
+        }
+
+        let previous = self.get_block_kind(location);
+        self.column_mut(loc).unwrap().set_block(x, y, z, block);
+
+        let chunk = self.storage.get_mut(&loc).map(Arc::make_mut).unwrap();
+        Arc::make_mut(&mut chunk.heightmap).update(&chunk.column, x, y, z);
+
+        if self.keep_light {
+            self.spread_light(location, block.kind().emitted_light());
+        }
+
+        // an indexed column is kept up to date instead of being scanned again. Columns which are only known roughly
+        // have nothing indexed.
+        if let Some(previous) = previous {
+            let chunk = self.storage.get_mut(&loc).map(Arc::make_mut).unwrap();
+            if let Some(index) = chunk.index.as_mut() {
+                index.replace(location, previous, block.kind(), &self.indexed);
+            }
+        }
+
+        // the block entity went with the block
+        let gone = self.storage[&loc].tile_entities.get(&location).map_or(false, |entity| !entity.fits(block.kind()));
+        if gone {
+            self.storage.get_mut(&loc).map(Arc::make_mut).unwrap().tile_entities.remove(&location);
+        }
+    }
+
+    /// Remember the block entity at `location`. Ignored if the column is not loaded.
+    pub fn set_tile_entity(&mut self, location: BlockLocation, entity: TileEntity) {
+        if let Some(chunk) = self.storage.get_mut(&ChunkLocation::from(location)).map(Arc::make_mut) {
+            chunk.tile_entities.insert(location, entity);
+        }
+    }
+
+    pub fn tile_entity(&self, location: BlockLocation) -> Option<&TileEntity> {
+        self.storage.get(&ChunkLocation::from(location))?.tile_entities.get(&location)
+    }
+
+    /// the block entities of every loaded column
+    pub fn tile_entities(&self) -> impl Iterator<Item=(BlockLocation, &TileEntity)> + '_ {
+        self.storage.values().flat_map(|chunk| chunk.tile_entities.iter().map(|(location, entity)| (*location, entity)))
+    }
+
+    pub fn sign_text(&self, location: BlockLocation) -> Option<SignText> {
+        match self.tile_entity(location)? {
+            TileEntity::Sign(text) => Some(text.clone()),
+            _ => None
+        }
+    }
+
+    /// the text of every sign in loaded columns
+    pub fn signs(&self) -> impl Iterator<Item=(BlockLocation, &SignText)> + '_ {
+        self.tile_entities().filter_map(|(location, entity)| match entity {
+            TileEntity::Sign(text) => Some((location, text)),
+            _ => None
+        })
+    }
+
+    /// The blocks in loaded columns the server said hold items named `name`, with how many they hold. Most servers
+    /// only say what is in a chest when it is opened.
+    pub fn holding<'a>(&'a self, name: &'a str) -> impl Iterator<Item=(BlockLocation, u32)> + 'a {
+        self.tile_entities().filter_map(move |(location, entity)| match entity.count(name) {
+            0 => None,
+            count => Some((location, count))
+        })
     }
 
     pub fn get_block_simple(&self, location: BlockLocation) -> Option<SimpleType> {
@@ -306,6 +721,18 @@ impl WorldBlocks {
         Some(block.s_type())
     }
 
+    pub fn get_shape(&self, location: BlockLocation) -> Option<Shape> {
+        self.get_block(location)?.shape()
+    }
+
+    /// How far above the bottom of `location` something standing in it is (i.e., 0.5 for a bottom slab)
+    pub fn standing_height(&self, location: BlockLocation) -> f64 {
+        match self.get_shape(location) {
+            Some(Shape::Bottom(height)) => f64::from(height) / 16.0,
+            _ => 0.0
+        }
+    }
+
     pub fn get_block_exact(&self, location: BlockLocation) -> Option<BlockState> {
         let block = self.get_block(location)?;
         match block {
@@ -323,14 +750,17 @@ impl WorldBlocks {
 #[cfg(test)]
 mod tests {
     use std::fs::OpenOptions;
+    use std::sync::Arc;
     use test::{Bencher, black_box};
 
     use assert_matches::assert_matches as am;
     use rand::Rng;
 
     use crate::schematic::Schematic;
-    use crate::storage::block::{BlockApprox, BlockLocation, BlockState};
-    use crate::storage::blocks::WorldBlocks;
+    use crate::storage::block::{BlockApprox, BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, MissingColumn, WorldBlocks, WorldSnapshot};
+    use crate::storage::chunk::ChunkColumn;
+    use crate::storage::tile_entity::{StoredItem, TileEntity};
 
     #[test]
     fn test_get_set() {
@@ -364,6 +794,100 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_tile_entities() {
+        let mut world = WorldBlocks::default();
+        let loc = BlockLocation::new(3, 70, -5);
+        let text = || ["base".to_string(), "100 64 -200".to_string(), String::new(), String::new()];
+
+        // nothing is kept for columns which are not loaded
+        world.set_tile_entity(loc, TileEntity::Sign(text()));
+        assert_eq!(world.sign_text(loc), None);
+
+        world.set_block(loc, BlockState::from(63, 4));
+        world.set_tile_entity(loc, TileEntity::Sign(text()));
+        assert_eq!(world.sign_text(loc), Some(text()));
+        assert_eq!(world.signs().count(), 1);
+
+        // a snapshot keeps the text after the sign is broken
+        let snapshot = world.snapshot();
+        world.set_block(loc, BlockState::AIR);
+        assert_eq!(world.sign_text(loc), None);
+        assert_eq!(snapshot.sign_text(loc), Some(text()));
+
+        // a new column comes with its own block entities
+        world.set_block(loc, BlockState::from(68, 2));
+        world.set_tile_entity(loc, TileEntity::Sign(text()));
+        world.add_column(ChunkLocation::from(loc), ChunkColumn::default());
+        assert_eq!(world.sign_text(loc), None);
+
+        // a spawner is not a sign
+        let spawner = TileEntity::Spawner { mob: Some("zombie".to_string()) };
+        world.set_block(loc, BlockState::from(BlockKind::MOB_SPAWNER.id(), 0));
+        world.set_tile_entity(loc, spawner.clone());
+        assert_eq!(world.tile_entity(loc), Some(&spawner));
+        assert_eq!(world.sign_text(loc), None);
+        assert_eq!(world.signs().count(), 0);
+
+        // chests whose items the server sent can be searched
+        let chest = loc + BlockLocation::new(1, 0, 0);
+        let diamonds = StoredItem { slot: 4, name: "diamond".to_string(), count: 12, damage: 0 };
+        world.set_block(chest, BlockState::from(54, 2));
+        world.set_tile_entity(chest, TileEntity::Container { id: "chest".to_string(), items: vec![diamonds] });
+        assert_eq!(world.holding("diamond").collect::<Vec<_>>(), vec![(chest, 12)]);
+        assert_eq!(world.holding("emerald").count(), 0);
+    }
+
+    #[test]
+    fn test_modify_missing() {
+        let location = ChunkLocation(2, -1);
+        let block = BlockLocation::new(32, 20, -16);
+        let update = || {
+            let mut column = ChunkColumn::default();
+            column.set_block(0, 20, 0, BlockState::STONE);
+            column
+        };
+
+        let mut world = WorldBlocks::default();
+        assert!(!world.modify_column(location, update(), MissingColumn::Drop));
+        assert_eq!(world.get_block_exact(block), None);
+
+        assert!(world.modify_column(location, update(), MissingColumn::Insert));
+        assert_eq!(world.get_block_exact(block), Some(BlockState::STONE));
+        assert_eq!(world.get_block_exact(BlockLocation::new(32, 1, -16)), Some(BlockState::AIR));
+
+        // once loaded the policy does not matter
+        world.set_block(BlockLocation::new(33, 1, -16), BlockState::STONE);
+        assert!(world.modify_column(location, ChunkColumn::default(), MissingColumn::Drop));
+        assert_eq!(world.get_block_exact(BlockLocation::new(33, 1, -16)), Some(BlockState::STONE));
+    }
+
+    #[test]
+    fn test_select_outwards() {
+        let mut world = WorldBlocks::default();
+        let ore = BlockState::from(56, 0);
+        let near = BlockLocation::new(-20, 12, 3);
+        let far = BlockLocation::new(200, 12, -300);
+
+        // many columns so the near one is not found first by chance
+        for x in -10..10 {
+            for z in -10..10 {
+                world.set_block(BlockLocation::new(x * 16, 0, z * 16), BlockState::STONE);
+            }
+        }
+        world.set_block(far, ore);
+        world.set_block(near, ore);
+
+        let origin = BlockLocation::new(0, 64, 0);
+        // the ore is two columns away, outside the closest 9 columns
+        assert_eq!(world.closest(origin, 9, |state| state == ore), None);
+        assert_eq!(world.closest(origin, 13, |state| state == ore), Some(near));
+        assert_eq!(world.closest(far, 1, |state| state == ore), Some(far));
+
+        let all: Vec<_> = world.closest_iter(origin, usize::MAX, |state| state == ore).collect();
+        assert_eq!(all, vec![near, far]);
+    }
+
     #[test]
     fn test_full_circle() {
         let mut world = WorldBlocks::default();
@@ -374,7 +898,7 @@ mod tests {
                 .open("test-data/2b2t.schematic")
                 .unwrap();
 
-            Schematic::load(&mut spawn_2b2t)
+            Schematic::load(&mut spawn_2b2t).unwrap()
         };
 
 
@@ -386,6 +910,205 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_snapshot_generation() {
+        let mut world = WorldBlocks::default();
+        let loc = BlockLocation::new(0, 0, 0);
+        let other = BlockLocation::new(100, 0, 100);
+
+        world.set_block(loc, BlockState::STONE);
+        world.set_block(other, BlockState::STONE);
+
+        let snapshot = world.snapshot();
+        assert!(!snapshot.is_stale(&world));
+
+        world.set_block(loc, BlockState::AIR);
+
+        assert!(snapshot.is_stale(&world));
+        assert!(snapshot.generation() < world.generation());
+        assert!(snapshot.is_chunk_stale(&world, ChunkLocation::from(loc)));
+        assert!(!snapshot.is_chunk_stale(&world, ChunkLocation::from(other)));
+
+        // the snapshot still sees the old world
+        am!(snapshot.get_block(loc), Some(BlockApprox::Realized(BlockState::STONE)));
+        am!(world.get_block(loc), Some(BlockApprox::Realized(BlockState::AIR)));
+    }
+
+    #[test]
+    fn test_snapshot_shares_columns() {
+        let mut world = WorldBlocks::flat();
+        let snapshot = world.snapshot();
+
+        let loc = BlockLocation::new(0, 1, 0);
+        world.set_block(loc, BlockState::STONE);
+
+        let touched = ChunkLocation::from(loc);
+        for (location, chunk) in world.storage.iter() {
+            let before = &snapshot.world.storage[location];
+            assert_eq!(Arc::ptr_eq(chunk, before), *location != touched, "column {:?}", location);
+            assert_eq!(Arc::ptr_eq(&chunk.column, &before.column), *location != touched, "column {:?}", location);
+        }
+    }
+
+    #[test]
+    fn test_retain_columns() {
+        let mut world = WorldBlocks::flat();
+        let count = world.chunk_count();
+        let generation = world.generation();
+
+        assert_eq!(world.retain_columns(|_| true), 0);
+        assert_eq!(world.generation(), generation);
+
+        let snapshot = world.snapshot();
+        let dropped = world.retain_columns(|ChunkLocation(x, z)| x.abs() <= 1 && z.abs() <= 1);
+        assert_eq!(dropped, count - 9);
+        assert_eq!(world.chunk_count(), 9);
+        assert!(world.get_block(BlockLocation::new(50, 0, 50)).is_none());
+        assert!(snapshot.is_chunk_stale(&world, ChunkLocation(3, 3)));
+
+        // the snapshot still sees the dropped columns
+        assert_eq!(snapshot.chunk_count(), count);
+
+        assert_eq!(world.retain_near(ChunkLocation(1, 1), 1), 5);
+        assert_eq!(world.chunk_count(), 4);
+        assert!(world.get_block(BlockLocation::new(0, 0, 0)).is_some());
+        assert!(world.get_block(BlockLocation::new(-16, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_hold_release() {
+        let mut world = WorldBlocks::default();
+        let location = ChunkLocation(2, -3);
+        world.add_column(location, ChunkColumn::default());
+        world.hold(location, 1);
+        world.hold(location, 2);
+        world.hold(location, 2);
+
+        // a new column of the same location keeps who is sent it
+        world.add_column(location, ChunkColumn::default());
+
+        assert!(!world.release(location, 1));
+        assert!(!world.release(location, 1));
+        assert_eq!(world.chunk_count(), 1);
+        assert!(world.release(location, 2));
+        assert_eq!(world.chunk_count(), 0);
+        assert!(!world.release(location, 2));
+
+        // holding a column which is not loaded does nothing
+        world.hold(location, 1);
+        assert_eq!(world.chunk_count(), 0);
+    }
+
+    #[test]
+    fn test_release_all() {
+        let mut world = WorldBlocks::default();
+        let shared = ChunkLocation(0, 0);
+        let own = ChunkLocation(1, 0);
+        for &location in &[shared, own] {
+            world.add_column(location, ChunkColumn::default());
+            world.hold(location, 1);
+        }
+        world.hold(shared, 2);
+
+        // the column only the removed bot held is dropped
+        assert_eq!(world.release_all(1), 1);
+        assert_eq!(world.chunk_count(), 1);
+        assert_eq!(world.release_all(1), 0);
+
+        // and the other bot is the last holder of the shared one
+        assert!(world.release(shared, 2));
+        assert_eq!(world.chunk_count(), 0);
+    }
+
+    /// a thread scanning snapshots while the world is being rewritten must only ever see whole sections
+    #[test]
+    fn test_snapshot_no_torn_reads() {
+        const ROUNDS: u32 = 20;
+
+        fn fill(world: &mut WorldBlocks, state: BlockState) {
+            for x in 0..32 {
+                for z in 0..32 {
+                    for y in 0..16 {
+                        world.set_block(BlockLocation::new(x, y, z), state);
+                    }
+                }
+            }
+        }
+
+        let mut world = WorldBlocks::default();
+        fill(&mut world, BlockState(1));
+
+        let (tx, rx) = std::sync::mpsc::channel::<(WorldSnapshot, BlockState)>();
+
+        let reader = std::thread::spawn(move || {
+            let mut scanned = 0;
+            while let Ok((snapshot, expected)) = rx.recv() {
+                // scan a few times so the writer is busy mutating while we read
+                for _ in 0..3 {
+                    for cx in 0..2 {
+                        for cz in 0..2 {
+                            let column = snapshot.get_real_column(ChunkLocation(cx, cz)).unwrap();
+                            let section = column.sections[0].as_ref().unwrap();
+                            let states = section.palette().all_states();
+                            assert!(states.iter().all(|&state| state == expected), "torn read in generation {}", snapshot.generation());
+                            scanned += 1;
+                        }
+                    }
+                }
+            }
+            scanned
+        });
+
+        for round in 1..=ROUNDS {
+            tx.send((world.snapshot(), BlockState(round))).unwrap();
+            fill(&mut world, BlockState(round + 1));
+        }
+
+        drop(tx);
+
+        let scanned = reader.join().unwrap();
+        assert_eq!(scanned, ROUNDS * 3 * 4);
+    }
+
+    fn load_2b2t() -> WorldBlocks {
+        let mut world = WorldBlocks::default();
+
+        let mut spawn_2b2t = OpenOptions::new()
+            .read(true)
+            .open("test-data/2b2t.schematic")
+            .unwrap();
+
+        let schematic = Schematic::load(&mut spawn_2b2t).unwrap();
+
+        world.paste(&schematic);
+        world
+    }
+
+    #[bench]
+    fn bench_snapshot(b: &mut Bencher) {
+        let world = load_2b2t();
+        b.iter(|| black_box(world.snapshot()));
+    }
+
+    #[bench]
+    fn bench_set_block(b: &mut Bencher) {
+        let mut world = load_2b2t();
+        let loc = BlockLocation::new(0, 10, 0);
+        b.iter(|| world.set_block(loc, BlockState::STONE));
+    }
+
+    /// worst case: every mutation has to copy the chunk map, the column and the section
+    #[bench]
+    fn bench_set_block_after_snapshot(b: &mut Bencher) {
+        let mut world = load_2b2t();
+        let loc = BlockLocation::new(0, 10, 0);
+        b.iter(|| {
+            let snapshot = world.snapshot();
+            world.set_block(loc, BlockState::STONE);
+            black_box(snapshot);
+        });
+    }
+
     #[bench]
     fn bench_get_block(b: &mut Bencher) {
         let mut world = WorldBlocks::default();
@@ -396,7 +1119,7 @@ mod tests {
                 .open("test-data/2b2t.schematic")
                 .unwrap();
 
-            Schematic::load(&mut spawn_2b2t)
+            Schematic::load(&mut spawn_2b2t).unwrap()
         };
 
 
@@ -0,0 +1,387 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Moving the saved world between machines. [`pack`] streams the columns of a world saved with
+//! [`WorldBlocks::save`] into an archive which starts with a [`Manifest`], and [`unpack`] merges an archive into a
+//! saved world, keeping the newest of each column. Every record of the archive is checksummed. Running an interrupted
+//! pack or unpack again is safe, and an unpack skips the columns it already merged.
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bincode::Options;
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+use crate::error::{err, Error, Res};
+use crate::storage::blocks::{ChunkLocation, unix_now, WorldBlocks};
+use crate::storage::blocks::persist::SavedColumn;
+use crate::storage::chunk::ChunkColumn;
+
+/// bumped when the format changes, archives of other versions are not unpacked
+const VERSION: u32 = 1;
+
+/// a column is well below this, a longer record is corrupt
+const MAX_RECORD: u64 = 16 << 20;
+
+/// how many columns are merged between saving the progress of an unpack
+const CHECKPOINT: usize = 4096;
+
+/// A rectangle of columns. Parsed from the block coordinates `x1,z1,x2,z2` (in any order).
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: ChunkLocation,
+    pub max: ChunkLocation,
+}
+
+impl Bounds {
+    pub fn contains(&self, ChunkLocation(x, z): ChunkLocation) -> bool {
+        (self.min.0..=self.max.0).contains(&x) && (self.min.1..=self.max.1).contains(&z)
+    }
+
+    /// the smallest bounds with `self` and `location`
+    fn grow(self, ChunkLocation(x, z): ChunkLocation) -> Bounds {
+        Bounds {
+            min: ChunkLocation(self.min.0.min(x), self.min.1.min(z)),
+            max: ChunkLocation(self.max.0.max(x), self.max.1.max(z)),
+        }
+    }
+}
+
+impl FromStr for Bounds {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || err(&format!("{} is not x1,z1,x2,z2", s));
+        let coords: Vec<i32> = s.split(',').map(|coord| coord.trim().parse()).collect::<Result<_, _>>().map_err(|_| invalid())?;
+        match coords[..] {
+            [x1, z1, x2, z2] => Ok(Bounds {
+                min: ChunkLocation(x1.min(x2) >> 4, z1.min(z2) >> 4),
+                max: ChunkLocation(x1.max(x2) >> 4, z1.max(z2) >> 4),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// The first record of an archive
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Manifest {
+    pub version: u32,
+
+    /// the server the columns are of
+    pub server: String,
+
+    /// when (unix seconds) the archive was packed
+    pub packed: u64,
+    pub columns: u64,
+
+    /// the columns lie within these. None if there are no columns.
+    pub bounds: Option<Bounds>,
+}
+
+/// A value with the checksum of its encoding
+#[derive(Serialize, Deserialize)]
+struct Record {
+    checksum: u32,
+    data: Vec<u8>,
+}
+
+fn encoding() -> impl Options {
+    bincode::DefaultOptions::new().with_fixint_encoding().with_limit(MAX_RECORD)
+}
+
+fn write_record(writer: &mut impl Write, value: &impl Serialize) -> Res {
+    let data = bincode::serialize(value).map_err(|error| err(&format!("could not encode record: {}", error)))?;
+    let record = Record { checksum: crc32fast::hash(&data), data };
+    encoding().serialize_into(writer, &record).map_err(|error| err(&format!("could not write record: {}", error)))
+}
+
+/// the value and its checksum, which identifies it
+fn read_record<T: DeserializeOwned>(reader: &mut impl Read) -> Res<(T, u32)> {
+    let Record { checksum, data } = encoding().deserialize_from(reader)
+        .map_err(|error| err(&format!("the archive is cut off or corrupt: {}", error)))?;
+    if crc32fast::hash(&data) != checksum {
+        return Err(err("a record of the archive does not match its checksum"));
+    }
+    let value = bincode::deserialize(&data).map_err(|error| err(&format!("could not decode record: {}", error)))?;
+    Ok((value, checksum))
+}
+
+/// Pack the columns saved in `world_file` (within `bounds`) of `server` into the archive `out`
+pub fn pack(world_file: &Path, out: &Path, server: &str, bounds: Option<Bounds>) -> Res<Manifest> {
+    let mut world = WorldBlocks::default();
+    world.load(world_file)?;
+
+    // an interrupted pack leaves only the temporary file behind
+    let tmp = out.with_extension("tmp");
+    let mut encoder = ZlibEncoder::new(BufWriter::new(File::create(&tmp)?), Compression::default());
+    let manifest = write_archive(&world, &mut encoder, server, bounds)?;
+    encoder.finish()?.flush()?;
+
+    fs::rename(&tmp, out)?;
+    Ok(manifest)
+}
+
+/// write the records of the archive uncompressed
+fn write_archive(world: &WorldBlocks, writer: &mut impl Write, server: &str, bounds: Option<Bounds>) -> Res<Manifest> {
+    let mut locations: Vec<_> = world.storage.iter()
+        .filter(|&(&location, chunk)| {
+            bounds.map_or(true, |bounds| bounds.contains(location)) && matches!(chunk.column.as_ref(), ChunkColumn::HighMemory { .. })
+        })
+        .map(|(&location, _)| location)
+        .collect();
+    locations.sort_by_key(|&ChunkLocation(x, z)| (x, z));
+
+    let manifest = Manifest {
+        version: VERSION,
+        server: server.to_string(),
+        packed: unix_now(),
+        columns: locations.len() as u64,
+        bounds: locations.iter().fold(None, |bounds: Option<Bounds>, &location| {
+            Some(bounds.map_or(Bounds { min: location, max: location }, |bounds| bounds.grow(location)))
+        }),
+    };
+
+    write_record(writer, &manifest)?;
+    for location in locations {
+        let column = SavedColumn::new(location, &world.storage[&location]).unwrap();
+        write_record(writer, &column)?;
+    }
+    Ok(manifest)
+}
+
+/// How an unpack went
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Unpacked {
+    /// columns the world did not have
+    pub added: usize,
+
+    /// columns newer than the ones of the world
+    pub replaced: usize,
+
+    /// columns not newer than the ones of the world
+    pub kept: usize,
+
+    /// columns merged by an earlier, interrupted unpack of the archive
+    pub skipped: usize,
+}
+
+/// where an interrupted unpack stopped
+#[derive(Serialize, Deserialize)]
+struct Progress {
+    /// the checksum of the manifest of the archive
+    archive: u32,
+    done: u64,
+}
+
+/// Merge the archive `archive` into the world saved in `world_file` (which is created if it does not exist). `server`
+/// must be the server of the archive, unless it is empty.
+pub fn unpack(archive: &Path, world_file: &Path, server: &str) -> Res<Unpacked> {
+    let mut decoder = ZlibDecoder::new(BufReader::new(File::open(archive)?));
+    unpack_from(&mut decoder, world_file, server, CHECKPOINT)
+}
+
+/// unpack the uncompressed records, saving the world and the progress every `every` columns
+fn unpack_from(reader: &mut impl Read, world_file: &Path, server: &str, every: usize) -> Res<Unpacked> {
+    let (manifest, id): (Manifest, _) = read_record(reader)?;
+    if manifest.version != VERSION {
+        return Err(err(&format!("the archive is in version {} of the format, not {}", manifest.version, VERSION)));
+    }
+    if !server.is_empty() && manifest.server != server {
+        return Err(err(&format!("the archive is of {}, not {}", manifest.server, server)));
+    }
+
+    let mut world = WorldBlocks::default();
+    if world_file.exists() {
+        world.load(world_file)?;
+    }
+
+    let progress_file: PathBuf = world_file.with_extension("unpack");
+    let done = fs::read(&progress_file).ok()
+        .and_then(|contents| serde_json::from_slice::<Progress>(&contents).ok())
+        .filter(|progress| progress.archive == id)
+        .map_or(0, |progress| progress.done);
+
+    let mut unpacked = Unpacked::default();
+    for idx in 0..manifest.columns {
+        let (column, _) = read_record(reader)?;
+        if idx < done {
+            unpacked.skipped += 1;
+            continue;
+        }
+
+        match world.merge_column(column)? {
+            Merge::Added => unpacked.added += 1,
+            Merge::Replaced => unpacked.replaced += 1,
+            Merge::Kept => unpacked.kept += 1,
+        }
+
+        // the world is saved before the progress, so the progress never claims a column the saved world lacks
+        if (idx + 1) % every as u64 == 0 {
+            world.save(world_file)?;
+            fs::write(&progress_file, serde_json::to_vec(&Progress { archive: id, done: idx + 1 })?)?;
+        }
+    }
+
+    world.save(world_file)?;
+    if progress_file.exists() {
+        fs::remove_file(&progress_file)?;
+    }
+    Ok(unpacked)
+}
+
+enum Merge {
+    Added,
+    Replaced,
+    Kept,
+}
+
+impl WorldBlocks {
+    /// add `column` unless the world has one seen at the same time or later
+    fn merge_column(&mut self, column: SavedColumn) -> Res<Merge> {
+        let merge = match self.storage.get(&column.location) {
+            None => Merge::Added,
+            Some(chunk) if chunk.seen < column.seen => Merge::Replaced,
+            Some(_) => return Ok(Merge::Kept),
+        };
+        self.restore_column(column)?;
+        Ok(merge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+    use crate::storage::blocks::archive::{Bounds, unpack_from, Unpacked, write_archive};
+
+    /// a world with a column at each location, with a block of the state at its corner and seen at the time
+    fn world(columns: &[(ChunkLocation, BlockState, u64)]) -> WorldBlocks {
+        let mut world = WorldBlocks::default();
+        for &(location, state, seen) in columns {
+            world.set_block(corner(location), state);
+            Arc::make_mut(world.storage.get_mut(&location).unwrap()).seen = seen;
+        }
+        world
+    }
+
+    fn corner(ChunkLocation(x, z): ChunkLocation) -> BlockLocation {
+        BlockLocation::new(x << 4, 10, z << 4)
+    }
+
+    fn archive(world: &WorldBlocks, bounds: Option<Bounds>) -> Vec<u8> {
+        let mut archive = Vec::new();
+        write_archive(world, &mut archive, "mc.example.com", bounds).unwrap();
+        archive
+    }
+
+    fn world_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("swarm-archive-{}-{}.bin", name, std::process::id()))
+    }
+
+    fn saved(path: &Path) -> WorldBlocks {
+        let mut world = WorldBlocks::default();
+        world.load(path).unwrap();
+        world
+    }
+
+    #[test]
+    fn test_bounds() {
+        let packed = world(&[
+            (ChunkLocation(0, 0), BlockState::STONE, 1),
+            (ChunkLocation(1, -1), BlockState::STONE, 1),
+            (ChunkLocation(5, 0), BlockState::STONE, 1),
+        ]);
+        let bounds: Bounds = "31,-16,0,15".parse().unwrap();
+        assert_eq!(bounds, Bounds { min: ChunkLocation(0, -1), max: ChunkLocation(1, 0) });
+        assert!("1,2,3".parse::<Bounds>().is_err());
+
+        let path = world_file("bounds");
+        let unpacked = unpack_from(&mut &archive(&packed, Some(bounds))[..], &path, "mc.example.com", 100).unwrap();
+        assert_eq!(unpacked, Unpacked { added: 2, ..Unpacked::default() });
+
+        let world = saved(&path);
+        assert_eq!(world.chunk_count(), 2);
+        assert!(world.get_block(corner(ChunkLocation(5, 0))).is_none());
+
+        // the archive is of another server
+        assert!(unpack_from(&mut &archive(&packed, None)[..], &path, "other.example.com", 100).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let (newer, older, new) = (ChunkLocation(0, 0), ChunkLocation(1, 0), ChunkLocation(2, 0));
+        let path = world_file("merge");
+        world(&[(newer, BlockState::STONE, 200), (older, BlockState::STONE, 100)]).save(&path).unwrap();
+
+        let packed = archive(&world(&[
+            (newer, BlockState::from(3, 0), 150),
+            (older, BlockState::from(3, 0), 300),
+            (new, BlockState::from(3, 0), 50),
+        ]), None);
+
+        let unpacked = unpack_from(&mut &packed[..], &path, "", 100).unwrap();
+        assert_eq!(unpacked, Unpacked { added: 1, replaced: 1, kept: 1, skipped: 0 });
+
+        let world = saved(&path);
+        assert_eq!(world.get_block_exact(corner(newer)), Some(BlockState::STONE));
+        assert_eq!(world.get_block_exact(corner(older)), Some(BlockState::from(3, 0)));
+        assert_eq!(world.get_block_exact(corner(new)), Some(BlockState::from(3, 0)));
+
+        // unpacking again changes nothing
+        let unpacked = unpack_from(&mut &packed[..], &path, "", 100).unwrap();
+        assert_eq!(unpacked, Unpacked { kept: 3, ..Unpacked::default() });
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_interrupted_unpack() {
+        let columns: Vec<_> = (0..5).map(|x| (ChunkLocation(x, 0), BlockState::STONE, 1)).collect();
+        let packed = archive(&world(&columns), None);
+        let path = world_file("interrupted");
+
+        // the last column is cut off after two checkpoints
+        let cut = &packed[..packed.len() - 10];
+        assert!(unpack_from(&mut &cut[..], &path, "", 2).is_err());
+        assert_eq!(saved(&path).chunk_count(), 4);
+        assert!(path.with_extension("unpack").exists());
+
+        let unpacked = unpack_from(&mut &packed[..], &path, "", 2).unwrap();
+        assert_eq!(unpacked, Unpacked { added: 1, skipped: 4, ..Unpacked::default() });
+        assert_eq!(saved(&path).chunk_count(), 5);
+        assert!(!path.with_extension("unpack").exists());
+
+        // a corrupt record is noticed
+        let mut corrupt = packed.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert!(unpack_from(&mut &corrupt[..], &path, "", 2).is_err());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_extension("unpack")).unwrap();
+    }
+}
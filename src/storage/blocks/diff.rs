@@ -0,0 +1,188 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Comparing the world with the blocks which should be in it, i.e., to check a build or a cleared area
+
+use std::collections::HashMap;
+
+use crate::schematic::Schematic;
+use crate::storage::block::{BlockLocation, BlockState};
+use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+
+/// How the world differs from what it should be. The lists are in no particular order.
+#[derive(Default, Debug)]
+pub struct DiffReport {
+    /// blocks which are air or another block, with the block which should be there
+    pub missing: Vec<(BlockLocation, BlockState)>,
+
+    /// blocks which should be air but are not
+    pub extra: Vec<BlockLocation>,
+
+    /// blocks which are what they should be
+    pub matches: Vec<BlockLocation>,
+
+    /// blocks in columns which are not loaded (or only known roughly) so they could not be compared
+    pub unknown: Vec<BlockLocation>,
+}
+
+impl DiffReport {
+    /// nothing is missing or extra where the world is known
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty()
+    }
+
+    fn add(&mut self, location: BlockLocation, expected: BlockState, same: bool) {
+        if same {
+            self.matches.push(location);
+        } else if expected == BlockState::AIR {
+            self.extra.push(location);
+        } else {
+            self.missing.push((location, expected));
+        }
+    }
+}
+
+impl WorldBlocks {
+    /// compare the world with `schematic` moved `offset` from where it was saved
+    pub fn diff(&self, schematic: &Schematic, offset: BlockLocation) -> DiffReport {
+        let expected = schematic.blocks().map(|(location, state)| (location + offset, state));
+        self.diff_by(expected, |expected, actual| expected == actual)
+    }
+
+    /// Compare the world with air at `locations`. Blocks which may stay (i.e., blocks which cannot be mined) are `kept`.
+    pub fn diff_clear(&self, locations: impl Iterator<Item=BlockLocation>, mut kept: impl FnMut(BlockState) -> bool) -> DiffReport {
+        let expected = locations.map(|location| (location, BlockState::AIR));
+        self.diff_by(expected, |_, actual| actual == BlockState::AIR || kept(actual))
+    }
+
+    /// Compare the world with the `expected` blocks. `same` says whether the actual block is good enough. The blocks
+    /// are grouped by chunk section so each column and section is looked up once instead of once per block.
+    pub fn diff_by(&self, expected: impl Iterator<Item=(BlockLocation, BlockState)>, mut same: impl FnMut(BlockState, BlockState) -> bool) -> DiffReport {
+        let mut sections: HashMap<(ChunkLocation, i16), Vec<(BlockLocation, BlockState)>> = HashMap::new();
+        for (location, state) in expected {
+            sections.entry((ChunkLocation::from(location), location.y >> 4)).or_default().push((location, state));
+        }
+
+        let mut report = DiffReport::default();
+        for ((chunk, section_idx), blocks) in sections {
+            let column = match self.get_real_column(chunk) {
+                Some(column) => column,
+                None => {
+                    report.unknown.extend(blocks.into_iter().map(|(location, _)| location));
+                    continue;
+                }
+            };
+
+            // above and below the world (and in sections which were never sent) is air
+            let section = if (0..16).contains(&section_idx) {
+                column.sections[section_idx as usize].as_deref()
+            } else {
+                None
+            };
+
+            for (location, expected) in blocks {
+                let actual = match section {
+                    Some(section) => section.palette().get_block((location.x & 15) as u8, (location.y & 15) as u8, (location.z & 15) as u8),
+                    None => BlockState::AIR
+                };
+                report.add(location, expected, same(expected, actual));
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::fs::OpenOptions;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use rand::seq::SliceRandom;
+
+    use crate::schematic::Schematic;
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+
+    fn load_2b2t() -> Schematic {
+        let mut reader = OpenOptions::new()
+            .read(true)
+            .open("test-data/2b2t.schematic")
+            .unwrap();
+
+        Schematic::load(&mut reader).unwrap()
+    }
+
+    #[test]
+    fn test_knocked_out() {
+        let schematic = load_2b2t();
+        let mut world = WorldBlocks::default();
+        world.paste(&schematic);
+
+        let solid: Vec<_> = schematic.blocks().filter(|(_, state)| *state != BlockState::AIR).collect();
+        let mut rng = StdRng::seed_from_u64(832);
+        let knocked: Vec<_> = solid.choose_multiple(&mut rng, 50).copied().collect();
+        for (location, _) in &knocked {
+            world.set_block(*location, BlockState::AIR);
+        }
+
+        let report = world.diff(&schematic, BlockLocation::default());
+        let missing: HashSet<_> = report.missing.iter().copied().collect();
+        let expected: HashSet<_> = knocked.into_iter().collect();
+
+        assert_eq!(missing, expected);
+        assert!(report.extra.is_empty());
+        assert!(report.unknown.is_empty());
+        assert_eq!(report.matches.len() as u64, schematic.volume() - 50);
+    }
+
+    #[test]
+    fn test_offset() {
+        let schematic = load_2b2t();
+        let mut world = WorldBlocks::default();
+        world.paste(&schematic);
+
+        // everything is somewhere else (or not loaded) when moved up
+        let moved = world.diff(&schematic, BlockLocation::new(0, 1, 0));
+        assert!(!moved.is_complete());
+
+        assert!(world.diff(&schematic, BlockLocation::default()).is_complete());
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut world = WorldBlocks::flat();
+        let bedrock = BlockState::from(7, 0);
+        let left = BlockLocation::new(2, 5, -3);
+        world.set_block(left, BlockState::STONE);
+        world.set_block(BlockLocation::new(0, 3, 0), bedrock);
+
+        let area = || (1..10).flat_map(|y| (-5..=5).flat_map(move |x| (-5..=5).map(move |z| BlockLocation::new(x, y, z))));
+
+        let report = world.diff_clear(area(), |state| state == bedrock);
+        assert_eq!(report.extra, vec![left]);
+        assert!(report.missing.is_empty());
+
+        world.set_block(left, BlockState::AIR);
+        assert!(world.diff_clear(area(), |state| state == bedrock).is_complete());
+
+        // a column which is not loaded is not clear
+        let far = world.diff_clear(std::iter::once(BlockLocation::new(500, 5, 500)), |_| false);
+        assert_eq!(far.unknown.len(), 1);
+    }
+}
@@ -0,0 +1,312 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Saving what the bots know about an area, as a schematic or as a top-down map
+
+use std::io::{self, Write};
+
+use flate2::Compression;
+use flate2::Crc;
+use flate2::write::ZlibEncoder;
+
+use crate::client::afk::Area;
+use crate::schematic::Schematic;
+use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+use crate::storage::chunk::{ChunkData, HighMemoryChunkSection};
+
+/// the colors of common blocks on a map. Other blocks are grey.
+const COLORS: &[(u32, [u8; 3])] = &[
+    (1, [125, 125, 125]), // stone
+    (2, [95, 159, 53]), // grass
+    (3, [134, 96, 67]), // dirt
+    (4, [110, 110, 110]), // cobblestone
+    (5, [157, 128, 79]), // planks
+    (7, [50, 50, 50]), // bedrock
+    (8, [47, 67, 244]), // flowing water
+    (9, [47, 67, 244]), // water
+    (10, [207, 91, 20]), // flowing lava
+    (11, [207, 91, 20]), // lava
+    (12, [219, 207, 163]), // sand
+    (13, [136, 126, 126]), // gravel
+    (17, [102, 81, 51]), // log
+    (18, [60, 120, 30]), // leaves
+    (24, [216, 203, 155]), // sandstone
+    (31, [80, 140, 45]), // tall grass
+    (49, [20, 18, 30]), // obsidian
+    (78, [240, 251, 251]), // snow layer
+    (79, [160, 188, 255]), // ice
+    (80, [240, 251, 251]), // snow
+    (87, [111, 54, 52]), // netherrack
+    (88, [84, 64, 51]), // soul sand
+    (161, [60, 120, 30]), // acacia and dark oak leaves
+    (162, [102, 81, 51]), // acacia and dark oak log
+];
+
+/// the color of columns which are not loaded or only air
+const NOTHING: [u8; 3] = [0, 0, 0];
+
+fn color(kind: BlockKind) -> [u8; 3] {
+    COLORS.iter()
+        .find(|(id, _)| *id == kind.id())
+        .map_or([150, 150, 150], |(_, color)| *color)
+}
+
+/// A top-down map. Every pixel is a column, with north (-z) up.
+pub struct MapImage {
+    pub width: u32,
+    pub height: u32,
+
+    /// rgb, row by row
+    pixels: Vec<[u8; 3]>,
+}
+
+impl MapImage {
+    fn new(width: u32, height: u32) -> MapImage {
+        MapImage { width, height, pixels: vec![NOTHING; width as usize * height as usize] }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// save as an 8 bit rgb png
+    pub fn write_png(&self, writer: &mut impl Write) -> io::Result<()> {
+        fn chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+            let mut crc = Crc::new();
+            crc.update(kind);
+            crc.update(data);
+
+            writer.write_all(&(data.len() as u32).to_be_bytes())?;
+            writer.write_all(kind)?;
+            writer.write_all(data)?;
+            writer.write_all(&crc.sum().to_be_bytes())
+        }
+
+        writer.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'])?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+
+        // bit depth, rgb, deflate, adaptive filtering, no interlacing
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        chunk(writer, b"IHDR", &header)?;
+
+        // every row starts with its filter, which is none
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        for row in self.pixels.chunks(self.width.max(1) as usize) {
+            encoder.write_all(&[0])?;
+            for pixel in row {
+                encoder.write_all(pixel)?;
+            }
+        }
+        chunk(writer, b"IDAT", &encoder.finish()?)?;
+
+        chunk(writer, b"IEND", &[])
+    }
+}
+
+/// the columns `region` covers with the part of each column which is in it (inclusive)
+fn columns(region: Area) -> impl Iterator<Item=(ChunkLocation, (i32, i32), (i32, i32))> {
+    let Area { min, max } = region;
+    (min.x >> 4..=max.x >> 4).flat_map(move |cx| {
+        (min.z >> 4..=max.z >> 4).map(move |cz| {
+            let xs = ((cx << 4).max(min.x), ((cx << 4) + 15).min(max.x));
+            let zs = ((cz << 4).max(min.z), ((cz << 4) + 15).min(max.z));
+            (ChunkLocation(cx, cz), xs, zs)
+        })
+    })
+}
+
+/// the state at `x y z` of a section's states
+fn state_at(states: &[BlockState; 4096], x: i32, y: i32, z: i32) -> BlockState {
+    states[(((y & 15) << 8) | ((z & 15) << 4) | (x & 15)) as usize]
+}
+
+/// the states of section `idx` of `column`. None if it is all air.
+fn section_states(column: &ChunkData<HighMemoryChunkSection>, idx: i32) -> Option<[BlockState; 4096]> {
+    column.sections[idx as usize].as_ref().map(|section| section.palette().all_states())
+}
+
+impl WorldBlocks {
+    /// The blocks between the corners `a` and `b` (inclusive) as a schematic with its origin at the lowest corner, and
+    /// how many columns were not loaded. Blocks which are not loaded (or only known roughly) are air. The cuboid must
+    /// be at most 32767 blocks across in each direction. [`Schematic::capture`] checks this.
+    pub fn to_schematic(&self, a: BlockLocation, b: BlockLocation) -> (Schematic, usize) {
+        let region = Area::new(a, b);
+        let Area { min, max } = region;
+        let size = |from: i32, to: i32| (to - from + 1) as i16;
+        let mut schematic = Schematic::new(min, size(min.x, max.x), size(min.y as i32, max.y as i32), size(min.z, max.z));
+
+        let mut missing = 0;
+        for (location, (min_x, max_x), (min_z, max_z)) in columns(region) {
+            let column = match self.get_real_column(location) {
+                Some(column) => column,
+                None => {
+                    missing += 1;
+                    continue;
+                }
+            };
+
+            // each section is decoded once
+            let (low, high) = ((min.y as i32).max(0), (max.y as i32).min(255));
+            for section_idx in low >> 4..=high >> 4 {
+                let states = match section_states(column, section_idx) {
+                    Some(states) => states,
+                    None => continue
+                };
+
+                let ys = ((section_idx << 4).max(low), ((section_idx << 4) + 15).min(high));
+                for y in ys.0..=ys.1 {
+                    for z in min_z..=max_z {
+                        for x in min_x..=max_x {
+                            let state = state_at(&states, x, y, z);
+                            if state != BlockState::AIR {
+                                schematic.set_block(BlockLocation::new(x, y as i16, z), state);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        (schematic, missing)
+    }
+
+    /// A map of `region` colored by the highest block in its height of every column, and how many columns were not
+    /// loaded (or only known roughly). They are black like columns of only air.
+    pub fn render_map(&self, region: Area) -> (MapImage, usize) {
+        let Area { min, max } = region;
+        let mut map = MapImage::new((max.x - min.x + 1) as u32, (max.z - min.z + 1) as u32);
+
+        let mut missing = 0;
+        for (location, (min_x, max_x), (min_z, max_z)) in columns(region) {
+            let column = match self.get_real_column(location) {
+                Some(column) => column,
+                None => {
+                    missing += 1;
+                    continue;
+                }
+            };
+
+            let mut left: Vec<_> = (min_z..=max_z).flat_map(|z| (min_x..=max_x).map(move |x| (x, z))).collect();
+
+            // top down, decoding each section once until every column has its highest block
+            let (low, high) = ((min.y as i32).max(0), (max.y as i32).min(255));
+            for section_idx in (low >> 4..=high >> 4).rev() {
+                if left.is_empty() {
+                    break;
+                }
+
+                let states = match section_states(column, section_idx) {
+                    Some(states) => states,
+                    None => continue
+                };
+
+                let ys = ((section_idx << 4).max(low), ((section_idx << 4) + 15).min(high));
+                left.retain(|&(x, z)| {
+                    let highest = (ys.0..=ys.1).rev()
+                        .map(|y| state_at(&states, x, y, z))
+                        .find(|state| state.kind() != BlockKind::AIR);
+
+                    match highest {
+                        Some(state) => {
+                            let idx = (z - min.z) as usize * map.width as usize + (x - min.x) as usize;
+                            map.pixels[idx] = color(state.kind());
+                            false
+                        }
+                        None => true
+                    }
+                });
+            }
+        }
+
+        (map, missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::afk::Area;
+    use crate::schematic::Schematic;
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::storage::blocks::export::NOTHING;
+
+    #[test]
+    fn test_schematic_round_trip() {
+        let mut world = WorldBlocks::flat();
+        let log = BlockState::from(17, 2);
+        world.set_block(BlockLocation::new(14, 1, 3), log);
+        world.set_block(BlockLocation::new(17, 2, -1), BlockState::from(5, 1));
+        world.set_block(BlockLocation::new(17, 40, -1), BlockState::STONE);
+
+        let region = Area::new(BlockLocation::new(20, 3, 4), BlockLocation::new(10, 0, -2));
+        let (schematic, missing) = world.to_schematic(region.min, region.max);
+        assert_eq!(missing, 0);
+        assert_eq!((schematic.width, schematic.height, schematic.length), (11, 4, 7));
+
+        let mut saved = Vec::new();
+        schematic.save(&mut saved).unwrap();
+        let loaded = Schematic::load(&mut &saved[..]).unwrap();
+        assert!(loaded.is_valid());
+        assert_eq!(loaded.origin(), Some(BlockLocation::new(10, 0, -2)));
+
+        let mut blocks = 0;
+        for (location, state) in loaded.blocks() {
+            assert!(region.contains(location));
+            assert_eq!(Some(state), world.get_block_exact(location), "{}", location);
+            blocks += 1;
+        }
+        assert_eq!(blocks, 11 * 4 * 7);
+
+        // a region partly outside of the loaded columns
+        let (schematic, missing) = world.to_schematic(BlockLocation::new(90, 0, 0), BlockLocation::new(115, 0, 0));
+        assert_eq!(missing, 1);
+        assert_eq!(schematic.blocks().filter(|(_, state)| *state == BlockState::STONE).count(), 11);
+    }
+
+    #[test]
+    fn test_map() {
+        let mut world = WorldBlocks::flat();
+        world.set_block(BlockLocation::new(2, 5, 1), BlockState::from(9, 0));
+        world.set_block(BlockLocation::new(2, 70, 1), BlockState::from(80, 0));
+
+        let region = Area::new(BlockLocation::new(-1, 0, -1), BlockLocation::new(3, 64, 2));
+        let (map, missing) = world.render_map(region);
+        assert_eq!(missing, 0);
+        assert_eq!((map.width, map.height), (5, 4));
+
+        // the snow is above the region
+        assert_eq!(map.pixel(3, 2), [47, 67, 244]);
+        assert_eq!(map.pixel(0, 0), [125, 125, 125]);
+
+        // the flat world ends at x=100 and its columns at x=111
+        let region = Area::new(BlockLocation::new(99, 0, 0), BlockLocation::new(112, 255, 0));
+        let (map, missing) = world.render_map(region);
+        assert_eq!(missing, 1);
+        assert_eq!(map.pixel(1, 0), [125, 125, 125]);
+        assert_eq!(map.pixel(2, 0), NOTHING);
+        assert_eq!(map.pixel(13, 0), NOTHING);
+
+        let mut png = Vec::new();
+        map.write_png(&mut png).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+}
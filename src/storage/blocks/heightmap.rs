@@ -0,0 +1,207 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The highest solid block of every column of blocks, kept up to date as blocks change so the terrain of a chunk can
+//! be judged without looking at all of its blocks
+
+use crate::storage::block::SimpleType;
+use crate::storage::chunk::ChunkColumn;
+
+/// the top of one column of blocks
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Surface {
+    /// the y of the highest solid block. None if there is no solid block (the void).
+    pub floor: Option<u8>,
+
+    /// whether there is water, lava or anything else to avoid above the floor
+    pub covered: bool,
+}
+
+impl Surface {
+    const VOID: Surface = Surface { floor: None, covered: false };
+
+    /// there is ground to stand on at the top
+    pub fn is_dry(&self) -> bool {
+        self.floor.is_some() && !self.covered
+    }
+}
+
+/// How easy a chunk column is to walk across judging by its surface only
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SurfaceSummary {
+    /// the share of the columns which are dry (see [`Surface::is_dry`])
+    pub dry: f64,
+
+    /// the share of the neighbouring dry columns which are more than a block apart, so they cannot be walked up
+    pub steep: f64,
+
+    /// the average floor of the dry columns. None if there are none.
+    pub floor: Option<f64>,
+}
+
+/// The [`Surface`] of each column of blocks of a chunk column
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightmap {
+    /// indexed by `z * 16 + x`
+    surfaces: [Surface; 256],
+}
+
+fn scan(column: &ChunkColumn, x: u8, z: u8) -> Surface {
+    let top = match column.top() {
+        None => return Surface::VOID,
+        Some(top) => top
+    };
+
+    let mut covered = false;
+    for y in (0..=top).rev() {
+        match column.get_block(x, y, z).s_type() {
+            SimpleType::Solid => return Surface { floor: Some(y), covered },
+            SimpleType::Water | SimpleType::Avoid => covered = true,
+            SimpleType::WalkThrough => {}
+        }
+    }
+
+    Surface { floor: None, covered }
+}
+
+impl Heightmap {
+    pub fn scan(column: &ChunkColumn) -> Heightmap {
+        let mut surfaces = [Surface::VOID; 256];
+        for z in 0..16 {
+            for x in 0..16 {
+                surfaces[z as usize * 16 + x as usize] = scan(column, x, z);
+            }
+        }
+        Heightmap { surfaces }
+    }
+
+    /// the block at `x, y, z` (relative to the column) of `column` changed
+    pub fn update(&mut self, column: &ChunkColumn, x: u8, y: u8, z: u8) {
+        let idx = z as usize * 16 + x as usize;
+
+        // nothing below the floor shows
+        if self.surfaces[idx].floor.map_or(false, |floor| y < floor) {
+            return;
+        }
+
+        self.surfaces[idx] = scan(column, x, z);
+    }
+
+    /// the surface at `x, z` relative to the column
+    pub fn surface(&self, x: u8, z: u8) -> Surface {
+        self.surfaces[z as usize * 16 + x as usize]
+    }
+
+    pub fn summary(&self) -> SurfaceSummary {
+        let mut dry = 0;
+        let mut total_floor = 0.0;
+        let mut pairs = 0;
+        let mut steep = 0;
+
+        for z in 0..16 {
+            for x in 0..16 {
+                let surface = self.surface(x, z);
+                let floor = match (surface.is_dry(), surface.floor) {
+                    (true, Some(floor)) => floor as i16,
+                    _ => continue,
+                };
+
+                dry += 1;
+                total_floor += floor as f64;
+
+                let neighbors = [(x + 1, z), (x, z + 1)];
+                for &(nx, nz) in neighbors.iter().filter(|(nx, nz)| *nx < 16 && *nz < 16) {
+                    let neighbor = self.surface(nx, nz);
+                    if let (true, Some(other)) = (neighbor.is_dry(), neighbor.floor) {
+                        pairs += 1;
+                        if (other as i16 - floor).abs() > 1 {
+                            steep += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        SurfaceSummary {
+            dry: dry as f64 / 256.0,
+            steep: if pairs == 0 { 0.0 } else { steep as f64 / pairs as f64 },
+            floor: (dry > 0).then(|| total_floor / dry as f64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::ChunkLocation;
+    use crate::storage::blocks::heightmap::Surface;
+    use crate::storage::blocks::WorldBlocks;
+
+    fn surface(world: &WorldBlocks, x: i32, z: i32) -> Surface {
+        world.surface(BlockLocation::new(x, 0, z)).unwrap()
+    }
+
+    #[test]
+    fn test_update() {
+        let mut world = WorldBlocks::flat();
+        assert_eq!(surface(&world, 3, 3), Surface { floor: Some(0), covered: false });
+
+        world.set_block(BlockLocation::new(3, 1, 3), BlockState::from(9, 0));
+        assert_eq!(surface(&world, 3, 3), Surface { floor: Some(0), covered: true });
+
+        // a new section on top
+        world.set_block(BlockLocation::new(3, 40, 3), BlockState::STONE);
+        assert_eq!(surface(&world, 3, 3), Surface { floor: Some(40), covered: false });
+
+        // changes below the floor do not show
+        world.set_block(BlockLocation::new(3, 1, 3), BlockState::AIR);
+        assert_eq!(surface(&world, 3, 3), Surface { floor: Some(40), covered: false });
+
+        world.set_block(BlockLocation::new(3, 40, 3), BlockState::AIR);
+        assert_eq!(surface(&world, 3, 3), Surface { floor: Some(0), covered: false });
+
+        world.set_block(BlockLocation::new(3, 0, 3), BlockState::AIR);
+        assert_eq!(surface(&world, 3, 3), Surface { floor: None, covered: false });
+
+        assert!(world.surface(BlockLocation::new(500, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut world = WorldBlocks::flat();
+        let summary = world.heightmap(ChunkLocation(0, 0)).unwrap().summary();
+        assert_eq!(summary.dry, 1.0);
+        assert_eq!(summary.steep, 0.0);
+        assert_eq!(summary.floor, Some(0.0));
+
+        // a lake in one half and a wall along the other
+        for z in 0..16 {
+            for x in 0..8 {
+                world.set_block(BlockLocation::new(x, 1, z), BlockState::from(9, 0));
+            }
+            for y in 1..=3 {
+                world.set_block(BlockLocation::new(12, y, z), BlockState::STONE);
+            }
+        }
+
+        let summary = world.heightmap(ChunkLocation(0, 0)).unwrap().summary();
+        assert_eq!(summary.dry, 0.5);
+
+        // the pairs across the wall are steep: 2 per row out of 7 across and 8 along each row
+        assert_eq!(summary.steep, 32.0 / (16.0 * 7.0 + 15.0 * 8.0));
+        assert_eq!(summary.floor, Some(3.0 * 16.0 / 128.0));
+    }
+}
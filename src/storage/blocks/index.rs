@@ -0,0 +1,342 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! An index of where the interesting blocks (ores, chests, spawners) of each column are, so looking for them does not
+//! have to scan every block of hundreds of columns
+
+use std::collections::{HashMap, HashSet};
+use std::iter::FromIterator;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use float_ord::FloatOrd;
+
+use crate::client::pathfind::MinHeapNode;
+use crate::error::{err, Error};
+use crate::storage::block::{BlockKind, BlockLocation};
+use crate::storage::blocks::{block_chunk_iter, ChunkLocation, HeapIter, WorldBlocks};
+use crate::storage::chunk::ChunkColumn;
+
+/// coal, iron, gold, diamond, lapis, (lit) redstone, emerald and quartz ore, (trapped and ender) chests and spawners
+const DEFAULT_KINDS: &[u32] = &[16, 15, 14, 56, 21, 73, 74, 129, 153, 54, 146, 130, 52];
+
+/// The kinds of blocks which are indexed. Cloning is `O(1)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexedKinds(Arc<HashSet<BlockKind>>);
+
+impl Default for IndexedKinds {
+    fn default() -> Self {
+        DEFAULT_KINDS.iter().map(|&id| BlockKind(id)).collect()
+    }
+}
+
+impl FromIterator<BlockKind> for IndexedKinds {
+    fn from_iter<T: IntoIterator<Item=BlockKind>>(iter: T) -> Self {
+        IndexedKinds(Arc::new(iter.into_iter().collect()))
+    }
+}
+
+impl FromStr for IndexedKinds {
+    type Err = Error;
+
+    /// block ids separated by commas (`56,52`) or `none`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "none" {
+            return Ok(IndexedKinds(Arc::default()));
+        }
+
+        s.split(',')
+            .map(|id| id.trim().parse().map(BlockKind).map_err(|_| err(&format!("{} is not a block id", id))))
+            .collect()
+    }
+}
+
+impl IndexedKinds {
+    pub fn contains(&self, kind: BlockKind) -> bool {
+        self.0.contains(&kind)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IndexConfig {
+    pub kinds: IndexedKinds,
+
+    /// how long the columns which were added or changed may be scanned for each game loop
+    pub budget: Duration,
+
+    /// whether columns keep their light (see [`WorldBlocks::keep_light`])
+    pub light: bool,
+
+    /// whether sections are repacked to save memory (see [`WorldBlocks::compact_sections`])
+    pub compact: bool,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            kinds: IndexedKinds::default(),
+            budget: Duration::from_millis(2),
+            light: false,
+            compact: false,
+        }
+    }
+}
+
+/// where the indexed blocks of a column are
+#[derive(Clone, Default, Debug, PartialEq)]
+pub(super) struct ChunkIndex {
+    locations: HashMap<BlockKind, HashSet<BlockLocation>>,
+}
+
+impl ChunkIndex {
+    /// find the blocks of `kinds` in `column`. Columns which are only known roughly have none.
+    fn scan(location: ChunkLocation, column: &ChunkColumn, kinds: &IndexedKinds) -> ChunkIndex {
+        let mut index = ChunkIndex::default();
+
+        if let ChunkColumn::HighMemory { data } = column {
+            for (section_idx, section) in data.sections.iter().enumerate() {
+                let section = match section {
+                    Some(section) => section,
+                    None => continue
+                };
+
+                for (idx, state) in IntoIterator::into_iter(section.palette().all_states()).enumerate() {
+                    if kinds.contains(state.kind()) {
+                        let block = data.block_location(location, (section_idx << 12) + idx);
+                        index.locations.entry(state.kind()).or_default().insert(block);
+                    }
+                }
+            }
+        }
+
+        index
+    }
+
+    /// the block at `location` changed from `from` to `to`
+    pub(super) fn replace(&mut self, location: BlockLocation, from: BlockKind, to: BlockKind, kinds: &IndexedKinds) {
+        if kinds.contains(from) {
+            if let Some(locations) = self.locations.get_mut(&from) {
+                locations.remove(&location);
+            }
+        }
+
+        if kinds.contains(to) {
+            self.locations.entry(to).or_default().insert(location);
+        }
+    }
+
+    fn get(&self, kind: BlockKind) -> impl Iterator<Item=BlockLocation> + '_ {
+        self.locations.get(&kind).into_iter().flatten().copied()
+    }
+}
+
+impl WorldBlocks {
+    /// Index the columns which were added or changed since they were last indexed until `end_at` (at least one if
+    /// any are waiting). If `kinds` are not the kinds the world was indexed for, every column is indexed again.
+    /// Returns how many columns were indexed.
+    pub fn index_pending(&mut self, kinds: &IndexedKinds, end_at: Instant) -> usize {
+        if *kinds != self.indexed {
+            self.indexed = kinds.clone();
+            for (_, chunk) in self.storage.iter_mut() {
+                Arc::make_mut(chunk).index = None;
+            }
+        }
+
+        // the queries scan the columns themselves when nothing is indexed
+        if self.indexed.is_empty() {
+            return 0;
+        }
+
+        let pending: Vec<_> = self.storage.iter()
+            .filter(|(_, chunk)| chunk.index.is_none())
+            .map(|(location, _)| *location)
+            .collect();
+
+        let mut indexed = 0;
+        for location in pending {
+            let chunk = self.storage.get_mut(&location).map(Arc::make_mut).unwrap();
+            chunk.index = Some(ChunkIndex::scan(location, &chunk.column, &self.indexed));
+            indexed += 1;
+
+            if Instant::now() >= end_at {
+                break;
+            }
+        }
+
+        indexed
+    }
+
+    /// Like [`WorldBlocks::select`] for blocks of `kind`. Columns which are indexed for `kind` are not scanned.
+    pub fn select_kind(&'a self, around: BlockLocation, max_chunks: usize, kind: BlockKind) -> impl Iterator<Item=BlockLocation> + 'a {
+        let indexed = self.indexed.contains(kind);
+
+        self.real_chunks_around(around)
+            .into_iter()
+            .take(max_chunks)
+            .flat_map(move |(location, chunk, data)| match &chunk.index {
+                Some(index) if indexed => index.get(kind).collect::<Vec<_>>(),
+                _ => block_chunk_iter(location, data, move |state| state.kind() == kind).collect()
+            })
+    }
+
+    /// like [`WorldBlocks::closest`] for blocks of `kind`, using the index where there is one
+    pub fn closest_kind(&self, origin: BlockLocation, max_chunks: usize, kind: BlockKind) -> Option<BlockLocation> {
+        self.select_kind(origin, max_chunks, kind)
+            .min_by_key(|loc| FloatOrd(loc.dist2(origin)))
+    }
+
+    /// like [`WorldBlocks::closest_iter`] for blocks of `kind`, using the index where there is one
+    pub fn closest_kind_iter(&'a self, origin: BlockLocation, max_chunks: usize, kind: BlockKind) -> impl Iterator<Item=BlockLocation> + 'a {
+        let heap = self.select_kind(origin, max_chunks, kind)
+            .map(|loc| MinHeapNode::new(loc, loc.dist2(origin)))
+            .collect();
+
+        let iterator = HeapIter { heap };
+        iterator.map(|node| node.contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Instant;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, MissingColumn, WorldBlocks};
+    use crate::storage::blocks::index::{ChunkIndex, IndexedKinds};
+    use crate::storage::chunk::ChunkColumn;
+
+    const DIAMOND: BlockKind = BlockKind(56);
+    const IRON: BlockKind = BlockKind(15);
+
+    fn kinds() -> IndexedKinds {
+        vec![DIAMOND, IRON, BlockKind::MOB_SPAWNER].into_iter().collect()
+    }
+
+    /// every column is indexed and its index is what scanning the column finds
+    fn assert_matches_scan(world: &WorldBlocks) {
+        for (location, chunk) in world.storage.iter() {
+            let index = chunk.index.as_ref().unwrap_or_else(|| panic!("{:?} is not indexed", location));
+            assert_eq!(index, &ChunkIndex::scan(*location, &chunk.column, &world.indexed));
+        }
+
+        let origin = BlockLocation::new(0, 64, 0);
+        for kind in [DIAMOND, IRON, BlockKind::MOB_SPAWNER, BlockKind::STONE] {
+            let indexed: HashSet<_> = world.select_kind(origin, usize::MAX, kind).collect();
+            let scanned: HashSet<_> = world.select(origin, usize::MAX, |state| state.kind() == kind).collect();
+            assert_eq!(indexed, scanned, "{:?}", kind);
+        }
+    }
+
+    fn random_column(rng: &mut StdRng, sections: &[u8]) -> ChunkColumn {
+        let mut column = ChunkColumn::default();
+        for &section in sections {
+            for _ in 0..40 {
+                let id = [1, 1, 1, 56, 15, 52][rng.gen_range(0..6)];
+                let (x, y, z) = (rng.gen_range(0..16), section * 16 + rng.gen_range(0..16), rng.gen_range(0..16));
+                column.set_block(x, y, z, BlockState::from(id, 0));
+            }
+        }
+        column
+    }
+
+    #[test]
+    fn test_matches_scan() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut world = WorldBlocks::default();
+        let kinds = kinds();
+        let all = || Instant::now() + std::time::Duration::from_secs(60);
+
+        for x in -3..3 {
+            for z in -3..3 {
+                world.add_column(ChunkLocation(x, z), random_column(&mut rng, &[0, 3]));
+            }
+        }
+        world.index_pending(&kinds, all());
+        assert_matches_scan(&world);
+
+        // an update only changes the sections which were sent
+        world.modify_column(ChunkLocation(1, -2), random_column(&mut rng, &[3, 4]), MissingColumn::Insert);
+        world.modify_column(ChunkLocation(7, 7), random_column(&mut rng, &[1]), MissingColumn::Insert);
+        assert!(world.storage[&ChunkLocation(1, -2)].index.is_none());
+        world.index_pending(&kinds, all());
+        assert_matches_scan(&world);
+
+        // breaking and placing blocks keeps the index up to date without scanning again
+        let mut ores: Vec<_> = world.select_kind(BlockLocation::default(), usize::MAX, DIAMOND).collect();
+        ores.sort_by_key(|location| (location.x, location.y, location.z));
+        for &ore in ores.iter().take(10) {
+            world.set_block(ore, BlockState::AIR);
+        }
+        for i in 0..30 {
+            let location = BlockLocation::new(rng.gen_range(-48..48), rng.gen_range(0..80), rng.gen_range(-48..48));
+            let id = [0, 1, 56, 15, 52][i % 5];
+            world.set_block(location, BlockState::from(id, 0));
+        }
+        assert_eq!(world.index_pending(&kinds, all()), 0);
+        assert_matches_scan(&world);
+
+        // other kinds index everything again
+        let fewer: IndexedKinds = vec![IRON].into_iter().collect();
+        assert_eq!(world.index_pending(&fewer, all()), world.chunk_count());
+        assert_matches_scan(&world);
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut world = WorldBlocks::default();
+        for x in 0..10 {
+            world.add_column(ChunkLocation(x, 0), random_column(&mut rng, &[0]));
+        }
+
+        // out of time: one column at a time
+        let kinds = kinds();
+        assert_eq!(world.index_pending(&kinds, Instant::now()), 1);
+        assert_eq!(world.index_pending(&kinds, Instant::now()), 1);
+
+        // the columns which are not indexed yet are still found
+        let origin = BlockLocation::new(0, 8, 0);
+        let indexed: Vec<_> = world.closest_kind_iter(origin, usize::MAX, DIAMOND).collect();
+        let scanned: Vec<_> = world.closest_iter(origin, usize::MAX, |state| state.kind() == DIAMOND).collect();
+        assert_eq!(indexed.len(), scanned.len());
+        for (a, b) in indexed.iter().zip(&scanned) {
+            assert_eq!(a.dist2(origin), b.dist2(origin));
+        }
+        assert_eq!(world.closest_kind(origin, usize::MAX, DIAMOND).map(|location| location.dist2(origin)), scanned.first().map(|location| location.dist2(origin)));
+
+        assert_eq!(world.index_pending(&kinds, Instant::now() + std::time::Duration::from_secs(60)), 8);
+    }
+
+    #[test]
+    fn test_parse() {
+        let kinds: IndexedKinds = "56, 52".parse().unwrap();
+        assert!(kinds.contains(DIAMOND));
+        assert!(kinds.contains(BlockKind::MOB_SPAWNER));
+        assert!(!kinds.contains(IRON));
+
+        assert!("none".parse::<IndexedKinds>().unwrap().is_empty());
+        assert!("diamond".parse::<IndexedKinds>().is_err());
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The light of loaded columns, for finding where mobs can spawn. Columns only keep their light if the world was told
+//! to (see [`WorldBlocks::keep_light`]).
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use float_ord::FloatOrd;
+
+use crate::storage::block::{BlockLocation, SimpleType};
+use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+
+/// how much darker the sky is at night
+const NIGHT_DARKENING: u8 = 11;
+
+/// hostile mobs spawn where the light is below this
+pub const SPAWN_LIGHT: u8 = 8;
+
+/// the six blocks sharing a face with `location`
+fn neighbors(location: BlockLocation) -> [BlockLocation; 6] {
+    let BlockLocation { x, y, z } = location;
+    [
+        BlockLocation::new(x - 1, y, z),
+        BlockLocation::new(x + 1, y, z),
+        BlockLocation::new(x, y - 1, z),
+        BlockLocation::new(x, y + 1, z),
+        BlockLocation::new(x, y, z - 1),
+        BlockLocation::new(x, y, z + 1),
+    ]
+}
+
+impl WorldBlocks {
+    /// Keep the light of the columns which are added or updated from now on. Off by default.
+    pub fn keep_light(&mut self, keep: bool) {
+        self.keep_light = keep;
+    }
+
+    /// (block light, sky light) at `location`. None if the column is not loaded or does not keep light.
+    pub fn light_at(&self, location: BlockLocation) -> Option<(u8, u8)> {
+        if !(0..256).contains(&location.y) {
+            return None;
+        }
+
+        let column = self.column(ChunkLocation::from(location))?;
+        column.light((location.x & 15) as u8, location.y as u8, (location.z & 15) as u8)
+    }
+
+    /// the light at `location` in the middle of the night. None like [`WorldBlocks::light_at`].
+    pub fn night_light(&self, location: BlockLocation) -> Option<u8> {
+        let (block, sky) = self.light_at(location)?;
+        Some(block.max(sky.saturating_sub(NIGHT_DARKENING)))
+    }
+
+    /// Where mobs could spawn at night within `radius` blocks of `origin` (in every direction), closest first: blocks
+    /// two high to walk through with a solid block below and light below [`SPAWN_LIGHT`]. Layers which are not
+    /// completely loaded are skipped, as are columns without light.
+    pub fn dark_spots(&self, origin: BlockLocation, radius: u8) -> Vec<BlockLocation> {
+        let low = (origin.y - radius as i16).max(1);
+        let high = (origin.y + radius as i16).min(254);
+
+        let mut spots: Vec<_> = (low..=high)
+            .filter_map(|y| self.y_slice(BlockLocation { y, ..origin }, radius, |state| state.simple_type() == SimpleType::WalkThrough))
+            .flatten()
+            .filter(|&location| {
+                self.get_block_simple(location.below()) == Some(SimpleType::Solid)
+                    && self.get_block_simple(location.above()) == Some(SimpleType::WalkThrough)
+            })
+            .filter(|&location| matches!(self.night_light(location), Some(light) if light < SPAWN_LIGHT))
+            .collect();
+
+        spots.sort_by_key(|location| FloatOrd(location.dist2(origin)));
+        spots
+    }
+
+    /// Light up the blocks around `origin` like a block giving off `level` light would. The light goes around solid
+    /// blocks but not through them.
+    pub(super) fn spread_light(&mut self, origin: BlockLocation, level: u8) {
+        let mut queue = VecDeque::new();
+        queue.push_back((origin, level));
+
+        while let Some((location, level)) = queue.pop_front() {
+            if level == 0 || !(0..256).contains(&location.y) {
+                continue;
+            }
+
+            if location != origin && self.get_block_simple(location) == Some(SimpleType::Solid) {
+                continue;
+            }
+
+            let chunk = match self.storage.get_mut(&ChunkLocation::from(location)).map(Arc::make_mut) {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+
+            let (x, y, z) = ((location.x & 15) as u8, location.y as u8, (location.z & 15) as u8);
+            if Arc::make_mut(&mut chunk.column).raise_light(x, y, z, level) {
+                queue.extend(neighbors(location).iter().map(|&neighbor| (neighbor, level - 1)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::storage::block::{BlockKind, BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+    use crate::storage::chunk::{ChunkColumn, ChunkData, HighMemoryChunkSection, Palette, SectionLight};
+
+    /// a column with a stone floor at y = 0 which is pitch black up to y = 15 and open to the sky above
+    fn cave(keep_light: bool) -> WorldBlocks {
+        let mut states = [BlockState::AIR; 4096];
+        states[..256].fill(BlockState::STONE);
+
+        let light = SectionLight::new([0; 2048], Some([0; 2048]));
+        let mut data = ChunkData::<HighMemoryChunkSection>::default();
+        data.sections[0] = Some(Arc::new(HighMemoryChunkSection::new(Palette::from_states(&states)).with_light(Some(light))));
+
+        let mut world = WorldBlocks::default();
+        world.keep_light(keep_light);
+        world.add_column(ChunkLocation(0, 0), ChunkColumn::HighMemory { data });
+        world
+    }
+
+    #[test]
+    fn test_light_at() {
+        let world = cave(true);
+        assert_eq!(world.light_at(BlockLocation::new(3, 5, 3)), Some((0, 0)));
+
+        // sections the server leaves out are air under the open sky
+        assert_eq!(world.light_at(BlockLocation::new(3, 40, 3)), Some((0, 15)));
+        assert_eq!(world.light_at(BlockLocation::new(20, 5, 3)), None);
+
+        // the sky is dark at night
+        assert_eq!(world.night_light(BlockLocation::new(3, 40, 3)), Some(4));
+
+        assert_eq!(cave(false).light_at(BlockLocation::new(3, 5, 3)), None);
+    }
+
+    #[test]
+    fn test_dark_spots() {
+        let origin = BlockLocation::new(8, 1, 8);
+        let mut world = cave(true);
+
+        // only the floor can be stood on
+        let spots = world.dark_spots(origin, 7);
+        assert_eq!(spots.len(), 15 * 15);
+        assert_eq!(spots[0], origin);
+        assert!(spots.iter().all(|spot| spot.y == 1));
+
+        // a wall the light has to go over
+        for z in 0..16 {
+            world.set_block(BlockLocation::new(4, 1, z), BlockState::STONE);
+            world.set_block(BlockLocation::new(4, 2, z), BlockState::STONE);
+        }
+
+        let torch = BlockLocation::new(2, 1, 2);
+        world.set_block(torch, BlockState::from(BlockKind::TORCH.id(), 5));
+        assert_eq!(world.light_at(torch), Some((14, 0)));
+        assert_eq!(world.light_at(BlockLocation::new(3, 1, 2)), Some((13, 0)));
+
+        // 7 blocks around the wall and 4 to the top of it
+        assert_eq!(world.light_at(BlockLocation::new(5, 1, 2)), Some((7, 0)));
+        assert_eq!(world.light_at(BlockLocation::new(4, 3, 2)), Some((10, 0)));
+
+        let spots = world.dark_spots(origin, 7);
+        assert!(!spots.contains(&torch));
+        assert!(!spots.contains(&BlockLocation::new(4, 3, 2)));
+        assert!(spots.contains(&BlockLocation::new(5, 1, 2)));
+        assert!(spots.contains(&BlockLocation::new(4, 3, 12)));
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Saving the columns the bots have seen so a restart does not lose the map. The light is not saved, it comes back
+//! with the columns the server sends.
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{err, Res};
+use crate::storage::block::{BlockLocation, BlockState};
+use crate::storage::blocks::{Chunk, ChunkLocation, WorldBlocks};
+use crate::storage::chunk::{ChunkColumn, ChunkData, HighMemoryChunkSection, Palette};
+use crate::storage::tile_entity::TileEntity;
+
+/// bumped when the format changes, files of other versions are not read
+const VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct SavedWorld {
+    version: u32,
+    columns: Vec<SavedColumn>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct SavedColumn {
+    pub(super) location: ChunkLocation,
+
+    /// when (unix seconds) the server last sent the column
+    pub(super) seen: u64,
+    sections: Vec<SavedSection>,
+    tile_entities: Vec<(BlockLocation, TileEntity)>,
+}
+
+impl SavedColumn {
+    /// None for columns which are only known roughly
+    pub(super) fn new(location: ChunkLocation, chunk: &Chunk) -> Option<SavedColumn> {
+        match chunk.column.as_ref() {
+            ChunkColumn::HighMemory { data } => Some(SavedColumn {
+                location,
+                seen: chunk.seen,
+                sections: data.sections.iter().enumerate()
+                    .filter_map(|(idx, section)| section.as_deref().map(|section| SavedSection::new(idx, section)))
+                    .collect(),
+                tile_entities: chunk.tile_entities.iter().map(|(&location, entity)| (location, entity.clone())).collect(),
+            }),
+            ChunkColumn::LowMemory { .. } => None
+        }
+    }
+}
+
+/// The states of a section run-length encoded in y z x order. Most sections are long runs of stone or air.
+#[derive(Serialize, Deserialize)]
+struct SavedSection {
+    idx: u8,
+    runs: Vec<(u16, u32)>,
+}
+
+impl SavedSection {
+    fn new(idx: usize, section: &HighMemoryChunkSection) -> SavedSection {
+        let mut runs: Vec<(u16, u32)> = Vec::new();
+        for state in section.palette().all_states().iter() {
+            match runs.last_mut() {
+                Some((len, last)) if *last == state.0 => *len += 1,
+                _ => runs.push((1, state.0))
+            }
+        }
+        SavedSection { idx: idx as u8, runs }
+    }
+
+    fn restore(&self) -> Res<(usize, HighMemoryChunkSection)> {
+        let mut states = [BlockState::AIR; 4096];
+        let mut at = 0;
+        for &(len, state) in &self.runs {
+            let end = at + len as usize;
+            states.get_mut(at..end).ok_or_else(|| err("saved section has more than 4096 blocks"))?.fill(BlockState(state));
+            at = end;
+        }
+
+        if at != states.len() || self.idx >= 16 {
+            return Err(err("saved section is corrupt"));
+        }
+        Ok((self.idx as usize, HighMemoryChunkSection::new(Palette::from_states(&states))))
+    }
+}
+
+impl WorldBlocks {
+    /// Save the columns and their block entities to `path`. Written to a temporary file first so a crash while
+    /// writing cannot lose the last save.
+    pub fn save(&self, path: &Path) -> Res {
+        let columns = self.storage.iter()
+            .filter_map(|(&location, chunk)| SavedColumn::new(location, chunk))
+            .collect();
+
+        let world = SavedWorld { version: VERSION, columns };
+
+        let tmp = path.with_extension("tmp");
+        let mut encoder = ZlibEncoder::new(BufWriter::new(File::create(&tmp)?), Compression::default());
+        bincode::serialize_into(&mut encoder, &world).map_err(|error| err(&format!("could not write world: {}", error)))?;
+        encoder.finish()?.flush()?;
+
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Add the columns saved at `path` with [`WorldBlocks::save`], replacing the ones the world has. Returns how many
+    /// were added.
+    pub fn load(&mut self, path: &Path) -> Res<usize> {
+        let reader = ZlibDecoder::new(BufReader::new(File::open(path)?));
+        let world: SavedWorld = bincode::deserialize_from(reader).map_err(|error| err(&format!("could not read world: {}", error)))?;
+
+        if world.version != VERSION {
+            return Err(err(&format!("the world was saved in version {} of the format, not {}", world.version, VERSION)));
+        }
+
+        let count = world.columns.len();
+        for column in world.columns {
+            self.restore_column(column)?;
+        }
+        Ok(count)
+    }
+
+    /// add a saved column as it was when it was saved
+    pub(super) fn restore_column(&mut self, column: SavedColumn) -> Res {
+        let mut data = ChunkData::<HighMemoryChunkSection>::default();
+        for section in &column.sections {
+            let (idx, section) = section.restore()?;
+            data.sections[idx] = Some(Arc::new(section));
+        }
+
+        self.insert_column(column.location, ChunkColumn::HighMemory { data }, column.seen);
+        for (location, entity) in column.tile_entities {
+            self.set_tile_entity(location, entity);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::{ChunkLocation, WorldBlocks};
+    use crate::storage::tile_entity::TileEntity;
+
+    #[test]
+    fn test_save_load() {
+        let mut world = WorldBlocks::flat();
+        world.set_block(BlockLocation::new(3, 70, -5), BlockState::from(1, 2));
+        world.set_tile_entity(BlockLocation::new(0, 1, 0), TileEntity::Spawner { mob: Some("zombie".to_string()) });
+
+        let path = std::env::temp_dir().join(format!("swarm-world-{}.bin", std::process::id()));
+        world.save(&path).unwrap();
+
+        let mut loaded = WorldBlocks::default();
+        assert_eq!(loaded.load(&path).unwrap(), world.chunk_count());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.get_block_exact(BlockLocation::new(3, 70, -5)), Some(BlockState::from(1, 2)));
+        assert_eq!(loaded.get_block_exact(BlockLocation::new(3, 0, -5)), world.get_block_exact(BlockLocation::new(3, 0, -5)));
+        assert_eq!(loaded.get_block_exact(BlockLocation::new(3, 71, -5)), Some(BlockState::AIR));
+        assert_eq!(loaded.tile_entity(BlockLocation::new(0, 1, 0)), Some(&TileEntity::Spawner { mob: Some("zombie".to_string()) }));
+        assert!(loaded.get_real_column(ChunkLocation(0, 0)).is_some());
+
+        assert!(loaded.load(&path).is_err());
+    }
+}
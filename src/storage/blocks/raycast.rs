@@ -0,0 +1,437 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Ray vs world queries for line of sight and reach checks. Rays walk the world block by block (Amanatides & Woo,
+//! [A Fast Voxel Traversal Algorithm for Ray Tracing](http://www.cse.yorku.ca/~amana/research/grid.pdf)), treating
+//! every block as a full cube.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::protocol::Face;
+use crate::storage::block::{BlockApprox, BlockKind, BlockLocation, SimpleType};
+use crate::storage::blocks::WorldBlocks;
+use crate::types::{Direction, Displacement, Location};
+
+/// how far players can reach blocks in survival
+pub const REACH: f64 = 4.5;
+
+const LAVA: BlockKind = BlockKind(10);
+const FLOWING_LAVA: BlockKind = BlockKind(11);
+
+/// how far aim points are from the edges of a face
+const INSET: f64 = 0.1;
+
+/// Points on a face as offsets from its center along the two axes of the face. Ordered from the most robust to aim at
+/// to the least: the center, the middles of the edges and the corners. Points close to an edge are easily rejected by
+/// servers which check the ray themselves.
+const SAMPLES: [(f64, f64); 9] = {
+    const E: f64 = 0.5 - INSET;
+    [(0.0, 0.0), (E, 0.0), (-E, 0.0), (0.0, E), (0.0, -E), (E, E), (E, -E), (-E, E), (-E, -E)]
+};
+
+/// the most [`AimCache`] remembers before it starts over
+const AIM_CACHE_CAP: usize = 512;
+
+#[derive(Copy, Clone, Debug)]
+pub struct RayHit {
+    pub location: BlockLocation,
+
+    /// the face of the block the ray entered through
+    pub face: Face,
+
+    /// where the ray hit the face
+    pub point: Location,
+    pub dist: f64,
+}
+
+/// A point on a face of a block which can be seen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aim {
+    pub face: Face,
+    pub point: Location,
+}
+
+#[derive(Error, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AimError {
+    #[error("{0} is out of reach")]
+    OutOfReach(BlockLocation),
+
+    #[error("no part of {0} is visible")]
+    Hidden(BlockLocation),
+}
+
+/// (eye cell, target, face if only that face could be used)
+type AimKey = (BlockLocation, BlockLocation, Option<Face>);
+
+/// Remembers where a block was aimed at from an eye cell, so a task aiming at the same block every tick does not cast
+/// up to 54 rays every tick. The aim is reused while the world has not changed and checked with one ray otherwise.
+#[derive(Debug, Default)]
+pub struct AimCache {
+    /// the world generation the aim was checked in
+    aims: HashMap<AimKey, (u64, Result<Aim, AimError>)>,
+}
+
+impl AimCache {
+    /// [`WorldBlocks::aim`] (any face) or [`WorldBlocks::aim_face`] (`face`) with caching
+    pub fn aim(&mut self, world: &WorldBlocks, eye: Location, target: BlockLocation, face: Option<Face>) -> Result<Aim, AimError> {
+        let key = (BlockLocation::from(eye), target, face);
+
+        if let Some(&(generation, cached)) = self.aims.get(&key) {
+            if generation == world.generation() {
+                return cached;
+            }
+
+            // the block might have been hidden since
+            if let Ok(aim) = cached {
+                if world.sees(eye, target, aim.face, aim.point) {
+                    self.aims.insert(key, (world.generation(), cached));
+                    return cached;
+                }
+            }
+        }
+
+        let aim = match face {
+            None => world.aim(eye, target),
+            Some(face) => world.aim_face(eye, target, face),
+        };
+
+        if self.aims.len() >= AIM_CACHE_CAP {
+            self.aims.clear();
+        }
+        self.aims.insert(key, (world.generation(), aim));
+        aim
+    }
+}
+
+fn axes(face: Face) -> (Displacement, Displacement) {
+    let x = Displacement::new(1.0, 0.0, 0.0);
+    let y = Displacement::new(0.0, 1.0, 0.0);
+    let z = Displacement::new(0.0, 0.0, 1.0);
+    match face {
+        Face::NegX | Face::PosX => (y, z),
+        Face::NegY | Face::PosY => (x, z),
+        Face::NegZ | Face::PosZ => (x, y),
+    }
+}
+
+fn normal(face: Face) -> Displacement {
+    let change = face.change();
+    Displacement::new(change.x as f64, change.y as f64, change.z as f64)
+}
+
+fn sample_point(target: BlockLocation, face: Face, sample: usize) -> Location {
+    let (u, v) = axes(face);
+    let (a, b) = SAMPLES[sample];
+    target.faces()[face as usize] + u * a + v * b
+}
+
+/// Air, water and lava let rays through. Everything else (including blocks we only have an estimate of) stops them.
+pub fn blocks_ray(block: BlockApprox) -> bool {
+    match block {
+        BlockApprox::Realized(state) => {
+            let kind = state.kind();
+            !matches!(state.simple_type(), SimpleType::WalkThrough | SimpleType::Water) && kind != LAVA && kind != FLOWING_LAVA
+        }
+        BlockApprox::Estimate(simple) => !matches!(simple, SimpleType::WalkThrough | SimpleType::Water)
+    }
+}
+
+fn face(axis: usize, positive: bool) -> Face {
+    match (axis, positive) {
+        (0, true) => Face::PosX,
+        (0, false) => Face::NegX,
+        (1, true) => Face::PosY,
+        (1, false) => Face::NegY,
+        (2, true) => Face::PosZ,
+        _ => Face::NegZ,
+    }
+}
+
+impl WorldBlocks {
+    /// The first block which [`blocks_ray`] within `max_dist` of `origin` looking in `direction`. None if there is no
+    /// such block or the ray reaches an unloaded chunk first.
+    pub fn raycast(&self, origin: Location, direction: Direction, max_dist: f64) -> Option<RayHit> {
+        self.raycast_by(origin, direction, max_dist, blocks_ray)
+    }
+
+    /// Like [`WorldBlocks::raycast`] but `hits` decides which blocks stop the ray
+    pub fn raycast_by(&self, origin: Location, direction: Direction, max_dist: f64, hits: impl FnMut(BlockApprox) -> bool) -> Option<RayHit> {
+        self.cast(origin, direction.unit_vector(), max_dist, hits)
+    }
+
+    /// The face of `target` visible from `eye` which is closest to it. None if no face is visible within [`REACH`].
+    pub fn visible_face(&self, eye: Location, target: BlockLocation) -> Option<Face> {
+        self.aim(eye, target).ok().map(|aim| aim.face)
+    }
+
+    /// Where to look to interact with `target`. The faces are tried from the closest to the furthest and the first
+    /// visible point (see [`WorldBlocks::aim_face`]) is used.
+    pub fn aim(&self, eye: Location, target: BlockLocation) -> Result<Aim, AimError> {
+        let mut faces: Vec<_> = IntoIterator::into_iter(target.faces()).enumerate().collect();
+        faces.sort_by(|(_, a), (_, b)| a.dist2(eye).partial_cmp(&b.dist2(eye)).unwrap());
+
+        for (idx, _) in faces {
+            let res = self.aim_face(eye, target, Face::from(idx as u8));
+            if matches!(res, Ok(_) | Err(AimError::OutOfReach(_))) {
+                return res;
+            }
+        }
+
+        Err(AimError::Hidden(target))
+    }
+
+    /// The point on `face` of `target` to look at. Points are sampled on the face (inset from the edges), and the
+    /// one furthest from the edges which a ray from `eye` reaches is used. The center of a face is often hidden
+    /// when the block is partially behind another one even though a corner can be seen.
+    pub fn aim_face(&self, eye: Location, target: BlockLocation, face: Face) -> Result<Aim, AimError> {
+        let lowest = Location::new(target.x as f64, target.y as f64, target.z as f64);
+        let closest = Location::new(
+            eye.x.clamp(lowest.x, lowest.x + 1.0),
+            eye.y.clamp(lowest.y, lowest.y + 1.0),
+            eye.z.clamp(lowest.z, lowest.z + 1.0),
+        );
+        if closest.dist2(eye) > REACH * REACH {
+            return Err(AimError::OutOfReach(target));
+        }
+
+        // the face points away from us
+        let center = target.faces()[face as usize];
+        if (eye - center).dot(normal(face)) <= 0.0 {
+            return Err(AimError::Hidden(target));
+        }
+
+        (0..SAMPLES.len())
+            .map(|sample| Aim { face, point: sample_point(target, face, sample) })
+            .find(|aim| self.sees(eye, target, face, aim.point))
+            .ok_or(AimError::Hidden(target))
+    }
+
+    /// whether the first block a ray from `eye` to `point` hits is `target` on `face`
+    fn sees(&self, eye: Location, target: BlockLocation, face: Face, point: Location) -> bool {
+        // aim slightly into the block so the ray does not graze the neighbouring face
+        let aim = point - normal(face) * 0.01;
+        match self.cast(eye, aim - eye, REACH, blocks_ray) {
+            Some(hit) => hit.location == target && hit.face == face,
+            None => false
+        }
+    }
+
+    fn cast(&self, origin: Location, direction: Displacement, max_dist: f64, mut hits: impl FnMut(BlockApprox) -> bool) -> Option<RayHit> {
+        let mag = direction.mag2().sqrt();
+        if mag == 0.0 {
+            return None;
+        }
+
+        let d = [direction.dx / mag, direction.dy / mag, direction.dz / mag];
+        let o = [origin.x, origin.y, origin.z];
+
+        let mut cell = [o[0].floor() as i64, o[1].floor() as i64, o[2].floor() as i64];
+        let mut step = [0_i64; 3];
+        let mut t_max = [f64::INFINITY; 3];
+        let mut t_delta = [f64::INFINITY; 3];
+
+        for axis in 0..3 {
+            if d[axis] > 0.0 {
+                step[axis] = 1;
+                t_max[axis] = (cell[axis] as f64 + 1.0 - o[axis]) / d[axis];
+                t_delta[axis] = 1.0 / d[axis];
+            } else if d[axis] < 0.0 {
+                step[axis] = -1;
+                t_max[axis] = (cell[axis] as f64 - o[axis]) / d[axis];
+                t_delta[axis] = -1.0 / d[axis];
+            }
+        }
+
+        let next_axis = |t_max: &[f64; 3]| (0..3).min_by(|&a, &b| t_max[a].partial_cmp(&t_max[b]).unwrap()).unwrap();
+        let location = |cell: [i64; 3]| BlockLocation::new(cell[0] as i32, cell[1] as i16, cell[2] as i32);
+
+        // starting inside a block hits it right away on the face the ray leaves through (like vanilla)
+        let start = location(cell);
+        if hits(self.get_block(start)?) {
+            let axis = next_axis(&t_max);
+            return Some(RayHit { location: start, face: face(axis, step[axis] > 0), point: origin, dist: 0.0 });
+        }
+
+        loop {
+            let axis = next_axis(&t_max);
+            let t = t_max[axis];
+            if t > max_dist {
+                return None;
+            }
+
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+
+            let loc = location(cell);
+            let block = self.get_block(loc)?;
+
+            if hits(block) {
+                let point = origin + Displacement::new(d[0] * t, d[1] * t, d[2] * t);
+
+                // moving in the positive direction enters through the negative face
+                return Some(RayHit { location: loc, face: face(axis, step[axis] < 0), point, dist: t });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use crate::protocol::Face;
+    use crate::storage::block::{BlockLocation, BlockState};
+    use crate::storage::blocks::WorldBlocks;
+    use crate::storage::blocks::raycast::{AimCache, AimError, blocks_ray, REACH};
+    use crate::types::{Direction, Displacement, Location};
+
+    fn world() -> WorldBlocks {
+        // a floor at y = 0 and a wall at x = 5
+        let mut world = WorldBlocks::flat();
+        for y in 1..4 {
+            for z in -3..=3 {
+                world.set_block(BlockLocation::new(5, y, z), BlockState::STONE);
+            }
+        }
+        world.set_block(BlockLocation::new(0, 3, 0), BlockState::WATER);
+        world
+    }
+
+    fn dir(dx: f64, dy: f64, dz: f64) -> Direction {
+        Direction::from(Displacement::new(dx, dy, dz))
+    }
+
+    #[test]
+    fn test_axis_aligned() {
+        let world = world();
+        let eye = Location::new(0.5, 2.5, 0.5);
+
+        let hit = world.raycast(eye, dir(1., 0., 0.), 10.0).unwrap();
+        assert_eq!(hit.location, BlockLocation::new(5, 2, 0));
+        assert_matches!(hit.face, Face::NegX);
+        assert!((hit.point.x - 5.0).abs() < 1e-6);
+        assert!((hit.dist - 4.5).abs() < 1e-6);
+
+        // the floor, through the water above
+        let hit = world.raycast(Location::new(0.5, 3.5, 0.5), dir(0., -1., 0.), 10.0).unwrap();
+        assert_eq!(hit.location, BlockLocation::new(0, 0, 0));
+        assert_matches!(hit.face, Face::PosY);
+
+        // too short or nothing to hit
+        assert!(world.raycast(eye, dir(1., 0., 0.), 4.0).is_none());
+        assert!(world.raycast(eye, dir(-1., 0., 0.), 50.0).is_none());
+    }
+
+    #[test]
+    fn test_diagonal() {
+        let world = world();
+
+        // down and forward at 45 degrees hits the top of the floor
+        let hit = world.raycast(Location::new(0.5, 2.5, 0.5), dir(1., -1., 0.), 10.0).unwrap();
+        assert_eq!(hit.location, BlockLocation::new(2, 0, 0));
+        assert_matches!(hit.face, Face::PosY);
+        assert!((hit.point.y - 1.0).abs() < 1e-6);
+        assert!((hit.point.x - 2.0).abs() < 1e-6);
+
+        // across the xz plane into the side of the wall
+        let hit = world.raycast(Location::new(0.5, 2.5, 0.5), dir(4., 0., -2.), 10.0).unwrap();
+        assert_eq!(hit.location, BlockLocation::new(5, 2, -2));
+        assert_matches!(hit.face, Face::NegX);
+
+        // a custom selector can let stone through too
+        assert!(world.raycast_by(Location::new(0.5, 2.5, 0.5), dir(1., 0., 0.), 20.0, |_| false).is_none());
+    }
+
+    #[test]
+    fn test_inside_block() {
+        let world = world();
+        let hit = world.raycast(Location::new(5.5, 2.5, 0.5), dir(0., 0., 1.), 10.0).unwrap();
+        assert_eq!(hit.location, BlockLocation::new(5, 2, 0));
+        assert_matches!(hit.face, Face::PosZ);
+        assert!(hit.dist < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_visible_face() {
+        let world = world();
+
+        // standing in front of the wall the side facing us is the one we can see
+        let eye = Location::new(3.5, 2.6, 0.5);
+        assert_matches!(world.visible_face(eye, BlockLocation::new(5, 2, 0)), Some(Face::NegX));
+
+        // the top of the floor block below
+        assert_matches!(world.visible_face(eye, BlockLocation::new(3, 0, 0)), Some(Face::PosY));
+
+        // the block behind the front of the wall is hidden
+        assert_eq!(world.visible_face(eye, BlockLocation::new(6, 2, 0)), None);
+    }
+
+    #[test]
+    fn test_aim_partially_hidden() {
+        // the block in front of the target hides the center of its face but not the top of it
+        let mut world = WorldBlocks::flat();
+        let target = BlockLocation::new(5, 1, 0);
+        world.set_block(target, BlockState::STONE);
+        world.set_block(BlockLocation::new(3, 1, 1), BlockState::STONE);
+
+        let eye = Location::new(1.5, 2.9, 2.5);
+        let center = target.faces()[Face::NegX as usize];
+        assert!(world.raycast_by(eye, Direction::from(center - eye), REACH, blocks_ray).unwrap().location != target);
+
+        let aim = world.aim(eye, target).unwrap();
+        assert_matches!(aim.face, Face::NegX);
+        assert!((aim.point.y - 1.9).abs() < 1e-6);
+        assert!((aim.point.z - 0.5).abs() < 1e-6);
+        assert_matches!(world.visible_face(eye, target), Some(Face::NegX));
+
+        // covered by the floor and the target on every side that faces us
+        assert_eq!(world.aim(eye, BlockLocation::new(5, 0, 0)), Err(AimError::Hidden(BlockLocation::new(5, 0, 0))));
+        assert_eq!(world.aim_face(eye, target, Face::PosX), Err(AimError::Hidden(target)));
+        assert_eq!(world.aim(eye, BlockLocation::new(20, 1, 0)), Err(AimError::OutOfReach(BlockLocation::new(20, 1, 0))));
+    }
+
+    #[test]
+    fn test_aim_cache() {
+        let mut world = WorldBlocks::flat();
+        let target = BlockLocation::new(5, 1, 0);
+        world.set_block(target, BlockState::STONE);
+
+        let eye = Location::new(1.5, 2.9, 2.5);
+        let mut cache = AimCache::default();
+
+        let aim = cache.aim(&world, eye, target, None).unwrap();
+        assert!((aim.point.y - 1.5).abs() < 1e-6);
+
+        // somewhere else in the same cell
+        assert_eq!(cache.aim(&world, eye + Displacement::new(0.2, 0.0, 0.2), target, None), Ok(aim));
+
+        // the center is not visible anymore so it is aimed at again
+        world.set_block(BlockLocation::new(3, 1, 1), BlockState::STONE);
+        let moved = cache.aim(&world, eye, target, None).unwrap();
+        assert!((moved.point.y - 1.9).abs() < 1e-6);
+
+        // a change elsewhere keeps the aim
+        world.set_block(BlockLocation::new(-5, 1, 0), BlockState::STONE);
+        assert_eq!(cache.aim(&world, eye, target, None), Ok(moved));
+
+        world.set_block(BlockLocation::new(4, 1, 0), BlockState::STONE);
+        world.set_block(BlockLocation::new(4, 2, 0), BlockState::STONE);
+        world.set_block(BlockLocation::new(5, 2, 0), BlockState::STONE);
+        world.set_block(BlockLocation::new(5, 1, 1), BlockState::STONE);
+        assert_eq!(cache.aim(&world, eye, target, None), Err(AimError::Hidden(target)));
+    }
+}
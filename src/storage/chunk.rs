@@ -15,9 +15,11 @@
  */
 
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::storage::block::{BlockApprox, BlockLocation, BlockState, SimpleType};
 use crate::storage::blocks::ChunkLocation;
+use crate::storage::composition::{Composition, StateCounts};
 
 const SECTION_ELEMENTS: usize = 16 * 16 * 16;
 const BITS_PER_ENUM: usize = 2;
@@ -25,28 +27,142 @@ const SECTION_BYTES: usize = SECTION_ELEMENTS * BITS_PER_ENUM / 8;
 
 const ONE_MASK: u64 = !0;
 
-#[derive(Default)]
+/// a section which reads the same as a missing section does not need to be stored
+trait Section {
+    fn is_empty(&self) -> bool;
+}
+
+/// The block light and sky light of a section, a nibble per block. The light is as the server sent it and only
+/// changes where we place a block giving off light (see [`ChunkColumn::raise_light`]).
+#[derive(Clone)]
+pub struct SectionLight {
+    block: [u8; SECTION_ELEMENTS / 2],
+
+    /// None in dimensions without a sky
+    sky: Option<[u8; SECTION_ELEMENTS / 2]>,
+}
+
+impl SectionLight {
+    pub fn new(block: [u8; SECTION_ELEMENTS / 2], sky: Option<[u8; SECTION_ELEMENTS / 2]>) -> SectionLight {
+        SectionLight { block, sky }
+    }
+
+    /// the same light everywhere, like in the sections the server leaves out
+    fn uniform(block: u8, sky: Option<u8>) -> SectionLight {
+        let fill = |level: u8| [level << 4 | level; SECTION_ELEMENTS / 2];
+        SectionLight {
+            block: fill(block),
+            sky: sky.map(fill),
+        }
+    }
+
+    fn nibble(nibbles: &[u8; SECTION_ELEMENTS / 2], idx: usize) -> u8 {
+        nibbles[idx >> 1] >> ((idx & 1) * 4) & 0xF
+    }
+
+    /// (block light, sky light) at `x y z` of the section
+    pub fn get(&self, x: u8, y: u8, z: u8) -> (u8, u8) {
+        let idx = (((y as usize * SECTION_HEIGHT) + z as usize) * SECTION_WIDTH) + x as usize;
+        let sky = self.sky.as_ref().map_or(0, |sky| Self::nibble(sky, idx));
+        (Self::nibble(&self.block, idx), sky)
+    }
+
+    /// Raise the block light at `x y z` to `level`. Returns false if it was at least as bright already.
+    fn raise(&mut self, x: u8, y: u8, z: u8, level: u8) -> bool {
+        let idx = (((y as usize * SECTION_HEIGHT) + z as usize) * SECTION_WIDTH) + x as usize;
+        if Self::nibble(&self.block, idx) >= level {
+            return false;
+        }
+
+        let shift = (idx & 1) * 4;
+        let byte = &mut self.block[idx >> 1];
+        *byte = *byte & !(0xF << shift) | level << shift;
+        true
+    }
+}
+
+#[derive(Clone)]
 pub struct HighMemoryChunkSection {
-    pub palette: Palette,
+    palette: Palette,
+
+    /// changed with the palette so compositions do not have to look at every block
+    counts: StateCounts,
+
+    /// None unless the world keeps light (see [`crate::storage::blocks::WorldBlocks::keep_light`])
+    light: Option<Box<SectionLight>>,
+}
+
+impl Default for HighMemoryChunkSection {
+    fn default() -> Self {
+        Self::new(Palette::default())
+    }
 }
 
 impl HighMemoryChunkSection {
     pub fn new(palette: Palette) -> Self {
         HighMemoryChunkSection {
-            palette
+            counts: StateCounts::of(&palette.all_states()),
+            palette,
+            light: None,
+        }
+    }
+
+    pub fn with_light(mut self, light: Option<SectionLight>) -> Self {
+        self.light = light.map(Box::new);
+        self
+    }
+
+    pub fn light(&self) -> Option<&SectionLight> {
+        self.light.as_deref()
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn set_block(&mut self, x: u8, y: u8, z: u8, state: BlockState) {
+        let old = self.palette.get_block(x, y, z);
+        if old != state {
+            self.palette.set_block(x, y, z, state);
+            self.counts.change(old, state);
         }
     }
+
+    pub fn composition(&self) -> Composition {
+        Composition::from_states(self.counts.iter())
+    }
+}
+
+impl Section for HighMemoryChunkSection {
+    fn is_empty(&self) -> bool {
+        self.counts.iter().all(|(state, _)| state == BlockState::AIR)
+    }
 }
 
 #[repr(packed)]
 pub struct LowMemoryChunkSection {
     storage: [u8; SECTION_BYTES],
+
+    /// the number of blocks of each simple type by [`SimpleType::id`]
+    counts: [u16; 4],
+}
+
+// derive(Clone) does not work on packed structs
+impl Clone for LowMemoryChunkSection {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage,
+            counts: self.counts,
+        }
+    }
 }
 
 impl Default for LowMemoryChunkSection {
     fn default() -> Self {
+        // every block is solid (id 0)
         Self {
-            storage: [0; SECTION_BYTES]
+            storage: [0; SECTION_BYTES],
+            counts: [SECTION_ELEMENTS as u16, 0, 0, 0],
         }
     }
 }
@@ -74,13 +190,24 @@ impl LowMemoryChunkSection {
         let offset = block_number - (idx << 2);
 
         let mut res = self.storage[idx];
-        res >>= offset;
+        res >>= offset * 2;
         res &= 0b11;
 
         SimpleType::from(res)
     }
 
     fn set_simple_type(&mut self, x: u8, y: u8, z: u8, input: SimpleType) {
+        let old = self.get_simple_type(x, y, z);
+        if old == input {
+            return;
+        }
+
+        // fields of packed structs cannot be borrowed
+        let mut counts = self.counts;
+        counts[old.id() as usize] -= 1;
+        counts[input.id() as usize] += 1;
+        self.counts = counts;
+
         let block_number = (((y as usize * SECTION_HEIGHT) + z as usize) * SECTION_WIDTH) + x as usize;
 
         // 2 bits per block
@@ -91,18 +218,47 @@ impl LowMemoryChunkSection {
 
         let id = input.id();
 
-        let zero_out = !(0b11 << offset);
+        let zero_out = !(0b11 << (offset * 2));
         block &= zero_out;
 
-        block |= id << offset;
+        block |= id << (offset * 2);
 
         self.storage[idx] = block;
     }
+
+    pub fn composition(&self) -> Composition {
+        let counts = self.counts;
+        let mut simple = [0; 4];
+        for (total, count) in simple.iter_mut().zip(counts) {
+            *total = count as u32;
+        }
+        Composition::from_simple(simple)
+    }
+}
+
+impl Section for LowMemoryChunkSection {
+    fn is_empty(&self) -> bool {
+        let counts = self.counts;
+        counts[SimpleType::WalkThrough.id() as usize] == SECTION_ELEMENTS as u16
+    }
+}
+
+impl From<&HighMemoryChunkSection> for LowMemoryChunkSection {
+    fn from(section: &HighMemoryChunkSection) -> Self {
+        let mut low = LowMemoryChunkSection::default();
+        for (idx, state) in section.palette.all_states().iter().enumerate() {
+            let (x, z, y) = (idx % 16, (idx >> 4) % 16, idx >> 8);
+            low.set_simple_type(x as u8, y as u8, z as u8, state.simple_type());
+        }
+        low
+    }
 }
 
-#[derive(Default)]
+/// Sections are reference counted so columns can be shared between [`crate::storage::blocks::WorldSnapshot`]s.
+/// Mutating a shared section clones only that section (copy-on-write).
+#[derive(Default, Clone)]
 pub struct ChunkData<T> {
-    pub sections: [Option<T>; 16],
+    pub sections: [Option<Arc<T>>; 16],
 }
 
 impl<T> ChunkData<T> {
@@ -117,16 +273,61 @@ impl<T> ChunkData<T> {
         BlockLocation::new(base_x + x as i32, y as i16, base_z + z as i32)
     }
 
-    fn highest_mut(&mut self) -> Option<&mut T> {
-        self.sections.iter_mut().rev().flatten().next()
+    fn highest_mut(&mut self) -> Option<&mut T> where T: Clone {
+        self.sections.iter_mut().rev().flatten().next().map(Arc::make_mut)
+    }
+
+    fn lowest_mut(&mut self) -> Option<&mut T> where T: Clone {
+        self.sections.iter_mut().flatten().next().map(Arc::make_mut)
+    }
+
+    /// the primary bit mask: bit `idx` is set if section `idx` is there
+    pub fn mask(&self) -> u16 {
+        self.sections.iter().enumerate()
+            .filter(|(_, section)| section.is_some())
+            .fold(0, |mask, (idx, _)| mask | 1 << idx)
     }
+}
 
-    fn lowest_mut(&mut self) -> Option<&mut T> {
-        self.sections.iter_mut().flatten().next()
+impl<T: Section> ChunkData<T> {
+    /// Replace the sections which were sent in `update` and keep the others. Sent sections which are empty are
+    /// removed.
+    fn merge(&mut self, update: ChunkData<T>) {
+        for (idx, section) in IntoIterator::into_iter(update.sections).enumerate() {
+            if let Some(section) = section {
+                self.sections[idx] = if section.is_empty() { None } else { Some(section) };
+            }
+        }
     }
 }
 
 impl ChunkData<HighMemoryChunkSection> {
+    /// The (block light, sky light) of the sections which are not there: dark without a sky and open to it with one.
+    /// None if the column does not keep light.
+    fn missing_light(&self) -> Option<(u8, Option<u8>)> {
+        let light = self.sections.iter().flatten().find_map(|section| section.light())?;
+        Some((0, light.sky.as_ref().map(|_| 15)))
+    }
+
+    /// section `idx`, added as air if it is not there
+    fn section_mut(&mut self, idx: usize) -> &mut HighMemoryChunkSection {
+        if self.sections[idx].is_none() {
+            let light = self.missing_light().map(|(block, sky)| SectionLight::uniform(block, sky));
+            let section = HighMemoryChunkSection::default().with_light(light);
+            self.sections[idx] = Some(Arc::new(section));
+        }
+        Arc::make_mut(self.sections[idx].as_mut().unwrap())
+    }
+
+    /// the same sections knowing only the simple types of their blocks
+    fn to_low_memory(&self) -> ChunkData<LowMemoryChunkSection> {
+        let mut low = ChunkData::default();
+        for (idx, section) in self.sections.iter().enumerate() {
+            low.sections[idx] = section.as_deref().map(|section| Arc::new(LowMemoryChunkSection::from(section)));
+        }
+        low
+    }
+
     pub fn all_at(&self, y: u8) -> [BlockState; 256] {
         let section_idx = y >> 4;
 
@@ -185,6 +386,7 @@ impl ChunkData<HighMemoryChunkSection> {
 const SECTION_HEIGHT: usize = 16;
 const SECTION_WIDTH: usize = 16;
 
+#[derive(Clone)]
 pub struct Palette {
     bits_per_block: u8,
     id_to_state: Option<Vec<BlockState>>,
@@ -224,6 +426,73 @@ impl Palette {
         }
     }
 
+    /// Build the smallest palette holding `states` (indexed like [`Palette::all_states`]) which the protocol allows.
+    /// Used by protocols whose chunk format differs from ours.
+    pub fn from_states(states: &[BlockState; 4096]) -> Palette {
+        Palette::with_min_bits(states, 4)
+    }
+
+    /// Like [`Palette::from_states`] but with fewer than 4 bits a block if that is enough. A section of a single
+    /// state takes a quarter of the memory. Changing a block to a new state grows it back to 4 bits.
+    pub fn packed(states: &[BlockState; 4096]) -> Palette {
+        Palette::with_min_bits(states, 1)
+    }
+
+    /// The blocks repacked with [`Palette::packed`]. None if that would not save any memory, as the palette has no
+    /// states which are not used anymore and already takes as few bits as it can.
+    pub fn compacted(&self) -> Option<Palette> {
+        if self.bits_per_block == 1 {
+            return None;
+        }
+
+        let packed = Palette::packed(&self.all_states());
+        (packed.bits_per_block < self.bits_per_block).then(|| packed)
+    }
+
+    /// the bytes the blocks take
+    pub fn size(&self) -> usize {
+        self.storage.len() * 8 + self.id_to_state.as_ref().map_or(0, |states| states.len() * 4)
+    }
+
+    fn with_min_bits(states: &[BlockState; 4096], min_bits: u8) -> Palette {
+        let mut id_to_state: Vec<BlockState> = Vec::new();
+        let mut reverse_map = HashMap::new();
+        for &state in states.iter() {
+            reverse_map.entry(state).or_insert_with(|| {
+                id_to_state.push(state);
+                id_to_state.len() - 1
+            });
+        }
+
+        let indirect = id_to_state.len() <= 256;
+        let bits_per_block = if indirect { bits_needed(id_to_state.len()).max(min_bits) as usize } else { 13 };
+
+        let mut storage = vec![0_u64; 4096 * bits_per_block / 64];
+        for (block_number, state) in states.iter().enumerate() {
+            let value = if indirect { reverse_map[state] as u64 } else { state.0 as u64 };
+
+            let start_long = (block_number * bits_per_block) / 64;
+            let start_offset = (block_number * bits_per_block) % 64;
+            let end_long = ((block_number + 1) * bits_per_block - 1) / 64;
+
+            storage[start_long] |= value << start_offset;
+
+            if start_long != end_long {
+                storage[end_long] |= value >> (64 - start_offset);
+            }
+        }
+
+        if indirect {
+            Palette {
+                bits_per_block: bits_per_block as u8,
+                id_to_state: Some(id_to_state),
+                storage,
+            }
+        } else {
+            Palette::direct(storage)
+        }
+    }
+
     pub fn all_states(&self) -> [BlockState; 4096] {
         let mut res = [BlockState::AIR; 4096];
         (0..4096).for_each(|i| res[i] = self.get_block_by_idx(i));
@@ -246,23 +515,23 @@ impl Palette {
                         id_to_state.push(state);
 
                         if required_bits > self.bits_per_block {
-                            let (required_bits, reverse_map) = if required_bits <= 8 {
+                            // we have to recreate the palette
+
+                            // TODO: we could modify states with new block id and instantly return
+                            let (required_bits, reverse_map, states) = if required_bits <= 8 {
                                 let reverse_map: HashMap<_, _> = id_to_state.iter().enumerate().map(|(k, v)| (*v, k)).collect();
 
-                                (required_bits.max(4), Some(reverse_map))
+                                (required_bits.max(4), Some(reverse_map), self.all_states())
                             } else {
+                                // the states have to be read while the old palette is still there
+                                let states = self.all_states();
                                 self.id_to_state = None;
 
-                                (13, None)
+                                (13, None, states)
                             };
 
                             // debug_println!("expand bits {} -> {} ... reverse_map {:?}", self.bits_per_block, required_bits, reverse_map);
 
-                            // we have to recreate the palette
-
-                            // TODO: we could modify states with new block id and instantly return
-                            let states = self.all_states();
-
                             // update bits per block
                             self.bits_per_block = required_bits;
 
@@ -295,6 +564,11 @@ impl Palette {
                             }
 
                             self.storage = storage;
+
+                            // a direct palette stores the state itself
+                            if self.id_to_state.is_none() {
+                                return self.set_block(x, y, z, state);
+                            }
                         }
                         (new_len - 1) as u32
                     }
@@ -359,7 +633,7 @@ impl Palette {
         }
     }
 
-    fn get_block(&self, x: u8, y: u8, z: u8) -> BlockState {
+    pub fn get_block(&self, x: u8, y: u8, z: u8) -> BlockState {
         let block_number = (((y as usize * SECTION_HEIGHT) + z as usize) * SECTION_WIDTH) + x as usize;
         self.get_block_by_idx(block_number)
     }
@@ -376,7 +650,7 @@ impl Palette {
     // }
 }
 
-
+#[derive(Clone)]
 pub enum ChunkColumn {
     LowMemory { data: ChunkData<LowMemoryChunkSection> },
     HighMemory { data: ChunkData<HighMemoryChunkSection> },
@@ -389,15 +663,14 @@ impl Default for ChunkColumn {
 }
 
 impl ChunkColumn {
+    /// Apply a partial update of the column. Only the sections which were sent (the primary bit mask of the update)
+    /// change and sent sections which are all air are removed.
     pub fn modify(&mut self, column: ChunkColumn) {
-        if let (ChunkColumn::HighMemory { data: left }, ChunkColumn::HighMemory { data: right }) = (self, column) {
-            for (idx, new_section) in IntoIterator::into_iter(right.sections).enumerate() {
-                if let Some(section) = new_section {
-                    left.sections[idx] = Some(section);
-                }
-            }
-        } else {
-            panic!("cannot modify low memory chunks");
+        match (self, column) {
+            (ChunkColumn::HighMemory { data: left }, ChunkColumn::HighMemory { data: right }) => left.merge(right),
+            (ChunkColumn::LowMemory { data: left }, ChunkColumn::LowMemory { data: right }) => left.merge(right),
+            (ChunkColumn::LowMemory { data: left }, ChunkColumn::HighMemory { data: right }) => left.merge(right.to_low_memory()),
+            (ChunkColumn::HighMemory { .. }, ChunkColumn::LowMemory { .. }) => panic!("cannot modify high memory chunks with low memory sections"),
         }
     }
 
@@ -408,15 +681,109 @@ impl ChunkColumn {
         let section_idx = section_idx as usize;
         match self {
             ChunkColumn::LowMemory { data } => {
-                let section = data.sections[section_idx].get_or_insert_default();
+                let section = Arc::make_mut(data.sections[section_idx].get_or_insert_default());
                 section.set_simple_type(x, y_offset, z, state.simple_type());
             }
             ChunkColumn::HighMemory { data } => {
-                let section = data.sections[section_idx].get_or_insert_default();
-                section.palette.set_block(x, y_offset, z, state);
+                data.section_mut(section_idx).set_block(x, y_offset, z, state);
             }
         }
     }
+
+    /// (block light, sky light) at `x y z`. None if the column does not keep light.
+    pub fn light(&self, x: u8, y: u8, z: u8) -> Option<(u8, u8)> {
+        let section_idx = (y >> 4) as usize;
+        let y_offset = y & 15;
+
+        let data = match self {
+            ChunkColumn::HighMemory { data } => data,
+            ChunkColumn::LowMemory { .. } => return None,
+        };
+
+        match &data.sections[section_idx] {
+            Some(section) => section.light().map(|light| light.get(x, y_offset, z)),
+            None => data.missing_light().map(|(block, sky)| (block, sky.unwrap_or(0))),
+        }
+    }
+
+    /// Raise the block light at `x y z` to `level`. Returns false if it was at least as bright already or the column
+    /// does not keep light.
+    pub fn raise_light(&mut self, x: u8, y: u8, z: u8, level: u8) -> bool {
+        let section_idx = (y >> 4) as usize;
+        let y_offset = y & 15;
+
+        let data = match self {
+            ChunkColumn::HighMemory { data } => data,
+            ChunkColumn::LowMemory { .. } => return false,
+        };
+
+        let lit = match &data.sections[section_idx] {
+            Some(section) => section.light().map_or(false, |light| light.get(x, y_offset, z).0 < level),
+            None => data.missing_light().is_some(),
+        };
+
+        lit && data.section_mut(section_idx).light.as_mut().unwrap().raise(x, y_offset, z, level)
+    }
+
+    /// Repack every section into as few bits as it needs to save memory (see [`Palette::packed`]). Returns how many
+    /// sections were repacked.
+    pub fn compact(&mut self) -> usize {
+        let mut compacted = 0;
+        if let ChunkColumn::HighMemory { data } = self {
+            for section in data.sections.iter_mut().flatten() {
+                // sections shared with snapshots are only copied if they get smaller
+                if let Some(palette) = section.palette.compacted() {
+                    Arc::make_mut(section).palette = palette;
+                    compacted += 1;
+                }
+            }
+        }
+        compacted
+    }
+
+    /// forget the light of every section to save memory
+    pub fn strip_light(&mut self) {
+        if let ChunkColumn::HighMemory { data } = self {
+            for section in data.sections.iter_mut().flatten() {
+                if section.light.is_some() {
+                    Arc::make_mut(section).light = None;
+                }
+            }
+        }
+    }
+    /// the blocks in the section `idx` (y from `idx * 16` to `idx * 16 + 15`). Missing sections are air.
+    pub fn section_composition(&self, idx: usize) -> Composition {
+        const MISSING: u32 = SECTION_ELEMENTS as u32;
+        match self {
+            ChunkColumn::LowMemory { data } => match &data.sections[idx] {
+                None => Composition::from_simple([0, 0, 0, MISSING]),
+                Some(section) => section.composition(),
+            },
+            ChunkColumn::HighMemory { data } => match &data.sections[idx] {
+                None => Composition::from_states([(BlockState::AIR, MISSING)]),
+                Some(section) => section.composition(),
+            }
+        }
+    }
+
+    /// The blocks in the column. Low memory columns only know the simple type of their blocks.
+    pub fn composition(&self) -> Composition {
+        let mut composition = Composition::default();
+        for idx in 0..16 {
+            composition.add(&self.section_composition(idx));
+        }
+        composition
+    }
+
+    /// the highest y of the highest section which is there. None if the column is all air.
+    pub fn top(&self) -> Option<u8> {
+        let mask = match self {
+            ChunkColumn::LowMemory { data } => data.mask(),
+            ChunkColumn::HighMemory { data } => data.mask(),
+        };
+        (mask != 0).then(|| ((16 - mask.leading_zeros()) * 16 - 1) as u8)
+    }
+
     pub fn get_block(&self, x: u8, y: u8, z: u8) -> BlockApprox {
         let section_idx = (y >> 4) as u8;
         let y_offset = y - (section_idx << 4);
@@ -447,10 +814,48 @@ impl ChunkColumn {
 mod tests {
     use std::collections::HashMap;
 
+    use std::sync::Arc;
+
     use itertools::Itertools;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use crate::storage::block::{BlockApprox, BlockState, SimpleType};
+    use crate::storage::chunk::{bits_needed, ChunkColumn, ChunkData, HighMemoryChunkSection, LowMemoryChunkSection, Palette, SectionLight};
+    use crate::storage::composition::Composition;
+
+    /// count every block of the column one by one
+    fn recount(column: &ChunkColumn) -> Composition {
+        let mut states = HashMap::new();
+        let mut simple = [0; 4];
+        for ((x, y), z) in (0..16).cartesian_product(0..=255).cartesian_product(0..16) {
+            match column.get_block(x, y, z) {
+                BlockApprox::Realized(state) => *states.entry(state).or_default() += 1,
+                BlockApprox::Estimate(simple_type) => simple[simple_type.id() as usize] += 1,
+            }
+        }
+
+        match column {
+            ChunkColumn::HighMemory { .. } => Composition::from_states(states),
+            ChunkColumn::LowMemory { .. } => Composition::from_simple(simple),
+        }
+    }
+
+    /// a high memory column with the sections in `mask` full of `state`
+    fn column(mask: u16, state: BlockState) -> ChunkColumn {
+        let mut data = ChunkData::<HighMemoryChunkSection>::default();
+        for idx in (0..16).filter(|idx| mask & 1 << idx != 0) {
+            data.sections[idx] = Some(Arc::new(HighMemoryChunkSection::new(Palette::from_states(&[state; 4096]))));
+        }
+        ChunkColumn::HighMemory { data }
+    }
 
-    use crate::storage::block::BlockState;
-    use crate::storage::chunk::{bits_needed, Palette};
+    fn mask(column: &ChunkColumn) -> u16 {
+        match column {
+            ChunkColumn::HighMemory { data } => data.mask(),
+            ChunkColumn::LowMemory { data } => data.mask(),
+        }
+    }
 
     #[test]
     fn test_bits_needed() {
@@ -525,4 +930,183 @@ mod tests {
             assert_eq!(palette.get_block(x as u8, y as u8, z as u8), block_state, "not eq at {} {} {}", x, y, z);
         }
     }
+
+    #[test]
+    fn test_from_states() {
+        // few states -> indirect, many states -> direct
+        for distinct in [1_u32, 3, 17, 256, 300, 4096] {
+            let mut states = [BlockState::AIR; 4096];
+            for (i, state) in states.iter_mut().enumerate() {
+                *state = BlockState((i as u32 * 7) % distinct);
+            }
+
+            let palette = Palette::from_states(&states);
+            assert_eq!(palette.all_states(), states, "{} distinct states", distinct);
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        // a section of stone and 3 states
+        let mut states = [BlockState::STONE; 4096];
+        states[100] = BlockState::AIR;
+        states[4000] = BlockState(17 << 4);
+        let full = Palette::from_states(&states);
+        let packed = full.compacted().unwrap();
+        assert_eq!(packed.all_states(), states);
+        assert_eq!(packed.bits_per_block, 2);
+        assert!(packed.size() < full.size());
+        assert!(packed.compacted().is_none());
+
+        // it grows again when a block changes to a new state
+        let mut grown = packed.clone();
+        grown.set_block(5, 5, 5, BlockState(3 << 4));
+        grown.set_block(6, 5, 5, BlockState(5 << 4));
+        states[(5 * 16 + 5) * 16 + 5] = BlockState(3 << 4);
+        states[(5 * 16 + 5) * 16 + 6] = BlockState(5 << 4);
+        assert_eq!(grown.all_states(), states);
+
+        // states which are not used anymore are dropped
+        let mut stale = Palette::from_states(&[BlockState::STONE; 4096]);
+        for state in 1..20 {
+            stale.set_block(0, 0, 0, BlockState(state << 4));
+        }
+        stale.set_block(0, 0, 0, BlockState::STONE);
+        assert_eq!(stale.compacted().unwrap().all_states(), [BlockState::STONE; 4096]);
+
+        let mut column = column(0b101, BlockState::STONE);
+        let before = recount(&column);
+        assert_eq!(column.compact(), 2);
+        assert_eq!(column.compact(), 0);
+        assert_eq!(recount(&column), before);
+    }
+
+    #[test]
+    fn test_composition_random() {
+        let mut rng = StdRng::seed_from_u64(804);
+
+        // a parsed section starts with the counts of its palette
+        let mut states = [BlockState::AIR; 4096];
+        for state in states.iter_mut() {
+            *state = BlockState(rng.gen_range(0..5) << 4);
+        }
+        let mut data = ChunkData::<HighMemoryChunkSection>::default();
+        data.sections[3] = Some(Arc::new(HighMemoryChunkSection::new(Palette::from_states(&states))));
+
+        let mut high = ChunkColumn::HighMemory { data };
+        let mut low = ChunkColumn::LowMemory { data: ChunkData::default() };
+        assert_eq!(high.composition(), recount(&high));
+
+        for round in 0..8 {
+            // later rounds use more distinct states so the palette becomes direct
+            let distinct = 4 << (round * 2);
+            for _ in 0..2000 {
+                let (x, y, z) = (rng.gen_range(0..16), rng.gen_range(0..=255), rng.gen_range(0..16));
+                let state = BlockState(rng.gen_range(0..distinct.min(4096)));
+                high.set_block(x, y, z, state);
+                low.set_block(x, y, z, state);
+            }
+
+            assert_eq!(high.composition(), recount(&high), "round {}", round);
+            assert_eq!(low.composition(), recount(&low), "round {}", round);
+            assert_eq!(high.composition().total(), 16 * 16 * 256);
+            assert_eq!(low.composition().total(), 16 * 16 * 256);
+        }
+    }
+
+    #[test]
+    fn test_modify_mask() {
+        let dirt = BlockState(3 << 4);
+        for &update in &[0_u16, 0b1, 0x00F0, 0b1010_0000_0000_0101, 0x8000, 0xFFFF] {
+            let mut modified = column(0x00FF, BlockState::STONE);
+            modified.modify(column(update, dirt));
+
+            assert_eq!(mask(&modified), 0x00FF | update, "update {:#06x}", update);
+            for idx in 0..16_u8 {
+                let expected = if update & 1 << idx != 0 {
+                    dirt
+                } else if idx < 8 {
+                    BlockState::STONE
+                } else {
+                    BlockState::AIR
+                };
+                assert_eq!(modified.get_block(3, idx * 16 + 5, 7).as_real(), expected, "update {:#06x} section {}", update, idx);
+            }
+            assert_eq!(modified.composition(), recount(&modified));
+        }
+    }
+
+    #[test]
+    fn test_modify_air() {
+        let mut modified = column(0b0111, BlockState::STONE);
+
+        // the middle section was mined out and is sent as all air
+        modified.modify(column(0b0010, BlockState::AIR));
+
+        assert_eq!(mask(&modified), 0b0101);
+        assert_eq!(modified.get_block(0, 0, 0).as_real(), BlockState::STONE);
+        assert_eq!(modified.get_block(0, 20, 0).as_real(), BlockState::AIR);
+        assert_eq!(modified.get_block(0, 40, 0).as_real(), BlockState::STONE);
+        assert_eq!(modified.composition(), recount(&modified));
+
+        // a section with a single block left is kept
+        let mut mostly_air = column(0b0010, BlockState::AIR);
+        mostly_air.set_block(4, 20, 4, BlockState::STONE);
+        modified.modify(mostly_air);
+        assert_eq!(mask(&modified), 0b0111);
+        assert_eq!(modified.get_block(4, 20, 4).as_real(), BlockState::STONE);
+    }
+
+    #[test]
+    fn test_modify_low_memory() {
+        let mut data = ChunkData::<LowMemoryChunkSection>::default();
+        data.sections[0] = Some(Arc::new(LowMemoryChunkSection::default()));
+        data.sections[2] = Some(Arc::new(LowMemoryChunkSection::default()));
+        let mut modified = ChunkColumn::LowMemory { data };
+
+        let water = BlockState(9 << 4);
+        modified.modify(column(0b0110, water));
+        assert_eq!(mask(&modified), 0b0111);
+        assert_eq!(modified.get_block(0, 0, 0).s_type(), SimpleType::Solid);
+        assert_eq!(modified.get_block(0, 20, 0).s_type(), SimpleType::Water);
+        assert_eq!(modified.get_block(0, 40, 0).s_type(), SimpleType::Water);
+
+        modified.modify(column(0b0100, BlockState::AIR));
+        assert_eq!(mask(&modified), 0b0011);
+        assert_eq!(modified.get_block(0, 40, 0).s_type(), SimpleType::WalkThrough);
+        assert_eq!(modified.composition(), recount(&modified));
+    }
+
+    #[test]
+    fn test_light() {
+        // block 0 has block light 1 (low nibble) and block 1 has block light 2
+        let mut block = [0; 2048];
+        block[0] = 0x21;
+        let light = SectionLight::new(block, Some([0xFF; 2048]));
+
+        let mut data = ChunkData::<HighMemoryChunkSection>::default();
+        data.sections[0] = Some(Arc::new(HighMemoryChunkSection::default().with_light(Some(light))));
+        let mut column = ChunkColumn::HighMemory { data };
+
+        assert_eq!(column.light(0, 0, 0), Some((1, 15)));
+        assert_eq!(column.light(1, 0, 0), Some((2, 15)));
+        assert_eq!(column.light(2, 0, 0), Some((0, 15)));
+
+        assert!(column.raise_light(1, 0, 0, 9));
+        assert!(!column.raise_light(1, 0, 0, 4));
+        assert_eq!(column.light(0, 0, 0), Some((1, 15)));
+        assert_eq!(column.light(1, 0, 0), Some((9, 15)));
+
+        // a missing section is added to hold the light
+        assert_eq!(column.light(5, 100, 5), Some((0, 15)));
+        assert!(column.raise_light(5, 100, 5, 3));
+        assert_eq!(column.light(5, 100, 5), Some((3, 15)));
+        assert_eq!(mask(&column), 0b0100_0001);
+        assert_eq!(column.get_block(5, 100, 5).as_real(), BlockState::AIR);
+
+        column.strip_light();
+        assert_eq!(column.light(0, 0, 0), None);
+        assert_eq!(column.light(5, 20, 5), None);
+        assert!(!column.raise_light(5, 20, 5, 3));
+    }
 }
@@ -0,0 +1,154 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! How many blocks of each kind a section, column or area holds. Sections keep their counts up to date as blocks
+//! change, so summaries do not have to look at every block.
+
+use std::collections::HashMap;
+
+use crate::storage::block::{BlockKind, BlockState, SimpleType};
+
+const SIMPLE_TYPES: usize = 4;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Composition {
+    /// the number of blocks of each state. Empty for low memory columns which only know simple types.
+    states: HashMap<BlockState, u32>,
+
+    /// the number of blocks of each simple type by [`SimpleType::id`]
+    simple: [u32; SIMPLE_TYPES],
+}
+
+impl Composition {
+    pub fn from_states(counts: impl IntoIterator<Item=(BlockState, u32)>) -> Composition {
+        let mut composition = Composition::default();
+        for (state, count) in counts {
+            composition.add_state(state, count);
+        }
+        composition
+    }
+
+    pub fn from_simple(simple: [u32; SIMPLE_TYPES]) -> Composition {
+        Composition { states: HashMap::new(), simple }
+    }
+
+    pub fn add_state(&mut self, state: BlockState, count: u32) {
+        *self.states.entry(state).or_default() += count;
+        self.simple[state.simple_type().id() as usize] += count;
+    }
+
+    pub fn add(&mut self, other: &Composition) {
+        for (&state, &count) in &other.states {
+            *self.states.entry(state).or_default() += count;
+        }
+        for (total, count) in self.simple.iter_mut().zip(other.simple) {
+            *total += count;
+        }
+    }
+
+    pub fn state(&self, state: BlockState) -> u32 {
+        self.states.get(&state).copied().unwrap_or_default()
+    }
+
+    /// the number of blocks of `kind` in any state
+    pub fn kind(&self, kind: BlockKind) -> u32 {
+        self.states.iter()
+            .filter(|(state, _)| state.kind() == kind)
+            .map(|(_, &count)| count)
+            .sum()
+    }
+
+    pub fn kinds(&self) -> HashMap<BlockKind, u32> {
+        let mut kinds = HashMap::new();
+        for (state, &count) in &self.states {
+            *kinds.entry(state.kind()).or_default() += count;
+        }
+        kinds
+    }
+
+    pub fn simple(&self, simple: SimpleType) -> u32 {
+        self.simple[simple.id() as usize]
+    }
+
+    pub fn total(&self) -> u32 {
+        self.simple.iter().sum()
+    }
+
+    /// false if only simple types are known for some of the blocks
+    pub fn is_exact(&self) -> bool {
+        self.states.values().sum::<u32>() == self.total()
+    }
+}
+
+/// The number of blocks of each state in a section, changed along with the section
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateCounts {
+    counts: HashMap<BlockState, u16>,
+}
+
+impl StateCounts {
+    pub fn of(states: &[BlockState]) -> StateCounts {
+        let mut counts = HashMap::new();
+        for &state in states {
+            *counts.entry(state).or_default() += 1;
+        }
+        StateCounts { counts }
+    }
+
+    /// a block changed from `old` to `new`
+    pub fn change(&mut self, old: BlockState, new: BlockState) {
+        if old == new {
+            return;
+        }
+
+        if let Some(count) = self.counts.get_mut(&old) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&old);
+            }
+        }
+        *self.counts.entry(new).or_default() += 1;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(BlockState, u32)> + '_ {
+        self.counts.iter().map(|(&state, &count)| (state, count as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::block::{BlockKind, BlockState, SimpleType};
+    use crate::storage::composition::{Composition, StateCounts};
+
+    #[test]
+    fn test_counts() {
+        let granite = BlockState(1 << 4 | 1);
+        let mut counts = StateCounts::of(&[BlockState::AIR, BlockState::AIR, BlockState::STONE]);
+        counts.change(BlockState::AIR, granite);
+        counts.change(BlockState::STONE, BlockState::STONE);
+
+        let mut composition = Composition::from_states(counts.iter());
+        assert_eq!(composition.state(BlockState::AIR), 1);
+        assert_eq!(composition.kind(BlockKind(1)), 2);
+        assert_eq!(composition.simple(SimpleType::Solid), 2);
+        assert!(composition.is_exact());
+
+        composition.add(&Composition::from_simple([0, 0, 0, 5]));
+        assert_eq!(composition.total(), 8);
+        assert_eq!(composition.simple(SimpleType::WalkThrough), 6);
+        assert!(!composition.is_exact());
+    }
+}
@@ -16,17 +16,32 @@
 
 use indexmap::map::IndexMap;
 
-use crate::types::{Location, LocationOrigin};
+use crate::storage::block::BlockState;
+use crate::types::{Displacement, Location, LocationOrigin};
 
 pub enum EntityKind {
     Normal,
+
+    /// a mob which attacks players on sight (zombies, skeletons, creepers, ...)
+    Hostile,
     Player {
         uuid: u128,
-    }
+    },
+
+    /// sand, gravel, anvils, ... which become `state` when they land
+    FallingBlock {
+        state: BlockState,
+    },
+
+    /// a dropped item. Which item it is is not tracked.
+    Item,
 }
 
 pub struct EntityData {
     pub location: Location,
+
+    /// the last velocity the server set. Zero until it sets one.
+    pub velocity: Displacement,
     pub owner: Option<u32>,
     pub kind: EntityKind
 }
@@ -49,6 +64,17 @@ impl WorldEntities {
         }
     }
 
+    pub fn set_velocity(&mut self, entity_id: u32, bot_id: u32, velocity: Displacement) {
+        let entity = match self.entities.get_mut(&entity_id) {
+            None => return,
+            Some(entity) => entity
+        };
+        let id = entity.owner.get_or_insert(bot_id);
+        if *id == bot_id {
+            entity.velocity = velocity;
+        }
+    }
+
     pub fn iter(&self) -> impl Iterator<Item=(&u32, &EntityData)> + '_ {
         self.entities.iter()
     }
@@ -59,11 +85,38 @@ impl WorldEntities {
 
     pub fn by_player_uuid(&self, uuid: u128) -> Option<u32> {
         self.iter().find_map(|(id, data)| match data.kind {
-            EntityKind::Normal => None,
-            EntityKind::Player{ uuid: player_uuid } => (player_uuid == uuid).then_some(*id)
+            EntityKind::Player{ uuid: player_uuid } => (player_uuid == uuid).then_some(*id),
+            _ => None,
         })
     }
 
+    /// Forget the falling block `entity_id`. Returns the block it turns into and where it was last seen.
+    pub fn remove_falling_block(&mut self, entity_id: u32) -> Option<(BlockState, Location)> {
+        match self.entities.get(&entity_id)?.kind {
+            EntityKind::FallingBlock { state } => {
+                let entity = self.entities.remove(&entity_id)?;
+                Some((state, entity.location))
+            }
+            _ => None
+        }
+    }
+
+    /// Forget the item `entity_id`. Items are destroyed when picked up or despawned, which is far more likely than every
+    /// bot losing sight of them, so unlike other entities they are not kept around. False if it is not an item.
+    pub fn remove_item(&mut self, entity_id: u32) -> bool {
+        match self.entities.get(&entity_id) {
+            Some(EntityData { kind: EntityKind::Item, .. }) => self.entities.remove(&entity_id).is_some(),
+            _ => false
+        }
+    }
+
+    /// the items within `radius` of `center` with where they are
+    pub fn items_near(&self, center: Location, radius: f64) -> impl Iterator<Item=(u32, Location)> + '_ {
+        self.iter()
+            .filter(move |(_, data)| matches!(data.kind, EntityKind::Item) && data.location.dist2(center) <= radius * radius)
+            .map(|(&id, data)| (id, data.location))
+    }
+
     pub fn remove_entity(&mut self, entity_id: u32, bot_id: u32) {
         let entity = self.entities.get_mut(&entity_id);
         let entity = match entity {
@@ -76,9 +129,19 @@ impl WorldEntities {
         }
     }
 
+    /// the bot cannot see the entities anymore (i.e., it went to another dimension)
+    pub fn release(&mut self, bot_id: u32) {
+        for entity in self.entities.values_mut() {
+            if entity.owner == Some(bot_id) {
+                entity.owner = None;
+            }
+        }
+    }
+
     pub fn put_entity(&mut self, entity_id: u32, bot_id: u32, location: Location, kind: EntityKind) {
         self.entities.insert(entity_id, EntityData {
             location,
+            velocity: Displacement::default(),
             owner: Some(bot_id),
             kind
         });
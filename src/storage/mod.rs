@@ -14,7 +14,11 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+pub mod anvil;
 pub mod chunk;
 pub mod entities;
 pub mod blocks;
 pub mod block;
+pub mod composition;
+pub mod sign;
+pub mod tile_entity;
@@ -0,0 +1,107 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The text of signs, which is part of their block entity
+
+use crate::chat::Component;
+use crate::nbt::{Compound, Tag};
+use crate::storage::block::BlockKind;
+
+/// the four lines of a sign as plain text
+pub type SignText = [String; 4];
+
+pub fn is_sign(kind: BlockKind) -> bool {
+    kind == BlockKind::STANDING_SIGN || kind == BlockKind::WALL_SIGN
+}
+
+/// The text of a sign block entity. The lines are chat json. None if `root` is not a sign.
+pub fn sign_text(root: &Compound) -> Option<SignText> {
+    match root.get("id").and_then(Tag::as_str) {
+        // 1.11 and later, and the name before
+        Some("minecraft:sign") | Some("Sign") => {}
+        _ => return None
+    }
+
+    let line = |key| root.get(key)
+        .and_then(Tag::as_str)
+        .map(|json| Component::parse(json).to_plain_text())
+        .unwrap_or_default();
+
+    Some([line("Text1"), line("Text2"), line("Text3"), line("Text4")])
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use crate::nbt::{NbtLimits, read};
+    use crate::storage::block::BlockLocation;
+    use crate::storage::sign::sign_text;
+    use crate::storage::tile_entity::location;
+
+    #[derive(Serialize)]
+    struct BlockEntity {
+        id: &'static str,
+        x: i32,
+        y: i32,
+        z: i32,
+
+        #[serde(rename = "Text1")]
+        text1: &'static str,
+
+        #[serde(rename = "Text2")]
+        text2: &'static str,
+
+        #[serde(rename = "Text3")]
+        text3: &'static str,
+
+        #[serde(rename = "Text4")]
+        text4: &'static str,
+    }
+
+    fn encode(entity: &BlockEntity) -> Vec<u8> {
+        let mut data = Vec::new();
+        nbt::to_writer(&mut data, entity, None).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_sign() {
+        let data = encode(&BlockEntity {
+            id: "minecraft:sign",
+            x: -12,
+            y: 70,
+            z: 300,
+            text1: r#"{"text":"base"}"#,
+            text2: r#"{"text":"","extra":[{"text":"100 "},{"text":"64","color":"red"}]}"#,
+            text3: r#""-2000""#,
+            text4: "",
+        });
+        let nbt = read(&data, &NbtLimits::NETWORK).unwrap();
+
+        assert_eq!(location(&nbt.root), Some(BlockLocation::new(-12, 70, 300)));
+        let text = sign_text(&nbt.root).unwrap();
+        assert_eq!(text, ["base".to_string(), "100 64".to_string(), "-2000".to_string(), String::new()]);
+    }
+
+    #[test]
+    fn test_not_sign() {
+        let data = encode(&BlockEntity { id: "minecraft:chest", x: 0, y: 0, z: 0, text1: "", text2: "", text3: "", text4: "" });
+        let nbt = read(&data, &NbtLimits::NETWORK).unwrap();
+        assert_eq!(sign_text(&nbt.root), None);
+        assert_eq!(location(&nbt.root), Some(BlockLocation::default()));
+    }
+}
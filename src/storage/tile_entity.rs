@@ -0,0 +1,278 @@
+/*
+ * Copyright (c) 2021 Andrew Gazelka - All Rights Reserved.
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Block entities (tile entities): what the server tells us about a block besides its state. They are sent with the
+//! chunk and again when they change. Vanilla servers leave the items of chests and furnaces out, so those are only
+//! known when the server sends them.
+
+use serde::{Deserialize, Serialize};
+
+use crate::nbt::{Compound, Tag};
+use crate::storage::block::{BlockKind, BlockLocation};
+use crate::storage::sign::{self, SignText};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TileEntity {
+    Sign(SignText),
+
+    /// the mob a spawner spawns without the `minecraft:` prefix (i.e., `zombie`). None if it is not set.
+    Spawner { mob: Option<String> },
+
+    /// a block entity with items (i.e., a chest, hopper or shulker box) which the server sent us the items of
+    Container { id: String, items: Vec<StoredItem> },
+
+    /// the ticks the fuel burns and the current item cooked for. The items are the input (slot 0), fuel and output.
+    Furnace { burn_time: i16, cook_time: i16, items: Vec<StoredItem> },
+
+    /// a block entity we do not read anything from (i.e., a chest without its items)
+    Other { id: String },
+}
+
+/// An item in a block entity
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StoredItem {
+    pub slot: u8,
+
+    /// without the `minecraft:` prefix (i.e., `cobblestone`)
+    pub name: String,
+    pub count: u8,
+    pub damage: i16,
+}
+
+impl TileEntity {
+    /// The block entity at `location` of `root`. None if `root` has no id or location.
+    pub fn parse(root: &Compound) -> Option<(BlockLocation, TileEntity)> {
+        let location = location(root)?;
+        let id = root.get("id")?.as_str()?;
+
+        let entity = match id {
+            // 1.11 and later, and the name before
+            "minecraft:sign" | "Sign" => TileEntity::Sign(sign::sign_text(root)?),
+            "minecraft:mob_spawner" | "MobSpawner" => TileEntity::Spawner { mob: spawner_mob(root) },
+            "minecraft:furnace" | "Furnace" => TileEntity::Furnace {
+                burn_time: root.get("BurnTime").and_then(Tag::as_i16).unwrap_or_default(),
+                cook_time: root.get("CookTime").and_then(Tag::as_i16).unwrap_or_default(),
+                items: items(root).unwrap_or_default(),
+            },
+            _ => {
+                let id = id.trim_start_matches("minecraft:").to_string();
+                match items(root) {
+                    Some(items) => TileEntity::Container { id, items },
+                    None => TileEntity::Other { id }
+                }
+            }
+        };
+
+        Some((location, entity))
+    }
+
+    /// whether a block of `kind` can hold the entity. It goes away when the block changes to something else.
+    pub fn fits(&self, kind: BlockKind) -> bool {
+        match self {
+            TileEntity::Sign(_) => sign::is_sign(kind),
+            TileEntity::Spawner { .. } => kind == BlockKind::MOB_SPAWNER,
+            TileEntity::Furnace { .. } => kind == BlockKind::FURNACE || kind == BlockKind::LIT_FURNACE,
+            TileEntity::Container { .. } | TileEntity::Other { .. } => kind != BlockKind::AIR,
+        }
+    }
+
+    /// the items the server told us the block holds. Empty for block entities without items.
+    pub fn items(&self) -> &[StoredItem] {
+        match self {
+            TileEntity::Container { items, .. } | TileEntity::Furnace { items, .. } => items,
+            _ => &[]
+        }
+    }
+
+    /// how many items named `name` (with or without `minecraft:`) the block holds
+    pub fn count(&self, name: &str) -> u32 {
+        let name = name.trim_start_matches("minecraft:");
+        self.items().iter()
+            .filter(|item| item.name == name)
+            .map(|item| item.count as u32)
+            .sum()
+    }
+}
+
+/// `Items` of a container. None if there is no such list.
+fn items(root: &Compound) -> Option<Vec<StoredItem>> {
+    let items = root.get("Items")?.as_list()?;
+    let items = items.iter()
+        .filter_map(Tag::as_compound)
+        .filter_map(|item| {
+            // numeric ids are from before 1.8
+            let name = match item.get("id")? {
+                Tag::String(name) => name.trim_start_matches("minecraft:").to_string(),
+                Tag::Short(id) => id.to_string(),
+                _ => return None
+            };
+            Some(StoredItem {
+                slot: item.get("Slot").and_then(Tag::as_i8)? as u8,
+                name,
+                count: item.get("Count").and_then(Tag::as_i8).unwrap_or(1) as u8,
+                damage: item.get("Damage").and_then(Tag::as_i16).unwrap_or_default(),
+            })
+        })
+        .collect();
+    Some(items)
+}
+
+/// `SpawnData` has the mob since 1.9, `EntityId` before
+fn spawner_mob(root: &Compound) -> Option<String> {
+    let id = root.get("SpawnData")
+        .and_then(Tag::as_compound)
+        .and_then(|data| data.get("id"))
+        .or_else(|| root.get("EntityId"))
+        .and_then(Tag::as_str)?;
+    Some(id.trim_start_matches("minecraft:").to_string())
+}
+
+/// where the block entity is
+pub fn location(root: &Compound) -> Option<BlockLocation> {
+    let x = root.get("x")?.as_i32()?;
+    let y = root.get("y")?.as_i32()?;
+    let z = root.get("z")?.as_i32()?;
+    Some(BlockLocation::new(x, y as i16, z))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use crate::nbt::{NbtLimits, read};
+    use crate::storage::block::{BlockKind, BlockLocation};
+    use crate::storage::tile_entity::{StoredItem, TileEntity};
+
+    #[derive(Serialize)]
+    struct SpawnData {
+        id: &'static str,
+    }
+
+    #[derive(Serialize)]
+    struct Spawner {
+        id: &'static str,
+        x: i32,
+        y: i32,
+        z: i32,
+
+        #[serde(rename = "SpawnData")]
+        spawn_data: SpawnData,
+
+        #[serde(rename = "Delay")]
+        delay: i16,
+    }
+
+    #[derive(Serialize)]
+    struct Chest {
+        id: &'static str,
+        x: i32,
+        y: i32,
+        z: i32,
+    }
+
+    #[derive(Serialize)]
+    struct Item {
+        #[serde(rename = "Slot")]
+        slot: i8,
+        id: &'static str,
+
+        #[serde(rename = "Count")]
+        count: i8,
+
+        #[serde(rename = "Damage")]
+        damage: i16,
+    }
+
+    #[derive(Serialize)]
+    struct FilledChest {
+        id: &'static str,
+        x: i32,
+        y: i32,
+        z: i32,
+
+        #[serde(rename = "Items")]
+        items: Vec<Item>,
+    }
+
+    #[derive(Serialize)]
+    struct Furnace {
+        id: &'static str,
+        x: i32,
+        y: i32,
+        z: i32,
+
+        #[serde(rename = "BurnTime")]
+        burn_time: i16,
+
+        #[serde(rename = "CookTime")]
+        cook_time: i16,
+
+        #[serde(rename = "Items")]
+        items: Vec<Item>,
+    }
+
+    fn parse(entity: &impl Serialize) -> Option<(BlockLocation, TileEntity)> {
+        let mut data = Vec::new();
+        nbt::to_writer(&mut data, entity, None).unwrap();
+        let nbt = read(&data, &NbtLimits::NETWORK).unwrap();
+        TileEntity::parse(&nbt.root)
+    }
+
+    #[test]
+    fn test_spawner() {
+        let spawner = Spawner { id: "minecraft:mob_spawner", x: 100, y: 30, z: -7, spawn_data: SpawnData { id: "minecraft:cave_spider" }, delay: 20 };
+        let (location, entity) = parse(&spawner).unwrap();
+        assert_eq!(location, BlockLocation::new(100, 30, -7));
+        assert_eq!(entity, TileEntity::Spawner { mob: Some("cave_spider".to_string()) });
+        assert!(entity.fits(BlockKind::MOB_SPAWNER));
+        assert!(!entity.fits(BlockKind::AIR));
+    }
+
+    #[test]
+    fn test_other() {
+        let (location, entity) = parse(&Chest { id: "minecraft:chest", x: 1, y: 2, z: 3 }).unwrap();
+        assert_eq!(location, BlockLocation::new(1, 2, 3));
+        assert_eq!(entity, TileEntity::Other { id: "chest".to_string() });
+    }
+
+    #[test]
+    fn test_container() {
+        let items = vec![
+            Item { slot: 0, id: "minecraft:diamond", count: 5, damage: 0 },
+            Item { slot: 13, id: "minecraft:diamond", count: 64, damage: 0 },
+            Item { slot: 26, id: "minecraft:wool", count: 3, damage: 14 },
+        ];
+        let (_, entity) = parse(&FilledChest { id: "minecraft:chest", x: 1, y: 2, z: 3, items }).unwrap();
+        assert_eq!(entity.items().len(), 3);
+        assert_eq!(entity.items()[2], StoredItem { slot: 26, name: "wool".to_string(), count: 3, damage: 14 });
+        assert_eq!(entity.count("diamond"), 69);
+        assert_eq!(entity.count("minecraft:diamond"), 69);
+        assert_eq!(entity.count("stone"), 0);
+        assert!(entity.fits(BlockKind(54)));
+
+        let items = vec![Item { slot: 0, id: "minecraft:iron_ore", count: 10, damage: 0 }, Item { slot: 1, id: "minecraft:coal", count: 2, damage: 0 }];
+        let (_, entity) = parse(&Furnace { id: "minecraft:furnace", x: 0, y: 0, z: 0, burn_time: 1200, cook_time: 40, items }).unwrap();
+        match &entity {
+            TileEntity::Furnace { burn_time, cook_time, items } => {
+                assert_eq!((*burn_time, *cook_time), (1200, 40));
+                assert_eq!(items[1].name, "coal");
+            }
+            _ => panic!("not a furnace: {:?}", entity)
+        }
+        assert!(entity.fits(BlockKind::LIT_FURNACE));
+        assert!(!entity.fits(BlockKind(54)));
+    }
+}
@@ -19,9 +19,7 @@ use std::fmt::{Display, Formatter};
 use std::lazy::SyncLazy;
 use std::ops::{Add, AddAssign, Index, Mul, MulAssign, Neg, Sub};
 
-use ansi_term::Style;
-use itertools::Itertools;
-use regex::{Captures, Regex};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use swarm_bot_packets::*;
@@ -30,6 +28,7 @@ use swarm_bot_packets::write::{ByteWritable, ByteWriter};
 
 use crate::client::pathfind::moves::Change;
 use crate::client::state::local::inventory::ItemStack;
+use crate::nbt::{Compound, NbtLimits, Tag};
 use crate::storage::block::BlockLocation;
 use crate::types::Origin::{Abs, Rel};
 
@@ -46,71 +45,6 @@ impl PacketData {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct ChatSection {
-    pub color: Option<String>,
-    pub bold: Option<bool>,
-    pub italic: Option<bool>,
-    pub underlined: Option<bool>,
-    pub strikethrough: Option<bool>,
-    pub text: String,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Chat {
-    pub extra: Option<Vec<ChatSection>>,
-    pub text: Option<String>,
-}
-
-impl Chat {
-    pub fn colorize(self) -> String {
-        if let Some(extra) = self.extra {
-            extra.into_iter().map(|section| section.colorize()).join("")
-        } else {
-            String::new()
-        }
-    }
-}
-
-impl ChatSection {
-    fn colorize(self) -> String {
-        use ansi_term::Color::*;
-
-        let color = match self.color.unwrap_or_default().as_str() {
-            "dark_blue" | "blue" => Blue,
-            "dark_aqua" | "aqua" => Cyan,
-            "red" | "dark_red" => Red,
-            "purple" | "light_purple" => Purple,
-            "gold" | "yellow" => Yellow,
-            "gray" => White,
-            "dark_gray" => Black,
-            "green" | "dark_green" => Green,
-            "white" => White,
-            _ => Black
-        };
-
-        let mut res = Style::from(color);
-
-        if self.bold.unwrap_or_default() {
-            res = res.bold();
-        }
-
-        if self.italic.unwrap_or_default() {
-            res = res.italic();
-        }
-
-        if self.underlined.unwrap_or_default() {
-            res = res.underline();
-        }
-
-        if self.strikethrough.unwrap_or_default() {
-            res = res.strikethrough();
-        }
-
-        res.paint(self.text).to_string()
-    }
-}
-
 #[derive(Debug)]
 pub struct Command {
     pub player: String,
@@ -150,51 +84,7 @@ impl PlayerMessage {
     }
 }
 
-impl Chat {
-    pub fn player_dm(&self) -> Option<PlayerMessage> {
-        static RE: SyncLazy<Regex> = SyncLazy::new(|| {
-            Regex::new(r"^([A-Za-z_0-9]+) whispers: (.*)").unwrap()
-        });
-
-        let text = self.extra.as_ref()?.iter().map(|x| &x.text).join("");
-
-        let captures: Captures = RE.captures(&text)?;
-
-        let player = captures.get(1)?.as_str().to_string();
-        let message = captures.get(2)?.as_str().to_string();
-        Some(PlayerMessage {
-            player,
-            message,
-        })
-    }
-    pub fn player_message(&self) -> Option<PlayerMessage> {
-        static RE: SyncLazy<Regex> = SyncLazy::new(|| {
-            Regex::new(r"^<([A-Za-z_0-9]+)> (.*)").unwrap()
-        });
-
-        let text = self.extra.as_ref()?.iter().map(|x| &x.text).join("");
-
-        let captures: Captures = RE.captures(&text)?;
-
-        let player = captures.get(1)?.as_str().to_string();
-        let message = captures.get(2)?.as_str().to_string();
-
-        Some(PlayerMessage {
-            player,
-            message,
-        })
-    }
-}
-
-impl ByteReadable for Chat {
-    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
-        let string: String = byte_reader.read();
-        let json: Chat = serde_json::from_str(&string).unwrap();
-        json
-    }
-}
-
-#[derive(Writable, Readable, Debug, Copy, Clone, Default, PartialEq)]
+#[derive(Writable, Readable, Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Location {
     pub x: f64,
     pub y: f64,
@@ -289,7 +179,7 @@ impl Display for Location {
     }
 }
 
-#[derive(Writable, Readable, Debug, Copy, Clone, Default)]
+#[derive(Writable, Readable, Debug, Copy, Clone, Default, Serialize, Deserialize)]
 pub struct Displacement {
     pub dx: f64,
     pub dy: f64,
@@ -312,14 +202,61 @@ impl Enchantment {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct ItemNbt {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub ench: Option<Vec<Enchantment>>,
+
+    /// the pages of a book
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<Vec<String>>,
+
+    /// only set once a book is signed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+}
+
+impl From<&Compound<'_>> for ItemNbt {
+    fn from(root: &Compound) -> Self {
+        let ench = root.get("ench").and_then(Tag::as_list).map(|list| {
+            list.iter()
+                .filter_map(Tag::as_compound)
+                .filter_map(|ench| {
+                    let lvl = ench.get("lvl")?.as_i16()? as u16;
+                    let id = ench.get("id")?.as_i16()? as u16;
+                    Some(Enchantment { lvl, id })
+                })
+                .collect()
+        });
+
+        let string = |key| root.get(key).and_then(Tag::as_str).map(str::to_string);
+        let pages = root.get("pages").and_then(Tag::as_list).map(|list| {
+            list.iter().filter_map(Tag::as_str).map(str::to_string).collect()
+        });
+
+        ItemNbt { ench, pages, title: string("title"), author: string("author") }
+    }
 }
 
 impl ByteReadable for ItemNbt {
     fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
-        nbt::from_reader(byte_reader).unwrap()
+        let (item, len) = match crate::nbt::read(byte_reader.remaining(), &NbtLimits::NETWORK) {
+            Ok(nbt) => {
+                // if the nbt is truncated we do not know where it ends so the rest of the packet cannot be trusted
+                let len = if nbt.truncated.is_some() { byte_reader.len() } else { nbt.len };
+                (ItemNbt::from(&nbt.root), len)
+            }
+            Err(err) => {
+                debug!(error = %err, "could not read item nbt");
+                (ItemNbt::default(), byte_reader.len())
+            }
+        };
+
+        byte_reader.advance(len);
+        item
     }
 }
 
@@ -364,6 +301,12 @@ impl From<ItemStack> for Slot {
     }
 }
 
+impl From<Option<ItemStack>> for Slot {
+    fn from(stack: Option<ItemStack>) -> Self {
+        stack.map(Slot::from).unwrap_or(Slot::EMPTY)
+    }
+}
+
 impl Slot {
     pub const EMPTY: Slot = {
         Slot {
@@ -474,6 +417,9 @@ impl Add for Displacement {
 
 impl Displacement {
     pub const EYE_HEIGHT: Displacement = Displacement::new(0., 1.6, 0.);
+
+    /// sneaking lowers the eyes by 0.08
+    pub const SNEAK_EYE_HEIGHT: Displacement = Displacement::new(0., 1.52, 0.);
     pub const EPSILON_Y: Displacement = Displacement::new(0., 0.01, 0.);
 
     pub const fn new(dx: f64, dy: f64, dz: f64) -> Displacement {
@@ -722,7 +668,7 @@ impl DirectionOrigin {
     }
 }
 
-#[derive(Readable, Writable, Copy, Clone, Default, Debug)]
+#[derive(Readable, Writable, Copy, Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Direction {
     /// wiki.vg:
     ///yaw is measured in degrees, and does not follow classical trigonometry rules.
@@ -742,7 +688,7 @@ impl Direction {
         let pitch = self.pitch.to_radians();
         let yaw = self.yaw.to_radians();
 
-        let x = -(pitch).to_radians().cos() * (yaw).sin();
+        let x = -(pitch).cos() * (yaw).sin();
         let y = -(pitch).sin();
         let z = (pitch).cos() * (yaw).cos();
 
@@ -781,13 +727,36 @@ impl From<Displacement> for Direction {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Dimension {
     Nether,
     Overworld,
     End,
 }
 
+impl Default for Dimension {
+    fn default() -> Self {
+        Dimension::Overworld
+    }
+}
+
+impl Dimension {
+    /// how many overworld blocks one block of the dimension is. The nether is 8 times smaller.
+    pub fn scale(self) -> f64 {
+        match self {
+            Dimension::Nether => 8.0,
+            Dimension::Overworld | Dimension::End => 1.0
+        }
+    }
+
+    /// the x and z in dimension `to` which a portal at `location` links to. y is kept.
+    pub fn translate(self, location: Location, to: Dimension) -> Location {
+        let ratio = self.scale() / to.scale();
+        Location::new(location.x * ratio, location.y, location.z * ratio)
+    }
+}
+
 impl Display for Dimension {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let to_write = match self {
@@ -812,6 +781,51 @@ impl ByteReadable for Dimension {
     }
 }
 
+/// What the server lets the player do
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+impl Default for GameMode {
+    fn default() -> Self {
+        GameMode::Survival
+    }
+}
+
+impl GameMode {
+    pub fn from_id(id: u8) -> Option<GameMode> {
+        match id {
+            0 => Some(GameMode::Survival),
+            1 => Some(GameMode::Creative),
+            2 => Some(GameMode::Adventure),
+            3 => Some(GameMode::Spectator),
+            _ => None
+        }
+    }
+
+    /// blocks break at once, items can be taken out of thin air and falls do not hurt
+    pub fn creative(self) -> bool {
+        self == GameMode::Creative
+    }
+}
+
+impl ByteReadable for GameMode {
+    /// Join Game sets bit 3 for hardcore. Gamemodes we do not know are read as survival, the mode the bot is most
+    /// careful in.
+    fn read_from_bytes(byte_reader: &mut ByteReader) -> Self {
+        let val: u8 = byte_reader.read();
+        GameMode::from_id(val & 0x7).unwrap_or_else(|| {
+            warn!(gamemode = val, "unknown gamemode, assuming survival");
+            GameMode::Survival
+        })
+    }
+}
+
 pub type Position = BlockLocation;
 
 
@@ -850,3 +864,19 @@ impl ByteWritable for Position {
         writer.write(write);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use swarm_bot_packets::read::ByteReader;
+
+    use crate::types::GameMode;
+
+    #[test]
+    fn test_read_gamemode() {
+        let mut reader = ByteReader::new(vec![1, 0x8 | 3, 7, 0xFF]);
+        assert_eq!(reader.read::<GameMode>(), GameMode::Creative);
+        assert_eq!(reader.read::<GameMode>(), GameMode::Spectator);
+        assert_eq!(reader.read::<GameMode>(), GameMode::Survival);
+        assert_eq!(reader.read::<GameMode>(), GameMode::Survival);
+    }
+}